@@ -2,11 +2,13 @@ use std::env;
 use std::time::Duration;
 
 mod benchmark {
-    use std::net::TcpStream;
+    use medusa::net;
     use std::io::{Write, Read};
     use std::time::{Duration, Instant};
     use std::thread;
 
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
     pub struct BenchmarkResult {
         pub operations: usize,
         pub duration: Duration,
@@ -38,7 +40,7 @@ mod benchmark {
     }
 
     pub fn run_benchmark(host: &str, port: u16, operations: usize) -> Result<BenchmarkResult, String> {
-        let mut stream = TcpStream::connect(format!("{}:{}", host, port))
+        let mut stream = net::connect(host, port, CONNECT_TIMEOUT)
             .map_err(|e| format!("Failed to connect: {}", e))?;
         
         let start = Instant::now();
@@ -62,7 +64,7 @@ mod benchmark {
     }
 
     pub fn run_get_benchmark(host: &str, port: u16, operations: usize) -> Result<BenchmarkResult, String> {
-        let mut stream = TcpStream::connect(format!("{}:{}", host, port))
+        let mut stream = net::connect(host, port, CONNECT_TIMEOUT)
             .map_err(|e| format!("Failed to connect: {}", e))?;
         
         // First set some values
@@ -120,7 +122,7 @@ mod benchmark {
     }
 
     pub fn run_stress_test(host: &str, port: u16, duration_secs: u64) -> Result<BenchmarkResult, String> {
-        let mut stream = TcpStream::connect(format!("{}:{}", host, port))
+        let mut stream = net::connect(host, port, CONNECT_TIMEOUT)
             .map_err(|e| format!("Failed to connect: {}", e))?;
         
         let start = Instant::now();
@@ -149,11 +151,20 @@ mod benchmark {
     }
 }
 
+// Remove `flag value` from the arguments, leaving the positional ones
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    args.remove(index);
+    (index < args.len()).then(|| args.remove(index))
+}
+
 fn main() {
     println!("⚡ Medusa Benchmark Client");
     println!("Testing server performance...\n");
 
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let timeseries = take_flag(&mut args, "--timeseries");
+    let csv = take_flag(&mut args, "--csv");
     let host = args.get(1).unwrap_or(&"127.0.0.1".to_string()).clone();
     let port = args.get(2).unwrap_or(&"2312".to_string()).parse::<u16>().unwrap_or(2312);
     let operations = args.get(3).unwrap_or(&"1000".to_string()).parse::<usize>().unwrap_or(1000);
@@ -166,6 +177,25 @@ fn main() {
     println!("  🧵 Threads: {}", threads);
     println!();
 
+    // Per-second throughput and latency instead of the usual suite
+    if let Some(seconds) = timeseries {
+        let seconds = seconds.parse::<u64>().unwrap_or(30);
+        println!("🚀 Running {}s time-series benchmark...", seconds);
+        match medusa::benchmark::run_timeseries_benchmark(&host, port, threads, seconds) {
+            Ok(samples) => {
+                medusa::benchmark::display_timeseries(&samples);
+                if let Some(path) = csv {
+                    match medusa::benchmark::save_timeseries_csv(&samples, &path) {
+                        Ok(()) => println!("📄 Wrote {}", path),
+                        Err(e) => eprintln!("❌ {}", e),
+                    }
+                }
+            }
+            Err(e) => eprintln!("❌ Time-series benchmark failed: {}", e),
+        }
+        return;
+    }
+
     // Single-threaded SET benchmark
     println!("🚀 Running single-threaded SET benchmark...");
     match benchmark::run_benchmark(&host, port, operations) {
@@ -204,5 +234,16 @@ fn main() {
         Err(e) => eprintln!("❌ Stress test failed: {}", e),
     }
 
+    // In-process GET reply path, no network
+    println!("🚀 Running GET reply path microbenchmark...");
+    let (previous, current) = medusa::benchmark::run_get_path_benchmark(operations.max(100_000));
+    previous.display("GET path (copy and format)");
+    current.display("GET path (in place, reused buffer)");
+
+    // Worst single insert while the keyspace grows, no network
+    println!("🚀 Running keyspace resize stall microbenchmark...");
+    let (plain, sharded) = medusa::benchmark::run_resize_stall_benchmark(2_000_000);
+    println!("Slowest insert into 2M keys: single HashMap {:?}, sharded keyspace {:?}", plain, sharded);
+
     println!("✅ Benchmark completed!");
 } 
\ No newline at end of file
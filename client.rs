@@ -1,14 +1,28 @@
+use medusa::net;
+use std::env;
 use std::io::{self, BufRead, BufReader, Write};
-use std::net::TcpStream;
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
 fn main() -> io::Result<()> {
     println!("⚡ Medusa Client");
-    println!("Connecting to server at 127.0.0.1:2312...");
-
-    let mut stream = match TcpStream::connect("127.0.0.1:2312") {
+    // medusa-client [host] [port], falling back to MEDUSA_HOST/MEDUSA_PORT
+    let args: Vec<String> = env::args().collect();
+    let host = args
+        .get(1)
+        .cloned()
+        .or_else(|| env::var("MEDUSA_HOST").ok())
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+    let port = args
+        .get(2)
+        .cloned()
+        .or_else(|| env::var("MEDUSA_PORT").ok())
+        .and_then(|port| port.parse::<u16>().ok())
+        .unwrap_or(2312);
+    println!("Connecting to server at {}...", net::format_address(&host, port));
+
+    let mut stream = match net::connect(&host, port, Duration::from_secs(10)) {
         Ok(stream) => {
             println!("[+] Connected to Medusa server!");
             stream
@@ -74,12 +88,8 @@ fn main() -> io::Result<()> {
                     continue;
                 }
 
-                // Handle special commands
+                // Handle special commands (HELP is answered by the server)
                 match trimmed.to_lowercase().as_str() {
-                    "help" => {
-                        print_help();
-                        continue;
-                    }
                     "quit" | "exit" => {
                         println!("[!] Goodbye!");
                         break;
@@ -114,49 +124,3 @@ fn main() -> io::Result<()> {
     println!("[-] Disconnected from server");
     Ok(())
 }
-
-fn print_help() {
-    println!("\n[-] Available Commands:");
-    println!("  SET key value [TTL]     - Store key-value pair with optional TTL");
-    println!("  GET key                  - Retrieve value by key");
-    println!("  DELETE key               - Remove key-value pair");
-    println!("  EXISTS key               - Check if key exists");
-    println!("  TTL key                  - Get time-to-live for key");
-    println!("  EXPIRE key seconds       - Set expiration time for key");
-    println!("  LIST                     - List all keys");
-    println!("  KEYS pattern             - Find keys matching pattern");
-    println!("  COUNT                    - Get number of entries");
-    println!("  CLEAR/FLUSHALL           - Remove all entries");
-    println!("  INFO                     - Get server statistics");
-    println!("  PING                     - Server health check");
-    println!("  QUIT/EXIT                - Disconnect");
-    println!("  HELP                     - Show this help");
-    println!("  CLEAR                    - Clear screen");
-
-    println!("\n[-]  Hash Operations:");
-    println!("  HSET key field value     - Set hash field to value");
-    println!("  HGET key field           - Get hash field value");
-    println!("  HGETALL key              - Get all hash fields and values");
-    println!("  HDEL key field           - Delete hash field");
-    println!("  HEXISTS key field        - Check if hash field exists");
-    println!("  HLEN key                 - Get hash length");
-
-    println!("\n[-] List Operations:");
-    println!("  LPUSH key value          - Push value to left of list");
-    println!("  RPUSH key value          - Push value to right of list");
-    println!("  LPOP key                 - Pop value from left of list");
-    println!("  RPOP key                 - Pop value from right of list");
-    println!("  LLEN key                 - Get list length");
-    println!("  LRANGE key start stop    - Get list range (supports negative indices)");
-
-    println!("\n[-] Examples:");
-    println!("  SET user:1 'John Doe' 3600    # Set with 1 hour TTL");
-    println!("  EXPIRE user:1 7200            # Set 2 hour expiration");
-    println!("  KEYS user:*                   # Find all user keys");
-    println!("  TTL user:1                    # Check remaining time");
-    println!("  HSET user:1 name 'John'       # Set hash field");
-    println!("  HGET user:1 name              # Get hash field");
-    println!("  LPUSH tasks 'task1'           # Push to list");
-    println!("  LRANGE tasks 0 -1             # Get all list items");
-    println!();
-}
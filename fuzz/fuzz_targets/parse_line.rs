@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use medusa::protocol::{parse_line, MAX_REQUEST_TAG_LEN};
+
+fuzz_target!(|line: &[u8]| {
+    if let Ok(Some(request)) = parse_line(line) {
+        assert!(!request.command.is_empty());
+        assert_eq!(request.command, request.command.trim());
+        assert!(request.tag.is_none_or(|tag| tag.len() <= MAX_REQUEST_TAG_LEN));
+        assert_eq!(request.args().first().copied(), Some(request.name()));
+    }
+});
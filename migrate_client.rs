@@ -0,0 +1,67 @@
+use medusa::migrate::{migrate, MigrateOptions};
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("Usage: medusa-migrate [--from host:port] [--to host:port] [--match pattern]");
+    eprintln!("                      [--batch n] [--staging-dir dir] [--progress file] [--verify]");
+    eprintln!();
+    eprintln!("Copies keys from a running Redis (default 127.0.0.1:6379) into a running");
+    eprintln!("Medusa (default 127.0.0.1:2312), keeping TTLs. The staging directory must be");
+    eprintln!("Medusa's MEDUSA_IMPORT_DIR. With --progress an interrupted run resumes where it");
+    eprintln!("stopped; --verify re-reads every key afterwards and reports differences.");
+    process::exit(2);
+}
+
+fn main() {
+    let mut options = MigrateOptions::default();
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--from" => options.source = value(),
+            "--to" => options.target = value(),
+            "--match" => options.pattern = Some(value()),
+            "--batch" => options.batch_size = value().parse().unwrap_or_else(|_| usage()),
+            "--staging-dir" => options.staging_dir = PathBuf::from(value()),
+            "--progress" => options.progress_file = Some(PathBuf::from(value())),
+            "--verify" => options.verify = true,
+            _ => usage(),
+        }
+    }
+
+    println!("⚡ Medusa Migrate");
+    println!("  📍 From Redis: {}", options.source);
+    println!("  🎯 To Medusa: {}", options.target);
+    if let Some(path) = &options.progress_file {
+        println!("  💾 Progress file: {}", path.display());
+    }
+    println!();
+
+    match migrate(&options) {
+        Ok(report) => {
+            println!("✅ Migrated {} keys", report.migrated);
+            if !report.skipped.is_empty() {
+                println!("⚠️  Skipped {} keys:", report.skipped.len());
+                for (key, reason) in &report.skipped {
+                    println!("    {}: {}", key, reason);
+                }
+            }
+            if options.verify {
+                println!("🔍 Verified {} keys, {} mismatches", report.verified, report.mismatches.len());
+                for key in &report.mismatches {
+                    println!("    {}", key);
+                }
+                if !report.mismatches.is_empty() {
+                    process::exit(1);
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("❌ Migration failed: {}", e);
+            process::exit(1);
+        }
+    }
+}
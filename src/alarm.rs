@@ -0,0 +1,222 @@
+use crate::lifecycle::Lifecycle;
+use crate::store::Store;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What alarms can watch: estimated memory in bytes, live keys and
+/// connected clients.
+pub const METRICS: &[&str] = &["memory", "keys", "clients"];
+
+// Raise/clear events kept for clients that have not heard about them yet
+const MAX_EVENTS: usize = 64;
+
+/// Parse `memory=1073741824;keys=1000000;clients=500` (any subset, any order).
+pub fn parse_thresholds(spec: &str) -> Result<Vec<(&'static str, u64)>, String> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|setting| {
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid alarm setting '{}'", setting))?;
+            let metric = metric_name(name.trim())?;
+            let value = value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid threshold for alarm '{}'", metric))?;
+            Ok((metric, value))
+        })
+        .collect()
+}
+
+fn metric_name(name: &str) -> Result<&'static str, String> {
+    let name = name.to_lowercase();
+    METRICS.iter()
+        .find(|metric| **metric == name)
+        .copied()
+        .ok_or_else(|| format!("Unknown alarm metric '{}' (expected {})", name, METRICS.join(", ")))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActiveAlarm {
+    pub metric: &'static str,
+    pub value: u64,
+    pub threshold: u64,
+    pub since: u64,
+}
+
+#[derive(Default)]
+struct AlarmState {
+    thresholds: BTreeMap<&'static str, u64>,
+    active: BTreeMap<&'static str, ActiveAlarm>,
+    events: VecDeque<(u64, String)>,
+    last_event: u64,
+}
+
+impl AlarmState {
+    fn record(&mut self, message: String) {
+        println!("⚠️  {}", message);
+        self.last_event += 1;
+        self.events.push_back((self.last_event, message));
+        if self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+}
+
+/// Thresholds on server-wide metrics. An alarm is raised when a metric
+/// goes above its threshold and cleared once it is back at or below it;
+/// both are logged and announced to clients that enabled notices.
+#[derive(Clone, Default)]
+pub struct AlarmMonitor {
+    inner: Arc<Mutex<AlarmState>>,
+}
+
+impl AlarmMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set or (with `None`) remove the threshold for `metric`.
+    pub fn set_threshold(&self, metric: &str, threshold: Option<u64>) -> Result<(), String> {
+        let metric = metric_name(metric)?;
+        let mut state = self.inner.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        match threshold {
+            Some(threshold) => {
+                state.thresholds.insert(metric, threshold);
+            }
+            None => {
+                state.thresholds.remove(metric);
+                if let Some(alarm) = state.active.remove(metric) {
+                    state.record(format!("Alarm cleared: {} threshold removed", alarm.metric));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn thresholds(&self) -> Vec<(&'static str, u64)> {
+        self.inner.lock()
+            .map(|state| state.thresholds.iter().map(|(metric, threshold)| (*metric, *threshold)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Compare fresh `readings` against the thresholds, raising and
+    /// clearing alarms as needed.
+    pub fn evaluate(&self, readings: &[(&'static str, u64)]) {
+        let mut state = match self.inner.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+        for &(metric, value) in readings {
+            let threshold = match state.thresholds.get(metric) {
+                Some(threshold) => *threshold,
+                None => continue,
+            };
+            if value > threshold {
+                match state.active.get_mut(metric) {
+                    Some(alarm) => {
+                        alarm.value = value;
+                        alarm.threshold = threshold;
+                    }
+                    None => {
+                        state.active.insert(metric, ActiveAlarm { metric, value, threshold, since: now });
+                        state.record(format!("Alarm raised: {} is {} (threshold {})", metric, value, threshold));
+                    }
+                }
+            } else if state.active.remove(metric).is_some() {
+                state.record(format!("Alarm cleared: {} is {} (threshold {})", metric, value, threshold));
+            }
+        }
+    }
+
+    /// Log `message` and pass it on to clients that enabled notices, like
+    /// an alarm event. Used for server events such as a failover.
+    pub fn announce(&self, message: String) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.record(message);
+        }
+    }
+
+    pub fn active(&self) -> Vec<ActiveAlarm> {
+        self.inner.lock()
+            .map(|state| state.active.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Id of the most recent raise/clear event, 0 if none yet.
+    pub fn last_event(&self) -> u64 {
+        self.inner.lock().map(|state| state.last_event).unwrap_or(0)
+    }
+
+    /// Events after `seen`, with the id to pass next time.
+    pub fn events_since(&self, seen: u64) -> (Vec<String>, u64) {
+        match self.inner.lock() {
+            Ok(state) => {
+                let events = state.events.iter()
+                    .filter(|(id, _)| *id > seen)
+                    .map(|(_, message)| message.clone())
+                    .collect();
+                (events, state.last_event)
+            }
+            Err(_) => (Vec::new(), seen),
+        }
+    }
+}
+
+/// Check the alarm thresholds every `interval` on a background thread,
+/// until the server stops.
+pub fn start_alarm_monitor(store: Store, lifecycle: Lifecycle, interval: Duration) -> thread::JoinHandle<()> {
+    thread::spawn(move || while !lifecycle.should_stop() {
+        if !store.alarms().thresholds().is_empty() {
+            if let Ok((keys, memory)) = store.prefix_usage("") {
+                store.alarms().evaluate(&[
+                    ("memory", memory as u64),
+                    ("keys", keys as u64),
+                    ("clients", lifecycle.active_connections() as u64),
+                ]);
+            }
+        }
+        thread::sleep(interval);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_thresholds() {
+        assert_eq!(parse_thresholds("memory=100; keys=5").unwrap(), vec![("memory", 100), ("keys", 5)]);
+        assert!(parse_thresholds("disk=1").is_err());
+        assert!(parse_thresholds("keys=many").is_err());
+    }
+
+    #[test]
+    fn test_alarm_raise_and_clear() {
+        let alarms = AlarmMonitor::new();
+        alarms.set_threshold("keys", Some(10)).unwrap();
+        assert!(alarms.set_threshold("disk", Some(1)).is_err());
+
+        alarms.evaluate(&[("keys", 5), ("memory", 1 << 30)]);
+        assert!(alarms.active().is_empty());
+        assert_eq!(alarms.last_event(), 0);
+
+        alarms.evaluate(&[("keys", 11)]);
+        alarms.evaluate(&[("keys", 12)]);
+        let active = alarms.active();
+        assert_eq!(active.len(), 1);
+        assert_eq!((active[0].metric, active[0].value, active[0].threshold), ("keys", 12, 10));
+        assert_eq!(alarms.last_event(), 1); // Raised once, not on every check
+
+        alarms.evaluate(&[("keys", 10)]);
+        assert!(alarms.active().is_empty());
+        let (events, last) = alarms.events_since(0);
+        assert_eq!(events, vec!["Alarm raised: keys is 11 (threshold 10)", "Alarm cleared: keys is 10 (threshold 10)"]);
+        assert_eq!(alarms.events_since(last).0.len(), 0);
+    }
+}
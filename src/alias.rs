@@ -0,0 +1,104 @@
+use crate::command_table;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Parse `MEDUSA_ALIASES`-style definitions: `SESSIONS=KEYS session:*,Q=QUIT`.
+pub fn parse_aliases(spec: &str) -> Result<Vec<(String, String)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|alias| {
+            let (name, command) = alias
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid alias definition '{}'", alias))?;
+            Ok((name.trim().to_string(), command.trim().to_string()))
+        })
+        .collect()
+}
+
+/// User-defined command names that expand to an existing command with
+/// preset arguments, e.g. `SESSIONS` -> `KEYS session:*`. Arguments given
+/// to an alias are appended to its expansion.
+#[derive(Clone, Default)]
+pub struct AliasRegistry {
+    aliases: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl AliasRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define or replace an alias. Aliases cannot shadow built-in commands
+    /// and must expand to one, so expansion never recurses.
+    pub fn set(&self, name: &str, command: &str) -> Result<(), String> {
+        let name = name.to_uppercase();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("Invalid alias name '{}'", name));
+        }
+        if !command_table::lookup(&name).is_empty() {
+            return Err(format!("'{}' is a built-in command", name));
+        }
+        let target = command.split_whitespace().next().unwrap_or("");
+        if command_table::lookup(target).is_empty() {
+            return Err(format!("Unknown command '{}'", target));
+        }
+
+        let mut aliases = self.aliases.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        aliases.insert(name, command.split_whitespace().collect::<Vec<_>>().join(" "));
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<bool, String> {
+        let mut aliases = self.aliases.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        Ok(aliases.remove(&name.to_uppercase()).is_some())
+    }
+
+    pub fn list(&self) -> Vec<(String, String)> {
+        self.aliases.lock()
+            .map(|aliases| aliases.iter().map(|(name, command)| (name.clone(), command.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    /// The command line `command` stands for, if it starts with an alias.
+    pub fn expand(&self, parts: &[&str]) -> Option<Vec<String>> {
+        let (name, args) = parts.split_first()?;
+        let aliases = self.aliases.lock().ok()?;
+        let expansion = aliases.get(&name.to_uppercase())?;
+        Some(expansion.split_whitespace().chain(args.iter().copied()).map(String::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_aliases() {
+        let aliases = parse_aliases("SESSIONS=KEYS session:*, q = QUIT").unwrap();
+        assert_eq!(aliases, vec![
+            ("SESSIONS".to_string(), "KEYS session:*".to_string()),
+            ("q".to_string(), "QUIT".to_string()),
+        ]);
+        assert!(parse_aliases("SESSIONS").is_err());
+    }
+
+    #[test]
+    fn test_alias_expansion() {
+        let aliases = AliasRegistry::new();
+        aliases.set("sessions", "KEYS   session:*").unwrap();
+        aliases.set("user", "HGET").unwrap();
+
+        assert_eq!(aliases.expand(&["SESSIONS"]), Some(vec!["KEYS".to_string(), "session:*".to_string()]));
+        assert_eq!(aliases.expand(&["user", "user:1", "full name"]).unwrap().join("|"), "HGET|user:1|full name");
+        assert_eq!(aliases.expand(&["GET", "key"]), None);
+
+        assert!(aliases.set("GET", "KEYS *").is_err());
+        assert!(aliases.set("loop", "SESSIONS").is_err());
+        assert!(aliases.set("bad name", "PING").is_err());
+
+        assert!(aliases.remove("Sessions").unwrap());
+        assert!(!aliases.remove("sessions").unwrap());
+        assert_eq!(aliases.list().len(), 1);
+    }
+}
@@ -1,8 +1,16 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use crate::client_handler;
+use crate::keyspace::ShardedMap;
+use crate::net;
+use crate::protocol::Protocol;
+use crate::store::Store;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
 use std::thread;
 use std::time::{Duration, Instant};
 
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct BenchmarkResult {
     pub operations: usize,
     pub duration: Duration,
@@ -34,7 +42,7 @@ impl BenchmarkResult {
 }
 
 pub fn run_benchmark(host: &str, port: u16, operations: usize) -> Result<BenchmarkResult, String> {
-    let mut stream = TcpStream::connect(format!("{}:{}", host, port))
+    let mut stream = net::connect(host, port, CONNECT_TIMEOUT)
         .map_err(|e| format!("Failed to connect: {}", e))?;
 
     let start = Instant::now();
@@ -90,7 +98,7 @@ pub fn run_stress_test(
     port: u16,
     duration_secs: u64,
 ) -> Result<BenchmarkResult, String> {
-    let mut stream = TcpStream::connect(format!("{}:{}", host, port))
+    let mut stream = net::connect(host, port, CONNECT_TIMEOUT)
         .map_err(|e| format!("Failed to connect: {}", e))?;
 
     let start = Instant::now();
@@ -119,6 +127,204 @@ pub fn run_stress_test(
     Ok(BenchmarkResult::new(operations, duration))
 }
 
+/// Throughput and latency over one second of a time-series run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntervalSample {
+    /// Seconds since the start of the run, counting from 0.
+    pub second: u64,
+    pub operations: usize,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl IntervalSample {
+    fn from_latencies(second: u64, latencies: &mut [Duration]) -> Self {
+        latencies.sort_unstable();
+        IntervalSample {
+            second,
+            operations: latencies.len(),
+            p50: percentile(latencies, 0.50),
+            p99: percentile(latencies, 0.99),
+            max: latencies.last().copied().unwrap_or(Duration::ZERO),
+        }
+    }
+}
+
+// Nearest-rank percentile of already sorted latencies
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Run alternating SETs and GETs from `threads` connections for
+/// `duration_secs` and report throughput and latency percentiles for each
+/// second separately, so warmup, expiry sweeps and save stalls show up as
+/// dips instead of disappearing into one aggregate number.
+pub fn run_timeseries_benchmark(
+    host: &str,
+    port: u16,
+    threads: usize,
+    duration_secs: u64,
+) -> Result<Vec<IntervalSample>, String> {
+    let start = Instant::now();
+    let mut handles = vec![];
+    for worker in 0..threads.max(1) {
+        let host = host.to_string();
+        handles.push(thread::spawn(move || -> Result<Vec<Vec<Duration>>, String> {
+            let mut stream = net::connect(&host, port, CONNECT_TIMEOUT)
+                .map_err(|e| format!("Failed to connect: {}", e))?;
+            let mut seconds: Vec<Vec<Duration>> = vec![Vec::new(); duration_secs as usize];
+            let mut buffer = [0; 1024];
+            let mut i = 0u64;
+            loop {
+                let second = start.elapsed().as_secs() as usize;
+                if second >= seconds.len() {
+                    break;
+                }
+                let key = format!("timeseries:{}:{}", worker, i % 10_000);
+                let command = if i.is_multiple_of(2) {
+                    format!("SET {} value_{}\n", key, i)
+                } else {
+                    format!("GET {}\n", key)
+                };
+
+                let sent = Instant::now();
+                stream
+                    .write_all(command.as_bytes())
+                    .map_err(|e| format!("Write error: {}", e))?;
+                stream
+                    .read(&mut buffer)
+                    .map_err(|e| format!("Read error: {}", e))?;
+                // Attributed to the second the request was sent in
+                seconds[second].push(sent.elapsed());
+                i += 1;
+            }
+            Ok(seconds)
+        }));
+    }
+
+    let mut merged: Vec<Vec<Duration>> = vec![Vec::new(); duration_secs as usize];
+    for handle in handles {
+        let seconds = match handle.join() {
+            Ok(result) => result?,
+            Err(_) => return Err("Thread join failed".to_string()),
+        };
+        for (all, latencies) in merged.iter_mut().zip(seconds) {
+            all.extend(latencies);
+        }
+    }
+    Ok(merged
+        .iter_mut()
+        .enumerate()
+        .map(|(second, latencies)| IntervalSample::from_latencies(second as u64, latencies))
+        .collect())
+}
+
+pub fn display_timeseries(samples: &[IntervalSample]) {
+    println!("{:>6} {:>10} {:>10} {:>10} {:>10}", "second", "ops/sec", "p50_us", "p99_us", "max_us");
+    for sample in samples {
+        println!(
+            "{:>6} {:>10} {:>10} {:>10} {:>10}",
+            sample.second,
+            sample.operations,
+            sample.p50.as_micros(),
+            sample.p99.as_micros(),
+            sample.max.as_micros()
+        );
+    }
+    println!();
+}
+
+/// Write one CSV row per second, latencies in microseconds.
+pub fn write_timeseries_csv(samples: &[IntervalSample], out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out, "second,ops_per_sec,p50_us,p99_us,max_us")?;
+    for sample in samples {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            sample.second,
+            sample.operations,
+            sample.p50.as_micros(),
+            sample.p99.as_micros(),
+            sample.max.as_micros()
+        )?;
+    }
+    Ok(())
+}
+
+pub fn save_timeseries_csv(samples: &[IntervalSample], path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut out = BufWriter::new(file);
+    write_timeseries_csv(samples, &mut out)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// In-process microbenchmark of the server's GET reply path, without the
+/// network: the previous approach (copy the value out of the store, format
+/// a new reply) against the current one (format the value in place into a
+/// reused buffer). Returns `(previous, current)`.
+pub fn run_get_path_benchmark(operations: usize) -> (BenchmarkResult, BenchmarkResult) {
+    let store = Store::new();
+    let keys: Vec<String> = (0..1000).map(|i| format!("bench:{}", i)).collect();
+    for key in &keys {
+        let _ = store.set(key, &"v".repeat(256));
+    }
+
+    let start = Instant::now();
+    let mut bytes = 0;
+    for i in 0..operations {
+        let key = &keys[i % keys.len()];
+        let reply = match store.get(key) {
+            Ok(Some(value)) => format!("OK: '{}' = {}\n", key, value),
+            _ => format!("NULL: Key '{}' not found or expired\n", key),
+        };
+        bytes += reply.len();
+    }
+    let previous = BenchmarkResult::new(operations, start.elapsed());
+
+    let start = Instant::now();
+    let mut reply = String::new();
+    for i in 0..operations {
+        reply.clear();
+        client_handler::write_get_reply(&store, &keys[i % keys.len()], true, Protocol::Text, &mut reply);
+        bytes -= reply.len();
+    }
+    let current = BenchmarkResult::new(operations, start.elapsed());
+
+    // Both paths must have produced the same replies
+    debug_assert_eq!(bytes, 0);
+    (previous, current)
+}
+
+/// The slowest single insert while filling a plain `HashMap` and the
+/// store's `ShardedMap` with `keys` keys. The outliers are resizes, which
+/// the store performs while holding its lock.
+pub fn run_resize_stall_benchmark(keys: usize) -> (Duration, Duration) {
+    let mut plain = HashMap::new();
+    let mut plain_worst = Duration::ZERO;
+    for i in 0..keys {
+        let key = format!("key:{}", i);
+        let start = Instant::now();
+        plain.insert(key, i);
+        plain_worst = plain_worst.max(start.elapsed());
+    }
+
+    let mut sharded = ShardedMap::new();
+    let mut sharded_worst = Duration::ZERO;
+    for i in 0..keys {
+        let key = format!("key:{}", i);
+        let start = Instant::now();
+        sharded.insert(key, i);
+        sharded_worst = sharded_worst.max(start.elapsed());
+    }
+    (plain_worst, sharded_worst)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -130,4 +336,32 @@ mod tests {
         assert_eq!(result.duration, Duration::from_secs(1));
         assert!((result.ops_per_second - 1000.0).abs() < 0.1);
     }
+
+    #[test]
+    fn test_timeseries_percentiles() {
+        let mut latencies: Vec<Duration> = (1..=100).rev().map(Duration::from_micros).collect();
+        let sample = IntervalSample::from_latencies(3, &mut latencies);
+        assert_eq!(sample.operations, 100);
+        assert_eq!(sample.p50, Duration::from_micros(50));
+        assert_eq!(sample.p99, Duration::from_micros(99));
+        assert_eq!(sample.max, Duration::from_micros(100));
+        assert_eq!(IntervalSample::from_latencies(4, &mut []).p99, Duration::ZERO);
+
+        let mut csv = Vec::new();
+        write_timeseries_csv(&[sample], &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "second,ops_per_sec,p50_us,p99_us,max_us\n3,100,50,99,100\n");
+    }
+
+    #[test]
+    fn test_get_path_benchmark() {
+        let (previous, current) = run_get_path_benchmark(1000);
+        assert_eq!(previous.operations, 1000);
+        assert_eq!(current.operations, 1000);
+    }
+
+    #[test]
+    fn test_resize_stall_benchmark() {
+        let (plain, sharded) = run_resize_stall_benchmark(1000);
+        assert!(plain > Duration::ZERO && sharded > Duration::ZERO);
+    }
 }
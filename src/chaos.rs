@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Artificial faults for testing how clients cope with a misbehaving
+/// server. Everything is off until the server enables injection
+/// (`MEDUSA_FAULT_INJECTION=true`) and faults are armed with `DEBUG INJECT`.
+#[derive(Clone, Default)]
+pub struct FaultInjector {
+    inner: Arc<FaultState>,
+}
+
+#[derive(Default)]
+struct FaultState {
+    enabled: AtomicBool,
+    latency_ms: AtomicU64,
+    latency_percent: AtomicU32,
+    drop_percent: AtomicU32,
+    fail_persistence: AtomicBool,
+    rng: AtomicU64,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.inner.enabled.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Delay `percent`% of commands by `latency`.
+    pub fn set_latency(&self, latency: Duration, percent: u32) {
+        self.inner.latency_ms.store(latency.as_millis() as u64, Ordering::SeqCst);
+        self.inner.latency_percent.store(percent.min(100), Ordering::SeqCst);
+    }
+
+    /// Close the connection instead of answering `percent`% of commands.
+    pub fn set_drop_rate(&self, percent: u32) {
+        self.inner.drop_percent.store(percent.min(100), Ordering::SeqCst);
+    }
+
+    /// Make every snapshot and backup write fail.
+    pub fn set_persistence_failure(&self, fail: bool) {
+        self.inner.fail_persistence.store(fail, Ordering::SeqCst);
+    }
+
+    pub fn clear(&self) {
+        self.set_latency(Duration::ZERO, 0);
+        self.set_drop_rate(0);
+        self.set_persistence_failure(false);
+    }
+
+    /// Latency to add before the next command, if one is due.
+    pub fn next_latency(&self) -> Option<Duration> {
+        let latency = self.inner.latency_ms.load(Ordering::SeqCst);
+        if latency == 0 || !self.roll(self.inner.latency_percent.load(Ordering::SeqCst)) {
+            return None;
+        }
+        Some(Duration::from_millis(latency))
+    }
+
+    pub fn should_drop(&self) -> bool {
+        self.roll(self.inner.drop_percent.load(Ordering::SeqCst))
+    }
+
+    pub fn check_persistence(&self) -> Result<(), String> {
+        if self.is_enabled() && self.inner.fail_persistence.load(Ordering::SeqCst) {
+            return Err("Injected persistence failure".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "latency={}ms@{}% drop={}% persistence_failure={}",
+            self.inner.latency_ms.load(Ordering::SeqCst),
+            self.inner.latency_percent.load(Ordering::SeqCst),
+            self.inner.drop_percent.load(Ordering::SeqCst),
+            self.inner.fail_persistence.load(Ordering::SeqCst),
+        )
+    }
+
+    // True with probability `percent`/100 (xorshift, good enough for chaos)
+    fn roll(&self, percent: u32) -> bool {
+        if percent == 0 || !self.is_enabled() {
+            return false;
+        }
+        if percent >= 100 {
+            return true;
+        }
+
+        let mut x = self.inner.rng.load(Ordering::Relaxed);
+        if x == 0 {
+            x = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0x2545F4914F6CDD1D)
+                | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.inner.rng.store(x, Ordering::Relaxed);
+        (x % 100) < percent as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faults_require_enabling() {
+        let faults = FaultInjector::new();
+        faults.set_drop_rate(100);
+        faults.set_latency(Duration::from_millis(5), 100);
+        faults.set_persistence_failure(true);
+        assert!(!faults.should_drop());
+        assert_eq!(faults.next_latency(), None);
+        assert!(faults.check_persistence().is_ok());
+
+        faults.set_enabled(true);
+        faults.set_drop_rate(100);
+        faults.set_latency(Duration::from_millis(5), 100);
+        faults.set_persistence_failure(true);
+        assert!(faults.should_drop());
+        assert_eq!(faults.next_latency(), Some(Duration::from_millis(5)));
+        assert!(faults.check_persistence().is_err());
+
+        faults.clear();
+        assert!(!faults.should_drop());
+        assert_eq!(faults.next_latency(), None);
+        assert!(faults.check_persistence().is_ok());
+    }
+
+    #[test]
+    fn test_fault_probability() {
+        let faults = FaultInjector::new();
+        faults.set_enabled(true);
+        faults.set_drop_rate(50);
+        let dropped = (0..1000).filter(|_| faults.should_drop()).count();
+        assert!(dropped > 300 && dropped < 700, "{}", dropped);
+    }
+}
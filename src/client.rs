@@ -0,0 +1,199 @@
+use crate::base64;
+use crate::proxy::{self, Pool};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Idle connections kept per endpoint.
+const POOL_SIZE: usize = 4;
+/// How long a replica that failed is skipped before reads try it again.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Primary,
+    Replica,
+}
+
+/// One Medusa instance the client talks to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Endpoint {
+    pub address: String,
+    pub role: Role,
+}
+
+/// Parse a comma-separated list of `primary=host:port` and
+/// `replica=host:port` endpoints. A bare `host:port` is the primary.
+pub fn parse_endpoints(spec: &str) -> Result<Vec<Endpoint>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| {
+            let (role, address) = match entry.split_once('=') {
+                Some((role, address)) => match role.trim().to_lowercase().as_str() {
+                    "primary" => (Role::Primary, address),
+                    "replica" => (Role::Replica, address),
+                    other => return Err(format!("Unknown role '{}', expected primary or replica", other)),
+                },
+                None => (Role::Primary, entry),
+            };
+            Ok(Endpoint { address: address.trim().to_string(), role })
+        })
+        .collect()
+}
+
+struct Node {
+    address: String,
+    pool: Pool,
+    // Set after a failure; the replica is skipped until then
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Node {
+    fn new(address: &str) -> Self {
+        Node { address: address.to_string(), pool: Pool::new(address, POOL_SIZE), down_until: Mutex::new(None) }
+    }
+
+    fn is_healthy(&self) -> bool {
+        match self.down_until.lock() {
+            Ok(down_until) => down_until.is_none_or(|until| Instant::now() >= until),
+            Err(_) => false,
+        }
+    }
+
+    fn mark(&self, healthy: bool, retry_after: Duration) {
+        if let Ok(mut down_until) = self.down_until.lock() {
+            *down_until = if healthy { None } else { Some(Instant::now() + retry_after) };
+        }
+    }
+}
+
+/// A client for a primary and its read replicas. Writes go to the primary;
+/// read-only commands are spread across healthy replicas. A replica that
+/// fails is skipped for a while and the read is retried on the primary, so
+/// callers only see an error when the primary itself is unreachable.
+///
+/// Connections are pooled and shared, so connection state (`CLIENT`
+/// commands) is not available.
+pub struct Client {
+    primary: Node,
+    replicas: Vec<Node>,
+    next_replica: AtomicUsize,
+    retry_after: Duration,
+}
+
+impl Client {
+    /// Exactly one endpoint must be the primary. No connection is made until
+    /// the first command.
+    pub fn new(endpoints: &[Endpoint]) -> Result<Self, String> {
+        let mut primaries = endpoints.iter().filter(|endpoint| endpoint.role == Role::Primary);
+        let primary = match (primaries.next(), primaries.next()) {
+            (Some(primary), None) => Node::new(&primary.address),
+            (None, _) => return Err("No primary endpoint given".to_string()),
+            (Some(_), Some(_)) => return Err("Only one primary endpoint is allowed".to_string()),
+        };
+        let replicas = endpoints
+            .iter()
+            .filter(|endpoint| endpoint.role == Role::Replica)
+            .map(|endpoint| Node::new(&endpoint.address))
+            .collect();
+        Ok(Client { primary, replicas, next_replica: AtomicUsize::new(0), retry_after: DEFAULT_RETRY_AFTER })
+    }
+
+    /// How long a failed replica is left out of rotation.
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Send one command and return its reply.
+    pub fn execute(&self, command: &str) -> Result<String, String> {
+        self.pipeline(&[command]).map(|mut replies| replies.remove(0))
+    }
+
+    /// Send several commands in one write and return their replies in
+    /// order. A batch containing any write goes to the primary.
+    pub fn pipeline(&self, commands: &[&str]) -> Result<Vec<String>, String> {
+        if let Some(command) = commands.iter().find(|command| proxy::is_session_command(command)) {
+            return Err(format!("'{}' needs a dedicated connection and is not supported", command));
+        }
+        if commands.iter().all(|command| proxy::is_read_command(command)) {
+            if let Some(replica) = self.pick_replica() {
+                match replica.pool.execute(commands) {
+                    Ok(replies) => return Ok(replies),
+                    Err(e) => {
+                        eprintln!("Warning: Replica {} failed, reading from the primary: {}", replica.address, e);
+                        replica.mark(false, self.retry_after);
+                    }
+                }
+            }
+        }
+        self.primary.pool.execute(commands)
+    }
+
+    /// Set a hash field to arbitrary bytes, such as a msgpack blob.
+    /// Returns whether the field is new.
+    pub fn hset_bytes(&self, key: &str, field: &str, value: &[u8]) -> Result<bool, String> {
+        let reply = self.execute(&format!("HSETBIN {} {} {}", key, field, base64::encode(value)))?;
+        match reply.strip_prefix("OK: ") {
+            Some(message) => Ok(message.starts_with("Created")),
+            None => Err(reply.trim_end().to_string()),
+        }
+    }
+
+    /// Read a hash field written with `hset_bytes` (or any other field) as
+    /// bytes.
+    pub fn hget_bytes(&self, key: &str, field: &str) -> Result<Option<Vec<u8>>, String> {
+        let reply = self.execute(&format!("HGETBIN {} {}", key, field))?;
+        if reply.starts_with("NULL") {
+            return Ok(None);
+        }
+        match reply.trim_end().split_once(" = ") {
+            Some((_, encoded)) if reply.starts_with("OK: ") => base64::decode(encoded).map(Some),
+            _ => Err(reply.trim_end().to_string()),
+        }
+    }
+
+    /// PING every replica, returning each address and whether it answered.
+    /// Replicas that answer go back into rotation straight away.
+    pub fn check_health(&self) -> Vec<(String, bool)> {
+        self.replicas
+            .iter()
+            .map(|replica| {
+                let healthy = matches!(replica.pool.execute(&["PING"]).as_deref(), Ok([pong]) if pong.starts_with("PONG"));
+                replica.mark(healthy, self.retry_after);
+                (replica.address.clone(), healthy)
+            })
+            .collect()
+    }
+
+    fn pick_replica(&self) -> Option<&Node> {
+        let start = self.next_replica.fetch_add(1, Ordering::Relaxed);
+        (0..self.replicas.len())
+            .map(|offset| &self.replicas[(start + offset) % self.replicas.len()])
+            .find(|replica| replica.is_healthy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoints() {
+        let endpoints = parse_endpoints("primary=a:1, replica=b:2,c:3,").unwrap();
+        assert_eq!(
+            endpoints,
+            vec![
+                Endpoint { address: "a:1".to_string(), role: Role::Primary },
+                Endpoint { address: "b:2".to_string(), role: Role::Replica },
+                Endpoint { address: "c:3".to_string(), role: Role::Primary },
+            ]
+        );
+        assert!(parse_endpoints("leader=a:1").is_err());
+
+        assert!(Client::new(&endpoints).is_err());
+        assert!(Client::new(&endpoints[1..2]).is_err());
+        assert!(Client::new(&endpoints[..2]).is_ok());
+    }
+}
@@ -1,416 +1,2986 @@
-use crate::store::Store;
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
-use std::time::Duration;
+use crate::base64;
+use crate::command::{self, deadline_timeout, Command, ParseError};
+use crate::command_table::{self, CommandKind};
+use crate::config::{self, ConfigOption, OptionKind};
+use crate::crdt;
+use crate::fairness::Scheduler;
+use crate::handoff;
+use crate::history::CommandContext;
+use crate::latency::CommandFamily;
+use crate::lifecycle::{ClientControl, ClientInfo, Lifecycle, UnblockMode};
+use crate::protocol::{self, Protocol, Request};
+use crate::pubsub::{self, Message};
+use crate::rdb;
+use crate::net;
+use crate::replication;
+use crate::sentinel;
+use crate::schedule;
+use crate::reply::Reply;
+use crate::resp;
+use crate::snapshot;
+use crate::stats;
+use crate::stream::{Entry, Fields, StreamId};
+use crate::store::{Store, StringUnit, WaitCondition, SIZE_BUCKETS, SIZE_OVERFLOW, TTL_BUCKETS, TTL_OVERFLOW};
+use crate::telemetry::{ActiveSpan, Tracer};
+use crate::tenant::{self, TenantQuota};
+use crate::timeseries::{now_millis, Aggregation};
+use crate::vector::Metric;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write as _;
+use std::future::{poll_fn, Future};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::net::TcpStream as StdTcpStream;
+use std::path::{Component, Path, PathBuf};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
 
+const DEFAULT_DRAIN_GRACE_SECS: u64 = 30;
 
+/// How long a new connection may stay silent before it is taken for a
+/// text client waiting for the greeting.
+const PROTOCOL_SNIFF_WINDOW: Duration = Duration::from_millis(100);
 
-pub fn handle_client_with_timeout(stream: TcpStream, store: Store, enable_timeouts: bool, timeout: Duration) {
-    let client_addr = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "unknown".to_string());
-    println!("New client connected: {}", client_addr);
+/// Room made in a connection's input buffer before each read.
+const READ_CHUNK: usize = 16 * 1024;
 
-    if enable_timeouts {
-        if let Err(e) = stream.set_read_timeout(Some(timeout)) {
-            eprintln!("Failed to set read timeout: {}", e);
-            return;
+/// Replies held back while pipelined commands run, before they are sent
+/// anyway.
+const WRITE_BATCH: usize = 64 * 1024;
+
+/// Per-connection state that outlives a single command.
+struct Session {
+    lifecycle: Lifecycle,
+    client_id: u64,
+    notices: bool,
+    drain_notice_sent: bool,
+    alarms_seen: u64,
+    no_touch: bool,
+    framed: bool,
+    control: Arc<ClientControl>,
+    transaction: Option<Transaction>,
+    ephemeral: bool,
+    // Keys created while ephemeral, deleted when the connection closes
+    ephemeral_keys: HashSet<String>,
+    // Channels this connection receives messages from, through `outbox`
+    subscriptions: BTreeSet<String>,
+    outbox: mpsc::Sender<Message>,
+}
+
+/// Commands queued between `MULTI` and `EXEC`, as their arguments.
+#[derive(Default)]
+struct Transaction {
+    queued: Vec<Vec<String>>,
+    // A command was refused while queueing, so EXEC runs none of them
+    aborted: bool,
+}
+
+/// Bytes read from a connection that no request has used yet.
+#[derive(Default)]
+struct Input {
+    buffer: Vec<u8>,
+    start: usize,
+}
+
+impl Input {
+    fn pending(&self) -> &[u8] {
+        &self.buffer[self.start..]
+    }
+
+    fn consume(&mut self, length: usize) {
+        self.start += length;
+    }
+
+    // Read more from the client; false once it has gone, or has been idle
+    // for longer than `timeout`
+    async fn fill(&mut self, reader: &mut OwnedReadHalf, timeout: Option<Duration>) -> bool {
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.reserve(READ_CHUNK);
+        let read = reader.read_buf(&mut self.buffer);
+        let result = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, read).await {
+                Ok(result) => result,
+                Err(_) => return false,
+            },
+            None => read.await,
+        };
+        matches!(result, Ok(read) if read > 0)
+    }
+}
+
+/// What woke a connection waiting for its next request.
+enum Wake {
+    /// More input, or false once the client has gone or been idle too long.
+    Input(bool),
+    /// A message published to a channel the connection subscribes to.
+    Message(Message),
+}
+
+// Wait for input or a published message, whichever comes first. Reads
+// are cancel-safe, so a message arriving mid-wait loses no input.
+async fn next_wake(input: &mut Input, reader: &mut OwnedReadHalf, timeout: Option<Duration>, inbox: &mut mpsc::Receiver<Message>) -> Wake {
+    let mut fill = pin!(input.fill(reader, timeout));
+    poll_fn(|cx| {
+        if let Poll::Ready(Some(message)) = inbox.poll_recv(cx) {
+            return Poll::Ready(Wake::Message(message));
         }
+        fill.as_mut().poll(cx).map(Wake::Input)
+    })
+    .await
+}
+
+fn write_message(output: &mut Vec<u8>, protocol: Protocol, framed: bool, (channel, payload): &Message) {
+    if protocol == Protocol::Resp {
+        resp::message(channel, payload).encode(output);
+        return;
+    }
+    let line = format!("MESSAGE: {} {}\n", channel, payload);
+    if framed {
+        output.extend_from_slice(format!("FRAME {}\n", line.len()).as_bytes());
+    }
+    output.extend_from_slice(line.as_bytes());
+}
+
+// Send the replies batched so far; false once the client has gone
+async fn flush(writer: &mut OwnedWriteHalf, output: &mut Vec<u8>) -> bool {
+    if output.is_empty() {
+        return true;
+    }
+    let written = writer.write_all(output).await.is_ok();
+    output.clear();
+    written
+}
+
+/// Where the next request in the input ends.
+enum Pending {
+    /// A text protocol or inline line, this many bytes long.
+    Line(usize),
+    /// A RESP array, already split into arguments, and its length.
+    Command(Vec<String>, usize),
+}
+
+fn next_request(protocol: Protocol, input: &[u8]) -> Result<Option<Pending>, String> {
+    // RESP requests are arrays of bulk strings; anything else is an inline
+    // command, parsed like a text protocol line
+    if protocol == Protocol::Resp && input.first() == Some(&b'*') {
+        return Ok(resp::parse_command(input)?.map(|(args, used)| Pending::Command(args, used)));
     }
+    Ok(input.iter().position(|byte| *byte == b'\n').map(|end| Pending::Line(end + 1)))
+}
 
-    let read_stream = match stream.try_clone() {
+/// Serve one client connection until it closes. Reads and writes are
+/// async, so an idle connection costs no thread; each command then runs to
+/// completion on the runtime worker, or in `block_in_place` when it may
+/// wait (blocking and admin commands, EXEC, or a scheduler turn).
+pub async fn handle_client_with_timeout(
+    stream: StdTcpStream,
+    store: Store,
+    timeout: Option<Duration>,
+    tracer: Tracer,
+    lifecycle: Lifecycle,
+    scheduler: Scheduler,
+    protocol: Protocol,
+) {
+    let client_addr = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "unknown".to_string());
+    println!("New client connected: {}", client_addr);
+
+    let mut connection_span = tracer.start_span("medusa.connection", None);
+    connection_span.set_string("net.peer.name", &client_addr);
+    let mut commands_processed = 0;
+
+    // CLIENT KILL shuts this clone down to end the connection
+    let attached = stream.try_clone();
+    let stream = match stream.set_nonblocking(true).and_then(|()| TcpStream::from_std(stream)) {
         Ok(stream) => stream,
         Err(e) => {
-            eprintln!("Failed to clone stream: {}", e);
+            eprintln!("Failed to register stream: {}", e);
             return;
         }
     };
-    let mut write_stream = stream;
+    let (mut reader, mut write_stream) = stream.into_split();
+    let mut input = Input::default();
+
+    // Redis clients send a command straight away, while text clients may
+    // wait for the greeting first, so a connection that stays silent is a
+    // text one
+    let protocol = match protocol {
+        Protocol::Auto => {
+            let sniffed = tokio::time::timeout(PROTOCOL_SNIFF_WINDOW, async {
+                loop {
+                    if let Some(detected) = protocol::detect(input.pending()) {
+                        return detected;
+                    }
+                    if !input.fill(&mut reader, None).await {
+                        return Protocol::Text;
+                    }
+                }
+            });
+            sniffed.await.unwrap_or(Protocol::Text)
+        }
+        fixed => fixed,
+    };
 
-    let welcome_msg = "Medusa server ready\n";
+    // Redis clients speak first, so only text clients are greeted
+    if protocol == Protocol::Text {
+        let welcome_msg = "Medusa server ready\n";
 
-    let _ = write_stream.write_all(welcome_msg.as_bytes());
-    let _ = write_stream.flush();
+        let _ = write_stream.write_all(welcome_msg.as_bytes()).await;
+    }
 
-    let mut reader = BufReader::new(read_stream);
-    let mut buffer = String::new();
+    let mut line = Vec::new();
+    let mut reply = String::new();
+    // Replies to commands that arrived together go out in one write
+    let mut output = Vec::new();
+    let client_id = lifecycle.register_client(&client_addr);
+    if let Ok(stream) = attached {
+        lifecycle.attach_stream(client_id, stream);
+    }
+    let control = lifecycle.control(client_id).unwrap_or_default();
+    // Where a connection handed over after SYNC or CRDTSYNC goes
+    let mut handoff_to: Option<fn(std::net::TcpStream, Store, Lifecycle)> = None;
+    let mut turn = scheduler.client(client_id);
+    let (outbox, mut inbox) = mpsc::channel(pubsub::SUBSCRIBER_BACKLOG);
+    let mut session = Session {
+        lifecycle,
+        client_id,
+        notices: false,
+        drain_notice_sent: false,
+        alarms_seen: store.alarms().last_event(),
+        no_touch: false,
+        framed: false,
+        control,
+        transaction: None,
+        ephemeral: false,
+        ephemeral_keys: HashSet::new(),
+        subscriptions: BTreeSet::new(),
+        outbox,
+    };
 
-    loop {
-        buffer.clear();
+    'connection: loop {
+        let pending = loop {
+            match next_request(protocol, input.pending()) {
+                Ok(Some(pending)) => break pending,
+                Ok(None) => {}
+                Err(e) => {
+                    // The rest of the stream cannot be trusted after a framing error
+                    resp::encode_reply(&Reply::error(e), &mut output);
+                    break 'connection;
+                }
+            }
+            if !flush(&mut write_stream, &mut output).await {
+                break 'connection;
+            }
+            // Subscribers may wait quietly for messages as long as they like
+            let timeout = if session.subscriptions.is_empty() { timeout } else { None };
+            match next_wake(&mut input, &mut reader, timeout, &mut inbox).await {
+                Wake::Input(true) => {}
+                Wake::Input(false) => {
+                    // A last line without a newline still runs
+                    match input.pending() {
+                        [] | [b'*', ..] => break 'connection,
+                        rest => break Pending::Line(rest.len()),
+                    }
+                }
+                Wake::Message(message) => {
+                    write_message(&mut output, protocol, session.framed, &message);
+                    while let Ok(message) = inbox.try_recv() {
+                        write_message(&mut output, protocol, session.framed, &message);
+                    }
+                }
+            }
+        };
 
-        match reader.read_line(&mut buffer) {
-            Ok(0) => break,
-            Ok(_) => {
-                let message = buffer.trim();
-                if message.is_empty() {
+        // A RESP request keeps its arguments as sent, empty ones and ones
+        // with spaces included; its joined line is only for logs
+        let (resp_args, resp_command);
+        let parsed = match pending {
+            Pending::Command(args, used) => {
+                input.consume(used);
+                if args.is_empty() {
+                    continue;
+                }
+                resp_command = args.join(" ");
+                resp_args = Some(args);
+                Ok(Some(Request { tag: None, command: resp_command.as_str() }))
+            }
+            Pending::Line(length) => {
+                resp_args = None;
+                line.clear();
+                line.extend_from_slice(&input.pending()[..length]);
+                input.consume(length);
+                protocol::parse_line(&line)
+            }
+        };
+        let (tag, message) = match parsed {
+            Ok(Some(request)) => (request.tag, request.command),
+            Ok(None) => continue,
+            Err(e) => {
+                let error = Reply::error(e);
+                if protocol == Protocol::Resp {
+                    resp::encode_reply(&error, &mut output);
                     continue;
                 }
+                let mut error = error.to_string();
+                if session.framed {
+                    error.insert_str(0, &format!("FRAME {}\n", error.len()));
+                }
+                output.extend_from_slice(error.as_bytes());
+                continue;
+            }
+        };
 
-                let response = process_command(message, &store);
+        // A replica's SYNC hands the connection over to replication, and a
+        // multi-primary peer's CRDTSYNC to merging its changes
+        if protocol == Protocol::Text && message.eq_ignore_ascii_case("SYNC") {
+            handoff_to = Some(replication::serve_replica);
+            break;
+        }
+        if protocol == Protocol::Text && message.eq_ignore_ascii_case("CRDTSYNC") {
+            handoff_to = Some(crdt::serve_peer);
+            break;
+        }
 
-                if write_stream.write_all(response.as_bytes()).is_err() {
-                    break;
-                }
-                let _ = write_stream.flush();
+        let args: Vec<&str> = match &resp_args {
+            Some(args) => args.iter().map(String::as_str).collect(),
+            None => message.split_whitespace().collect(),
+        };
 
-                if matches!(message.to_lowercase().as_str(), "quit" | "exit") {
-                    break;
-                }
+        // Injected faults spare DEBUG itself so they can always be cleared
+        if !args.first().is_some_and(|name| name.eq_ignore_ascii_case("DEBUG")) {
+            if store.faults().should_drop() {
+                println!("Dropping client {} (injected fault)", client_addr);
+                break;
+            }
+            if let Some(latency) = store.faults().next_latency() {
+                tokio::time::sleep(latency).await;
             }
-            Err(_) => break,
         }
-    }
-}
 
-fn process_command(command: &str, store: &Store) -> String {
-    let parts: Vec<&str> = command.split_whitespace().collect();
+        let kind = command_table::kind(&args);
+        let blocking = kind == Some(CommandKind::Blocking);
+        let backlog = !input.pending().is_empty() && !blocking;
+        let exec = session.transaction.is_some() && args.first().is_some_and(|name| name.eq_ignore_ascii_case("EXEC"));
+        let may_wait = !matches!(kind, Some(CommandKind::Read | CommandKind::Write)) || exec || (backlog && scheduler.quantum() > 0);
+        // Earlier replies are not held back while a command waits
+        if blocking && !flush(&mut write_stream, &mut output).await {
+            break;
+        }
 
-    if parts.is_empty() {
-        return "ERROR: Empty command\n".to_string();
-    }
+        // The fast GET path writes `reply` itself; everything else
+        // answers with a typed reply, written out below
+        let mut typed = None;
+        let mut run = || {
+            // A client with more commands already buffered takes turns
+            // with the other pipelining clients. Blocking commands give
+            // up the turn so they do not hold the others up while waiting.
+            turn.before_command(backlog);
 
-    match parts[0].to_uppercase().as_str() {
-        "SET" => {
-            if parts.len() < 3 {
-                return "ERROR: SET requires key and value (SET key value [TTL seconds])\n".to_string();
-            }
-            let key = parts[1];
-            let value = parts[2..].join(" ");
+            // Blocking commands hold no gate while they wait, so they
+            // cannot hold up an EXEC
+            let exclusive = exec.then(|| store.exec_gate().write().ok());
+            let shared = (!exec && !blocking).then(|| store.exec_gate().read().ok());
 
-            // Check if TTL is provided
-            if let Some(ttl_part) = parts.last() {
-                if let Ok(ttl_seconds) = ttl_part.parse::<u64>() {
-                    // Remove TTL from value if it was parsed as TTL
-                    let value_without_ttl = parts[2..parts.len()-1].join(" ");
-                    match store.set_with_ttl(key, &value_without_ttl, ttl_seconds) {
-                        Ok(_) => format!("OK: Set '{}' = '{}' with TTL {}s\n", key, value_without_ttl, ttl_seconds),
-                        Err(e) => format!("ERROR: Failed to set value: {}\n", e),
+            let operation = args.first().copied().unwrap_or("");
+            let started = Instant::now();
+            reply.clear();
+            let fast = !tracer.is_enabled()
+                && session.transaction.is_none()
+                && session.subscriptions.is_empty()
+                && plain_get_key(&args).is_some_and(|key| write_get_reply(&store, key, !session.no_touch, protocol, &mut reply));
+            if !fast {
+                let context = store.history().is_enabled().then(|| CommandContext::enter(&operation.to_uppercase(), &client_addr, tag));
+                typed = Some(if tracer.is_enabled() {
+                    let mut command_span = tracer.start_span("medusa.command", Some(&connection_span));
+                    let response = process_command(&args, &store, &mut session);
+                    record_command(&mut command_span, message, &response);
+                    if let Some(tag) = tag {
+                        command_span.set_string("medusa.request_tag", tag);
                     }
+                    command_span.finish(&tracer);
+                    response
                 } else {
-                    match store.set(key, &value) {
-                        Ok(_) => format!("OK: Set '{}' = '{}'\n", key, value),
-                        Err(e) => format!("ERROR: Failed to set value: {}\n", e),
-                    }
+                    process_command(&args, &store, &mut session)
+                });
+                drop(context);
+            }
+            drop((exclusive, shared));
+            let elapsed = started.elapsed();
+            store.slowlog().record_tagged(message, elapsed, &client_addr, tag);
+            store.latency().record(CommandFamily::of(&args), elapsed);
+            session.lifecycle.client_command(client_id, operation);
+        };
+        if may_wait {
+            tokio::task::block_in_place(run);
+        } else {
+            run();
+        }
+        commands_processed += 1;
+        if protocol == Protocol::Resp {
+            match &typed {
+                Some(typed) => resp::encode_reply(typed, &mut output),
+                None => output.extend_from_slice(reply.as_bytes()),
+            }
+        } else {
+            if let Some(typed) = &typed {
+                let _ = write!(reply, "{}", typed);
+            }
+            // Clients that negotiated notices hear about alarms and a
+            // drain ahead of their next reply
+            if session.notices {
+                let (events, last) = store.alarms().events_since(session.alarms_seen);
+                session.alarms_seen = last;
+                for event in events.iter().rev() {
+                    reply.insert_str(0, &format!("NOTICE: {}\n", event));
                 }
-            } else {
-                // No TTL provided
-                match store.set(key, &value) {
-                    Ok(_) => format!("OK: Set '{}' = '{}'\n", key, value),
-                    Err(e) => format!("ERROR: Failed to set value: {}\n", e),
+            }
+            if session.notices && !session.drain_notice_sent {
+                if let Some(remaining) = session.lifecycle.drain_remaining() {
+                    reply.insert_str(
+                        0,
+                        &format!("NOTICE: Server is closing in {}s, please reconnect elsewhere\n", remaining.as_secs()),
+                    );
+                    session.drain_notice_sent = true;
                 }
             }
-        }
 
-        "GET" => {
-            if parts.len() < 2 {
-                return "ERROR: GET requires a key (GET key)\n".to_string();
+            if session.framed {
+                output.extend_from_slice(format!("FRAME {}\n", reply.len()).as_bytes());
             }
-            let key = parts[1];
+            output.extend_from_slice(reply.as_bytes());
+        }
+        if output.len() >= WRITE_BATCH && !flush(&mut write_stream, &mut output).await {
+            break;
+        }
 
-            match store.get(key) {
-                Ok(Some(value)) => format!("OK: '{}' = {}\n", key, value),
-                Ok(None) => format!("NULL: Key '{}' not found or expired\n", key),
-                Err(e) => format!("ERROR: Failed to get value: {}\n", e),
-            }
+        if message.eq_ignore_ascii_case("quit") || message.eq_ignore_ascii_case("exit") || session.control.is_killed() {
+            break;
         }
 
-        "DELETE" => {
-            if parts.len() < 2 {
-                return "ERROR: DELETE requires a key (DELETE key)\n".to_string();
+        if session.lifecycle.drain_expired() {
+            if protocol == Protocol::Text {
+                output.extend_from_slice(b"NOTICE: Server is closing, goodbye\n");
             }
-            let key = parts[1];
+            break;
+        }
+    }
+    flush(&mut write_stream, &mut output).await;
+
+    for key in &session.ephemeral_keys {
+        let _ = store.delete(key);
+    }
+    store.pubsub().unsubscribe_all(client_id);
+    session.lifecycle.unregister_client(client_id);
+    connection_span.set_int("medusa.commands_processed", commands_processed);
+    connection_span.finish(&tracer);
 
-            match store.delete(key) {
-                Ok(Some(value)) => format!("OK: Deleted '{}' (was '{}')\n", key, value),
-                Ok(None) => format!("NULL: Key '{}' not found\n", key),
-                Err(e) => format!("ERROR: Failed to delete: {}\n", e),
+    if let Some(serve) = handoff_to {
+        match reader.reunite(write_stream).map_err(|e| e.to_string()).and_then(|stream| stream.into_std().map_err(|e| e.to_string())) {
+            Ok(stream) if stream.set_nonblocking(false).is_ok() => {
+                let lifecycle = session.lifecycle.clone();
+                std::thread::spawn(move || serve(stream, store, lifecycle));
             }
+            Ok(_) => eprintln!("Failed to hand {} over to replication", client_addr),
+            Err(e) => eprintln!("Failed to hand {} over to replication: {}", client_addr, e),
         }
+    }
+}
 
-        "EXISTS" => {
-            if parts.len() < 2 {
-                return "ERROR: EXISTS requires a key (EXISTS key)\n".to_string();
-            }
-            let key = parts[1];
+// Queue a command inside MULTI. Commands that could never run are refused
+// now and doom the transaction; errors while running are left to EXEC.
+fn queue_command(transaction: &mut Transaction, parts: &[&str], store: &Store) -> Reply {
+    let name = parts[0].to_uppercase();
+    let known = !command_table::lookup(&name).is_empty() || store.aliases().expand(parts).is_some() || store.commands().contains(&name);
+    let refusal = if !known {
+        Some(Reply::error(format!("Unknown command '{}'", name)))
+    } else if ["CLIENT", "SUBSCRIBE", "UNSUBSCRIBE"].contains(&name.as_str()) || command_table::kind(parts) == Some(CommandKind::Blocking) {
+        Some(Reply::error(format!("{} cannot be used inside MULTI", name)))
+    } else {
+        None
+    };
 
-            match store.exists(key) {
-                Ok(true) => format!("TRUE: Key '{}' exists\n", key),
-                Ok(false) => format!("FALSE: Key '{}' does not exist\n", key),
-                Err(e) => format!("ERROR: Failed to check existence: {}\n", e),
-            }
+    match refusal {
+        Some(refusal) => {
+            transaction.aborted = true;
+            refusal
         }
+        None => {
+            transaction.queued.push(parts.iter().map(|part| part.to_string()).collect());
+            Reply::status("QUEUED")
+        }
+    }
+}
 
-        "TTL" => {
-            if parts.len() < 2 {
-                return "ERROR: TTL requires a key (TTL key)\n".to_string();
-            }
-            let key = parts[1];
+// The key of a plain `GET key`, which `write_get_reply` can answer
+// without going through `process_command`
+fn plain_get_key<'a>(parts: &[&'a str]) -> Option<&'a str> {
+    match parts {
+        [name, key] if name.eq_ignore_ascii_case("GET") => Some(key),
+        _ => None,
+    }
+}
 
-            match store.ttl(key) {
-                Ok(Some(ttl)) => {
-                    if ttl == -1 {
-                        format!("TTL: Key '{}' has expired\n", key)
-                    } else {
-                        format!("TTL: Key '{}' expires in {} seconds\n", key, ttl)
-                    }
-                }
-                Ok(None) => format!("NULL: Key '{}' not found\n", key),
-                Err(e) => format!("ERROR: Failed to get TTL: {}\n", e),
+/// Answer `GET key` straight into `out` in `protocol`'s format, reading
+/// the value in place: no argument vector, no copy of the value and no
+/// reply allocation once `out` has grown. Returns false, leaving `out`
+/// empty, when the full path must handle it instead (tenant keys, values
+/// over the chunk threshold and errors), so replies are the same either way.
+pub(crate) fn write_get_reply(store: &Store, key: &str, touch: bool, protocol: Protocol, out: &mut String) -> bool {
+    if store.tenants().tenant_for_key(key).is_some() {
+        return false;
+    }
+    let threshold = store.chunk_threshold();
+    let written = store.read_string(key, touch, |value| {
+        if threshold.is_some_and(|threshold| value.len() > threshold) {
+            return false;
+        }
+        if protocol == Protocol::Resp {
+            let _ = write!(out, "${}\r\n", value.len());
+            out.push_str(value);
+            out.push_str("\r\n");
+        } else {
+            out.push_str("OK: '");
+            out.push_str(key);
+            out.push_str("' = ");
+            out.push_str(value);
+            out.push('\n');
+        }
+        true
+    });
+    match written {
+        Ok(Some(written)) => {
+            if written {
+                store.stats().record(key, true);
             }
+            written
         }
-
-        "EXPIRE" => {
-            if parts.len() < 3 {
-                return "ERROR: EXPIRE requires key and seconds (EXPIRE key seconds)\n".to_string();
+        Ok(None) => {
+            store.stats().record(key, false);
+            if protocol == Protocol::Resp {
+                out.push_str("$-1\r\n");
+            } else {
+                out.push_str("NULL: Key '");
+                out.push_str(key);
+                out.push_str("' not found or expired\n");
             }
-            let key = parts[1];
-            let ttl_seconds = match parts[2].parse::<u64>() {
-                Ok(seconds) => seconds,
-                Err(_) => return "ERROR: Invalid TTL value\n".to_string(),
-            };
+            true
+        }
+        Err(_) => false,
+    }
+}
 
-            match store.expire(key, ttl_seconds) {
-                Ok(true) => format!("OK: Set expiration for '{}' to {} seconds\n", key, ttl_seconds),
-                Ok(false) => format!("FALSE: Key '{}' not found\n", key),
-                Err(e) => format!("ERROR: Failed to set expiration: {}\n", e),
-            }
+const CLIENT_KILL_SYNTAX: &str =
+    "CLIENT KILL [ID id] [ADDR ip:port] [LADDR ip:port] [USER name] [TYPE normal|replica|master|pubsub] [IDLE secs] [MAXAGE secs]";
+
+/// One `CLIENT KILL` selector; a client is killed when it matches all of them.
+enum KillFilter {
+    Id(u64),
+    Addr(String),
+    Laddr(String),
+    /// Every connection is the `default` user, as in Redis without ACLs.
+    User(String),
+    /// `normal`, `pubsub`, `replica` or `master`, as `client_type` names them.
+    Type(&'static str),
+    Idle(Duration),
+    MaxAge(Duration),
+}
+
+impl KillFilter {
+    fn matches(&self, client: &ClientInfo) -> bool {
+        match self {
+            KillFilter::Id(id) => client.id == *id,
+            KillFilter::Addr(addr) => client.addr == *addr,
+            KillFilter::Laddr(laddr) => client.laddr == *laddr,
+            KillFilter::User(user) => user == "default",
+            KillFilter::Type(kind) => client_type(client) == *kind,
+            KillFilter::Idle(idle) => client.last_active.elapsed() >= *idle,
+            KillFilter::MaxAge(age) => client.connected_at.elapsed() >= *age,
         }
+    }
+}
 
-        "LIST" => match store.list_keys() {
-            Ok(keys) => {
-                if keys.is_empty() {
-                    "OK: No keys found\n".to_string()
-                } else {
-                    format!("OK: Keys: {}\n", keys.join(", "))
-                }
-            }
-            Err(e) => format!("ERROR: Failed to list keys: {}\n", e),
+// The connection type `CLIENT KILL TYPE` selects on
+fn client_type(client: &ClientInfo) -> &'static str {
+    if client.replica {
+        "replica"
+    } else if client.primary_link {
+        "master"
+    } else if client.subscriber {
+        "pubsub"
+    } else {
+        "normal"
+    }
+}
+
+fn parse_kill_filter(name: &str, value: &str) -> Result<KillFilter, String> {
+    let seconds = || value.parse::<u64>().map(Duration::from_secs).map_err(|_| format!("Invalid {} '{}', expected seconds", name.to_uppercase(), value));
+    match name.to_uppercase().as_str() {
+        "ID" => value.parse().map(KillFilter::Id).map_err(|_| format!("Invalid client id '{}'", value)),
+        "ADDR" => Ok(KillFilter::Addr(value.to_string())),
+        "LADDR" => Ok(KillFilter::Laddr(value.to_string())),
+        "USER" => Ok(KillFilter::User(value.to_string())),
+        "TYPE" => match value.to_lowercase().as_str() {
+            "normal" => Ok(KillFilter::Type("normal")),
+            "pubsub" => Ok(KillFilter::Type("pubsub")),
+            "replica" | "slave" => Ok(KillFilter::Type("replica")),
+            "master" => Ok(KillFilter::Type("master")),
+            _ => Err(format!("Unknown client type '{}'", value)),
         },
+        "IDLE" => seconds().map(KillFilter::Idle),
+        "MAXAGE" => seconds().map(KillFilter::MaxAge),
+        _ => Err(format!("Unknown CLIENT KILL filter '{}'", name)),
+    }
+}
 
-        "KEYS" => {
-            if parts.len() < 2 {
-                return "ERROR: KEYS requires a pattern (KEYS pattern)\n".to_string();
-            }
-            let pattern = parts[1];
+fn record_command(span: &mut ActiveSpan, command: &str, response: &Reply) {
+    let response = response.to_string();
+    let mut parts = command.split_whitespace();
+    let operation = parts.next().unwrap_or("").to_uppercase();
+    let outcome = response.split(':').next().unwrap_or("").trim();
 
-            match store.keys(pattern) {
-                Ok(keys) => {
-                    if keys.is_empty() {
-                        format!("OK: No keys matching pattern '{}'\n", pattern)
-                    } else {
-                        format!("OK: Keys matching '{}': {}\n", pattern, keys.join(", "))
-                    }
-                }
-                Err(e) => format!("ERROR: Failed to find keys: {}\n", e),
-            }
+    span.set_string("db.system", "medusa");
+    span.set_string("db.operation", &operation);
+    if let Some(key) = parts.next() {
+        span.set_string("db.medusa.key", key);
+    }
+    span.set_string("medusa.outcome", outcome);
+    span.set_int("medusa.request_bytes", command.len() as i64);
+    span.set_int("medusa.response_bytes", response.len() as i64);
+    span.set_error(outcome == "ERROR");
+}
+
+fn process_command(parts: &[&str], store: &Store, session: &mut Session) -> Reply {
+    if !session.ephemeral {
+        return run_command(parts, store, session);
+    }
+
+    // On an ephemeral connection, remember which keys a write brought into
+    // existence so they can be deleted when it closes
+    let growing = parts.first().is_some_and(|name| tenant::is_growing_command(&name.to_uppercase()));
+    let missing: Vec<String> = if growing {
+        let keys = command_table::get_keys(parts).unwrap_or_default();
+        keys.into_iter().filter(|key| store.exists(key) == Ok(false)).map(String::from).collect()
+    } else {
+        Vec::new()
+    };
+    let reply = run_command(parts, store, session);
+    for key in missing {
+        if store.exists(&key) == Ok(true) {
+            session.ephemeral_keys.insert(key);
         }
+    }
+    reply
+}
 
-        "COUNT" => match store.count() {
-            Ok(count) => format!("OK: {} entries\n", count),
-            Err(e) => format!("ERROR: Failed to count entries: {}\n", e),
-        },
+fn run_command(parts: &[&str], store: &Store, session: &mut Session) -> Reply {
+    if parts.is_empty() {
+        return Reply::error("Empty command");
+    }
 
-        "CLEAR" | "FLUSHALL" => match store.clear() {
-            Ok(_) => "OK: All entries cleared\n".to_string(),
-            Err(e) => format!("ERROR: Failed to clear: {}\n", e),
-        },
+    // A subscribed connection only receives messages until it unsubscribes
+    if !session.subscriptions.is_empty() && !["SUBSCRIBE", "UNSUBSCRIBE", "PING", "QUIT", "EXIT"].iter().any(|name| parts[0].eq_ignore_ascii_case(name)) {
+        return Reply::error("Only SUBSCRIBE, UNSUBSCRIBE, PING and QUIT are allowed while subscribed");
+    }
 
-        "INFO" => match store.info() {
-            Ok(info) => format!("OK: Server Info:\n{}\n", info),
-            Err(e) => format!("ERROR: Failed to get info: {}\n", e),
-        },
+    // A replica takes its data from the primary alone
+    if store.replication().is_replica() && command_table::kind(parts) == Some(CommandKind::Write) {
+        return Reply::error("This server is a read-only replica, send writes to its primary");
+    }
 
-        "PING" => "PONG\n".to_string(),
+    // Inside MULTI everything but the transaction commands is queued
+    if let Some(transaction) = &mut session.transaction {
+        if !["MULTI", "EXEC", "DISCARD", "QUIT", "EXIT"].iter().any(|name| parts[0].eq_ignore_ascii_case(name)) {
+            return queue_command(transaction, parts, store);
+        }
+    }
 
-        "QUIT" | "EXIT" => "OK: Goodbye!\n".to_string(),
+    // Aliases always expand to a built-in command, so this recurses once at most
+    if let Some(expanded) = store.aliases().expand(parts) {
+        let expanded: Vec<&str> = expanded.iter().map(String::as_str).collect();
+        return process_command(&expanded, store, session);
+    }
 
-        // Hash operations
-        "HSET" => {
+    // Keys inside a tenant's namespace count against that tenant's quota,
+    // as does the request's size, arguments and separators
+    let request_len = parts.iter().map(|part| part.len() + 1).sum::<usize>() - 1;
+    let keys = command_table::get_keys(parts).unwrap_or_default();
+    for key in &keys {
+        if let Err(e) = store.check_tenant_quota(&parts[0].to_uppercase(), key, request_len) {
+            return Reply::error(format!("Quota exceeded: {}", e));
+        }
+    }
+
+    // Writes that can grow the dataset first make room under MEDUSA_MAXMEMORY,
+    // and may not create keys beyond MEDUSA_MAX_KEYS. The room for new keys
+    // stays reserved until the command has run.
+    let _reservation = if tenant::is_growing_command(&parts[0].to_uppercase()) {
+        if let Err(e) = store.evict_if_needed(request_len) {
+            return Reply::error(e);
+        }
+        match store.reserve_keys(&keys) {
+            Ok(reservation) => Some(reservation),
+            Err(e) => return Reply::error(e),
+        }
+    } else {
+        None
+    };
+
+    // OBJECT inspects access times without changing them
+    if !session.no_touch && !keys.is_empty() && !parts[0].eq_ignore_ascii_case("OBJECT") {
+        let _ = store.touch(&keys);
+    }
+
+    match command::parse(parts) {
+        Ok(command) => return execute(command, store),
+        Err(ParseError::Unknown(_)) => {}
+        Err(e) => return Reply::error(e.to_string()),
+    }
+
+    match parts[0].to_uppercase().as_str() {
+        "GETCHUNK" => {
             if parts.len() < 4 {
-                return "ERROR: HSET requires key, field, and value (HSET key field value)\n".to_string();
+                return Reply::error("GETCHUNK requires key, offset and length (GETCHUNK key offset length)");
             }
             let key = parts[1];
-            let field = parts[2];
-            let value = parts[3..].join(" ");
+            let (offset, length) = match (parts[2].parse::<usize>(), parts[3].parse::<usize>()) {
+                (Ok(offset), Ok(length)) => (offset, length),
+                _ => return Reply::error("Offset and length must be non-negative numbers"),
+            };
 
-            match store.hset(key, field, &value) {
-                Ok(is_new) => {
-                    if is_new {
-                        format!("OK: Created new field '{}' in hash '{}'\n", field, key)
-                    } else {
-                        format!("OK: Updated field '{}' in hash '{}'\n", field, key)
-                    }
-                }
-                Err(e) => format!("ERROR: Failed to set hash field: {}\n", e),
+            match store.get_chunk(key, offset, length) {
+                Ok(Some((chunk, total))) => Reply::value(
+                    format!("'{}' bytes {}-{} of {} = {}", key, offset, offset + chunk.len(), total, chunk),
+                    chunk,
+                ),
+                Ok(None) => Reply::nil(format!("Key '{}' not found or expired", key)),
+                Err(e) => Reply::error(format!("Failed to get chunk: {}", e)),
             }
         }
 
-        "HGET" => {
-            if parts.len() < 3 {
-                return "ERROR: HGET requires key and field (HGET key field)\n".to_string();
+        "SETCHUNK" => {
+            if parts.len() < 4 {
+                return Reply::error("SETCHUNK requires key, offset and data (SETCHUNK key offset data)");
             }
             let key = parts[1];
-            let field = parts[2];
+            let offset = match parts[2].parse::<usize>() {
+                Ok(offset) => offset,
+                Err(_) => return Reply::error("Offset must be a non-negative number"),
+            };
 
-            match store.hget(key, field) {
-                Ok(Some(value)) => format!("OK: '{}:{}' = {}\n", key, field, value),
-                Ok(None) => format!("NULL: Field '{}' not found in hash '{}'\n", field, key),
-                Err(e) => format!("ERROR: Failed to get hash field: {}\n", e),
+            match store.set_chunk(key, offset, &parts[3..].join(" ")) {
+                Ok(length) => Reply::count(length, format!("'{}' is now {} bytes", key, length)),
+                Err(e) => Reply::error(format!("Failed to set chunk: {}", e)),
             }
         }
 
-        "HGETALL" => {
-            if parts.len() < 2 {
-                return "ERROR: HGETALL requires a key (HGETALL key)\n".to_string();
+        "STRLEN" => {
+            let (parts, unit) = string_unit(parts, 2);
+            if parts.len() != 2 {
+                return Reply::error("STRLEN requires a key (STRLEN key [UTF8])");
             }
-            let key = parts[1];
-
-            match store.hgetall(key) {
-                Ok(fields) => {
-                    if fields.is_empty() {
-                        format!("OK: Hash '{}' is empty\n", key)
-                    } else {
-                        let field_list: Vec<String> = fields.iter()
-                            .map(|(k, v)| format!("{}:{}", k, v))
-                            .collect();
-                        format!("OK: Hash '{}' fields: {}\n", key, field_list.join(", "))
-                    }
-                }
-                Err(e) => format!("ERROR: Failed to get hash: {}\n", e),
+            match store.strlen(parts[1], unit) {
+                Ok(length) => Reply::count(length, format!("'{}' is {} {}", parts[1], length, unit_name(unit))),
+                Err(e) => Reply::error(format!("Failed to get length: {}", e)),
             }
         }
 
-        "HDEL" => {
-            if parts.len() < 3 {
-                return "ERROR: HDEL requires key and field (HDEL key field)\n".to_string();
+        "GETRANGE" | "SUBSTR" => {
+            let (parts, unit) = string_unit(parts, 4);
+            if parts.len() != 4 {
+                let name = parts[0].to_uppercase();
+                return Reply::error(format!("{} requires key, start and end ({} key start end [UTF8])", name, name));
             }
             let key = parts[1];
-            let field = parts[2];
+            let (start, end) = match (parts[2].parse::<i64>(), parts[3].parse::<i64>()) {
+                (Ok(start), Ok(end)) => (start, end),
+                _ => return Reply::error("Start and end must be numbers"),
+            };
 
-            match store.hdel(key, field) {
-                Ok(true) => format!("OK: Deleted field '{}' from hash '{}'\n", field, key),
-                Ok(false) => format!("FALSE: Field '{}' not found in hash '{}'\n", field, key),
-                Err(e) => format!("ERROR: Failed to delete hash field: {}\n", e),
+            match store.getrange(key, start, end, unit) {
+                Ok(range) => Reply::value(format!("'{}' {} {} to {} = {}", key, unit_name(unit), start, end, range), range),
+                Err(e) => Reply::error(format!("Failed to get range: {}", e)),
             }
         }
 
-        "HEXISTS" => {
-            if parts.len() < 3 {
-                return "ERROR: HEXISTS requires key and field (HEXISTS key field)\n".to_string();
+        "SETRANGE" => {
+            let (parts, unit) = string_unit(parts, 4);
+            if parts.len() < 4 {
+                return Reply::error("SETRANGE requires key, offset and value (SETRANGE key offset value [UTF8])");
             }
             let key = parts[1];
-            let field = parts[2];
+            let offset = match parts[2].parse::<usize>() {
+                Ok(offset) => offset,
+                Err(_) => return Reply::error("Offset must be a non-negative number"),
+            };
 
-            match store.hexists(key, field) {
-                Ok(true) => format!("TRUE: Field '{}' exists in hash '{}'\n", field, key),
-                Ok(false) => format!("FALSE: Field '{}' does not exist in hash '{}'\n", field, key),
-                Err(e) => format!("ERROR: Failed to check hash field existence: {}\n", e),
+            match store.setrange(key, offset, &parts[3..].join(" "), unit) {
+                Ok(length) => Reply::count(length, format!("'{}' is now {} {}", key, length, unit_name(unit))),
+                Err(e) => Reply::error(format!("Failed to set range: {}", e)),
             }
         }
 
-        "HLEN" => {
-            if parts.len() < 2 {
-                return "ERROR: HLEN requires a key (HLEN key)\n".to_string();
+        "CAS" => {
+            if parts.len() < 4 {
+                return Reply::error("CAS requires key, expected and new value (CAS key expected new)");
             }
             let key = parts[1];
-
-            match store.hlen(key) {
-                Ok(len) => format!("OK: Hash '{}' has {} fields\n", key, len),
-                Err(e) => format!("ERROR: Failed to get hash length: {}\n", e),
+            match store.compare_and_swap(key, parts[2], &parts[3..].join(" ")) {
+                Ok(true) => Reply::bool(true, format!("'{}' swapped", key)),
+                Ok(false) => Reply::bool(false, format!("'{}' does not hold '{}'", key, parts[2])),
+                Err(e) => Reply::error(format!("Failed to compare and swap: {}", e)),
             }
         }
 
-        // List operations
-        "LPUSH" => {
+        "LCS" => {
             if parts.len() < 3 {
-                return "ERROR: LPUSH requires key and value (LPUSH key value)\n".to_string();
+                return Reply::error("LCS requires two keys (LCS key1 key2 [LEN] [IDX] [MINMATCHLEN n])");
+            }
+            let (mut len_only, mut idx, mut min_match_len) = (false, false, 0);
+            let mut options = parts[3..].iter();
+            while let Some(option) = options.next() {
+                match option.to_uppercase().as_str() {
+                    "LEN" => len_only = true,
+                    "IDX" => idx = true,
+                    "MINMATCHLEN" => match options.next().and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) => min_match_len = n,
+                        None => return Reply::error("MINMATCHLEN requires a number"),
+                    },
+                    other => return Reply::error(format!("Unknown LCS option '{}'", other)),
+                }
+            }
+            if len_only && idx {
+                return Reply::error("LEN and IDX cannot be combined");
             }
-            let key = parts[1];
-            let value = parts[2..].join(" ");
 
-            match store.lpush(key, &value) {
-                Ok(len) => format!("OK: Pushed to left of list '{}', new length: {}\n", key, len),
-                Err(e) => format!("ERROR: Failed to push to list: {}\n", e),
+            match store.lcs(parts[1], parts[2]) {
+                Ok(result) if len_only => {
+                    let length = result.sequence.chars().count();
+                    Reply::count(length, length)
+                }
+                Ok(result) if idx => {
+                    let matches: Vec<_> = result.matches.iter().filter(|m| m.len >= min_match_len).collect();
+                    let header = format!("LCS length {}, {} matches:", result.sequence.chars().count(), matches.len());
+                    Reply::list(
+                        header,
+                        matches.iter().map(|m| format!("{}={}-{} {}={}-{} len={}", parts[1], m.a.0, m.a.1, parts[2], m.b.0, m.b.1, m.len)),
+                    )
+                }
+                Ok(result) => Reply::value(format!("LCS of '{}' and '{}' = '{}'", parts[1], parts[2], result.sequence), result.sequence),
+                Err(e) => Reply::error(format!("Failed to compute LCS: {}", e)),
             }
         }
 
-        "RPUSH" => {
-            if parts.len() < 3 {
-                return "ERROR: RPUSH requires key and value (RPUSH key value)\n".to_string();
+        "OBJECT" => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+            Some("IDLETIME") if parts.len() >= 3 => match store.idle_time(parts[2]) {
+                Ok(Some(idle)) => Reply::count(idle.as_secs(), idle.as_secs()),
+                Ok(None) => Reply::nil(format!("Key '{}' not found", parts[2])),
+                Err(e) => Reply::error(format!("Failed to read idle time: {}", e)),
+            },
+            _ => Reply::error("OBJECT requires a subcommand (OBJECT IDLETIME key)"),
+        },
+
+        "PIN" => {
+            if parts.len() != 2 {
+                return Reply::error("PIN requires a key or pattern (PIN key|prefix*)");
             }
-            let key = parts[1];
-            let value = parts[2..].join(" ");
+            if store.eviction().pin(parts[1]) {
+                Reply::bool(true, format!("Pinned '{}'", parts[1]))
+            } else {
+                Reply::bool(false, format!("'{}' is already pinned", parts[1]))
+            }
+        }
 
-            match store.rpush(key, &value) {
-                Ok(len) => format!("OK: Pushed to right of list '{}', new length: {}\n", key, len),
-                Err(e) => format!("ERROR: Failed to push to list: {}\n", e),
+        "UNPIN" => {
+            if parts.len() != 2 {
+                return Reply::error("UNPIN requires a key or pattern (UNPIN key|prefix*)");
+            }
+            if store.eviction().unpin(parts[1]) {
+                Reply::bool(true, format!("Unpinned '{}'", parts[1]))
+            } else {
+                Reply::bool(false, format!("'{}' is not pinned", parts[1]))
             }
         }
 
-        "LPOP" => {
-            if parts.len() < 2 {
-                return "ERROR: LPOP requires a key (LPOP key)\n".to_string();
+        "PINNED" => {
+            let pinned = store.eviction().pinned();
+            Reply::list(format!("{} pinned:", pinned.len()), pinned)
+        }
+
+        "EXPIREMANY" => {
+            if parts.len() < 3 {
+                return Reply::error("EXPIREMANY requires seconds and keys (EXPIREMANY seconds key [key ...])");
             }
-            let key = parts[1];
+            let ttl_seconds = match parts[1].parse::<u64>() {
+                Ok(seconds) if deadline_timeout(Duration::from_secs(seconds)).is_some() => seconds,
+                _ => return Reply::error("Invalid TTL value"),
+            };
 
-            match store.lpop(key) {
-                Ok(Some(value)) => format!("OK: Popped from left of list '{}': {}\n", key, value),
-                Ok(None) => format!("NULL: List '{}' is empty\n", key),
-                Err(e) => format!("ERROR: Failed to pop from list: {}\n", e),
+            match store.expire_many(&parts[2..], ttl_seconds) {
+                Ok(updated) => Reply::ok(format!(
+                    "Set expiration for {} of {} keys to {} seconds",
+                    updated,
+                    parts.len() - 2,
+                    ttl_seconds
+                )),
+                Err(e) => Reply::error(format!("Failed to set expiration: {}", e)),
             }
         }
 
-        "RPOP" => {
-            if parts.len() < 2 {
-                return "ERROR: RPOP requires a key (RPOP key)\n".to_string();
+        "EXPIREPATTERN" => {
+            if parts.len() != 3 {
+                return Reply::error("EXPIREPATTERN requires pattern and seconds (EXPIREPATTERN pattern seconds)");
             }
-            let key = parts[1];
+            let pattern = parts[1];
+            let ttl_seconds = match parts[2].parse::<u64>() {
+                Ok(seconds) if deadline_timeout(Duration::from_secs(seconds)).is_some() => seconds,
+                _ => return Reply::error("Invalid TTL value"),
+            };
 
-            match store.rpop(key) {
-                Ok(Some(value)) => format!("OK: Popped from right of list '{}': {}\n", key, value),
-                Ok(None) => format!("NULL: List '{}' is empty\n", key),
-                Err(e) => format!("ERROR: Failed to pop from list: {}\n", e),
+            match store.expire_pattern(pattern, ttl_seconds) {
+                Ok(updated) => Reply::ok(format!(
+                    "Set expiration for {} keys matching '{}' to {} seconds",
+                    updated, pattern, ttl_seconds
+                )),
+                Err(e) => Reply::error(format!("Failed to set expiration: {}", e)),
             }
         }
 
-        "LLEN" => {
-            if parts.len() < 2 {
-                return "ERROR: LLEN requires a key (LLEN key)\n".to_string();
+        "WAITKEY" => {
+            if parts.len() < 3 || parts.len() > 4 {
+                return Reply::error("WAITKEY requires key and timeout (WAITKEY key seconds [CHANGE])");
             }
             let key = parts[1];
+            let Some(timeout) = timeout_secs(parts[2]) else {
+                return Reply::error("Invalid timeout (seconds, 0 waits forever)");
+            };
+            let condition = match parts.get(3) {
+                None => WaitCondition::Exists,
+                Some(mode) if mode.eq_ignore_ascii_case("CHANGE") => WaitCondition::Changed,
+                Some(mode) => return Reply::error(format!("Unknown WAITKEY mode '{}'", mode)),
+            };
+
+            let control = session.control.clone();
+            control.set_blocked(true);
+            let result = store.wait_for_key_until(key, condition, timeout, &|| control.interrupted());
+            let unblocked = control.unblock_mode();
+            control.set_blocked(false);
 
-            match store.llen(key) {
-                Ok(len) => format!("OK: List '{}' has {} items\n", key, len),
-                Err(e) => format!("ERROR: Failed to get list length: {}\n", e),
+            match result {
+                Ok(true) if condition == WaitCondition::Exists => Reply::bool(true, format!("Key '{}' exists", key)),
+                Ok(true) => Reply::bool(true, format!("Key '{}' changed", key)),
+                Ok(false) if unblocked == Some(UnblockMode::Error) || control.is_killed() => {
+                    Reply::error("Unblocked by an operator (CLIENT UNBLOCK or CLIENT KILL)")
+                }
+                Ok(false) => Reply::bool(false, format!("Timed out waiting for '{}'", key)),
+                Err(e) => Reply::error(format!("Failed to wait for key: {}", e)),
             }
         }
 
-        "LRANGE" => {
-            if parts.len() < 4 {
-                return "ERROR: LRANGE requires key, start, and stop (LRANGE key start stop)\n".to_string();
+        name @ ("BZPOPMIN" | "BZPOPMAX") => {
+            if parts.len() != 3 {
+                return Reply::error(format!("{} requires key and timeout ({} key seconds)", name, name));
+            }
+            if store.replication().is_replica() {
+                return Reply::error("This server is a read-only replica, send writes to its primary");
             }
             let key = parts[1];
-            let start = match parts[2].parse::<i64>() {
-                Ok(s) => s,
-                Err(_) => return "ERROR: Invalid start index\n".to_string(),
+            let Some(timeout) = timeout_secs(parts[2]) else {
+                return Reply::error("Invalid timeout (seconds, 0 waits forever)");
             };
-            let stop = match parts[3].parse::<i64>() {
-                Ok(s) => s,
-                Err(_) => return "ERROR: Invalid stop index\n".to_string(),
+            let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+            let pop = if name == "BZPOPMIN" { Store::zpopmin } else { Store::zpopmax };
+
+            let control = session.control.clone();
+            control.set_blocked(true);
+            // Another client can pop the member between the key appearing
+            // and this pop, in which case it goes back to waiting
+            let result = loop {
+                match pop(store, key, 1) {
+                    Ok(mut popped) if !popped.is_empty() => break Ok(popped.pop()),
+                    Ok(_) => {}
+                    Err(e) => break Err(e),
+                }
+                let remaining = match deadline {
+                    Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                        Some(remaining) if !remaining.is_zero() => Some(remaining),
+                        _ => break Ok(None),
+                    },
+                    None => None,
+                };
+                match store.wait_for_key_until(key, WaitCondition::Exists, remaining, &|| control.interrupted()) {
+                    Ok(true) => {}
+                    Ok(false) => break Ok(None),
+                    Err(e) => break Err(e),
+                }
             };
+            let unblocked = control.unblock_mode();
+            control.set_blocked(false);
 
-            match store.lrange(key, start, stop) {
-                Ok(items) => {
-                    if items.is_empty() {
-                        format!("OK: No items in range [{}, {}] for list '{}'\n", start, stop, key)
-                    } else {
-                        format!("OK: List '{}' range [{}, {}]: {}\n", key, start, stop, items.join(", "))
-                    }
+            // The key comes first, as Redis clients expect
+            match result {
+                Ok(Some((member, score))) => {
+                    Reply::list(format!("Popped '{}' from sorted set '{}':", member, key), [key.to_string(), member, score.to_string()])
                 }
-                Err(e) => format!("ERROR: Failed to get list range: {}\n", e),
+                Ok(None) if unblocked == Some(UnblockMode::Error) || control.is_killed() => {
+                    Reply::error("Unblocked by an operator (CLIENT UNBLOCK or CLIENT KILL)")
+                }
+                Ok(None) => Reply::nil(format!("Timed out waiting for sorted set '{}'", key)),
+                Err(e) => Reply::error(format!("Failed to pop from sorted set: {}", e)),
             }
         }
 
-        _ => {
-            format!("ERROR: Unknown command '{}'\n", parts[0])
-        }
-    }
+        "XREAD" => {
+            let usage = "XREAD requires streams and IDs (XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...])";
+            let (mut count, mut block) = (None, None);
+            let mut i = 1;
+            loop {
+                let option = parts.get(i).map(|option| option.to_uppercase());
+                let argument = parts.get(i + 1);
+                match (option.as_deref(), argument) {
+                    (Some("STREAMS"), _) => break,
+                    (Some("COUNT"), Some(n)) => match n.parse::<usize>() {
+                        Ok(n) => count = Some(n),
+                        Err(_) => return Reply::error(format!("Invalid count '{}'", n)),
+                    },
+                    (Some("BLOCK"), Some(ms)) => match timeout_millis(ms) {
+                        Some(timeout) => block = Some(timeout),
+                        None => return Reply::error("Invalid timeout (milliseconds, 0 waits forever)"),
+                    },
+                    (Some("COUNT" | "BLOCK") | None, _) => return Reply::error(usage),
+                    (Some(_), _) => return Reply::error(format!("Unknown XREAD option '{}'", parts[i])),
+                }
+                i += 2;
+            }
+            let streams = &parts[i + 1..];
+            if streams.is_empty() || !streams.len().is_multiple_of(2) {
+                return Reply::error(usage);
+            }
+            let (keys, ids) = streams.split_at(streams.len() / 2);
+
+            // `$` reads only what is added after this call
+            let mut after = Vec::new();
+            for (key, id) in keys.iter().zip(ids) {
+                let id = match *id {
+                    "$" => match store.xlast_id(key) {
+                        Ok(id) => id,
+                        Err(e) => return Reply::error(format!("Failed to read stream: {}", e)),
+                    },
+                    id => match command::parse_stream_id(id, 0) {
+                        Ok(id) => id,
+                        Err(e) => return Reply::error(e.to_string()),
+                    },
+                };
+                after.push((*key, id));
+            }
+
+            let mut unblocked = None;
+            let result = match block {
+                None => store.xread(&after, count),
+                Some(timeout) => {
+                    let control = session.control.clone();
+                    control.set_blocked(true);
+                    let mut read = Vec::new();
+                    let waited = store.wait_for_keys_until(keys, timeout, &|| control.interrupted(), &mut || {
+                        read = store.xread(&after, count)?;
+                        Ok(!read.is_empty())
+                    });
+                    unblocked = Some(control.unblock_mode() == Some(UnblockMode::Error) || control.is_killed());
+                    control.set_blocked(false);
+                    waited.map(|_| read)
+                }
+            };
+
+            match result {
+                Ok(read) if read.is_empty() => match unblocked {
+                    Some(true) => Reply::error("Unblocked by an operator (CLIENT UNBLOCK or CLIENT KILL)"),
+                    Some(false) => Reply::nil("Timed out waiting for new entries"),
+                    None => Reply::nil("No new entries"),
+                },
+                Ok(read) => streams_read(read),
+                Err(e) => Reply::error(format!("Failed to read stream: {}", e)),
+            }
+        }
+
+        "WAIT" => {
+            if parts.len() != 3 {
+                return Reply::error("WAIT requires a replica count and timeout (WAIT numreplicas milliseconds)");
+            }
+            if store.replication().is_replica() {
+                return Reply::error("WAIT cannot be used on a replica");
+            }
+            let Ok(count) = parts[1].parse::<usize>() else {
+                return Reply::error(format!("Invalid replica count '{}'", parts[1]));
+            };
+            let Some(timeout) = timeout_millis(parts[2]) else {
+                return Reply::error("Invalid timeout (milliseconds, 0 waits forever)");
+            };
+
+            let control = session.control.clone();
+            control.set_blocked(true);
+            let result = store.replication().wait_for_acks(count, timeout, &|| control.interrupted());
+            let unblocked = control.unblock_mode();
+            control.set_blocked(false);
+
+            match result {
+                Ok(_) if unblocked == Some(UnblockMode::Error) || control.is_killed() => {
+                    Reply::error("Unblocked by an operator (CLIENT UNBLOCK or CLIENT KILL)")
+                }
+                Ok(acked) => Reply::count(acked, acked),
+                Err(e) => Reply::error(format!("Failed to wait for replicas: {}", e)),
+            }
+        }
+
+        "LIST" => match store.list_keys() {
+            Ok(keys) => {
+                if keys.is_empty() {
+                    Reply::array("No keys found", keys)
+                } else {
+                    Reply::array(format!("Keys: {}", keys.join(", ")), keys)
+                }
+            }
+            Err(e) => Reply::error(format!("Failed to list keys: {}", e)),
+        },
+
+        "KEYS" => {
+            if parts.len() < 2 {
+                return Reply::error("KEYS requires a pattern (KEYS pattern)");
+            }
+            let pattern = parts[1];
+
+            match store.keys(pattern) {
+                Ok(keys) => {
+                    if keys.is_empty() {
+                        Reply::array(format!("No keys matching pattern '{}'", pattern), keys)
+                    } else {
+                        Reply::array(format!("Keys matching '{}': {}", pattern, keys.join(", ")), keys)
+                    }
+                }
+                Err(e) => Reply::error(format!("Failed to find keys: {}", e)),
+            }
+        }
+
+        "SCAN" => {
+            let cursor = match parts.get(1).map(|cursor| cursor.parse::<u64>()) {
+                Some(Ok(cursor)) => cursor,
+                _ => return Reply::error("SCAN requires a cursor (SCAN cursor [MATCH pattern] [COUNT n] [TYPE type])"),
+            };
+            let mut pattern = None;
+            let mut count = 10;
+            let mut type_name = None;
+            let mut args = parts[2..].iter();
+            while let Some(option) = args.next() {
+                match (option.to_uppercase().as_str(), args.next()) {
+                    ("MATCH", Some(value)) => pattern = Some(*value),
+                    ("COUNT", Some(value)) => match value.parse::<usize>() {
+                        Ok(n) if n > 0 => count = n,
+                        _ => return Reply::error("COUNT must be a positive number"),
+                    },
+                    ("TYPE", Some(value)) => type_name = Some(value.to_lowercase()),
+                    _ => return Reply::error(format!("Unknown or incomplete SCAN option '{}'", option)),
+                }
+            }
+
+            match store.scan(cursor, count, pattern, type_name.as_deref()) {
+                // RESP gets `[cursor, [keys]]`, as Redis clients expect
+                Ok((next, keys)) => {
+                    let mut message = format!("Cursor {}, {} keys:", next, keys.len());
+                    for key in &keys {
+                        let _ = write!(message, "\n  {}", key);
+                    }
+                    Reply::Array(message, vec![Reply::text(next.to_string()), Reply::array(String::new(), keys)])
+                }
+                Err(e) => Reply::error(format!("Failed to scan: {}", e)),
+            }
+        }
+
+        "COUNT" => match store.count() {
+            Ok(count) => Reply::count(count, format!("{} entries", count)),
+            Err(e) => Reply::error(format!("Failed to count entries: {}", e)),
+        },
+
+        "CLEAR" | "FLUSHALL" => {
+            let confirm = match parts.len() {
+                1 => None,
+                3 if parts[1].eq_ignore_ascii_case("CONFIRM") => Some(parts[2]),
+                _ => return Reply::error(format!("Usage: {} [CONFIRM token]", parts[0].to_uppercase())),
+            };
+            if let Err(e) = store.check_flush(confirm) {
+                return Reply::error(e);
+            }
+
+            match store.clear() {
+                Ok(_) => Reply::ok("All entries cleared"),
+                Err(e) => Reply::error(format!("Failed to clear: {}", e)),
+            }
+        }
+
+        "INFO" => match store.info() {
+            Ok(info) => Reply::value(format!("Server Info:\n{}", info), info),
+            Err(e) => Reply::error(format!("Failed to get info: {}", e)),
+        },
+
+        "PING" => Reply::status("PONG"),
+
+        // Pub/sub
+        "SUBSCRIBE" => {
+            if parts.len() < 2 {
+                return Reply::error("SUBSCRIBE requires at least one channel (SUBSCRIBE channel [channel ...])");
+            }
+            let mut confirmed = Vec::new();
+            for channel in &parts[1..] {
+                store.pubsub().subscribe(channel, session.client_id, &session.outbox);
+                session.subscriptions.insert(channel.to_string());
+                confirmed.push((channel.to_string(), session.subscriptions.len()));
+            }
+            session.lifecycle.set_subscriber(session.client_id, true);
+            subscription_reply("subscribe", format!("Subscribed to {} channels:", parts.len() - 1), confirmed)
+        }
+
+        "UNSUBSCRIBE" => {
+            // Without channels, every subscription ends
+            let channels: Vec<String> = if parts.len() > 1 {
+                parts[1..].iter().map(|channel| channel.to_string()).collect()
+            } else {
+                session.subscriptions.iter().cloned().collect()
+            };
+            let header = format!("Unsubscribed from {} channels:", channels.len());
+            let mut confirmed = Vec::new();
+            for channel in channels {
+                store.pubsub().unsubscribe(&channel, session.client_id);
+                session.subscriptions.remove(&channel);
+                confirmed.push((channel, session.subscriptions.len()));
+            }
+            session.lifecycle.set_subscriber(session.client_id, !session.subscriptions.is_empty());
+            subscription_reply("unsubscribe", header, confirmed)
+        }
+
+        "PUBLISH" => {
+            if parts.len() < 3 {
+                return Reply::error("PUBLISH requires channel and message (PUBLISH channel message)");
+            }
+            let received = store.pubsub().publish(parts[1], &parts[2..].join(" "));
+            Reply::count(received, received)
+        }
+
+        // Transactions
+        "MULTI" => {
+            if session.transaction.is_some() {
+                return Reply::error("MULTI calls cannot be nested");
+            }
+            session.transaction = Some(Transaction::default());
+            Reply::ok("Transaction started, commands are queued until EXEC")
+        }
+
+        "DISCARD" => match session.transaction.take() {
+            Some(transaction) => Reply::ok(format!("Discarded {} queued commands", transaction.queued.len())),
+            None => Reply::error("DISCARD without MULTI"),
+        },
+
+        "EXEC" => {
+            let transaction = match session.transaction.take() {
+                Some(transaction) => transaction,
+                None => return Reply::error("EXEC without MULTI"),
+            };
+            if transaction.aborted {
+                return Reply::error("EXECABORT Transaction discarded because of previous errors");
+            }
+
+            // One reply per command, errors included. The text protocol
+            // indents each under the header, so a reply's own continuation
+            // lines are indented again.
+            let mut message = format!("{} replies:", transaction.queued.len());
+            let mut replies = Vec::new();
+            for queued in &transaction.queued {
+                let queued: Vec<&str> = queued.iter().map(String::as_str).collect();
+                let reply = process_command(&queued, store, session);
+                for line in reply.to_string().lines() {
+                    message.push_str("\n  ");
+                    message.push_str(line);
+                }
+                replies.push(reply);
+            }
+            Reply::Array(message, replies)
+        }
+
+        "HELP" => {
+            if parts.len() < 2 {
+                return Reply::list(
+                    format!("{} commands (HELP command for details):", command_table::COMMANDS.len()),
+                    command_table::COMMANDS.iter().map(|spec| format!("{} - {}", spec.syntax, spec.summary)),
+                );
+            }
+
+            let name = parts[1..].join(" ");
+            let specs = command_table::lookup(&name);
+            if specs.is_empty() {
+                return Reply::error(format!("Unknown command '{}'", name));
+            }
+            let mut help = format!("Help for {}:", name.to_uppercase());
+            let mut lines = Vec::new();
+            for spec in specs {
+                let details = format!("{} (since {})", spec.summary, spec.since);
+                help.push_str(&format!("\n  {}\n    {}", spec.syntax, details));
+                lines.extend([spec.syntax.to_string(), details]);
+            }
+            Reply::array(help, lines)
+        }
+
+        "MEMORY" => {
+            if parts.len() < 2 || !parts[1].eq_ignore_ascii_case("ANALYZE") {
+                return Reply::error("MEMORY requires a subcommand (MEMORY ANALYZE [separator] [SAMPLES n])");
+            }
+
+            let mut separator = ":";
+            let mut samples = None;
+            let mut args = parts[2..].iter();
+            while let Some(arg) = args.next() {
+                if arg.eq_ignore_ascii_case("SAMPLES") {
+                    match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                        Some(n) if n > 0 => samples = Some(n),
+                        _ => return Reply::error("SAMPLES requires a positive number"),
+                    }
+                } else {
+                    separator = arg;
+                }
+            }
+
+            match store.memory_by_prefix(separator, samples) {
+                Ok((usage, _)) if usage.is_empty() => Reply::text("No keys to analyze"),
+                Ok((usage, inspected)) => {
+                    let header = format!(
+                        "Memory by prefix ({} keys inspected{}):",
+                        inspected,
+                        if samples.is_some() { ", totals estimated" } else { "" }
+                    );
+                    Reply::list(
+                        header,
+                        usage.iter().map(|group| {
+                            let prefix = if group.prefix.is_empty() { "(no prefix)" } else { &group.prefix };
+                            format!("{} keys={} memory={}", prefix, group.keys, group.memory)
+                        }),
+                    )
+                }
+                Err(e) => Reply::error(format!("Failed to analyze memory: {}", e)),
+            }
+        }
+
+        "DBSTATS" => {
+            // Sample 1000 keys unless told otherwise; SAMPLES 0 inspects them all
+            let samples = match parts.get(1) {
+                None => Some(1000),
+                Some(arg) if arg.eq_ignore_ascii_case("SAMPLES") => match parts.get(2).and_then(|n| n.parse::<usize>().ok()) {
+                    Some(0) if parts.len() == 3 => None,
+                    Some(n) if parts.len() == 3 => Some(n),
+                    _ => return Reply::error("SAMPLES requires a number (0 for every key)"),
+                },
+                Some(_) => return Reply::error("Usage: DBSTATS [SAMPLES n]"),
+            };
+
+            match store.keyspace_distribution(samples) {
+                Ok(distribution) if distribution.sampled == 0 => Reply::text("No keys to sample"),
+                Ok(distribution) => {
+                    let share = |count: usize| format!("{} ({:.1}%)", count, count as f64 * 100.0 / distribution.sampled as f64);
+                    let header = format!("Sampled {} of {} keys:", distribution.sampled, distribution.total);
+                    let mut report = Vec::new();
+                    report.push(format!("persistent: {}", share(distribution.persistent)));
+                    report.push(format!("volatile: {}", share(distribution.volatile)));
+                    let ttl_labels = TTL_BUCKETS.iter().map(|(_, label)| *label).chain([TTL_OVERFLOW]);
+                    for (label, count) in ttl_labels.zip(&distribution.ttl_buckets) {
+                        report.push(format!("ttl {}: {}", label, share(*count)));
+                    }
+                    let size_labels = SIZE_BUCKETS.iter().map(|(_, label)| *label).chain([SIZE_OVERFLOW]);
+                    for (label, count) in size_labels.zip(&distribution.size_buckets) {
+                        report.push(format!("size {}: {}", label, share(*count)));
+                    }
+                    for (type_name, count) in &distribution.types {
+                        report.push(format!("type {}: {}", type_name, share(*count)));
+                    }
+                    Reply::list(header, report)
+                }
+                Err(e) => Reply::error(format!("Failed to sample keyspace: {}", e)),
+            }
+        }
+
+        "STATS" => {
+            let stats = store.stats();
+            match parts.get(1).map(|sub| sub.to_uppercase()).as_deref() {
+                None => Reply::text(format!(
+                    "keyspace_hits={} keyspace_misses={} hit_rate={:.4}",
+                    stats.hits(),
+                    stats.misses(),
+                    stats::hit_rate(stats.hits(), stats.misses())
+                )),
+                Some("PREFIXES") => match parts.get(2).map(|flag| flag.to_uppercase()).as_deref() {
+                    Some("ON") => {
+                        let separator = parts.get(3).copied().unwrap_or(":");
+                        stats.track_prefixes(Some(separator));
+                        Reply::ok(format!("Counting hits and misses per prefix (separator '{}')", separator))
+                    }
+                    Some("OFF") => {
+                        stats.track_prefixes(None);
+                        Reply::ok("Stopped counting per prefix")
+                    }
+                    Some(other) => Reply::error(format!("Unknown STATS PREFIXES option '{}'", other)),
+                    None if stats.prefix_separator().is_none() => {
+                        Reply::text("Per-prefix stats are off (STATS PREFIXES ON [separator])")
+                    }
+                    None => {
+                        let prefixes = stats.prefixes();
+                        let header = format!("{} prefixes:", prefixes.len());
+                        let mut report = Vec::new();
+                        for prefix in prefixes {
+                            let name = if prefix.prefix.is_empty() { "(none)" } else { prefix.prefix.as_str() };
+                            report.push(format!(
+                                "{} hits={} misses={} hit_rate={:.4}",
+                                name,
+                                prefix.hits,
+                                prefix.misses,
+                                prefix.hit_rate()
+                            ));
+                        }
+                        Reply::list(header, report)
+                    }
+                },
+                Some("RESET") => {
+                    stats.reset();
+                    Reply::ok("Keyspace stats reset")
+                }
+                Some(other) => Reply::error(format!("Unknown STATS subcommand '{}'", other)),
+            }
+        }
+
+        "SLOWLOG" => {
+            let slowlog = store.slowlog();
+            match parts.get(1).map(|sub| sub.to_uppercase()).as_deref() {
+                Some("GET") => {
+                    let count = match parts.get(2) {
+                        Some(raw) => match raw.parse::<usize>() {
+                            Ok(count) => count,
+                            Err(_) => return Reply::error("Invalid count (SLOWLOG GET [count])"),
+                        },
+                        None => 10,
+                    };
+                    let entries = slowlog.get(count);
+                    if entries.is_empty() {
+                        return Reply::text("Slow log is empty");
+                    }
+                    let header = format!("{} slow commands:", entries.len());
+                    let mut log = Vec::new();
+                    for entry in entries {
+                        let tag = entry.tag.map(|tag| format!(" tag={}", tag)).unwrap_or_default();
+                        log.push(format!(
+                            "#{} at={} duration={}us client={}{} {}",
+                            entry.id,
+                            entry.timestamp,
+                            entry.duration.as_micros(),
+                            entry.client,
+                            tag,
+                            entry.command
+                        ));
+                    }
+                    Reply::list(header, log)
+                }
+                Some("LEN") => Reply::count(slowlog.len(), slowlog.len()),
+                Some("RESET") => {
+                    slowlog.reset();
+                    Reply::ok("Slow log cleared")
+                }
+                _ => Reply::error("SLOWLOG requires a subcommand (SLOWLOG GET [count]|LEN|RESET)"),
+            }
+        }
+
+        "HISTORY" => {
+            if parts.len() != 2 {
+                return Reply::error("HISTORY requires a key (HISTORY key)");
+            }
+            let history = store.history();
+            if !history.is_enabled() {
+                return Reply::error("Key history is disabled (set MEDUSA_KEY_HISTORY)");
+            }
+            let entries = history.get(parts[1]);
+            if entries.is_empty() {
+                return Reply::text(format!("No recorded changes to '{}'", parts[1]));
+            }
+            let header = format!("{} changes to '{}':", entries.len(), parts[1]);
+            let mut response = Vec::new();
+            for entry in entries {
+                let tag = entry.tag.map(|tag| format!(" tag={}", tag)).unwrap_or_default();
+                response.push(format!("at={} op={} client={}{}", entry.timestamp, entry.op, entry.client, tag));
+            }
+            Reply::list(header, response)
+        }
+
+        "ALIAS" => {
+            let aliases = store.aliases();
+            match parts.get(1).map(|sub| sub.to_uppercase()).as_deref() {
+                Some("SET") if parts.len() >= 4 => {
+                    let expansion = parts[3..].join(" ");
+                    match aliases.set(parts[2], &expansion) {
+                        Ok(()) => Reply::ok(format!("Alias '{}' -> '{}'", parts[2].to_uppercase(), expansion)),
+                        Err(e) => Reply::error(format!("Failed to set alias: {}", e)),
+                    }
+                }
+                Some("DEL") if parts.len() == 3 => match aliases.remove(parts[2]) {
+                    Ok(true) => Reply::ok(format!("Removed alias '{}'", parts[2].to_uppercase())),
+                    Ok(false) => Reply::nil(format!("Alias '{}' not found", parts[2].to_uppercase())),
+                    Err(e) => Reply::error(format!("Failed to remove alias: {}", e)),
+                },
+                Some("LIST") => {
+                    let list = aliases.list();
+                    if list.is_empty() {
+                        return Reply::text("No aliases defined");
+                    }
+                    let list: Vec<String> = list.iter()
+                        .map(|(name, command)| format!("{} -> {}", name, command))
+                        .collect();
+                    Reply::text(format!("Aliases: {}", list.join(", ")))
+                }
+                _ => Reply::error("ALIAS requires a subcommand (ALIAS SET name command [args ...], ALIAS DEL name, ALIAS LIST)"),
+            }
+        }
+
+        "ALARMS" => {
+            let alarms = store.alarms();
+            match parts.get(1).map(|sub| sub.to_uppercase()).as_deref() {
+                None | Some("LIST") => {
+                    let active = alarms.active();
+                    if active.is_empty() {
+                        return Reply::text("No active alarms");
+                    }
+                    let header = format!("{} active alarms:", active.len());
+                    let mut list = Vec::new();
+                    for alarm in active {
+                        list.push(format!(
+                            "{} value={} threshold={} since={}",
+                            alarm.metric, alarm.value, alarm.threshold, alarm.since
+                        ));
+                    }
+                    Reply::list(header, list)
+                }
+                Some("THRESHOLDS") => {
+                    let thresholds = alarms.thresholds();
+                    if thresholds.is_empty() {
+                        return Reply::text("No alarm thresholds set");
+                    }
+                    let thresholds: Vec<String> = thresholds.iter()
+                        .map(|(metric, threshold)| format!("{}={}", metric, threshold))
+                        .collect();
+                    Reply::text(format!("Alarm thresholds: {}", thresholds.join(", ")))
+                }
+                Some("SET") => {
+                    if parts.len() != 4 {
+                        return Reply::error("ALARMS SET requires a metric and threshold (ALARMS SET memory|keys|clients value|OFF)");
+                    }
+                    let threshold = if parts[3].eq_ignore_ascii_case("OFF") {
+                        None
+                    } else {
+                        match parts[3].parse::<u64>() {
+                            Ok(threshold) => Some(threshold),
+                            Err(_) => return Reply::error("Invalid threshold"),
+                        }
+                    };
+                    match alarms.set_threshold(parts[2], threshold) {
+                        Ok(()) => match threshold {
+                            Some(threshold) => Reply::ok(format!("Alarm when {} > {}", parts[2].to_lowercase(), threshold)),
+                            None => Reply::ok(format!("Alarm on {} removed", parts[2].to_lowercase())),
+                        },
+                        Err(e) => Reply::error(e),
+                    }
+                }
+                Some(other) => Reply::error(format!("Unknown ALARMS subcommand '{}'", other)),
+            }
+        }
+
+        "COMMAND" => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+            Some("GETKEYS") if parts.len() >= 3 => match command_table::get_keys(&parts[2..]) {
+                Ok(keys) if keys.is_empty() => Reply::error("The command has no key arguments"),
+                Ok(keys) => Reply::array(format!("Keys: {}", keys.join(", ")), keys.iter().map(|key| key.to_string())),
+                Err(e) => Reply::error(e),
+            },
+            Some("INFO") if parts.len() >= 3 => match command_table::find(&parts[2..]) {
+                Some(spec) => Reply::text(format!(
+                    "{} kind={} first_key={} last_key={} key_step={}",
+                    spec.name,
+                    spec.kind.name(),
+                    spec.first_key,
+                    spec.last_key,
+                    spec.key_step
+                )),
+                None => Reply::error(format!("Unknown command '{}'", parts[2])),
+            },
+            _ => Reply::error("COMMAND requires a subcommand (COMMAND GETKEYS command [arg ...]|INFO command [subcommand])"),
+        },
+
+        "CONFIG" => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+            Some("HELP") if parts.len() <= 3 => {
+                let options = config::schema();
+                match parts.get(2) {
+                    None => {
+                        let header = format!("{} settings:", options.len());
+                        Reply::list(header, options.iter().map(describe_option))
+                    }
+                    Some(name) => {
+                        let name = name.to_uppercase();
+                        let name = if name.starts_with("MEDUSA_") { name } else { format!("MEDUSA_{}", name) };
+                        match options.iter().find(|option| option.name == name) {
+                            Some(option) => Reply::text(describe_option(option)),
+                            None => Reply::error(format!("Unknown setting '{}'", parts[2])),
+                        }
+                    }
+                }
+            }
+            _ => Reply::error("CONFIG requires a subcommand (CONFIG HELP [setting])"),
+        },
+
+        "DRAIN" => {
+            let grace_secs = match parts.get(1) {
+                Some(raw) => match raw.parse::<u64>() {
+                    Ok(secs) if deadline_timeout(Duration::from_secs(secs)).is_some() => secs,
+                    _ => return Reply::error("Invalid grace period (DRAIN [seconds])"),
+                },
+                None => DEFAULT_DRAIN_GRACE_SECS,
+            };
+
+            if session.lifecycle.start_drain(Duration::from_secs(grace_secs)) {
+                Reply::ok(format!("Draining, no new connections accepted; closing in {}s", grace_secs))
+            } else {
+                let remaining = session.lifecycle.drain_remaining().unwrap_or_default();
+                Reply::ok(format!("Already draining; closing in {}s", remaining.as_secs()))
+            }
+        }
+
+        "HANDOFF" => {
+            let grace_secs = match parts.get(1) {
+                Some(raw) => match raw.parse::<u64>() {
+                    Ok(secs) if deadline_timeout(Duration::from_secs(secs)).is_some() => secs,
+                    _ => return Reply::error("Invalid grace period (HANDOFF [seconds])"),
+                },
+                None => DEFAULT_DRAIN_GRACE_SECS,
+            };
+
+            match handoff::hand_off(store, &session.lifecycle, Duration::from_secs(grace_secs)) {
+                Ok(pid) => Reply::ok(format!("Listener handed to new process {}; draining for {}s", pid, grace_secs)),
+                Err(e) => Reply::error(format!("Handoff failed: {}", e)),
+            }
+        }
+
+        // Reached over RESP or inside MULTI; a text connection's SYNC never gets here
+        "SYNC" => Reply::error("SYNC is only accepted as a plain text command"),
+        "CRDTSYNC" => Reply::error("CRDTSYNC is only accepted as a plain text command"),
+
+        "REPLICAOF" if store.crdt().is_enabled() => Reply::error("REPLICAOF cannot be used in multi-primary mode"),
+        "REPLICAOF" => match parts.get(1..) {
+            Some([no, one]) if no.eq_ignore_ascii_case("NO") && one.eq_ignore_ascii_case("ONE") => {
+                replication::set_primary(store, &session.lifecycle, None);
+                Reply::ok("This server is a primary")
+            }
+            Some([host, port]) => match port.parse::<u16>() {
+                Ok(port) => {
+                    let primary = net::format_address(host, port);
+                    replication::set_primary(store, &session.lifecycle, Some(primary.clone()));
+                    Reply::ok(format!("Replicating from {}", primary))
+                }
+                Err(_) => Reply::error(format!("Invalid port '{}'", port)),
+            },
+            _ => Reply::error("REPLICAOF requires a host and port, or NO ONE (REPLICAOF host port|NO ONE)"),
+        },
+
+        "SENTINEL" => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+            Some("IS-DOWN") if parts.len() == 3 => {
+                if store.sentinel().is_down(parts[2]) {
+                    Reply::bool(true, format!("{} is down", parts[2]))
+                } else {
+                    Reply::bool(false, format!("{} is not known to be down", parts[2]))
+                }
+            }
+            Some("PRIMARY") => match sentinel::current_primary(store) {
+                Some(primary) => Reply::text(primary.to_string()),
+                None => Reply::nil("This server is a primary outside any sentinel group"),
+            },
+            Some("GOSSIP") => match store.sentinel().gossip(&parts[2..]) {
+                Ok(entries) => Reply::text(entries.to_string()),
+                Err(e) => Reply::error(e),
+            },
+            _ => Reply::error("SENTINEL requires a subcommand (SENTINEL IS-DOWN host:port|PRIMARY|GOSSIP entries...)"),
+        },
+
+        "SAVE" | "BGSAVE" => {
+            // Only ever the configured file, so clients cannot write
+            // snapshots to paths of their choosing
+            let name = parts[0].to_uppercase();
+            if parts.len() > 1 {
+                return Reply::error(format!("{} takes no arguments, it writes to MEDUSA_SAVE_FILE", name));
+            }
+            let Some(path) = store.save_file() else {
+                return Reply::error(format!("{} requires MEDUSA_SAVE_FILE to be set", name));
+            };
+            if name == "BGSAVE" {
+                return match schedule::save_in_background(store, path.clone()) {
+                    Ok(_) => Reply::ok(format!("Background save to '{}' started", path.display())),
+                    Err(e) => Reply::error(e),
+                };
+            }
+            match schedule::save(store, &path) {
+                Ok(keys) => Reply::ok(format!("Saved {} keys to '{}'", keys, path.display())),
+                Err(e) => Reply::error(format!("Save failed: {}", e)),
+            }
+        }
+
+        "BACKUP" => {
+            if parts.len() < 3 {
+                return Reply::error("BACKUP requires a subcommand and path (BACKUP FULL|INCREMENTAL path, BACKUP RESTORE full [incremental ...])");
+            }
+
+            // Backups only go in MEDUSA_BACKUP_DIR, so clients cannot read
+            // or write files elsewhere
+            let resolve = |name: &str| confined_path(store.backup_dir(), "MEDUSA_BACKUP_DIR", name);
+            match parts[1].to_uppercase().as_str() {
+                subcommand @ ("FULL" | "INCREMENTAL") => {
+                    let path = parts[2];
+                    let file = match resolve(path) {
+                        Ok(file) => file,
+                        Err(e) => return Reply::error(e),
+                    };
+                    let mut writer = match File::create(file) {
+                        Ok(file) => BufWriter::new(file),
+                        Err(e) => return Reply::error(format!("Failed to create '{}': {}", path, e)),
+                    };
+                    let result = if subcommand == "FULL" {
+                        snapshot::write_full_backup(store, &mut writer)
+                    } else {
+                        snapshot::write_incremental(store, &mut writer)
+                    };
+                    match result {
+                        Ok(keys) => Reply::ok(format!("Wrote {} backup of {} keys to '{}'", subcommand.to_lowercase(), keys, path)),
+                        Err(e) => Reply::error(format!("Backup failed: {}", e)),
+                    }
+                }
+                "RESTORE" => {
+                    let files = match parts[2..].iter().map(|name| resolve(name)).collect::<Result<Vec<_>, _>>() {
+                        Ok(files) => files,
+                        Err(e) => return Reply::error(e),
+                    };
+                    match snapshot::restore_backup_chain(store, &files[0], &files[1..]) {
+                        Ok(keys) => Reply::ok(format!("Restored {} keys from {} backup file(s)", keys, files.len())),
+                        Err(e) => Reply::error(format!("Restore failed: {}", e)),
+                    }
+                }
+                other => Reply::error(format!("Unknown BACKUP subcommand '{}'", other)),
+            }
+        }
+
+        "DEBUG" => {
+            if parts.len() < 2 || !parts[1].eq_ignore_ascii_case("INJECT") {
+                return Reply::error("DEBUG requires a subcommand (DEBUG INJECT ...)");
+            }
+            let faults = store.faults();
+            if !faults.is_enabled() {
+                return Reply::error("Fault injection is disabled (start the server with MEDUSA_FAULT_INJECTION=true)");
+            }
+            let number = |index: usize| parts.get(index).and_then(|n| n.parse::<u64>().ok());
+
+            match parts.get(2).map(|fault| fault.to_uppercase()).as_deref() {
+                None | Some("STATUS") => Reply::text(format!("Injected faults: {}", faults.describe())),
+                Some("LATENCY") => match (number(3), parts.get(4)) {
+                    (Some(ms), None) => {
+                        faults.set_latency(Duration::from_millis(ms), 100);
+                        Reply::ok(format!("Delaying every command by {}ms", ms))
+                    }
+                    (Some(ms), Some(_)) => match number(4) {
+                        Some(percent) if percent <= 100 => {
+                            faults.set_latency(Duration::from_millis(ms), percent as u32);
+                            Reply::ok(format!("Delaying {}% of commands by {}ms", percent, ms))
+                        }
+                        _ => Reply::error("Percentage must be between 0 and 100"),
+                    },
+                    _ => Reply::error("DEBUG INJECT LATENCY requires milliseconds (DEBUG INJECT LATENCY ms [percent])"),
+                },
+                Some("DROP") => match number(3) {
+                    Some(percent) if percent <= 100 => {
+                        faults.set_drop_rate(percent as u32);
+                        Reply::ok(format!("Dropping the connection on {}% of commands", percent))
+                    }
+                    _ => Reply::error("DEBUG INJECT DROP requires a percentage between 0 and 100"),
+                },
+                Some("PERSISTENCE") => match parts.get(3).map(|flag| flag.to_uppercase()).as_deref() {
+                    Some("ON") => {
+                        faults.set_persistence_failure(true);
+                        Reply::ok("Snapshot and backup writes will fail")
+                    }
+                    Some("OFF") => {
+                        faults.set_persistence_failure(false);
+                        Reply::ok("Snapshot and backup writes restored")
+                    }
+                    _ => Reply::error("DEBUG INJECT PERSISTENCE requires ON or OFF"),
+                },
+                Some("CONTENTION") => match number(3) {
+                    Some(ms) => {
+                        let store = store.clone();
+                        std::thread::spawn(move || store.stall(Duration::from_millis(ms)));
+                        Reply::ok(format!("Holding the store lock for {}ms", ms))
+                    }
+                    None => Reply::error("DEBUG INJECT CONTENTION requires milliseconds"),
+                },
+                Some("CLEAR") => {
+                    faults.clear();
+                    Reply::ok("Cleared injected faults")
+                }
+                Some(other) => Reply::error(format!("Unknown fault '{}'", other)),
+            }
+        }
+
+        "IMPORT" => {
+            if parts.len() < 3 {
+                return Reply::error("IMPORT requires a format and path (IMPORT RDB|SNAPSHOT path)");
+            }
+            // Only files in MEDUSA_IMPORT_DIR, so clients cannot make the
+            // server read anything else
+            let path = parts[2..].join(" ");
+            let file = match confined_path(store.import_dir(), "MEDUSA_IMPORT_DIR", &path) {
+                Ok(file) => file,
+                Err(e) => return Reply::error(e),
+            };
+
+            match parts[1].to_uppercase().as_str() {
+                "RDB" => match rdb::load_rdb_file(store, &file) {
+                    Ok(import) => Reply::ok(format!("Imported '{}': {}", path, import.describe())),
+                    Err(e) => Reply::error(format!("Import failed: {}", e)),
+                },
+                // Merge a Medusa snapshot into the current dataset
+                "SNAPSHOT" => {
+                    let result = File::open(&file)
+                        .map_err(|e| format!("Failed to open {}: {}", path, e))
+                        .and_then(|file| snapshot::read_snapshot(store, &mut BufReader::new(file)));
+                    match result {
+                        Ok(keys) => Reply::ok(format!("Imported '{}': {} keys loaded", path, keys)),
+                        Err(e) => Reply::error(format!("Import failed: {}", e)),
+                    }
+                }
+                other => Reply::error(format!("Unknown IMPORT format '{}'", other)),
+            }
+        }
+
+        "CLIENT" => {
+            if parts.len() < 2 {
+                return Reply::error("CLIENT requires a subcommand (CLIENT NOTICES|NO-EVICT|NO-TOUCH|FRAMING ON|OFF, CLIENT LIST, CLIENT ID, CLIENT KILL, CLIENT UNBLOCK)");
+            }
+
+            match parts[1].to_uppercase().as_str() {
+                "NOTICES" => match parts.get(2).map(|flag| flag.to_uppercase()).as_deref() {
+                    Some("ON") => {
+                        session.notices = true;
+                        Reply::ok("Server notices enabled")
+                    }
+                    Some("OFF") => {
+                        session.notices = false;
+                        Reply::ok("Server notices disabled")
+                    }
+                    _ => Reply::error("CLIENT NOTICES requires ON or OFF"),
+                },
+                "EPHEMERAL" => {
+                    let enabled = match parts.get(2).map(|s| s.to_uppercase()).as_deref() {
+                        Some("ON") => true,
+                        Some("OFF") => false,
+                        _ => return Reply::error("CLIENT EPHEMERAL requires ON or OFF"),
+                    };
+                    session.ephemeral = enabled;
+                    session.lifecycle.set_ephemeral(session.client_id, enabled);
+                    if enabled {
+                        Reply::ok("Keys this connection creates will be deleted when it closes")
+                    } else {
+                        Reply::ok(format!(
+                            "EPHEMERAL disabled, {} keys created so far will still be deleted when the connection closes",
+                            session.ephemeral_keys.len()
+                        ))
+                    }
+                }
+                "NO-EVICT" | "NO-TOUCH" => {
+                    let flag = parts[1].to_uppercase();
+                    let enabled = match parts.get(2).map(|s| s.to_uppercase()).as_deref() {
+                        Some("ON") => true,
+                        Some("OFF") => false,
+                        _ => return Reply::error(format!("CLIENT {} requires ON or OFF", flag)),
+                    };
+                    if flag == "NO-EVICT" {
+                        session.lifecycle.set_no_evict(session.client_id, enabled);
+                    } else {
+                        session.no_touch = enabled;
+                        session.lifecycle.set_no_touch(session.client_id, enabled);
+                    }
+                    Reply::ok(format!("{} {}", flag, if enabled { "enabled" } else { "disabled" }))
+                }
+                "KILL" => {
+                    if parts.len() < 4 || !parts.len().is_multiple_of(2) {
+                        return Reply::error(format!("CLIENT KILL requires filters ({})", CLIENT_KILL_SYNTAX));
+                    }
+                    let mut filters = Vec::new();
+                    for filter in parts[2..].chunks(2) {
+                        match parse_kill_filter(filter[0], filter[1]) {
+                            Ok(filter) => filters.push(filter),
+                            Err(e) => return Reply::error(e),
+                        }
+                    }
+
+                    let killed = session.lifecycle.kill_clients(|client| filters.iter().all(|filter| filter.matches(client)));
+                    store.wake_waiters();
+                    Reply::ok(format!("Killed {} clients", killed))
+                }
+                "UNBLOCK" => {
+                    let id = match parts.get(2).and_then(|id| id.parse::<u64>().ok()) {
+                        Some(id) => id,
+                        None => return Reply::error("CLIENT UNBLOCK requires a client id (CLIENT UNBLOCK id [TIMEOUT|ERROR])"),
+                    };
+                    let mode = match parts.get(3).map(|s| s.to_uppercase()).as_deref() {
+                        None | Some("TIMEOUT") => UnblockMode::Timeout,
+                        Some("ERROR") => UnblockMode::Error,
+                        Some(other) => return Reply::error(format!("Unknown CLIENT UNBLOCK mode '{}'", other)),
+                    };
+
+                    if session.lifecycle.unblock_client(id, mode) {
+                        store.wake_waiters();
+                        Reply::bool(true, format!("Client {} unblocked", id))
+                    } else {
+                        Reply::bool(false, format!("Client {} is not blocked", id))
+                    }
+                }
+                "ID" => Reply::count(session.client_id, session.client_id),
+                "FRAMING" => match parts.get(2).map(|flag| flag.to_uppercase()).as_deref() {
+                    Some("ON") => {
+                        session.framed = true;
+                        Reply::ok("Replies framed with their length")
+                    }
+                    Some("OFF") => {
+                        session.framed = false;
+                        Reply::ok("Replies unframed")
+                    }
+                    _ => Reply::error("CLIENT FRAMING requires ON or OFF"),
+                },
+                "LIST" => {
+                    let clients = session.lifecycle.clients();
+                    let header = format!("{} clients:", clients.len());
+                    let mut list = Vec::new();
+                    for client in clients {
+                        list.push(format!(
+                            "id={} addr={} laddr={} age={}s idle={}s commands={} last={} flags={}",
+                            client.id,
+                            client.addr,
+                            client.laddr,
+                            client.connected_at.elapsed().as_secs(),
+                            client.last_active.elapsed().as_secs(),
+                            client.commands,
+                            client.last_command,
+                            client_flags(&client)
+                        ));
+                    }
+                    Reply::list(header, list)
+                }
+                other => Reply::error(format!("Unknown CLIENT subcommand '{}'", other)),
+            }
+        }
+
+        "TENANT" => {
+            if parts.len() < 2 {
+                return Reply::error("TENANT requires a subcommand (TENANT SET|DEL|LIST|INFO)");
+            }
+
+            match parts[1].to_uppercase().as_str() {
+                "SET" => {
+                    if parts.len() < 3 || parts.len().is_multiple_of(2) {
+                        return Reply::error("TENANT SET requires a name (TENANT SET name [KEYS n] [MEMORY bytes] [OPS n])");
+                    }
+                    let name = parts[2];
+                    let mut quota = TenantQuota::default();
+                    for option in parts[3..].chunks(2) {
+                        let value = match option[1].parse::<u64>() {
+                            Ok(value) => value,
+                            Err(_) => return Reply::error(format!("Invalid value for {}", option[0].to_uppercase())),
+                        };
+                        match option[0].to_uppercase().as_str() {
+                            "KEYS" => quota.max_keys = Some(value as usize),
+                            "MEMORY" => quota.max_memory = Some(value as usize),
+                            "OPS" => quota.max_ops_per_sec = Some(value),
+                            other => return Reply::error(format!("Unknown TENANT SET option '{}'", other)),
+                        }
+                    }
+
+                    let description = quota.describe();
+                    match store.set_tenant(name, quota) {
+                        Ok(()) => Reply::ok(format!("Tenant '{}' owns keys '{}:*' ({})", name, name, description)),
+                        Err(e) => Reply::error(format!("Failed to set tenant: {}", e)),
+                    }
+                }
+                "DEL" => {
+                    if parts.len() < 3 {
+                        return Reply::error("TENANT DEL requires a name (TENANT DEL name)");
+                    }
+                    match store.tenants().remove(parts[2]) {
+                        Ok(true) => Reply::ok(format!("Removed tenant '{}'", parts[2])),
+                        Ok(false) => Reply::nil(format!("Tenant '{}' not found", parts[2])),
+                        Err(e) => Reply::error(format!("Failed to remove tenant: {}", e)),
+                    }
+                }
+                "LIST" => match store.tenants().names() {
+                    Ok(names) if names.is_empty() => Reply::array("No tenants defined", Vec::new()),
+                    Ok(names) => Reply::array(format!("Tenants: {}", names.join(", ")), names),
+                    Err(e) => Reply::error(format!("Failed to list tenants: {}", e)),
+                },
+                "INFO" => {
+                    if parts.len() < 3 {
+                        return Reply::error("TENANT INFO requires a name (TENANT INFO name)");
+                    }
+                    let name = parts[2];
+                    let tenants = store.tenants();
+                    match (tenants.quota(name), tenants.stats(name), tenants.usage(name)) {
+                        (Some(quota), Some(stats), Some((keys, memory))) => Reply::text(format!(
+                            "Tenant '{}': keys={} used_memory={} total_ops={} rejected_ops={} ({})",
+                            name, keys, memory, stats.total_ops, stats.rejected_ops, quota.describe()
+                        )),
+                        _ => Reply::nil(format!("Tenant '{}' not found", name)),
+                    }
+                }
+                other => Reply::error(format!("Unknown TENANT subcommand '{}'", other)),
+            }
+        }
+
+        "QUIT" | "EXIT" => Reply::ok("Goodbye!"),
+
+        // Hash operations
+        "HSETBIN" => {
+            if parts.len() != 4 {
+                return Reply::error("HSETBIN requires key, field and a base64 value (HSETBIN key field base64)");
+            }
+            let key = parts[1];
+            let field = parts[2];
+            let value = match base64::decode(parts[3]) {
+                Ok(value) => value,
+                Err(e) => return Reply::error(e),
+            };
+
+            match store.hset_bytes(key, field, &value) {
+                Ok(true) => Reply::ok(format!("Created new field '{}' in hash '{}'", field, key)),
+                Ok(false) => Reply::ok(format!("Updated field '{}' in hash '{}'", field, key)),
+                Err(e) => Reply::error(format!("Failed to set hash field: {}", e)),
+            }
+        }
+
+        "HGETBIN" => {
+            if parts.len() < 3 {
+                return Reply::error("HGETBIN requires key and field (HGETBIN key field)");
+            }
+            let key = parts[1];
+            let field = parts[2];
+
+            let result = store.hget_bytes(key, field);
+            if let Ok(found) = &result {
+                store.stats().record(key, found.is_some());
+            }
+            match result {
+                Ok(Some(value)) => {
+                    let encoded = base64::encode(&value);
+                    Reply::value(format!("'{}:{}' = {}", key, field, encoded), encoded)
+                },
+                Ok(None) => Reply::nil(format!("Field '{}' not found in hash '{}'", field, key)),
+                Err(e) => Reply::error(format!("Failed to get hash field: {}", e)),
+            }
+        }
+
+        "ZADDDELAY" => {
+            if parts.len() < 4 {
+                return Reply::error("ZADDDELAY requires queue, timestamp and payload (ZADDDELAY queue timestamp_ms payload)");
+            }
+            let queue = parts[1];
+            let deliver_at = match parts[2].parse::<u64>() {
+                Ok(timestamp) => timestamp,
+                Err(_) => return Reply::error("Invalid timestamp"),
+            };
+
+            match store.delayed().add(queue, deliver_at, &parts[3..].join(" ")) {
+                Ok(pending) => Reply::ok(format!("Scheduled for '{}' at {} ({} pending)", queue, deliver_at, pending)),
+                Err(e) => Reply::error(format!("Failed to schedule item: {}", e)),
+            }
+        }
+
+        "DELAYED" => {
+            if parts.len() < 2 {
+                return Reply::error("DELAYED requires a queue (DELAYED queue)");
+            }
+            match store.delayed().pending(parts[1]) {
+                (0, _) => Reply::text(format!("No items pending for '{}'", parts[1])),
+                (pending, next) => Reply::text(format!(
+                    "{} items pending for '{}', next due at {}",
+                    pending,
+                    parts[1],
+                    next.unwrap_or(0)
+                )),
+            }
+        }
+
+        // Time series operations
+        "TS.CREATE" => {
+            if parts.len() != 2 && parts.len() != 4 {
+                return Reply::error("TS.CREATE requires a key (TS.CREATE key [RETENTION ms])");
+            }
+            let key = parts[1];
+            let retention_ms = if parts.len() == 4 {
+                if !parts[2].eq_ignore_ascii_case("RETENTION") {
+                    return Reply::error(format!("Unknown TS.CREATE option '{}'", parts[2]));
+                }
+                match parts[3].parse::<u64>() {
+                    Ok(ms) => Some(ms),
+                    Err(_) => return Reply::error("Invalid retention value"),
+                }
+            } else {
+                None
+            };
+
+            match store.ts_create(key, retention_ms) {
+                Ok(true) => Reply::ok(format!("Created time series '{}'", key)),
+                Ok(false) => Reply::error(format!("Key '{}' already exists", key)),
+                Err(e) => Reply::error(format!("Failed to create time series: {}", e)),
+            }
+        }
+
+        "TS.ADD" => {
+            if parts.len() < 4 {
+                return Reply::error("TS.ADD requires key, timestamp, and value (TS.ADD key timestamp|* value)");
+            }
+            let key = parts[1];
+            let timestamp = if parts[2] == "*" {
+                now_millis()
+            } else {
+                match parts[2].parse::<u64>() {
+                    Ok(ts) => ts,
+                    Err(_) => return Reply::error("Invalid timestamp"),
+                }
+            };
+            let value = match parts[3].parse::<f64>() {
+                Ok(v) if v.is_finite() => v,
+                _ => return Reply::error("Invalid sample value"),
+            };
+
+            match store.ts_add(key, timestamp, value) {
+                Ok(ts) => Reply::ok(format!("Added sample to '{}' at {}", key, ts)),
+                Err(e) => Reply::error(format!("Failed to add sample: {}", e)),
+            }
+        }
+
+        "TS.GET" => {
+            if parts.len() < 2 {
+                return Reply::error("TS.GET requires a key (TS.GET key)");
+            }
+            let key = parts[1];
+
+            match store.ts_get(key) {
+                Ok(Some((ts, value))) => Reply::text(format!("'{}' latest sample {} = {}", key, ts, value)),
+                Ok(None) => Reply::nil(format!("Time series '{}' is empty", key)),
+                Err(e) => Reply::error(format!("Failed to get sample: {}", e)),
+            }
+        }
+
+        "TS.RANGE" => {
+            if parts.len() != 4 && parts.len() != 7 {
+                return Reply::error("TS.RANGE requires key, from, and to (TS.RANGE key from to [AGGREGATION avg|min|max bucket_ms])");
+            }
+            let key = parts[1];
+            let from = match parts[2] {
+                "-" => 0,
+                raw => match raw.parse::<u64>() {
+                    Ok(ts) => ts,
+                    Err(_) => return Reply::error("Invalid from timestamp"),
+                },
+            };
+            let to = match parts[3] {
+                "+" => u64::MAX,
+                raw => match raw.parse::<u64>() {
+                    Ok(ts) => ts,
+                    Err(_) => return Reply::error("Invalid to timestamp"),
+                },
+            };
+            let aggregation = if parts.len() == 7 {
+                if !parts[4].eq_ignore_ascii_case("AGGREGATION") {
+                    return Reply::error(format!("Unknown TS.RANGE option '{}'", parts[4]));
+                }
+                let aggregation = match Aggregation::parse(parts[5]) {
+                    Some(aggregation) => aggregation,
+                    None => return Reply::error("Aggregation must be avg, min, or max"),
+                };
+                match parts[6].parse::<u64>() {
+                    Ok(bucket_ms) if bucket_ms > 0 => Some((aggregation, bucket_ms)),
+                    _ => return Reply::error("Invalid bucket duration"),
+                }
+            } else {
+                None
+            };
+
+            match store.ts_range(key, from, to, aggregation) {
+                Ok(samples) => {
+                    if samples.is_empty() {
+                        Reply::text(format!("No samples in range for '{}'", key))
+                    } else {
+                        let sample_list: Vec<String> = samples.iter()
+                            .map(|(ts, value)| format!("{}:{}", ts, value))
+                            .collect();
+                        Reply::text(format!("Time series '{}' samples: {}", key, sample_list.join(", ")))
+                    }
+                }
+                Err(e) => Reply::error(format!("Failed to get range: {}", e)),
+            }
+        }
+
+        // Vector operations
+        "VADD" => {
+            if parts.len() < 3 {
+                return Reply::error("VADD requires key and components (VADD key f1 f2 ...)");
+            }
+            let key = parts[1];
+            let vector = match parse_vector(&parts[2..]) {
+                Some(vector) => vector,
+                None => return Reply::error("Vector components must be numbers"),
+            };
+            let dimensions = vector.len();
+
+            match store.vadd(key, vector) {
+                Ok(_) => Reply::ok(format!("Stored {}-dimensional vector at '{}'", dimensions, key)),
+                Err(e) => Reply::error(format!("Failed to store vector: {}", e)),
+            }
+        }
+
+        "VGET" => {
+            if parts.len() < 2 {
+                return Reply::error("VGET requires a key (VGET key)");
+            }
+            let key = parts[1];
+
+            match store.vget(key) {
+                Ok(Some(vector)) => {
+                    let components: Vec<String> = vector.iter().map(|c| c.to_string()).collect();
+                    Reply::text(format!("'{}' = [{}]", key, components.join(", ")))
+                }
+                Ok(None) => Reply::nil(format!("Key '{}' not found or expired", key)),
+                Err(e) => Reply::error(format!("Failed to get vector: {}", e)),
+            }
+        }
+
+        "VSEARCH" => {
+            if parts.len() < 5 {
+                return Reply::error("VSEARCH requires pattern, k, metric, and query (VSEARCH pattern k COSINE|L2 f1 f2 ...)");
+            }
+            let pattern = parts[1];
+            let k = match parts[2].parse::<usize>() {
+                Ok(k) if k > 0 => k,
+                _ => return Reply::error("Invalid k value"),
+            };
+            let metric = match Metric::parse(parts[3]) {
+                Some(metric) => metric,
+                None => return Reply::error("Metric must be COSINE or L2"),
+            };
+            let query = match parse_vector(&parts[4..]) {
+                Some(query) => query,
+                None => return Reply::error("Vector components must be numbers"),
+            };
+
+            match store.vsearch(pattern, &query, k, metric) {
+                Ok(results) => {
+                    if results.is_empty() {
+                        Reply::text(format!("No vectors matching '{}'", pattern))
+                    } else {
+                        let result_list: Vec<String> = results.iter()
+                            .map(|(key, distance)| format!("{}:{:.6}", key, distance))
+                            .collect();
+                        Reply::text(format!("Nearest vectors: {}", result_list.join(", ")))
+                    }
+                }
+                Err(e) => Reply::error(format!("Failed to search vectors: {}", e)),
+            }
+        }
+
+        // Secondary index operations
+        "INDEX" => {
+            if parts.len() < 2 {
+                return Reply::error("INDEX requires a subcommand (INDEX CREATE|DROP|LIST)");
+            }
+
+            match parts[1].to_uppercase().as_str() {
+                "CREATE" => {
+                    if parts.len() != 5 {
+                        return Reply::error("INDEX CREATE requires name, pattern, and field (INDEX CREATE name pattern field)");
+                    }
+                    match store.create_index(parts[2], parts[3], parts[4]) {
+                        Ok(true) => Reply::ok(format!("Created index '{}' on field '{}' for keys '{}'", parts[2], parts[4], parts[3])),
+                        Ok(false) => Reply::error(format!("Index '{}' already exists", parts[2])),
+                        Err(e) => Reply::error(format!("Failed to create index: {}", e)),
+                    }
+                }
+                "DROP" => {
+                    if parts.len() != 3 {
+                        return Reply::error("INDEX DROP requires a name (INDEX DROP name)");
+                    }
+                    match store.drop_index(parts[2]) {
+                        Ok(true) => Reply::ok(format!("Dropped index '{}'", parts[2])),
+                        Ok(false) => Reply::nil(format!("Index '{}' not found", parts[2])),
+                        Err(e) => Reply::error(format!("Failed to drop index: {}", e)),
+                    }
+                }
+                "LIST" => match store.list_indexes() {
+                    Ok(indexes) => {
+                        if indexes.is_empty() {
+                            Reply::text("No indexes defined")
+                        } else {
+                            let index_list: Vec<String> = indexes.iter()
+                                .map(|(name, pattern, field)| format!("{} ({} -> {})", name, pattern, field))
+                                .collect();
+                            Reply::text(format!("Indexes: {}", index_list.join(", ")))
+                        }
+                    }
+                    Err(e) => Reply::error(format!("Failed to list indexes: {}", e)),
+                },
+                other => Reply::error(format!("Unknown INDEX subcommand '{}'", other)),
+            }
+        }
+
+        "FIND" => {
+            if parts.len() < 3 {
+                return Reply::error("FIND requires an index and a value (FIND index value)");
+            }
+            let index = parts[1];
+            let value = parts[2..].join(" ");
+
+            match store.find(index, &value) {
+                Ok(keys) => {
+                    if keys.is_empty() {
+                        Reply::array(format!("No keys in index '{}' with value '{}'", index, value), Vec::new())
+                    } else {
+                        Reply::array(format!("Keys in index '{}' with value '{}': {}", index, value, keys.join(", ")), keys)
+                    }
+                }
+                Err(e) => Reply::error(format!("Failed to query index: {}", e)),
+            }
+        }
+
+        // Full-text search operations
+        "FT.CREATE" => {
+            if parts.len() < 3 || (parts.len() > 3 && !parts[3].eq_ignore_ascii_case("FIELDS")) {
+                return Reply::error("FT.CREATE requires name and pattern (FT.CREATE name pattern [FIELDS field ...])");
+            }
+            let name = parts[1];
+            let pattern = parts[2];
+            let fields: Vec<String> = parts.iter().skip(4).map(|f| f.to_string()).collect();
+
+            match store.ft_create(name, pattern, fields) {
+                Ok(true) => Reply::ok(format!("Created search index '{}' for keys '{}'", name, pattern)),
+                Ok(false) => Reply::error(format!("Search index '{}' already exists", name)),
+                Err(e) => Reply::error(format!("Failed to create search index: {}", e)),
+            }
+        }
+
+        "FT.ADD" => {
+            if parts.len() != 3 {
+                return Reply::error("FT.ADD requires name and key (FT.ADD name key)");
+            }
+            let name = parts[1];
+            let key = parts[2];
+
+            match store.ft_add(name, key) {
+                Ok(true) => Reply::ok(format!("Added '{}' to search index '{}'", key, name)),
+                Ok(false) => Reply::nil(format!("Key '{}' not found", key)),
+                Err(e) => Reply::error(format!("Failed to add to search index: {}", e)),
+            }
+        }
+
+        "FT.SEARCH" => {
+            if parts.len() < 3 {
+                return Reply::error("FT.SEARCH requires name and query (FT.SEARCH name query)");
+            }
+            let name = parts[1];
+            let query = parts[2..].join(" ");
+
+            match store.ft_search(name, &query) {
+                Ok(keys) => {
+                    if keys.is_empty() {
+                        Reply::array(format!("No documents matching '{}'", query), Vec::new())
+                    } else {
+                        Reply::array(format!("{} documents matching '{}': {}", keys.len(), query, keys.join(", ")), keys)
+                    }
+                }
+                Err(e) => Reply::error(format!("Failed to search: {}", e)),
+            }
+        }
+
+        "FT.DROP" => {
+            if parts.len() != 2 {
+                return Reply::error("FT.DROP requires a name (FT.DROP name)");
+            }
+
+            match store.ft_drop(parts[1]) {
+                Ok(true) => Reply::ok(format!("Dropped search index '{}'", parts[1])),
+                Ok(false) => Reply::nil(format!("Search index '{}' not found", parts[1])),
+                Err(e) => Reply::error(format!("Failed to drop search index: {}", e)),
+            }
+        }
+
+        _ => match store.commands().call(parts, store) {
+            Some(reply) => reply,
+            None => Reply::error(format!("Unknown command '{}'", parts[0])),
+        },
+    }
+}
+
+// Run a command that `command::parse` has already validated
+fn execute(command: Command, store: &Store) -> Reply {
+    match command {
+        Command::Set { key, value, ttl } => set_string(store, key, &value, ttl),
+
+        Command::Get { key } => {
+            if let Some(error) = over_chunk_threshold(store, key) {
+                return error;
+            }
+
+            let result = store.get(key);
+            if let Ok(found) = &result {
+                store.stats().record(key, found.is_some());
+            }
+            match result {
+                Ok(Some(value)) => Reply::value(format!("'{}' = {}", key, value), value),
+                Ok(None) => Reply::nil(format!("Key '{}' not found or expired", key)),
+                Err(e) => Reply::error(format!("Failed to get value: {}", e)),
+            }
+        }
+
+        Command::GetWithTtl { key } => {
+            if let Some(error) = over_chunk_threshold(store, key) {
+                return error;
+            }
+
+            let result = store.get_with_ttl(key);
+            if let Ok(found) = &result {
+                store.stats().record(key, found.is_some());
+            }
+            match result {
+                Ok(Some((value, Some(ttl)))) => Reply::value(format!("'{}' = {} (expires in {} seconds)", key, value, ttl), value),
+                Ok(Some((value, None))) => Reply::value(format!("'{}' = {} (no expiry)", key, value), value),
+                Ok(None) => Reply::nil(format!("Key '{}' not found or expired", key)),
+                Err(e) => Reply::error(format!("Failed to get value: {}", e)),
+            }
+        }
+
+        Command::Delete { key } => match store.delete(key) {
+            Ok(Some(value)) => Reply::ok(format!("Deleted '{}' (was '{}')", key, value)),
+            Ok(None) => Reply::nil(format!("Key '{}' not found", key)),
+            Err(e) => Reply::error(format!("Failed to delete: {}", e)),
+        },
+
+        Command::Exists { key } => {
+            let result = store.exists(key);
+            if let Ok(exists) = &result {
+                store.stats().record(key, *exists);
+            }
+            match result {
+                Ok(true) => Reply::bool(true, format!("Key '{}' exists", key)),
+                Ok(false) => Reply::bool(false, format!("Key '{}' does not exist", key)),
+                Err(e) => Reply::error(format!("Failed to check existence: {}", e)),
+            }
+        }
+
+        Command::Ttl { key } => match store.ttl(key) {
+            // As in Redis, -1 is a key without an expiry and -2 one that is missing or expired
+            Ok(Some(-1)) => Reply::Integer(-2, format!("TTL: Key '{}' has expired", key)),
+            Ok(Some(ttl)) => Reply::Integer(ttl, format!("TTL: Key '{}' expires in {} seconds", key, ttl)),
+            Ok(None) if store.exists(key) == Ok(true) => Reply::Integer(-1, format!("NULL: Key '{}' has no expiry", key)),
+            Ok(None) => Reply::Integer(-2, format!("NULL: Key '{}' not found", key)),
+            Err(e) => Reply::error(format!("Failed to get TTL: {}", e)),
+        },
+
+        Command::Expire { key, seconds } => match store.expire(key, seconds) {
+            Ok(true) => Reply::count(1, format!("Set expiration for '{}' to {} seconds", key, seconds)),
+            Ok(false) => Reply::bool(false, format!("Key '{}' not found", key)),
+            Err(e) => Reply::error(format!("Failed to set expiration: {}", e)),
+        },
+
+        Command::HSet { key, field, value } => match store.hset(key, field, &value) {
+            Ok(true) => Reply::count(1, format!("Created new field '{}' in hash '{}'", field, key)),
+            Ok(false) => Reply::count(0, format!("Updated field '{}' in hash '{}'", field, key)),
+            Err(e) => Reply::error(format!("Failed to set hash field: {}", e)),
+        },
+
+        Command::HGet { key, field } => {
+            let result = store.hget(key, field);
+            if let Ok(found) = &result {
+                store.stats().record(key, found.is_some());
+            }
+            match result {
+                Ok(Some(value)) => Reply::value(format!("'{}:{}' = {}", key, field, value), value),
+                Ok(None) => Reply::nil(format!("Field '{}' not found in hash '{}'", field, key)),
+                Err(e) => Reply::error(format!("Failed to get hash field: {}", e)),
+            }
+        }
+
+        Command::HGetAll { key } => match store.hgetall_bytes(key) {
+            Ok(fields) if fields.is_empty() => Reply::array(format!("Hash '{}' is empty", key), Vec::new()),
+            Ok(fields) => {
+                // Binary values would break the line; HGETBIN reads them
+                let fields: Vec<(String, String)> = fields
+                    .into_iter()
+                    .map(|(k, v)| match String::from_utf8(v) {
+                        Ok(v) => (k, v),
+                        Err(e) => (k, format!("<{} bytes binary>", e.as_bytes().len())),
+                    })
+                    .collect();
+                let field_list: Vec<String> = fields.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+                let message = format!("Hash '{}' fields: {}", key, field_list.join(", "));
+                Reply::array(message, fields.into_iter().flat_map(|(k, v)| [k, v]))
+            }
+            Err(e) => Reply::error(format!("Failed to get hash: {}", e)),
+        },
+
+        Command::HDel { key, field } => match store.hdel(key, field) {
+            Ok(true) => Reply::count(1, format!("Deleted field '{}' from hash '{}'", field, key)),
+            Ok(false) => Reply::bool(false, format!("Field '{}' not found in hash '{}'", field, key)),
+            Err(e) => Reply::error(format!("Failed to delete hash field: {}", e)),
+        },
+
+        Command::HExists { key, field } => match store.hexists(key, field) {
+            Ok(true) => Reply::bool(true, format!("Field '{}' exists in hash '{}'", field, key)),
+            Ok(false) => Reply::bool(false, format!("Field '{}' does not exist in hash '{}'", field, key)),
+            Err(e) => Reply::error(format!("Failed to check hash field existence: {}", e)),
+        },
+
+        Command::HLen { key } => match store.hlen(key) {
+            Ok(len) => Reply::count(len, format!("Hash '{}' has {} fields", key, len)),
+            Err(e) => Reply::error(format!("Failed to get hash length: {}", e)),
+        },
+
+        Command::LPush { key, value } => match store.lpush(key, &value) {
+            Ok(len) => Reply::count(len, format!("Pushed to left of list '{}', new length: {}", key, len)),
+            Err(e) => Reply::error(format!("Failed to push to list: {}", e)),
+        },
+
+        Command::RPush { key, value } => match store.rpush(key, &value) {
+            Ok(len) => Reply::count(len, format!("Pushed to right of list '{}', new length: {}", key, len)),
+            Err(e) => Reply::error(format!("Failed to push to list: {}", e)),
+        },
+
+        Command::LPop { key } => match store.lpop(key) {
+            Ok(Some(value)) => Reply::value(format!("Popped from left of list '{}': {}", key, value), value),
+            Ok(None) => Reply::nil(format!("List '{}' is empty", key)),
+            Err(e) => Reply::error(format!("Failed to pop from list: {}", e)),
+        },
+
+        Command::RPop { key } => match store.rpop(key) {
+            Ok(Some(value)) => Reply::value(format!("Popped from right of list '{}': {}", key, value), value),
+            Ok(None) => Reply::nil(format!("List '{}' is empty", key)),
+            Err(e) => Reply::error(format!("Failed to pop from list: {}", e)),
+        },
+
+        Command::LLen { key } => match store.llen(key) {
+            Ok(len) => Reply::count(len, format!("List '{}' has {} items", key, len)),
+            Err(e) => Reply::error(format!("Failed to get list length: {}", e)),
+        },
+
+        Command::LRange { key, start, stop } => match store.lrange(key, start, stop) {
+            Ok(items) if items.is_empty() => Reply::array(format!("No items in range [{}, {}] for list '{}'", start, stop, key), items),
+            Ok(items) => Reply::array(format!("List '{}' range [{}, {}]: {}", key, start, stop, items.join(", ")), items),
+            Err(e) => Reply::error(format!("Failed to get list range: {}", e)),
+        },
+
+        Command::SAdd { key, members } => match store.sadd(key, &members) {
+            Ok(added) => Reply::count(added, format!("Added {} new members to set '{}'", added, key)),
+            Err(e) => Reply::error(format!("Failed to add to set: {}", e)),
+        },
+
+        Command::SRem { key, members } => match store.srem(key, &members) {
+            Ok(removed) => Reply::count(removed, format!("Removed {} members from set '{}'", removed, key)),
+            Err(e) => Reply::error(format!("Failed to remove from set: {}", e)),
+        },
+
+        Command::SMembers { key } => match store.smembers(key) {
+            Ok(members) if members.is_empty() => Reply::array(format!("Set '{}' is empty", key), members),
+            Ok(members) => Reply::list(format!("Set '{}' has {} members:", key, members.len()), members),
+            Err(e) => Reply::error(format!("Failed to get set members: {}", e)),
+        },
+
+        Command::SCard { key } => match store.scard(key) {
+            Ok(len) => Reply::count(len, format!("Set '{}' has {} members", key, len)),
+            Err(e) => Reply::error(format!("Failed to get set size: {}", e)),
+        },
+
+        Command::SIsMember { key, member } => match store.sismember(key, member) {
+            Ok(true) => Reply::bool(true, format!("'{}' is a member of set '{}'", member, key)),
+            Ok(false) => Reply::bool(false, format!("'{}' is not a member of set '{}'", member, key)),
+            Err(e) => Reply::error(format!("Failed to check set membership: {}", e)),
+        },
+
+        Command::ZAdd { key, entries } => match store.zadd(key, &entries) {
+            Ok(added) => Reply::count(added, format!("Added {} new members to sorted set '{}'", added, key)),
+            Err(e) => Reply::error(format!("Failed to add to sorted set: {}", e)),
+        },
+
+        Command::ZScore { key, member } => match store.zscore(key, member) {
+            Ok(Some(score)) => Reply::value(format!("'{}:{}' = {}", key, member, score), score.to_string()),
+            Ok(None) => Reply::nil(format!("'{}' is not in sorted set '{}'", member, key)),
+            Err(e) => Reply::error(format!("Failed to get score: {}", e)),
+        },
+
+        Command::ZRank { key, member } => match store.zrank(key, member) {
+            Ok(Some(rank)) => Reply::count(rank, rank),
+            Ok(None) => Reply::nil(format!("'{}' is not in sorted set '{}'", member, key)),
+            Err(e) => Reply::error(format!("Failed to get rank: {}", e)),
+        },
+
+        Command::ZRange { key, start, stop, with_scores } => match store.zrange(key, start, stop) {
+            Ok(members) if members.is_empty() => Reply::array(format!("No members in range [{}, {}] for sorted set '{}'", start, stop, key), Vec::new()),
+            Ok(members) => {
+                let header = format!("Sorted set '{}' range [{}, {}]:", key, start, stop);
+                sorted_set_members(header, members, with_scores)
+            }
+            Err(e) => Reply::error(format!("Failed to get sorted set range: {}", e)),
+        },
+
+        Command::ZCard { key } => match store.zcard(key) {
+            Ok(len) => Reply::count(len, format!("Sorted set '{}' has {} members", key, len)),
+            Err(e) => Reply::error(format!("Failed to get sorted set size: {}", e)),
+        },
+
+        Command::ZRangeByScore { key, min, max, with_scores, offset, count } => match store.zrangebyscore(key, min, max, offset, count) {
+            Ok(members) if members.is_empty() => Reply::array(format!("No members scored in [{}, {}] for sorted set '{}'", min, max, key), Vec::new()),
+            Ok(members) => {
+                let header = format!("Sorted set '{}' scores [{}, {}]:", key, min, max);
+                sorted_set_members(header, members, with_scores)
+            }
+            Err(e) => Reply::error(format!("Failed to get sorted set range: {}", e)),
+        },
+
+        Command::ZRemRangeByScore { key, min, max } => match store.zremrangebyscore(key, min, max) {
+            Ok(removed) => Reply::count(removed, format!("Removed {} members from sorted set '{}'", removed, key)),
+            Err(e) => Reply::error(format!("Failed to remove from sorted set: {}", e)),
+        },
+
+        Command::ZIncrBy { key, increment, member } => match store.zincrby(key, increment, member) {
+            Ok(score) => Reply::value(format!("'{}:{}' = {}", key, member, score), score.to_string()),
+            Err(e) => Reply::error(format!("Failed to increment score: {}", e)),
+        },
+
+        Command::ZPopMin { key, count } => popped_reply(key, store.zpopmin(key, count)),
+        Command::ZPopMax { key, count } => popped_reply(key, store.zpopmax(key, count)),
+
+        Command::XAdd { key, id, fields, trim } => match store.xadd(key, id, &fields, trim) {
+            Ok(id) => Reply::value(format!("Added entry to stream '{}': {}", key, id), id.to_string()),
+            Err(e) => Reply::error(format!("Failed to add to stream: {}", e)),
+        },
+
+        Command::XLen { key } => match store.xlen(key) {
+            Ok(len) => Reply::count(len, format!("Stream '{}' has {} entries", key, len)),
+            Err(e) => Reply::error(format!("Failed to get stream length: {}", e)),
+        },
+
+        Command::XRange { key, start, end, count } => match store.xrange(key, start, end, count) {
+            Ok(entries) if entries.is_empty() => Reply::array(format!("No entries in range [{}, {}] for stream '{}'", start, end, key), Vec::new()),
+            Ok(entries) => {
+                let mut message = format!("Stream '{}' range [{}, {}]:", key, start, end);
+                let entries = stream_entries(&mut message, &entries, "  ");
+                Reply::Array(message, entries)
+            }
+            Err(e) => Reply::error(format!("Failed to get stream range: {}", e)),
+        },
+
+        Command::XTrim { key, trim } => match store.xtrim(key, trim) {
+            Ok(removed) => Reply::count(removed, format!("Trimmed {} entries from stream '{}'", removed, key)),
+            Err(e) => Reply::error(format!("Failed to trim stream: {}", e)),
+        },
+
+        Command::XGroupCreate { key, group, start, mkstream } => match store.xgroup_create(key, group, start, mkstream) {
+            Ok(true) => Reply::ok(format!("Created consumer group '{}' for stream '{}'", group, key)),
+            Ok(false) => Reply::error(format!("Consumer group '{}' already exists for stream '{}'", group, key)),
+            Err(e) => Reply::error(format!("Failed to create consumer group: {}", e)),
+        },
+
+        Command::XGroupDestroy { key, group } => match store.xgroup_destroy(key, group) {
+            Ok(true) => Reply::bool(true, format!("Destroyed consumer group '{}' of stream '{}'", group, key)),
+            Ok(false) => Reply::bool(false, format!("Stream '{}' has no consumer group '{}'", key, group)),
+            Err(e) => Reply::error(format!("Failed to destroy consumer group: {}", e)),
+        },
+
+        Command::XReadGroup { group, consumer, count, streams } => match store.xreadgroup(group, consumer, &streams, count) {
+            Ok(read) => streams_read(read),
+            Err(e) => Reply::error(format!("Failed to read stream: {}", e)),
+        },
+
+        Command::XAck { key, group, ids } => match store.xack(key, group, &ids) {
+            Ok(acked) => Reply::count(acked, format!("Acknowledged {} entries in group '{}' of stream '{}'", acked, group, key)),
+            Err(e) => Reply::error(format!("Failed to acknowledge entries: {}", e)),
+        },
+
+        // Each consumer with a pending entry, followed by how many it has
+        Command::XPending { key, group } => match store.xgroup(key, group) {
+            Ok(state) => {
+                let mut consumers: BTreeMap<&str, usize> = BTreeMap::new();
+                for (_, pending) in state.pending() {
+                    *consumers.entry(&pending.consumer).or_default() += 1;
+                }
+                match (state.pending().next(), state.pending().last()) {
+                    (Some((first, _)), Some((last, _))) => {
+                        let total: usize = consumers.values().sum();
+                        let header = format!("Group '{}' of stream '{}' has {} pending entries, {} to {}:", group, key, total, first, last);
+                        Reply::list(header, consumers.into_iter().flat_map(|(consumer, count)| [consumer.to_string(), count.to_string()]))
+                    }
+                    _ => Reply::array(format!("Group '{}' of stream '{}' has no pending entries", group, key), Vec::new()),
+                }
+            }
+            Err(e) => Reply::error(format!("Failed to get pending entries: {}", e)),
+        },
+
+        Command::XPendingRange { key, group, min_idle_ms, start, end, count, consumer } => match store.xgroup(key, group) {
+            Ok(state) => {
+                let now = now_millis();
+                let entries: Vec<(StreamId, Fields)> = state
+                    .pending()
+                    .filter(|(id, pending)| {
+                        (start..=end).contains(id)
+                            && consumer.is_none_or(|consumer| pending.consumer == consumer)
+                            && now.saturating_sub(pending.delivered_ms) >= min_idle_ms
+                    })
+                    .take(count)
+                    .map(|(id, pending)| {
+                        let idle = now.saturating_sub(pending.delivered_ms);
+                        let details = [("consumer", pending.consumer.clone()), ("idle_ms", idle.to_string()), ("deliveries", pending.deliveries.to_string())];
+                        (id, details.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+                    })
+                    .collect();
+                if entries.is_empty() {
+                    Reply::array(format!("No pending entries in range [{}, {}] for group '{}' of stream '{}'", start, end, group, key), Vec::new())
+                } else {
+                    let mut message = format!("{} pending entries in group '{}' of stream '{}':", entries.len(), group, key);
+                    let entries = stream_entries(&mut message, &entries, "  ");
+                    Reply::Array(message, entries)
+                }
+            }
+            Err(e) => Reply::error(format!("Failed to get pending entries: {}", e)),
+        },
+
+        Command::XAutoClaim { key, group, consumer, min_idle_ms, start, count } => {
+            match store.xautoclaim(key, group, consumer, min_idle_ms, start, count) {
+                Ok(claim) => {
+                    let mut message = format!(
+                        "Claimed {} entries for '{}' in group '{}' of stream '{}' ({} deleted), next {}:",
+                        claim.claimed.len(),
+                        consumer,
+                        group,
+                        key,
+                        claim.deleted.len(),
+                        claim.next
+                    );
+                    let entries = stream_entries(&mut message, &claim.claimed, "  ");
+                    Reply::Array(message, entries)
+                }
+                Err(e) => Reply::error(format!("Failed to claim entries: {}", e)),
+            }
+        }
+    }
+}
+
+// ZPOPMIN and ZPOPMAX reply with each popped member followed by its score
+fn popped_reply(key: &str, popped: Result<Vec<(String, f64)>, String>) -> Reply {
+    match popped {
+        Ok(members) if members.is_empty() => Reply::nil(format!("Sorted set '{}' is empty", key)),
+        Ok(members) => {
+            let header = format!("Popped {} members from sorted set '{}':", members.len(), key);
+            sorted_set_members(header, members, true)
+        }
+        Err(e) => Reply::error(format!("Failed to pop from sorted set: {}", e)),
+    }
+}
+
+// Each entry's ID at `indent`, with its fields and values one line each
+// indented beneath it, added to `message`. RESP sends each entry as
+// [id, [field, value, ...]].
+fn stream_entries(message: &mut String, entries: &[Entry], indent: &str) -> Vec<Reply> {
+    entries.iter()
+        .map(|(id, fields)| {
+            message.push_str(&format!("\n{}{}", indent, id));
+            let mut values = Vec::new();
+            for (field, value) in fields {
+                message.push_str(&format!("\n{}  {}\n{}  {}", indent, field, indent, value));
+                values.extend([Reply::text(field.as_str()), Reply::text(value.as_str())]);
+            }
+            Reply::Array(String::new(), vec![Reply::text(id.to_string()), Reply::Array(String::new(), values)])
+        })
+        .collect()
+}
+
+// Entries read from each stream by XREAD or XREADGROUP, grouped under the
+// stream's key
+fn streams_read(read: Vec<(String, Vec<Entry>)>) -> Reply {
+    if read.is_empty() {
+        return Reply::nil("No new entries");
+    }
+    let entries: usize = read.iter().map(|(_, entries)| entries.len()).sum();
+    let mut message = format!("Read {} entries from {} streams:", entries, read.len());
+    let mut streams = Vec::new();
+    for (key, entries) in &read {
+        message.push_str(&format!("\n  {}", key));
+        let entries = stream_entries(&mut message, entries, "    ");
+        streams.push(Reply::Array(String::new(), vec![Reply::text(key.as_str()), Reply::Array(String::new(), entries)]));
+    }
+    Reply::Array(message, streams)
+}
+
+// Members listed under `header`. With scores, each member's score follows
+// it on a line of its own, and in the flat member/score array RESP sends.
+fn sorted_set_members(header: String, members: Vec<(String, f64)>, with_scores: bool) -> Reply {
+    let lines = members.into_iter().flat_map(|(member, score)| {
+        let score = with_scores.then(|| score.to_string());
+        std::iter::once(member).chain(score)
+    });
+    Reply::list(header, lines)
+}
+
+// SUBSCRIBE and UNSUBSCRIBE confirm each channel with a reply of its own
+// in RESP, `[kind, channel, subscriptions left]`, where the text protocol
+// lists the channels and counts under one header
+fn subscription_reply(kind: &str, header: String, confirmed: Vec<(String, usize)>) -> Reply {
+    let mut message = header;
+    let mut replies = Vec::new();
+    for (channel, count) in confirmed {
+        message.push_str(&format!("\n  {} {}", channel, count));
+        replies.push(Reply::Array(String::new(), vec![Reply::text(kind), Reply::text(channel), Reply::count(count, count)]));
+    }
+    // Unsubscribing from nothing still gets one reply
+    if replies.is_empty() {
+        replies.push(Reply::Array(String::new(), vec![Reply::text(kind), Reply::nil(""), Reply::count(0, 0)]));
+    }
+    Reply::Each(message, replies)
+}
+
+// Redis-style flag letters for CLIENT LIST: e = no-evict, T = no-touch,
+// x = ephemeral, P = subscriber, S = replica, M = link to the primary
+fn client_flags(client: &ClientInfo) -> String {
+    let mut flags = String::new();
+    if client.no_evict {
+        flags.push('e');
+    }
+    if client.no_touch {
+        flags.push('T');
+    }
+    if client.ephemeral {
+        flags.push('x');
+    }
+    if client.subscriber {
+        flags.push('P');
+    }
+    if client.replica {
+        flags.push('S');
+    }
+    if client.primary_link {
+        flags.push('M');
+    }
+    if flags.is_empty() {
+        flags.push('N');
+    }
+    flags
+}
+
+// Values over the chunk threshold are only served in pieces
+fn over_chunk_threshold(store: &Store, key: &str) -> Option<Reply> {
+    let threshold = store.chunk_threshold()?;
+    match store.string_len(key) {
+        Ok(Some(length)) if length > threshold => Some(Reply::error(format!(
+            "Value of '{}' is {} bytes, over the {} byte chunk threshold; read it with GETCHUNK",
+            key, length, threshold
+        ))),
+        _ => None,
+    }
+}
+
+// Strip a trailing UTF8 flag, as long as `min_len` parts remain, so a
+// SETRANGE value that is just "UTF8" is still a value
+fn string_unit<'a, 'b>(parts: &'b [&'a str], min_len: usize) -> (&'b [&'a str], StringUnit) {
+    match parts.split_last() {
+        Some((last, rest)) if last.eq_ignore_ascii_case("UTF8") && rest.len() >= min_len => (rest, StringUnit::Chars),
+        _ => (parts, StringUnit::Bytes),
+    }
+}
+
+fn unit_name(unit: StringUnit) -> &'static str {
+    match unit {
+        StringUnit::Bytes => "bytes",
+        StringUnit::Chars => "characters",
+    }
+}
+
+/// One line of `CONFIG HELP`: name, type, default, whether it can be
+/// changed while running, and what it does.
+fn describe_option(option: &ConfigOption) -> String {
+    let kind = match option.kind {
+        OptionKind::Enum(values) => format!("enum({})", values.join("|")),
+        kind => kind.name().to_string(),
+    };
+    let runtime = match option.runtime {
+        Some(command) => format!("runtime ({})", command),
+        None => "startup".to_string(),
+    };
+    format!(
+        "{} {} default={} {} - {}",
+        option.name,
+        kind,
+        option.default.as_deref().unwrap_or("(none)"),
+        runtime,
+        option.description
+    )
+}
+
+fn set_string(store: &Store, key: &str, value: &str, ttl: Option<Duration>) -> Reply {
+    let result = match ttl {
+        Some(ttl) => store.set_with_expiry(key, value, ttl),
+        None => store.set(key, value),
+    };
+    match (result, ttl) {
+        (Ok(()), Some(ttl)) if ttl.subsec_millis() == 0 => {
+            Reply::ok(format!("Set '{}' = '{}' with TTL {}s", key, value, ttl.as_secs()))
+        }
+        (Ok(()), Some(ttl)) => Reply::ok(format!("Set '{}' = '{}' with TTL {}ms", key, value, ttl.as_millis())),
+        (Ok(()), None) => Reply::ok(format!("Set '{}' = '{}'", key, value)),
+        (Err(e), _) => Reply::error(format!("Failed to set value: {}", e)),
+    }
+}
+
+// A file a client named, inside `dir`, the directory `setting` configures.
+// Absolute paths and `..` are refused so the name cannot leave it.
+fn confined_path(dir: Option<PathBuf>, setting: &str, name: &str) -> Result<PathBuf, String> {
+    let dir = dir.ok_or_else(|| format!("{} is not set", setting))?;
+    let path = Path::new(name);
+    if !path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("'{}' must be a relative path inside {}", name, setting));
+    }
+    Ok(dir.join(path))
+}
+
+// A blocking command's timeout in seconds: `Some(None)` for 0, which
+// waits forever, and `None` for anything that is not a number of seconds
+// or is too far off to be a deadline
+fn timeout_secs(text: &str) -> Option<Option<Duration>> {
+    match text.parse::<f64>().ok()? {
+        0.0 => Some(None),
+        secs => deadline_timeout(Duration::try_from_secs_f64(secs).ok()?),
+    }
+}
+
+// The same for a timeout in whole milliseconds
+fn timeout_millis(text: &str) -> Option<Option<Duration>> {
+    match text.parse::<u64>().ok()? {
+        0 => Some(None),
+        ms => deadline_timeout(Duration::from_millis(ms)),
+    }
+}
+
+fn parse_vector(parts: &[&str]) -> Option<Vec<f32>> {
+    parts.iter()
+        .map(|part| part.parse::<f32>().ok().filter(|c| c.is_finite()))
+        .collect()
 }
@@ -0,0 +1,660 @@
+//! Typed commands, parsed from a request's arguments before anything runs.
+//!
+//! `parse` checks argument counts and types up front, so execution never
+//! sees a malformed command and the rules can be unit-tested without a
+//! store or a socket. It covers the string, keyspace, hash, list, set,
+//! sorted set and stream commands; anything else is `ParseError::Unknown` and left to the
+//! handler's own parsing in `client_handler`.
+
+use crate::stream::{StreamId, Trim};
+use crate::zset::{parse_score, ScoreBound};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command<'a> {
+    /// `SET`, `SETEX` and `PSETEX`.
+    Set { key: &'a str, value: String, ttl: Option<Duration> },
+    Get { key: &'a str },
+    GetWithTtl { key: &'a str },
+    Delete { key: &'a str },
+    Exists { key: &'a str },
+    Ttl { key: &'a str },
+    Expire { key: &'a str, seconds: u64 },
+    HSet { key: &'a str, field: &'a str, value: String },
+    HGet { key: &'a str, field: &'a str },
+    HGetAll { key: &'a str },
+    HDel { key: &'a str, field: &'a str },
+    HExists { key: &'a str, field: &'a str },
+    HLen { key: &'a str },
+    LPush { key: &'a str, value: String },
+    RPush { key: &'a str, value: String },
+    LPop { key: &'a str },
+    RPop { key: &'a str },
+    LLen { key: &'a str },
+    LRange { key: &'a str, start: i64, stop: i64 },
+    SAdd { key: &'a str, members: Vec<&'a str> },
+    SRem { key: &'a str, members: Vec<&'a str> },
+    SMembers { key: &'a str },
+    SCard { key: &'a str },
+    SIsMember { key: &'a str, member: &'a str },
+    ZAdd { key: &'a str, entries: Vec<(f64, &'a str)> },
+    ZScore { key: &'a str, member: &'a str },
+    ZRank { key: &'a str, member: &'a str },
+    ZRange { key: &'a str, start: i64, stop: i64, with_scores: bool },
+    ZCard { key: &'a str },
+    /// `count` of `None` keeps every member after `offset`.
+    ZRangeByScore { key: &'a str, min: ScoreBound, max: ScoreBound, with_scores: bool, offset: usize, count: Option<usize> },
+    ZRemRangeByScore { key: &'a str, min: ScoreBound, max: ScoreBound },
+    ZIncrBy { key: &'a str, increment: f64, member: &'a str },
+    ZPopMin { key: &'a str, count: usize },
+    ZPopMax { key: &'a str, count: usize },
+    /// An `id` of `None` (`*`) has the stream generate one.
+    XAdd { key: &'a str, id: Option<StreamId>, fields: Vec<(&'a str, &'a str)>, trim: Option<Trim> },
+    XLen { key: &'a str },
+    XRange { key: &'a str, start: StreamId, end: StreamId, count: Option<usize> },
+    XTrim { key: &'a str, trim: Trim },
+    /// A `start` of `None` (`$`) delivers only entries added from now on.
+    XGroupCreate { key: &'a str, group: &'a str, start: Option<StreamId>, mkstream: bool },
+    XGroupDestroy { key: &'a str, group: &'a str },
+    /// An ID of `None` (`>`) reads entries never delivered to the group.
+    XReadGroup { group: &'a str, consumer: &'a str, count: Option<usize>, streams: Vec<(&'a str, Option<StreamId>)> },
+    XAck { key: &'a str, group: &'a str, ids: Vec<StreamId> },
+    XPending { key: &'a str, group: &'a str },
+    XPendingRange { key: &'a str, group: &'a str, min_idle_ms: u64, start: StreamId, end: StreamId, count: usize, consumer: Option<&'a str> },
+    XAutoClaim { key: &'a str, group: &'a str, consumer: &'a str, min_idle_ms: u64, start: StreamId, count: usize },
+}
+
+/// Why a request is not a valid `Command`. Displays as the message sent
+/// back after `ERROR: `.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    /// A command this module does not parse, by the name it was sent as.
+    Unknown(String),
+    /// Too few arguments; the message shows the command's usage.
+    Usage(String),
+    /// An argument of the wrong type, such as a TTL that is not a number.
+    Invalid(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "Empty command"),
+            ParseError::Unknown(name) => write!(f, "Unknown command '{}'", name),
+            ParseError::Usage(message) | ParseError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Parse a request split on whitespace, command name first. Values that
+/// run to the end of the line (SET, HSET, LPUSH...) are joined back with
+/// single spaces.
+pub fn parse<'a>(parts: &[&'a str]) -> Result<Command<'a>, ParseError> {
+    let Some(name) = parts.first() else { return Err(ParseError::Empty) };
+    let name = name.to_uppercase();
+    let usage = |min_len: usize, usage: &str| {
+        if parts.len() < min_len {
+            Err(ParseError::Usage(format!("{} requires {}", name, usage)))
+        } else {
+            Ok(())
+        }
+    };
+
+    let command = match name.as_str() {
+        "SET" => {
+            usage(3, "key and value (SET key value [EX seconds|PX milliseconds])")?;
+            // Only an explicit EX/PX option sets a TTL; a trailing number is part of the value
+            let n = parts.len();
+            let unit: Option<fn(u64) -> Duration> = if n >= 5 {
+                match parts[n - 2].to_uppercase().as_str() {
+                    "EX" => Some(Duration::from_secs),
+                    "PX" => Some(Duration::from_millis),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            match unit {
+                Some(unit) => Command::Set { key: parts[1], value: parts[2..n - 2].join(" "), ttl: Some(parse_expiry(parts[n - 1], unit)?) },
+                None => Command::Set { key: parts[1], value: parts[2..].join(" "), ttl: None },
+            }
+        }
+        "SETEX" => {
+            usage(4, "key, seconds and value (SETEX key seconds value)")?;
+            Command::Set { key: parts[1], value: parts[3..].join(" "), ttl: Some(parse_expiry(parts[2], Duration::from_secs)?) }
+        }
+        "PSETEX" => {
+            usage(4, "key, milliseconds and value (PSETEX key milliseconds value)")?;
+            Command::Set { key: parts[1], value: parts[3..].join(" "), ttl: Some(parse_expiry(parts[2], Duration::from_millis)?) }
+        }
+        "GET" => {
+            usage(2, "a key (GET key)")?;
+            Command::Get { key: parts[1] }
+        }
+        "GETWITHTTL" => {
+            usage(2, "a key (GETWITHTTL key)")?;
+            Command::GetWithTtl { key: parts[1] }
+        }
+        "DELETE" => {
+            usage(2, "a key (DELETE key)")?;
+            Command::Delete { key: parts[1] }
+        }
+        "EXISTS" => {
+            usage(2, "a key (EXISTS key)")?;
+            Command::Exists { key: parts[1] }
+        }
+        "TTL" => {
+            usage(2, "a key (TTL key)")?;
+            Command::Ttl { key: parts[1] }
+        }
+        "EXPIRE" => {
+            usage(3, "key and seconds (EXPIRE key seconds)")?;
+            let seconds = parts[2]
+                .parse::<u64>()
+                .ok()
+                .filter(|seconds| deadline_timeout(Duration::from_secs(*seconds)).is_some())
+                .ok_or_else(|| ParseError::Invalid("Invalid TTL value".to_string()))?;
+            Command::Expire { key: parts[1], seconds }
+        }
+        "HSET" => {
+            usage(4, "key, field, and value (HSET key field value)")?;
+            Command::HSet { key: parts[1], field: parts[2], value: parts[3..].join(" ") }
+        }
+        "HGET" => {
+            usage(3, "key and field (HGET key field)")?;
+            Command::HGet { key: parts[1], field: parts[2] }
+        }
+        "HGETALL" => {
+            usage(2, "a key (HGETALL key)")?;
+            Command::HGetAll { key: parts[1] }
+        }
+        "HDEL" => {
+            usage(3, "key and field (HDEL key field)")?;
+            Command::HDel { key: parts[1], field: parts[2] }
+        }
+        "HEXISTS" => {
+            usage(3, "key and field (HEXISTS key field)")?;
+            Command::HExists { key: parts[1], field: parts[2] }
+        }
+        "HLEN" => {
+            usage(2, "a key (HLEN key)")?;
+            Command::HLen { key: parts[1] }
+        }
+        "LPUSH" => {
+            usage(3, "key and value (LPUSH key value)")?;
+            Command::LPush { key: parts[1], value: parts[2..].join(" ") }
+        }
+        "RPUSH" => {
+            usage(3, "key and value (RPUSH key value)")?;
+            Command::RPush { key: parts[1], value: parts[2..].join(" ") }
+        }
+        "LPOP" => {
+            usage(2, "a key (LPOP key)")?;
+            Command::LPop { key: parts[1] }
+        }
+        "RPOP" => {
+            usage(2, "a key (RPOP key)")?;
+            Command::RPop { key: parts[1] }
+        }
+        "LLEN" => {
+            usage(2, "a key (LLEN key)")?;
+            Command::LLen { key: parts[1] }
+        }
+        "LRANGE" => {
+            usage(4, "key, start, and stop (LRANGE key start stop)")?;
+            let start = parts[2].parse().map_err(|_| ParseError::Invalid("Invalid start index".to_string()))?;
+            let stop = parts[3].parse().map_err(|_| ParseError::Invalid("Invalid stop index".to_string()))?;
+            Command::LRange { key: parts[1], start, stop }
+        }
+        "SADD" => {
+            usage(3, "key and at least one member (SADD key member [member ...])")?;
+            Command::SAdd { key: parts[1], members: parts[2..].to_vec() }
+        }
+        "SREM" => {
+            usage(3, "key and at least one member (SREM key member [member ...])")?;
+            Command::SRem { key: parts[1], members: parts[2..].to_vec() }
+        }
+        "SMEMBERS" => {
+            usage(2, "a key (SMEMBERS key)")?;
+            Command::SMembers { key: parts[1] }
+        }
+        "SCARD" => {
+            usage(2, "a key (SCARD key)")?;
+            Command::SCard { key: parts[1] }
+        }
+        "SISMEMBER" => {
+            usage(3, "key and member (SISMEMBER key member)")?;
+            Command::SIsMember { key: parts[1], member: parts[2] }
+        }
+        "ZADD" => {
+            usage(4, "key and score/member pairs (ZADD key score member [score member ...])")?;
+            if !parts.len().is_multiple_of(2) {
+                return Err(ParseError::Usage("ZADD requires a member after every score".to_string()));
+            }
+            let entries = parts[2..]
+                .chunks(2)
+                .map(|pair| parse_score(pair[0]).map(|score| (score, pair[1])).ok_or_else(|| ParseError::Invalid(format!("Invalid score '{}'", pair[0]))))
+                .collect::<Result<_, _>>()?;
+            Command::ZAdd { key: parts[1], entries }
+        }
+        "ZSCORE" => {
+            usage(3, "key and member (ZSCORE key member)")?;
+            Command::ZScore { key: parts[1], member: parts[2] }
+        }
+        "ZRANK" => {
+            usage(3, "key and member (ZRANK key member)")?;
+            Command::ZRank { key: parts[1], member: parts[2] }
+        }
+        "ZRANGE" => {
+            usage(4, "key, start, and stop (ZRANGE key start stop [WITHSCORES])")?;
+            let start = parts[2].parse().map_err(|_| ParseError::Invalid("Invalid start index".to_string()))?;
+            let stop = parts[3].parse().map_err(|_| ParseError::Invalid("Invalid stop index".to_string()))?;
+            let with_scores = match parts.get(4) {
+                None => false,
+                Some(option) if option.eq_ignore_ascii_case("WITHSCORES") && parts.len() == 5 => true,
+                Some(option) => return Err(ParseError::Invalid(format!("Unknown ZRANGE option '{}'", option))),
+            };
+            Command::ZRange { key: parts[1], start, stop, with_scores }
+        }
+        "ZCARD" => {
+            usage(2, "a key (ZCARD key)")?;
+            Command::ZCard { key: parts[1] }
+        }
+        "ZRANGEBYSCORE" => {
+            usage(4, "key, min, and max (ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count])")?;
+            let (min, max) = (parse_bound(parts[2])?, parse_bound(parts[3])?);
+            let (mut with_scores, mut offset, mut count) = (false, 0, None);
+            let mut options = parts[4..].iter();
+            while let Some(option) = options.next() {
+                if option.eq_ignore_ascii_case("WITHSCORES") {
+                    with_scores = true;
+                } else if option.eq_ignore_ascii_case("LIMIT") {
+                    let (Some(start), Some(limit)) = (options.next(), options.next()) else {
+                        return Err(ParseError::Usage("LIMIT requires an offset and a count (LIMIT offset count)".to_string()));
+                    };
+                    offset = start.parse().map_err(|_| ParseError::Invalid(format!("Invalid LIMIT offset '{}'", start)))?;
+                    // A negative count, as in Redis, means no limit
+                    let limit: i64 = limit.parse().map_err(|_| ParseError::Invalid(format!("Invalid LIMIT count '{}'", limit)))?;
+                    count = usize::try_from(limit).ok();
+                } else {
+                    return Err(ParseError::Invalid(format!("Unknown ZRANGEBYSCORE option '{}'", option)));
+                }
+            }
+            Command::ZRangeByScore { key: parts[1], min, max, with_scores, offset, count }
+        }
+        "ZREMRANGEBYSCORE" => {
+            usage(4, "key, min, and max (ZREMRANGEBYSCORE key min max)")?;
+            Command::ZRemRangeByScore { key: parts[1], min: parse_bound(parts[2])?, max: parse_bound(parts[3])? }
+        }
+        "ZINCRBY" => {
+            usage(4, "key, increment, and member (ZINCRBY key increment member)")?;
+            let increment = parse_score(parts[2]).ok_or_else(|| ParseError::Invalid(format!("Invalid increment '{}'", parts[2])))?;
+            Command::ZIncrBy { key: parts[1], increment, member: parts[3] }
+        }
+        "ZPOPMIN" | "ZPOPMAX" => {
+            if parts.len() < 2 || parts.len() > 3 {
+                return Err(ParseError::Usage(format!("{} requires a key and an optional count ({} key [count])", name, name)));
+            }
+            let count = match parts.get(2) {
+                Some(count) => count.parse().map_err(|_| ParseError::Invalid(format!("Invalid count '{}'", count)))?,
+                None => 1,
+            };
+            if name == "ZPOPMIN" {
+                Command::ZPopMin { key: parts[1], count }
+            } else {
+                Command::ZPopMax { key: parts[1], count }
+            }
+        }
+        "XADD" => {
+            let syntax = "key, ID, and field/value pairs (XADD key [MAXLEN|MINID [=|~] threshold] id|* field value [field value ...])";
+            usage(5, syntax)?;
+            let (trim, used) = match parse_trim(&parts[2..]) {
+                Ok((trim, used)) => (Some(trim), used),
+                Err(None) => (None, 0),
+                Err(Some(e)) => return Err(e),
+            };
+            let rest = &parts[2 + used..];
+            if rest.len() < 3 {
+                return Err(ParseError::Usage(format!("XADD requires {}", syntax)));
+            }
+            if rest.len().is_multiple_of(2) {
+                return Err(ParseError::Usage("XADD requires a value after every field".to_string()));
+            }
+            let id = match rest[0] {
+                "*" => None,
+                id => Some(parse_stream_id(id, 0)?),
+            };
+            let fields = rest[1..].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+            Command::XAdd { key: parts[1], id, fields, trim }
+        }
+        "XLEN" => {
+            usage(2, "a key (XLEN key)")?;
+            Command::XLen { key: parts[1] }
+        }
+        "XRANGE" => {
+            usage(4, "key, start, and end (XRANGE key start end [COUNT count])")?;
+            let (start, end) = (parse_range_start(parts[2])?, parse_range_end(parts[3])?);
+            let count = match &parts[4..] {
+                [] => None,
+                [option, count] if option.eq_ignore_ascii_case("COUNT") => {
+                    Some(count.parse().map_err(|_| ParseError::Invalid(format!("Invalid count '{}'", count)))?)
+                }
+                [option, ..] => return Err(ParseError::Invalid(format!("Unknown XRANGE option '{}'", option))),
+            };
+            Command::XRange { key: parts[1], start, end, count }
+        }
+        "XTRIM" => {
+            usage(4, "key and a threshold (XTRIM key MAXLEN|MINID [=|~] threshold)")?;
+            match parse_trim(&parts[2..]) {
+                Ok((trim, used)) if 2 + used == parts.len() => Command::XTrim { key: parts[1], trim },
+                Ok(_) => return Err(ParseError::Invalid(format!("Unknown XTRIM option '{}'", parts[parts.len() - 1]))),
+                Err(Some(e)) => return Err(e),
+                Err(None) => return Err(ParseError::Invalid(format!("Unknown XTRIM strategy '{}', expected MAXLEN or MINID", parts[2]))),
+            }
+        }
+        "XGROUP" => {
+            usage(2, "a subcommand (XGROUP CREATE key group id|$ [MKSTREAM] or XGROUP DESTROY key group)")?;
+            match parts[1].to_uppercase().as_str() {
+                "CREATE" => {
+                    if parts.len() < 5 || parts.len() > 6 {
+                        return Err(ParseError::Usage("XGROUP CREATE requires key, group, and ID (XGROUP CREATE key group id|$ [MKSTREAM])".to_string()));
+                    }
+                    let start = match parts[4] {
+                        "$" => None,
+                        id => Some(parse_stream_id(id, 0)?),
+                    };
+                    let mkstream = match parts.get(5) {
+                        None => false,
+                        Some(option) if option.eq_ignore_ascii_case("MKSTREAM") => true,
+                        Some(option) => return Err(ParseError::Invalid(format!("Unknown XGROUP CREATE option '{}'", option))),
+                    };
+                    Command::XGroupCreate { key: parts[2], group: parts[3], start, mkstream }
+                }
+                "DESTROY" => {
+                    if parts.len() != 4 {
+                        return Err(ParseError::Usage("XGROUP DESTROY requires key and group (XGROUP DESTROY key group)".to_string()));
+                    }
+                    Command::XGroupDestroy { key: parts[2], group: parts[3] }
+                }
+                _ => return Err(ParseError::Invalid(format!("Unknown XGROUP subcommand '{}'", parts[1]))),
+            }
+        }
+        "XREADGROUP" => {
+            let syntax = "a group, consumer, streams, and IDs (XREADGROUP GROUP group consumer [COUNT count] STREAMS key [key ...] id|> [id|> ...])";
+            usage(7, syntax)?;
+            if !parts[1].eq_ignore_ascii_case("GROUP") {
+                return Err(ParseError::Usage(format!("XREADGROUP requires {}", syntax)));
+            }
+            let (group, consumer) = (parts[2], parts[3]);
+            let mut count = None;
+            let mut i = 4;
+            while !parts[i].eq_ignore_ascii_case("STREAMS") {
+                match (parts[i].to_uppercase().as_str(), parts.get(i + 1)) {
+                    ("COUNT", Some(n)) => count = Some(n.parse().map_err(|_| ParseError::Invalid(format!("Invalid count '{}'", n)))?),
+                    ("COUNT", None) => return Err(ParseError::Usage(format!("XREADGROUP requires {}", syntax))),
+                    _ => return Err(ParseError::Invalid(format!("Unknown XREADGROUP option '{}'", parts[i]))),
+                }
+                i += 2;
+                if i >= parts.len() {
+                    return Err(ParseError::Usage(format!("XREADGROUP requires {}", syntax)));
+                }
+            }
+            let streams = &parts[i + 1..];
+            if streams.is_empty() || !streams.len().is_multiple_of(2) {
+                return Err(ParseError::Usage(format!("XREADGROUP requires {}", syntax)));
+            }
+            let (keys, ids) = streams.split_at(streams.len() / 2);
+            let ids = ids
+                .iter()
+                .map(|id| match *id {
+                    ">" => Ok(None),
+                    id => parse_stream_id(id, 0).map(Some),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Command::XReadGroup { group, consumer, count, streams: keys.iter().copied().zip(ids).collect() }
+        }
+        "XACK" => {
+            usage(4, "key, group, and at least one ID (XACK key group id [id ...])")?;
+            let ids = parts[3..].iter().map(|id| parse_stream_id(id, 0)).collect::<Result<_, _>>()?;
+            Command::XAck { key: parts[1], group: parts[2], ids }
+        }
+        "XPENDING" => {
+            let syntax = "key and group (XPENDING key group [[IDLE min-idle-ms] start end count [consumer]])";
+            usage(3, syntax)?;
+            let (key, group) = (parts[1], parts[2]);
+            let (min_idle_ms, range) = match parts.get(3) {
+                None => return Ok(Command::XPending { key, group }),
+                Some(option) if option.eq_ignore_ascii_case("IDLE") => {
+                    let idle = parts.get(4).ok_or_else(|| ParseError::Usage(format!("XPENDING requires {}", syntax)))?;
+                    (idle.parse().map_err(|_| ParseError::Invalid(format!("Invalid idle time '{}'", idle)))?, &parts[5..])
+                }
+                Some(_) => (0, &parts[3..]),
+            };
+            let (start, end, count, consumer) = match range {
+                [start, end, count] => (start, end, count, None),
+                [start, end, count, consumer] => (start, end, count, Some(*consumer)),
+                _ => return Err(ParseError::Usage(format!("XPENDING requires {}", syntax))),
+            };
+            Command::XPendingRange {
+                key,
+                group,
+                min_idle_ms,
+                start: parse_range_start(start)?,
+                end: parse_range_end(end)?,
+                count: count.parse().map_err(|_| ParseError::Invalid(format!("Invalid count '{}'", count)))?,
+                consumer,
+            }
+        }
+        "XAUTOCLAIM" => {
+            usage(6, "key, group, consumer, idle time, and start (XAUTOCLAIM key group consumer min-idle-ms start [COUNT count])")?;
+            let min_idle_ms = parts[4].parse().map_err(|_| ParseError::Invalid(format!("Invalid idle time '{}'", parts[4])))?;
+            let count = match &parts[6..] {
+                [] => 100,
+                [option, count] if option.eq_ignore_ascii_case("COUNT") => match count.parse() {
+                    Ok(count) if count > 0 => count,
+                    _ => return Err(ParseError::Invalid(format!("Invalid count '{}'", count))),
+                },
+                [option, ..] => return Err(ParseError::Invalid(format!("Unknown XAUTOCLAIM option '{}'", option))),
+            };
+            Command::XAutoClaim { key: parts[1], group: parts[2], consumer: parts[3], min_idle_ms, start: parse_range_start(parts[5])?, count }
+        }
+        _ => return Err(ParseError::Unknown(parts[0].to_string())),
+    };
+    Ok(command)
+}
+
+/// A positive expiry amount, converted with `unit`, that is not too far
+/// off to be a deadline.
+fn parse_expiry(amount: &str, unit: fn(u64) -> Duration) -> Result<Duration, ParseError> {
+    amount.parse::<u64>()
+        .ok()
+        .filter(|amount| *amount > 0)
+        .map(unit)
+        .filter(|ttl| deadline_timeout(*ttl).is_some())
+        .ok_or_else(|| ParseError::Invalid(format!("Invalid expire time '{}'", amount)))
+}
+
+/// `Some(Some(timeout))` if a deadline `timeout` from now can be
+/// represented, `None` if it is too far off.
+pub fn deadline_timeout(timeout: Duration) -> Option<Option<Duration>> {
+    Instant::now().checked_add(timeout).map(|_| Some(timeout))
+}
+
+/// A score range bound such as `5`, `(5` or `-inf`.
+fn parse_bound(text: &str) -> Result<ScoreBound, ParseError> {
+    ScoreBound::parse(text).ok_or_else(|| ParseError::Invalid(format!("Invalid score bound '{}'", text)))
+}
+
+/// A stream ID, `ms-seq` or a bare `ms` taking `default_seq`.
+pub fn parse_stream_id(text: &str, default_seq: u64) -> Result<StreamId, ParseError> {
+    StreamId::parse(text, default_seq).ok_or_else(|| ParseError::Invalid(format!("Invalid stream ID '{}'", text)))
+}
+
+// The first ID of a range: `-` for the start of the stream, or an ID
+fn parse_range_start(text: &str) -> Result<StreamId, ParseError> {
+    if text == "-" { Ok(StreamId::MIN) } else { parse_stream_id(text, 0) }
+}
+
+// The last ID of a range: `+` for the end of the stream, or an ID, where a
+// bare millisecond covers every sequence number in it
+fn parse_range_end(text: &str) -> Result<StreamId, ParseError> {
+    if text == "+" { Ok(StreamId::MAX) } else { parse_stream_id(text, u64::MAX) }
+}
+
+/// `MAXLEN|MINID [=|~] threshold` at the start of `parts`, with how many
+/// arguments it took. `Err(None)` if `parts` does not start with one.
+/// Trimming is always exact, so `~` is accepted and treated as `=`.
+fn parse_trim(parts: &[&str]) -> Result<(Trim, usize), Option<ParseError>> {
+    let Some(strategy) = parts.first().map(|strategy| strategy.to_uppercase()) else { return Err(None) };
+    if strategy != "MAXLEN" && strategy != "MINID" {
+        return Err(None);
+    }
+    let (threshold, used) = match parts.get(1) {
+        Some(&"=" | &"~") => (parts.get(2), 3),
+        threshold => (threshold, 2),
+    };
+    let Some(threshold) = threshold else {
+        return Err(Some(ParseError::Usage(format!("{} requires a threshold ({} [=|~] threshold)", strategy, strategy))));
+    };
+    let trim = if strategy == "MAXLEN" {
+        threshold.parse().map(Trim::MaxLen).map_err(|_| ParseError::Invalid(format!("Invalid MAXLEN '{}'", threshold)))
+    } else {
+        parse_stream_id(threshold, 0).map(Trim::MinId)
+    };
+    trim.map(|trim| (trim, used)).map_err(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Command<'_>, ParseError> {
+        parse(&line.split_whitespace().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse_line("set greeting hello  world"), Ok(Command::Set { key: "greeting", value: "hello world".to_string(), ttl: None }));
+        assert_eq!(
+            parse_line("SET session abc EX 60"),
+            Ok(Command::Set { key: "session", value: "abc".to_string(), ttl: Some(Duration::from_secs(60)) })
+        );
+        // A trailing number without EX/PX is part of the value
+        assert_eq!(parse_line("SET version 1 2"), Ok(Command::Set { key: "version", value: "1 2".to_string(), ttl: None }));
+        assert_eq!(
+            parse_line("PSETEX k 1500 v"),
+            Ok(Command::Set { key: "k", value: "v".to_string(), ttl: Some(Duration::from_millis(1500)) })
+        );
+        assert_eq!(parse_line("HGET user:1 name"), Ok(Command::HGet { key: "user:1", field: "name" }));
+        assert_eq!(parse_line("LRANGE l 0 -1"), Ok(Command::LRange { key: "l", start: 0, stop: -1 }));
+        assert_eq!(parse_line("Expire k 10"), Ok(Command::Expire { key: "k", seconds: 10 }));
+        // Set members are separate arguments, unlike list values
+        assert_eq!(parse_line("SADD tags rust  db"), Ok(Command::SAdd { key: "tags", members: vec!["rust", "db"] }));
+        assert_eq!(
+            parse_line("ZADD board 12.5 ann -inf bob"),
+            Ok(Command::ZAdd { key: "board", entries: vec![(12.5, "ann"), (f64::NEG_INFINITY, "bob")] })
+        );
+        assert_eq!(
+            parse_line("ZRANGE board 0 -1 withscores"),
+            Ok(Command::ZRange { key: "board", start: 0, stop: -1, with_scores: true })
+        );
+        let bound = |score, exclusive| ScoreBound { score, exclusive };
+        assert_eq!(
+            parse_line("ZRANGEBYSCORE board (10 +inf LIMIT 1 -1 WITHSCORES"),
+            Ok(Command::ZRangeByScore {
+                key: "board",
+                min: bound(10.0, true),
+                max: bound(f64::INFINITY, false),
+                with_scores: true,
+                offset: 1,
+                count: None
+            })
+        );
+        assert_eq!(parse_line("zpopmax board"), Ok(Command::ZPopMax { key: "board", count: 1 }));
+        assert_eq!(parse_line("ZPOPMIN board 3"), Ok(Command::ZPopMin { key: "board", count: 3 }));
+        assert_eq!(
+            parse_line("XADD events * type click page home"),
+            Ok(Command::XAdd { key: "events", id: None, fields: vec![("type", "click"), ("page", "home")], trim: None })
+        );
+        assert_eq!(
+            parse_line("XADD events maxlen ~ 100 5-1 type click"),
+            Ok(Command::XAdd { key: "events", id: Some(StreamId::new(5, 1)), fields: vec![("type", "click")], trim: Some(Trim::MaxLen(100)) })
+        );
+        assert_eq!(parse_line("XTRIM events MINID 1000"), Ok(Command::XTrim { key: "events", trim: Trim::MinId(StreamId::new(1000, 0)) }));
+        assert_eq!(
+            parse_line("XGROUP create events workers $ MKSTREAM"),
+            Ok(Command::XGroupCreate { key: "events", group: "workers", start: None, mkstream: true })
+        );
+        assert_eq!(
+            parse_line("XREADGROUP GROUP workers ann COUNT 5 STREAMS events audit > 0"),
+            Ok(Command::XReadGroup { group: "workers", consumer: "ann", count: Some(5), streams: vec![("events", None), ("audit", Some(StreamId::MIN))] })
+        );
+        assert_eq!(
+            parse_line("XPENDING events workers IDLE 5000 - + 10 ann"),
+            Ok(Command::XPendingRange {
+                key: "events",
+                group: "workers",
+                min_idle_ms: 5000,
+                start: StreamId::MIN,
+                end: StreamId::MAX,
+                count: 10,
+                consumer: Some("ann")
+            })
+        );
+        assert_eq!(
+            parse_line("XAUTOCLAIM events workers bob 60000 0-0"),
+            Ok(Command::XAutoClaim { key: "events", group: "workers", consumer: "bob", min_idle_ms: 60000, start: StreamId::MIN, count: 100 })
+        );
+        assert_eq!(
+            parse_line("XRANGE events 1000 + count 10"),
+            Ok(Command::XRange { key: "events", start: StreamId::new(1000, 0), end: StreamId::MAX, count: Some(10) })
+        );
+        assert_eq!(
+            parse_line("XRANGE events - 1000"),
+            Ok(Command::XRange { key: "events", start: StreamId::MIN, end: StreamId::new(1000, u64::MAX), count: None })
+        );
+        assert_eq!(parse_line("ZINCRBY board -2.5 ann"), Ok(Command::ZIncrBy { key: "board", increment: -2.5, member: "ann" }));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(&[]), Err(ParseError::Empty));
+        assert_eq!(parse_line("PING"), Err(ParseError::Unknown("PING".to_string())));
+        let message = |line| parse_line(line).unwrap_err().to_string();
+        assert_eq!(message("get"), "GET requires a key (GET key)");
+        assert_eq!(message("SETEX k v"), "SETEX requires key, seconds and value (SETEX key seconds value)");
+        assert_eq!(message("SET k v EX soon"), "Invalid expire time 'soon'");
+        assert_eq!(message("SETEX k 0 v"), "Invalid expire time '0'");
+        assert_eq!(message("SETEX k 18446744073709551615 v"), "Invalid expire time '18446744073709551615'");
+        assert_eq!(message("SET k v EX 18446744073709551615"), "Invalid expire time '18446744073709551615'");
+        assert_eq!(message("EXPIRE k -1"), "Invalid TTL value");
+        assert_eq!(message("EXPIRE k 18446744073709551615"), "Invalid TTL value");
+        assert_eq!(message("LRANGE l a 1"), "Invalid start index");
+        assert_eq!(message("HSET h f"), "HSET requires key, field, and value (HSET key field value)");
+        assert_eq!(message("SREM tags"), "SREM requires key and at least one member (SREM key member [member ...])");
+        assert_eq!(message("ZADD board 1 ann 2"), "ZADD requires a member after every score");
+        assert_eq!(message("ZADD board NaN ann"), "Invalid score 'NaN'");
+        assert_eq!(message("ZRANGE board 0 1 LIMIT"), "Unknown ZRANGE option 'LIMIT'");
+        assert_eq!(message("ZRANGEBYSCORE board [1 5"), "Invalid score bound '[1'");
+        assert_eq!(message("ZRANGEBYSCORE board 1 5 LIMIT 0"), "LIMIT requires an offset and a count (LIMIT offset count)");
+        assert_eq!(message("ZRANGEBYSCORE board 1 5 LIMIT -1 2"), "Invalid LIMIT offset '-1'");
+        assert_eq!(message("ZINCRBY board NaN ann"), "Invalid increment 'NaN'");
+        assert_eq!(message("zpopmin board 1 2"), "ZPOPMIN requires a key and an optional count (ZPOPMIN key [count])");
+        assert_eq!(message("ZPOPMAX board -1"), "Invalid count '-1'");
+        assert_eq!(
+            message("XADD events MAXLEN 5 * type"),
+            "XADD requires key, ID, and field/value pairs (XADD key [MAXLEN|MINID [=|~] threshold] id|* field value [field value ...])"
+        );
+        assert_eq!(message("XADD events MAXLEN x * type click"), "Invalid MAXLEN 'x'");
+        assert_eq!(message("XTRIM events LENGTH 5"), "Unknown XTRIM strategy 'LENGTH', expected MAXLEN or MINID");
+        assert_eq!(message("XTRIM events MAXLEN ="), "MAXLEN requires a threshold (MAXLEN [=|~] threshold)");
+        assert_eq!(message("XGROUP SETID events workers 0"), "Unknown XGROUP subcommand 'SETID'");
+        assert_eq!(message("XREADGROUP GROUP workers ann BLOCK 0 STREAMS events >"), "Unknown XREADGROUP option 'BLOCK'");
+        assert_eq!(message("XPENDING events workers - +"), "XPENDING requires key and group (XPENDING key group [[IDLE min-idle-ms] start end count [consumer]])");
+        assert_eq!(message("XADD events * type click page"), "XADD requires a value after every field");
+        assert_eq!(message("XADD events 12-x type click"), "Invalid stream ID '12-x'");
+        assert_eq!(message("XRANGE events - + COUNT"), "Unknown XRANGE option 'COUNT'");
+    }
+}
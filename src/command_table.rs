@@ -0,0 +1,329 @@
+/// Documentation for one command (or one subcommand, named "CLIENT LIST"
+/// style), served by `HELP`, and where its key arguments are.
+///
+/// Key positions count the command name (and subcommand) as arguments, so
+/// `GET key` has its key at 1. `first_key` 0 means the command takes no
+/// keys; a negative `last_key` counts back from the last argument.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub syntax: &'static str,
+    pub summary: &'static str,
+    pub since: &'static str,
+    pub first_key: usize,
+    pub last_key: isize,
+    pub key_step: usize,
+    pub kind: CommandKind,
+}
+
+/// What running a command does, so replica routing, fair scheduling and
+/// the like can tell commands apart without their own lists.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandKind {
+    /// Only reads the dataset; safe to send to a replica.
+    Read,
+    /// Changes the dataset (keys, values, TTLs or indexes).
+    Write,
+    /// Acts on the server or connection rather than the dataset.
+    Admin,
+    /// May wait for another client before replying.
+    Blocking,
+}
+
+impl CommandKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandKind::Read => "read",
+            CommandKind::Write => "write",
+            CommandKind::Admin => "admin",
+            CommandKind::Blocking => "blocking",
+        }
+    }
+}
+
+const fn spec(name: &'static str, syntax: &'static str, summary: &'static str) -> CommandSpec {
+    CommandSpec { name, syntax, summary, since: "0.1.0", first_key: 0, last_key: 0, key_step: 0, kind: CommandKind::Admin }
+}
+
+impl CommandSpec {
+    const fn read(self) -> Self {
+        CommandSpec { kind: CommandKind::Read, ..self }
+    }
+
+    const fn write(self) -> Self {
+        CommandSpec { kind: CommandKind::Write, ..self }
+    }
+
+    const fn admin(self) -> Self {
+        CommandSpec { kind: CommandKind::Admin, ..self }
+    }
+
+    const fn blocking(self) -> Self {
+        CommandSpec { kind: CommandKind::Blocking, ..self }
+    }
+
+    const fn keys(self, first_key: usize, last_key: isize, key_step: usize) -> Self {
+        CommandSpec { first_key, last_key, key_step, ..self }
+    }
+
+    // A command whose only key is the first argument
+    const fn key(self) -> Self {
+        self.keys(1, 1, 1)
+    }
+}
+
+/// Every command the server understands, in the order `HELP` lists them.
+pub static COMMANDS: &[CommandSpec] = &[
+    spec("SET", "SET key value [EX seconds|PX milliseconds]", "Store a string, optionally expiring after a TTL").key().write(),
+    spec("SETEX", "SETEX key seconds value", "Store a string that expires after the given seconds").key().write(),
+    spec("PSETEX", "PSETEX key milliseconds value", "Store a string that expires after the given milliseconds").key().write(),
+    spec("GET", "GET key", "Retrieve a string value").key().read(),
+    spec("GETCHUNK", "GETCHUNK key offset length", "Read part of a large string value by byte range").key().read(),
+    spec("SETCHUNK", "SETCHUNK key offset data", "Write a large string value in pieces: offset 0 starts it, later chunks append at its length").key().write(),
+    spec("STRLEN", "STRLEN key [UTF8]", "Length of a string value in bytes, or characters with UTF8").key().read(),
+    spec("GETRANGE", "GETRANGE key start end [UTF8]", "Part of a string value by inclusive byte range; negative indexes count from the end").key().read(),
+    spec("SUBSTR", "SUBSTR key start end [UTF8]", "Same as GETRANGE").key().read(),
+    spec("SETRANGE", "SETRANGE key offset value [UTF8]", "Overwrite part of a string value from a byte offset, padding with NULs").key().write(),
+    spec("CAS", "CAS key expected new", "Replace a string value only if it equals expected; replies whether it did").key().write(),
+    spec("GETWITHTTL", "GETWITHTTL key", "Retrieve a string value together with its remaining TTL").key().read(),
+    spec("LCS", "LCS key1 key2 [LEN] [IDX] [MINMATCHLEN n]", "Longest common subsequence of two strings, its length or matching ranges").keys(1, 2, 1).read(),
+    spec("DELETE", "DELETE key", "Remove a key").key().write(),
+    spec("EXISTS", "EXISTS key", "Check whether a key exists").key().read(),
+    spec("PIN", "PIN key|prefix*", "Never evict the key, or keys matching the pattern, under maxmemory").admin(),
+    spec("UNPIN", "UNPIN key|prefix*", "Make a pinned key or pattern evictable again").admin(),
+    spec("PINNED", "PINNED", "List pinned keys and patterns").admin(),
+    spec("OBJECT IDLETIME", "OBJECT IDLETIME key", "Seconds since a command last accessed the key").keys(2, 2, 1).read(),
+    spec("TTL", "TTL key", "Remaining time to live of a key").key().read(),
+    spec("EXPIRE", "EXPIRE key seconds", "Set a key's time to live").key().write(),
+    spec("EXPIREMANY", "EXPIREMANY seconds key [key ...]", "Set the same time to live on several keys at once").keys(2, -1, 1).write(),
+    spec("EXPIREPATTERN", "EXPIREPATTERN pattern seconds", "Set a time to live on every key matching a pattern").write(),
+    spec("WAIT", "WAIT numreplicas milliseconds", "Block until that many replicas have applied every earlier write; replies how many have").blocking(),
+    spec("WAITKEY", "WAITKEY key seconds [CHANGE]", "Block until a key exists (or, with CHANGE, is next written); 0 waits forever").key().blocking(),
+    spec("LIST", "LIST", "List all keys").read(),
+    spec("KEYS", "KEYS pattern", "Find keys matching a pattern (* wildcard)").read(),
+    spec("SCAN", "SCAN cursor [MATCH pattern] [COUNT n] [TYPE type]", "Iterate over keys a batch at a time, starting from cursor 0 until it returns 0").read(),
+    spec("COUNT", "COUNT", "Number of keys").read(),
+    spec("CLEAR", "CLEAR [CONFIRM token]", "Remove all keys (alias FLUSHALL); the token is required if flush protection is on").write(),
+    spec("FLUSHALL", "FLUSHALL [CONFIRM token]", "Remove all keys (alias CLEAR); the token is required if flush protection is on").write(),
+    spec("HSET", "HSET key field value", "Set a hash field").key().write(),
+    spec("HGET", "HGET key field", "Get a hash field").key().read(),
+    spec("HSETBIN", "HSETBIN key field base64", "Set a hash field to binary data, sent base64-encoded").key().write(),
+    spec("HGETBIN", "HGETBIN key field", "Get a hash field as base64, for values that are not text").key().read(),
+    spec("HGETALL", "HGETALL key", "Get all fields and values of a hash").key().read(),
+    spec("HDEL", "HDEL key field", "Delete a hash field").key().write(),
+    spec("HEXISTS", "HEXISTS key field", "Check whether a hash field exists").key().read(),
+    spec("HLEN", "HLEN key", "Number of fields in a hash").key().read(),
+    spec("LPUSH", "LPUSH key value", "Push a value onto the head of a list").key().write(),
+    spec("RPUSH", "RPUSH key value", "Push a value onto the tail of a list").key().write(),
+    spec("LPOP", "LPOP key", "Pop a value from the head of a list").key().write(),
+    spec("RPOP", "RPOP key", "Pop a value from the tail of a list").key().write(),
+    spec("LLEN", "LLEN key", "Length of a list").key().read(),
+    spec("LRANGE", "LRANGE key start stop", "Range of list items (negative indices count from the end)").key().read(),
+    spec("SADD", "SADD key member [member ...]", "Add members to a set; replies how many were new").key().write(),
+    spec("SREM", "SREM key member [member ...]", "Remove members from a set, deleting it once empty").key().write(),
+    spec("SMEMBERS", "SMEMBERS key", "Members of a set, sorted").key().read(),
+    spec("SCARD", "SCARD key", "Number of members in a set").key().read(),
+    spec("SISMEMBER", "SISMEMBER key member", "Whether a value is a member of a set").key().read(),
+    spec("ZADD", "ZADD key score member [score member ...]", "Set members' scores in a sorted set; replies how many were new").key().write(),
+    spec("ZSCORE", "ZSCORE key member", "Score of a sorted set member").key().read(),
+    spec("ZRANK", "ZRANK key member", "Rank of a sorted set member, counting from 0 at the lowest score").key().read(),
+    spec("ZRANGE", "ZRANGE key start stop [WITHSCORES]", "Members by rank, lowest score first (negative ranks count from the end)").key().read(),
+    spec("ZCARD", "ZCARD key", "Number of members in a sorted set").key().read(),
+    spec("ZRANGEBYSCORE", "ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]", "Members scored between min and max, lowest first; '(' makes a bound exclusive").key().read(),
+    spec("ZREMRANGEBYSCORE", "ZREMRANGEBYSCORE key min max", "Remove members scored between min and max; replies how many were removed").key().write(),
+    spec("ZPOPMIN", "ZPOPMIN key [count]", "Remove and return the members with the lowest scores, with their scores").key().write(),
+    spec("ZPOPMAX", "ZPOPMAX key [count]", "Remove and return the members with the highest scores, with their scores").key().write(),
+    spec("BZPOPMIN", "BZPOPMIN key seconds", "ZPOPMIN that blocks until the sorted set has a member; 0 waits forever").key().blocking(),
+    spec("BZPOPMAX", "BZPOPMAX key seconds", "ZPOPMAX that blocks until the sorted set has a member; 0 waits forever").key().blocking(),
+    spec("ZINCRBY", "ZINCRBY key increment member", "Add to a member's score, adding the member if missing; replies the new score").key().write(),
+    spec("XADD", "XADD key [MAXLEN|MINID [=|~] threshold] id|* field value [field value ...]", "Append an entry to a stream; * generates a millisecond-sequence ID").key().write(),
+    spec("XLEN", "XLEN key", "Number of entries in a stream").key().read(),
+    spec("XRANGE", "XRANGE key start end [COUNT count]", "Entries with IDs from start to end; - and + are the first and last").key().read(),
+    spec("XREAD", "XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]", "Entries after the given IDs ($ for new ones only), optionally waiting for some").blocking(),
+    spec("XTRIM", "XTRIM key MAXLEN|MINID [=|~] threshold", "Remove a stream's oldest entries, keeping at most MAXLEN or none below MINID").key().write(),
+    spec("XGROUP CREATE", "XGROUP CREATE key group id|$ [MKSTREAM]", "Add a consumer group that delivers entries after the ID ($ for new ones only)").keys(2, 2, 1).write(),
+    spec("XGROUP DESTROY", "XGROUP DESTROY key group", "Remove a consumer group and its pending entries").keys(2, 2, 1).write(),
+    spec("XREADGROUP", "XREADGROUP GROUP group consumer [COUNT count] STREAMS key [key ...] id|> [id|> ...]", "Read as a group's consumer: > for undelivered entries, which become pending, or an ID to reread its own").write(),
+    spec("XACK", "XACK key group id [id ...]", "Acknowledge pending entries; replies how many were pending").key().write(),
+    spec("XPENDING", "XPENDING key group [[IDLE min-idle-ms] start end count [consumer]]", "Pending entries per consumer, or each pending entry's consumer, idle time and deliveries").key().read(),
+    spec("XAUTOCLAIM", "XAUTOCLAIM key group consumer min-idle-ms start [COUNT count]", "Claim pending entries idle at least min-idle-ms for a consumer; replies the cursor to continue from").key().write(),
+    spec("ZADDDELAY", "ZADDDELAY queue timestamp_ms payload", "Push payload onto list queue once the unix ms timestamp passes").key().write(),
+    spec("DELAYED", "DELAYED queue", "Items still waiting to be delivered to a list").key().read(),
+    spec("TS.CREATE", "TS.CREATE key [RETENTION ms]", "Create a time series").key().write(),
+    spec("TS.ADD", "TS.ADD key timestamp|* value", "Append a sample to a time series").key().write(),
+    spec("TS.GET", "TS.GET key", "Latest sample of a time series").key().read(),
+    spec("TS.RANGE", "TS.RANGE key from to [AGGREGATION avg|min|max bucket_ms]", "Samples in a time range, optionally downsampled").key().read(),
+    spec("VADD", "VADD key f1 f2 ...", "Store an embedding").key().write(),
+    spec("VGET", "VGET key", "Read an embedding back").key().read(),
+    spec("VSEARCH", "VSEARCH pattern k COSINE|L2 f1 f2 ...", "k nearest vectors among keys matching a pattern").read(),
+    spec("INDEX CREATE", "INDEX CREATE name pattern field", "Index a hash field for keys matching a pattern").write(),
+    spec("INDEX DROP", "INDEX DROP name", "Remove a secondary index").write(),
+    spec("INDEX LIST", "INDEX LIST", "List secondary indexes").admin(),
+    spec("FIND", "FIND index value", "Keys whose indexed field equals a value").read(),
+    spec("FT.CREATE", "FT.CREATE name pattern [FIELDS field ...]", "Create a full-text index over matching keys").write(),
+    spec("FT.ADD", "FT.ADD name key", "Add a key to a full-text index").keys(2, 2, 1).write(),
+    spec("FT.SEARCH", "FT.SEARCH name query", "Search a full-text index").read(),
+    spec("FT.DROP", "FT.DROP name", "Remove a full-text index").write(),
+    spec("TENANT SET", "TENANT SET name [KEYS n] [MEMORY bytes] [OPS n]", "Define or update a tenant owning keys 'name:*'").admin(),
+    spec("TENANT DEL", "TENANT DEL name", "Remove a tenant (its keys are kept)").admin(),
+    spec("TENANT LIST", "TENANT LIST", "List tenants").admin(),
+    spec("TENANT INFO", "TENANT INFO name", "A tenant's usage, op counters and limits").admin(),
+    spec("INFO", "INFO", "Server statistics").admin(),
+    spec("PING", "PING", "Server health check").read(),
+    spec("SUBSCRIBE", "SUBSCRIBE channel [channel ...]", "Receive messages published to these channels; only SUBSCRIBE, UNSUBSCRIBE, PING and QUIT work meanwhile").admin(),
+    spec("UNSUBSCRIBE", "UNSUBSCRIBE [channel ...]", "Stop receiving from these channels, or from all of them").admin(),
+    spec("PUBLISH", "PUBLISH channel message", "Send a message to a channel's subscribers; replies how many received it").admin(),
+    spec("MULTI", "MULTI", "Start a transaction; commands are queued until EXEC").admin(),
+    spec("EXEC", "EXEC", "Run the queued commands together, replying with each command's reply or error").write(),
+    spec("DISCARD", "DISCARD", "Drop the queued commands and end the transaction").admin(),
+    spec("HELP", "HELP [command]", "List commands, or show usage of one command").read(),
+    spec("MEMORY ANALYZE", "MEMORY ANALYZE [separator] [SAMPLES n]", "Key counts and memory grouped by prefix").admin(),
+    spec("DBSTATS", "DBSTATS [SAMPLES n]", "TTL, value size and type distribution over a sample of keys").admin(),
+    spec("STATS", "STATS [PREFIXES [ON [separator]|OFF]|RESET]", "Keyspace hits and misses of GET, HGET and EXISTS, optionally per prefix").admin(),
+    spec("SLOWLOG GET", "SLOWLOG GET [count]", "Most recent slow commands").admin(),
+    spec("SLOWLOG LEN", "SLOWLOG LEN", "Number of slow log entries").admin(),
+    spec("SLOWLOG RESET", "SLOWLOG RESET", "Clear the slow log").admin(),
+    spec("HISTORY", "HISTORY key", "Recent changes to a key: when, which command and which client").key().read(),
+    spec("CLIENT NOTICES", "CLIENT NOTICES ON|OFF", "Opt in to NOTICE lines such as 'server is closing'").admin(),
+    spec("CLIENT LIST", "CLIENT LIST", "Connected clients with command counts").admin(),
+    spec("CLIENT ID", "CLIENT ID", "This connection's id").admin(),
+    spec("CLIENT FRAMING", "CLIENT FRAMING ON|OFF", "Prefix every reply with 'FRAME <bytes>' so multi-line replies can be read exactly").admin(),
+    spec(
+        "CLIENT KILL",
+        "CLIENT KILL [ID id] [ADDR ip:port] [LADDR ip:port] [USER name] [TYPE normal|replica|master|pubsub] [IDLE secs] [MAXAGE secs]",
+        "Disconnect the clients matching every filter, interrupting blocking commands",
+    ).admin(),
+    spec("CLIENT UNBLOCK", "CLIENT UNBLOCK id [TIMEOUT|ERROR]", "End a client's blocking command as a timeout or an error").admin(),
+    spec("CLIENT NO-EVICT", "CLIENT NO-EVICT ON|OFF", "Exempt this connection from being disconnected to reclaim memory").admin(),
+    spec("CLIENT NO-TOUCH", "CLIENT NO-TOUCH ON|OFF", "Keep this connection's commands from updating key access times").admin(),
+    spec("CLIENT EPHEMERAL", "CLIENT EPHEMERAL ON|OFF", "Delete the keys this connection creates when it closes").admin(),
+    spec("ALIAS SET", "ALIAS SET name command [args ...]", "Define a command alias with preset arguments").admin(),
+    spec("ALIAS DEL", "ALIAS DEL name", "Remove a command alias").admin(),
+    spec("ALIAS LIST", "ALIAS LIST", "List command aliases").admin(),
+    spec("ALARMS LIST", "ALARMS LIST", "Active alarms (also plain ALARMS)").admin(),
+    spec("ALARMS THRESHOLDS", "ALARMS THRESHOLDS", "Configured alarm thresholds").admin(),
+    spec("ALARMS SET", "ALARMS SET memory|keys|clients value|OFF", "Set or remove an alarm threshold").admin(),
+    spec("DRAIN", "DRAIN [seconds]", "Stop accepting clients and close remaining ones after a grace period").admin(),
+    spec("HANDOFF", "HANDOFF [seconds]", "Exec a new Medusa that adopts the listener and a dataset snapshot").admin(),
+    spec("SAVE", "SAVE", "Write a snapshot to MEDUSA_SAVE_FILE, loaded at the next start").admin(),
+    spec("BGSAVE", "BGSAVE", "SAVE on a background thread; progress and outcome are in INFO").admin(),
+    spec("SYNC", "SYNC", "Turn the connection into a replication stream; sent by replicas").admin(),
+    spec("CRDTSYNC", "CRDTSYNC", "Turn the connection into a stream of changes to merge; sent by multi-primary peers").admin(),
+    spec("REPLICAOF", "REPLICAOF host port|NO ONE", "Replicate from another server, or stop replicating and become a primary").admin(),
+    spec("SENTINEL IS-DOWN", "SENTINEL IS-DOWN host:port", "Whether this node has found that primary unreachable").admin(),
+    spec("SENTINEL PRIMARY", "SENTINEL PRIMARY", "The primary this node follows, or itself if it is the primary").admin(),
+    spec("SENTINEL GOSSIP", "SENTINEL GOSSIP addr/epoch/heartbeat...", "Merge a peer's member list and reply with this node's").admin(),
+    spec("BACKUP FULL", "BACKUP FULL path", "Write a full backup into MEDUSA_BACKUP_DIR and start tracking changes").admin(),
+    spec("BACKUP INCREMENTAL", "BACKUP INCREMENTAL path", "Write only the keys changed since the previous backup").admin(),
+    spec("BACKUP RESTORE", "BACKUP RESTORE full [incremental ...]", "Replace the dataset with a full backup plus its incrementals").write(),
+    spec("IMPORT RDB", "IMPORT RDB path", "Load keys from a Redis RDB dump in MEDUSA_IMPORT_DIR").write(),
+    spec("IMPORT SNAPSHOT", "IMPORT SNAPSHOT path", "Merge keys from a Medusa snapshot in MEDUSA_IMPORT_DIR").write(),
+    spec("DEBUG INJECT", "DEBUG INJECT [STATUS|LATENCY ms [pct]|DROP pct|PERSISTENCE ON|OFF|CONTENTION ms|CLEAR]", "Inject faults (needs MEDUSA_FAULT_INJECTION=true)").admin(),
+    spec("COMMAND GETKEYS", "COMMAND GETKEYS command [arg ...]", "Which arguments of a command are keys").admin(),
+    spec("COMMAND INFO", "COMMAND INFO command [subcommand]", "Whether a command reads, writes, blocks or administers, and where its keys are").admin(),
+    spec("CONFIG HELP", "CONFIG HELP [setting]", "Every MEDUSA_* setting with its type, default and whether it can change at runtime").admin(),
+    spec("QUIT", "QUIT", "Disconnect (alias EXIT)").admin(),
+    spec("EXIT", "EXIT", "Disconnect (alias QUIT)").admin(),
+];
+
+/// The entries documenting `name`: the command itself, or all of its
+/// subcommands ("CLIENT" finds "CLIENT LIST", "CLIENT ID", ...).
+pub fn lookup(name: &str) -> Vec<&'static CommandSpec> {
+    let name = name.to_uppercase();
+    COMMANDS.iter()
+        .filter(|spec| {
+            spec.name == name
+                || spec.name.strip_prefix(name.as_str()).is_some_and(|rest| rest.starts_with(' '))
+        })
+        .collect()
+}
+
+/// The spec that `args` (a full command line) runs: the subcommand entry
+/// if there is one, otherwise the command's own.
+pub fn find(args: &[&str]) -> Option<&'static CommandSpec> {
+    let name = args.first()?.to_uppercase();
+    let subcommand = args.get(1).map(|sub| format!("{} {}", name, sub.to_uppercase()));
+    COMMANDS.iter()
+        .find(|spec| Some(spec.name) == subcommand.as_deref())
+        .or_else(|| COMMANDS.iter().find(|spec| spec.name == name))
+}
+
+/// What a full command line does, or `None` for an unknown command.
+pub fn kind(args: &[&str]) -> Option<CommandKind> {
+    find(args).map(|spec| spec.kind)
+}
+
+/// The key arguments of a full command line, as used by `COMMAND GETKEYS`
+/// and the tenant quota checks.
+pub fn get_keys<'a>(args: &[&'a str]) -> Result<Vec<&'a str>, String> {
+    let spec = match find(args) {
+        Some(spec) => spec,
+        None => return Err(format!("Unknown command '{}'", args.first().unwrap_or(&""))),
+    };
+    if spec.first_key == 0 {
+        return Ok(Vec::new());
+    }
+
+    let last_key = if spec.last_key < 0 {
+        args.len() as isize + spec.last_key
+    } else {
+        spec.last_key
+    };
+    if last_key < spec.first_key as isize || last_key as usize >= args.len() {
+        return Err(format!("Invalid number of arguments for '{}'", spec.name));
+    }
+    Ok((spec.first_key..=last_key as usize)
+        .step_by(spec.key_step.max(1))
+        .map(|position| args[position])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("get")[0].syntax, "GET key");
+        assert_eq!(lookup("client").len(), 9);
+        assert_eq!(lookup("client list").len(), 1);
+        assert!(lookup("CLIEN").is_empty());
+        assert!(lookup("NOPE").is_empty());
+    }
+
+    #[test]
+    fn test_get_keys() {
+        assert_eq!(get_keys(&["SET", "foo", "bar"]).unwrap(), vec!["foo"]);
+        assert_eq!(get_keys(&["hget", "user:1", "name"]).unwrap(), vec!["user:1"]);
+        assert_eq!(get_keys(&["FT.ADD", "docs", "doc:1"]).unwrap(), vec!["doc:1"]);
+        assert!(get_keys(&["KEYS", "user:*"]).unwrap().is_empty());
+        assert!(get_keys(&["CLIENT", "LIST"]).unwrap().is_empty());
+        assert!(get_keys(&["GET"]).is_err());
+        assert!(get_keys(&["NOPE", "foo"]).is_err());
+    }
+
+    #[test]
+    fn test_kinds() {
+        assert_eq!(kind(&["get", "k"]), Some(CommandKind::Read));
+        assert_eq!(kind(&["SET", "k", "v"]), Some(CommandKind::Write));
+        assert_eq!(kind(&["WAITKEY", "k", "0"]), Some(CommandKind::Blocking));
+        assert_eq!(kind(&["CLIENT", "LIST"]), Some(CommandKind::Admin));
+        assert_eq!(kind(&["OBJECT", "IDLETIME", "k"]), Some(CommandKind::Read));
+        assert_eq!(kind(&["NOPE"]), None);
+
+        // Commands that take keys touch the dataset, so none is admin
+        for spec in COMMANDS.iter().filter(|spec| spec.first_key > 0) {
+            assert_ne!(spec.kind, CommandKind::Admin, "{} takes keys but is admin", spec.name);
+        }
+    }
+
+    #[test]
+    fn test_names_are_unique() {
+        let mut names = HashSet::new();
+        for spec in COMMANDS {
+            assert!(names.insert(spec.name), "duplicate entry {}", spec.name);
+            assert!(spec.syntax.starts_with(spec.name), "{} syntax does not start with its name", spec.name);
+        }
+    }
+}
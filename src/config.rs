@@ -1,6 +1,24 @@
+use crate::alarm;
+use crate::alias;
+use crate::eviction::{self, EvictionPolicy};
+use crate::fairness;
+use crate::latency;
+use crate::net::{self, TcpTuning};
+use crate::proxy::{self, ProxyConfig};
+use crate::schedule::{self, CronSchedule, SaveRule, SnapshotSchedule};
+use crate::sentinel::{self, SentinelConfig};
+use crate::crdt::{self, CrdtConfig};
+use crate::snapshot::CorruptionPolicy;
+use crate::telemetry::escape_json;
+use crate::tenant::{self, TenantQuota};
+use std::path::PathBuf;
 use std::env;
 use std::time::Duration;
 
+const DEFAULT_SNAPSHOT_DIR: &str = "snapshots";
+const DEFAULT_SNAPSHOT_KEEP: usize = 7;
+const DEFAULT_PROXY_POOL: usize = 8;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub host: String,
@@ -10,6 +28,60 @@ pub struct Config {
     pub enable_timeouts: bool,
     pub log_level: String,
     pub enable_metrics: bool,
+    pub enable_tracing: bool,
+    pub otlp_endpoint: String,
+    pub tenants: Vec<(String, TenantQuota)>,
+    pub import_rdb: Option<String>,
+    pub enable_fault_injection: bool,
+    pub http_port: Option<u16>,
+    pub resp_port: Option<u16>,
+    pub slowlog_threshold: Duration,
+    /// Upper bounds in microseconds of the `/metrics` latency buckets.
+    pub latency_buckets: Vec<u64>,
+    /// Estimated memory limit in bytes; 0 means unlimited.
+    pub max_memory: usize,
+    pub eviction_policy: EvictionPolicy,
+    /// Keys sampled per eviction round, like Redis's `maxmemory-samples`.
+    pub eviction_samples: usize,
+    /// Changes remembered per key for `HISTORY`; 0 disables it.
+    pub key_history: usize,
+    pub snapshot_schedule: Option<SnapshotSchedule>,
+    pub alarm_thresholds: Vec<(&'static str, u64)>,
+    pub aliases: Vec<(String, String)>,
+    pub chunk_threshold: usize,
+    /// Live keys the store may hold; 0 means unlimited.
+    pub max_keys: usize,
+    /// Keys and `prefix*` patterns maxmemory never evicts.
+    pub pinned_keys: Vec<String>,
+    /// Pass the dataset to a `HANDOFF` successor in a memfd (experimental).
+    pub handoff_memfd: bool,
+    pub prefix_stats: Option<String>,
+    pub proxy: Option<ProxyConfig>,
+    /// Token `FLUSHALL CONFIRM` must quote; "random" generates one at startup.
+    pub flush_token: Option<String>,
+    /// Directory of fixture files loaded at startup (`--seed`).
+    pub seed_dir: Option<PathBuf>,
+    /// Snapshot written by `SAVE` and loaded at startup.
+    pub save_file: Option<PathBuf>,
+    /// Directory `BACKUP` writes and restores files in; unset disables it.
+    pub backup_dir: Option<PathBuf>,
+    /// Directory `IMPORT` reads files from; unset disables it.
+    pub import_dir: Option<PathBuf>,
+    pub save_rules: Vec<SaveRule>,
+    pub corrupt_snapshot: CorruptionPolicy,
+    pub replica_of: Option<String>,
+    pub sentinel: Option<SentinelConfig>,
+    pub crdt: Option<CrdtConfig>,
+    pub tcp: TcpTuning,
+    /// Commands a pipelining client runs before other clients get a turn.
+    pub fair_quantum: usize,
+    /// Threads connections are multiplexed over; 0 means one per CPU core.
+    pub worker_threads: usize,
+    /// Fork into the background at startup (`--daemonize`).
+    pub daemonize: bool,
+    pub pid_file: Option<PathBuf>,
+    /// Where a daemon's output goes; discarded if unset.
+    pub log_file: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -22,6 +94,44 @@ impl Default for Config {
             enable_timeouts: false,
             log_level: "info".to_string(),
             enable_metrics: false,
+            enable_tracing: false,
+            otlp_endpoint: "http://127.0.0.1:4318".to_string(),
+            tenants: Vec::new(),
+            import_rdb: None,
+            enable_fault_injection: false,
+            http_port: None,
+            resp_port: None,
+            slowlog_threshold: Duration::from_millis(10),
+            latency_buckets: latency::DEFAULT_BUCKETS_MICROS.to_vec(),
+            max_memory: 0,
+            eviction_policy: EvictionPolicy::NoEviction,
+            eviction_samples: eviction::DEFAULT_SAMPLES,
+            key_history: 0,
+            snapshot_schedule: None,
+            alarm_thresholds: Vec::new(),
+            aliases: Vec::new(),
+            chunk_threshold: 0,
+            max_keys: 0,
+            pinned_keys: Vec::new(),
+            handoff_memfd: false,
+            prefix_stats: None,
+            proxy: None,
+            flush_token: None,
+            seed_dir: None,
+            save_file: None,
+            backup_dir: None,
+            import_dir: None,
+            save_rules: Vec::new(),
+            corrupt_snapshot: CorruptionPolicy::Refuse,
+            replica_of: None,
+            sentinel: None,
+            crdt: None,
+            tcp: TcpTuning::default(),
+            fair_quantum: fairness::DEFAULT_QUANTUM,
+            worker_threads: 0,
+            daemonize: false,
+            pid_file: None,
+            log_file: None,
         }
     }
 }
@@ -61,10 +171,274 @@ impl Config {
             config.log_level = log_level;
         }
 
+        if let Ok(path) = env::var("MEDUSA_LOG_FILE") {
+            config.log_file = Some(PathBuf::from(path));
+        }
+
+        if let Ok(daemonize) = env::var("MEDUSA_DAEMONIZE") {
+            config.daemonize = daemonize.to_lowercase() == "true";
+        }
+
+        if let Ok(path) = env::var("MEDUSA_PID_FILE") {
+            config.pid_file = Some(PathBuf::from(path));
+        }
+
         if let Ok(metrics) = env::var("MEDUSA_METRICS") {
             config.enable_metrics = metrics.to_lowercase() == "true";
         }
 
+        if let Ok(nodelay) = env::var("MEDUSA_TCP_NODELAY") {
+            config.tcp.nodelay = nodelay.to_lowercase() != "false";
+        }
+
+        if let Ok(keepalive) = env::var("MEDUSA_TCP_KEEPALIVE") {
+            match keepalive.parse::<u64>() {
+                Ok(0) => config.tcp.keepalive = None,
+                Ok(secs) => config.tcp.keepalive = Some(Duration::from_secs(secs)),
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_TCP_KEEPALIVE '{}'", keepalive),
+            }
+        }
+
+        if let Ok(backlog) = env::var("MEDUSA_TCP_BACKLOG") {
+            match backlog.parse::<i32>() {
+                Ok(size) if size > 0 => config.tcp.backlog = size,
+                _ => eprintln!("Warning: Ignoring invalid MEDUSA_TCP_BACKLOG '{}'", backlog),
+            }
+        }
+
+        for (name, buffer) in [("MEDUSA_TCP_RCVBUF", &mut config.tcp.recv_buffer), ("MEDUSA_TCP_SNDBUF", &mut config.tcp.send_buffer)] {
+            if let Ok(size) = env::var(name) {
+                match size.parse::<usize>() {
+                    Ok(bytes) if bytes > 0 => *buffer = Some(bytes),
+                    _ => eprintln!("Warning: Ignoring invalid {} '{}'", name, size),
+                }
+            }
+        }
+
+        if let Ok(quantum) = env::var("MEDUSA_FAIR_QUANTUM") {
+            match quantum.parse::<usize>() {
+                Ok(commands) => config.fair_quantum = commands,
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_FAIR_QUANTUM '{}'", quantum),
+            }
+        }
+
+        if let Ok(threads) = env::var("MEDUSA_WORKER_THREADS") {
+            match threads.parse::<usize>() {
+                Ok(count) => config.worker_threads = count,
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_WORKER_THREADS '{}'", threads),
+            }
+        }
+
+        if let Ok(tracing) = env::var("MEDUSA_TRACING") {
+            config.enable_tracing = tracing.to_lowercase() == "true";
+        }
+
+        if let Ok(endpoint) = env::var("MEDUSA_OTLP_ENDPOINT") {
+            config.otlp_endpoint = endpoint;
+        }
+
+        if let Ok(tenants) = env::var("MEDUSA_TENANTS") {
+            match tenant::parse_tenants(&tenants) {
+                Ok(tenants) => config.tenants = tenants,
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_TENANTS: {}", e),
+            }
+        }
+
+        if let Ok(path) = env::var("MEDUSA_IMPORT_RDB") {
+            config.import_rdb = Some(path);
+        }
+
+        if let Ok(dir) = env::var("MEDUSA_IMPORT_DIR") {
+            config.import_dir = Some(PathBuf::from(dir));
+        }
+
+        if let Ok(dir) = env::var("MEDUSA_SEED_DIR") {
+            config.seed_dir = Some(PathBuf::from(dir));
+        }
+
+        if let Ok(path) = env::var("MEDUSA_SAVE_FILE") {
+            config.save_file = Some(PathBuf::from(path));
+        }
+
+        if let Ok(dir) = env::var("MEDUSA_BACKUP_DIR") {
+            config.backup_dir = Some(PathBuf::from(dir));
+        }
+
+        if let Ok(spec) = env::var("MEDUSA_SAVE") {
+            match schedule::parse_save_rules(&spec) {
+                Ok(rules) => config.save_rules = rules,
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_SAVE: {}", e),
+            }
+        }
+
+        if let Ok(policy) = env::var("MEDUSA_CORRUPT_SNAPSHOT") {
+            match CorruptionPolicy::parse(&policy) {
+                Ok(policy) => config.corrupt_snapshot = policy,
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_CORRUPT_SNAPSHOT: {}", e),
+            }
+        }
+
+        if let Ok(primary) = env::var("MEDUSA_REPLICAOF") {
+            config.replica_of = Some(primary).filter(|primary| !primary.is_empty());
+        }
+
+        if let Ok(peers) = env::var("MEDUSA_SENTINEL_PEERS") {
+            let down_after = match env::var("MEDUSA_SENTINEL_DOWN_AFTER_MS").map(|ms| ms.parse::<u64>()) {
+                Ok(Ok(ms)) if ms > 0 => Duration::from_millis(ms),
+                Ok(_) => {
+                    eprintln!("Warning: Ignoring invalid MEDUSA_SENTINEL_DOWN_AFTER_MS");
+                    sentinel::DEFAULT_DOWN_AFTER
+                }
+                Err(_) => sentinel::DEFAULT_DOWN_AFTER,
+            };
+            config.sentinel = Some(SentinelConfig {
+                announce: env::var("MEDUSA_SENTINEL_ANNOUNCE")
+                    .unwrap_or_else(|_| net::format_address(net::split_hosts(&config.host)[0], config.port)),
+                peers: proxy::parse_backends(&peers),
+                quorum: env::var("MEDUSA_SENTINEL_QUORUM").ok().and_then(|quorum| quorum.parse().ok()).unwrap_or(0),
+                down_after,
+            });
+        }
+
+        if let Ok(peers) = env::var("MEDUSA_CRDT_PEERS") {
+            let interval = match env::var("MEDUSA_CRDT_SYNC_MS").map(|ms| ms.parse::<u64>()) {
+                Ok(Ok(ms)) if ms > 0 => Duration::from_millis(ms),
+                Ok(_) => {
+                    eprintln!("Warning: Ignoring invalid MEDUSA_CRDT_SYNC_MS");
+                    crdt::DEFAULT_SYNC_INTERVAL
+                }
+                Err(_) => crdt::DEFAULT_SYNC_INTERVAL,
+            };
+            config.crdt = Some(CrdtConfig { peers: proxy::parse_backends(&peers), interval });
+        }
+
+        if let Ok(faults) = env::var("MEDUSA_FAULT_INJECTION") {
+            config.enable_fault_injection = faults.to_lowercase() == "true";
+        }
+
+        if let Ok(port) = env::var("MEDUSA_HTTP_PORT") {
+            match port.parse::<u16>() {
+                Ok(port_num) => config.http_port = Some(port_num),
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_HTTP_PORT '{}'", port),
+            }
+        }
+
+        if let Ok(port) = env::var("MEDUSA_RESP_PORT") {
+            match port.parse::<u16>() {
+                Ok(port_num) => config.resp_port = Some(port_num),
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_RESP_PORT '{}'", port),
+            }
+        }
+
+        if let Ok(micros) = env::var("MEDUSA_SLOWLOG_MICROS") {
+            if let Ok(micros_num) = micros.parse::<u64>() {
+                config.slowlog_threshold = Duration::from_micros(micros_num);
+            }
+        }
+
+        if let Ok(buckets) = env::var("MEDUSA_LATENCY_BUCKETS") {
+            match latency::parse_buckets(&buckets) {
+                Ok(buckets) => config.latency_buckets = buckets,
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_LATENCY_BUCKETS: {}", e),
+            }
+        }
+
+        if let Ok(bytes) = env::var("MEDUSA_MAXMEMORY") {
+            match bytes.parse::<usize>() {
+                Ok(bytes) => config.max_memory = bytes,
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_MAXMEMORY '{}'", bytes),
+            }
+        }
+
+        if let Ok(policy) = env::var("MEDUSA_MAXMEMORY_POLICY") {
+            match EvictionPolicy::parse(&policy) {
+                Ok(policy) => config.eviction_policy = policy,
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_MAXMEMORY_POLICY: {}", e),
+            }
+        }
+
+        if let Ok(samples) = env::var("MEDUSA_MAXMEMORY_SAMPLES") {
+            match samples.parse::<usize>() {
+                Ok(samples) if samples > 0 => config.eviction_samples = samples,
+                _ => eprintln!("Warning: Ignoring invalid MEDUSA_MAXMEMORY_SAMPLES '{}'", samples),
+            }
+        }
+
+        if let Ok(depth) = env::var("MEDUSA_KEY_HISTORY") {
+            match depth.parse::<usize>() {
+                Ok(depth) => config.key_history = depth,
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_KEY_HISTORY '{}'", depth),
+            }
+        }
+
+        if let Ok(cron) = env::var("MEDUSA_SNAPSHOT_CRON") {
+            match CronSchedule::parse(&cron) {
+                Ok(cron) => {
+                    let directory = env::var("MEDUSA_SNAPSHOT_DIR").unwrap_or_else(|_| DEFAULT_SNAPSHOT_DIR.to_string());
+                    let keep = env::var("MEDUSA_SNAPSHOT_KEEP").ok().and_then(|keep| keep.parse().ok()).unwrap_or(DEFAULT_SNAPSHOT_KEEP);
+                    config.snapshot_schedule = Some(SnapshotSchedule { cron, directory: PathBuf::from(directory), keep });
+                }
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_SNAPSHOT_CRON: {}", e),
+            }
+        }
+
+        if let Ok(alarms) = env::var("MEDUSA_ALARMS") {
+            match alarm::parse_thresholds(&alarms) {
+                Ok(thresholds) => config.alarm_thresholds = thresholds,
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_ALARMS: {}", e),
+            }
+        }
+
+        if let Ok(aliases) = env::var("MEDUSA_ALIASES") {
+            match alias::parse_aliases(&aliases) {
+                Ok(aliases) => config.aliases = aliases,
+                Err(e) => eprintln!("Warning: Ignoring MEDUSA_ALIASES: {}", e),
+            }
+        }
+
+        if let Ok(separator) = env::var("MEDUSA_PREFIX_STATS") {
+            if !separator.is_empty() {
+                config.prefix_stats = Some(separator);
+            }
+        }
+
+        if let Ok(primary) = env::var("MEDUSA_PROXY_PRIMARY") {
+            config.proxy = Some(ProxyConfig {
+                listen: net::format_address(net::split_hosts(&config.host)[0], config.port),
+                primary,
+                replicas: env::var("MEDUSA_PROXY_REPLICAS").map(|spec| proxy::parse_backends(&spec)).unwrap_or_default(),
+                pool_size: env::var("MEDUSA_PROXY_POOL").ok().and_then(|size| size.parse().ok()).unwrap_or(DEFAULT_PROXY_POOL),
+            });
+        }
+
+        if let Ok(token) = env::var("MEDUSA_FLUSH_TOKEN") {
+            if !token.is_empty() {
+                config.flush_token = Some(token);
+            }
+        }
+
+        if let Ok(threshold) = env::var("MEDUSA_CHUNK_THRESHOLD") {
+            match threshold.parse::<usize>() {
+                Ok(bytes) => config.chunk_threshold = bytes,
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_CHUNK_THRESHOLD '{}'", threshold),
+            }
+        }
+
+        if let Ok(memfd) = env::var("MEDUSA_HANDOFF_MEMFD") {
+            config.handoff_memfd = memfd.to_lowercase() == "true";
+        }
+
+        if let Ok(keys) = env::var("MEDUSA_MAX_KEYS") {
+            match keys.parse::<usize>() {
+                Ok(keys) => config.max_keys = keys,
+                Err(_) => eprintln!("Warning: Ignoring invalid MEDUSA_MAX_KEYS '{}'", keys),
+            }
+        }
+
+        if let Ok(pinned) = env::var("MEDUSA_PINNED_KEYS") {
+            config.pinned_keys = pinned.split(',').map(str::trim).filter(|pattern| !pattern.is_empty()).map(String::from).collect();
+        }
+
         config
     }
 
@@ -84,12 +458,390 @@ impl Config {
         if self.enable_timeouts {
             println!("    Timeout Duration: {:?}", self.connection_timeout);
         }
+        if self.tcp != TcpTuning::default() {
+            println!(" TCP: {}", self.tcp.describe());
+        }
+        match self.fair_quantum {
+            0 => println!(" Fair Scheduling: Disabled"),
+            quantum => println!(" Fair Scheduling: {} commands per turn", quantum),
+        }
+        if self.worker_threads > 0 {
+            println!(" Worker Threads: {}", self.worker_threads);
+        }
         println!(" Log Level: {}", self.log_level);
+        if self.daemonize {
+            match &self.log_file {
+                Some(path) => println!(" Daemon: logging to {}", path.display()),
+                None => println!(" Daemon: output discarded (set MEDUSA_LOG_FILE to keep it)"),
+            }
+        }
+        if let Some(path) = &self.pid_file {
+            println!(" PID File: {}", path.display());
+        }
         println!(" Metrics: {}", self.enable_metrics);
+        if self.enable_tracing {
+            println!(" Tracing: OTLP -> {}", self.otlp_endpoint);
+        } else {
+            println!(" Tracing: Disabled");
+        }
+        if let Some(port) = self.http_port {
+            let urls: Vec<String> =
+                net::split_hosts(&self.host).iter().map(|host| format!("http://{}/", net::format_address(host, port))).collect();
+            println!(" Dashboard: {}", urls.join(", "));
+        }
+        if let Some(port) = self.resp_port {
+            println!(" Redis Protocol: {}", net::format_addresses(&self.host, port));
+        }
+        println!(" Slow Log Threshold: {:?}", self.slowlog_threshold);
+        if self.latency_buckets != latency::DEFAULT_BUCKETS_MICROS {
+            let buckets: Vec<String> = self.latency_buckets.iter().map(u64::to_string).collect();
+            println!(" Latency Buckets: {} us", buckets.join(", "));
+        }
+        if self.max_memory > 0 {
+            println!(
+                " Max Memory: {} bytes ({}, {} samples)",
+                self.max_memory,
+                self.eviction_policy.name(),
+                self.eviction_samples
+            );
+        }
+        if self.max_keys > 0 {
+            println!(" Max Keys: {} (writes creating more keys are refused)", self.max_keys);
+        }
+        if !self.pinned_keys.is_empty() {
+            println!(" Pinned Keys: {}", self.pinned_keys.join(", "));
+        }
+        if self.key_history > 0 {
+            println!(" Key History: last {} changes per key", self.key_history);
+        }
+        if let Some(proxy) = &self.proxy {
+            println!(" Proxy Mode: primary {} replicas [{}] pool {}", proxy.primary, proxy.replicas.join(", "), proxy.pool_size);
+        }
+        if let Some(separator) = &self.prefix_stats {
+            println!(" Prefix Stats: keys grouped by '{}'", separator);
+        }
+        if self.flush_token.is_some() {
+            println!(" Flush Protection: FLUSHALL requires CONFIRM <token>");
+        }
+        if self.chunk_threshold > 0 {
+            println!(" Chunk Threshold: {} bytes (larger values need GETCHUNK)", self.chunk_threshold);
+        }
+        if let Some(schedule) = &self.snapshot_schedule {
+            println!(
+                " Snapshots: '{}' UTC into {} (keep {})",
+                schedule.cron.expression(),
+                schedule.directory.display(),
+                schedule.keep
+            );
+        }
+        if self.handoff_memfd {
+            println!(" Handoff: dataset passed in memory (experimental)");
+        }
+        if self.enable_fault_injection {
+            println!(" Fault Injection: Enabled (DEBUG INJECT)");
+        }
+        if let Some(path) = &self.import_rdb {
+            println!(" Import RDB: {}", path);
+        }
+        if let Some(dir) = &self.import_dir {
+            println!(" Import Dir: {}", dir.display());
+        }
+        if let Some(dir) = &self.seed_dir {
+            println!(" Seed Fixtures: {}", dir.display());
+        }
+        if let Some(path) = &self.save_file {
+            println!(" Save File: {}", path.display());
+        }
+        if let Some(dir) = &self.backup_dir {
+            println!(" Backup Dir: {}", dir.display());
+        }
+        if !self.save_rules.is_empty() {
+            println!(" Autosave: {}", schedule::describe_save_rules(&self.save_rules));
+        }
+        if self.corrupt_snapshot != CorruptionPolicy::Refuse {
+            println!(" Corrupt Snapshots: {}", self.corrupt_snapshot.name());
+        }
+        if let Some(primary) = &self.replica_of {
+            println!(" Replica Of: {}", primary);
+        }
+        if let Some(sentinel) = &self.sentinel {
+            println!(
+                " Sentinel: {} with peers [{}], quorum {}, down after {:?}",
+                sentinel.announce,
+                sentinel.peers.join(", "),
+                match sentinel.quorum {
+                    0 => "majority".to_string(),
+                    quorum => quorum.to_string(),
+                },
+                sentinel.down_after
+            );
+        }
+        if let Some(crdt) = &self.crdt {
+            println!(" Multi-primary: peers [{}], sync every {:?}", crdt.peers.join(", "), crdt.interval);
+        }
+        for (metric, threshold) in &self.alarm_thresholds {
+            println!(" Alarm: {} > {}", metric, threshold);
+        }
+        for (name, command) in &self.aliases {
+            println!(" Alias {}: {}", name, command);
+        }
+        for (name, quota) in &self.tenants {
+            println!(" Tenant {}: {}", name, quota.describe());
+        }
         println!();
     }
 }
 
+/// The type of a setting's value, as it appears in the schema.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionKind {
+    String,
+    Integer,
+    /// "true" or "false".
+    Boolean,
+    Path,
+    /// One of a fixed set of names.
+    Enum(&'static [&'static str]),
+    /// Several entries in one value; the description gives the syntax.
+    List,
+}
+
+impl OptionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            OptionKind::String => "string",
+            OptionKind::Integer => "integer",
+            OptionKind::Boolean => "boolean",
+            OptionKind::Path => "path",
+            OptionKind::Enum(_) => "enum",
+            OptionKind::List => "list",
+        }
+    }
+}
+
+/// One `MEDUSA_*` setting, for `CONFIG HELP` and `--dump-config-schema`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+    /// `None` when the setting is off or unset by default.
+    pub default: Option<String>,
+    pub description: &'static str,
+    /// Command that changes the setting on a running server; `None` if it
+    /// is only read at startup.
+    pub runtime: Option<&'static str>,
+}
+
+/// Every setting `Config::from_env` reads, with defaults taken from
+/// `Config::default()` so the two cannot disagree.
+pub fn schema() -> Vec<ConfigOption> {
+    let defaults = Config::default();
+    let option = |name, kind, default: Option<String>, description| ConfigOption { name, kind, default, description, runtime: None };
+    let some = |value: &dyn ToString| Some(value.to_string());
+    const POLICIES: &[&str] = &["noeviction", "allkeys-lru", "volatile-lru"];
+
+    vec![
+        option("MEDUSA_HOST", OptionKind::List, some(&defaults.host), "Address to listen on, or a comma-separated list; \"::\" listens on IPv6 and IPv4"),
+        option("MEDUSA_PORT", OptionKind::Integer, some(&defaults.port), "Port to listen on"),
+        option("MEDUSA_MAX_CONNECTIONS", OptionKind::Integer, some(&defaults.max_connections), "Clients connected at once"),
+        option(
+            "MEDUSA_TIMEOUT",
+            OptionKind::Integer,
+            some(&defaults.connection_timeout.as_secs()),
+            "Seconds a client may stay idle when MEDUSA_ENABLE_TIMEOUTS is on",
+        ),
+        option("MEDUSA_ENABLE_TIMEOUTS", OptionKind::Boolean, some(&defaults.enable_timeouts), "Disconnect idle clients"),
+        option("MEDUSA_LOG_LEVEL", OptionKind::String, some(&defaults.log_level), "Log level"),
+        option("MEDUSA_LOG_FILE", OptionKind::Path, None, "Where a daemon's output goes (--logfile); discarded if unset"),
+        option("MEDUSA_DAEMONIZE", OptionKind::Boolean, some(&defaults.daemonize), "Run in the background (--daemonize)"),
+        option("MEDUSA_PID_FILE", OptionKind::Path, None, "PID file removed on shutdown (--pidfile); medusa.pid when daemonized"),
+        option("MEDUSA_METRICS", OptionKind::Boolean, some(&defaults.enable_metrics), "Collect metrics"),
+        option("MEDUSA_TCP_NODELAY", OptionKind::Boolean, some(&defaults.tcp.nodelay), "Disable Nagle's algorithm on client sockets"),
+        option(
+            "MEDUSA_TCP_KEEPALIVE",
+            OptionKind::Integer,
+            some(&defaults.tcp.keepalive.map_or(0, |keepalive| keepalive.as_secs())),
+            "Keepalive probe idle time and interval in seconds; 0 turns keepalive off",
+        ),
+        option("MEDUSA_TCP_BACKLOG", OptionKind::Integer, some(&defaults.tcp.backlog), "Listen backlog"),
+        option("MEDUSA_TCP_RCVBUF", OptionKind::Integer, None, "SO_RCVBUF in bytes; the system default if unset"),
+        option("MEDUSA_TCP_SNDBUF", OptionKind::Integer, None, "SO_SNDBUF in bytes; the system default if unset"),
+        option(
+            "MEDUSA_FAIR_QUANTUM",
+            OptionKind::Integer,
+            some(&defaults.fair_quantum),
+            "Commands a pipelining client runs before other clients get a turn; 0 turns scheduling off",
+        ),
+        option(
+            "MEDUSA_WORKER_THREADS",
+            OptionKind::Integer,
+            some(&defaults.worker_threads),
+            "Threads serving connections; 0 means one per CPU core",
+        ),
+        option("MEDUSA_TRACING", OptionKind::Boolean, some(&defaults.enable_tracing), "Export command traces over OTLP"),
+        option("MEDUSA_OTLP_ENDPOINT", OptionKind::String, some(&defaults.otlp_endpoint), "OTLP/HTTP collector for traces"),
+        ConfigOption {
+            runtime: Some("TENANT SET"),
+            ..option(
+                "MEDUSA_TENANTS",
+                OptionKind::List,
+                None,
+                "Tenants and their quotas: team_a:keys=100;memory=1048576;ops=50,team_b:...",
+            )
+        },
+        option("MEDUSA_IMPORT_RDB", OptionKind::Path, None, "Redis dump.rdb loaded at startup (--import-rdb)"),
+        option("MEDUSA_IMPORT_DIR", OptionKind::Path, None, "Directory IMPORT reads files from; IMPORT is off without it"),
+        option("MEDUSA_SEED_DIR", OptionKind::Path, None, "Directory of .medusa and .json fixtures loaded at startup (--seed)"),
+        option("MEDUSA_SAVE_FILE", OptionKind::Path, None, "Snapshot (.mdb) written by SAVE and loaded at startup"),
+        option("MEDUSA_BACKUP_DIR", OptionKind::Path, None, "Directory BACKUP writes and restores files in; BACKUP is off without it"),
+        option(
+            "MEDUSA_SAVE",
+            OptionKind::List,
+            None,
+            "Autosave rules as \"seconds changes\" pairs, e.g. \"900 1,300 100\"; needs MEDUSA_SAVE_FILE",
+        ),
+        option(
+            "MEDUSA_CORRUPT_SNAPSHOT",
+            OptionKind::Enum(&["refuse", "truncate"]),
+            some(&defaults.corrupt_snapshot.name()),
+            "At startup, refuse a damaged save file or snapshot, or load the records before the damage",
+        ),
+        ConfigOption {
+            runtime: Some("REPLICAOF"),
+            ..option("MEDUSA_REPLICAOF", OptionKind::String, None, "Primary (host:port) to replicate from; a replica refuses writes")
+        },
+        option(
+            "MEDUSA_SENTINEL_PEERS",
+            OptionKind::List,
+            None,
+            "Nodes of this primary and its replicas to gossip with, one is enough; turns on automatic failover",
+        ),
+        option("MEDUSA_SENTINEL_ANNOUNCE", OptionKind::String, None, "This node's host:port as its peers reach it; defaults to the first MEDUSA_HOST"),
+        option("MEDUSA_SENTINEL_QUORUM", OptionKind::Integer, some(&0), "Nodes that must find the primary down to fail over; 0 means a majority of the known group"),
+        option(
+            "MEDUSA_SENTINEL_DOWN_AFTER_MS",
+            OptionKind::Integer,
+            some(&sentinel::DEFAULT_DOWN_AFTER.as_millis()),
+            "Milliseconds the primary must be unreachable, or a member silent, before a node calls it down or failed",
+        ),
+        option(
+            "MEDUSA_CRDT_PEERS",
+            OptionKind::List,
+            None,
+            "Nodes to exchange writes with; turns on multi-primary mode, where every node accepts writes and the latest wins",
+        ),
+        option(
+            "MEDUSA_CRDT_SYNC_MS",
+            OptionKind::Integer,
+            some(&crdt::DEFAULT_SYNC_INTERVAL.as_millis()),
+            "Milliseconds between pushes of changed keys to each multi-primary peer",
+        ),
+        option(
+            "MEDUSA_FAULT_INJECTION",
+            OptionKind::Boolean,
+            some(&defaults.enable_fault_injection),
+            "Allow DEBUG INJECT",
+        ),
+        option("MEDUSA_HTTP_PORT", OptionKind::Integer, None, "Port of the HTTP dashboard; off if unset"),
+        option("MEDUSA_RESP_PORT", OptionKind::Integer, None, "Port serving the Redis protocol (RESP2); off if unset"),
+        option(
+            "MEDUSA_SLOWLOG_MICROS",
+            OptionKind::Integer,
+            some(&defaults.slowlog_threshold.as_micros()),
+            "Commands slower than this many microseconds go to the slow log",
+        ),
+        option(
+            "MEDUSA_LATENCY_BUCKETS",
+            OptionKind::List,
+            Some(defaults.latency_buckets.iter().map(u64::to_string).collect::<Vec<_>>().join(",")),
+            "Upper bounds in microseconds of the latency histogram buckets served at /metrics",
+        ),
+        option("MEDUSA_MAXMEMORY", OptionKind::Integer, some(&defaults.max_memory), "Estimated memory limit in bytes; 0 is unlimited"),
+        option(
+            "MEDUSA_MAXMEMORY_POLICY",
+            OptionKind::Enum(POLICIES),
+            some(&defaults.eviction_policy.name()),
+            "What a write over MEDUSA_MAXMEMORY evicts",
+        ),
+        option("MEDUSA_MAXMEMORY_SAMPLES", OptionKind::Integer, some(&defaults.eviction_samples), "Keys sampled per eviction round"),
+        option(
+            "MEDUSA_MAX_KEYS",
+            OptionKind::Integer,
+            some(&defaults.max_keys),
+            "Live keys the store may hold; writes creating more are refused, 0 is unlimited",
+        ),
+        ConfigOption {
+            runtime: Some("PIN"),
+            ..option("MEDUSA_PINNED_KEYS", OptionKind::List, None, "Keys and prefix* patterns never evicted: config:*,settings")
+        },
+        option("MEDUSA_KEY_HISTORY", OptionKind::Integer, some(&defaults.key_history), "Changes remembered per key for HISTORY; 0 is off"),
+        option("MEDUSA_SNAPSHOT_CRON", OptionKind::String, None, "Cron expression (UTC) for scheduled snapshots; off if unset"),
+        option(
+            "MEDUSA_SNAPSHOT_DIR",
+            OptionKind::Path,
+            some(&DEFAULT_SNAPSHOT_DIR),
+            "Directory scheduled snapshots are written to",
+        ),
+        option("MEDUSA_SNAPSHOT_KEEP", OptionKind::Integer, some(&DEFAULT_SNAPSHOT_KEEP), "Scheduled snapshots kept"),
+        ConfigOption {
+            runtime: Some("ALARMS SET"),
+            ..option("MEDUSA_ALARMS", OptionKind::List, None, "Alarm thresholds: memory=bytes;keys=n;clients=n")
+        },
+        ConfigOption {
+            runtime: Some("ALIAS SET"),
+            ..option("MEDUSA_ALIASES", OptionKind::List, None, "Command aliases: NAME=COMMAND args,NAME=...")
+        },
+        ConfigOption {
+            runtime: Some("STATS PREFIXES"),
+            ..option("MEDUSA_PREFIX_STATS", OptionKind::String, None, "Separator to count hits and misses per key prefix by")
+        },
+        option("MEDUSA_PROXY_PRIMARY", OptionKind::String, None, "host:port to proxy writes to; runs as a proxy instead of a store"),
+        option("MEDUSA_PROXY_REPLICAS", OptionKind::List, None, "Comma-separated host:port replicas for proxied reads"),
+        option("MEDUSA_PROXY_POOL", OptionKind::Integer, some(&DEFAULT_PROXY_POOL), "Idle proxy connections kept per backend"),
+        option("MEDUSA_FLUSH_TOKEN", OptionKind::String, None, "Token FLUSHALL CONFIRM must quote; \"random\" generates one"),
+        option(
+            "MEDUSA_HANDOFF_MEMFD",
+            OptionKind::Boolean,
+            some(&defaults.handoff_memfd),
+            "Experimental, Linux only: HANDOFF passes the dataset in a memfd instead of a temporary file",
+        ),
+        option(
+            "MEDUSA_CHUNK_THRESHOLD",
+            OptionKind::Integer,
+            some(&defaults.chunk_threshold),
+            "Values larger than this many bytes need GETCHUNK; 0 is no limit",
+        ),
+    ]
+}
+
+/// The schema as a JSON array, for `--dump-config-schema`.
+pub fn schema_json() -> String {
+    let options: Vec<String> = schema()
+        .iter()
+        .map(|option| {
+            let string = |value: Option<&str>| value.map_or("null".to_string(), |value| format!("\"{}\"", escape_json(value)));
+            let values = match option.kind {
+                OptionKind::Enum(values) => {
+                    let values: Vec<String> = values.iter().map(|value| string(Some(value))).collect();
+                    format!(",\"values\":[{}]", values.join(","))
+                }
+                _ => String::new(),
+            };
+            format!(
+                "{{\"name\":{},\"type\":{}{},\"default\":{},\"runtime\":{},\"set_by\":{},\"description\":{}}}",
+                string(Some(option.name)),
+                string(Some(option.kind.name())),
+                values,
+                string(option.default.as_deref()),
+                option.runtime.is_some(),
+                string(option.runtime),
+                string(Some(option.description))
+            )
+        })
+        .collect();
+    format!("[\n  {}\n]\n", options.join(",\n  "))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,4 +863,24 @@ mod tests {
         assert!(!config.host.is_empty());
         assert!(config.port > 0);
     }
+
+    #[test]
+    fn test_schema_covers_every_setting() {
+        let source = include_str!("config.rs");
+        let (source, _) = source.split_once("pub fn display").unwrap();
+        let options = schema();
+        for read in source.split('"').filter(|word| word.starts_with("MEDUSA_")) {
+            assert!(options.iter().any(|option| option.name == read), "{} is missing from the schema", read);
+        }
+
+        let port = options.iter().find(|option| option.name == "MEDUSA_PORT").unwrap();
+        assert_eq!(port.default.as_deref(), Some("2312"));
+        assert_eq!(port.runtime, None);
+        let aliases = options.iter().find(|option| option.name == "MEDUSA_ALIASES").unwrap();
+        assert_eq!(aliases.runtime, Some("ALIAS SET"));
+
+        let json = schema_json();
+        assert!(json.contains(r#"{"name":"MEDUSA_PORT","type":"integer","default":"2312","runtime":false,"set_by":null,"#));
+        assert!(json.contains(r#""type":"enum","values":["noeviction","allkeys-lru","volatile-lru"],"default":"noeviction""#));
+    }
 }
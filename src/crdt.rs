@@ -0,0 +1,382 @@
+//! Multi-primary mode: every node accepts writes, and their data converges.
+//!
+//! Each key is a last-writer-wins register. A write is stamped with the
+//! writing node's clock, in milliseconds but never behind a stamp the node
+//! has seen, and its node id to break ties. A node takes a peer's version of
+//! a key only if its stamp is newer than the one it holds. A deletion keeps
+//! its stamp as a tombstone, so an older write arriving late cannot bring
+//! the key back. Keys present at startup carry no stamp and lose to any
+//! write; a node that lacks a key takes whatever version it is sent.
+//!
+//! Every `MEDUSA_CRDT_SYNC_MS` each node pushes the keys changed since its
+//! last push to every peer in `MEDUSA_CRDT_PEERS`. A push connection sends
+//! `CRDTSYNC`, waits for `READY`, then carries, from the pushing node only:
+//!
+//! - `MERGE bytes` and an incremental backup of the keys' states, followed
+//!   by each key's stamp as two little-endian u64s, in the same order
+//! - `PING` after a quiet round, so the receiving side can tell a dead
+//!   link from an idle one
+//!
+//! A new connection starts with every key and tombstone, so a node that was
+//! away catches up. Keys merged from one peer are passed on to the others,
+//! so peers need not all list each other.
+//!
+//! Values merge whole: concurrent writes to different fields of one hash,
+//! or pushes onto one list, keep only the later write.
+
+use crate::lifecycle::Lifecycle;
+use crate::snapshot;
+use crate::store::Store;
+use crate::telemetry;
+use crate::timeseries::now_millis;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+// A push connection silent for this long past the sync interval is dropped
+const LINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Milliseconds and the writing node's id; later stamps win.
+pub type Stamp = (u64, u64);
+
+#[derive(Clone, Debug)]
+pub struct CrdtConfig {
+    /// Nodes to push changes to, as `host:port`.
+    pub peers: Vec<String>,
+    pub interval: Duration,
+}
+
+#[derive(Debug, Default)]
+struct PeerFeed {
+    addr: String,
+    // Keys written since the last push
+    pending: HashSet<String>,
+    linked: bool,
+    pushes: u64,
+    keys_pushed: u64,
+    last_error: Option<String>,
+}
+
+struct CrdtState {
+    node: u64,
+    clock: u64,
+    interval: Duration,
+    versions: HashMap<String, Stamp>,
+    peers: Vec<PeerFeed>,
+    merged: u64,
+    stale: u64,
+}
+
+/// Per-key stamps and the keys waiting to be pushed to each peer.
+#[derive(Clone)]
+pub struct Crdt {
+    // Writes skip the lock unless multi-primary mode is on
+    enabled: Arc<AtomicBool>,
+    inner: Arc<Mutex<CrdtState>>,
+}
+
+impl Default for Crdt {
+    fn default() -> Self {
+        Crdt {
+            enabled: Arc::new(AtomicBool::new(false)),
+            inner: Arc::new(Mutex::new(CrdtState {
+                node: telemetry::random_u64(),
+                clock: 0,
+                interval: DEFAULT_SYNC_INTERVAL,
+                versions: HashMap::new(),
+                peers: Vec::new(),
+                merged: 0,
+                stale: 0,
+            })),
+        }
+    }
+}
+
+thread_local! {
+    // Set while a peer's version of a key is applied, so the write it
+    // makes is not stamped and pushed as a local one
+    static MERGING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl Crdt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Stamp a local write to `key` and queue it for every peer. Called for
+    /// each write.
+    pub fn record(&self, key: &str) {
+        if !self.is_enabled() || MERGING.with(Cell::get) {
+            return;
+        }
+        if let Ok(mut state) = self.inner.lock() {
+            state.clock = (state.clock + 1).max(now_millis());
+            let stamp = (state.clock, state.node);
+            state.versions.insert(key.to_string(), stamp);
+            for peer in &mut state.peers {
+                peer.pending.insert(key.to_string());
+            }
+        }
+    }
+
+    /// Run `apply` if `stamp` beats the version of `key` held here, which
+    /// then becomes `stamp`, and queue the key for the other peers. `exists`
+    /// says whether the key is in the store. Returns whether it ran.
+    pub fn merge<T>(&self, key: &str, stamp: Stamp, exists: bool, apply: impl FnOnce() -> T) -> Option<T> {
+        let Ok(mut state) = self.inner.lock() else { return None };
+        state.clock = state.clock.max(stamp.0);
+        let held = state.versions.get(key).copied().or(if exists { Some((0, 0)) } else { None });
+        if held.is_some_and(|held| held >= stamp) {
+            state.stale += 1;
+            return None;
+        }
+        state.versions.insert(key.to_string(), stamp);
+        for peer in &mut state.peers {
+            peer.pending.insert(key.to_string());
+        }
+        state.merged += 1;
+        drop(state);
+
+        MERGING.with(|merging| merging.set(true));
+        let result = apply();
+        MERGING.with(|merging| merging.set(false));
+        Some(result)
+    }
+
+    /// The `# Multi-primary` section of `INFO`, empty unless the mode is on.
+    pub fn info(&self) -> String {
+        if !self.is_enabled() {
+            return String::new();
+        }
+        let Ok(state) = self.inner.lock() else { return String::new() };
+        let mut info = format!(
+            "\n\n# Multi-primary\ncrdt_node:{:016x}\ncrdt_sync_interval_ms:{}\ncrdt_stamped_keys:{}\ncrdt_merged:{}\ncrdt_stale:{}",
+            state.node,
+            state.interval.as_millis(),
+            state.versions.len(),
+            state.merged,
+            state.stale
+        );
+        for (i, peer) in state.peers.iter().enumerate() {
+            info.push_str(&format!(
+                "\npeer{}:addr={},link={},pending={},pushes={},keys_pushed={},last_error={}",
+                i,
+                peer.addr,
+                if peer.linked { "up" } else { "down" },
+                peer.pending.len(),
+                peer.pushes,
+                peer.keys_pushed,
+                peer.last_error.as_deref().unwrap_or("none")
+            ));
+        }
+        info
+    }
+
+    /// The stamp of the version of `key` held here; (0, 0) if unstamped.
+    pub fn stamp(&self, key: &str) -> Stamp {
+        self.inner.lock().ok().and_then(|state| state.versions.get(key).copied()).unwrap_or((0, 0))
+    }
+
+    fn interval(&self) -> Duration {
+        self.inner.lock().map(|state| state.interval).unwrap_or(DEFAULT_SYNC_INTERVAL)
+    }
+
+    fn update_peer<T>(&self, index: usize, update: impl FnOnce(&mut PeerFeed) -> T) -> Option<T> {
+        let mut state = self.inner.lock().ok()?;
+        state.peers.get_mut(index).map(update)
+    }
+
+    // Queue every key in `keys` and every tombstone for a peer just linked
+    fn queue_everything(&self, index: usize, keys: Vec<String>) {
+        if let Ok(mut state) = self.inner.lock() {
+            let tombstones: Vec<String> = state.versions.keys().cloned().collect();
+            if let Some(peer) = state.peers.get_mut(index) {
+                peer.pending.extend(keys);
+                peer.pending.extend(tombstones);
+            }
+        }
+    }
+}
+
+/// Turn on multi-primary mode and push changes to each of `config.peers`
+/// until the server stops.
+pub fn start_crdt(store: &Store, lifecycle: &Lifecycle, config: CrdtConfig) {
+    let crdt = store.crdt();
+    if let Ok(mut state) = crdt.inner.lock() {
+        state.interval = config.interval;
+        state.peers = config.peers.iter().map(|addr| PeerFeed { addr: addr.clone(), ..Default::default() }).collect();
+    }
+    crdt.enabled.store(true, Ordering::SeqCst);
+
+    for (index, addr) in config.peers.into_iter().enumerate() {
+        let (store, lifecycle) = (store.clone(), lifecycle.clone());
+        thread::spawn(move || while !lifecycle.should_stop() {
+            let result = push_to_peer(&store, &lifecycle, index, &addr);
+            store.crdt().update_peer(index, |peer| {
+                peer.linked = false;
+                peer.last_error = result.as_ref().err().cloned();
+            });
+            if let Err(e) = result {
+                eprintln!("Warning: Pushing changes to {} stopped: {}", addr, e);
+                thread::sleep(RETRY_DELAY);
+            }
+        });
+    }
+}
+
+fn push_to_peer(store: &Store, lifecycle: &Lifecycle, index: usize, addr: &str) -> Result<(), String> {
+    let crdt = store.crdt();
+    let interval = crdt.interval();
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("Failed to connect: {}", e))?;
+    stream.set_read_timeout(Some(LINK_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(LINK_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.write_all(b"CRDTSYNC\n").map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err("Peer closed the connection".to_string()),
+            Ok(_) if line.trim_end() == "READY" => break,
+            Ok(_) if line.trim_end() == "Medusa server ready" => continue,
+            Ok(_) => return Err(format!("Unexpected reply from peer: {}", line.trim())),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+
+    crdt.queue_everything(index, store.list_keys()?);
+    crdt.update_peer(index, |peer| {
+        peer.linked = true;
+        peer.last_error = None;
+    });
+    let mut payload = Vec::new();
+    while !lifecycle.should_stop() {
+        let keys: Vec<String> = crdt.update_peer(index, |peer| peer.pending.drain().collect()).unwrap_or_default();
+        if keys.is_empty() {
+            stream.write_all(b"PING\n").map_err(|e| e.to_string())?;
+            thread::sleep(interval);
+            continue;
+        }
+
+        // States are read after the keys are taken, so a write racing the
+        // push is either in it or queued for the next one
+        let (changes, stamps): (Vec<_>, Vec<_>) = store.stamped_states(keys)?.into_iter().unzip();
+        payload.clear();
+        snapshot::write_changes(&changes, 0, 0, &mut payload)?;
+        for (millis, node) in stamps {
+            payload.extend_from_slice(&millis.to_le_bytes());
+            payload.extend_from_slice(&node.to_le_bytes());
+        }
+        stream
+            .write_all(format!("MERGE {}\n", payload.len()).as_bytes())
+            .and_then(|()| stream.write_all(&payload))
+            .map_err(|e| e.to_string())?;
+        crdt.update_peer(index, |peer| {
+            peer.pushes += 1;
+            peer.keys_pushed += changes.len() as u64;
+        });
+        thread::sleep(interval);
+    }
+    Ok(())
+}
+
+/// Merge the changes a peer pushes over `stream`, which has just sent
+/// `CRDTSYNC`, until it disconnects or the server stops.
+pub fn serve_peer(stream: TcpStream, store: Store, lifecycle: Lifecycle) {
+    let addr = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "unknown".to_string());
+    match merge_from_peer(stream, &store, &lifecycle) {
+        Ok(()) => println!("Peer {} disconnected", addr),
+        Err(e) => println!("Peer {} disconnected: {}", addr, e),
+    }
+}
+
+fn merge_from_peer(mut stream: TcpStream, store: &Store, lifecycle: &Lifecycle) -> Result<(), String> {
+    if !store.crdt().is_enabled() {
+        let _ = stream.write_all(b"ERROR: Multi-primary mode is not enabled on this server\n");
+        return Err("Multi-primary mode is not enabled".to_string());
+    }
+    stream.set_read_timeout(Some(LINK_TIMEOUT + store.crdt().interval())).map_err(|e| e.to_string())?;
+    stream.write_all(b"READY\n").map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    while !lifecycle.should_stop() {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["MERGE", len] => {
+                let len = len.parse::<usize>().map_err(|_| format!("Invalid payload length '{}'", len))?;
+                let mut payload = vec![0; len];
+                reader.read_exact(&mut payload).map_err(|e| format!("Failed to read payload: {}", e))?;
+                merge_payload(store, &payload)?;
+            }
+            ["PING"] => {}
+            _ => return Err(format!("Unexpected message from peer: {}", line.trim())),
+        }
+    }
+    Ok(())
+}
+
+fn merge_payload(store: &Store, payload: &[u8]) -> Result<usize, String> {
+    let mut reader = payload;
+    let (_, _, changes) = snapshot::read_changes(&mut reader)?;
+    if reader.len() != changes.len() * 16 {
+        return Err(format!("Expected {} stamps after the changes, got {} bytes", changes.len(), reader.len()));
+    }
+    let mut merged = 0;
+    for (change, stamp) in changes.into_iter().zip(reader.chunks_exact(16)) {
+        let millis = u64::from_le_bytes(stamp[..8].try_into().unwrap_or_default());
+        let node = u64::from_le_bytes(stamp[8..].try_into().unwrap_or_default());
+        if store.merge_change(change, (millis, node))? {
+            merged += 1;
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_writer_wins() {
+        let crdt = Crdt::new();
+        crdt.enabled.store(true, Ordering::SeqCst);
+        let merge = |key, stamp, exists| crdt.merge(key, stamp, exists, || ()).is_some();
+
+        // A key nobody stamped loses to any write, unless it is missing here
+        assert!(!merge("k", (0, 0), true));
+        assert!(merge("k", (0, 0), false));
+        assert!(merge("k", (5, 1), true));
+        assert!(!merge("k", (5, 1), true));
+        assert!(merge("k", (5, 2), true), "the node id breaks ties");
+        assert!(!merge("k", (4, 9), true));
+
+        // Local writes are stamped past anything seen, even from a clock ahead
+        let ahead = now_millis() + 60_000;
+        assert!(merge("other", (ahead, 1), true));
+        crdt.record("k");
+        assert!(crdt.stamp("k").0 > ahead);
+        assert!(!merge("k", (ahead, 9), true));
+
+        // Writes made while merging are not stamped as local ones
+        let before = crdt.stamp("other");
+        crdt.merge("k", (ahead + 10_000, 1), true, || crdt.record("other"));
+        assert_eq!(crdt.stamp("other"), before);
+    }
+}
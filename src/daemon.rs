@@ -0,0 +1,162 @@
+//! Classic Unix daemon mode: detach from the terminal, send output to a log
+//! file and keep a PID file for init scripts.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+static TERMINATING: AtomicBool = AtomicBool::new(false);
+
+/// A PID file naming this process. Removal only deletes the file while it
+/// still holds our PID, so a process that replaced us (see `handoff`) keeps
+/// its own.
+#[derive(Clone, Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    pid: u32,
+}
+
+impl PidFile {
+    /// Write the current PID to `path`, refusing if the file names another
+    /// process that is still running.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let pid = process::id();
+        if let Some(existing) = fs::read_to_string(&path).ok().and_then(|text| text.trim().parse::<u32>().ok()) {
+            if existing != pid && is_running(existing) {
+                return Err(format!("{} belongs to running process {}", path.display(), existing));
+            }
+        }
+        fs::write(&path, format!("{}\n", pid)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(PidFile { path, pid })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn remove(&self) {
+        let ours = fs::read_to_string(&self.path).is_ok_and(|text| text.trim() == self.pid.to_string());
+        if ours {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks that the process exists
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+/// Fork into the background and detach from the terminal. The calling
+/// process exits; the daemon continues with stdin from `/dev/null` and
+/// stdout/stderr appended to `log_file` (discarded without one). The working
+/// directory is kept so relative paths in the configuration still resolve.
+/// Must run before any threads are started.
+#[cfg(unix)]
+pub fn daemonize(log_file: Option<&Path>) -> Result<(), String> {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    let null = OpenOptions::new().read(true).write(true).open("/dev/null").map_err(|e| format!("Failed to open /dev/null: {}", e))?;
+    let log = match log_file {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open log file {}: {}", path.display(), e))?,
+        None => null.try_clone().map_err(|e| format!("Failed to open /dev/null: {}", e))?,
+    };
+    let _ = std::io::stdout().flush();
+
+    // SAFETY: no other threads exist yet, so forking cannot leave locks held
+    // in the child; the descriptors passed to dup2 are open files we own
+    unsafe {
+        // Fork twice with a new session in between, so the daemon is not a
+        // session leader and can never reacquire a controlling terminal
+        for _ in 0..2 {
+            match libc::fork() {
+                -1 => return Err(format!("Failed to fork: {}", std::io::Error::last_os_error())),
+                0 => {}
+                _ => libc::_exit(0),
+            }
+            libc::setsid();
+        }
+        if libc::dup2(null.as_raw_fd(), 0) < 0 || libc::dup2(log.as_raw_fd(), 1) < 0 || libc::dup2(log.as_raw_fd(), 2) < 0 {
+            return Err(format!("Failed to redirect output: {}", std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_log_file: Option<&Path>) -> Result<(), String> {
+    Err("Daemon mode is only supported on Unix".to_string())
+}
+
+/// Remove `pid_file` and exit when the process gets SIGTERM or SIGINT, as
+/// an init script's `stop` sends.
+pub fn remove_on_termination(pid_file: PidFile) {
+    install_handlers();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(100));
+        if TERMINATING.load(Ordering::SeqCst) {
+            println!("Terminated, removing {}", pid_file.path().display());
+            pid_file.remove();
+            process::exit(0);
+        }
+    });
+}
+
+#[cfg(unix)]
+extern "C" fn on_terminate(_signal: libc::c_int) {
+    TERMINATING.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn install_handlers() {
+    // SAFETY: the handler only stores to an atomic, which is signal-safe
+    unsafe {
+        let handler = on_terminate as extern "C" fn(libc::c_int) as *const () as libc::sighandler_t;
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_handlers() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file() {
+        let path = std::env::temp_dir().join(format!("medusa-pid-{}", process::id()));
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", process::id()));
+
+        // A file taken over by another process is left alone
+        fs::write(&path, "1\n").unwrap();
+        pid_file.remove();
+        assert!(path.exists());
+        fs::write(&path, format!("{}\n", process::id())).unwrap();
+        pid_file.remove();
+        assert!(!path.exists());
+
+        // A stale file from a process that is gone is replaced
+        fs::write(&path, format!("{}\n", u32::MAX / 2)).unwrap();
+        PidFile::create(&path).unwrap().remove();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::lifecycle::Lifecycle;
+use crate::store::Store;
+use crate::timeseries::now_millis;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Default)]
+struct DelayedState {
+    // Pending payloads per destination list, keyed by (deliver at ms, sequence)
+    queues: HashMap<String, BTreeMap<(u64, u64), String>>,
+    next_seq: u64,
+}
+
+/// Payloads scheduled for a list but not yet visible in it. The delay
+/// mover pushes each one onto the tail of its list once its delivery time
+/// has passed, so consumers just pop the list as usual.
+#[derive(Clone, Default)]
+pub struct DelayedQueues {
+    inner: Arc<Mutex<DelayedState>>,
+}
+
+impl DelayedQueues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedule `payload` for `queue` at `deliver_at` (unix ms). Returns the
+    /// number of payloads now pending for that queue.
+    pub fn add(&self, queue: &str, deliver_at: u64, payload: &str) -> Result<usize, String> {
+        let mut state = self.inner.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        let pending = state.queues.entry(queue.to_string()).or_default();
+        pending.insert((deliver_at, seq), payload.to_string());
+        Ok(pending.len())
+    }
+
+    /// How many payloads are waiting for `queue`, and when the next is due.
+    pub fn pending(&self, queue: &str) -> (usize, Option<u64>) {
+        self.inner.lock()
+            .ok()
+            .and_then(|state| state.queues.get(queue).map(|pending| {
+                (pending.len(), pending.keys().next().map(|(deliver_at, _)| *deliver_at))
+            }))
+            .unwrap_or((0, None))
+    }
+
+    /// Remove and return every payload due at or before `now` as
+    /// (queue, payload), in delivery order.
+    pub fn take_due(&self, now: u64) -> Vec<(String, String)> {
+        let mut state = match self.inner.lock() {
+            Ok(state) => state,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut due = Vec::new();
+        for (queue, pending) in state.queues.iter_mut() {
+            let later = pending.split_off(&(now + 1, 0));
+            for ((deliver_at, seq), payload) in std::mem::replace(pending, later) {
+                due.push((deliver_at, seq, queue.clone(), payload));
+            }
+        }
+        state.queues.retain(|_, pending| !pending.is_empty());
+
+        due.sort_by_key(|(deliver_at, seq, _, _)| (*deliver_at, *seq));
+        due.into_iter().map(|(_, _, queue, payload)| (queue, payload)).collect()
+    }
+}
+
+/// Deliver due payloads every `interval` on a background thread, until the
+/// server stops.
+pub fn start_delay_mover(store: Store, lifecycle: Lifecycle, interval: Duration) -> thread::JoinHandle<()> {
+    thread::spawn(move || while !lifecycle.should_stop() {
+        for (queue, payload) in store.delayed().take_due(now_millis()) {
+            if let Err(e) = store.rpush(&queue, &payload) {
+                eprintln!("Warning: Could not deliver delayed item to '{}': {}", queue, e);
+            }
+        }
+        thread::sleep(interval);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_due_in_order() {
+        let delayed = DelayedQueues::new();
+        assert_eq!(delayed.add("jobs", 300, "c").unwrap(), 1);
+        delayed.add("jobs", 100, "a").unwrap();
+        delayed.add("mail", 200, "b").unwrap();
+        delayed.add("jobs", 100, "a2").unwrap();
+        assert_eq!(delayed.pending("jobs"), (3, Some(100)));
+
+        assert!(delayed.take_due(99).is_empty());
+        let due = delayed.take_due(200);
+        assert_eq!(due, vec![
+            ("jobs".to_string(), "a".to_string()),
+            ("jobs".to_string(), "a2".to_string()),
+            ("mail".to_string(), "b".to_string()),
+        ]);
+        assert_eq!(delayed.pending("jobs"), (1, Some(300)));
+        assert_eq!(delayed.pending("mail"), (0, None));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::store::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Candidates kept between evictions, as in Redis.
+pub const POOL_SIZE: usize = 16;
+pub const DEFAULT_SAMPLES: usize = 5;
+
+/// What happens when a write would take memory over the limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvictionPolicy {
+    /// Refuse the write.
+    NoEviction,
+    /// Evict the least recently used keys.
+    AllKeysLru,
+    /// Evict the least recently used keys that have a TTL.
+    VolatileLru,
+}
+
+impl EvictionPolicy {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "noeviction" => Ok(EvictionPolicy::NoEviction),
+            "allkeys-lru" => Ok(EvictionPolicy::AllKeysLru),
+            "volatile-lru" => Ok(EvictionPolicy::VolatileLru),
+            other => Err(format!("Unknown eviction policy '{}', expected noeviction, allkeys-lru or volatile-lru", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+        }
+    }
+}
+
+/// Called with each evicted key, its value and the policy that chose it.
+pub type EvictHook = Arc<dyn Fn(&str, &Value, EvictionPolicy) + Send + Sync>;
+/// Called when a write is refused for lack of memory.
+pub type OomHook = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+struct Hooks {
+    on_evict: Option<EvictHook>,
+    on_oom: Option<OomHook>,
+}
+
+/// Pinned keys and patterns as of the start of an eviction, so checking a
+/// candidate takes no lock.
+pub(crate) struct Pins {
+    exact: HashSet<String>,
+    prefixes: Vec<String>,
+}
+
+impl Pins {
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.exact.contains(key) || self.prefixes.iter().any(|prefix| key.starts_with(prefix.as_str()))
+    }
+}
+
+/// Memory limit settings plus the candidate pool for approximate LRU.
+///
+/// Rather than keeping every key on an LRU list (a pointer update on every
+/// read), each eviction samples a few keys and compares their access
+/// stamps. The best candidates seen so far stay in a small pool sorted by
+/// idle time, so later samples only need to beat them; more samples per
+/// round gets closer to true LRU at more cost per eviction.
+#[derive(Clone)]
+pub struct Eviction {
+    inner: Arc<EvictionInner>,
+}
+
+struct EvictionInner {
+    max_memory: AtomicUsize,
+    samples: AtomicUsize,
+    policy: Mutex<EvictionPolicy>,
+    // Sorted by idle time, most idle last
+    pool: Mutex<Vec<(Duration, String)>>,
+    evicted: AtomicU64,
+    hooks: RwLock<Hooks>,
+    // Keys and `prefix*` patterns that are never evicted
+    pinned: Mutex<BTreeSet<String>>,
+}
+
+impl Default for Eviction {
+    fn default() -> Self {
+        Eviction {
+            inner: Arc::new(EvictionInner {
+                max_memory: AtomicUsize::new(0),
+                samples: AtomicUsize::new(DEFAULT_SAMPLES),
+                policy: Mutex::new(EvictionPolicy::NoEviction),
+                pool: Mutex::new(Vec::with_capacity(POOL_SIZE)),
+                evicted: AtomicU64::new(0),
+                hooks: RwLock::new(Hooks::default()),
+                pinned: Mutex::new(BTreeSet::new()),
+            }),
+        }
+    }
+}
+
+impl Eviction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Memory limit in bytes of estimated usage; 0 means unlimited.
+    pub fn max_memory(&self) -> usize {
+        self.inner.max_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn set_max_memory(&self, bytes: usize) {
+        self.inner.max_memory.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn policy(&self) -> EvictionPolicy {
+        self.inner.policy.lock().map(|policy| *policy).unwrap_or(EvictionPolicy::NoEviction)
+    }
+
+    pub fn set_policy(&self, policy: EvictionPolicy) {
+        if let Ok(mut current) = self.inner.policy.lock() {
+            *current = policy;
+        }
+        self.clear_pool();
+    }
+
+    /// Keys sampled per eviction round.
+    pub fn samples(&self) -> usize {
+        self.inner.samples.load(Ordering::Relaxed)
+    }
+
+    pub fn set_samples(&self, samples: usize) {
+        self.inner.samples.store(samples.max(1), Ordering::Relaxed);
+    }
+
+    pub fn evicted(&self) -> u64 {
+        self.inner.evicted.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_evicted(&self) {
+        self.inner.evicted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Exempt a key, or every key matching a `KEYS`-style pattern such as
+    /// `config:*`, from eviction. The key need not exist yet, and stays
+    /// pinned if deleted. Returns false if it was already pinned.
+    pub fn pin(&self, pattern: &str) -> bool {
+        self.inner.pinned.lock().map(|mut pinned| pinned.insert(pattern.to_string())).unwrap_or(false)
+    }
+
+    /// Returns false if the key or pattern was not pinned.
+    pub fn unpin(&self, pattern: &str) -> bool {
+        self.inner.pinned.lock().map(|mut pinned| pinned.remove(pattern)).unwrap_or(false)
+    }
+
+    /// Pinned keys and patterns, sorted.
+    pub fn pinned(&self) -> Vec<String> {
+        self.inner.pinned.lock().map(|pinned| pinned.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    pub fn is_pinned(&self, key: &str) -> bool {
+        self.pins().contains(key)
+    }
+
+    pub(crate) fn pins(&self) -> Pins {
+        let mut pins = Pins { exact: HashSet::new(), prefixes: Vec::new() };
+        if let Ok(pinned) = self.inner.pinned.lock() {
+            for pattern in pinned.iter() {
+                match pattern.split_once('*') {
+                    Some((prefix, _)) => pins.prefixes.push(prefix.to_string()),
+                    None => {
+                        pins.exact.insert(pattern.clone());
+                    }
+                }
+            }
+        }
+        pins
+    }
+
+    /// Register a hook run for every key evicted to make room, so an
+    /// embedding application can keep evicted entries somewhere else. It
+    /// runs on the writing client's thread after the store lock has been
+    /// released, so it may use the store, but it delays that write.
+    pub fn on_evict<F: Fn(&str, &Value, EvictionPolicy) + Send + Sync + 'static>(&self, hook: F) {
+        if let Ok(mut hooks) = self.inner.hooks.write() {
+            hooks.on_evict = Some(Arc::new(hook));
+        }
+    }
+
+    /// Register a hook run whenever a write is refused with OOM, for
+    /// shedding load while the cache is full. Runs like `on_evict` hooks.
+    pub fn on_oom<F: Fn() + Send + Sync + 'static>(&self, hook: F) {
+        if let Ok(mut hooks) = self.inner.hooks.write() {
+            hooks.on_oom = Some(Arc::new(hook));
+        }
+    }
+
+    pub fn clear_hooks(&self) {
+        if let Ok(mut hooks) = self.inner.hooks.write() {
+            *hooks = Hooks::default();
+        }
+    }
+
+    pub(crate) fn evict_hook(&self) -> Option<EvictHook> {
+        self.inner.hooks.read().ok().and_then(|hooks| hooks.on_evict.clone())
+    }
+
+    pub(crate) fn oom_hook(&self) -> Option<OomHook> {
+        self.inner.hooks.read().ok().and_then(|hooks| hooks.on_oom.clone())
+    }
+
+    /// Offer a sampled key. It joins the pool if there is room or it has
+    /// been idle longer than the pool's least idle candidate.
+    pub(crate) fn offer(&self, key: &str, idle: Duration) {
+        let Ok(mut pool) = self.inner.pool.lock() else { return };
+        if pool.iter().any(|(_, candidate)| candidate == key) {
+            return;
+        }
+        if pool.len() == POOL_SIZE {
+            if idle <= pool[0].0 {
+                return;
+            }
+            pool.remove(0);
+        }
+        let position = pool.partition_point(|(candidate_idle, _)| *candidate_idle < idle);
+        pool.insert(position, (idle, key.to_string()));
+    }
+
+    /// Take the most idle candidate out of the pool.
+    pub(crate) fn take_best(&self) -> Option<String> {
+        self.inner.pool.lock().ok().and_then(|mut pool| pool.pop()).map(|(_, key)| key)
+    }
+
+    pub(crate) fn clear_pool(&self) {
+        if let Ok(mut pool) = self.inner.pool.lock() {
+            pool.clear();
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.max_memory() {
+            0 => "unlimited".to_string(),
+            bytes => format!("{} bytes, {}, {} samples", bytes, self.policy().name(), self.samples()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_keeps_most_idle() {
+        let eviction = Eviction::new();
+        for i in 0..POOL_SIZE as u64 + 4 {
+            eviction.offer(&format!("key{}", i), Duration::from_secs(i));
+        }
+        // Too fresh to displace anything in a full pool
+        eviction.offer("fresh", Duration::ZERO);
+        eviction.offer("key19", Duration::from_secs(19));
+
+        assert_eq!(eviction.take_best().as_deref(), Some("key19"));
+        assert_eq!(eviction.take_best().as_deref(), Some("key18"));
+        let mut rest = Vec::new();
+        while let Some(key) = eviction.take_best() {
+            rest.push(key);
+        }
+        assert_eq!(rest.len(), POOL_SIZE - 2);
+        assert_eq!(rest.last().map(String::as_str), Some("key4"));
+    }
+
+    #[test]
+    fn test_pins() {
+        let eviction = Eviction::new();
+        assert!(eviction.pin("settings"));
+        assert!(eviction.pin("config:*"));
+        assert!(!eviction.pin("settings"));
+        assert!(eviction.is_pinned("settings"));
+        assert!(eviction.is_pinned("config:db:url"));
+        assert!(!eviction.is_pinned("settings:old"));
+        assert_eq!(eviction.pinned(), ["config:*", "settings"]);
+
+        assert!(eviction.unpin("config:*"));
+        assert!(!eviction.unpin("config:*"));
+        assert!(!eviction.is_pinned("config:db:url"));
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(EvictionPolicy::parse("ALLKEYS-LRU").unwrap(), EvictionPolicy::AllKeysLru);
+        assert_eq!(EvictionPolicy::parse("volatile-lru").unwrap().name(), "volatile-lru");
+        assert!(EvictionPolicy::parse("lfu").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::command_table;
+use crate::reply::Reply;
+use crate::store::Store;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// A command added by the embedding program. It gets the arguments after
+/// the command name and the server's store, and returns a `Reply` that
+/// each protocol renders its own way; an `Err` is sent back as an error.
+pub type CommandHandler = Arc<dyn Fn(&[&str], &Store) -> Result<Reply, String> + Send + Sync>;
+
+/// Commands registered by a program embedding medusa, consulted by the
+/// dispatcher for any name that is not a built-in command. Clones share
+/// the same registry, so commands registered after the server starts are
+/// picked up straight away.
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    commands: Arc<RwLock<BTreeMap<String, CommandHandler>>>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace a command. Names are case-insensitive and cannot
+    /// shadow a built-in command.
+    pub fn register(&self, name: &str, handler: impl Fn(&[&str], &Store) -> Result<Reply, String> + Send + Sync + 'static) -> Result<(), String> {
+        let name = name.to_uppercase();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("Invalid command name '{}'", name));
+        }
+        if !command_table::lookup(&name).is_empty() {
+            return Err(format!("'{}' is a built-in command", name));
+        }
+
+        let mut commands = self.commands.write().map_err(|_| "Failed to acquire lock".to_string())?;
+        commands.insert(name, Arc::new(handler));
+        Ok(())
+    }
+
+    pub fn unregister(&self, name: &str) -> Result<bool, String> {
+        let mut commands = self.commands.write().map_err(|_| "Failed to acquire lock".to_string())?;
+        Ok(commands.remove(&name.to_uppercase()).is_some())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.commands.read().map(|commands| commands.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Run `parts` (command name first) if it names a registered command,
+    /// returning its reply.
+    pub fn call(&self, parts: &[&str], store: &Store) -> Option<Reply> {
+        // The lock is let go before the handler runs, so it may register commands itself
+        let handler = self.get(parts.first()?)?;
+        Some(handler(&parts[1..], store).unwrap_or_else(Reply::error))
+    }
+
+    fn get(&self, name: &str) -> Option<CommandHandler> {
+        self.commands.read().ok()?.get(&name.to_uppercase()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_registry() {
+        let commands = CommandRegistry::new();
+        let store = Store::new();
+        commands
+            .register("greet", |args, _| match args {
+                [name] => Ok(Reply::ok(format!("Hello, {}", name))),
+                _ => Err("GREET requires a name (GREET name)".to_string()),
+            })
+            .unwrap();
+
+        assert_eq!(commands.call(&["GREET", "Ada"], &store), Some(Reply::ok("Hello, Ada")));
+        assert_eq!(commands.call(&["greet"], &store), Some(Reply::error("GREET requires a name (GREET name)")));
+        assert_eq!(commands.call(&["WAVE"], &store), None);
+        assert_eq!(commands.names(), ["GREET"]);
+
+        assert!(commands.register("GET", |_, _| Ok(Reply::ok("Done"))).is_err());
+        assert!(commands.register("two words", |_, _| Ok(Reply::ok("Done"))).is_err());
+
+        assert!(commands.unregister("Greet").unwrap());
+        assert!(!commands.contains("GREET"));
+    }
+}
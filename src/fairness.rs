@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Commands a pipelining client may run before letting the next one go.
+pub const DEFAULT_QUANTUM: usize = 64;
+
+/// Round-robin turns for clients with a pipelined backlog.
+///
+/// Connections run their commands in parallel, so a client that pipelines
+/// thousands of commands competes for the store on every one of them and
+/// can crowd out everyone else. A client only needs a turn while more of its commands
+/// are already buffered; it then runs at most `quantum` of them before
+/// queueing behind the other backlogged clients. Interactive clients never
+/// wait for a turn, so they share the store with at most one bulk client at
+/// a time.
+#[derive(Clone)]
+pub struct Scheduler {
+    quantum: usize,
+    queue: Arc<(Mutex<VecDeque<u64>>, Condvar)>,
+}
+
+impl Scheduler {
+    /// A quantum of 0 turns scheduling off.
+    pub fn new(quantum: usize) -> Self {
+        Scheduler { quantum, queue: Arc::new((Mutex::new(VecDeque::new()), Condvar::new())) }
+    }
+
+    pub fn quantum(&self) -> usize {
+        self.quantum
+    }
+
+    /// Clients holding or waiting for a turn.
+    pub fn backlogged(&self) -> usize {
+        self.queue.0.lock().map(|queue| queue.len()).unwrap_or(0)
+    }
+
+    /// Per-connection scheduling state.
+    pub fn client(&self, client_id: u64) -> ClientTurn {
+        ClientTurn { scheduler: self.clone(), client_id, holding: false, used: 0 }
+    }
+
+    fn acquire(&self, client_id: u64) {
+        let (lock, ready) = &*self.queue;
+        let Ok(mut queue) = lock.lock() else { return };
+        if !queue.contains(&client_id) {
+            queue.push_back(client_id);
+        }
+        while queue.front() != Some(&client_id) {
+            queue = match ready.wait(queue) {
+                Ok(queue) => queue,
+                Err(_) => return,
+            };
+        }
+    }
+
+    fn release(&self, client_id: u64) {
+        let (lock, ready) = &*self.queue;
+        if let Ok(mut queue) = lock.lock() {
+            queue.retain(|&id| id != client_id);
+            ready.notify_all();
+        }
+    }
+}
+
+/// One connection's place in the [`Scheduler`]. The turn is given up when
+/// this is dropped, so a client that disconnects mid-turn cannot stall the
+/// others.
+pub struct ClientTurn {
+    scheduler: Scheduler,
+    client_id: u64,
+    holding: bool,
+    used: usize,
+}
+
+impl ClientTurn {
+    /// Call before running a command. `backlog` says whether more commands
+    /// from this client are already buffered behind it; without one the
+    /// client gives up any turn it holds and runs straight away.
+    pub fn before_command(&mut self, backlog: bool) {
+        if self.scheduler.quantum == 0 {
+            return;
+        }
+        if !backlog {
+            self.release();
+            return;
+        }
+        if self.holding && self.used >= self.scheduler.quantum {
+            self.release();
+        }
+        if !self.holding {
+            self.scheduler.acquire(self.client_id);
+            self.holding = true;
+        }
+        self.used += 1;
+    }
+
+    fn release(&mut self) {
+        if self.holding {
+            self.scheduler.release(self.client_id);
+            self.holding = false;
+        }
+        self.used = 0;
+    }
+}
+
+impl Drop for ClientTurn {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_turns_alternate() {
+        let scheduler = Scheduler::new(2);
+        let mut first = scheduler.client(1);
+        first.before_command(true);
+        first.before_command(true);
+        assert_eq!(scheduler.backlogged(), 1);
+
+        // The second client waits until the first uses up its quantum
+        let ran = Arc::new(AtomicUsize::new(0));
+        let waiter = {
+            let scheduler = scheduler.clone();
+            let ran = ran.clone();
+            thread::spawn(move || {
+                let mut second = scheduler.client(2);
+                second.before_command(true);
+                ran.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(50));
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+        assert_eq!(scheduler.backlogged(), 2);
+
+        first.before_command(true);
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+        waiter.join().unwrap();
+
+        // Without a backlog the turn is dropped and nobody waits
+        first.before_command(false);
+        assert_eq!(scheduler.backlogged(), 0);
+        drop(first);
+
+        let mut unscheduled = Scheduler::new(0).client(3);
+        unscheduled.before_command(true);
+    }
+}
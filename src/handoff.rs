@@ -0,0 +1,215 @@
+//! Zero-downtime restarts: the running server snapshots its dataset, clears
+//! close-on-exec on the listening socket and spawns a fresh copy of itself
+//! that adopts both. The old process then stops accepting and drains.
+//!
+//! The snapshot normally goes through a temporary file. With the
+//! experimental in-memory mode (Linux only) it is written to an anonymous
+//! memfd instead, which the new process inherits and reads back, so an
+//! upgrade never writes the dataset to disk.
+
+use crate::lifecycle::Lifecycle;
+use crate::snapshot::{read_snapshot, write_snapshot};
+use crate::store::Store;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::net::TcpListener;
+use std::process::{self, Command};
+use std::time::Duration;
+
+pub const LISTEN_FD_ENV: &str = "MEDUSA_LISTEN_FD";
+pub const SNAPSHOT_ENV: &str = "MEDUSA_HANDOFF_SNAPSHOT";
+pub const SNAPSHOT_FD_ENV: &str = "MEDUSA_HANDOFF_SNAPSHOT_FD";
+
+/// Start a replacement process that takes over the listener and the data.
+/// Returns the new process id.
+pub fn hand_off(store: &Store, lifecycle: &Lifecycle, grace: Duration) -> Result<u32, String> {
+    let fd = lifecycle.listener_fd().ok_or("No listening socket to hand off")?;
+    if lifecycle.is_draining() {
+        return Err("Server is already draining".to_string());
+    }
+
+    if lifecycle.handoff_in_memory() {
+        let snapshot = snapshot_to_memory(store)?;
+        let snapshot_fd = raw_fd(&snapshot);
+        set_inheritable(snapshot_fd, true)?;
+        let spawned = spawn_successor(fd, |command| {
+            command.env(SNAPSHOT_FD_ENV, snapshot_fd.to_string()).env_remove(SNAPSHOT_ENV);
+        });
+        // The child holds its own copy; ours closes when `snapshot` drops
+        let _ = set_inheritable(snapshot_fd, false);
+        if spawned.is_ok() {
+            lifecycle.mark_handed_off(grace);
+        }
+        return spawned;
+    }
+
+    let path = env::temp_dir().join(format!("medusa-handoff-{}.snapshot", process::id()));
+    let file = File::create(&path).map_err(|e| format!("Failed to create snapshot file: {}", e))?;
+    let mut writer = BufWriter::new(file);
+    write_snapshot(store, &mut writer)?;
+    drop(writer);
+
+    match spawn_successor(fd, |command| {
+        command.env(SNAPSHOT_ENV, &path).env_remove(SNAPSHOT_FD_ENV);
+    }) {
+        Ok(pid) => {
+            lifecycle.mark_handed_off(grace);
+            Ok(pid)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&path);
+            Err(e)
+        }
+    }
+}
+
+/// Spawn a copy of this executable that inherits the listener `fd`, with
+/// `configure` pointing it at the snapshot.
+fn spawn_successor<F: FnOnce(&mut Command)>(fd: i32, configure: F) -> Result<u32, String> {
+    set_inheritable(fd, true)?;
+    let exe = env::current_exe().map_err(|e| format!("Failed to locate executable: {}", e))?;
+    let mut command = Command::new(exe);
+    command.args(env::args().skip(1)).env(LISTEN_FD_ENV, fd.to_string());
+    configure(&mut command);
+    let spawned = command.spawn();
+    // Nothing else we start should inherit the socket
+    let _ = set_inheritable(fd, false);
+    spawned.map(|child| child.id()).map_err(|e| format!("Failed to start new process: {}", e))
+}
+
+/// Adopt a listening socket passed down by `hand_off`, if any.
+pub fn inherited_listener() -> Option<TcpListener> {
+    let fd = env::var(LISTEN_FD_ENV).ok()?.parse::<i32>().ok()?;
+    adopt_listener(fd)
+}
+
+/// Load the snapshot passed down by `hand_off`, if any, deleting its file
+/// or closing its memfd afterwards.
+pub fn restore_handoff_snapshot(store: &Store) -> Option<Result<usize, String>> {
+    if let Ok(fd) = env::var(SNAPSHOT_FD_ENV) {
+        let result = fd
+            .parse::<i32>()
+            .map_err(|_| format!("Invalid {} '{}'", SNAPSHOT_FD_ENV, fd))
+            .and_then(|fd| read_memory_snapshot(store, fd));
+        return Some(result);
+    }
+    let path = env::var(SNAPSHOT_ENV).ok()?;
+    let result = File::open(&path)
+        .map_err(|e| format!("Failed to open handoff snapshot: {}", e))
+        .and_then(|file| read_snapshot(store, &mut BufReader::new(file)));
+    let _ = fs::remove_file(&path);
+    Some(result)
+}
+
+/// Write a snapshot of `store` into a fresh memfd.
+#[cfg(target_os = "linux")]
+fn snapshot_to_memory(store: &Store) -> Result<File, String> {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: memfd_create with a NUL-terminated name; the descriptor it
+    // returns is new and owned by the File below
+    let fd = unsafe { libc::memfd_create(c"medusa-handoff".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(format!("Failed to create memfd: {}", std::io::Error::last_os_error()));
+    }
+    let file = unsafe { File::from_raw_fd(fd) };
+    let mut writer = BufWriter::new(&file);
+    write_snapshot(store, &mut writer)?;
+    writer.flush().map_err(|e| format!("Failed to write snapshot to memfd: {}", e))?;
+    drop(writer);
+    Ok(file)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn snapshot_to_memory(_store: &Store) -> Result<File, String> {
+    Err("In-memory handoff needs memfd, which is only available on Linux".to_string())
+}
+
+/// Read a snapshot back from an inherited memfd and close it.
+#[cfg(unix)]
+fn read_memory_snapshot(store: &Store, fd: i32) -> Result<usize, String> {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: the descriptor was left open across exec by our parent for
+    // us to own; dropping the File closes it and frees the memory
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    // The offset is shared with the parent, which left it at the end
+    file.seek(SeekFrom::Start(0)).map_err(|e| format!("Failed to rewind handoff memfd: {}", e))?;
+    read_snapshot(store, &mut BufReader::new(file))
+}
+
+#[cfg(not(unix))]
+fn read_memory_snapshot(_store: &Store, _fd: i32) -> Result<usize, String> {
+    Err("In-memory handoff is only supported on Unix".to_string())
+}
+
+#[cfg(unix)]
+fn raw_fd(file: &File) -> i32 {
+    use std::os::unix::io::AsRawFd;
+    file.as_raw_fd()
+}
+
+#[cfg(not(unix))]
+fn raw_fd(_file: &File) -> i32 {
+    -1
+}
+
+#[cfg(unix)]
+fn adopt_listener(fd: i32) -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let _ = set_inheritable(fd, false);
+    // SAFETY: the descriptor was created by our parent as a bound TCP
+    // listener and deliberately left open across exec for us to own
+    Some(unsafe { TcpListener::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn adopt_listener(_fd: i32) -> Option<TcpListener> {
+    None
+}
+
+#[cfg(unix)]
+fn set_inheritable(fd: i32, inheritable: bool) -> Result<(), String> {
+    // SAFETY: fcntl on a descriptor we own, only touching FD_CLOEXEC
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags < 0 {
+            return Err("Failed to read socket flags".to_string());
+        }
+        let flags = if inheritable {
+            flags & !libc::FD_CLOEXEC
+        } else {
+            flags | libc::FD_CLOEXEC
+        };
+        if libc::fcntl(fd, libc::F_SETFD, flags) < 0 {
+            return Err("Failed to update socket flags".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_inheritable(_fd: i32, _inheritable: bool) -> Result<(), String> {
+    Err("Listener handoff is only supported on Unix".to_string())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::io::IntoRawFd;
+
+    #[test]
+    fn test_memory_snapshot_round_trip() {
+        let store = Store::new();
+        store.set("greeting", "hello").unwrap();
+        store.hset("user:1", "name", "Ada").unwrap();
+
+        let fd = snapshot_to_memory(&store).unwrap().into_raw_fd();
+        let restored = Store::new();
+        assert_eq!(read_memory_snapshot(&restored, fd).unwrap(), 2);
+        assert_eq!(restored.get("greeting").unwrap().as_deref(), Some("hello"));
+        assert_eq!(restored.hget("user:1", "name").unwrap().as_deref(), Some("Ada"));
+    }
+}
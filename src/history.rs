@@ -0,0 +1,170 @@
+use crate::timeseries::now_millis;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Keys with a history kept at once; the key first tracked longest ago is
+/// forgotten to make room, so a flush of a large keyspace cannot grow the
+/// table without bound.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// One change to a key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    /// Unix milliseconds.
+    pub timestamp: u64,
+    /// Command that made the change, or "(internal)" for changes the server
+    /// made on its own (delayed delivery, seeding) or through the library.
+    pub op: String,
+    /// Address of the client that sent it, or "server".
+    pub client: String,
+    /// The client's request tag (`*TAG id command`), if it sent one.
+    pub tag: Option<String>,
+}
+
+struct Context {
+    op: String,
+    client: String,
+    tag: Option<String>,
+}
+
+thread_local! {
+    // A command runs start to finish on one thread, so the command it is
+    // running is known here without passing it through every store method
+    static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+/// Marks this thread as running `op` for `client` until dropped, so store
+/// changes made meanwhile are attributed to it.
+pub struct CommandContext(());
+
+impl CommandContext {
+    pub fn enter(op: &str, client: &str, tag: Option<&str>) -> Self {
+        CONTEXT.with(|context| {
+            *context.borrow_mut() =
+                Some(Context { op: op.to_string(), client: client.to_string(), tag: tag.map(str::to_string) });
+        });
+        CommandContext(())
+    }
+}
+
+impl Drop for CommandContext {
+    fn drop(&mut self) {
+        CONTEXT.with(|context| *context.borrow_mut() = None);
+    }
+}
+
+#[derive(Default)]
+struct Histories {
+    keys: HashMap<String, VecDeque<HistoryEntry>>,
+    order: VecDeque<String>,
+}
+
+/// The last few changes to each key, for `HISTORY`. Off (depth 0) unless
+/// configured, since it costs a lock and an allocation on every write.
+#[derive(Clone, Default)]
+pub struct KeyHistory {
+    depth: Arc<AtomicUsize>,
+    histories: Arc<Mutex<Histories>>,
+}
+
+impl KeyHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Changes kept per key; 0 turns history off and forgets what was kept.
+    pub fn set_depth(&self, depth: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+        if let Ok(mut histories) = self.histories.lock() {
+            if depth == 0 {
+                *histories = Histories::default();
+            } else {
+                for entries in histories.keys.values_mut() {
+                    entries.truncate(depth);
+                }
+            }
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.depth() > 0
+    }
+
+    /// Note a change to `key` by whatever command this thread is running.
+    pub fn record(&self, key: &str) {
+        let depth = self.depth();
+        if depth == 0 {
+            return;
+        }
+        let entry = CONTEXT.with(|context| match &*context.borrow() {
+            Some(context) => HistoryEntry {
+                timestamp: now_millis(),
+                op: context.op.clone(),
+                client: context.client.clone(),
+                tag: context.tag.clone(),
+            },
+            None => HistoryEntry { timestamp: now_millis(), op: "(internal)".to_string(), client: "server".to_string(), tag: None },
+        });
+
+        if let Ok(mut histories) = self.histories.lock() {
+            let histories = &mut *histories;
+            if !histories.keys.contains_key(key) {
+                if histories.order.len() >= MAX_TRACKED_KEYS {
+                    if let Some(oldest) = histories.order.pop_front() {
+                        histories.keys.remove(&oldest);
+                    }
+                }
+                histories.order.push_back(key.to_string());
+            }
+            let entries = histories.keys.entry(key.to_string()).or_default();
+            entries.push_front(entry);
+            entries.truncate(depth);
+        }
+    }
+
+    /// Changes to `key`, newest first.
+    pub fn get(&self, key: &str) -> Vec<HistoryEntry> {
+        self.histories
+            .lock()
+            .map(|histories| histories.keys.get(key).map(|entries| entries.iter().cloned().collect()).unwrap_or_default())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_history() {
+        let history = KeyHistory::new();
+        history.record("config:mode");
+        assert!(history.get("config:mode").is_empty());
+
+        history.set_depth(2);
+        history.record("config:mode");
+        {
+            let _context = CommandContext::enter("SET", "10.0.0.7:5123", Some("deploy-42"));
+            history.record("config:mode");
+            history.record("config:mode");
+        }
+        let entries = history.get("config:mode");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].op, "SET");
+        assert_eq!(entries[0].client, "10.0.0.7:5123");
+        assert_eq!(entries[0].tag.as_deref(), Some("deploy-42"));
+
+        // Outside a command, changes are the server's own
+        history.record("config:mode");
+        assert_eq!(history.get("config:mode")[0].op, "(internal)");
+
+        history.set_depth(0);
+        assert!(history.get("config:mode").is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+use crate::lifecycle::Lifecycle;
+use crate::store::{Store, Value};
+use crate::telemetry::escape_json;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DASHBOARD: &str = include_str!("dashboard.html");
+const DEFAULT_KEY_LIMIT: usize = 100;
+// Large lists and series are cut short in the key viewer
+const MAX_VALUE_ITEMS: usize = 1000;
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(body: String) -> Self {
+        Response { status: "200 OK", content_type: "application/json", body }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: format!("{{\"error\":\"{}\"}}", escape_json(message)),
+        }
+    }
+}
+
+/// Serve the admin dashboard and its JSON API on `listener` from a
+/// background thread. Read-only: nothing here modifies the store.
+pub fn start_http_server(listener: TcpListener, store: Store, lifecycle: Lifecycle) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let store = store.clone();
+                    let lifecycle = lifecycle.clone();
+                    thread::spawn(move || handle_connection(stream, &store, &lifecycle));
+                }
+                Err(e) => eprintln!("❌ Failed to accept HTTP connection: {}", e),
+            }
+        }
+    })
+}
+
+fn handle_connection(stream: TcpStream, store: &Store, lifecycle: &Lifecycle) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // Headers are not needed, but must be consumed before replying
+    let mut header = String::new();
+    loop {
+        header.clear();
+        match reader.read_line(&mut header) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if header.trim().is_empty() => break,
+            Ok(_) => {}
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("/");
+
+    let response = if method == "GET" {
+        route(target, store, lifecycle)
+    } else {
+        Response::error("405 Method Not Allowed", "Only GET is supported")
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    let _ = writer.write_all(head.as_bytes());
+    let _ = writer.write_all(response.body.as_bytes());
+    let _ = writer.flush();
+}
+
+fn route(target: &str, store: &Store, lifecycle: &Lifecycle) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let param = |name: &str| {
+        query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| percent_decode(value))
+    };
+
+    let result = match path {
+        "/" | "/index.html" => {
+            return Response { status: "200 OK", content_type: "text/html; charset=utf-8", body: DASHBOARD.to_string() };
+        }
+        "/api/info" => info_json(store, lifecycle),
+        "/api/keys" => {
+            let pattern = param("pattern").filter(|p| !p.is_empty()).unwrap_or_else(|| "*".to_string());
+            let limit = param("limit").and_then(|l| l.parse().ok()).unwrap_or(DEFAULT_KEY_LIMIT);
+            keys_json(store, &pattern, limit)
+        }
+        "/api/key" => match param("name") {
+            Some(name) => match key_json(store, &name) {
+                Ok(Some(json)) => Ok(json),
+                Ok(None) => return Response::error("404 Not Found", "No such key"),
+                Err(e) => Err(e),
+            },
+            None => return Response::error("400 Bad Request", "Missing 'name' parameter"),
+        },
+        "/api/slowlog" => Ok(slowlog_json(store)),
+        "/api/clients" => Ok(clients_json(lifecycle)),
+        "/metrics" => {
+            return Response {
+                status: "200 OK",
+                content_type: "text/plain; version=0.0.4; charset=utf-8",
+                body: store.latency().prometheus(),
+            };
+        }
+        _ => return Response::error("404 Not Found", "Not found"),
+    };
+
+    match result {
+        Ok(body) => Response::json(body),
+        Err(e) => Response::error("500 Internal Server Error", &e),
+    }
+}
+
+// INFO as a flat JSON object, plus the connection stats only the server knows
+fn info_json(store: &Store, lifecycle: &Lifecycle) -> Result<String, String> {
+    let info = store.info()?;
+    let mut fields = Vec::new();
+    for line in info.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            fields.push(format!("\"{}\":{}", escape_json(key), json_scalar(value)));
+        }
+    }
+
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    fields.push(format!("\"connected_clients\":{}", lifecycle.clients().len()));
+    fields.push(format!("\"total_commands_processed\":{}", lifecycle.total_commands()));
+    fields.push(format!("\"slowlog_len\":{}", store.slowlog().len()));
+    fields.push(format!("\"timestamp_ms\":{}", now_ms));
+    Ok(format!("{{{}}}", fields.join(",")))
+}
+
+fn keys_json(store: &Store, pattern: &str, limit: usize) -> Result<String, String> {
+    let mut keys = store.keys_pattern(pattern)?;
+    keys.sort();
+    let total = keys.len();
+
+    let mut entries = Vec::new();
+    for key in keys.iter().take(limit) {
+        // Keys can expire between listing and inspecting them
+        if let Some((value, ttl)) = store.inspect(key)? {
+            entries.push(format!(
+                "{{\"key\":\"{}\",\"type\":\"{}\",\"ttl\":{}}}",
+                escape_json(key),
+                value.type_name(),
+                ttl_json(ttl)
+            ));
+        }
+    }
+    Ok(format!("{{\"total\":{},\"keys\":[{}]}}", total, entries.join(",")))
+}
+
+fn key_json(store: &Store, key: &str) -> Result<Option<String>, String> {
+    let (value, ttl) = match store.inspect(key)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    let (json, size) = match &value {
+        Value::String(s) => (format!("\"{}\"", escape_json(s)), 1),
+        Value::Hash(hash) => {
+            let mut fields: Vec<_> = hash.iter().collect();
+            fields.sort();
+            let json = fields.iter()
+                .take(MAX_VALUE_ITEMS)
+                .map(|(field, value)| format!("\"{}\":\"{}\"", escape_json(field), escape_json(&String::from_utf8_lossy(value))))
+                .collect::<Vec<_>>();
+            (format!("{{{}}}", json.join(",")), hash.len())
+        }
+        Value::List(list) => {
+            let json = list.iter()
+                .take(MAX_VALUE_ITEMS)
+                .map(|item| format!("\"{}\"", escape_json(item)))
+                .collect::<Vec<_>>();
+            (format!("[{}]", json.join(",")), list.len())
+        }
+        Value::Set(set) => {
+            let mut members: Vec<_> = set.iter().collect();
+            members.sort();
+            let json = members.iter()
+                .take(MAX_VALUE_ITEMS)
+                .map(|member| format!("\"{}\"", escape_json(member)))
+                .collect::<Vec<_>>();
+            (format!("[{}]", json.join(",")), set.len())
+        }
+        Value::SortedSet(set) => {
+            let json = set.iter()
+                .take(MAX_VALUE_ITEMS)
+                .map(|(member, score)| format!("[\"{}\",{}]", escape_json(member), json_number(score)))
+                .collect::<Vec<_>>();
+            (format!("[{}]", json.join(",")), set.len())
+        }
+        // Most recent entries, oldest first, as [id, {field: value}]
+        Value::Stream(stream) => {
+            let skip = stream.len().saturating_sub(MAX_VALUE_ITEMS);
+            let json = stream.iter()
+                .skip(skip)
+                .map(|(id, fields)| {
+                    let fields = fields.iter()
+                        .map(|(field, value)| format!("\"{}\":\"{}\"", escape_json(field), escape_json(value)))
+                        .collect::<Vec<_>>();
+                    format!("[\"{}\",{{{}}}]", id, fields.join(","))
+                })
+                .collect::<Vec<_>>();
+            (format!("[{}]", json.join(",")), stream.len())
+        }
+        // Most recent samples, oldest first, so they plot left to right
+        Value::TimeSeries(series) => {
+            let skip = series.len().saturating_sub(MAX_VALUE_ITEMS);
+            let json = series.samples()
+                .skip(skip)
+                .map(|(timestamp, value)| format!("[{},{}]", timestamp, json_number(*value)))
+                .collect::<Vec<_>>();
+            (format!("[{}]", json.join(",")), series.len())
+        }
+        Value::Vector(vector) => {
+            let json = vector.iter().map(|x| json_number(*x as f64)).collect::<Vec<_>>();
+            (format!("[{}]", json.join(",")), vector.len())
+        }
+    };
+
+    Ok(Some(format!(
+        "{{\"key\":\"{}\",\"type\":\"{}\",\"ttl\":{},\"size\":{},\"truncated\":{},\"value\":{}}}",
+        escape_json(key),
+        value.type_name(),
+        ttl_json(ttl),
+        size,
+        size > MAX_VALUE_ITEMS && !matches!(value, Value::Vector(_)),
+        json
+    )))
+}
+
+fn slowlog_json(store: &Store) -> String {
+    let entries = store.slowlog().get(usize::MAX).iter()
+        .map(|entry| format!(
+            "{{\"id\":{},\"timestamp\":{},\"duration_us\":{},\"client\":\"{}\",\"command\":\"{}\",\"tag\":{}}}",
+            entry.id,
+            entry.timestamp,
+            entry.duration.as_micros(),
+            escape_json(&entry.client),
+            escape_json(&entry.command),
+            entry.tag.as_ref().map_or("null".to_string(), |tag| format!("\"{}\"", escape_json(tag)))
+        ))
+        .collect::<Vec<_>>();
+    format!("[{}]", entries.join(","))
+}
+
+fn clients_json(lifecycle: &Lifecycle) -> String {
+    let clients = lifecycle.clients().iter()
+        .map(|client| format!(
+            "{{\"id\":{},\"addr\":\"{}\",\"age\":{},\"commands\":{},\"last_command\":\"{}\"}}",
+            client.id,
+            escape_json(&client.addr),
+            client.connected_at.elapsed().as_secs(),
+            client.commands,
+            escape_json(&client.last_command)
+        ))
+        .collect::<Vec<_>>();
+    format!("[{}]", clients.join(","))
+}
+
+fn ttl_json(ttl: Option<Duration>) -> String {
+    ttl.map(|ttl| ttl.as_secs().to_string()).unwrap_or_else(|| "-1".to_string())
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_scalar(value: &str) -> String {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => value.to_string(),
+        _ => format!("\"{}\"", escape_json(value)),
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'+', _) => decoded.push(b' '),
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 2;
+            }
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
@@ -0,0 +1,69 @@
+use std::collections::{HashMap, HashSet};
+
+/// Secondary index over one hash field for every key matching a pattern.
+///
+/// Entries are maintained on write by the store, but lookups are always
+/// re-verified against the live data, so entries left behind by expired
+/// keys are harmless and get pruned when they are found.
+#[derive(Clone, Debug)]
+pub struct SecondaryIndex {
+    pub pattern: String,
+    pub field: String,
+    entries: HashMap<String, HashSet<String>>,
+}
+
+impl SecondaryIndex {
+    pub fn new(pattern: &str, field: &str) -> Self {
+        SecondaryIndex {
+            pattern: pattern.to_string(),
+            field: field.to_string(),
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn covers(&self, key: &str) -> bool {
+        pattern_matches(&self.pattern, key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.entries
+            .entry(value.to_string())
+            .or_default()
+            .insert(key.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str, value: &str) {
+        if let Some(keys) = self.entries.get_mut(value) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.entries.remove(value);
+            }
+        }
+    }
+
+    pub fn lookup(&self, value: &str) -> Vec<String> {
+        self.entries
+            .get(value)
+            .map(|keys| keys.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The key patterns of `KEYS` and everything else that takes one: `*`
+/// matches everything, `user:*` matches by prefix, anything else must
+/// match exactly.
+pub fn pattern_matches(pattern: &str, key: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    if pattern.contains('*') {
+        let prefix = pattern.split('*').next().unwrap_or("");
+        key.starts_with(prefix)
+    } else {
+        key == pattern
+    }
+}
@@ -0,0 +1,450 @@
+use crate::telemetry;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::ops::Index;
+
+/// Independent tables the keyspace is split across.
+pub const SHARDS: usize = 64;
+
+// Buckets a shard starts with once it holds a key
+const MIN_BUCKETS: usize = 4;
+
+/// The store's key → value table, split across [`SHARDS`] hash tables.
+///
+/// A single `HashMap` doubles by rehashing every entry at once, and the
+/// store does that while holding its lock, so past a few million keys one
+/// unlucky write stalls every client for hundreds of milliseconds. Here
+/// each shard grows on its own when it fills up, moving only its share of
+/// the keys, so resize cost is spread over many smaller steps.
+///
+/// Each shard is a power-of-two array of buckets chaining the keys that
+/// hash to them. Unlike a `HashMap`, buckets can be addressed directly, so
+/// [`sample`](Self::sample) starts at a random bucket and walks only as far
+/// as it needs to.
+///
+/// Every entry carries a weight, its size as the map's weigh function
+/// estimates it, and [`used`](Self::used) is their running total. Writers
+/// that change a value in place call [`reweigh`](Self::reweigh) after.
+/// An [`on_change`](Self::on_change) listener hears of every change to
+/// the entries and their weights, to keep totals of its own.
+pub struct ShardedMap<V> {
+    shards: Vec<Shard<V>>,
+    selector: RandomState,
+    weigh: fn(&str, &V) -> usize,
+    used: usize,
+    on_change: Option<Listener>,
+}
+
+/// Called with a key, the change in the number of entries (-1, 0 or 1)
+/// and the change in weight.
+pub type Listener = Box<dyn Fn(&str, isize, isize) + Send>;
+
+// Move the running total from `old` to `new` and tell the listener
+fn account(used: &mut usize, on_change: &Option<Listener>, key: &str, entries: isize, old: usize, new: usize) {
+    *used = *used - old + new;
+    if let Some(listener) = on_change {
+        listener(key, entries, new as isize - old as isize);
+    }
+}
+
+struct Slot<V> {
+    hash: u64,
+    key: String,
+    value: V,
+    weight: usize,
+}
+
+struct Shard<V> {
+    buckets: Vec<Vec<Slot<V>>>,
+    len: usize,
+}
+
+impl<V> Shard<V> {
+    fn bucket_of(&self, hash: u64) -> usize {
+        (hash / SHARDS as u64) as usize & (self.buckets.len() - 1)
+    }
+
+    fn position(&self, hash: u64, key: &str) -> Option<(usize, usize)> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket_of(hash);
+        let index = self.buckets[bucket].iter().position(|slot| slot.hash == hash && slot.key == key)?;
+        Some((bucket, index))
+    }
+
+    // Rehash into `size` buckets: doubling once there are as many keys as
+    // buckets, shrinking to half full once they are under an eighth full
+    fn resize(&mut self, size: usize) {
+        let old = std::mem::replace(&mut self.buckets, (0..size).map(|_| Vec::new()).collect());
+        for slot in old.into_iter().flatten() {
+            let bucket = self.bucket_of(slot.hash);
+            self.buckets[bucket].push(slot);
+        }
+    }
+
+    fn grow_if_full(&mut self) {
+        if self.buckets.is_empty() {
+            self.resize(MIN_BUCKETS);
+        } else if self.len >= self.buckets.len() {
+            self.resize(self.buckets.len() * 2);
+        }
+    }
+
+    fn shrink_if_sparse(&mut self) {
+        if self.len == 0 {
+            self.buckets = Vec::new();
+        } else if self.buckets.len() > MIN_BUCKETS && self.len < self.buckets.len() / 8 {
+            self.resize((self.len * 2).next_power_of_two().max(MIN_BUCKETS));
+        }
+    }
+
+    fn remove(&mut self, bucket: usize, index: usize) -> Slot<V> {
+        let slot = self.buckets[bucket].swap_remove(index);
+        self.len -= 1;
+        self.shrink_if_sparse();
+        slot
+    }
+}
+
+impl<V> Default for ShardedMap<V> {
+    fn default() -> Self {
+        Self::weighed(|_, _| 0)
+    }
+}
+
+impl<V> ShardedMap<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A map whose entries weigh what `weigh` says they do.
+    pub fn weighed(weigh: fn(&str, &V) -> usize) -> Self {
+        ShardedMap {
+            shards: (0..SHARDS).map(|_| Shard { buckets: Vec::new(), len: 0 }).collect(),
+            selector: RandomState::new(),
+            weigh,
+            used: 0,
+            on_change: None,
+        }
+    }
+
+    /// Have `listener` told of every insert, removal and reweigh.
+    pub fn on_change(&mut self, listener: impl Fn(&str, isize, isize) + Send + 'static) {
+        self.on_change = Some(Box::new(listener));
+    }
+
+    // Low bits pick the shard, the bits above them the bucket
+    fn hash(&self, key: &str) -> u64 {
+        self.selector.hash_one(key)
+    }
+
+    fn shard_of(hash: u64) -> usize {
+        (hash % SHARDS as u64) as usize
+    }
+
+    fn find(&self, key: &str) -> Option<&Slot<V>> {
+        let hash = self.hash(key);
+        let shard = &self.shards[Self::shard_of(hash)];
+        shard.position(hash, key).map(|(bucket, index)| &shard.buckets[bucket][index])
+    }
+
+    fn find_mut(&mut self, key: &str) -> Option<&mut Slot<V>> {
+        let hash = self.hash(key);
+        let shard = &mut self.shards[Self::shard_of(hash)];
+        shard.position(hash, key).map(|(bucket, index)| &mut shard.buckets[bucket][index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.len == 0)
+    }
+
+    /// Total weight of every entry.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.find(key).map(|slot| &slot.value)
+    }
+
+    /// A value to change in place; call `reweigh` once done.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        self.find_mut(key).map(|slot| &mut slot.value)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.find(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: String, value: V) -> Option<V> {
+        let weight = (self.weigh)(&key, &value);
+        if let Some(slot) = self.find_mut(&key) {
+            let old_weight = std::mem::replace(&mut slot.weight, weight);
+            let old = std::mem::replace(&mut slot.value, value);
+            account(&mut self.used, &self.on_change, &key, 0, old_weight, weight);
+            return Some(old);
+        }
+
+        account(&mut self.used, &self.on_change, &key, 1, 0, weight);
+        let hash = self.hash(&key);
+        let shard = &mut self.shards[Self::shard_of(hash)];
+        shard.grow_if_full();
+        let bucket = shard.bucket_of(hash);
+        shard.buckets[bucket].push(Slot { hash, key, value, weight });
+        shard.len += 1;
+        None
+    }
+
+    /// The value at `key`, inserting `default()` first if there is none.
+    /// Call `reweigh` once done changing it.
+    pub fn get_or_insert_with(&mut self, key: &str, default: impl FnOnce() -> V) -> &mut V {
+        if !self.contains_key(key) {
+            self.insert(key.to_string(), default());
+        }
+        self.get_mut(key).expect("key was just inserted")
+    }
+
+    /// Weigh `key` again after its value was changed in place.
+    pub fn reweigh(&mut self, key: &str) {
+        let weigh = self.weigh;
+        let hash = self.hash(key);
+        let shard = &mut self.shards[Self::shard_of(hash)];
+        if let Some((bucket, index)) = shard.position(hash, key) {
+            let slot = &mut shard.buckets[bucket][index];
+            let weight = weigh(&slot.key, &slot.value);
+            let old_weight = std::mem::replace(&mut slot.weight, weight);
+            if weight != old_weight {
+                account(&mut self.used, &self.on_change, key, 0, old_weight, weight);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry(&mut self, key: &str) -> Option<(String, V)> {
+        let hash = self.hash(key);
+        let shard = &mut self.shards[Self::shard_of(hash)];
+        let (bucket, index) = shard.position(hash, key)?;
+        let slot = shard.remove(bucket, index);
+        account(&mut self.used, &self.on_change, &slot.key, -1, slot.weight, 0);
+        Some((slot.key, slot.value))
+    }
+
+    pub fn retain<F: FnMut(&String, &mut V) -> bool>(&mut self, mut keep: F) {
+        for shard in &mut self.shards {
+            let before = shard.len;
+            for bucket in &mut shard.buckets {
+                bucket.retain_mut(|slot| {
+                    let kept = keep(&slot.key, &mut slot.value);
+                    if !kept {
+                        account(&mut self.used, &self.on_change, &slot.key, -1, slot.weight, 0);
+                        shard.len -= 1;
+                    }
+                    kept
+                });
+            }
+            if shard.len < before {
+                shard.shrink_if_sparse();
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for shard in &mut self.shards {
+            let buckets = std::mem::take(&mut shard.buckets);
+            if self.on_change.is_some() {
+                for slot in buckets.iter().flatten() {
+                    account(&mut self.used, &self.on_change, &slot.key, -1, slot.weight, 0);
+                }
+            }
+            shard.len = 0;
+        }
+        self.used = 0;
+    }
+
+    /// Up to `n` entries that `accept` takes, walking the buckets from a
+    /// random one onwards and stopping as soon as `n` are found. The walk
+    /// only covers the whole map when fewer than `n` entries are accepted.
+    pub fn sample(&self, n: usize, mut accept: impl FnMut(&String, &V) -> bool) -> Vec<(&String, &V)> {
+        let mut sample = Vec::new();
+        if n == 0 {
+            return sample;
+        }
+        let start = telemetry::random_u64();
+        for offset in 0..SHARDS {
+            let shard = &self.shards[(Self::shard_of(start) + offset) % SHARDS];
+            let size = shard.buckets.len();
+            let first = (start / SHARDS as u64) as usize % size.max(1);
+            for bucket in (first..size).chain(0..first) {
+                for slot in &shard.buckets[bucket] {
+                    if accept(&slot.key, &slot.value) {
+                        sample.push((&slot.key, &slot.value));
+                        if sample.len() == n {
+                            return sample;
+                        }
+                    }
+                }
+            }
+        }
+        sample
+    }
+
+    /// One step of a scan: visits whole buckets from `cursor` on, about
+    /// `count` entries' worth, and returns the cursor to continue from, 0
+    /// once every shard is done. The cursor holds the shard in its low bits
+    /// and the bucket above them. Buckets are walked in reverse-binary
+    /// order, as Redis does, so an entry present for the whole scan is
+    /// visited at least once even if its shard grows or shrinks between
+    /// steps. A step also ends after `10 * count` empty buckets, so a
+    /// sparse shard cannot make one step long.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&String, &V)) -> u64 {
+        let mut shard = Self::shard_of(cursor);
+        let mut bucket = cursor / SHARDS as u64;
+        let (mut visited, mut empty) = (0, 0);
+        loop {
+            let buckets = &self.shards[shard].buckets;
+            if buckets.is_empty() {
+                bucket = 0;
+            } else {
+                let mask = buckets.len() as u64 - 1;
+                let slots = &buckets[(bucket & mask) as usize];
+                if visited > 0 && visited + slots.len() > count {
+                    return bucket * SHARDS as u64 + shard as u64;
+                }
+                slots.iter().for_each(|slot| visit(&slot.key, &slot.value));
+                visited += slots.len();
+                empty += usize::from(slots.is_empty());
+                // Increment the bits under the mask from the top down
+                bucket = (bucket | !mask).reverse_bits().wrapping_add(1).reverse_bits();
+            }
+            if bucket == 0 {
+                shard += 1;
+                if shard == SHARDS {
+                    return 0;
+                }
+            }
+            if visited >= count || empty >= count.saturating_mul(10) {
+                return bucket * SHARDS as u64 + shard as u64;
+            }
+        }
+    }
+
+    fn slots(&self) -> impl Iterator<Item = &Slot<V>> + Clone {
+        self.shards.iter().flat_map(|shard| shard.buckets.iter().flatten())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> + Clone {
+        self.slots().map(|slot| (&slot.key, &slot.value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> + Clone {
+        self.slots().map(|slot| &slot.key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> + Clone {
+        self.slots().map(|slot| &slot.value)
+    }
+}
+
+impl<V> Index<&str> for ShardedMap<V> {
+    type Output = V;
+
+    fn index(&self, key: &str) -> &V {
+        self.get(key).expect("key not in keyspace")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded_map() {
+        let mut map = ShardedMap::new();
+        assert!(map.is_empty());
+        for i in 0..1000 {
+            assert_eq!(map.insert(format!("key:{}", i), i), None);
+        }
+        assert_eq!(map.insert("key:7".to_string(), 70), Some(7));
+        assert_eq!(map.len(), 1000);
+        assert_eq!(map["key:7"], 70);
+        assert_eq!(map.get("missing"), None);
+
+        *map.get_or_insert_with("key:8", || 0) += 1;
+        *map.get_or_insert_with("new", || 5) += 1;
+        *map.get_mut("key:9").unwrap() *= 2;
+        assert_eq!((map["key:8"], map["key:9"], map["new"]), (9, 18, 6));
+        assert_eq!(map.remove_entry("key:0"), Some(("key:0".to_string(), 0)));
+        assert_eq!(map.remove("new"), Some(6));
+
+        // Keys spread over every shard
+        assert!(map.shards.iter().all(|shard| shard.len > 0));
+        map.retain(|_, value| *value % 2 == 0);
+        assert!(map.values().all(|value| value % 2 == 0));
+        assert_eq!(map.iter().count(), map.len());
+        assert_eq!(map.keys().count(), map.len());
+
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_weights_and_sampling() {
+        let mut map = ShardedMap::weighed(|key, value: &String| key.len() + value.len());
+        let totals = std::sync::Arc::new(std::sync::Mutex::new((0, 0)));
+        let listened = totals.clone();
+        map.on_change(move |_, entries, weight| {
+            let mut totals = listened.lock().unwrap();
+            *totals = (totals.0 + entries, totals.1 + weight);
+        });
+        for i in 0..500 {
+            map.insert(format!("k{:03}", i), "x".repeat(i % 10));
+        }
+        let total = |map: &ShardedMap<String>| map.iter().map(|(key, value)| key.len() + value.len()).sum::<usize>();
+        assert_eq!(map.used(), total(&map));
+
+        map.get_mut("k001").unwrap().push_str("grown");
+        map.reweigh("k001");
+        map.insert("k002".to_string(), String::new());
+        map.remove("k003");
+        map.retain(|key, _| !key.ends_with('9'));
+        assert_eq!(map.used(), total(&map));
+        // The listener's totals follow the map's
+        assert_eq!(*totals.lock().unwrap(), (map.len() as isize, map.used() as isize));
+
+        let sample = map.sample(5, |_, value| !value.is_empty());
+        assert_eq!(sample.len(), 5);
+        assert!(sample.iter().all(|(_, value)| !value.is_empty()));
+        // Fewer matches than asked for returns every one of them
+        assert_eq!(map.sample(100, |key, _| key.starts_with("k00")).len(), 8);
+        assert!(map.sample(0, |_, _| true).is_empty());
+
+        // A scan sees every key once however small its steps
+        let mut cursor = 0;
+        let mut seen = Vec::new();
+        loop {
+            cursor = map.scan(cursor, 3, |key, _| seen.push(key.clone()));
+            if cursor == 0 {
+                break;
+            }
+        }
+        let mut keys: Vec<String> = map.keys().cloned().collect();
+        keys.sort();
+        seen.sort();
+        assert_eq!(seen, keys);
+
+        // Shards shrink as they empty, and the weights go with them
+        map.retain(|key, _| key.ends_with('0'));
+        assert!(map.shards.iter().all(|shard| shard.buckets.len() <= MIN_BUCKETS || shard.len * 8 >= shard.buckets.len()));
+        assert_eq!(map.used(), total(&map));
+        map.clear();
+        assert_eq!((map.used(), *totals.lock().unwrap()), (0, (0, 0)));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::command_table::{self, CommandKind};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Upper bucket bounds in microseconds, from 100µs to 1s.
+pub const DEFAULT_BUCKETS_MICROS: &[u64] = &[100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 1_000_000];
+
+/// Commands on hashes, lists, delayed queues, time series, vectors and
+/// search documents, as opposed to plain string keys.
+const COLLECTION_COMMANDS: &[&str] = &[
+    "HSET", "HGET", "HSETBIN", "HGETBIN", "HGETALL", "HDEL", "HEXISTS", "HLEN", "LPUSH", "RPUSH", "LPOP", "RPOP", "LLEN", "LRANGE",
+    "SADD", "SREM", "SMEMBERS", "SCARD", "SISMEMBER", "ZADD", "ZSCORE", "ZRANK", "ZRANGE", "ZCARD",
+    "ZRANGEBYSCORE", "ZREMRANGEBYSCORE", "ZINCRBY", "ZPOPMIN", "ZPOPMAX", "XADD", "XLEN", "XRANGE", "XTRIM", "XACK", "XPENDING", "XAUTOCLAIM",
+    "ZADDDELAY", "DELAYED", "TS.CREATE", "TS.ADD", "TS.GET", "TS.RANGE", "VADD", "VGET", "VSEARCH", "FIND", "FT.ADD", "FT.SEARCH",
+];
+
+/// Commands grouped by what they cost, so read and write SLOs can be
+/// tracked apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandFamily {
+    /// String and keyspace reads: GET, EXISTS, TTL, KEYS...
+    Get,
+    /// String and keyspace writes: SET, DELETE, EXPIRE...
+    Set,
+    CollectionRead,
+    CollectionWrite,
+    Blocking,
+    /// Server and connection commands, and anything unknown.
+    Admin,
+}
+
+pub const FAMILIES: [CommandFamily; 6] = [
+    CommandFamily::Get,
+    CommandFamily::Set,
+    CommandFamily::CollectionRead,
+    CommandFamily::CollectionWrite,
+    CommandFamily::Blocking,
+    CommandFamily::Admin,
+];
+
+impl CommandFamily {
+    pub fn of(args: &[&str]) -> Self {
+        let Some(spec) = command_table::find(args) else { return CommandFamily::Admin };
+        let collection = COLLECTION_COMMANDS.contains(&spec.name);
+        match (spec.kind, collection) {
+            (CommandKind::Read, false) => CommandFamily::Get,
+            (CommandKind::Read, true) => CommandFamily::CollectionRead,
+            (CommandKind::Write, false) => CommandFamily::Set,
+            (CommandKind::Write, true) => CommandFamily::CollectionWrite,
+            (CommandKind::Blocking, _) => CommandFamily::Blocking,
+            (CommandKind::Admin, _) => CommandFamily::Admin,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandFamily::Get => "get",
+            CommandFamily::Set => "set",
+            CommandFamily::CollectionRead => "collection_read",
+            CommandFamily::CollectionWrite => "collection_write",
+            CommandFamily::Blocking => "blocking",
+            CommandFamily::Admin => "admin",
+        }
+    }
+
+    fn index(self) -> usize {
+        FAMILIES.iter().position(|family| *family == self).unwrap_or(0)
+    }
+}
+
+/// Parse `MEDUSA_LATENCY_BUCKETS`: upper bounds in microseconds, such as
+/// `500,1000,5000`. They are sorted and deduplicated.
+pub fn parse_buckets(spec: &str) -> Result<Vec<u64>, String> {
+    let mut bounds = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|bound| !bound.is_empty())
+        .map(|bound| match bound.parse::<u64>() {
+            Ok(micros) if micros > 0 => Ok(micros),
+            _ => Err(format!("Invalid bucket '{}', expected microseconds", bound)),
+        })
+        .collect::<Result<Vec<u64>, String>>()?;
+    if bounds.is_empty() {
+        return Err("No buckets given".to_string());
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+    Ok(bounds)
+}
+
+struct Histogram {
+    // One count per bound plus the +Inf bucket, not cumulative
+    buckets: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: usize) -> Self {
+        Histogram { buckets: (0..=bounds).map(|_| AtomicU64::new(0)).collect(), sum_micros: AtomicU64::new(0) }
+    }
+}
+
+struct Histograms {
+    bounds: Vec<u64>,
+    families: Vec<Histogram>,
+}
+
+impl Histograms {
+    fn new(bounds: Vec<u64>) -> Self {
+        let families = FAMILIES.iter().map(|_| Histogram::new(bounds.len())).collect();
+        Histograms { bounds, families }
+    }
+}
+
+/// Command latency histograms per `CommandFamily`, exported in the
+/// Prometheus text format at `/metrics`. Recording is a few atomic adds.
+#[derive(Clone)]
+pub struct LatencyHistograms {
+    inner: Arc<RwLock<Histograms>>,
+}
+
+impl Default for LatencyHistograms {
+    fn default() -> Self {
+        LatencyHistograms { inner: Arc::new(RwLock::new(Histograms::new(DEFAULT_BUCKETS_MICROS.to_vec()))) }
+    }
+}
+
+impl LatencyHistograms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the bucket bounds (microseconds, ascending). Counts so far
+    /// are dropped, since they no longer fit the buckets.
+    pub fn set_buckets(&self, bounds: Vec<u64>) {
+        if let Ok(mut histograms) = self.inner.write() {
+            *histograms = Histograms::new(bounds);
+        }
+    }
+
+    pub fn buckets(&self) -> Vec<u64> {
+        self.inner.read().map(|histograms| histograms.bounds.clone()).unwrap_or_default()
+    }
+
+    pub fn record(&self, family: CommandFamily, duration: Duration) {
+        let Ok(histograms) = self.inner.read() else { return };
+        let micros = duration.as_micros() as u64;
+        let bucket = histograms.bounds.partition_point(|bound| *bound < micros);
+        let histogram = &histograms.families[family.index()];
+        histogram.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        histogram.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Commands recorded for a family.
+    pub fn count(&self, family: CommandFamily) -> u64 {
+        self.inner
+            .read()
+            .map(|histograms| histograms.families[family.index()].buckets.iter().map(|count| count.load(Ordering::Relaxed)).sum())
+            .unwrap_or(0)
+    }
+
+    /// `medusa_command_duration_seconds` in the Prometheus text format.
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP medusa_command_duration_seconds Time to run a command, by command family.\n");
+        out.push_str("# TYPE medusa_command_duration_seconds histogram\n");
+        let Ok(histograms) = self.inner.read() else { return out };
+
+        for family in FAMILIES {
+            let histogram = &histograms.families[family.index()];
+            let mut cumulative = 0;
+            for (i, count) in histogram.buckets.iter().enumerate() {
+                cumulative += count.load(Ordering::Relaxed);
+                let le = match histograms.bounds.get(i) {
+                    Some(bound) => format_seconds(*bound),
+                    None => "+Inf".to_string(),
+                };
+                let _ = writeln!(out, "medusa_command_duration_seconds_bucket{{family=\"{}\",le=\"{}\"}} {}", family.name(), le, cumulative);
+            }
+            let sum = histogram.sum_micros.load(Ordering::Relaxed);
+            let _ = writeln!(out, "medusa_command_duration_seconds_sum{{family=\"{}\"}} {}", family.name(), format_seconds(sum));
+            let _ = writeln!(out, "medusa_command_duration_seconds_count{{family=\"{}\"}} {}", family.name(), cumulative);
+        }
+        out
+    }
+}
+
+// Microseconds as decimal seconds without float rounding: 2500 -> 0.0025
+fn format_seconds(micros: u64) -> String {
+    let fraction = format!("{:06}", micros % 1_000_000);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}", micros / 1_000_000)
+    } else {
+        format!("{}.{}", micros / 1_000_000, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_families() {
+        assert_eq!(CommandFamily::of(&["get", "k"]), CommandFamily::Get);
+        assert_eq!(CommandFamily::of(&["SET", "k", "v"]), CommandFamily::Set);
+        assert_eq!(CommandFamily::of(&["HGETALL", "h"]), CommandFamily::CollectionRead);
+        assert_eq!(CommandFamily::of(&["RPUSH", "l", "a"]), CommandFamily::CollectionWrite);
+        assert_eq!(CommandFamily::of(&["WAITKEY", "k", "0"]), CommandFamily::Blocking);
+        assert_eq!(CommandFamily::of(&["CLIENT", "LIST"]), CommandFamily::Admin);
+        assert_eq!(CommandFamily::of(&["NOSUCH"]), CommandFamily::Admin);
+    }
+
+    #[test]
+    fn test_histogram_export() {
+        let histograms = LatencyHistograms::new();
+        histograms.set_buckets(parse_buckets("1000, 100,1000,5000").unwrap());
+        assert_eq!(histograms.buckets(), [100, 1000, 5000]);
+        histograms.record(CommandFamily::Get, Duration::from_micros(100));
+        histograms.record(CommandFamily::Get, Duration::from_micros(2500));
+        histograms.record(CommandFamily::Get, Duration::from_secs(2));
+        assert_eq!(histograms.count(CommandFamily::Get), 3);
+
+        let text = histograms.prometheus();
+        assert!(text.contains("medusa_command_duration_seconds_bucket{family=\"get\",le=\"0.0001\"} 1\n"));
+        assert!(text.contains("medusa_command_duration_seconds_bucket{family=\"get\",le=\"0.005\"} 2\n"));
+        assert!(text.contains("medusa_command_duration_seconds_bucket{family=\"get\",le=\"+Inf\"} 3\n"));
+        assert!(text.contains("medusa_command_duration_seconds_sum{family=\"get\"} 2.0026\n"));
+        assert!(text.contains("medusa_command_duration_seconds_count{family=\"set\"} 0\n"));
+
+        assert!(parse_buckets("10,soon").is_err());
+        assert!(parse_buckets("0").is_err());
+        assert!(parse_buckets("").is_err());
+    }
+}
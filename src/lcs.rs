@@ -0,0 +1,102 @@
+/// The dynamic programming table grows with the product of both lengths,
+/// so refuse inputs that would need more cells than this.
+pub const MAX_CELLS: usize = 16 * 1024 * 1024;
+
+/// A run of characters shared by both strings, as inclusive character
+/// ranges into each.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LcsMatch {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+    pub len: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LcsResult {
+    pub sequence: String,
+    /// Matching runs, last to first like Redis reports them.
+    pub matches: Vec<LcsMatch>,
+}
+
+/// Longest common subsequence of `a` and `b`, compared by character.
+pub fn longest_common_subsequence(a: &str, b: &str) -> Result<LcsResult, String> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let width = b.len() + 1;
+    let cells = (a.len() + 1).saturating_mul(width);
+    if cells > MAX_CELLS {
+        return Err(format!("Strings too long for LCS ({} x {} characters)", a.len(), b.len()));
+    }
+
+    // table[i * width + j] = LCS length of a[..i] and b[..j]
+    let mut table = vec![0u32; cells];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    // Walk back from the end, collecting matched positions
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        if a[i - 1] == b[j - 1] {
+            pairs.push((i - 1, j - 1));
+            i -= 1;
+            j -= 1;
+        } else if table[(i - 1) * width + j] > table[i * width + j - 1] {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+
+    let sequence = pairs.iter().rev().map(|&(i, _)| a[i]).collect();
+
+    // Consecutive positions in both strings form one run
+    let mut matches: Vec<LcsMatch> = Vec::new();
+    for (i, j) in pairs {
+        match matches.last_mut() {
+            Some(run) if run.a.0 == i + 1 && run.b.0 == j + 1 => {
+                run.a.0 = i;
+                run.b.0 = j;
+                run.len += 1;
+            }
+            _ => matches.push(LcsMatch { a: (i, i), b: (j, j), len: 1 }),
+        }
+    }
+
+    Ok(LcsResult { sequence, matches })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcs_matches() {
+        let result = longest_common_subsequence("ohmytext", "mynewtext").unwrap();
+        assert_eq!(result.sequence, "mytext");
+        assert_eq!(result.matches, vec![
+            LcsMatch { a: (4, 7), b: (5, 8), len: 4 },
+            LcsMatch { a: (2, 3), b: (0, 1), len: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_lcs_edge_cases() {
+        let empty = longest_common_subsequence("", "abc").unwrap();
+        assert_eq!(empty.sequence, "");
+        assert!(empty.matches.is_empty());
+
+        assert_eq!(longest_common_subsequence("abc", "xyz").unwrap().sequence, "");
+        assert_eq!(longest_common_subsequence("héllo", "hällo").unwrap().sequence, "hllo");
+
+        let long = "a".repeat(5000);
+        assert!(longest_common_subsequence(&long, &long).is_err());
+    }
+}
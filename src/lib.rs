@@ -1,4 +1,49 @@
 pub mod store;
 pub mod config;
 pub mod server;
-pub mod client_handler;
\ No newline at end of file
+pub mod client_handler;
+pub mod timeseries;
+pub mod index;
+pub mod search;
+pub mod vector;
+pub mod telemetry;
+pub mod lifecycle;
+pub mod snapshot;
+pub mod handoff;
+pub mod tenant;
+pub mod rdb;
+pub mod migrate;
+pub mod chaos;
+pub mod slowlog;
+pub mod http;
+pub mod command;
+pub mod command_table;
+pub mod schedule;
+pub mod alarm;
+pub mod alias;
+pub mod lcs;
+pub mod delayed;
+pub mod stats;
+pub mod proxy;
+pub mod net;
+pub mod client;
+pub mod seed;
+pub mod daemon;
+pub mod fairness;
+pub mod history;
+pub mod eviction;
+pub mod benchmark;
+pub mod testing;
+pub mod base64;
+pub mod protocol;
+pub mod keyspace;
+pub mod latency;
+pub mod resp;
+pub mod reply;
+pub mod replication;
+pub mod sentinel;
+pub mod crdt;
+pub mod extension;
+pub mod pubsub;
+pub mod zset;
+pub mod stream;
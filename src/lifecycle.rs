@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A connected client as shown by `CLIENT LIST` and the dashboard.
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: String,
+    /// Server address the client connected to, empty until a stream is attached.
+    pub laddr: String,
+    pub connected_at: Instant,
+    /// When the client last sent a command (or connected, before its first).
+    pub last_active: Instant,
+    pub commands: u64,
+    pub last_command: String,
+    /// Set by `CLIENT NO-EVICT ON`: never disconnected to reclaim memory.
+    pub no_evict: bool,
+    /// Set by `CLIENT NO-TOUCH ON`: commands leave key access times alone.
+    pub no_touch: bool,
+    /// Set by `CLIENT EPHEMERAL ON`: keys it creates go when it disconnects.
+    pub ephemeral: bool,
+    /// Subscribed to at least one pub/sub channel.
+    pub subscriber: bool,
+    /// A replica this server streams its writes to, after the replica's `SYNC`.
+    pub replica: bool,
+    /// This replica's link to its primary.
+    pub primary_link: bool,
+}
+
+/// How `CLIENT UNBLOCK` ends a blocking command: as if it timed out, or
+/// with an error.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnblockMode {
+    Timeout,
+    Error,
+}
+
+/// Operator requests aimed at one connection by `CLIENT KILL` and
+/// `CLIENT UNBLOCK`, checked by its handler.
+#[derive(Debug, Default)]
+pub struct ClientControl {
+    killed: AtomicBool,
+    blocked: AtomicBool,
+    unblock: Mutex<Option<UnblockMode>>,
+    stream: Mutex<Option<TcpStream>>,
+}
+
+impl ClientControl {
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        self.blocked.load(Ordering::SeqCst)
+    }
+
+    /// Mark the connection as inside (or out of) a blocking command.
+    pub fn set_blocked(&self, blocked: bool) {
+        self.blocked.store(blocked, Ordering::SeqCst);
+        if !blocked {
+            if let Ok(mut unblock) = self.unblock.lock() {
+                *unblock = None;
+            }
+        }
+    }
+
+    /// The pending unblock request, if any.
+    pub fn unblock_mode(&self) -> Option<UnblockMode> {
+        self.unblock.lock().ok().and_then(|unblock| *unblock)
+    }
+
+    /// Whether a blocking command should give up now.
+    pub fn interrupted(&self) -> bool {
+        self.is_killed() || self.unblock_mode().is_some()
+    }
+
+    fn kill(&self) {
+        self.killed.store(true, Ordering::SeqCst);
+        // Ends a pending read; the handler still gets to write its reply
+        if let Ok(stream) = self.stream.lock() {
+            if let Some(stream) = stream.as_ref() {
+                let _ = stream.shutdown(Shutdown::Read);
+            }
+        }
+    }
+}
+
+/// Server-wide lifecycle state shared by the accept loop and every
+/// connection handler: active connection count, connected clients, drain
+/// status and the listening socket (for handing it to a replacement process).
+#[derive(Clone, Default)]
+pub struct Lifecycle {
+    inner: Arc<LifecycleInner>,
+}
+
+struct LifecycleInner {
+    drain_deadline: Mutex<Option<Instant>>,
+    active_connections: AtomicUsize,
+    listener_fd: AtomicI64,
+    handed_off: AtomicBool,
+    handoff_in_memory: AtomicBool,
+    clients: Mutex<HashMap<u64, ClientInfo>>,
+    controls: Mutex<HashMap<u64, Arc<ClientControl>>>,
+    next_client_id: AtomicU64,
+    total_commands: AtomicU64,
+}
+
+impl Default for LifecycleInner {
+    fn default() -> Self {
+        LifecycleInner {
+            drain_deadline: Mutex::new(None),
+            active_connections: AtomicUsize::new(0),
+            listener_fd: AtomicI64::new(-1),
+            handed_off: AtomicBool::new(false),
+            handoff_in_memory: AtomicBool::new(false),
+            clients: Mutex::new(HashMap::new()),
+            controls: Mutex::new(HashMap::new()),
+            next_client_id: AtomicU64::new(1),
+            total_commands: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_listener_fd(&self, fd: i32) {
+        self.inner.listener_fd.store(fd as i64, Ordering::SeqCst);
+    }
+
+    pub fn listener_fd(&self) -> Option<i32> {
+        match self.inner.listener_fd.load(Ordering::SeqCst) {
+            fd if fd >= 0 => Some(fd as i32),
+            _ => None,
+        }
+    }
+
+    /// Hand the dataset to a replacement process through a memfd instead of
+    /// a temporary file (experimental, Linux only).
+    pub fn set_handoff_in_memory(&self, enabled: bool) {
+        self.inner.handoff_in_memory.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn handoff_in_memory(&self) -> bool {
+        self.inner.handoff_in_memory.load(Ordering::SeqCst)
+    }
+
+    /// The listener now belongs to another process: stop accepting and
+    /// drain existing clients.
+    pub fn mark_handed_off(&self, grace: Duration) {
+        self.inner.handed_off.store(true, Ordering::SeqCst);
+        self.start_drain(grace);
+    }
+
+    pub fn is_handed_off(&self) -> bool {
+        self.inner.handed_off.load(Ordering::SeqCst)
+    }
+
+    /// Stop accepting new connections and give existing clients `grace` to
+    /// finish. Returns false if a drain is already in progress.
+    pub fn start_drain(&self, grace: Duration) -> bool {
+        let new_deadline = Instant::now() + grace;
+        match self.inner.drain_deadline.lock() {
+            Ok(mut deadline) => {
+                if deadline.is_some() {
+                    return false;
+                }
+                *deadline = Some(new_deadline);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.drain_deadline().is_some()
+    }
+
+    /// Time left before draining clients are disconnected.
+    pub fn drain_remaining(&self) -> Option<Duration> {
+        self.drain_deadline()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn drain_expired(&self) -> bool {
+        self.drain_deadline().is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// True once a drain has started and either every client has left or
+    /// the grace period is over.
+    pub fn should_stop(&self) -> bool {
+        self.is_draining() && (self.active_connections() == 0 || self.drain_expired())
+    }
+
+    pub fn connection_opened(&self) {
+        self.inner.active_connections.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Count a new connection unless `limit` are already open. The check
+    /// and the increment are one step, so listeners cannot overshoot it.
+    pub fn try_connection_opened(&self, limit: usize) -> bool {
+        self.inner
+            .active_connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| (active < limit).then_some(active + 1))
+            .is_ok()
+    }
+
+    pub fn connection_closed(&self) {
+        self.inner.active_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.inner.active_connections.load(Ordering::SeqCst)
+    }
+
+    /// Track a newly connected client, returning its id.
+    pub fn register_client(&self, addr: &str) -> u64 {
+        let id = self.inner.next_client_id.fetch_add(1, Ordering::SeqCst);
+        if let Ok(mut clients) = self.inner.clients.lock() {
+            clients.insert(id, ClientInfo {
+                id,
+                addr: addr.to_string(),
+                laddr: String::new(),
+                connected_at: Instant::now(),
+                last_active: Instant::now(),
+                commands: 0,
+                last_command: String::new(),
+                no_evict: false,
+                no_touch: false,
+                ephemeral: false,
+                subscriber: false,
+                replica: false,
+                primary_link: false,
+            });
+        }
+        id
+    }
+
+    pub fn client_command(&self, id: u64, command: &str) {
+        self.inner.total_commands.fetch_add(1, Ordering::Relaxed);
+        self.update_client(id, |client| {
+            client.commands += 1;
+            client.last_active = Instant::now();
+            // Reuse the previous name's allocation; this runs for every command
+            client.last_command.clear();
+            client.last_command.extend(command.chars().flat_map(char::to_uppercase));
+        });
+    }
+
+    /// Give `CLIENT KILL` a handle on the client's socket.
+    pub fn attach_stream(&self, id: u64, stream: TcpStream) {
+        let laddr = stream.local_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        self.update_client(id, |client| client.laddr = laddr);
+        if let Some(control) = self.control(id) {
+            if let Ok(mut slot) = control.stream.lock() {
+                *slot = Some(stream);
+            }
+        }
+    }
+
+    pub fn control(&self, id: u64) -> Option<Arc<ClientControl>> {
+        let mut controls = self.inner.controls.lock().ok()?;
+        if !self.inner.clients.lock().ok()?.contains_key(&id) {
+            return None;
+        }
+        Some(controls.entry(id).or_default().clone())
+    }
+
+    /// Disconnect every client `matches` accepts, returning how many. A
+    /// client inside a blocking command is interrupted too, once its waiters
+    /// are woken.
+    pub fn kill_clients<F: Fn(&ClientInfo) -> bool>(&self, matches: F) -> usize {
+        let ids: Vec<u64> = self.clients().iter().filter(|client| matches(client)).map(|client| client.id).collect();
+        for id in &ids {
+            if let Some(control) = self.control(*id) {
+                control.kill();
+            }
+        }
+        ids.len()
+    }
+
+    /// Ask a client blocked in a command to stop waiting. Returns false if
+    /// it is not blocked.
+    pub fn unblock_client(&self, id: u64, mode: UnblockMode) -> bool {
+        match self.control(id) {
+            Some(control) if control.is_blocked() => {
+                if let Ok(mut unblock) = control.unblock.lock() {
+                    *unblock = Some(mode);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn set_no_evict(&self, id: u64, enabled: bool) {
+        self.update_client(id, |client| client.no_evict = enabled);
+    }
+
+    pub fn set_no_touch(&self, id: u64, enabled: bool) {
+        self.update_client(id, |client| client.no_touch = enabled);
+    }
+
+    pub fn set_ephemeral(&self, id: u64, enabled: bool) {
+        self.update_client(id, |client| client.ephemeral = enabled);
+    }
+
+    pub fn set_subscriber(&self, id: u64, subscriber: bool) {
+        self.update_client(id, |client| client.subscriber = subscriber);
+    }
+
+    /// Track a replication connection on `stream` as a client, so `CLIENT
+    /// LIST` shows it and `CLIENT KILL TYPE replica|master` can end it.
+    /// `primary_link` is true for a replica's link to its primary, false
+    /// for a primary's link to a replica.
+    pub fn register_link(&self, addr: &str, stream: &TcpStream, primary_link: bool) -> (u64, Arc<ClientControl>) {
+        let id = self.register_client(addr);
+        if let Ok(stream) = stream.try_clone() {
+            self.attach_stream(id, stream);
+        }
+        self.update_client(id, |client| {
+            client.replica = !primary_link;
+            client.primary_link = primary_link;
+        });
+        (id, self.control(id).unwrap_or_default())
+    }
+
+    fn update_client<F: FnOnce(&mut ClientInfo)>(&self, id: u64, update: F) {
+        if let Ok(mut clients) = self.inner.clients.lock() {
+            if let Some(client) = clients.get_mut(&id) {
+                update(client);
+            }
+        }
+    }
+
+    pub fn unregister_client(&self, id: u64) {
+        if let Ok(mut controls) = self.inner.controls.lock() {
+            controls.remove(&id);
+        }
+        if let Ok(mut clients) = self.inner.clients.lock() {
+            clients.remove(&id);
+        }
+    }
+
+    /// Connected clients, oldest first.
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        let mut clients: Vec<ClientInfo> = self.inner.clients.lock()
+            .map(|clients| clients.values().cloned().collect())
+            .unwrap_or_default();
+        clients.sort_by_key(|client| client.id);
+        clients
+    }
+
+    /// Commands processed across all connections since startup.
+    pub fn total_commands(&self) -> u64 {
+        self.inner.total_commands.load(Ordering::Relaxed)
+    }
+
+    fn drain_deadline(&self) -> Option<Instant> {
+        self.inner.drain_deadline.lock().ok().and_then(|deadline| *deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_lifecycle() {
+        let lifecycle = Lifecycle::new();
+        assert!(!lifecycle.is_draining());
+        assert!(!lifecycle.should_stop());
+
+        lifecycle.connection_opened();
+        assert!(lifecycle.start_drain(Duration::from_secs(60)));
+        assert!(!lifecycle.start_drain(Duration::from_secs(60)));
+        assert!(lifecycle.is_draining());
+        assert!(!lifecycle.drain_expired());
+        assert!(!lifecycle.should_stop()); // Still one client connected
+
+        lifecycle.connection_closed();
+        assert!(lifecycle.should_stop());
+    }
+
+    #[test]
+    fn test_client_registry() {
+        let lifecycle = Lifecycle::new();
+        let first = lifecycle.register_client("127.0.0.1:5000");
+        let second = lifecycle.register_client("127.0.0.1:5001");
+        lifecycle.client_command(second, "GET");
+        lifecycle.client_command(second, "SET");
+
+        let clients = lifecycle.clients();
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[0].id, first);
+        assert_eq!(clients[1].commands, 2);
+        assert_eq!(clients[1].last_command, "SET");
+        assert_eq!(lifecycle.total_commands(), 2);
+
+        lifecycle.set_no_evict(first, true);
+        let clients = lifecycle.clients();
+        assert!(clients[0].no_evict && !clients[0].no_touch);
+        assert!(!clients[1].no_evict);
+
+        lifecycle.unregister_client(first);
+        assert_eq!(lifecycle.clients().len(), 1);
+    }
+
+    #[test]
+    fn test_kill_and_unblock() {
+        let lifecycle = Lifecycle::new();
+        let first = lifecycle.register_client("127.0.0.1:5000");
+        let second = lifecycle.register_client("127.0.0.1:5001");
+        let control = lifecycle.control(second).unwrap();
+
+        assert!(!lifecycle.unblock_client(second, UnblockMode::Error));
+        control.set_blocked(true);
+        assert!(lifecycle.unblock_client(second, UnblockMode::Error));
+        assert!(control.interrupted());
+        assert_eq!(control.unblock_mode(), Some(UnblockMode::Error));
+        control.set_blocked(false);
+        assert!(!control.interrupted());
+
+        assert_eq!(lifecycle.kill_clients(|client| client.addr == "127.0.0.1:5000"), 1);
+        assert!(lifecycle.control(first).unwrap().is_killed());
+        assert!(!control.is_killed());
+
+        lifecycle.unregister_client(second);
+        assert!(lifecycle.control(second).is_none());
+    }
+
+    #[test]
+    fn test_drain_grace_expiry() {
+        let lifecycle = Lifecycle::new();
+        lifecycle.connection_opened();
+        assert!(lifecycle.start_drain(Duration::ZERO));
+        assert!(lifecycle.drain_expired());
+        assert!(lifecycle.should_stop());
+    }
+}
@@ -1,21 +1,168 @@
-use medusa::config::Config;
+use medusa::config::{self, Config};
+use medusa::daemon::{self, PidFile};
+use medusa::extension::CommandRegistry;
+use medusa::proxy::start_proxy;
 use medusa::server::{start_server_with_config, ServerConfig};
+use medusa::snapshot;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("Usage: medusa [--seed dir] [--import-rdb file] [--daemonize] [--pidfile file] [--logfile file]");
+    eprintln!("       medusa --dump-config-schema");
+    eprintln!("       medusa --check file [file ...]");
+    eprintln!();
+    eprintln!("Settings are read from MEDUSA_* environment variables; --seed loads");
+    eprintln!("the .medusa and .json fixture files in dir at startup, and --import-rdb");
+    eprintln!("the strings, hashes and lists in a Redis dump.rdb. --daemonize runs");
+    eprintln!("in the background with output sent to --logfile and its PID written to");
+    eprintln!("--pidfile (default medusa.pid), which is removed again on shutdown.");
+    eprintln!("--dump-config-schema prints every setting as JSON and exits. --check");
+    eprintln!("validates snapshots and backups offline and exits non-zero if any is");
+    eprintln!("corrupt.");
+    process::exit(2);
+}
+
+fn check_files(paths: Vec<String>) -> ! {
+    if paths.is_empty() {
+        usage();
+    }
+    let mut failed = false;
+    for path in &paths {
+        match snapshot::check_file(path) {
+            Ok(check) => {
+                println!("{}: OK", path);
+                for line in check.describe() {
+                    println!("  {}", line);
+                }
+            }
+            Err(e) => {
+                println!("{}: FAILED", path);
+                println!("  {}", e);
+                failed = true;
+            }
+        }
+    }
+    process::exit(if failed { 1 } else { 0 });
+}
 
 fn main() {
+    // Before the banner, so the output is nothing but JSON
+    if env::args().skip(1).any(|arg| arg == "--dump-config-schema") {
+        print!("{}", config::schema_json());
+        return;
+    }
+
+    if env::args().nth(1).as_deref() == Some("--check") {
+        check_files(env::args().skip(2).collect());
+    }
+
     println!("[:)] Medusa - Lightning Fast Key-Value Store");
     println!("Built with Rust for learning and experimentation\n");
 
-    let config = Config::from_env();
+    let mut config = Config::from_env();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => match args.next() {
+                Some(dir) => config.seed_dir = Some(PathBuf::from(dir)),
+                None => usage(),
+            },
+            "--import-rdb" => match args.next() {
+                Some(path) => config.import_rdb = Some(path),
+                None => usage(),
+            },
+            "--daemonize" => config.daemonize = true,
+            "--pidfile" => match args.next() {
+                Some(path) => config.pid_file = Some(PathBuf::from(path)),
+                None => usage(),
+            },
+            "--logfile" => match args.next() {
+                Some(path) => config.log_file = Some(PathBuf::from(path)),
+                None => usage(),
+            },
+            _ => usage(),
+        }
+    }
+    if config.daemonize && config.pid_file.is_none() {
+        config.pid_file = Some(PathBuf::from("medusa.pid"));
+    }
     config.display();
 
+    if config.daemonize {
+        if let Err(e) = daemon::daemonize(config.log_file.as_deref()) {
+            eprintln!("Failed to daemonize: {}", e);
+            process::exit(1);
+        }
+    }
+    let pid_file = config.pid_file.as_ref().map(|path| match PidFile::create(path) {
+        Ok(pid_file) => {
+            daemon::remove_on_termination(pid_file.clone());
+            pid_file
+        }
+        Err(e) => {
+            eprintln!("Failed to write PID file: {}", e);
+            process::exit(1);
+        }
+    });
+
+    if let Some(proxy) = config.proxy {
+        start_proxy(proxy);
+        return;
+    }
+
     let server_config = ServerConfig {
         host: config.host,
         port: config.port,
         max_connections: config.max_connections,
         connection_timeout: config.connection_timeout,
         enable_timeouts: config.enable_timeouts,
+        enable_tracing: config.enable_tracing,
+        otlp_endpoint: config.otlp_endpoint,
+        tenants: config.tenants,
+        import_rdb: config.import_rdb,
+        enable_fault_injection: config.enable_fault_injection,
+        http_port: config.http_port,
+        resp_port: config.resp_port,
+        slowlog_threshold: config.slowlog_threshold,
+        latency_buckets: config.latency_buckets,
+        key_history: config.key_history,
+        max_memory: config.max_memory,
+        eviction_policy: config.eviction_policy,
+        eviction_samples: config.eviction_samples,
+        snapshot_schedule: config.snapshot_schedule,
+        alarm_thresholds: config.alarm_thresholds,
+        aliases: config.aliases,
+        chunk_threshold: config.chunk_threshold,
+        max_keys: config.max_keys,
+        pinned_keys: config.pinned_keys,
+        handoff_memfd: config.handoff_memfd,
+        prefix_stats: config.prefix_stats,
+        flush_token: config.flush_token,
+        seed_dir: config.seed_dir,
+        save_file: config.save_file,
+        backup_dir: config.backup_dir,
+        import_dir: config.import_dir,
+        save_rules: config.save_rules,
+        corrupt_snapshot: config.corrupt_snapshot,
+        replica_of: config.replica_of,
+        sentinel: config.sentinel,
+        crdt: config.crdt,
+        tcp: config.tcp,
+        fair_quantum: config.fair_quantum,
+        worker_threads: config.worker_threads,
+        // The standalone server has only the built-in commands
+        commands: CommandRegistry::new(),
     };
 
     // Start the server
-    start_server_with_config(server_config);
+    let result = start_server_with_config(server_config).map(|server| server.join());
+    if let Some(pid_file) = pid_file {
+        pid_file.remove();
+    }
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
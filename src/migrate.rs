@@ -0,0 +1,300 @@
+use crate::net;
+use crate::rdb;
+use crate::snapshot::write_snapshot;
+use crate::store::{Store, Value};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Settings for copying keys from a running Redis into a running Medusa.
+///
+/// Keys are read with `SCAN` + `DUMP` + `PTTL`, written batch by batch to a
+/// snapshot file in `staging_dir` and merged into Medusa with
+/// `IMPORT SNAPSHOT`, so `staging_dir` must be Medusa's `MEDUSA_IMPORT_DIR`.
+#[derive(Clone, Debug)]
+pub struct MigrateOptions {
+    pub source: String,
+    pub target: String,
+    pub pattern: Option<String>,
+    pub batch_size: usize,
+    pub staging_dir: PathBuf,
+    /// Where the SCAN cursor is saved after every batch; an existing file
+    /// resumes the migration from the saved cursor.
+    pub progress_file: Option<PathBuf>,
+    pub verify: bool,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        MigrateOptions {
+            source: "127.0.0.1:6379".to_string(),
+            target: "127.0.0.1:2312".to_string(),
+            pattern: None,
+            batch_size: 500,
+            staging_dir: std::env::temp_dir(),
+            progress_file: None,
+            verify: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MigrateReport {
+    pub migrated: usize,
+    /// Keys that could not be migrated, with the reason.
+    pub skipped: Vec<(String, String)>,
+    pub verified: usize,
+    /// Keys whose copy in Medusa is missing or differs from Redis.
+    pub mismatches: Vec<String>,
+}
+
+/// A Redis protocol (RESP2) reply.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    Status(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Minimal blocking RESP client, enough to read keys out of Redis.
+pub struct RedisClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl RedisClient {
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let stream = net::connect_address(address, CONNECT_TIMEOUT).map_err(|e| format!("Failed to connect to Redis at {}: {}", address, e))?;
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(30)));
+        let reader = BufReader::new(stream.try_clone().map_err(|e| format!("Failed to clone stream: {}", e))?);
+        Ok(RedisClient { reader, writer: stream })
+    }
+
+    /// Send several commands in one write and read all their replies.
+    pub fn pipeline(&mut self, commands: &[Vec<&[u8]>]) -> Result<Vec<Reply>, String> {
+        let mut request = Vec::new();
+        for args in commands {
+            request.extend(format!("*{}\r\n", args.len()).into_bytes());
+            for arg in args {
+                request.extend(format!("${}\r\n", arg.len()).into_bytes());
+                request.extend_from_slice(arg);
+                request.extend_from_slice(b"\r\n");
+            }
+        }
+        self.writer.write_all(&request).map_err(|e| format!("Write error: {}", e))?;
+
+        commands.iter().map(|_| read_reply(&mut self.reader)).collect()
+    }
+
+    pub fn call(&mut self, args: &[&[u8]]) -> Result<Reply, String> {
+        let mut replies = self.pipeline(&[args.to_vec()])?;
+        Ok(replies.remove(0))
+    }
+}
+
+fn read_reply<R: BufRead>(reader: &mut R) -> Result<Reply, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| format!("Read error: {}", e))?;
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.is_empty() {
+        return Err("Connection closed by Redis".to_string());
+    }
+
+    let (kind, rest) = line.split_at(1);
+    let number = || rest.parse::<i64>().map_err(|_| format!("Invalid reply header '{}'", line));
+    match kind {
+        "+" => Ok(Reply::Status(rest.to_string())),
+        "-" => Ok(Reply::Error(rest.to_string())),
+        ":" => Ok(Reply::Integer(number()?)),
+        "$" => match number()? {
+            len if len < 0 => Ok(Reply::Bulk(None)),
+            len => {
+                let mut data = vec![0u8; len as usize + 2];
+                reader.read_exact(&mut data).map_err(|e| format!("Read error: {}", e))?;
+                data.truncate(len as usize);
+                Ok(Reply::Bulk(Some(data)))
+            }
+        },
+        "*" => match number()? {
+            len if len < 0 => Ok(Reply::Array(None)),
+            len => (0..len).map(|_| read_reply(reader)).collect::<Result<Vec<_>, _>>().map(|items| Reply::Array(Some(items))),
+        },
+        _ => Err(format!("Unexpected reply '{}'", line)),
+    }
+}
+
+/// Line-protocol client for the Medusa side of the migration.
+struct MedusaClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl MedusaClient {
+    fn connect(address: &str) -> Result<Self, String> {
+        let stream = net::connect_address(address, CONNECT_TIMEOUT).map_err(|e| format!("Failed to connect to Medusa at {}: {}", address, e))?;
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(30)));
+        let mut reader = BufReader::new(stream.try_clone().map_err(|e| format!("Failed to clone stream: {}", e))?);
+        let mut welcome = String::new();
+        reader.read_line(&mut welcome).map_err(|e| format!("Read error: {}", e))?;
+        Ok(MedusaClient { reader, writer: stream })
+    }
+
+    fn command(&mut self, command: &str) -> Result<String, String> {
+        self.writer
+            .write_all(format!("{}\n", command).as_bytes())
+            .map_err(|e| format!("Write error: {}", e))?;
+        let mut response = String::new();
+        self.reader.read_line(&mut response).map_err(|e| format!("Read error: {}", e))?;
+        Ok(response.trim_end().to_string())
+    }
+}
+
+/// Copy every key (or every key matching `pattern`) from Redis into Medusa,
+/// keeping TTLs, then optionally verify the copy.
+pub fn migrate(options: &MigrateOptions) -> Result<MigrateReport, String> {
+    let mut redis = RedisClient::connect(&options.source)?;
+    let mut medusa = MedusaClient::connect(&options.target)?;
+    let mut report = MigrateReport::default();
+
+    let mut cursor = match &options.progress_file {
+        Some(path) => fs::read_to_string(path).map(|saved| saved.trim().to_string()).unwrap_or_else(|_| "0".to_string()),
+        None => "0".to_string(),
+    };
+    let staging_name = format!("medusa-migrate-{}.mdsnap", std::process::id());
+    let staging = options.staging_dir.join(&staging_name);
+
+    loop {
+        let (next_cursor, keys) = scan(&mut redis, &cursor, options)?;
+        let batch = Store::new();
+
+        let commands: Vec<Vec<&[u8]>> = keys
+            .iter()
+            .flat_map(|key| [vec![&b"DUMP"[..], key], vec![&b"PTTL"[..], key]])
+            .collect();
+        let replies = redis.pipeline(&commands)?;
+
+        for (key, replies) in keys.iter().zip(replies.chunks(2)) {
+            let name = String::from_utf8_lossy(key).to_string();
+            match decode_key(key, &replies[0], &replies[1]) {
+                Ok(Some((key, value, ttl))) => batch.import_entry(&key, value, ttl)?,
+                Ok(None) => {} // Deleted or expired since SCAN returned it
+                Err(reason) => report.skipped.push((name, reason)),
+            }
+        }
+
+        let mut writer = BufWriter::new(File::create(&staging).map_err(|e| format!("Failed to create {}: {}", staging.display(), e))?);
+        let written = write_snapshot(&batch, &mut writer)?;
+        drop(writer);
+        if written > 0 {
+            let response = medusa.command(&format!("IMPORT SNAPSHOT {}", staging_name))?;
+            if !response.starts_with("OK") {
+                let _ = fs::remove_file(&staging);
+                return Err(format!("Medusa rejected batch: {}", response));
+            }
+        }
+        report.migrated += written;
+
+        cursor = next_cursor;
+        if let Some(path) = &options.progress_file {
+            fs::write(path, &cursor).map_err(|e| format!("Failed to save progress: {}", e))?;
+        }
+        if cursor == "0" {
+            break;
+        }
+    }
+    let _ = fs::remove_file(&staging);
+    if let Some(path) = &options.progress_file {
+        let _ = fs::remove_file(path);
+    }
+
+    if options.verify {
+        verify(&mut redis, &mut medusa, options, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn scan(redis: &mut RedisClient, cursor: &str, options: &MigrateOptions) -> Result<(String, Vec<Vec<u8>>), String> {
+    let count = options.batch_size.to_string();
+    let mut args: Vec<&[u8]> = vec![b"SCAN", cursor.as_bytes(), b"COUNT", count.as_bytes()];
+    if let Some(pattern) = &options.pattern {
+        args.extend([&b"MATCH"[..], pattern.as_bytes()]);
+    }
+
+    match redis.call(&args)? {
+        Reply::Array(Some(reply)) => match reply.as_slice() {
+            [Reply::Bulk(Some(next)), Reply::Array(Some(keys))] => {
+                let keys = keys
+                    .iter()
+                    .filter_map(|key| match key {
+                        Reply::Bulk(Some(key)) => Some(key.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                Ok((String::from_utf8_lossy(next).to_string(), keys))
+            }
+            _ => Err("Malformed SCAN reply".to_string()),
+        },
+        Reply::Error(e) => Err(format!("SCAN failed: {}", e)),
+        _ => Err("Malformed SCAN reply".to_string()),
+    }
+}
+
+type Decoded = (String, Value, Option<Duration>);
+
+fn decode_key(key: &[u8], dump: &Reply, pttl: &Reply) -> Result<Option<Decoded>, String> {
+    let key = String::from_utf8(key.to_vec()).map_err(|_| "non-UTF-8 key".to_string())?;
+    let payload = match dump {
+        Reply::Bulk(Some(payload)) => payload,
+        Reply::Bulk(None) => return Ok(None),
+        Reply::Error(e) => return Err(format!("DUMP failed: {}", e)),
+        _ => return Err("Malformed DUMP reply".to_string()),
+    };
+    let ttl = match pttl {
+        Reply::Integer(-2) => return Ok(None),
+        Reply::Integer(ms) if *ms >= 0 => Some(Duration::from_millis(*ms as u64)),
+        _ => None,
+    };
+    Ok(Some((key, rdb::decode_dump(payload)?, ttl)))
+}
+
+// Re-read every key from Redis and check that Medusa holds it. Strings are
+// compared by value; other types by existence, as the line protocol has no
+// lossless way to read them back.
+fn verify(redis: &mut RedisClient, medusa: &mut MedusaClient, options: &MigrateOptions, report: &mut MigrateReport) -> Result<(), String> {
+    let mut cursor = "0".to_string();
+    loop {
+        let (next_cursor, keys) = scan(redis, &cursor, options)?;
+        for key in keys {
+            let dump = redis.call(&[b"DUMP", &key])?;
+            let pttl = redis.call(&[b"PTTL", &key])?;
+            let (key, value, _) = match decode_key(&key, &dump, &pttl) {
+                Ok(Some(decoded)) => decoded,
+                _ => continue,
+            };
+            if key.contains(char::is_whitespace) {
+                continue; // Not addressable over the line protocol
+            }
+
+            let matches = match &value {
+                Value::String(expected) => medusa.command(&format!("GET {}", key))? == format!("OK: '{}' = {}", key, expected),
+                _ => medusa.command(&format!("EXISTS {}", key))?.starts_with("TRUE"),
+            };
+            if matches {
+                report.verified += 1;
+            } else {
+                report.mismatches.push(key);
+            }
+        }
+
+        cursor = next_cursor;
+        if cursor == "0" {
+            return Ok(());
+        }
+    }
+}
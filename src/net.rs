@@ -0,0 +1,346 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long one connection attempt gets before the next address is tried
+/// alongside it (RFC 8305 recommends 250ms).
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Format `host` and `port` as an address, bracketing IPv6 literals so the
+/// result parses back (`[::1]:2312` rather than `::1:2312`).
+pub fn format_address(host: &str, port: u16) -> String {
+    let host = unbracket(host);
+    if host.parse::<std::net::Ipv6Addr>().is_ok() {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// The hosts in a comma-separated list such as `127.0.0.1,10.0.0.5`, in
+/// order and never empty; the first is the primary.
+pub fn split_hosts(hosts: &str) -> Vec<&str> {
+    let list: Vec<&str> = hosts.split(',').map(str::trim).filter(|host| !host.is_empty()).collect();
+    if list.is_empty() {
+        vec![hosts.trim()]
+    } else {
+        list
+    }
+}
+
+/// `format_address` for every host in a list, joined with commas.
+pub fn format_addresses(hosts: &str, port: u16) -> String {
+    split_hosts(hosts).iter().map(|host| format_address(host, port)).collect::<Vec<_>>().join(", ")
+}
+
+/// Split a `host:port` address, accepting bracketed IPv6 hosts.
+pub fn split_address(address: &str) -> Option<(String, u16)> {
+    let (host, port) = address.rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    if host.contains(':') && !(host.starts_with('[') && host.ends_with(']')) {
+        return None;
+    }
+    Some((unbracket(host).to_string(), port))
+}
+
+fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host)
+}
+
+/// Pending connections a listener queues by default (the same as std).
+pub const DEFAULT_BACKLOG: i32 = 128;
+
+/// Socket options for accepted client connections and the listener.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TcpTuning {
+    pub nodelay: bool,
+    /// Idle time before keepalive probes start, also used as the interval
+    /// between probes; `None` leaves keepalive off.
+    pub keepalive: Option<Duration>,
+    pub backlog: i32,
+    /// `SO_RCVBUF`/`SO_SNDBUF` in bytes; `None` keeps the system default.
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+}
+
+impl Default for TcpTuning {
+    fn default() -> Self {
+        TcpTuning { nodelay: true, keepalive: None, backlog: DEFAULT_BACKLOG, recv_buffer: None, send_buffer: None }
+    }
+}
+
+impl TcpTuning {
+    pub fn describe(&self) -> String {
+        let buffer = |size: Option<usize>| size.map_or("default".to_string(), |size| size.to_string());
+        format!(
+            "nodelay={} keepalive={} backlog={} rcvbuf={} sndbuf={}",
+            self.nodelay,
+            self.keepalive.map_or("off".to_string(), |keepalive| format!("{}s", keepalive.as_secs())),
+            self.backlog,
+            buffer(self.recv_buffer),
+            buffer(self.send_buffer)
+        )
+    }
+}
+
+/// Apply `tuning` to an accepted connection. Keepalive and buffer sizes
+/// are only supported on Linux and are left alone elsewhere.
+pub fn tune_stream(stream: &TcpStream, tuning: &TcpTuning) -> io::Result<()> {
+    stream.set_nodelay(tuning.nodelay)?;
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = stream.as_raw_fd();
+        // SAFETY: setsockopt on a descriptor `stream` keeps open for the call
+        unsafe {
+            if let Some(keepalive) = tuning.keepalive {
+                let seconds = keepalive.as_secs().clamp(1, i32::MAX as u64) as libc::c_int;
+                set_socket_option(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+                set_socket_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, seconds)?;
+                set_socket_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, seconds)?;
+            }
+            if let Some(size) = tuning.recv_buffer {
+                set_socket_option(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, size.min(i32::MAX as usize) as libc::c_int)?;
+            }
+            if let Some(size) = tuning.send_buffer {
+                set_socket_option(fd, libc::SOL_SOCKET, libc::SO_SNDBUF, size.min(i32::MAX as usize) as libc::c_int)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Listen on `host:port`. Binding the IPv6 wildcard `::` also accepts IPv4
+/// clients as v4-mapped addresses, whatever the system default for
+/// `IPV6_V6ONLY` is.
+pub fn bind(host: &str, port: u16) -> io::Result<TcpListener> {
+    bind_with_backlog(host, port, DEFAULT_BACKLOG)
+}
+
+/// `bind` with a listen backlog of `backlog` connections. The backlog is
+/// only honoured on Linux; elsewhere the std default applies.
+pub fn bind_with_backlog(host: &str, port: u16, backlog: i32) -> io::Result<TcpListener> {
+    let host = unbracket(host);
+    let mut last_error = None;
+    for address in (host, port).to_socket_addrs()? {
+        match bind_socket(address, backlog) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| invalid_address(&format_address(host, port))))
+}
+
+/// `bind` for a `host:port` address.
+pub fn bind_address(address: &str) -> io::Result<TcpListener> {
+    let (host, port) = split_address(address).ok_or_else(|| invalid_address(address))?;
+    bind(&host, port)
+}
+
+#[cfg(target_os = "linux")]
+fn bind_socket(address: SocketAddr, backlog: i32) -> io::Result<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let family = if address.is_ipv6() { libc::AF_INET6 } else { libc::AF_INET };
+    // SAFETY: the new descriptor is owned by `listener` straight away, so it
+    // is closed on every error path; the calls below only configure it
+    unsafe {
+        let fd = libc::socket(family, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let listener = TcpListener::from_raw_fd(fd);
+        set_socket_option(fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+
+        let result = match address {
+            SocketAddr::V4(address) => {
+                let mut raw: libc::sockaddr_in = std::mem::zeroed();
+                raw.sin_family = libc::AF_INET as libc::sa_family_t;
+                raw.sin_port = address.port().to_be();
+                raw.sin_addr.s_addr = u32::from(*address.ip()).to_be();
+                let length = std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+                libc::bind(fd, &raw as *const libc::sockaddr_in as *const libc::sockaddr, length)
+            }
+            SocketAddr::V6(address) => {
+                if address.ip().is_unspecified() {
+                    set_socket_option(fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, 0)?;
+                }
+                let mut raw: libc::sockaddr_in6 = std::mem::zeroed();
+                raw.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                raw.sin6_port = address.port().to_be();
+                raw.sin6_addr.s6_addr = address.ip().octets();
+                raw.sin6_flowinfo = address.flowinfo();
+                raw.sin6_scope_id = address.scope_id();
+                let length = std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+                libc::bind(fd, &raw as *const libc::sockaddr_in6 as *const libc::sockaddr, length)
+            }
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::listen(fd, backlog) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(listener)
+    }
+}
+
+// Elsewhere the system default decides whether `::` accepts IPv4 clients
+#[cfg(not(target_os = "linux"))]
+fn bind_socket(address: SocketAddr, _backlog: i32) -> io::Result<TcpListener> {
+    TcpListener::bind(address)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn set_socket_option(fd: i32, level: i32, name: i32, value: libc::c_int) -> io::Result<()> {
+    let length = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    if libc::setsockopt(fd, level, name, &value as *const libc::c_int as *const libc::c_void, length) < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Connect to `host:port`, resolving hostnames and trying every address
+/// they resolve to. Attempts alternate between IPv6 and IPv4 and start
+/// `ATTEMPT_DELAY` apart, so an unreachable family costs a short delay
+/// rather than a full timeout; the first connection to succeed wins.
+pub fn connect(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let addresses = interleave_families((unbracket(host), port).to_socket_addrs()?.collect());
+    if addresses.len() == 1 {
+        return TcpStream::connect_timeout(&addresses[0], timeout);
+    }
+
+    let deadline = Instant::now() + timeout;
+    let (tx, rx) = mpsc::channel();
+    let mut pending = 0;
+    let mut last_error = None;
+
+    for address in addresses {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let _ = tx.send(TcpStream::connect_timeout(&address, timeout));
+        });
+        pending += 1;
+
+        // A failure starts the next attempt straight away
+        match rx.recv_timeout(ATTEMPT_DELAY.min(deadline.saturating_duration_since(Instant::now()))) {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => {
+                pending -= 1;
+                last_error = Some(e);
+            }
+            Err(_) => {}
+        }
+    }
+
+    while pending > 0 {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => {
+                pending -= 1;
+                last_error = Some(e);
+            }
+            Err(_) => break,
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::TimedOut, format!("Timed out connecting to {}", format_address(host, port)))
+    }))
+}
+
+/// `connect` for a `host:port` address.
+pub fn connect_address(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let (host, port) = split_address(address).ok_or_else(|| invalid_address(address))?;
+    connect(&host, port, timeout)
+}
+
+fn invalid_address(address: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid address '{}', expected host:port", address))
+}
+
+// Alternate families, starting with whichever the resolver listed first
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_v6 = addresses.first().is_some_and(SocketAddr::is_ipv6);
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) =
+        addresses.into_iter().partition(|address| address.is_ipv6() == first_is_v6);
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(format_address("127.0.0.1", 2312), "127.0.0.1:2312");
+        assert_eq!(format_address("::1", 2312), "[::1]:2312");
+        assert_eq!(format_address("[::]", 2312), "[::]:2312");
+        assert_eq!(format_address("localhost", 2312), "localhost:2312");
+        assert_eq!(split_address("[::1]:2312"), Some(("::1".to_string(), 2312)));
+        assert_eq!(split_address("db.local:6379"), Some(("db.local".to_string(), 6379)));
+        assert_eq!(split_address("::1:2312"), None);
+        assert_eq!(split_address("localhost"), None);
+        assert_eq!(split_hosts("127.0.0.1, ::1,"), ["127.0.0.1", "::1"]);
+        assert_eq!(split_hosts("localhost"), ["localhost"]);
+        assert_eq!(format_addresses("127.0.0.1,::1", 2312), "127.0.0.1:2312, [::1]:2312");
+    }
+
+    #[test]
+    fn test_tuned_sockets() {
+        let listener = bind_with_backlog("127.0.0.1", 0, 16).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = connect("127.0.0.1", port, Duration::from_secs(2)).unwrap();
+
+        let tuning = TcpTuning {
+            nodelay: false,
+            keepalive: Some(Duration::from_secs(60)),
+            recv_buffer: Some(64 * 1024),
+            send_buffer: Some(64 * 1024),
+            ..Default::default()
+        };
+        tune_stream(&stream, &tuning).unwrap();
+        assert!(!stream.nodelay().unwrap());
+        tune_stream(&stream, &TcpTuning::default()).unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert_eq!(tuning.describe(), "nodelay=false keepalive=60s backlog=128 rcvbuf=65536 sndbuf=65536");
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let addresses: Vec<SocketAddr> =
+            ["[::1]:1", "[::2]:1", "127.0.0.1:1", "127.0.0.2:1"].iter().map(|a| a.parse().unwrap()).collect();
+        let ordered = interleave_families(addresses);
+        let expected: Vec<SocketAddr> =
+            ["[::1]:1", "127.0.0.1:1", "[::2]:1", "127.0.0.2:1"].iter().map(|a| a.parse().unwrap()).collect();
+        assert_eq!(ordered, expected);
+    }
+
+    #[test]
+    fn test_dual_stack_listener() {
+        // Skipped where the host has no IPv6 support
+        let listener = match bind("::", 0) {
+            Ok(listener) => listener,
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+
+        let v4 = connect("127.0.0.1", port, Duration::from_secs(2)).unwrap();
+        assert!(v4.peer_addr().unwrap().is_ipv4());
+        if let Ok(v6) = connect("::1", port, Duration::from_secs(2)) {
+            assert!(v6.peer_addr().unwrap().is_ipv6());
+        }
+        let resolved = connect("localhost", port, Duration::from_secs(2)).unwrap();
+        assert_eq!(resolved.peer_addr().unwrap().port(), port);
+    }
+}
@@ -0,0 +1,83 @@
+//! The request side of the line protocol: one command per line, arguments
+//! separated by whitespace, optionally prefixed with `*TAG id` to label the
+//! request for the slow log and traces.
+//!
+//! Everything here runs on bytes straight off the socket, so it must never
+//! panic; `tests/protocol_tests.rs` holds the conformance corpus and the
+//! `fuzz/` crate drives it with libFuzzer.
+
+pub const MAX_REQUEST_TAG_LEN: usize = 64;
+
+/// The wire protocol a connection speaks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// The line protocol described here.
+    Text,
+    /// RESP2, see `crate::resp`.
+    Resp,
+    /// Whichever of the two the client's first bytes look like; see `detect`.
+    Auto,
+}
+
+/// Tell the protocols apart from the first bytes a client sends: RESP
+/// requests open with `*` and an argument count, which no text command
+/// does (`*TAG` is followed by a letter). `None` means more bytes are
+/// needed to decide.
+pub fn detect(prefix: &[u8]) -> Option<Protocol> {
+    match prefix {
+        [] | [b'*'] => None,
+        [b'*', next, ..] if next.is_ascii_digit() || *next == b'-' => Some(Protocol::Resp),
+        _ => Some(Protocol::Text),
+    }
+}
+
+/// One request line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request<'a> {
+    /// The `*TAG` label, if the client sent one.
+    pub tag: Option<&'a str>,
+    /// The command with the tag removed, trimmed and never empty.
+    pub command: &'a str,
+}
+
+impl<'a> Request<'a> {
+    /// The command name as sent.
+    pub fn name(&self) -> &'a str {
+        self.command.split_whitespace().next().unwrap_or("")
+    }
+
+    /// The command name followed by its arguments.
+    pub fn args(&self) -> Vec<&'a str> {
+        self.command.split_whitespace().collect()
+    }
+}
+
+/// Parse one line as read from a client, with or without its line ending.
+/// Blank lines give `Ok(None)`; an error is the message to send back.
+pub fn parse_line(line: &[u8]) -> Result<Option<Request<'_>>, String> {
+    let line = std::str::from_utf8(line).map_err(|_| "Request is not valid UTF-8".to_string())?;
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let (tag, command) = split_request_tag(line)?;
+    Ok(Some(Request { tag, command }))
+}
+
+// A command line may start with `*TAG id` so it can be matched up with the
+// application request that sent it
+fn split_request_tag(line: &str) -> Result<(Option<&str>, &str), String> {
+    let rest = match line.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("*TAG ") => line[5..].trim_start(),
+        _ => return Ok((None, line)),
+    };
+    let (tag, command) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let command = command.trim_start();
+    if tag.len() > MAX_REQUEST_TAG_LEN {
+        return Err(format!("Request tag is longer than {} bytes", MAX_REQUEST_TAG_LEN));
+    }
+    if command.is_empty() {
+        return Err("*TAG requires a tag and a command (*TAG id command)".to_string());
+    }
+    Ok((Some(tag), command))
+}
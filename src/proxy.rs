@@ -0,0 +1,280 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use crate::command_table::{self, CommandKind};
+use crate::net;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Commands a client may pipeline that are forwarded in one write
+const MAX_PIPELINE: usize = 64;
+const BACKEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection state lives on the backend connection, which the proxy
+/// shares between clients, so these are refused rather than forwarded.
+const SESSION_COMMANDS: &[&str] = &["CLIENT", "MULTI", "EXEC", "DISCARD"];
+
+/// Where a proxy listens and which Medusa instances it fronts.
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    pub listen: String,
+    pub primary: String,
+    /// With replicas, read-only commands are spread across them and
+    /// everything else goes to the primary.
+    pub replicas: Vec<String>,
+    /// Idle connections kept open per backend.
+    pub pool_size: usize,
+}
+
+/// Parse a comma-separated list of `host:port` backends.
+pub fn parse_backends(spec: &str) -> Vec<String> {
+    spec.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect()
+}
+
+/// A backend connection with framed replies (`CLIENT FRAMING ON`), so
+/// replies of any length can be read back exactly.
+struct BackendConnection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl BackendConnection {
+    fn connect(addr: &str) -> Result<Self, String> {
+        let stream = net::connect_address(addr, BACKEND_TIMEOUT).map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+        let _ = stream.set_read_timeout(Some(BACKEND_TIMEOUT));
+        let writer = stream.try_clone().map_err(|e| format!("Failed to clone stream: {}", e))?;
+        let mut connection = BackendConnection { reader: BufReader::new(stream), writer };
+
+        // Sent ahead of reading the greeting, so the backend sees a text
+        // client straight away instead of waiting to detect the protocol
+        connection.writer.write_all(b"CLIENT FRAMING ON\n").map_err(|e| format!("Failed to write to {}: {}", addr, e))?;
+        let mut welcome = String::new();
+        connection.reader.read_line(&mut welcome).map_err(|e| format!("Failed to read from {}: {}", addr, e))?;
+        let reply = connection.read_frame()?;
+        if !reply.starts_with("OK") {
+            return Err(format!("{} does not support framed replies: {}", addr, reply.trim()));
+        }
+        Ok(connection)
+    }
+
+    /// Write `commands` in one go, then read one reply per command.
+    fn send(&mut self, commands: &[&str]) -> Result<Vec<String>, String> {
+        let mut batch = String::new();
+        for command in commands {
+            batch.push_str(command);
+            batch.push('\n');
+        }
+        self.writer.write_all(batch.as_bytes()).map_err(|e| format!("Failed to write to backend: {}", e))?;
+        commands.iter().map(|_| self.read_frame()).collect()
+    }
+
+    fn read_frame(&mut self) -> Result<String, String> {
+        let mut header = String::new();
+        match self.reader.read_line(&mut header) {
+            Ok(0) => return Err("Backend closed the connection".to_string()),
+            Ok(_) => {}
+            Err(e) => return Err(format!("Failed to read from backend: {}", e)),
+        }
+        let length = header
+            .trim()
+            .strip_prefix("FRAME ")
+            .and_then(|length| length.parse::<usize>().ok())
+            .ok_or_else(|| format!("Unexpected reply from backend: {}", header.trim()))?;
+
+        let mut reply = vec![0; length];
+        self.reader.read_exact(&mut reply).map_err(|e| format!("Failed to read from backend: {}", e))?;
+        String::from_utf8(reply).map_err(|_| "Backend sent invalid UTF-8".to_string())
+    }
+}
+
+/// Reusable connections to one backend. Connections are opened on demand;
+/// up to `max_idle` are kept for the next request.
+pub(crate) struct Pool {
+    addr: String,
+    idle: Mutex<Vec<BackendConnection>>,
+    max_idle: usize,
+}
+
+impl Pool {
+    pub(crate) fn new(addr: &str, max_idle: usize) -> Self {
+        Pool { addr: addr.to_string(), idle: Mutex::new(Vec::new()), max_idle }
+    }
+
+    pub(crate) fn execute(&self, commands: &[&str]) -> Result<Vec<String>, String> {
+        let idle = self.idle.lock().ok().and_then(|mut idle| idle.pop());
+        let mut connection = match idle {
+            Some(connection) => connection,
+            None => BackendConnection::connect(&self.addr)?,
+        };
+
+        // A connection that failed mid-batch is in an unknown state and is
+        // dropped. Writes are never retried, since they may have been applied.
+        let replies = connection.send(commands)?;
+        if let Ok(mut idle) = self.idle.lock() {
+            if idle.len() < self.max_idle {
+                idle.push(connection);
+            }
+        }
+        Ok(replies)
+    }
+}
+
+/// Routes client commands to pooled backend connections.
+pub struct Proxy {
+    primary: Pool,
+    replicas: Vec<Pool>,
+    next_replica: AtomicUsize,
+}
+
+impl Proxy {
+    pub fn new(config: &ProxyConfig) -> Self {
+        Proxy {
+            primary: Pool::new(&config.primary, config.pool_size),
+            replicas: config.replicas.iter().map(|addr| Pool::new(addr, config.pool_size)).collect(),
+            next_replica: AtomicUsize::new(0),
+        }
+    }
+
+    /// Forward `commands` as one pipelined batch, returning their replies in
+    /// order. A batch containing any write goes to the primary.
+    pub fn execute(&self, commands: &[&str]) -> Result<Vec<String>, String> {
+        let read_only = commands.iter().all(|command| is_read_command(command));
+        if read_only && !self.replicas.is_empty() {
+            let replica = self.next_replica.fetch_add(1, Ordering::Relaxed) % self.replicas.len();
+            return self.replicas[replica].execute(commands);
+        }
+        self.primary.execute(commands)
+    }
+}
+
+fn command_name(command: &str) -> String {
+    command.split_whitespace().next().unwrap_or("").to_uppercase()
+}
+
+/// Commands that only read may be served by a replica.
+pub(crate) fn is_read_command(command: &str) -> bool {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    command_table::kind(&args) == Some(CommandKind::Read)
+}
+
+pub(crate) fn is_session_command(command: &str) -> bool {
+    SESSION_COMMANDS.contains(&command_name(command).as_str())
+}
+
+/// Accept clients on `config.listen` and proxy them until the process exits.
+pub fn start_proxy(config: ProxyConfig) {
+    let listener = match net::bind_address(&config.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to bind to {}: {}", config.listen, e);
+            return;
+        }
+    };
+    println!(
+        "Medusa proxy listening on {} (primary {}, {} replicas, pool {})",
+        config.listen,
+        config.primary,
+        config.replicas.len(),
+        config.pool_size
+    );
+
+    let proxy = Arc::new(Proxy::new(&config));
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let proxy = proxy.clone();
+                thread::spawn(move || handle_proxy_client(stream, &proxy));
+            }
+            Err(e) => eprintln!("❌ Failed to accept connection: {}", e),
+        }
+    }
+}
+
+fn handle_proxy_client(stream: TcpStream, proxy: &Proxy) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    if writer.write_all(b"Medusa proxy ready\n").is_err() {
+        return;
+    }
+
+    let mut line = String::new();
+    loop {
+        // Take every command the client has already pipelined
+        let mut batch = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) if batch.is_empty() => return,
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if !line.trim().is_empty() {
+                batch.push(line.trim().to_string());
+            }
+            if batch.len() >= MAX_PIPELINE || !reader.buffer().contains(&b'\n') {
+                break;
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+
+        let (replies, quit) = run_batch(proxy, &batch);
+        if writer.write_all(replies.concat().as_bytes()).is_err() || quit {
+            return;
+        }
+    }
+}
+
+// Forward runs of ordinary commands and answer the rest locally, keeping
+// replies in order. Stops at QUIT.
+fn run_batch(proxy: &Proxy, batch: &[String]) -> (Vec<String>, bool) {
+    let mut replies = Vec::new();
+    let mut pending: Vec<&str> = Vec::new();
+
+    let flush = |pending: &mut Vec<&str>, replies: &mut Vec<String>| {
+        if pending.is_empty() {
+            return;
+        }
+        match proxy.execute(pending) {
+            Ok(forwarded) => replies.extend(forwarded),
+            Err(e) => replies.extend(pending.iter().map(|_| format!("ERROR: Proxy: {}\n", e))),
+        }
+        pending.clear();
+    };
+
+    for command in batch {
+        let name = command_name(command);
+        if name == "QUIT" || name == "EXIT" {
+            flush(&mut pending, &mut replies);
+            replies.push("OK: Goodbye!\n".to_string());
+            return (replies, true);
+        }
+        if is_session_command(command) {
+            flush(&mut pending, &mut replies);
+            replies.push(format!("ERROR: {} is not available through the proxy\n", name));
+            continue;
+        }
+        pending.push(command);
+    }
+    flush(&mut pending, &mut replies);
+    (replies, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_classes() {
+        assert!(is_read_command("get user:1"));
+        assert!(is_read_command("HGETALL user:1"));
+        assert!(!is_read_command("SET user:1 x"));
+        assert!(!is_read_command(""));
+        assert_eq!(parse_backends("a:1, b:2,,"), vec!["a:1".to_string(), "b:2".to_string()]);
+    }
+}
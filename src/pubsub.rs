@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Messages queued for one subscriber before further ones are dropped, so
+/// a client that stops reading cannot grow the server's memory.
+pub const SUBSCRIBER_BACKLOG: usize = 1024;
+
+/// A published message as a subscriber receives it: channel and payload.
+pub type Message = (String, String);
+
+/// The broker every connection shares. Each subscribed connection hands in
+/// the sending side of its own queue, and `publish` fans a message out to
+/// those queues; the connection writes them to its socket between
+/// commands.
+#[derive(Clone, Default)]
+pub struct PubSub {
+    inner: Arc<Mutex<Broker>>,
+}
+
+#[derive(Default)]
+struct Broker {
+    // Channel -> client id -> that client's queue
+    channels: BTreeMap<String, HashMap<u64, mpsc::Sender<Message>>>,
+    published: u64,
+    dropped: u64,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `client_id` to `channel`. Returns false if it was already there.
+    pub fn subscribe(&self, channel: &str, client_id: u64, queue: &mpsc::Sender<Message>) -> bool {
+        let Ok(mut broker) = self.inner.lock() else { return false };
+        broker.channels.entry(channel.to_string()).or_default().insert(client_id, queue.clone()).is_none()
+    }
+
+    /// Remove `client_id` from `channel`. Returns whether it was there.
+    pub fn unsubscribe(&self, channel: &str, client_id: u64) -> bool {
+        let Ok(mut broker) = self.inner.lock() else { return false };
+        let Some(subscribers) = broker.channels.get_mut(channel) else { return false };
+        let removed = subscribers.remove(&client_id).is_some();
+        if subscribers.is_empty() {
+            broker.channels.remove(channel);
+        }
+        removed
+    }
+
+    /// Remove `client_id` from every channel, when its connection closes.
+    pub fn unsubscribe_all(&self, client_id: u64) {
+        let Ok(mut broker) = self.inner.lock() else { return };
+        broker.channels.retain(|_, subscribers| {
+            subscribers.remove(&client_id);
+            !subscribers.is_empty()
+        });
+    }
+
+    /// Queue `message` for every subscriber of `channel`. Returns how many
+    /// received it; a subscriber whose queue is full misses it.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let Ok(mut broker) = self.inner.lock() else { return 0 };
+        broker.published += 1;
+        let Some(subscribers) = broker.channels.get(channel) else { return 0 };
+        let (mut delivered, mut dropped) = (0, 0);
+        for queue in subscribers.values() {
+            match queue.try_send((channel.to_string(), message.to_string())) {
+                Ok(()) => delivered += 1,
+                Err(TrySendError::Full(_)) => dropped += 1,
+                // The connection is closing and unsubscribes on its way out
+                Err(TrySendError::Closed(_)) => {}
+            }
+        }
+        broker.dropped += dropped;
+        delivered
+    }
+
+    pub fn info(&self) -> String {
+        let Ok(broker) = self.inner.lock() else { return String::new() };
+        let subscriptions: usize = broker.channels.values().map(HashMap::len).sum();
+        format!(
+            "\n\n# Pub/Sub\npubsub_channels:{}\npubsub_subscriptions:{}\npubsub_published:{}\npubsub_dropped:{}",
+            broker.channels.len(),
+            subscriptions,
+            broker.published,
+            broker.dropped
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fan_out() {
+        let pubsub = PubSub::new();
+        let (first, mut first_inbox) = mpsc::channel(SUBSCRIBER_BACKLOG);
+        let (second, mut second_inbox) = mpsc::channel(1);
+        assert!(pubsub.subscribe("news", 1, &first));
+        assert!(!pubsub.subscribe("news", 1, &first));
+        assert!(pubsub.subscribe("news", 2, &second));
+        assert!(pubsub.subscribe("alerts", 2, &second));
+
+        assert_eq!(pubsub.publish("news", "hello"), 2);
+        assert_eq!(first_inbox.try_recv().unwrap(), ("news".to_string(), "hello".to_string()));
+        assert_eq!(second_inbox.try_recv().unwrap(), ("news".to_string(), "hello".to_string()));
+        assert_eq!(pubsub.publish("nobody", "hello"), 0);
+
+        // A full queue misses the message instead of holding up the publisher
+        assert_eq!(pubsub.publish("alerts", "one"), 1);
+        assert_eq!(pubsub.publish("alerts", "two"), 0);
+        assert!(pubsub.info().contains("pubsub_dropped:1"));
+
+        assert!(pubsub.unsubscribe("news", 1));
+        assert!(!pubsub.unsubscribe("news", 1));
+        pubsub.unsubscribe_all(2);
+        assert!(pubsub.info().contains("pubsub_channels:0\npubsub_subscriptions:0"));
+    }
+}
@@ -0,0 +1,500 @@
+use crate::store::{ClockAnchor, Store, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// Opcodes
+const OP_FUNCTION2: u8 = 0xF5;
+const OP_MODULE_AUX: u8 = 0xF7;
+const OP_IDLE: u8 = 0xF8;
+const OP_FREQ: u8 = 0xF9;
+const OP_AUX: u8 = 0xFA;
+const OP_RESIZEDB: u8 = 0xFB;
+const OP_EXPIRETIME_MS: u8 = 0xFC;
+const OP_EXPIRETIME: u8 = 0xFD;
+const OP_SELECTDB: u8 = 0xFE;
+const OP_EOF: u8 = 0xFF;
+
+// Value types
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+
+// Lengths come from the file, so never trust them for up-front allocation
+const PREALLOCATE_LIMIT: usize = 1024;
+
+/// Outcome of importing a Redis RDB file.
+#[derive(Debug, Default)]
+pub struct RdbImport {
+    pub loaded: usize,
+    pub expired: usize,
+    /// Keys that could not be represented in Medusa, counted by reason.
+    pub skipped: BTreeMap<String, usize>,
+}
+
+impl RdbImport {
+    pub fn skipped_total(&self) -> usize {
+        self.skipped.values().sum()
+    }
+
+    fn skip(&mut self, reason: &str) {
+        *self.skipped.entry(reason.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn describe(&self) -> String {
+        let mut summary = format!("{} keys loaded, {} already expired", self.loaded, self.expired);
+        if !self.skipped.is_empty() {
+            let reasons: Vec<String> = self.skipped.iter().map(|(reason, count)| format!("{} {}", count, reason)).collect();
+            summary.push_str(&format!(", skipped: {}", reasons.join(", ")));
+        }
+        summary
+    }
+}
+
+enum Decoded {
+    Value(Value),
+    Unsupported(&'static str),
+}
+
+/// Load a Redis RDB file from disk. See `load_rdb`.
+pub fn load_rdb_file<P: AsRef<Path>>(store: &Store, path: P) -> Result<RdbImport, String> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    load_rdb(store, &mut BufReader::new(file))
+}
+
+/// Import the keys of database 0 from a Redis RDB dump (format versions up
+/// to 12). Strings, lists and hashes are imported in every encoding Redis
+/// writes them; types Medusa has no equivalent for (sets, sorted sets,
+/// streams, modules) and keys in other databases are skipped and counted.
+/// Expiry times are kept; keys already past their expiry are dropped.
+pub fn load_rdb<R: Read>(store: &Store, reader: &mut R) -> Result<RdbImport, String> {
+    let mut header = [0u8; 9];
+    read_exact(reader, &mut header)?;
+    if &header[..5] != b"REDIS" {
+        return Err("Not a Redis RDB file".to_string());
+    }
+    let version: u32 = std::str::from_utf8(&header[5..])
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| "Invalid RDB version".to_string())?;
+    if version > 12 {
+        return Err(format!("Unsupported RDB version {}", version));
+    }
+
+    let clock = ClockAnchor::now();
+    let mut import = RdbImport::default();
+    let mut db = 0;
+    let mut expires_at_ms: Option<u64> = None;
+
+    loop {
+        let opcode = read_u8(reader)?;
+        match opcode {
+            OP_EOF => return Ok(import), // The trailing CRC64 is not verified
+            OP_SELECTDB => db = read_length(reader)?,
+            OP_RESIZEDB => {
+                read_length(reader)?;
+                read_length(reader)?;
+            }
+            OP_AUX => {
+                read_string(reader)?;
+                read_string(reader)?;
+            }
+            OP_EXPIRETIME_MS => expires_at_ms = Some(u64::from_le_bytes(read_array(reader)?)),
+            OP_EXPIRETIME => expires_at_ms = Some(u32::from_le_bytes(read_array(reader)?) as u64 * 1000),
+            OP_IDLE => {
+                read_length(reader)?;
+            }
+            OP_FREQ => {
+                read_u8(reader)?;
+            }
+            OP_MODULE_AUX | OP_FUNCTION2 => {
+                return Err("RDB files with modules or functions are not supported".to_string());
+            }
+            value_type => {
+                let key = read_string(reader)?;
+                let decoded = read_value(reader, value_type)?;
+                let expiry = expires_at_ms.take();
+
+                let key = match String::from_utf8(key) {
+                    Ok(key) => key,
+                    Err(_) => {
+                        import.skip("non-UTF-8 keys");
+                        continue;
+                    }
+                };
+                if db != 0 {
+                    import.skip("keys outside database 0");
+                    continue;
+                }
+                let value = match decoded {
+                    Decoded::Value(value) => value,
+                    Decoded::Unsupported(reason) => {
+                        import.skip(reason);
+                        continue;
+                    }
+                };
+                let expires_at = match expiry.map(|deadline| clock.instant_at(deadline)) {
+                    Some(None) => {
+                        import.expired += 1;
+                        continue;
+                    }
+                    Some(expires_at) => expires_at,
+                    None => None,
+                };
+                store.import_entry_until(&key, value, expires_at)?;
+                import.loaded += 1;
+            }
+        }
+    }
+}
+
+/// Decode the payload of a Redis `DUMP` reply: one serialized value
+/// followed by a 2-byte RDB version and an 8-byte CRC64 (not verified).
+/// `Err` carries the reason a value cannot be represented in Medusa.
+pub fn decode_dump(payload: &[u8]) -> Result<Value, String> {
+    if payload.len() < 11 {
+        return Err("DUMP payload too short".to_string());
+    }
+    let (body, footer) = payload.split_at(payload.len() - 10);
+    let version = u16::from_le_bytes([footer[0], footer[1]]);
+    if version > 12 {
+        return Err(format!("Unsupported RDB version {}", version));
+    }
+
+    let mut reader = &body[1..];
+    match read_value(&mut reader, body[0])? {
+        Decoded::Value(value) => Ok(value),
+        Decoded::Unsupported(reason) => Err(format!("{} are not supported", reason)),
+    }
+}
+
+fn read_value<R: Read>(reader: &mut R, value_type: u8) -> Result<Decoded, String> {
+    let decoded = match value_type {
+        TYPE_STRING => match String::from_utf8(read_string(reader)?) {
+            Ok(s) => Decoded::Value(Value::String(s)),
+            Err(_) => Decoded::Unsupported("non-UTF-8 values"),
+        },
+        TYPE_LIST | TYPE_SET => {
+            let len = read_length(reader)?;
+            let mut items = Vec::with_capacity(len.min(PREALLOCATE_LIMIT));
+            for _ in 0..len {
+                items.push(read_string(reader)?);
+            }
+            if value_type == TYPE_SET {
+                return Ok(Decoded::Unsupported("sets"));
+            }
+            list_value(items)
+        }
+        TYPE_HASH => {
+            let len = read_length(reader)?;
+            let mut items = Vec::with_capacity(len.min(PREALLOCATE_LIMIT) * 2);
+            for _ in 0..len * 2 {
+                items.push(read_string(reader)?);
+            }
+            hash_value(items)?
+        }
+        TYPE_LIST_ZIPLIST => list_value(parse_ziplist(&read_string(reader)?)?),
+        TYPE_HASH_ZIPLIST => hash_value(parse_ziplist(&read_string(reader)?)?)?,
+        TYPE_HASH_LISTPACK => hash_value(parse_listpack(&read_string(reader)?)?)?,
+        TYPE_LIST_QUICKLIST => {
+            let mut items = Vec::new();
+            for _ in 0..read_length(reader)? {
+                items.extend(parse_ziplist(&read_string(reader)?)?);
+            }
+            list_value(items)
+        }
+        TYPE_LIST_QUICKLIST_2 => {
+            let mut items = Vec::new();
+            for _ in 0..read_length(reader)? {
+                let container = read_length(reader)? as u64;
+                let node = read_string(reader)?;
+                if container == QUICKLIST_NODE_PLAIN {
+                    items.push(node);
+                } else {
+                    items.extend(parse_listpack(&node)?);
+                }
+            }
+            list_value(items)
+        }
+        TYPE_SET_INTSET | TYPE_SET_LISTPACK => {
+            read_string(reader)?;
+            return Ok(Decoded::Unsupported("sets"));
+        }
+        TYPE_ZSET | TYPE_ZSET_2 => {
+            for _ in 0..read_length(reader)? {
+                read_string(reader)?;
+                if value_type == TYPE_ZSET_2 {
+                    read_array::<R, 8>(reader)?;
+                } else {
+                    let len = read_u8(reader)?;
+                    if len < 253 {
+                        read_bytes(reader, len as usize)?;
+                    }
+                }
+            }
+            return Ok(Decoded::Unsupported("sorted sets"));
+        }
+        TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+            read_string(reader)?;
+            return Ok(Decoded::Unsupported("sorted sets"));
+        }
+        other => return Err(format!("Unsupported RDB value type {}", other)),
+    };
+    Ok(decoded)
+}
+
+fn list_value(items: Vec<Vec<u8>>) -> Decoded {
+    let mut list = VecDeque::with_capacity(items.len());
+    for item in items {
+        match String::from_utf8(item) {
+            Ok(item) => list.push_back(item),
+            Err(_) => return Decoded::Unsupported("non-UTF-8 values"),
+        }
+    }
+    Decoded::Value(Value::List(list))
+}
+
+fn hash_value(items: Vec<Vec<u8>>) -> Result<Decoded, String> {
+    if !items.len().is_multiple_of(2) {
+        return Err("Hash with an odd number of entries".to_string());
+    }
+    let mut hash = HashMap::with_capacity(items.len() / 2);
+    let mut items = items.into_iter();
+    while let (Some(field), Some(value)) = (items.next(), items.next()) {
+        // Values may be binary; fields must be UTF-8 to be addressable
+        match String::from_utf8(field) {
+            Ok(field) => hash.insert(field, value),
+            Err(_) => return Ok(Decoded::Unsupported("non-UTF-8 hash fields")),
+        };
+    }
+    Ok(Decoded::Value(Value::Hash(hash)))
+}
+
+enum Length {
+    Plain(usize),
+    IntString(u8),
+    Compressed,
+}
+
+fn read_length_encoding<R: Read>(reader: &mut R) -> Result<Length, String> {
+    let first = read_u8(reader)?;
+    Ok(match first >> 6 {
+        0 => Length::Plain((first & 0x3F) as usize),
+        1 => Length::Plain((((first & 0x3F) as usize) << 8) | read_u8(reader)? as usize),
+        2 => match first {
+            0x80 => Length::Plain(u32::from_be_bytes(read_array(reader)?) as usize),
+            0x81 => Length::Plain(u64::from_be_bytes(read_array(reader)?) as usize),
+            other => return Err(format!("Invalid length encoding {:#x}", other)),
+        },
+        _ => match first & 0x3F {
+            3 => Length::Compressed,
+            format @ 0..=2 => Length::IntString(format),
+            other => return Err(format!("Invalid string encoding {}", other)),
+        },
+    })
+}
+
+fn read_length<R: Read>(reader: &mut R) -> Result<usize, String> {
+    match read_length_encoding(reader)? {
+        Length::Plain(len) => Ok(len),
+        _ => Err("Expected a length, found an encoded string".to_string()),
+    }
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<Vec<u8>, String> {
+    match read_length_encoding(reader)? {
+        Length::Plain(len) => read_bytes(reader, len),
+        Length::IntString(0) => Ok((read_u8(reader)? as i8).to_string().into_bytes()),
+        Length::IntString(1) => Ok(i16::from_le_bytes(read_array(reader)?).to_string().into_bytes()),
+        Length::IntString(_) => Ok(i32::from_le_bytes(read_array(reader)?).to_string().into_bytes()),
+        Length::Compressed => {
+            let compressed_len = read_length(reader)?;
+            let len = read_length(reader)?;
+            lzf_decompress(&read_bytes(reader, compressed_len)?, len)
+        }
+    }
+}
+
+fn lzf_decompress(input: &[u8], expected_len: usize) -> Result<Vec<u8>, String> {
+    let corrupt = || "Corrupt LZF-compressed string".to_string();
+    let mut output = Vec::with_capacity(expected_len.min(PREALLOCATE_LIMIT * 64));
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let ctrl = input[pos] as usize;
+        pos += 1;
+        if ctrl < 32 {
+            let run = input.get(pos..pos + ctrl + 1).ok_or_else(corrupt)?;
+            output.extend_from_slice(run);
+            pos += ctrl + 1;
+        } else {
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += *input.get(pos).ok_or_else(corrupt)? as usize;
+                pos += 1;
+            }
+            let offset = ((ctrl & 0x1F) << 8) + *input.get(pos).ok_or_else(corrupt)? as usize + 1;
+            pos += 1;
+            let start = output.len().checked_sub(offset).ok_or_else(corrupt)?;
+            // Byte by byte: the copied range may overlap what it produces
+            for i in 0..len + 2 {
+                output.push(output[start + i]);
+            }
+        }
+    }
+
+    if output.len() != expected_len {
+        return Err(corrupt());
+    }
+    Ok(output)
+}
+
+fn parse_ziplist(blob: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let corrupt = || "Corrupt ziplist".to_string();
+    let mut pos = 10; // zlbytes, zltail, zllen
+    let mut entries = Vec::new();
+
+    loop {
+        match *blob.get(pos).ok_or_else(corrupt)? {
+            0xFF => return Ok(entries),
+            0xFE => pos += 5, // prevlen 0xFE + u32
+            _ => pos += 1,
+        }
+
+        let encoding = *blob.get(pos).ok_or_else(corrupt)?;
+        pos += 1;
+        let mut take = |len: usize| -> Result<&[u8], String> {
+            let bytes = blob.get(pos..pos + len).ok_or_else(corrupt)?;
+            pos += len;
+            Ok(bytes)
+        };
+        let entry = match encoding >> 6 {
+            0 => take((encoding & 0x3F) as usize)?.to_vec(),
+            1 => {
+                let len = (((encoding & 0x3F) as usize) << 8) | take(1)?[0] as usize;
+                take(len)?.to_vec()
+            }
+            2 => {
+                let len = u32::from_be_bytes(take(4)?.try_into().map_err(|_| corrupt())?) as usize;
+                take(len)?.to_vec()
+            }
+            _ => {
+                let number: i64 = match encoding {
+                    0xC0 => i16::from_le_bytes(take(2)?.try_into().map_err(|_| corrupt())?) as i64,
+                    0xD0 => i32::from_le_bytes(take(4)?.try_into().map_err(|_| corrupt())?) as i64,
+                    0xE0 => i64::from_le_bytes(take(8)?.try_into().map_err(|_| corrupt())?),
+                    0xF0 => {
+                        let bytes = take(3)?;
+                        (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as i64
+                    }
+                    0xFE => take(1)?[0] as i8 as i64,
+                    0xF1..=0xFD => (encoding & 0x0F) as i64 - 1,
+                    other => return Err(format!("Invalid ziplist entry encoding {:#x}", other)),
+                };
+                number.to_string().into_bytes()
+            }
+        };
+        entries.push(entry);
+    }
+}
+
+fn parse_listpack(blob: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let corrupt = || "Corrupt listpack".to_string();
+    let mut pos = 6; // total bytes, element count
+    let mut entries = Vec::new();
+
+    loop {
+        let encoding = *blob.get(pos).ok_or_else(corrupt)?;
+        if encoding == 0xFF {
+            return Ok(entries);
+        }
+        let start = pos;
+        pos += 1;
+        let mut take = |len: usize| -> Result<&[u8], String> {
+            let bytes = blob.get(pos..pos + len).ok_or_else(corrupt)?;
+            pos += len;
+            Ok(bytes)
+        };
+
+        let entry = if encoding & 0x80 == 0 {
+            (encoding as i64).to_string().into_bytes()
+        } else if encoding & 0xC0 == 0x80 {
+            take((encoding & 0x3F) as usize)?.to_vec()
+        } else if encoding & 0xE0 == 0xC0 {
+            let raw = (((encoding & 0x1F) as i64) << 8) | take(1)?[0] as i64;
+            // 13-bit two's complement
+            let number = if raw >= 1 << 12 { raw - (1 << 13) } else { raw };
+            number.to_string().into_bytes()
+        } else if encoding & 0xF0 == 0xE0 {
+            let len = (((encoding & 0x0F) as usize) << 8) | take(1)?[0] as usize;
+            take(len)?.to_vec()
+        } else {
+            match encoding {
+                0xF0 => {
+                    let len = u32::from_le_bytes(take(4)?.try_into().map_err(|_| corrupt())?) as usize;
+                    take(len)?.to_vec()
+                }
+                0xF1 => i16::from_le_bytes(take(2)?.try_into().map_err(|_| corrupt())?).to_string().into_bytes(),
+                0xF2 => {
+                    let bytes = take(3)?;
+                    (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8).to_string().into_bytes()
+                }
+                0xF3 => i32::from_le_bytes(take(4)?.try_into().map_err(|_| corrupt())?).to_string().into_bytes(),
+                0xF4 => i64::from_le_bytes(take(8)?.try_into().map_err(|_| corrupt())?).to_string().into_bytes(),
+                other => return Err(format!("Invalid listpack entry encoding {:#x}", other)),
+            }
+        };
+
+        // Skip the back-length, which is sized by the entry it follows
+        pos += match pos - start {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        };
+        entries.push(entry);
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), String> {
+    reader.read_exact(buf).map_err(|e| format!("Truncated RDB file: {}", e))
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], String> {
+    let mut buf = [0u8; N];
+    read_exact(reader, &mut buf)?;
+    Ok(buf)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, String> {
+    Ok(read_array::<R, 1>(reader)?[0])
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    reader
+        .take(len as u64)
+        .read_to_end(&mut buf)
+        .map_err(|e| format!("Truncated RDB file: {}", e))?;
+    if buf.len() != len {
+        return Err("Truncated RDB file: unexpected end of file".to_string());
+    }
+    Ok(buf)
+}
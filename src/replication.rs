@@ -0,0 +1,496 @@
+//! Primary→replica replication.
+//!
+//! A replica connects to its primary like any other client and sends
+//! `SYNC`. The primary answers with a full snapshot, then streams the
+//! current state of every key written since, as incremental records in the
+//! snapshot format. Shipping key states rather than the commands that made
+//! them keeps replicas exact for writes whose effect depends on when they
+//! ran, such as relative TTLs, evictions and blocking pops.
+//!
+//! Every write the primary makes while replicas are attached moves its
+//! replication offset on by one. After `SYNC` the connection carries, from
+//! the primary:
+//!
+//! - `FULLSYNC id bytes offset` and a snapshot, where `id` is the primary's
+//!   replication id and the snapshot holds every write up to `offset`
+//! - `DELTA bytes offset` and an incremental backup in chain `id`, numbered
+//!   from 1, that brings the replica up to `offset`
+//! - `PING` after a quiet second, so a replica can tell a dead link from an
+//!   idle one
+//!
+//! and from the replica, `ACK offset` once it has applied each of these.
+//! `WAIT` counts the replicas whose acknowledged offset has reached the
+//! primary's.
+
+use crate::lifecycle::{ClientControl, Lifecycle};
+use crate::snapshot;
+use crate::store::Store;
+use crate::telemetry;
+use crate::timeseries::now_millis;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the primary stays quiet before sending `PING`.
+const HEARTBEAT: Duration = Duration::from_secs(1);
+/// A link silent for this long is dropped; the replica then reconnects
+/// and syncs in full again.
+const LINK_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+// How often a `WAIT` re-checks whether its client was unblocked
+const WAIT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A replica as its primary sees it.
+#[derive(Clone, Debug, Default)]
+pub struct ReplicaInfo {
+    pub id: u64,
+    pub addr: String,
+    /// Keys in the full sync it started with.
+    pub synced_keys: usize,
+    pub deltas_sent: u64,
+    pub keys_sent: u64,
+    /// The offset the replica has confirmed applying.
+    pub acked_offset: u64,
+    /// Unix milliseconds of its last acknowledgement.
+    pub last_ack: Option<u64>,
+}
+
+/// A replica's view of its link to the primary.
+#[derive(Clone, Debug, Default)]
+pub struct PrimaryLink {
+    pub primary: String,
+    pub link_up: bool,
+    pub full_syncs: u64,
+    /// Unix seconds of the last full sync.
+    pub last_sync: Option<u64>,
+    pub keys_applied: u64,
+    /// The primary's offset this replica has applied up to.
+    pub offset: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Feeds {
+    next_id: u64,
+    offset: u64,
+    // Each replica's keys written since its last delta
+    replicas: Vec<(ReplicaInfo, HashSet<String>)>,
+}
+
+/// Replication state shared by the store, the connections streaming to
+/// replicas and, on a replica, the thread following the primary.
+#[derive(Clone)]
+pub struct Replication {
+    id: u64,
+    feeds: Arc<(Mutex<Feeds>, Condvar)>,
+    // Replicas attached, so writes skip the lock while there are none
+    attached: Arc<AtomicUsize>,
+    // Set on a replica; the thread following the primary runs while it is
+    link: Arc<Mutex<Option<PrimaryLink>>>,
+    following: Arc<AtomicBool>,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication {
+            id: telemetry::random_u64(),
+            feeds: Arc::new((Mutex::new(Feeds::default()), Condvar::new())),
+            attached: Arc::new(AtomicUsize::new(0)),
+            link: Arc::new(Mutex::new(None)),
+            following: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Replication {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Chain id of the deltas this server streams; new on every start.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Queue `key` for every attached replica. Called for each write.
+    pub fn record(&self, key: &str) {
+        if self.attached.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let (feeds, changed) = &*self.feeds;
+        if let Ok(mut feeds) = feeds.lock() {
+            feeds.offset += 1;
+            for (_, keys) in &mut feeds.replicas {
+                keys.insert(key.to_string());
+            }
+            changed.notify_all();
+        }
+    }
+
+    /// The replication offset: writes made while replicas were attached.
+    pub fn offset(&self) -> u64 {
+        let (feeds, _) = &*self.feeds;
+        feeds.lock().map(|feeds| feeds.offset).unwrap_or(0)
+    }
+
+    /// Block until `count` replicas have acknowledged every write made
+    /// before the call, `timeout` passes or `interrupted` says so, and
+    /// return how many have.
+    pub fn wait_for_acks(&self, count: usize, timeout: Option<Duration>, interrupted: &dyn Fn() -> bool) -> Result<usize, String> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let (feeds, acked) = &*self.feeds;
+        let mut feeds = feeds.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        let target = feeds.offset;
+        loop {
+            let caught_up = feeds.replicas.iter().filter(|(info, _)| info.acked_offset >= target).count();
+            if caught_up >= count || interrupted() {
+                return Ok(caught_up);
+            }
+            let wait = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => remaining.min(WAIT_CHECK_INTERVAL),
+                    _ => return Ok(caught_up),
+                },
+                None => WAIT_CHECK_INTERVAL,
+            };
+            feeds = acked.wait_timeout(feeds, wait).map_err(|_| "Failed to acquire lock".to_string())?.0;
+        }
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.link.lock().map(|link| link.is_some()).unwrap_or(false)
+    }
+
+    /// The primary this server replicates from, if it is a replica.
+    pub fn primary(&self) -> Option<String> {
+        self.link().map(|link| link.primary)
+    }
+
+    pub fn link(&self) -> Option<PrimaryLink> {
+        self.link.lock().ok().and_then(|link| link.clone())
+    }
+
+    pub fn replicas(&self) -> Vec<ReplicaInfo> {
+        let (feeds, _) = &*self.feeds;
+        feeds.lock().map(|feeds| feeds.replicas.iter().map(|(info, _)| info.clone()).collect()).unwrap_or_default()
+    }
+
+    /// The `# Replication` section of `INFO`.
+    pub fn info(&self) -> String {
+        let mut info = String::from("\n\n# Replication");
+        match self.link() {
+            Some(link) => {
+                info.push_str(&format!(
+                    "\nrole:replica\nprimary:{}\nprimary_link_status:{}\nfull_syncs:{}\nlast_full_sync_time:{}\nkeys_applied:{}\nreplication_offset:{}\nlast_link_error:{}",
+                    link.primary,
+                    if link.link_up { "up" } else { "down" },
+                    link.full_syncs,
+                    link.last_sync.map(|t| t.to_string()).unwrap_or_else(|| "-1".to_string()),
+                    link.keys_applied,
+                    link.offset,
+                    link.last_error.as_deref().unwrap_or("none")
+                ));
+            }
+            None => {
+                let replicas = self.replicas();
+                let offset = self.offset();
+                info.push_str(&format!(
+                    "\nrole:primary\nreplication_id:{:016x}\nreplication_offset:{}\nconnected_replicas:{}",
+                    self.id,
+                    offset,
+                    replicas.len()
+                ));
+                // lag is the writes not yet acknowledged, and ack_age_ms how
+                // long ago the replica last acknowledged any
+                for (i, replica) in replicas.iter().enumerate() {
+                    info.push_str(&format!(
+                        "\nreplica{}:addr={},synced_keys={},deltas_sent={},keys_sent={},offset={},lag={},ack_age_ms={}",
+                        i,
+                        replica.addr,
+                        replica.synced_keys,
+                        replica.deltas_sent,
+                        replica.keys_sent,
+                        replica.acked_offset,
+                        offset.saturating_sub(replica.acked_offset),
+                        replica.last_ack.map(|at| now_millis().saturating_sub(at) as i64).unwrap_or(-1)
+                    ));
+                }
+            }
+        }
+        info
+    }
+
+    fn attach(&self, addr: &str) -> u64 {
+        let (feeds, _) = &*self.feeds;
+        let Ok(mut feeds) = feeds.lock() else { return 0 };
+        feeds.next_id += 1;
+        let id = feeds.next_id;
+        feeds.replicas.push((ReplicaInfo { id, addr: addr.to_string(), ..Default::default() }, HashSet::new()));
+        self.attached.fetch_add(1, Ordering::Relaxed);
+        id
+    }
+
+    fn detach(&self, id: u64) {
+        let (feeds, _) = &*self.feeds;
+        if let Ok(mut feeds) = feeds.lock() {
+            let before = feeds.replicas.len();
+            feeds.replicas.retain(|(info, _)| info.id != id);
+            if feeds.replicas.len() < before {
+                self.attached.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn update_replica<F: FnOnce(&mut ReplicaInfo)>(&self, id: u64, update: F) {
+        let (feeds, _) = &*self.feeds;
+        if let Ok(mut feeds) = feeds.lock() {
+            if let Some((info, _)) = feeds.replicas.iter_mut().find(|(info, _)| info.id == id) {
+                update(info);
+            }
+        }
+    }
+
+    fn acknowledge(&self, id: u64, offset: u64) {
+        let (feeds, acked) = &*self.feeds;
+        if let Ok(mut feeds) = feeds.lock() {
+            if let Some((info, _)) = feeds.replicas.iter_mut().find(|(info, _)| info.id == id) {
+                info.acked_offset = info.acked_offset.max(offset);
+                info.last_ack = Some(now_millis());
+            }
+            acked.notify_all();
+        }
+    }
+
+    // Wait up to `timeout` for writes to stream to replica `id`, and take
+    // them with the offset they bring it up to
+    fn take(&self, id: u64, timeout: Duration) -> Result<(Vec<String>, u64), String> {
+        let (feeds, changed) = &*self.feeds;
+        let feeds = feeds.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        let pending = |feeds: &mut Feeds| feeds.replicas.iter().any(|(info, keys)| info.id == id && !keys.is_empty());
+        let (mut feeds, _) = changed
+            .wait_timeout_while(feeds, timeout, |feeds| !pending(feeds))
+            .map_err(|_| "Failed to acquire lock".to_string())?;
+        let offset = feeds.offset;
+        match feeds.replicas.iter_mut().find(|(info, _)| info.id == id) {
+            Some((_, keys)) => Ok((keys.drain().collect(), offset)),
+            None => Err("Replica detached".to_string()),
+        }
+    }
+
+    fn update_link<F: FnOnce(&mut PrimaryLink)>(&self, update: F) {
+        if let Ok(mut link) = self.link.lock() {
+            if let Some(link) = link.as_mut() {
+                update(link);
+            }
+        }
+    }
+}
+
+/// Stream `store` to the replica on `stream`, which has just sent `SYNC`,
+/// until it disconnects or the server stops.
+pub fn serve_replica(mut stream: TcpStream, store: Store, lifecycle: Lifecycle) {
+    let addr = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_else(|_| "unknown".to_string());
+    let replication = store.replication().clone();
+    // Attached before the snapshot is taken, so no write falls between the two
+    let id = replication.attach(&addr);
+    let (client_id, control) = lifecycle.register_link(&addr, &stream, false);
+    println!("Replica {} attached", addr);
+    match stream.try_clone() {
+        Ok(acks) => {
+            let replication = replication.clone();
+            thread::spawn(move || read_acks(acks, &replication, id));
+        }
+        Err(e) => eprintln!("Warning: Acknowledgements from replica {} will be ignored: {}", addr, e),
+    }
+    match stream_to_replica(&mut stream, &store, &lifecycle, &control, id) {
+        Ok(()) => println!("Replica {} detached", addr),
+        Err(e) => println!("Replica {} detached: {}", addr, e),
+    }
+    replication.detach(id);
+    lifecycle.unregister_client(client_id);
+    // Ends the acknowledgement reader too
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+fn read_acks(stream: TcpStream, replication: &Replication, id: u64) {
+    let _ = stream.set_read_timeout(None);
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { return };
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["ACK", offset] => match offset.parse() {
+                Ok(offset) => replication.acknowledge(id, offset),
+                Err(_) => return,
+            },
+            _ => return,
+        }
+    }
+}
+
+fn stream_to_replica(stream: &mut TcpStream, store: &Store, lifecycle: &Lifecycle, control: &ClientControl, id: u64) -> Result<(), String> {
+    let replication = store.replication();
+    stream.set_write_timeout(Some(LINK_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mut payload = Vec::new();
+    let offset = replication.offset();
+    let keys = snapshot::write_snapshot(store, &mut payload)?;
+    write_frame(stream, &format!("FULLSYNC {} {} {}", replication.id(), payload.len(), offset), &payload)?;
+    replication.update_replica(id, |info| info.synced_keys = keys);
+
+    let mut sequence = 0;
+    while !lifecycle.should_stop() && !control.is_killed() {
+        let (keys, offset) = replication.take(id, HEARTBEAT)?;
+        if keys.is_empty() {
+            stream.write_all(b"PING\n").map_err(|e| e.to_string())?;
+            continue;
+        }
+        sequence += 1;
+        payload.clear();
+        let sent = snapshot::write_changes(&store.key_states(keys)?, replication.id(), sequence, &mut payload)?;
+        write_frame(stream, &format!("DELTA {} {}", payload.len(), offset), &payload)?;
+        replication.update_replica(id, |info| {
+            info.deltas_sent += 1;
+            info.keys_sent += sent as u64;
+        });
+    }
+    Ok(())
+}
+
+fn write_frame(stream: &mut TcpStream, header: &str, payload: &[u8]) -> Result<(), String> {
+    stream
+        .write_all(format!("{}\n", header).as_bytes())
+        .and_then(|()| stream.write_all(payload))
+        .map_err(|e| e.to_string())
+}
+
+/// Make `store` a read-only replica of `primary` (`host:port`), or with
+/// `None` promote it to a primary that keeps its data (`REPLICAOF NO ONE`).
+///
+/// A replica syncs in full, then applies the primary's deltas, reconnecting
+/// and syncing again whenever the link drops, until the server stops or
+/// its primary changes.
+pub fn set_primary(store: &Store, lifecycle: &Lifecycle, primary: Option<String>) {
+    let replication = store.replication().clone();
+    let Ok(mut link) = replication.link.lock() else { return };
+    if link.as_ref().map(|link| &link.primary) == primary.as_ref() {
+        return;
+    }
+    *link = primary.map(|primary| PrimaryLink { primary, ..Default::default() });
+    if link.is_none() || replication.following.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    drop(link);
+
+    let (store, lifecycle) = (store.clone(), lifecycle.clone());
+    thread::spawn(move || while !lifecycle.should_stop() {
+        // Stopping is decided under the lock `set_primary` holds, so a
+        // replica is never left without a thread following its primary
+        let primary = {
+            let Ok(link) = replication.link.lock() else { return };
+            match link.as_ref() {
+                Some(link) => link.primary.clone(),
+                None => {
+                    replication.following.store(false, Ordering::SeqCst);
+                    return;
+                }
+            }
+        };
+
+        let result = follow(&store, &lifecycle, &primary);
+        replication.update_link(|link| {
+            if link.primary == primary {
+                link.link_up = false;
+                link.last_error = result.as_ref().err().cloned();
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Warning: Replication from {} stopped: {}", primary, e);
+            thread::sleep(RETRY_DELAY);
+        }
+    });
+}
+
+fn follow(store: &Store, lifecycle: &Lifecycle, primary: &str) -> Result<(), String> {
+    let stream = TcpStream::connect(primary).map_err(|e| format!("Failed to connect: {}", e))?;
+    let (client_id, control) = lifecycle.register_link(primary, &stream, true);
+    let result = sync_from(store, lifecycle, &control, primary, stream);
+    lifecycle.unregister_client(client_id);
+    result
+}
+
+// Sync in full from the primary on `stream`, then apply its deltas
+fn sync_from(store: &Store, lifecycle: &Lifecycle, control: &ClientControl, primary: &str, mut stream: TcpStream) -> Result<(), String> {
+    let replication = store.replication();
+    stream.set_read_timeout(Some(LINK_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.write_all(b"SYNC\n").map_err(|e| e.to_string())?;
+    let mut acks = stream.try_clone().map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream);
+
+    let mut chain = None;
+    let mut sequence = 0;
+    let mut line = String::new();
+    while !lifecycle.should_stop() && replication.primary().as_deref() == Some(primary) {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) if control.is_killed() => return Err("Link closed by CLIENT KILL".to_string()),
+            Ok(0) => return Err("Primary closed the connection".to_string()),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["FULLSYNC", id, len, offset] => {
+                let offset = parse_offset(offset)?;
+                let payload = read_payload(&mut reader, len)?;
+                store.clear()?;
+                let keys = snapshot::read_snapshot(store, &mut payload.as_slice())?;
+                println!("Full sync from {}: {} keys", primary, keys);
+                chain = id.parse::<u64>().ok();
+                sequence = 0;
+                replication.update_link(|link| {
+                    link.link_up = true;
+                    link.full_syncs += 1;
+                    link.last_sync = Some(now_millis() / 1000);
+                    link.offset = offset;
+                    link.last_error = None;
+                });
+                acknowledge(&mut acks, offset)?;
+            }
+            ["DELTA", len, offset] => {
+                let offset = parse_offset(offset)?;
+                let payload = read_payload(&mut reader, len)?;
+                let (id, number, applied) = snapshot::read_incremental(store, &mut payload.as_slice())?;
+                if Some(id) != chain || number != sequence + 1 {
+                    return Err(format!("Delta {} of chain {:016x} does not follow delta {}", number, id, sequence));
+                }
+                sequence = number;
+                replication.update_link(|link| {
+                    link.keys_applied += applied as u64;
+                    link.offset = offset;
+                });
+                acknowledge(&mut acks, offset)?;
+            }
+            ["PING"] | ["Medusa", "server", "ready"] => {}
+            _ => return Err(format!("Unexpected reply from primary: {}", line.trim())),
+        }
+    }
+    Ok(())
+}
+
+fn acknowledge(stream: &mut TcpStream, offset: u64) -> Result<(), String> {
+    stream.write_all(format!("ACK {}\n", offset).as_bytes()).map_err(|e| e.to_string())
+}
+
+fn parse_offset(offset: &str) -> Result<u64, String> {
+    offset.parse().map_err(|_| format!("Invalid replication offset '{}'", offset))
+}
+
+fn read_payload<R: Read>(reader: &mut R, len: &str) -> Result<Vec<u8>, String> {
+    let len = len.parse::<usize>().map_err(|_| format!("Invalid payload length '{}'", len))?;
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).map_err(|e| format!("Failed to read payload: {}", e))?;
+    Ok(payload)
+}
@@ -0,0 +1,128 @@
+//! Command replies as values. Handlers build a `Reply` and each protocol
+//! writes it its own way: the text protocol as `OK: ...` style lines (its
+//! `Display`) and RESP as typed frames (`resp::encode_reply`), so values
+//! are never parsed back out of a human-readable message.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    /// A bare line such as `PONG` or `QUEUED`; a simple string in RESP.
+    Status(String),
+    /// `OK: message` acknowledging a change; `+OK` in RESP.
+    Ok(String),
+    /// `ERROR: message`.
+    Error(String),
+    /// `NULL: message`; nil in RESP.
+    Nil(String),
+    /// A number and the text protocol line that reports it, as
+    /// `TRUE: Key 'k' exists` for 1.
+    Integer(i64, String),
+    /// `OK: message` answering with a value, which RESP sends alone as a
+    /// bulk string.
+    Bulk(String, String),
+    /// `OK: message`, the message listing the items on indented lines
+    /// where the text protocol shows them; an array in RESP.
+    Array(String, Vec<Reply>),
+    /// One `OK: message` in the text protocol, but a frame per item in
+    /// RESP, as SUBSCRIBE confirms each channel.
+    Each(String, Vec<Reply>),
+}
+
+impl Reply {
+    pub fn status(line: impl Into<String>) -> Self {
+        Reply::Status(line.into())
+    }
+
+    pub fn ok(message: impl Into<String>) -> Self {
+        Reply::Ok(message.into())
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Reply::Error(message.into())
+    }
+
+    pub fn nil(message: impl Into<String>) -> Self {
+        Reply::Nil(message.into())
+    }
+
+    /// `OK: message`, an integer in RESP.
+    pub fn count(n: impl TryInto<i64>, message: impl fmt::Display) -> Self {
+        Reply::Integer(n.try_into().unwrap_or(i64::MAX), format!("OK: {}", message))
+    }
+
+    /// `TRUE: message` or `FALSE: message`, 1 or 0 in RESP.
+    pub fn bool(value: bool, message: impl fmt::Display) -> Self {
+        let tag = if value { "TRUE" } else { "FALSE" };
+        Reply::Integer(value as i64, format!("{}: {}", tag, message))
+    }
+
+    /// `OK: message` carrying `value`.
+    pub fn value(message: impl Into<String>, value: impl Into<String>) -> Self {
+        Reply::Bulk(message.into(), value.into())
+    }
+
+    /// An `OK:` reply whose message is the answer itself, or an item of
+    /// an array.
+    pub fn text(text: impl Into<String>) -> Self {
+        let text = text.into();
+        Reply::Bulk(text.clone(), text)
+    }
+
+    /// `message` naming the items itself, as `Keys: a, b`.
+    pub fn array(message: impl Into<String>, items: impl IntoIterator<Item = String>) -> Self {
+        Reply::Array(message.into(), items.into_iter().map(Reply::text).collect())
+    }
+
+    /// `header` with each item on an indented line under it.
+    pub fn list(header: impl Into<String>, items: impl IntoIterator<Item = String>) -> Self {
+        let mut message = header.into();
+        let items: Vec<Reply> = items.into_iter()
+            .map(|item| {
+                message.push_str("\n  ");
+                message.push_str(&item);
+                Reply::text(item)
+            })
+            .collect();
+        Reply::Array(message, items)
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Reply::Error(_))
+    }
+}
+
+/// The text protocol reply, newline included.
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reply::Status(line) | Reply::Integer(_, line) => writeln!(f, "{}", line),
+            Reply::Ok(message) | Reply::Bulk(message, _) | Reply::Array(message, _) | Reply::Each(message, _) => {
+                writeln!(f, "OK: {}", message)
+            }
+            Reply::Error(message) => writeln!(f, "ERROR: {}", message),
+            Reply::Nil(message) => writeln!(f, "NULL: {}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_replies() {
+        assert_eq!(Reply::status("PONG").to_string(), "PONG\n");
+        assert_eq!(Reply::ok("Set 'k' = 'v'").to_string(), "OK: Set 'k' = 'v'\n");
+        assert_eq!(Reply::error("GET requires a key").to_string(), "ERROR: GET requires a key\n");
+        assert_eq!(Reply::nil("Key 'k' not found").to_string(), "NULL: Key 'k' not found\n");
+        assert_eq!(Reply::count(3usize, "List 'l' has 3 items").to_string(), "OK: List 'l' has 3 items\n");
+        assert_eq!(Reply::bool(false, "Key 'k' does not exist").to_string(), "FALSE: Key 'k' does not exist\n");
+        assert_eq!(Reply::value("'k' = a, b", "a, b").to_string(), "OK: 'k' = a, b\n");
+        assert_eq!(
+            Reply::list("2 pinned:", ["a".to_string(), "b".to_string()]),
+            Reply::Array("2 pinned:\n  a\n  b".to_string(), vec![Reply::text("a"), Reply::text("b")])
+        );
+        assert_eq!(Reply::list("0 pinned:", Vec::new()).to_string(), "OK: 0 pinned:\n");
+    }
+}
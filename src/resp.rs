@@ -0,0 +1,246 @@
+//! RESP2, the Redis wire protocol, so standard Redis client libraries can
+//! talk to Medusa. It is detected per connection on the main port (see
+//! `protocol::detect`) and always spoken on `MEDUSA_RESP_PORT`.
+//!
+//! Requests arrive as arrays of bulk strings (or inline lines, as
+//! `redis-cli` sends when piped). Their arguments go to the same command
+//! implementation as the words of a text protocol line, empty ones and
+//! ones with spaces included, and the `Reply` they return is written as
+//! RESP frames by `encode_reply`.
+
+use crate::reply::Reply;
+use std::io::{BufRead, Write};
+
+/// Largest bulk string a client may send, as in Redis.
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Most arguments one command may have.
+pub const MAX_ARGS: usize = 1024 * 1024;
+
+/// One RESP2 value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    /// `None` is the null bulk string, Redis's nil.
+    Bulk(Option<String>),
+    Array(Vec<Frame>),
+}
+
+impl Frame {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let _ = match self {
+            Frame::Simple(line) => write!(out, "+{}\r\n", line),
+            Frame::Error(message) => write!(out, "-{}\r\n", message),
+            Frame::Integer(n) => write!(out, ":{}\r\n", n),
+            Frame::Bulk(None) => write!(out, "$-1\r\n"),
+            Frame::Bulk(Some(data)) => write!(out, "${}\r\n{}\r\n", data.len(), data),
+            Frame::Array(items) => {
+                let _ = write!(out, "*{}\r\n", items.len());
+                for item in items {
+                    item.encode(out);
+                }
+                Ok(())
+            }
+        };
+    }
+}
+
+// The number after a type marker, as in `*3\r\n` or `$5\r\n`
+fn parse_length(line: &[u8], marker: u8) -> Result<i64, String> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    match line.split_first() {
+        Some((first, digits)) if *first == marker => std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| digits.parse::<i64>().ok())
+            .ok_or_else(|| format!("Protocol error: invalid length '{}'", String::from_utf8_lossy(digits))),
+        _ => Err(format!("Protocol error: expected '{}', got '{}'", marker as char, String::from_utf8_lossy(line))),
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R, line: &mut Vec<u8>) -> Result<(), String> {
+    line.clear();
+    match reader.read_until(b'\n', line) {
+        Ok(0) => Err("Protocol error: connection closed mid-request".to_string()),
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Protocol error: {}", e)),
+    }
+}
+
+fn read_bulk<R: BufRead>(reader: &mut R, length: usize) -> Result<String, String> {
+    let mut data = vec![0; length + 2];
+    reader.read_exact(&mut data).map_err(|e| format!("Protocol error: {}", e))?;
+    if !data.ends_with(b"\r\n") {
+        return Err("Protocol error: bulk string is not followed by CRLF".to_string());
+    }
+    data.truncate(length);
+    String::from_utf8(data).map_err(|_| "Protocol error: argument is not valid UTF-8".to_string())
+}
+
+/// Parse one request off the front of `input`: an array of N bulk
+/// strings, the command name and its arguments. Returns them with the
+/// bytes used, or `None` while the request is still incomplete.
+pub fn parse_command(input: &[u8]) -> Result<Option<(Vec<String>, usize)>, String> {
+    let mut position = 0;
+    let Some(count) = next_length(input, &mut position, b'*')? else { return Ok(None) };
+    if !(0..=MAX_ARGS as i64).contains(&count) {
+        return Err(format!("Protocol error: invalid argument count {}", count));
+    }
+
+    let mut args = Vec::with_capacity((count as usize).min(64));
+    for _ in 0..count {
+        let Some(length) = next_length(input, &mut position, b'$')? else { return Ok(None) };
+        if !(0..=MAX_BULK_LEN as i64).contains(&length) {
+            return Err(format!("Protocol error: invalid bulk length {}", length));
+        }
+        let end = position + length as usize;
+        let Some(data) = input.get(position..end + 2) else { return Ok(None) };
+        if !data.ends_with(b"\r\n") {
+            return Err("Protocol error: bulk string is not followed by CRLF".to_string());
+        }
+        let arg = std::str::from_utf8(&data[..length as usize]).map_err(|_| "Protocol error: argument is not valid UTF-8".to_string())?;
+        args.push(arg.to_string());
+        position = end + 2;
+    }
+    Ok(Some((args, position)))
+}
+
+// The length line at `position`, moving past it
+fn next_length(input: &[u8], position: &mut usize, marker: u8) -> Result<Option<i64>, String> {
+    let rest = &input[*position..];
+    let Some(end) = rest.iter().position(|byte| *byte == b'\n') else { return Ok(None) };
+    let length = parse_length(&rest[..=end], marker)?;
+    *position += end + 1;
+    Ok(Some(length))
+}
+
+/// Read one reply frame, as a client does.
+pub fn read_frame<R: BufRead>(reader: &mut R) -> Result<Frame, String> {
+    let mut line = Vec::new();
+    read_line(reader, &mut line)?;
+    let text = String::from_utf8_lossy(&line);
+    let text = text.trim_end_matches(['\r', '\n']);
+    match line.first() {
+        Some(b'+') => Ok(Frame::Simple(text[1..].to_string())),
+        Some(b'-') => Ok(Frame::Error(text[1..].to_string())),
+        Some(b':') => text[1..].parse().map(Frame::Integer).map_err(|_| format!("Protocol error: invalid integer '{}'", text)),
+        Some(b'$') => match parse_length(&line, b'$')? {
+            -1 => Ok(Frame::Bulk(None)),
+            length if (0..=MAX_BULK_LEN as i64).contains(&length) => Ok(Frame::Bulk(Some(read_bulk(reader, length as usize)?))),
+            length => Err(format!("Protocol error: invalid bulk length {}", length)),
+        },
+        Some(b'*') => {
+            let count = parse_length(&line, b'*')?;
+            (0..count.max(0)).map(|_| read_frame(reader)).collect::<Result<_, _>>().map(Frame::Array)
+        }
+        _ => Err(format!("Protocol error: unknown reply '{}'", text)),
+    }
+}
+
+/// The RESP form of a reply: a status is a simple string and an `OK:`
+/// acknowledgement is `+OK`, an error is prefixed `ERR`, `NULL:` is nil,
+/// numbers are integers, values are bulk strings and lists are arrays.
+pub fn frame(reply: &Reply) -> Frame {
+    match reply {
+        Reply::Status(line) => Frame::Simple(one_line(line)),
+        Reply::Ok(_) => Frame::Simple("OK".to_string()),
+        Reply::Error(message) => Frame::Error(format!("ERR {}", one_line(message))),
+        Reply::Nil(_) => Frame::Bulk(None),
+        Reply::Integer(n, _) => Frame::Integer(*n),
+        Reply::Bulk(_, value) => Frame::Bulk(Some(value.clone())),
+        Reply::Array(_, items) | Reply::Each(_, items) => Frame::Array(items.iter().map(frame).collect()),
+    }
+}
+
+/// Write `reply` to `out`, one frame per item for `Reply::Each`.
+pub fn encode_reply(reply: &Reply, out: &mut Vec<u8>) {
+    match reply {
+        Reply::Each(_, items) => items.iter().for_each(|item| frame(item).encode(out)),
+        reply => frame(reply).encode(out),
+    }
+}
+
+// Simple strings and errors end at the first line break, so any in a
+// message (an argument echoed back, say) become spaces
+fn one_line(line: &str) -> String {
+    line.replace(['\r', '\n'], " ")
+}
+
+/// A published message pushed to a subscriber.
+pub fn message(channel: &str, payload: &str) -> Frame {
+    Frame::Array(["message", channel, payload].iter().map(|part| Frame::Bulk(Some(part.to_string()))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encoded(frame: Frame) -> String {
+        let mut out = Vec::new();
+        frame.encode(&mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let input = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$11\r\nhello world\r\nPING\r\n";
+        let (args, used) = parse_command(input).unwrap().unwrap();
+        assert_eq!(args, ["SET", "k", "hello world"]);
+        assert_eq!(&input[used..], b"PING\r\n");
+
+        // Every prefix of a request is incomplete, not an error
+        for end in 0..used {
+            assert_eq!(parse_command(&input[..end]), Ok(None), "{}", end);
+        }
+        assert!(parse_command(b"*1\r\n$2\r\nhiya\r\n").is_err());
+        assert!(parse_command(b"*1\r\n+OK\r\n").is_err());
+        assert!(parse_command(b"*-1\r\n").is_err());
+        assert!(parse_command(b"*1\r\n$-5\r\n").is_err());
+        assert_eq!(parse_command(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$0\r\n\r\n").unwrap().unwrap().0, ["SET", "k", ""]);
+    }
+
+    #[test]
+    fn test_encode_and_read_frames() {
+        let frame = Frame::Array(vec![
+            Frame::Simple("OK".to_string()),
+            Frame::Error("ERR nope".to_string()),
+            Frame::Integer(-7),
+            Frame::Bulk(None),
+            Frame::Bulk(Some("caf\u{e9}".to_string())),
+        ]);
+        let text = encoded(frame.clone());
+        assert_eq!(text, "*5\r\n+OK\r\n-ERR nope\r\n:-7\r\n$-1\r\n$5\r\ncaf\u{e9}\r\n");
+        assert_eq!(read_frame(&mut Cursor::new(text.into_bytes())).unwrap(), frame);
+    }
+
+    #[test]
+    fn test_reply_frames() {
+        let bulk = |value: &str| Frame::Bulk(Some(value.to_string()));
+        assert_eq!(frame(&Reply::ok("Set 'k' = 'line1\nline2'")), Frame::Simple("OK".to_string()));
+        assert_eq!(frame(&Reply::value("'k' = line1\nline2", "line1\nline2")), bulk("line1\nline2"));
+        assert_eq!(frame(&Reply::status("PONG")), Frame::Simple("PONG".to_string()));
+        assert_eq!(frame(&Reply::error("Unknown command 'a\r\nb'")), Frame::Error("ERR Unknown command 'a  b'".to_string()));
+        assert_eq!(frame(&Reply::nil("Key 'k' not found")), Frame::Bulk(None));
+        assert_eq!(frame(&Reply::bool(true, "Key 'k' exists")), Frame::Integer(1));
+        assert_eq!(frame(&Reply::count(3usize, "List 'l' has 3 items")), Frame::Integer(3));
+        assert_eq!(
+            frame(&Reply::array("Hash 'h' fields: a:b:x, y", ["a:b".to_string(), "x, y".to_string()])),
+            Frame::Array(vec![bulk("a:b"), bulk("x, y")])
+        );
+        assert_eq!(
+            frame(&Reply::Array("2 replies".to_string(), vec![Reply::count(5, 5), Reply::list("1 pinned:", ["a, b".to_string()])])),
+            Frame::Array(vec![Frame::Integer(5), Frame::Array(vec![bulk("a, b")])])
+        );
+
+        let mut out = Vec::new();
+        encode_reply(
+            &Reply::Each("Subscribed to 2 channels".to_string(), vec![Reply::array("", ["a".to_string()]), Reply::count(2, 2)]),
+            &mut out,
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), "*1\r\n$1\r\na\r\n:2\r\n");
+        assert_eq!(encoded(message("news", "hi there")), "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$8\r\nhi there\r\n");
+    }
+}
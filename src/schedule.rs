@@ -0,0 +1,437 @@
+use crate::lifecycle::Lifecycle;
+use crate::snapshot;
+use crate::store::Store;
+use crate::timeseries::now_millis;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const SNAPSHOT_PREFIX: &str = "medusa-";
+const SNAPSHOT_SUFFIX: &str = ".snap";
+// Far enough ahead for "0 0 29 2 *" style schedules, which only fire in leap years
+const MAX_LOOKAHEAD_DAYS: u64 = 366 * 8;
+
+/// A standard five-field cron expression (minute, hour, day of month,
+/// month, day of week), evaluated in UTC. Fields accept `*`, numbers,
+/// ranges (`1-5`), steps (`*/15`, `0-30/10`) and comma lists; day of week
+/// runs 0-6 from Sunday, with 7 also meaning Sunday.
+#[derive(Clone, Debug)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 fields in cron expression '{}'", expression));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(CronSchedule {
+            expression: fields.join(" "),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// The first matching minute strictly after `unix_secs`, as unix seconds.
+    pub fn next_after(&self, unix_secs: u64) -> Option<u64> {
+        let first_minute = unix_secs / 60 + 1;
+        let mut day = first_minute / 1440;
+        let last_day = day + MAX_LOOKAHEAD_DAYS;
+
+        while day <= last_day {
+            if self.matches_day(day) {
+                let start = if day == first_minute / 1440 { first_minute % 1440 } else { 0 };
+                for minute_of_day in start..1440 {
+                    if bit(self.hours, minute_of_day / 60) && bit(self.minutes, minute_of_day % 60) {
+                        return Some((day * 1440 + minute_of_day) * 60);
+                    }
+                }
+            }
+            day += 1;
+        }
+        None
+    }
+
+    // Like cron, a restricted day of month and day of week match if either does
+    fn matches_day(&self, day: u64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        if !bit(self.months, month) {
+            return false;
+        }
+        let weekday = (day + 4) % 7; // 1970-01-01 was a Thursday
+        let day_matches = bit(self.days, day_of_month);
+        let weekday_matches = bit(self.weekdays, weekday);
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday_matches,
+            (false, true) => day_matches,
+            (false, false) => day_matches || weekday_matches,
+        }
+    }
+}
+
+fn bit(mask: u64, n: u64) -> bool {
+    mask & (1 << n) != 0
+}
+
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let invalid = || format!("Invalid cron field '{}' (expected values {}-{})", field, min, max);
+    let mut mask = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse().map_err(|_| invalid())?, end.parse().map_err(|_| invalid())?)
+        } else {
+            let value = range.parse().map_err(|_| invalid())?;
+            // "5/15" means from 5 to the end in steps of 15
+            (value, if part.contains('/') { max } else { value })
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+// Days since the unix epoch to a (year, month, day) date in the proleptic
+// Gregorian calendar (Howard Hinnant's algorithm)
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Snapshot state reported in the `# Persistence` section of `INFO`.
+#[derive(Clone, Debug, Default)]
+pub struct PersistenceState {
+    pub schedule: Option<String>,
+    pub directory: Option<PathBuf>,
+    pub keep: usize,
+    pub next_snapshot: Option<u64>,
+    pub last_snapshot: Option<u64>,
+    pub last_error: Option<String>,
+    pub snapshots_taken: u64,
+    pub snapshots_failed: u64,
+    pub snapshots_retained: usize,
+    /// When the `SAVE` or `BGSAVE` still running began, in unix seconds.
+    pub save_started: Option<u64>,
+    pub last_save: Option<u64>,
+    pub last_save_keys: usize,
+    pub last_save_error: Option<String>,
+    /// `Store::change_count` as of the last successful save.
+    pub changes_at_last_save: u64,
+    /// `MEDUSA_SAVE` rules, if autosave is on.
+    pub autosave: Option<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct PersistenceStatus {
+    inner: Arc<Mutex<PersistenceState>>,
+}
+
+impl PersistenceStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> PersistenceState {
+        self.inner.lock().map(|state| state.clone()).unwrap_or_default()
+    }
+
+    fn update<F: FnOnce(&mut PersistenceState)>(&self, update: F) {
+        if let Ok(mut state) = self.inner.lock() {
+            update(&mut state);
+        }
+    }
+
+    // Claim the one save that may run at a time
+    fn start_save(&self) -> Result<(), String> {
+        let mut state = self.inner.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        if state.save_started.is_some() {
+            return Err("A save is already in progress".to_string());
+        }
+        state.save_started = Some(now_millis() / 1000);
+        Ok(())
+    }
+
+    fn finish_save(&self, result: &Result<usize, String>, changes: u64) {
+        self.update(|state| {
+            state.save_started = None;
+            match result {
+                Ok(keys) => {
+                    state.last_save = Some(now_millis() / 1000);
+                    state.last_save_keys = *keys;
+                    state.last_save_error = None;
+                    state.changes_at_last_save = changes;
+                }
+                Err(e) => state.last_save_error = Some(e.clone()),
+            }
+        });
+    }
+
+    /// Count changes up to `changes` as saved, such as the keys just
+    /// loaded from the save file at startup.
+    pub fn mark_clean(&self, changes: u64) {
+        self.update(|state| state.changes_at_last_save = changes);
+    }
+}
+
+/// `SAVE`: write a snapshot of `store` to `path` now, reporting the result
+/// in `INFO`. Returns the number of keys written.
+pub fn save(store: &Store, path: &Path) -> Result<usize, String> {
+    let status = store.persistence();
+    status.start_save()?;
+    // Changes made while the keys are copied out may or may not be in the
+    // file, so they are counted as unsaved
+    let changes = store.change_count();
+    let result = snapshot::save_file(store, path);
+    status.finish_save(&result, changes);
+    result
+}
+
+/// `BGSAVE`: like `save`, but on a background thread so clients are only
+/// held up while the keys are copied out of the store, not while they are
+/// written. Progress and the outcome show up in `INFO`.
+pub fn save_in_background(store: &Store, path: PathBuf) -> Result<thread::JoinHandle<()>, String> {
+    let status = store.persistence().clone();
+    status.start_save()?;
+    let store = store.clone();
+    let changes = store.change_count();
+    Ok(thread::spawn(move || {
+        let result = snapshot::save_file(&store, &path);
+        match &result {
+            Ok(keys) => println!("Background save of {} keys written to {}", keys, path.display()),
+            Err(e) => eprintln!("Warning: Background save failed: {}", e),
+        }
+        status.finish_save(&result, changes);
+    }))
+}
+
+/// How long autosave waits after a failed save before trying again.
+const AUTOSAVE_RETRY_DELAY: u64 = 5;
+
+/// A Redis-style autosave rule: save once `changes` writes have been made
+/// and `seconds` have passed since the last save.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+impl SaveRule {
+    pub fn is_due(&self, elapsed_secs: u64, changes: u64) -> bool {
+        changes >= self.changes && elapsed_secs >= self.seconds
+    }
+}
+
+/// Parse `MEDUSA_SAVE`: comma-separated `seconds changes` pairs, such as
+/// `900 1,300 100`. An empty spec turns autosave off.
+pub fn parse_save_rules(spec: &str) -> Result<Vec<SaveRule>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let numbers: Vec<&str> = rule.split_whitespace().collect();
+            match numbers.as_slice() {
+                [seconds, changes] => match (seconds.parse(), changes.parse()) {
+                    (Ok(seconds), Ok(changes)) if changes > 0 => Ok(SaveRule { seconds, changes }),
+                    _ => Err(format!("Invalid save rule '{}', expected seconds and a positive change count", rule)),
+                },
+                _ => Err(format!("Invalid save rule '{}', expected 'seconds changes'", rule)),
+            }
+        })
+        .collect()
+}
+
+pub fn describe_save_rules(rules: &[SaveRule]) -> String {
+    rules.iter().map(|rule| format!("{} {}", rule.seconds, rule.changes)).collect::<Vec<_>>().join(",")
+}
+
+/// Save `store` to `path` whenever one of `rules` is met, checking once a
+/// second until the server stops. Time is counted from the last save, or
+/// from startup before the first one.
+pub fn start_autosave(store: Store, lifecycle: Lifecycle, path: PathBuf, rules: Vec<SaveRule>) -> thread::JoinHandle<()> {
+    let status = store.persistence().clone();
+    status.update(|state| state.autosave = Some(describe_save_rules(&rules)));
+    let started = now_millis() / 1000;
+    let mut failed_at = None;
+
+    thread::spawn(move || while !lifecycle.should_stop() {
+        thread::sleep(Duration::from_secs(1));
+        let now = now_millis() / 1000;
+        if failed_at.is_some_and(|failed: u64| now < failed + AUTOSAVE_RETRY_DELAY) {
+            continue;
+        }
+        let state = status.get();
+        let elapsed = now.saturating_sub(state.last_save.unwrap_or(started));
+        let changes = store.change_count().saturating_sub(state.changes_at_last_save);
+        if state.save_started.is_some() || !rules.iter().any(|rule| rule.is_due(elapsed, changes)) {
+            continue;
+        }
+
+        match save(&store, &path) {
+            Ok(keys) => {
+                println!("Autosave: {} changes in {}s, saved {} keys to {}", changes, elapsed, keys, path.display());
+                failed_at = None;
+            }
+            Err(e) => {
+                eprintln!("Warning: Autosave failed: {}", e);
+                failed_at = Some(now);
+            }
+        }
+    })
+}
+
+/// Periodic snapshots into `directory`, keeping the newest `keep` files.
+#[derive(Clone, Debug)]
+pub struct SnapshotSchedule {
+    pub cron: CronSchedule,
+    pub directory: PathBuf,
+    pub keep: usize,
+}
+
+impl SnapshotSchedule {
+    /// Write a snapshot now and prune old ones. Returns the new file's path.
+    pub fn take_snapshot(&self, store: &Store) -> Result<PathBuf, String> {
+        fs::create_dir_all(&self.directory)
+            .map_err(|e| format!("Failed to create {}: {}", self.directory.display(), e))?;
+
+        let path = self.directory.join(format!("{}{}{}", SNAPSHOT_PREFIX, now_millis(), SNAPSHOT_SUFFIX));
+        snapshot::save_file(store, &path)?;
+        self.prune()?;
+        Ok(path)
+    }
+
+    /// Scheduled snapshots in `directory`, oldest first.
+    pub fn snapshots(&self) -> Result<Vec<PathBuf>, String> {
+        let entries = fs::read_dir(&self.directory)
+            .map_err(|e| format!("Failed to read {}: {}", self.directory.display(), e))?;
+        let mut snapshots: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_scheduled_snapshot(path))
+            .collect();
+        // Fixed-width millisecond timestamps sort chronologically by name
+        snapshots.sort();
+        Ok(snapshots)
+    }
+
+    fn prune(&self) -> Result<(), String> {
+        let snapshots = self.snapshots()?;
+        let excess = snapshots.len().saturating_sub(self.keep.max(1));
+        for path in &snapshots[..excess] {
+            fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// The file to restore at startup: whichever of `save_file` and the newest
+/// scheduled snapshot was written last, if either exists.
+pub fn latest_persisted(save_file: Option<&Path>, schedule: Option<&SnapshotSchedule>) -> Option<PathBuf> {
+    let scheduled = schedule.and_then(|schedule| schedule.snapshots().ok()).and_then(|mut snapshots| snapshots.pop());
+    save_file
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(scheduled)
+        .filter_map(|path| fs::metadata(&path).and_then(|metadata| metadata.modified()).ok().map(|modified| (modified, path)))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+fn is_scheduled_snapshot(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix(SNAPSHOT_PREFIX))
+        .and_then(|name| name.strip_suffix(SNAPSHOT_SUFFIX))
+        .is_some_and(|stamp| !stamp.is_empty() && stamp.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Run `schedule` on a background thread for the life of the process,
+/// reporting through `store.persistence()`.
+pub fn start_snapshot_scheduler(store: Store, schedule: SnapshotSchedule) -> thread::JoinHandle<()> {
+    let status = store.persistence().clone();
+    status.update(|state| {
+        state.schedule = Some(schedule.cron.expression().to_string());
+        state.directory = Some(schedule.directory.clone());
+        state.keep = schedule.keep;
+        state.snapshots_retained = schedule.snapshots().map(|s| s.len()).unwrap_or(0);
+    });
+
+    thread::spawn(move || loop {
+        let next = schedule.cron.next_after(now_millis() / 1000);
+        status.update(|state| state.next_snapshot = next);
+        let next = match next {
+            Some(next) => next,
+            None => return,
+        };
+
+        // Wake up at least once a minute so a clock change is noticed
+        loop {
+            let now = now_millis() / 1000;
+            if now >= next {
+                break;
+            }
+            thread::sleep(Duration::from_secs((next - now).min(60)));
+        }
+
+        match schedule.take_snapshot(&store) {
+            Ok(path) => {
+                println!("Scheduled snapshot written to {}", path.display());
+                let retained = schedule.snapshots().map(|s| s.len()).unwrap_or(0);
+                status.update(|state| {
+                    state.last_snapshot = Some(now_millis() / 1000);
+                    state.last_error = None;
+                    state.snapshots_taken += 1;
+                    state.snapshots_retained = retained;
+                });
+            }
+            Err(e) => {
+                eprintln!("Warning: Scheduled snapshot failed: {}", e);
+                status.update(|state| {
+                    state.last_error = Some(e);
+                    state.snapshots_failed += 1;
+                });
+            }
+        }
+    })
+}
@@ -0,0 +1,139 @@
+use crate::index::pattern_matches;
+use crate::store::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Inverted index over the text of string values and selected hash fields.
+///
+/// Keys matching `pattern` are (re)indexed on every write; keys added with
+/// `add_document` are tracked the same way even if they fall outside it.
+#[derive(Clone, Debug)]
+pub struct SearchIndex {
+    pub pattern: String,
+    pub fields: Vec<String>,
+    postings: HashMap<String, HashSet<String>>,
+    documents: HashMap<String, HashSet<String>>,
+    explicit: HashSet<String>,
+}
+
+impl SearchIndex {
+    pub fn new(pattern: &str, fields: Vec<String>) -> Self {
+        SearchIndex {
+            pattern: pattern.to_string(),
+            fields,
+            postings: HashMap::new(),
+            documents: HashMap::new(),
+            explicit: HashSet::new(),
+        }
+    }
+
+    pub fn covers(&self, key: &str) -> bool {
+        self.explicit.contains(key) || pattern_matches(&self.pattern, key)
+    }
+
+    /// Track `key` even if it does not match the index pattern.
+    pub fn add_document(&mut self, key: &str, value: &Value) {
+        self.explicit.insert(key.to_string());
+        self.index_document(key, Some(value));
+    }
+
+    /// Replace whatever was indexed for `key` with the terms of `value`.
+    /// Passing `None` (or a value with no indexable text) removes the key.
+    pub fn index_document(&mut self, key: &str, value: Option<&Value>) {
+        if let Some(old_terms) = self.documents.remove(key) {
+            for term in old_terms {
+                if let Some(keys) = self.postings.get_mut(&term) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+        }
+
+        let text = match value.and_then(|value| self.text_of(value)) {
+            Some(text) => text,
+            None => return,
+        };
+        let terms: HashSet<String> = tokenize(&text).into_iter().collect();
+        if terms.is_empty() {
+            return;
+        }
+        for term in &terms {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(key.to_string());
+        }
+        self.documents.insert(key.to_string(), terms);
+    }
+
+    pub fn clear(&mut self) {
+        self.postings.clear();
+        self.documents.clear();
+        self.explicit.clear();
+    }
+
+    /// Evaluate a query. Whitespace-separated terms are ANDed, the `OR`
+    /// keyword separates alternative clauses, and a trailing `*` turns a
+    /// term into a prefix match.
+    pub fn search(&self, query: &str) -> HashSet<String> {
+        let mut result = HashSet::new();
+
+        for clause in query.split(" OR ") {
+            let mut clause_keys: Option<HashSet<String>> = None;
+            for raw_term in clause.split_whitespace() {
+                let term_keys = self.term_keys(raw_term);
+                clause_keys = Some(match clause_keys {
+                    Some(keys) => keys.intersection(&term_keys).cloned().collect(),
+                    None => term_keys,
+                });
+            }
+            if let Some(keys) = clause_keys {
+                result.extend(keys);
+            }
+        }
+
+        result
+    }
+
+    fn term_keys(&self, raw_term: &str) -> HashSet<String> {
+        let lowered = raw_term.to_lowercase();
+        if let Some(prefix) = lowered.strip_suffix('*') {
+            let prefix: String = prefix.chars().filter(|c| c.is_alphanumeric()).collect();
+            return self.postings.iter()
+                .filter(|(term, _)| term.starts_with(&prefix))
+                .flat_map(|(_, keys)| keys.iter().cloned())
+                .collect();
+        }
+
+        match tokenize(&lowered).first() {
+            Some(term) => self.postings.get(term).cloned().unwrap_or_default(),
+            None => HashSet::new(),
+        }
+    }
+
+    fn text_of(&self, value: &Value) -> Option<String> {
+        match value {
+            Value::String(s) if self.fields.is_empty() => Some(s.clone()),
+            Value::Hash(hash) => {
+                let parts: Vec<&str> = if self.fields.is_empty() {
+                    hash.values().filter_map(|value| std::str::from_utf8(value).ok()).collect()
+                } else {
+                    self.fields.iter()
+                        .filter_map(|field| hash.get(field).and_then(|value| std::str::from_utf8(value).ok()))
+                        .collect()
+                };
+                Some(parts.join(" "))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Lowercase `text` and split it into alphanumeric terms.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
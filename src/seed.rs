@@ -0,0 +1,352 @@
+use crate::store::{Store, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A key as a fixture defines it: value and optional time to live.
+type Fixture = (String, Value, Option<Duration>);
+
+/// What happened to one file in the seed directory.
+#[derive(Debug)]
+pub struct SeedResult {
+    pub path: PathBuf,
+    /// Keys written, or why the file was rejected.
+    pub outcome: Result<usize, String>,
+}
+
+/// Load every `.medusa` and `.json` fixture in `dir` into `store`, in file
+/// name order. Each file is parsed completely before any of its keys are
+/// written, so a file with an error changes nothing. Fixture keys replace
+/// whatever the store holds under the same name, which makes seeding the
+/// same directory again a no-op. Other files are ignored.
+pub fn seed_directory<P: AsRef<Path>>(store: &Store, dir: P) -> Result<Vec<SeedResult>, String> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| matches!(path.extension().and_then(|ext| ext.to_str()), Some("medusa" | "json")))
+        .collect();
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let outcome = seed_file(store, &path);
+            SeedResult { path, outcome }
+        })
+        .collect())
+}
+
+/// Load a single fixture file, returning the number of keys written.
+pub fn seed_file(store: &Store, path: &Path) -> Result<usize, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let fixtures = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => parse_json_fixture(&text)?,
+        _ => parse_medusa_fixture(&text)?,
+    };
+    for (key, value, ttl) in &fixtures {
+        store.import_entry(key, value.clone(), *ttl)?;
+    }
+    Ok(fixtures.len())
+}
+
+/// Parse a `.medusa` fixture: one command per line, in the same syntax the
+/// server accepts. Supported are `SET key value`, `HSET key field value`,
+/// `RPUSH key value` and `EXPIRE key seconds`; blank lines and lines
+/// starting with `#` are skipped. A key set more than once keeps its last
+/// value, and pushes build up a list from empty.
+pub fn parse_medusa_fixture(text: &str) -> Result<Vec<Fixture>, String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut entries: HashMap<String, (Value, Option<Duration>)> = HashMap::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let key = match parts.get(1) {
+            Some(key) => key.to_string(),
+            None => return Err(error("missing key")),
+        };
+        if !entries.contains_key(&key) {
+            keys.push(key.clone());
+        }
+
+        match parts[0].to_uppercase().as_str() {
+            "SET" if parts.len() >= 3 => {
+                entries.insert(key, (Value::new(parts[2..].join(" ")), None));
+            }
+            "HSET" if parts.len() >= 4 => {
+                let entry = entries.entry(key).or_insert_with(|| (Value::new_hash(), None));
+                match &mut entry.0 {
+                    Value::Hash(hash) => {
+                        hash.insert(parts[2].to_string(), parts[3..].join(" ").into_bytes());
+                    }
+                    _ => return Err(error("HSET on a key that is not a hash")),
+                }
+            }
+            "RPUSH" if parts.len() >= 3 => {
+                let entry = entries.entry(key).or_insert_with(|| (Value::new_list(), None));
+                match &mut entry.0 {
+                    Value::List(list) => list.push_back(parts[2..].join(" ")),
+                    _ => return Err(error("RPUSH on a key that is not a list")),
+                }
+            }
+            "EXPIRE" if parts.len() == 3 => {
+                let seconds = parts[2].parse::<u64>().map_err(|_| error("EXPIRE needs a number of seconds"))?;
+                match entries.get_mut(&key) {
+                    Some(entry) => entry.1 = Some(Duration::from_secs(seconds)),
+                    None => return Err(error("EXPIRE before the key is defined")),
+                }
+            }
+            "SET" | "HSET" | "RPUSH" | "EXPIRE" => return Err(error(&format!("wrong number of arguments for {}", parts[0]))),
+            other => return Err(error(&format!("unsupported command '{}'", other))),
+        }
+    }
+
+    Ok(keys
+        .into_iter()
+        .filter_map(|key| entries.remove(&key).map(|(value, ttl)| (key, value, ttl)))
+        .collect())
+}
+
+/// Parse a JSON fixture: an object mapping each key to its value. Strings,
+/// numbers and booleans become string values, arrays become lists and
+/// objects become hashes; list items and hash fields must themselves be
+/// strings, numbers or booleans.
+pub fn parse_json_fixture(text: &str) -> Result<Vec<Fixture>, String> {
+    let mut parser = JsonParser { text: text.as_bytes(), pos: 0 };
+    let document = parser.parse_document()?;
+    let members = match document {
+        Json::Object(members) => members,
+        _ => return Err("top level must be an object of keys".to_string()),
+    };
+
+    let mut fixtures: Vec<Fixture> = Vec::new();
+    for (key, json) in members {
+        let value = match json {
+            Json::Array(items) => {
+                let items: Result<VecDeque<String>, String> =
+                    items.into_iter().map(|item| scalar(item).ok_or_else(|| nested_error(&key))).collect();
+                Value::List(items?)
+            }
+            Json::Object(fields) => {
+                let mut hash = HashMap::new();
+                for (field, item) in fields {
+                    hash.insert(field, scalar(item).ok_or_else(|| nested_error(&key))?.into_bytes());
+                }
+                Value::Hash(hash)
+            }
+            other => Value::new(scalar(other).ok_or_else(|| format!("key '{}': null is not a value", key))?),
+        };
+        // A repeated key keeps its last value, as with JSON objects generally
+        fixtures.retain(|(existing, _, _)| *existing != key);
+        fixtures.push((key, value, None));
+    }
+    Ok(fixtures)
+}
+
+fn nested_error(key: &str) -> String {
+    format!("key '{}': list items and hash fields must be strings, numbers or booleans", key)
+}
+
+fn scalar(json: Json) -> Option<String> {
+    match json {
+        Json::String(s) | Json::Number(s) => Some(s),
+        Json::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+enum Json {
+    Null,
+    Bool(bool),
+    // Kept as written, since values are stored as text anyway
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn parse_document(&mut self) -> Result<Json, String> {
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        if self.pos < self.text.len() {
+            return Err(self.error("unexpected data after the document"));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.pos.min(self.text.len())].iter().filter(|&&b| b == b'\n').count() + 1;
+        format!("line {}: {}", line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => self.parse_string().map(Json::String),
+            Some(b't') => self.parse_literal("true", Json::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Json::Bool(false)),
+            Some(b'n') => self.parse_literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            members.push((key, self.parse_value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        if self.text[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self.pos < self.text.len() && matches!(self.text[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        let number = std::str::from_utf8(&self.text[start..self.pos]).map_err(|_| self.error("invalid number"))?;
+        if number.parse::<f64>().is_err() {
+            return Err(self.error(&format!("invalid number '{}'", number)));
+        }
+        Ok(Json::Number(number.to_string()))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self.text.get(self.pos).ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match byte {
+                b'"' => return String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in string")),
+                b'\\' => {
+                    let escape = *self.text.get(self.pos).ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    let decoded = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    bytes.extend(decoded.to_string().as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+    }
+
+    // After `\u`: four hex digits, or a surrogate pair written as two escapes
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.text[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or_else(|| self.error("invalid unicode escape"))?;
+        let code = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
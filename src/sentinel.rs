@@ -0,0 +1,445 @@
+//! Automatic failover, in the style of Redis Sentinel but run by the data
+//! nodes themselves.
+//!
+//! Every node of a primary and its replicas runs a sentinel. Nodes find
+//! each other by gossip: each check, a node sends the members it knows,
+//! with their epoch and heartbeat, to one of them (`SENTINEL GOSSIP`) and
+//! merges the list it gets back, so listing a single other node in
+//! `MEDUSA_SENTINEL_PEERS` is enough. A member whose heartbeat has not
+//! moved for `down_after` is marked failed and is left out of votes and
+//! the choice of a new primary until it is heard from again.
+//!
+//! A replica that cannot reach its primary for `down_after` asks the
+//! others whether they can (`SENTINEL IS-DOWN`).
+//! Once a quorum agrees the primary is down, the surviving replica with
+//! the lowest address promotes itself and the rest replicate from it. Every
+//! node reaches the same choice on its own, so no election is needed. A
+//! replica that finds a peer already following a new, reachable primary
+//! follows it too. A former primary that comes back and finds a quorum
+//! following someone else rejoins as a replica; writes it took while cut
+//! off are lost.
+//!
+//! Clients with notices enabled hear about each change, and
+//! `SENTINEL PRIMARY` names the primary a node follows.
+
+use crate::lifecycle::Lifecycle;
+use crate::replication;
+use crate::store::Store;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(5);
+// Probes of the primary and the peers give up after this long at most
+const MAX_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct SentinelConfig {
+    /// This node's address as the others reach it.
+    pub announce: String,
+    /// Nodes to gossip with first; the rest of the group is learned
+    /// from them.
+    pub peers: Vec<String>,
+    /// Nodes, this one included, that must find the primary unreachable
+    /// before it is failed over; 0 means a majority of the known group.
+    pub quorum: usize,
+    /// How long the primary must be unreachable before this node
+    /// considers it down.
+    pub down_after: Duration,
+}
+
+impl SentinelConfig {
+    /// Votes needed in a group of `nodes`, this one included.
+    pub fn quorum(&self, nodes: usize) -> usize {
+        match self.quorum {
+            0 => nodes / 2 + 1,
+            quorum => quorum,
+        }
+    }
+}
+
+// The epoch is when a node started, so a restarted node's heartbeats
+// outrank those of its previous run
+type Heartbeat = (u64, u64);
+
+struct Member {
+    heartbeat: Heartbeat,
+    // When the heartbeat last moved
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct SentinelState {
+    config: Option<SentinelConfig>,
+    heartbeat: Heartbeat,
+    members: HashMap<String, Member>,
+    // The primary this node cannot reach, and since when
+    down: Option<(String, Instant)>,
+    failovers: u64,
+}
+
+impl SentinelState {
+    fn is_failed(&self, member: &Member) -> bool {
+        self.config.as_ref().is_some_and(|config| member.updated.elapsed() >= config.down_after)
+    }
+}
+
+/// A node's failover state, answered to peers asking about the primary.
+#[derive(Clone, Default)]
+pub struct Sentinel {
+    inner: Arc<Mutex<SentinelState>>,
+}
+
+impl Sentinel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn config(&self) -> Option<SentinelConfig> {
+        self.inner.lock().ok().and_then(|state| state.config.clone())
+    }
+
+    /// Whether this node has found `primary` unreachable for `down_after`.
+    pub fn is_down(&self, primary: &str) -> bool {
+        let Ok(state) = self.inner.lock() else { return false };
+        match (&state.config, &state.down) {
+            (Some(config), Some((down, since))) => down == primary && since.elapsed() >= config.down_after,
+            _ => false,
+        }
+    }
+
+    /// The `# Sentinel` section of `INFO`, empty unless failover is on.
+    pub fn info(&self) -> String {
+        let Ok(state) = self.inner.lock() else { return String::new() };
+        let Some(config) = &state.config else { return String::new() };
+        let mut members: Vec<String> = state
+            .members
+            .iter()
+            .map(|(addr, member)| format!("{}={}", addr, if state.is_failed(member) { "failed" } else { "up" }))
+            .collect();
+        members.sort();
+        format!(
+            "\n\n# Sentinel\nsentinel_announce:{}\nsentinel_epoch:{}\nsentinel_peers:{}\nsentinel_members:{}\nsentinel_quorum:{}\nsentinel_down_after_ms:{}\nprimary_down_ms:{}\nfailovers:{}",
+            config.announce,
+            state.heartbeat.0,
+            config.peers.join(","),
+            members.join(","),
+            config.quorum(state.members.len() + 1),
+            config.down_after.as_millis(),
+            state.down.as_ref().map(|(_, since)| since.elapsed().as_millis() as i64).unwrap_or(-1),
+            state.failovers
+        )
+    }
+
+    /// Merge a gossiped member list, `addr/epoch/heartbeat` entries
+    /// separated by spaces, and return this node's own list in reply.
+    pub fn gossip(&self, entries: &[&str]) -> Result<String, String> {
+        let Ok(mut state) = self.inner.lock() else { return Err("Failed to acquire lock".to_string()) };
+        let Some(announce) = state.config.as_ref().map(|config| config.announce.clone()) else {
+            return Err("Automatic failover is not enabled on this server".to_string());
+        };
+        for entry in entries {
+            let (addr, heartbeat) = parse_entry(entry).ok_or_else(|| format!("Invalid gossip entry '{}'", entry))?;
+            if addr == announce {
+                continue;
+            }
+            let member = state.members.entry(addr.to_string()).or_insert(Member { heartbeat, updated: Instant::now() });
+            if heartbeat > member.heartbeat {
+                *member = Member { heartbeat, updated: Instant::now() };
+            }
+        }
+        let (epoch, count) = state.heartbeat;
+        let mut reply = vec![format!("{}/{}/{}", announce, epoch, count)];
+        reply.extend(state.members.iter().map(|(addr, member)| format!("{}/{}/{}", addr, member.heartbeat.0, member.heartbeat.1)));
+        Ok(reply.join(" "))
+    }
+
+    /// Members that have not failed, in address order.
+    fn live_peers(&self) -> Vec<String> {
+        let Ok(state) = self.inner.lock() else { return Vec::new() };
+        let mut peers: Vec<String> = state.members.iter().filter(|(_, member)| !state.is_failed(member)).map(|(addr, _)| addr.clone()).collect();
+        peers.sort();
+        peers
+    }
+
+    /// Every member ever heard of, failed or not, in address order.
+    fn all_peers(&self) -> Vec<String> {
+        let Ok(state) = self.inner.lock() else { return Vec::new() };
+        let mut peers: Vec<String> = state.members.keys().cloned().collect();
+        peers.sort();
+        peers
+    }
+
+    fn quorum(&self) -> usize {
+        let Ok(state) = self.inner.lock() else { return usize::MAX };
+        state.config.as_ref().map(|config| config.quorum(state.members.len() + 1)).unwrap_or(usize::MAX)
+    }
+
+    fn set_config(&self, config: SentinelConfig) {
+        if let Ok(mut state) = self.inner.lock() {
+            // Seeds count as up until they have had `down_after` to answer
+            for peer in config.peers.iter().filter(|peer| **peer != config.announce) {
+                state.members.insert(peer.clone(), Member { heartbeat: (0, 0), updated: Instant::now() });
+            }
+            let epoch = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_millis() as u64).unwrap_or(0);
+            state.heartbeat = (epoch, 0);
+            state.config = Some(config);
+        }
+    }
+
+    fn beat(&self) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.heartbeat.1 += 1;
+        }
+    }
+
+    // Note `primary` as unreachable, keeping the time it was first found so
+    fn mark_down(&self, primary: &str) -> Duration {
+        let Ok(mut state) = self.inner.lock() else { return Duration::ZERO };
+        match &state.down {
+            Some((down, since)) if down == primary => since.elapsed(),
+            _ => {
+                state.down = Some((primary.to_string(), Instant::now()));
+                Duration::ZERO
+            }
+        }
+    }
+
+    fn mark_up(&self) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.down = None;
+        }
+    }
+
+    fn count_failover(&self) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.down = None;
+            state.failovers += 1;
+        }
+    }
+}
+
+/// The primary this node follows, or itself if it is the primary, as
+/// `SENTINEL PRIMARY` reports it. `None` on a primary outside a group.
+pub fn current_primary(store: &Store) -> Option<String> {
+    store.replication().primary().or_else(|| store.sentinel().config().map(|config| config.announce))
+}
+
+/// Watch the primary from this node until the server stops, failing over
+/// as described in the module docs.
+pub fn start_sentinel(store: Store, lifecycle: Lifecycle, config: SentinelConfig) -> thread::JoinHandle<()> {
+    store.sentinel().set_config(config.clone());
+    let interval = (config.down_after / 4).clamp(Duration::from_millis(50), Duration::from_secs(1));
+    let mut probe = Probe::new(config.down_after.min(MAX_PROBE_TIMEOUT));
+
+    // Gossip runs apart from the checks, which can wait on an unreachable
+    // primary, so heartbeats keep their pace; a member that takes longer
+    // than a check interval to answer is skipped until the next round
+    let (gossip_store, gossip_lifecycle) = (store.clone(), lifecycle.clone());
+    let mut gossip_probe = Probe::new(interval);
+    thread::spawn(move || {
+        let mut round = 0;
+        while !gossip_lifecycle.should_stop() {
+            thread::sleep(interval);
+            gossip_round(&gossip_store, &mut gossip_probe, round);
+            round += 1;
+        }
+    });
+
+    thread::spawn(move || while !lifecycle.should_stop() {
+        thread::sleep(interval);
+        match store.replication().primary() {
+            Some(primary) => watch_primary(&store, &lifecycle, &config, &mut probe, &primary),
+            None => check_still_primary(&store, &lifecycle, &config, &mut probe),
+        }
+    })
+}
+
+// Gossip with one member, taking each in turn; failed ones too, so a
+// member that comes back is noticed
+fn gossip_round(store: &Store, probe: &mut Probe, round: usize) {
+    let sentinel = store.sentinel();
+    sentinel.beat();
+    let peers = sentinel.all_peers();
+    if peers.is_empty() {
+        return;
+    }
+    let peer = &peers[round % peers.len()];
+    let Ok(entries) = sentinel.gossip(&[]) else { return };
+    if let Ok(reply) = probe.ask(peer, &format!("SENTINEL GOSSIP {}", entries)) {
+        if let Some(entries) = reply.strip_prefix("OK: ") {
+            let _ = sentinel.gossip(&entries.split_whitespace().collect::<Vec<_>>());
+        }
+    }
+}
+
+fn parse_entry(entry: &str) -> Option<(&str, Heartbeat)> {
+    let mut fields = entry.rsplitn(3, '/');
+    let count = fields.next()?.parse().ok()?;
+    let epoch = fields.next()?.parse().ok()?;
+    let addr = fields.next().filter(|addr| !addr.is_empty())?;
+    Some((addr, (epoch, count)))
+}
+
+fn watch_primary(store: &Store, lifecycle: &Lifecycle, config: &SentinelConfig, probe: &mut Probe, primary: &str) {
+    let sentinel = store.sentinel();
+    if probe.ask(primary, "PING").is_ok_and(|reply| reply == "PONG") {
+        sentinel.mark_up();
+        return;
+    }
+    if sentinel.mark_down(primary) < config.down_after {
+        return;
+    }
+
+    // A peer may have failed over already: to another node, which this
+    // one follows too, or to this one, which counts as its vote. Otherwise
+    // once a quorum agrees the lowest addressed replica still following
+    // the old primary takes over
+    let mut votes = 1;
+    let mut chosen_by_peer = false;
+    let mut candidates = vec![config.announce.clone()];
+    for peer in &sentinel.live_peers() {
+        match probe.ask(peer, "SENTINEL PRIMARY").as_deref().ok().and_then(|reply| reply.strip_prefix("OK: ")) {
+            Some(followed) if followed == primary => {
+                candidates.push(peer.clone());
+                if probe.ask(peer, &format!("SENTINEL IS-DOWN {}", primary)).is_ok_and(|reply| reply.starts_with("TRUE:")) {
+                    votes += 1;
+                }
+            }
+            Some(followed) if followed == config.announce => {
+                chosen_by_peer = true;
+                votes += 1;
+            }
+            Some(followed) if probe.ask(followed, "PING").is_ok_and(|reply| reply == "PONG") => {
+                follow(store, lifecycle, followed, &format!("Failover: {} is down, following {}", primary, followed));
+                return;
+            }
+            _ => {}
+        }
+    }
+    if votes < sentinel.quorum() {
+        return;
+    }
+    let chosen = if chosen_by_peer { config.announce.clone() } else { candidates.into_iter().min().unwrap_or_default() };
+    if chosen == config.announce {
+        announce(store, &format!("Failover: {} is down, {} is the new primary", primary, chosen));
+        replication::set_primary(store, lifecycle, None);
+        sentinel.count_failover();
+    } else {
+        follow(store, lifecycle, &chosen, &format!("Failover: {} is down, {} is the new primary", primary, chosen));
+    }
+}
+
+// A primary cut off during a failover steps down once it is back
+fn check_still_primary(store: &Store, lifecycle: &Lifecycle, config: &SentinelConfig, probe: &mut Probe) {
+    let mut followed: HashMap<String, usize> = HashMap::new();
+    for peer in &store.sentinel().live_peers() {
+        if let Some(primary) = probe.ask(peer, "SENTINEL PRIMARY").ok().and_then(|reply| reply.strip_prefix("OK: ").map(str::to_string)) {
+            *followed.entry(primary).or_default() += 1;
+        }
+    }
+    let Some((primary, count)) = followed.into_iter().filter(|(primary, _)| *primary != config.announce).max_by_key(|(_, count)| *count) else { return };
+    if count + 1 >= store.sentinel().quorum() && probe.ask(&primary, "PING").is_ok_and(|reply| reply == "PONG") {
+        follow(store, lifecycle, &primary, &format!("Failover: {} took over as primary, rejoining as its replica", primary));
+    }
+}
+
+fn follow(store: &Store, lifecycle: &Lifecycle, primary: &str, message: &str) {
+    announce(store, message);
+    replication::set_primary(store, lifecycle, Some(primary.to_string()));
+    store.sentinel().count_failover();
+}
+
+// Logged, and sent to clients that enabled notices ahead of their next reply
+fn announce(store: &Store, message: &str) {
+    store.alarms().announce(message.to_string());
+}
+
+/// Connections to the primary and the peers, kept open between checks.
+struct Probe {
+    timeout: Duration,
+    connections: HashMap<String, (BufReader<TcpStream>, TcpStream)>,
+}
+
+impl Probe {
+    fn new(timeout: Duration) -> Self {
+        Probe { timeout, connections: HashMap::new() }
+    }
+
+    // Send one command and read its one-line reply
+    fn ask(&mut self, addr: &str, command: &str) -> Result<String, String> {
+        let result = self.try_ask(addr, command);
+        if result.is_err() {
+            self.connections.remove(addr);
+        }
+        result
+    }
+
+    fn try_ask(&mut self, addr: &str, command: &str) -> Result<String, String> {
+        if !self.connections.contains_key(addr) {
+            let connection = self.connect(addr)?;
+            self.connections.insert(addr.to_string(), connection);
+        }
+        let Some((reader, writer)) = self.connections.get_mut(addr) else { return Err("Not connected".to_string()) };
+        writer.write_all(format!("{}\n", command).as_bytes()).map_err(|e| e.to_string())?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) => return Err("Connection closed".to_string()),
+                Ok(_) if line.trim_end() == "Medusa server ready" => continue,
+                Ok(_) => return Ok(line.trim_end().to_string()),
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    fn connect(&self, addr: &str) -> Result<(BufReader<TcpStream>, TcpStream), String> {
+        let resolved = addr.to_socket_addrs().map_err(|e| e.to_string())?.next().ok_or_else(|| format!("No address for {}", addr))?;
+        let stream = TcpStream::connect_timeout(&resolved, self.timeout).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+        let writer = stream.try_clone().map_err(|e| e.to_string())?;
+        Ok((BufReader::new(stream), writer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentinel(peers: &[&str]) -> Sentinel {
+        let sentinel = Sentinel::new();
+        let peers = peers.iter().map(|peer| peer.to_string()).collect();
+        sentinel.set_config(SentinelConfig { announce: "a:1".to_string(), peers, quorum: 0, down_after: Duration::from_millis(50) });
+        sentinel
+    }
+
+    #[test]
+    fn test_gossip_membership() {
+        let sentinel = sentinel(&["b:1"]);
+        assert_eq!(sentinel.live_peers(), ["b:1"]);
+        assert_eq!(sentinel.quorum(), 2);
+
+        // Members learned from a peer; this node's own entry is skipped
+        sentinel.gossip(&["b:1/7/3", "c:1/9/1", "a:1/1/1"]).unwrap();
+        assert_eq!(sentinel.all_peers(), ["b:1", "c:1"]);
+        assert_eq!(sentinel.quorum(), 2);
+        let reply = sentinel.gossip(&[]).unwrap();
+        assert!(reply.starts_with("a:1/") && reply.contains("b:1/7/3") && reply.contains("c:1/9/1"), "{}", reply);
+
+        // Only a heartbeat that moved keeps a member up
+        std::thread::sleep(Duration::from_millis(60));
+        sentinel.gossip(&["b:1/7/4", "c:1/9/1", "c:1/8/5"]).unwrap();
+        assert_eq!(sentinel.live_peers(), ["b:1"]);
+        assert_eq!(sentinel.all_peers(), ["b:1", "c:1"]);
+        assert!(sentinel.info().contains("sentinel_members:b:1=up,c:1=failed"));
+
+        assert!(sentinel.gossip(&["c:1/x/1"]).is_err());
+        assert!(sentinel.gossip(&["/1/1"]).is_err());
+        assert!(Sentinel::new().gossip(&[]).is_err());
+    }
+}
@@ -1,8 +1,32 @@
+use crate::alarm;
 use crate::client_handler::handle_client_with_timeout;
+use crate::delayed;
+use crate::eviction::{self, EvictionPolicy};
+use crate::extension::CommandRegistry;
+use crate::fairness::{self, Scheduler};
+use crate::handoff;
+use crate::http;
+use crate::latency;
+use crate::lifecycle::Lifecycle;
+use crate::net::{self, TcpTuning};
+use crate::protocol::Protocol;
+use crate::rdb;
+use crate::replication;
+use crate::schedule::{self, SaveRule, SnapshotSchedule};
+use crate::seed;
+use crate::sentinel::{self, SentinelConfig};
+use crate::crdt::{self, CrdtConfig};
+use crate::snapshot::{self, CorruptionPolicy};
 use crate::store::Store;
-use std::net::{TcpListener, TcpStream};
+use crate::telemetry::{self, Tracer};
+use crate::tenant::TenantQuota;
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub struct ServerConfig {
     pub host: String,
@@ -10,6 +34,55 @@ pub struct ServerConfig {
     pub max_connections: usize,
     pub connection_timeout: Duration,
     pub enable_timeouts: bool,
+    pub enable_tracing: bool,
+    pub otlp_endpoint: String,
+    pub tenants: Vec<(String, TenantQuota)>,
+    pub import_rdb: Option<String>,
+    pub enable_fault_injection: bool,
+    pub http_port: Option<u16>,
+    /// Port serving the Redis protocol (RESP2); off if unset.
+    pub resp_port: Option<u16>,
+    pub slowlog_threshold: Duration,
+    pub latency_buckets: Vec<u64>,
+    pub key_history: usize,
+    pub max_memory: usize,
+    pub eviction_policy: EvictionPolicy,
+    pub eviction_samples: usize,
+    pub snapshot_schedule: Option<SnapshotSchedule>,
+    pub alarm_thresholds: Vec<(&'static str, u64)>,
+    pub aliases: Vec<(String, String)>,
+    pub chunk_threshold: usize,
+    /// Live keys the store may hold; 0 means unlimited.
+    pub max_keys: usize,
+    pub pinned_keys: Vec<String>,
+    /// Hand the dataset over in a memfd on `HANDOFF` (experimental).
+    pub handoff_memfd: bool,
+    pub prefix_stats: Option<String>,
+    pub flush_token: Option<String>,
+    pub seed_dir: Option<PathBuf>,
+    /// Snapshot written by `SAVE` and loaded at startup.
+    pub save_file: Option<PathBuf>,
+    /// Directory `BACKUP` paths are relative to.
+    pub backup_dir: Option<PathBuf>,
+    /// Directory `IMPORT` paths are relative to.
+    pub import_dir: Option<PathBuf>,
+    /// Save to `save_file` when one of these is met; empty disables autosave.
+    pub save_rules: Vec<SaveRule>,
+    /// What startup does when the file it would restore is damaged.
+    pub corrupt_snapshot: CorruptionPolicy,
+    /// Primary to replicate from, as `host:port`.
+    pub replica_of: Option<String>,
+    /// Automatic failover among this server and its peers.
+    pub sentinel: Option<SentinelConfig>,
+    /// Multi-primary mode: peers to exchange writes with.
+    pub crdt: Option<CrdtConfig>,
+    pub tcp: TcpTuning,
+    /// Commands a pipelining client runs per turn; 0 disables scheduling.
+    pub fair_quantum: usize,
+    /// Threads connections are multiplexed over; 0 means one per CPU core.
+    pub worker_threads: usize,
+    /// Commands added by the embedding program; see [`CommandRegistry`].
+    pub commands: CommandRegistry,
 }
 
 impl Default for ServerConfig {
@@ -20,16 +93,101 @@ impl Default for ServerConfig {
             max_connections: 100,
             connection_timeout: Duration::from_secs(30),
             enable_timeouts: false,
+            enable_tracing: false,
+            otlp_endpoint: "http://127.0.0.1:4318".to_string(),
+            tenants: Vec::new(),
+            import_rdb: None,
+            enable_fault_injection: false,
+            http_port: None,
+            resp_port: None,
+            slowlog_threshold: Duration::from_millis(10),
+            latency_buckets: latency::DEFAULT_BUCKETS_MICROS.to_vec(),
+            key_history: 0,
+            max_memory: 0,
+            eviction_policy: EvictionPolicy::NoEviction,
+            eviction_samples: eviction::DEFAULT_SAMPLES,
+            snapshot_schedule: None,
+            alarm_thresholds: Vec::new(),
+            aliases: Vec::new(),
+            chunk_threshold: 0,
+            max_keys: 0,
+            pinned_keys: Vec::new(),
+            handoff_memfd: false,
+            prefix_stats: None,
+            flush_token: None,
+            seed_dir: None,
+            save_file: None,
+            backup_dir: None,
+            import_dir: None,
+            save_rules: Vec::new(),
+            corrupt_snapshot: CorruptionPolicy::Refuse,
+            replica_of: None,
+            sentinel: None,
+            crdt: None,
+            tcp: TcpTuning::default(),
+            fair_quantum: fairness::DEFAULT_QUANTUM,
+            worker_threads: 0,
+            commands: CommandRegistry::new(),
         }
     }
 }
 
-pub fn start_server_with_config(config: ServerConfig) {
-    let address = format!("{}:{}", config.host, config.port);
+/// A server running on its own thread, returned by
+/// [`start_server_with_config`]. Dropping the handle leaves the server
+/// running; `stop` shuts it down and `join` waits for it.
+pub struct ServerHandle {
+    addr: SocketAddr,
+    lifecycle: Lifecycle,
+    thread: thread::JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// The address the server is listening on, with the real port when
+    /// it was started on port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The server's lifecycle, to inspect its clients or start a drain.
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    /// Disconnect every client and wait for the server to finish.
+    pub fn stop(self) {
+        self.lifecycle.start_drain(Duration::ZERO);
+        self.lifecycle.kill_clients(|_| true);
+        // Wake the accept loop so it notices the drain
+        let _ = TcpStream::connect(self.addr);
+        self.join();
+    }
+
+    /// Wait until the server stops on its own, after a drain or handoff.
+    pub fn join(self) {
+        let _ = self.thread.join();
+    }
+}
+
+/// Bind the listener (or adopt one handed off by a previous process) and
+/// serve it on a new thread. The listener is bound before this returns, so
+/// clients can connect straight away.
+pub fn start_server_with_config(config: ServerConfig) -> Result<ServerHandle, String> {
+    let primary = net::format_address(net::split_hosts(&config.host)[0], config.port);
 
     println!("Starting Medusa server...");
-    println!("Address: {}", address);
+    println!("Address: {}", net::format_addresses(&config.host, config.port));
     println!("Max connections: {}", config.max_connections);
+    if config.max_memory > 0 {
+        println!("Max memory: {} bytes ({})", config.max_memory, config.eviction_policy.name());
+    }
+    println!("TCP: {}", config.tcp.describe());
+    match config.fair_quantum {
+        0 => println!("Fair scheduling: Disabled"),
+        quantum => println!("Fair scheduling: {} commands per turn", quantum),
+    }
+    if config.worker_threads > 0 {
+        println!("Worker threads: {}", config.worker_threads);
+    }
     println!(
         "Timeouts: {}",
         if config.enable_timeouts {
@@ -42,46 +200,372 @@ pub fn start_server_with_config(config: ServerConfig) {
         println!("Connection timeout: {:?}", config.connection_timeout);
     }
 
-    let listener = match TcpListener::bind(&address) {
-        Ok(listener) => {
-            println!("Server bound successfully to {}", address);
+    let inherited = handoff::inherited_listener();
+    // A handoff restores from the previous process, not from disk
+    if inherited.is_none() {
+        check_persisted(&config)?;
+    }
+    if config.crdt.is_some() && (config.replica_of.is_some() || config.sentinel.is_some()) {
+        return Err("Refusing to start: multi-primary mode (MEDUSA_CRDT_PEERS) cannot be combined with MEDUSA_REPLICAOF or MEDUSA_SENTINEL_PEERS".to_string());
+    }
+    let listener = match inherited {
+        Some(listener) => {
+            println!("Adopted listening socket from previous process");
             listener
         }
+        None => match net::bind_with_backlog(net::split_hosts(&config.host)[0], config.port, config.tcp.backlog) {
+            Ok(listener) => {
+                println!("Server bound successfully to {}", primary);
+                listener
+            }
+            Err(e) => return Err(format!("Failed to bind to {}: {}", primary, e)),
+        },
+    };
+
+    let addr = listener.local_addr().map_err(|e| format!("Failed to read listener address: {}", e))?;
+    let lifecycle = Lifecycle::new();
+    let thread = {
+        let lifecycle = lifecycle.clone();
+        thread::spawn(move || serve(listener, config, lifecycle))
+    };
+    Ok(ServerHandle { addr, lifecycle, thread })
+}
+
+// Check the file startup will restore before anything is loaded from it, so
+// a damaged one is not taken for a whole one
+fn check_persisted(config: &ServerConfig) -> Result<(), String> {
+    let Some(path) = schedule::latest_persisted(config.save_file.as_deref(), config.snapshot_schedule.as_ref()) else { return Ok(()) };
+    let Err(e) = snapshot::check_file(&path) else { return Ok(()) };
+    match config.corrupt_snapshot {
+        CorruptionPolicy::Refuse => Err(format!(
+            "Refusing to start: {} is damaged: {}. Repair or remove it, or set MEDUSA_CORRUPT_SNAPSHOT=truncate to load the records before the damage",
+            path.display(),
+            e
+        )),
+        CorruptionPolicy::Truncate => {
+            eprintln!("Warning: {} is damaged: {}", path.display(), e);
+            Ok(())
+        }
+    }
+}
+
+/// Run the server on an already bound listener until a drain completes or
+/// the listener is handed off. `lifecycle` lets the caller drain it.
+pub fn serve(listener: TcpListener, config: ServerConfig, lifecycle: Lifecycle) {
+    let tracer = if config.enable_tracing {
+        match Tracer::otlp(&config.otlp_endpoint, "medusa") {
+            Ok(tracer) => {
+                println!("Exporting traces to {}", config.otlp_endpoint);
+                tracer
+            }
+            Err(e) => {
+                eprintln!("Warning: Tracing disabled: {}", e);
+                Tracer::disabled()
+            }
+        }
+    } else {
+        Tracer::disabled()
+    };
+
+    let store = Store::with_commands(config.commands.clone());
+    store.faults().set_enabled(config.enable_fault_injection);
+    store.slowlog().set_threshold(config.slowlog_threshold);
+    store.latency().set_buckets(config.latency_buckets.clone());
+    store.history().set_depth(config.key_history);
+    store.eviction().set_max_memory(config.max_memory);
+    store.eviction().set_policy(config.eviction_policy);
+    store.eviction().set_samples(config.eviction_samples);
+    for pattern in &config.pinned_keys {
+        store.eviction().pin(pattern);
+    }
+    store.set_chunk_threshold(config.chunk_threshold);
+    store.set_save_file(config.save_file.clone());
+    store.set_backup_dir(config.backup_dir.clone());
+    store.set_import_dir(config.import_dir.clone());
+    store.set_max_keys(config.max_keys);
+    store.stats().track_prefixes(config.prefix_stats.as_deref());
+    if let Some(token) = config.flush_token {
+        let token = if token.eq_ignore_ascii_case("random") {
+            let token = format!("{:016x}", telemetry::random_u64());
+            println!("FLUSHALL confirmation token: {}", token);
+            token
+        } else {
+            token
+        };
+        store.set_flush_token(Some(token));
+    }
+    for (name, quota) in config.tenants {
+        if let Err(e) = store.set_tenant(&name, quota) {
+            eprintln!("Warning: Could not register tenant '{}': {}", name, e);
+        }
+    }
+    for (metric, threshold) in config.alarm_thresholds {
+        if let Err(e) = store.alarms().set_threshold(metric, Some(threshold)) {
+            eprintln!("Warning: Could not set alarm '{}': {}", metric, e);
+        }
+    }
+    for (name, command) in config.aliases {
+        if let Err(e) = store.aliases().set(&name, &command) {
+            eprintln!("Warning: Could not define alias '{}': {}", name, e);
+        }
+    }
+    alarm::start_alarm_monitor(store.clone(), lifecycle.clone(), Duration::from_secs(1));
+    delayed::start_delay_mover(store.clone(), lifecycle.clone(), Duration::from_millis(100));
+    let scheduler = Scheduler::new(config.fair_quantum);
+    let accepting = Arc::new(AtomicBool::new(true));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        lifecycle.set_listener_fd(listener.as_raw_fd());
+    }
+    lifecycle.set_handoff_in_memory(config.handoff_memfd);
+
+    match handoff::restore_handoff_snapshot(&store) {
+        Some(Ok(keys)) => println!("Restored {} keys handed off by previous process", keys),
+        Some(Err(e)) => eprintln!("Warning: Could not restore handoff snapshot: {}", e),
+        None => {
+            // No save file or snapshot yet is a first start, not an error
+            if let Some(path) = schedule::latest_persisted(config.save_file.as_deref(), config.snapshot_schedule.as_ref()) {
+                let started = Instant::now();
+                match snapshot::load_file(&store, &path) {
+                    Ok(keys) => {
+                        println!("Loaded {} keys from {} in {:.1?}", keys, path.display(), started.elapsed());
+                        // A scheduled snapshot leaves the save file behind, so autosave still has work to do
+                        if config.save_file.as_deref() == Some(path.as_path()) {
+                            store.persistence().mark_clean(store.change_count());
+                        }
+                    }
+                    Err(e) if config.corrupt_snapshot == CorruptionPolicy::Truncate => match snapshot::load_file_truncated(&store, &path) {
+                        Ok((keys, damage)) => println!(
+                            "Loaded {} keys from {} in {:.1?}, stopped at: {}",
+                            keys,
+                            path.display(),
+                            started.elapsed(),
+                            damage.unwrap_or(e)
+                        ),
+                        Err(e) => eprintln!("Warning: Could not load {}: {}", path.display(), e),
+                    },
+                    Err(e) => eprintln!("Warning: Could not load {}: {}", path.display(), e),
+                }
+            }
+            if let Some(path) = &config.import_rdb {
+                match rdb::load_rdb_file(&store, path) {
+                    Ok(import) => println!("Imported {}: {}", path, import.describe()),
+                    Err(e) => eprintln!("Warning: Could not import {}: {}", path, e),
+                }
+            }
+            if let Some(dir) = &config.seed_dir {
+                match seed::seed_directory(&store, dir) {
+                    Ok(results) => {
+                        for result in results {
+                            match result.outcome {
+                                Ok(keys) => println!("Seeded {} keys from {}", keys, result.path.display()),
+                                Err(e) => eprintln!("Warning: Could not seed from {}: {}", result.path.display(), e),
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("Warning: Could not seed: {}", e),
+                }
+            }
+        }
+    }
+    report_startup_check(&store);
+
+    if let Some(primary) = config.replica_of.clone() {
+        println!("Replicating from {}", primary);
+        replication::set_primary(&store, &lifecycle, Some(primary));
+    }
+    if let Some(crdt_config) = config.crdt.clone() {
+        println!("Multi-primary: exchanging writes with {}", crdt_config.peers.join(", "));
+        crdt::start_crdt(&store, &lifecycle, crdt_config);
+    }
+    if let Some(sentinel_config) = config.sentinel.clone() {
+        println!("Sentinel: watching the primary, gossiping with {}", sentinel_config.peers.join(", "));
+        sentinel::start_sentinel(store.clone(), lifecycle.clone(), sentinel_config);
+    }
+
+    if let Some(snapshot_schedule) = config.snapshot_schedule {
+        println!(
+            "Scheduled snapshots: '{}' UTC into {}",
+            snapshot_schedule.cron.expression(),
+            snapshot_schedule.directory.display()
+        );
+        schedule::start_snapshot_scheduler(store.clone(), snapshot_schedule);
+    }
+
+    if !config.save_rules.is_empty() {
+        match &config.save_file {
+            Some(path) => {
+                println!("Autosave: '{}' into {}", schedule::describe_save_rules(&config.save_rules), path.display());
+                schedule::start_autosave(store.clone(), lifecycle.clone(), path.clone(), config.save_rules);
+            }
+            None => eprintln!("Warning: MEDUSA_SAVE is set but MEDUSA_SAVE_FILE is not, autosave is off"),
+        }
+    }
+
+    if let Some(http_port) = config.http_port {
+        for host in net::split_hosts(&config.host) {
+            let http_address = net::format_address(host, http_port);
+            match net::bind(host, http_port) {
+                Ok(http_listener) => {
+                    println!("Dashboard available at http://{}/", http_address);
+                    http::start_http_server(http_listener, store.clone(), lifecycle.clone());
+                }
+                Err(e) => eprintln!("Warning: Dashboard disabled, could not bind {}: {}", http_address, e),
+            }
+        }
+    }
+
+    if let Ok(local_addr) = listener.local_addr() {
+        spawn_shutdown_watcher(lifecycle.clone(), local_addr, accepting.clone());
+    }
+
+    let clients = Clients {
+        store,
+        tracer,
+        lifecycle: lifecycle.clone(),
+        scheduler,
+        max_connections: config.max_connections,
+        timeout: config.enable_timeouts.then_some(config.connection_timeout),
+        tcp: config.tcp,
+        count: Arc::new(AtomicUsize::new(0)),
+    };
+
+    // Connections are tasks multiplexed over a fixed set of worker
+    // threads, so idle ones are cheap
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if config.worker_threads > 0 {
+        builder.worker_threads(config.worker_threads);
+    }
+    let runtime = match builder.thread_name("medusa-worker").enable_all().build() {
+        Ok(runtime) => runtime,
         Err(e) => {
-            eprintln!("Failed to bind to {}: {}", address, e);
+            eprintln!("Failed to start the connection runtime: {}", e);
             return;
         }
     };
 
-    if let Err(e) = listener.set_nonblocking(false) {
-        eprintln!("Warning: Could not set non-blocking mode: {}", e);
+    // Further hosts share the primary listener's port, so port 0 gives
+    // every address the same ephemeral port
+    let port = listener.local_addr().map(|addr| addr.port()).unwrap_or(config.port);
+    for host in net::split_hosts(&config.host).into_iter().skip(1) {
+        let address = net::format_address(host, port);
+        match net::bind_with_backlog(host, port, clients.tcp.backlog) {
+            Ok(listener) => {
+                println!("Also listening on {}", address);
+                spawn_listener(&runtime, listener, Protocol::Auto, &clients);
+            }
+            Err(e) => eprintln!("Warning: Could not bind {}: {}", address, e),
+        }
     }
 
-    let store = Store::new();
-    let mut connection_count = 0;
+    if let Some(resp_port) = config.resp_port {
+        for host in net::split_hosts(&config.host) {
+            let resp_address = net::format_address(host, resp_port);
+            match net::bind_with_backlog(host, resp_port, clients.tcp.backlog) {
+                Ok(resp_listener) => {
+                    println!("Redis protocol (RESP2) available on {}", resp_address);
+                    spawn_listener(&runtime, resp_listener, Protocol::Resp, &clients);
+                }
+                Err(e) => eprintln!("Warning: Redis protocol disabled, could not bind {}: {}", resp_address, e),
+            }
+        }
+    }
 
     println!("Medusa server is ready! Waiting for connections...\n");
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                connection_count += 1;
+    runtime.block_on(accept_connections(listener, Protocol::Auto, clients));
+    accepting.store(false, Ordering::SeqCst);
+
+    // After a handoff the new process owns the socket, but clients already
+    // connected here still get their grace period
+    while lifecycle.is_handed_off() && !lifecycle.should_stop() {
+        thread::sleep(Duration::from_millis(100));
+    }
+    // Clients still blocked in a command are not waited for
+    runtime.shutdown_background();
+}
+
+// Accept on a listener besides the primary one, until a drain completes
+// or the server is handed off
+fn spawn_listener(runtime: &tokio::runtime::Runtime, listener: TcpListener, protocol: Protocol, clients: &Clients) {
+    let accepting = Arc::new(AtomicBool::new(true));
+    if let Ok(local_addr) = listener.local_addr() {
+        spawn_shutdown_watcher(clients.lifecycle.clone(), local_addr, accepting.clone());
+    }
+    let clients = clients.clone();
+    runtime.spawn(async move {
+        accept_connections(listener, protocol, clients).await;
+        accepting.store(false, Ordering::SeqCst);
+    });
+}
+
+/// What each accepted connection is handed, shared by the listeners.
+#[derive(Clone)]
+struct Clients {
+    store: Store,
+    tracer: Tracer,
+    lifecycle: Lifecycle,
+    scheduler: Scheduler,
+    max_connections: usize,
+    timeout: Option<Duration>,
+    tcp: TcpTuning,
+    // Connections accepted on any listener
+    count: Arc<AtomicUsize>,
+}
+
+// Serve each connection on `listener` in its own task until a drain
+// completes or the listener is handed off
+async fn accept_connections(listener: TcpListener, protocol: Protocol, clients: Clients) {
+    let listener = match listener.set_nonblocking(true).and_then(|()| tokio::net::TcpListener::from_std(listener)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("❌ Failed to register listener: {}", e);
+            return;
+        }
+    };
+    let lifecycle = &clients.lifecycle;
+    loop {
+        match listener.accept().await.and_then(|(stream, _)| stream.into_std()) {
+            Ok(mut stream) => {
+                if lifecycle.is_handed_off() {
+                    println!("Listener handed off, no longer accepting connections");
+                    break;
+                }
+                if lifecycle.should_stop() {
+                    println!("Drain complete, shutting down");
+                    break;
+                }
+                if lifecycle.is_draining() {
+                    let refusal = match protocol {
+                        Protocol::Resp => "-ERR Server is draining, please reconnect elsewhere\r\n",
+                        _ => "ERROR: Server is draining, please reconnect elsewhere\n",
+                    };
+                    let _ = stream.write_all(refusal.as_bytes());
+                    continue;
+                }
 
-                if connection_count > config.max_connections {
+                // A slot is taken here and given back when the connection's
+                // task ends
+                if !lifecycle.try_connection_opened(clients.max_connections) {
                     eprintln!(
                         "Max connections reached ({}), rejecting new connection",
-                        config.max_connections
+                        clients.max_connections
                     );
+                    let refusal = match protocol {
+                        Protocol::Resp => "-ERR max clients reached\r\n",
+                        _ => "ERROR: Max clients reached\n",
+                    };
+                    let _ = stream.write_all(refusal.as_bytes());
                     continue;
                 }
+                let connection_count = clients.count.fetch_add(1, Ordering::SeqCst) + 1;
 
-                if config.enable_timeouts {
-                    if let Err(e) = configure_client_socket(&stream, config.connection_timeout) {
-                        eprintln!("⚠️  Warning: Could not configure client socket: {}", e);
-                    }
+                if let Err(e) = net::tune_stream(&stream, &clients.tcp) {
+                    eprintln!("⚠️  Warning: Could not configure client socket: {}", e);
                 }
 
-                let store_clone = store.clone();
                 let client_addr = match stream.peer_addr() {
                     Ok(addr) => addr.to_string(),
                     Err(_) => "unknown".to_string(),
@@ -89,13 +573,19 @@ pub fn start_server_with_config(config: ServerConfig) {
 
                 println!(" New connection #{} from {}", connection_count, client_addr);
 
-                thread::spawn(move || {
+                let clients = clients.clone();
+                tokio::spawn(async move {
                     handle_client_with_timeout(
                         stream,
-                        store_clone,
-                        config.enable_timeouts,
-                        config.connection_timeout,
-                    );
+                        clients.store,
+                        clients.timeout,
+                        clients.tracer,
+                        clients.lifecycle.clone(),
+                        clients.scheduler,
+                        protocol,
+                    )
+                    .await;
+                    clients.lifecycle.connection_closed();
                     println!(
                         "Connection #{} from {} closed",
                         connection_count, client_addr
@@ -109,17 +599,52 @@ pub fn start_server_with_config(config: ServerConfig) {
     }
 }
 
-fn configure_client_socket(stream: &TcpStream, timeout: Duration) -> std::io::Result<()> {
-    stream.set_read_timeout(Some(timeout))?;
-    stream.set_write_timeout(Some(timeout))?;
-    stream.set_nodelay(true)?;
-    Ok(())
+// Summarize what the server starts with, and warn if a restore already
+// puts it over a limit that would make writes evict or fail
+fn report_startup_check(store: &Store) {
+    let (distribution, memory) = match (store.keyspace_distribution(None), store.prefix_usage("")) {
+        (Ok(distribution), Ok((_, memory))) => (distribution, memory),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Warning: Startup check failed: {}", e);
+            return;
+        }
+    };
+    let types: Vec<String> = distribution.types.iter().map(|(name, count)| format!("{} {}", count, name)).collect();
+    println!(
+        "Startup check: {} keys ({}), {} with a TTL, about {} bytes",
+        distribution.total,
+        if types.is_empty() { "empty".to_string() } else { types.join(", ") },
+        distribution.volatile,
+        memory
+    );
+
+    let max_memory = store.eviction().max_memory();
+    if max_memory > 0 && memory > max_memory {
+        eprintln!("Warning: The dataset is already over MEDUSA_MAXMEMORY ({} > {} bytes)", memory, max_memory);
+    }
+    if let Some(max_keys) = store.max_keys().filter(|max_keys| distribution.total > *max_keys) {
+        eprintln!("Warning: The dataset already has more keys than MEDUSA_MAX_KEYS ({} > {})", distribution.total, max_keys);
+    }
+}
+
+// The accept loop only notices a finished drain or a handoff when it wakes
+// up, so poke it with throwaway connections until it has stopped
+fn spawn_shutdown_watcher(lifecycle: Lifecycle, local_addr: SocketAddr, accepting: Arc<AtomicBool>) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(100));
+        if !accepting.load(Ordering::SeqCst) {
+            return;
+        }
+        if lifecycle.is_handed_off() || lifecycle.should_stop() {
+            let _ = TcpStream::connect(local_addr);
+        }
+    });
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::net::TcpStream;
+    use std::net::{TcpListener, TcpStream};
     use std::time::Duration;
 
     #[test]
@@ -139,7 +664,7 @@ mod tests {
 
         // Test client connection
         let client_stream = TcpStream::connect(addr).unwrap();
-        let result = configure_client_socket(&client_stream, Duration::from_secs(10));
-        assert!(result.is_ok());
+        assert!(net::tune_stream(&client_stream, &TcpTuning::default()).is_ok());
+        assert!(client_stream.nodelay().unwrap());
     }
 }
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_THRESHOLD: Duration = Duration::from_millis(10);
+const MAX_ENTRIES: usize = 128;
+// Long commands (bulk VADD, FT.ADD...) are cut down in the log
+const MAX_COMMAND_LEN: usize = 128;
+
+#[derive(Clone, Debug)]
+pub struct SlowLogEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub duration: Duration,
+    pub command: String,
+    pub client: String,
+    /// The client's request tag (`*TAG id command`), if it sent one.
+    pub tag: Option<String>,
+}
+
+/// The most recent commands that took longer than the threshold.
+#[derive(Clone)]
+pub struct SlowLog {
+    entries: Arc<Mutex<VecDeque<SlowLogEntry>>>,
+    threshold_micros: Arc<AtomicU64>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Default for SlowLog {
+    fn default() -> Self {
+        SlowLog {
+            entries: Arc::new(Mutex::new(VecDeque::new())),
+            threshold_micros: Arc::new(AtomicU64::new(DEFAULT_THRESHOLD.as_micros() as u64)),
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl SlowLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn threshold(&self) -> Duration {
+        Duration::from_micros(self.threshold_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn set_threshold(&self, threshold: Duration) {
+        self.threshold_micros.store(threshold.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Log `command` if it ran for at least the threshold.
+    pub fn record(&self, command: &str, duration: Duration, client: &str) {
+        self.record_tagged(command, duration, client, None);
+    }
+
+    /// `record` for a command the client tagged with a request id.
+    pub fn record_tagged(&self, command: &str, duration: Duration, client: &str, tag: Option<&str>) {
+        if duration < self.threshold() {
+            return;
+        }
+
+        let mut command = command.to_string();
+        if command.len() > MAX_COMMAND_LEN {
+            let mut cut = MAX_COMMAND_LEN;
+            while !command.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            command.truncate(cut);
+            command.push_str("...");
+        }
+
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            duration,
+            command,
+            client: client.to_string(),
+            tag: tag.map(str::to_string),
+        };
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push_front(entry);
+            entries.truncate(MAX_ENTRIES);
+        }
+    }
+
+    /// Up to `count` entries, newest first.
+    pub fn get(&self, count: usize) -> Vec<SlowLogEntry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.iter().take(count).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|entries| entries.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn reset(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slowlog_threshold_and_order() {
+        let slowlog = SlowLog::new();
+        slowlog.set_threshold(Duration::from_millis(5));
+
+        slowlog.record("GET fast", Duration::from_millis(1), "127.0.0.1:1");
+        slowlog.record("KEYS *", Duration::from_millis(20), "127.0.0.1:1");
+        slowlog.record("FIND idx value", Duration::from_millis(8), "127.0.0.1:2");
+        assert_eq!(slowlog.len(), 2);
+
+        let entries = slowlog.get(10);
+        assert_eq!(entries[0].command, "FIND idx value");
+        assert_eq!(entries[1].command, "KEYS *");
+        assert!(entries[0].id > entries[1].id);
+        assert_eq!(entries[0].tag, None);
+
+        slowlog.record_tagged("LIST", Duration::from_millis(9), "127.0.0.1:3", Some("req-42"));
+        assert_eq!(slowlog.get(1)[0].tag.as_deref(), Some("req-42"));
+
+        slowlog.reset();
+        assert!(slowlog.is_empty());
+    }
+
+    #[test]
+    fn test_slowlog_is_bounded() {
+        let slowlog = SlowLog::new();
+        slowlog.set_threshold(Duration::ZERO);
+        for i in 0..MAX_ENTRIES + 10 {
+            slowlog.record(&format!("SET key{} {}", i, "x".repeat(200)), Duration::from_millis(1), "client");
+        }
+        assert_eq!(slowlog.len(), MAX_ENTRIES);
+        assert!(slowlog.get(1)[0].command.ends_with("..."));
+    }
+}
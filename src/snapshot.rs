@@ -0,0 +1,766 @@
+use crate::store::{Change, ClockAnchor, Store, Value};
+use crate::stream::{ConsumerGroup, Pending, Stream, StreamId};
+use crate::timeseries::{now_millis, TimeSeries};
+use crate::zset::SortedSet;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const MAGIC: &[u8; 6] = b"MDSNAP";
+const INCREMENTAL_MAGIC: &[u8; 6] = b"MDINCR";
+/// Version 2 added the CRC-32 trailer; version 1 files are still read.
+pub const FORMAT_VERSION: u8 = 2;
+const FIRST_CHECKSUMMED_VERSION: u8 = 2;
+
+const TAG_STRING: u8 = 1;
+const TAG_HASH: u8 = 2;
+const TAG_LIST: u8 = 3;
+const TAG_TIMESERIES: u8 = 4;
+const TAG_VECTOR: u8 = 5;
+const TAG_DELETE: u8 = 6;
+const TAG_SET: u8 = 7;
+const TAG_SORTED_SET: u8 = 8;
+const TAG_STREAM: u8 = 9;
+const TAG_EOF: u8 = 0xFF;
+
+// Lengths come from the file, so never trust them for up-front allocation
+const PREALLOCATE_LIMIT: usize = 1024;
+
+/// What startup does with a snapshot that fails its checks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CorruptionPolicy {
+    /// Refuse to start until the file is repaired or removed.
+    Refuse,
+    /// Load every record up to the damage and start with those.
+    Truncate,
+}
+
+impl CorruptionPolicy {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "refuse" => Ok(CorruptionPolicy::Refuse),
+            "truncate" => Ok(CorruptionPolicy::Truncate),
+            other => Err(format!("Unknown corruption policy '{}', expected refuse or truncate", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CorruptionPolicy::Refuse => "refuse",
+            CorruptionPolicy::Truncate => "truncate",
+        }
+    }
+}
+
+/// Write every live key in `store` to `writer`.
+///
+/// Layout: magic, format version, then one record per key
+/// (`tag, key, expiry, payload`), a terminating EOF tag and the CRC-32 of
+/// everything before it. Expiry is an absolute unix-millisecond deadline
+/// (0 = no TTL) so it stays meaningful when read back by another process.
+/// Returns the number of keys written.
+pub fn write_snapshot<W: Write>(store: &Store, writer: &mut W) -> Result<usize, String> {
+    store.faults().check_persistence()?;
+    let entries = store.export_entries()?;
+    let now = now_millis();
+    let mut writer = Checksummed::new(writer);
+
+    let mut write = || -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        for (key, value, ttl) in &entries {
+            write_entry(&mut writer, key, value, *ttl, now)?;
+        }
+        writer.write_all(&[TAG_EOF])?;
+        writer.write_checksum()?;
+        writer.flush()
+    };
+
+    write().map_err(|e| format!("Failed to write snapshot: {}", e))?;
+    Ok(entries.len())
+}
+
+/// Write a snapshot of `store` to the file at `path` (conventionally
+/// `.mdb`). It is written aside and renamed into place, so a crash never
+/// leaves a truncated file. Returns the number of keys written.
+pub fn save_file<P: AsRef<Path>>(store: &Store, path: P) -> Result<usize, String> {
+    let path = path.as_ref();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+
+    let result = File::create(&partial)
+        .map_err(|e| format!("Failed to create {}: {}", Path::new(&partial).display(), e))
+        .and_then(|file| write_snapshot(store, &mut BufWriter::new(file)))
+        .and_then(|keys| fs::rename(&partial, path).map(|()| keys).map_err(|e| format!("Failed to rename snapshot: {}", e)));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Load the snapshot file at `path` into `store`, on top of any keys it
+/// already holds. The whole file is checked first, so a damaged one
+/// leaves the store untouched. Returns the number of keys loaded.
+pub fn load_file<P: AsRef<Path>>(store: &Store, path: P) -> Result<usize, String> {
+    let path = path.as_ref();
+    check_file(path).map_err(|e| format!("{} is damaged: {}", path.display(), e))?;
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    read_snapshot(store, &mut BufReader::new(file))
+}
+
+/// Load what can be read of a damaged snapshot: every record before the
+/// first one that fails to decode. The records cannot be told apart from
+/// good ones when only the checksum is wrong, so all of them are loaded.
+/// Returns the keys loaded and what stopped the load, if anything did.
+pub fn load_file_truncated<P: AsRef<Path>>(store: &Store, path: P) -> Result<(usize, Option<String>), String> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut reader = Checksummed::new(BufReader::new(file));
+    let version = read_header(&mut reader, MAGIC, "Not a Medusa snapshot")?;
+    let mut loaded = 0;
+    let damage = load_records(store, &mut reader, &mut loaded).and_then(|()| reader.verify_checksum(version)).err();
+    Ok((loaded, damage))
+}
+
+/// Take a full backup: a regular snapshot that also starts tracking changes
+/// for the incremental backups that follow it. Returns the number of keys written.
+pub fn write_full_backup<W: Write>(store: &Store, writer: &mut W) -> Result<usize, String> {
+    // Start tracking first so nothing written during the export is missed;
+    // a key caught by both is simply written again by the next incremental
+    store.start_change_tracking()?;
+    write_snapshot(store, writer)
+}
+
+/// Write only the keys changed since the previous full or incremental
+/// backup. Deleted keys are recorded so a restore removes them too.
+///
+/// Layout: incremental magic, format version, chain id, sequence number,
+/// then records as in a snapshot plus delete records (`tag, key`), and
+/// the checksum. Returns the number of changed keys written.
+pub fn write_incremental<W: Write>(store: &Store, writer: &mut W) -> Result<usize, String> {
+    store.faults().check_persistence()?;
+    let (chain_id, sequence, changes) = store.take_changes()?;
+    write_changes(&changes, chain_id, sequence, writer)
+}
+
+/// Write `changes` as incremental `sequence` of chain `chain_id`, in the
+/// layout `write_incremental` describes. Replication streams key states
+/// with it. Returns the number of changes written.
+pub fn write_changes<W: Write>(changes: &[Change], chain_id: u64, sequence: u64, writer: &mut W) -> Result<usize, String> {
+    let now = now_millis();
+    let mut writer = Checksummed::new(writer);
+
+    let mut write = || -> io::Result<()> {
+        writer.write_all(INCREMENTAL_MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        writer.write_all(&chain_id.to_le_bytes())?;
+        writer.write_all(&sequence.to_le_bytes())?;
+        for (key, state) in changes {
+            match state {
+                Some((value, ttl)) => write_entry(&mut writer, key, value, *ttl, now)?,
+                None => {
+                    writer.write_all(&[TAG_DELETE])?;
+                    write_bytes(&mut writer, key.as_bytes())?;
+                }
+            }
+        }
+        writer.write_all(&[TAG_EOF])?;
+        writer.write_checksum()?;
+        writer.flush()
+    };
+
+    write().map_err(|e| format!("Failed to write incremental backup: {}", e))?;
+    Ok(changes.len())
+}
+
+fn write_entry<W: Write>(writer: &mut W, key: &str, value: &Value, ttl: Option<Duration>, now: u64) -> io::Result<()> {
+    let tag = match value {
+        Value::String(_) => TAG_STRING,
+        Value::Hash(_) => TAG_HASH,
+        Value::List(_) => TAG_LIST,
+        Value::Set(_) => TAG_SET,
+        Value::SortedSet(_) => TAG_SORTED_SET,
+        Value::Stream(_) => TAG_STREAM,
+        Value::TimeSeries(_) => TAG_TIMESERIES,
+        Value::Vector(_) => TAG_VECTOR,
+    };
+    writer.write_all(&[tag])?;
+    write_bytes(writer, key.as_bytes())?;
+    let deadline = ttl.map(|ttl| now + ttl.as_millis() as u64).unwrap_or(0);
+    writer.write_all(&deadline.to_le_bytes())?;
+
+    match value {
+        Value::String(s) => write_bytes(writer, s.as_bytes())?,
+        Value::Hash(hash) => {
+            write_len(writer, hash.len())?;
+            for (field, field_value) in hash {
+                write_bytes(writer, field.as_bytes())?;
+                write_bytes(writer, field_value)?;
+            }
+        }
+        Value::List(list) => {
+            write_len(writer, list.len())?;
+            for item in list {
+                write_bytes(writer, item.as_bytes())?;
+            }
+        }
+        Value::Set(set) => {
+            write_len(writer, set.len())?;
+            for member in set {
+                write_bytes(writer, member.as_bytes())?;
+            }
+        }
+        Value::SortedSet(set) => {
+            write_len(writer, set.len())?;
+            for (member, score) in set.iter() {
+                write_bytes(writer, member.as_bytes())?;
+                writer.write_all(&score.to_le_bytes())?;
+            }
+        }
+        // The last ID goes first: it can be past the newest entry once
+        // entries are removed. Consumer groups follow the entries.
+        Value::Stream(stream) => {
+            writer.write_all(&stream.last_id().ms.to_le_bytes())?;
+            writer.write_all(&stream.last_id().seq.to_le_bytes())?;
+            write_len(writer, stream.len())?;
+            for (id, fields) in stream.iter() {
+                writer.write_all(&id.ms.to_le_bytes())?;
+                writer.write_all(&id.seq.to_le_bytes())?;
+                write_len(writer, fields.len())?;
+                for (field, value) in fields {
+                    write_bytes(writer, field.as_bytes())?;
+                    write_bytes(writer, value.as_bytes())?;
+                }
+            }
+            let groups: Vec<_> = stream.groups().collect();
+            write_len(writer, groups.len())?;
+            for (name, group) in groups {
+                write_bytes(writer, name.as_bytes())?;
+                writer.write_all(&group.last_delivered().ms.to_le_bytes())?;
+                writer.write_all(&group.last_delivered().seq.to_le_bytes())?;
+                let pending: Vec<_> = group.pending().collect();
+                write_len(writer, pending.len())?;
+                for (id, pending) in pending {
+                    writer.write_all(&id.ms.to_le_bytes())?;
+                    writer.write_all(&id.seq.to_le_bytes())?;
+                    write_bytes(writer, pending.consumer.as_bytes())?;
+                    writer.write_all(&pending.delivered_ms.to_le_bytes())?;
+                    writer.write_all(&pending.deliveries.to_le_bytes())?;
+                }
+            }
+        }
+        Value::TimeSeries(series) => {
+            match series.retention_ms() {
+                Some(retention) => {
+                    writer.write_all(&[1])?;
+                    writer.write_all(&retention.to_le_bytes())?;
+                }
+                None => writer.write_all(&[0])?,
+            }
+            write_len(writer, series.len())?;
+            for (ts, sample) in series.samples() {
+                writer.write_all(&ts.to_le_bytes())?;
+                writer.write_all(&sample.to_le_bytes())?;
+            }
+        }
+        Value::Vector(vector) => {
+            write_len(writer, vector.len())?;
+            for component in vector {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Load a snapshot produced by `write_snapshot` into `store`. Keys whose
+/// deadline has already passed are skipped. Returns the number of keys loaded.
+pub fn read_snapshot<R: Read>(store: &Store, reader: &mut R) -> Result<usize, String> {
+    let mut reader = Checksummed::new(reader);
+    let version = read_header(&mut reader, MAGIC, "Not a Medusa snapshot")?;
+    let mut loaded = 0;
+    load_records(store, &mut reader, &mut loaded)?;
+    reader.verify_checksum(version)?;
+    Ok(loaded)
+}
+
+/// Apply an incremental backup produced by `write_incremental` on top of
+/// `store`. Returns `(chain id, sequence number, keys applied)`.
+pub fn read_incremental<R: Read>(store: &Store, reader: &mut R) -> Result<(u64, u64, usize), String> {
+    let mut reader = Checksummed::new(reader);
+    let version = read_header(&mut reader, INCREMENTAL_MAGIC, "Not a Medusa incremental backup")?;
+    let chain_id = read_u64(&mut reader)?;
+    let sequence = read_u64(&mut reader)?;
+    let mut applied = 0;
+    load_records(store, &mut reader, &mut applied)?;
+    reader.verify_checksum(version)?;
+    Ok((chain_id, sequence, applied))
+}
+
+/// Decode an incremental backup into the key states it carries, without
+/// applying them. Keys whose deadline has passed come back as deletions.
+/// Returns `(chain id, sequence number, changes)`.
+pub fn read_changes<R: Read>(reader: &mut R) -> Result<(u64, u64, Vec<Change>), String> {
+    let mut reader = Checksummed::new(reader);
+    let version = read_header(&mut reader, INCREMENTAL_MAGIC, "Not a Medusa incremental backup")?;
+    let chain_id = read_u64(&mut reader)?;
+    let sequence = read_u64(&mut reader)?;
+    let now = now_millis();
+    let mut changes = Vec::new();
+    read_records(&mut reader, |record, _| {
+        changes.push(match record {
+            Record::Delete(key) => (key, None),
+            Record::Entry { key, deadline, .. } if deadline != 0 && deadline <= now => (key, None),
+            Record::Entry { key, value, deadline: 0 } => (key, Some((value, None))),
+            Record::Entry { key, value, deadline } => (key, Some((value, Some(Duration::from_millis(deadline - now))))),
+        });
+        Ok(())
+    })?;
+    reader.verify_checksum(version)?;
+    Ok((chain_id, sequence, changes))
+}
+
+/// Restore a full backup followed by its incrementals, in order. The store
+/// is cleared first; every incremental must belong to the same chain and
+/// follow the previous one. Returns the number of keys in the store afterwards.
+pub fn restore_backup_chain<F: AsRef<Path>, P: AsRef<Path>>(store: &Store, full: F, incrementals: &[P]) -> Result<usize, String> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+    };
+
+    let mut full_reader = open(full.as_ref())?;
+    store.clear()?;
+    read_snapshot(store, &mut full_reader)?;
+
+    let mut chain: Option<u64> = None;
+    for (position, path) in incrementals.iter().enumerate() {
+        let (chain_id, sequence, _) = read_incremental(store, &mut open(path.as_ref())?)?;
+        if chain.is_some_and(|chain| chain != chain_id) {
+            return Err(format!("{} belongs to a different backup chain", path.as_ref().display()));
+        }
+        if sequence != position as u64 + 1 {
+            return Err(format!(
+                "{} is incremental #{}, expected #{}",
+                path.as_ref().display(),
+                sequence,
+                position + 1
+            ));
+        }
+        chain = Some(chain_id);
+    }
+
+    store.count()
+}
+
+fn read_header<R: Read>(reader: &mut R, magic: &[u8; 6], not_ours: &str) -> Result<u8, String> {
+    let mut found = [0u8; 6];
+    read_exact(reader, &mut found)?;
+    if &found != magic {
+        return Err(not_ours.to_string());
+    }
+    read_version(reader)
+}
+
+fn read_version<R: Read>(reader: &mut R) -> Result<u8, String> {
+    let version = read_u8(reader)?;
+    if version > FORMAT_VERSION {
+        return Err(format!("Unsupported snapshot version {}", version));
+    }
+    Ok(version)
+}
+
+enum Record {
+    Entry { key: String, value: Value, deadline: u64 },
+    Delete(String),
+}
+
+/// Decode records up to the EOF tag, handing each to `visit` with the
+/// number of bytes it took up.
+fn read_records<R: Read>(reader: &mut Checksummed<R>, mut visit: impl FnMut(Record, u64) -> Result<(), String>) -> Result<(), String> {
+    loop {
+        let start = reader.position;
+        let tag = read_u8(reader)?;
+        if tag == TAG_EOF {
+            return Ok(());
+        }
+
+        let key = read_string(reader)?;
+        if tag == TAG_DELETE {
+            visit(Record::Delete(key), reader.position - start)?;
+            continue;
+        }
+        let deadline = read_u64(reader)?;
+
+        let value = match tag {
+            TAG_STRING => Value::String(read_string(reader)?),
+            TAG_HASH => {
+                let len = read_len(reader)?;
+                let mut hash = HashMap::with_capacity(len.min(PREALLOCATE_LIMIT));
+                for _ in 0..len {
+                    let field = read_string(reader)?;
+                    hash.insert(field, read_bytes(reader)?);
+                }
+                Value::Hash(hash)
+            }
+            TAG_LIST => {
+                let len = read_len(reader)?;
+                let mut list = VecDeque::with_capacity(len.min(PREALLOCATE_LIMIT));
+                for _ in 0..len {
+                    list.push_back(read_string(reader)?);
+                }
+                Value::List(list)
+            }
+            TAG_SET => {
+                let len = read_len(reader)?;
+                let mut set = HashSet::with_capacity(len.min(PREALLOCATE_LIMIT));
+                for _ in 0..len {
+                    set.insert(read_string(reader)?);
+                }
+                Value::Set(set)
+            }
+            TAG_SORTED_SET => {
+                let mut set = SortedSet::new();
+                for _ in 0..read_len(reader)? {
+                    let member = read_string(reader)?;
+                    set.insert(&member, f64::from_le_bytes(read_array(reader)?));
+                }
+                Value::SortedSet(set)
+            }
+            TAG_STREAM => {
+                let last_id = StreamId::new(read_u64(reader)?, read_u64(reader)?);
+                let mut entries = Vec::new();
+                for _ in 0..read_len(reader)? {
+                    let id = StreamId::new(read_u64(reader)?, read_u64(reader)?);
+                    let mut fields = Vec::new();
+                    for _ in 0..read_len(reader)? {
+                        fields.push((read_string(reader)?, read_string(reader)?));
+                    }
+                    entries.push((id, fields));
+                }
+                let mut stream = Stream::restore(last_id, entries);
+                for _ in 0..read_len(reader)? {
+                    let name = read_string(reader)?;
+                    let last_delivered = StreamId::new(read_u64(reader)?, read_u64(reader)?);
+                    let mut pending = Vec::new();
+                    for _ in 0..read_len(reader)? {
+                        let id = StreamId::new(read_u64(reader)?, read_u64(reader)?);
+                        let consumer = read_string(reader)?;
+                        pending.push((id, Pending { consumer, delivered_ms: read_u64(reader)?, deliveries: read_u64(reader)? }));
+                    }
+                    stream.restore_group(&name, ConsumerGroup::restore(last_delivered, pending));
+                }
+                Value::Stream(stream)
+            }
+            TAG_TIMESERIES => {
+                let retention = match read_u8(reader)? {
+                    0 => None,
+                    _ => Some(read_u64(reader)?),
+                };
+                let mut series = TimeSeries::new(retention);
+                for _ in 0..read_len(reader)? {
+                    let ts = read_u64(reader)?;
+                    series.add(ts, f64::from_le_bytes(read_array(reader)?));
+                }
+                Value::TimeSeries(series)
+            }
+            TAG_VECTOR => {
+                let len = read_len(reader)?;
+                let mut vector = Vec::with_capacity(len.min(PREALLOCATE_LIMIT));
+                for _ in 0..len {
+                    vector.push(f32::from_le_bytes(read_array(reader)?));
+                }
+                Value::Vector(vector)
+            }
+            other => return Err(format!("Unknown record type {} for key '{}'", other, key)),
+        };
+        visit(Record::Entry { key, value, deadline }, reader.position - start)?;
+    }
+}
+
+// Counts into `loaded` so a caller can tell how far a failed load got
+fn load_records<R: Read>(store: &Store, reader: &mut Checksummed<R>, loaded: &mut usize) -> Result<(), String> {
+    let clock = ClockAnchor::now();
+
+    read_records(reader, |record, _| {
+        let (key, value, deadline) = match record {
+            Record::Delete(key) => {
+                store.delete(&key)?;
+                *loaded += 1;
+                return Ok(());
+            }
+            Record::Entry { key, value, deadline } => (key, value, deadline),
+        };
+
+        let expires_at = match deadline {
+            0 => None,
+            deadline => match clock.instant_at(deadline) {
+                Some(expires_at) => Some(expires_at),
+                None => {
+                    // Expired since the backup was taken; it may still exist
+                    // from an earlier backup in the chain
+                    store.delete(&key)?;
+                    return Ok(());
+                }
+            },
+        };
+        store.import_entry_until(&key, value, expires_at)?;
+        *loaded += 1;
+        Ok(())
+    })
+}
+
+/// What `check_snapshot` found in a snapshot or incremental backup.
+#[derive(Debug, Default)]
+pub struct SnapshotCheck {
+    pub incremental: bool,
+    pub version: u8,
+    /// Chain id and sequence number of an incremental backup.
+    pub chain: Option<(u64, u64)>,
+    /// Key records, counted by type.
+    pub types: BTreeMap<&'static str, usize>,
+    pub deletes: usize,
+    pub with_ttl: usize,
+    /// Keys whose deadline has passed; a restore would skip them.
+    pub expired: usize,
+    /// Keys that appear more than once; a snapshot writer never does that.
+    pub duplicates: usize,
+    /// The key taking the most bytes in the file, and how many.
+    pub largest: Option<(String, u64)>,
+    pub bytes: u64,
+    /// False for version 1 files, which have no checksum.
+    pub checksummed: bool,
+}
+
+impl SnapshotCheck {
+    pub fn keys(&self) -> usize {
+        self.types.values().sum()
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![match self.chain {
+            Some((chain, sequence)) => format!("incremental backup #{} of chain {:016x}, format version {}", sequence, chain, self.version),
+            None => format!("snapshot, format version {}", self.version),
+        }];
+        let types: Vec<String> = self.types.iter().map(|(name, count)| format!("{} {}", count, name)).collect();
+        lines.push(format!("{} keys ({}), {} with a TTL, {} already expired", self.keys(), types.join(", "), self.with_ttl, self.expired));
+        if self.incremental {
+            lines.push(format!("{} deletes", self.deletes));
+        }
+        if let Some((key, bytes)) = &self.largest {
+            lines.push(format!("largest key '{}' ({} bytes)", key, bytes));
+        }
+        lines.push(format!(
+            "{} bytes, checksum {}",
+            self.bytes,
+            if self.checksummed { "verified" } else { "absent (version 1)" }
+        ));
+        lines
+    }
+}
+
+/// Validate a snapshot or incremental backup without loading it: every
+/// record must decode, the checksum must match and nothing may follow it.
+/// Errors say at which byte the file went wrong.
+pub fn check_snapshot<R: Read>(reader: &mut R) -> Result<SnapshotCheck, String> {
+    let mut reader = Checksummed::new(reader);
+    let mut check = SnapshotCheck::default();
+    check_records(&mut reader, &mut check).map_err(|e| format!("{} (at byte {})", e, reader.position))?;
+    check.bytes = reader.position;
+    Ok(check)
+}
+
+fn check_records<R: Read>(reader: &mut Checksummed<R>, check: &mut SnapshotCheck) -> Result<(), String> {
+    let magic: [u8; 6] = read_array(reader)?;
+    check.incremental = match &magic {
+        MAGIC => false,
+        INCREMENTAL_MAGIC => true,
+        _ => return Err("Not a Medusa snapshot or incremental backup".to_string()),
+    };
+    check.version = read_version(reader)?;
+    if check.incremental {
+        check.chain = Some((read_u64(reader)?, read_u64(reader)?));
+    }
+
+    let now = now_millis();
+    let mut seen = HashSet::new();
+    read_records(reader, |record, bytes| {
+        let (key, value, deadline) = match record {
+            Record::Delete(_) => {
+                check.deletes += 1;
+                return Ok(());
+            }
+            Record::Entry { key, value, deadline } => (key, value, deadline),
+        };
+        *check.types.entry(value.type_name()).or_default() += 1;
+        if deadline != 0 {
+            check.with_ttl += 1;
+            if deadline <= now {
+                check.expired += 1;
+            }
+        }
+        if check.largest.as_ref().is_none_or(|(_, largest)| bytes > *largest) {
+            check.largest = Some((key.clone(), bytes));
+        }
+        if !seen.insert(key) {
+            check.duplicates += 1;
+        }
+        Ok(())
+    })?;
+    reader.verify_checksum(check.version)?;
+    check.checksummed = check.version >= FIRST_CHECKSUMMED_VERSION;
+
+    let mut trailing = Vec::new();
+    reader.read_to_end(&mut trailing).map_err(|e| format!("Failed to read snapshot: {}", e))?;
+    if !trailing.is_empty() {
+        return Err(format!("{} unexpected bytes after the end of the snapshot", trailing.len()));
+    }
+    if check.duplicates > 0 {
+        return Err(format!("{} keys appear more than once", check.duplicates));
+    }
+    Ok(())
+}
+
+/// Run `check_snapshot` on a file.
+pub fn check_file<P: AsRef<Path>>(path: P) -> Result<SnapshotCheck, String> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    check_snapshot(&mut BufReader::new(file))
+}
+
+/// Passes bytes through while keeping a CRC-32 (IEEE) of them and a count.
+struct Checksummed<T> {
+    inner: T,
+    crc: u32,
+    position: u64,
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Self {
+        Checksummed { inner, crc: !0, position: 0 }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.crc = CRC_TABLE[((self.crc ^ *byte as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+        self.position += bytes.len() as u64;
+    }
+
+    fn checksum(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl<W: Write> Checksummed<W> {
+    fn write_checksum(&mut self) -> io::Result<()> {
+        let checksum = self.checksum();
+        self.inner.write_all(&checksum.to_le_bytes())
+    }
+}
+
+impl<R: Read> Checksummed<R> {
+    /// Read the trailer and compare it with the bytes read so far. Files
+    /// from before checksums end at the EOF tag.
+    fn verify_checksum(&mut self, version: u8) -> Result<(), String> {
+        if version < FIRST_CHECKSUMMED_VERSION {
+            return Ok(());
+        }
+        let computed = self.checksum();
+        let mut stored = [0u8; 4];
+        self.inner.read_exact(&mut stored).map_err(|e| format!("Truncated snapshot: {}", e))?;
+        self.position += 4;
+        let stored = u32::from_le_bytes(stored);
+        if stored != computed {
+            return Err(format!("Snapshot checksum mismatch: stored {:08x}, computed {:08x}", stored, computed));
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn write_len<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    writer.write_all(&(len as u32).to_le_bytes())
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_len(writer, bytes.len())?;
+    writer.write_all(bytes)
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), String> {
+    reader.read_exact(buf).map_err(|e| format!("Truncated snapshot: {}", e))
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], String> {
+    let mut buf = [0u8; N];
+    read_exact(reader, &mut buf)?;
+    Ok(buf)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, String> {
+    Ok(read_array::<R, 1>(reader)?[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, String> {
+    Ok(u64::from_le_bytes(read_array(reader)?))
+}
+
+fn read_len<R: Read>(reader: &mut R) -> Result<usize, String> {
+    Ok(u32::from_le_bytes(read_array(reader)?) as usize)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| "Snapshot contains invalid UTF-8".to_string())
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, String> {
+    let len = read_len(reader)?;
+    let mut buf = Vec::new();
+    reader
+        .take(len as u64)
+        .read_to_end(&mut buf)
+        .map_err(|e| format!("Truncated snapshot: {}", e))?;
+    if buf.len() != len {
+        return Err("Truncated snapshot: unexpected end of file".to_string());
+    }
+    Ok(buf)
+}
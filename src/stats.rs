@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Distinct prefixes tracked before the rest are lumped together, so a
+// keyspace without a real prefix structure cannot grow the table unbounded
+const MAX_PREFIXES: usize = 1024;
+const OTHER_PREFIXES: &str = "(other)";
+
+/// Hits and misses for one key prefix.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PrefixStats {
+    pub prefix: String,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl PrefixStats {
+    pub fn hit_rate(&self) -> f64 {
+        hit_rate(self.hits, self.misses)
+    }
+}
+
+pub fn hit_rate(hits: u64, misses: u64) -> f64 {
+    match hits + misses {
+        0 => 0.0,
+        lookups => hits as f64 / lookups as f64,
+    }
+}
+
+#[derive(Default)]
+struct PrefixTable {
+    separator: String,
+    counts: HashMap<String, (u64, u64)>,
+}
+
+#[derive(Default)]
+struct StatsInner {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    prefixes: Mutex<Option<PrefixTable>>,
+}
+
+/// Keyspace hit/miss counters for the read commands (`GET`, `HGET`,
+/// `EXISTS`, ...), reported by `INFO` and `STATS`. Per-prefix counts are
+/// kept only once enabled, grouping keys by the part before a separator
+/// like `MEMORY ANALYZE` does.
+#[derive(Clone, Default)]
+pub struct KeyspaceStats {
+    inner: Arc<StatsInner>,
+}
+
+impl KeyspaceStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, key: &str, hit: bool) {
+        let counter = if hit { &self.inner.hits } else { &self.inner.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        if let Ok(mut prefixes) = self.inner.prefixes.lock() {
+            if let Some(table) = prefixes.as_mut() {
+                let prefix = match key.find(&table.separator) {
+                    Some(pos) if !table.separator.is_empty() => &key[..pos + table.separator.len()],
+                    _ => "",
+                };
+                let prefix = if table.counts.contains_key(prefix) || table.counts.len() < MAX_PREFIXES {
+                    prefix
+                } else {
+                    OTHER_PREFIXES
+                };
+                let counts = table.counts.entry(prefix.to_string()).or_default();
+                if hit {
+                    counts.0 += 1;
+                } else {
+                    counts.1 += 1;
+                }
+            }
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.inner.misses.load(Ordering::Relaxed)
+    }
+
+    /// Start (or with `None` stop) counting per prefix. Changing the
+    /// separator starts the prefix counts over.
+    pub fn track_prefixes(&self, separator: Option<&str>) {
+        if let Ok(mut prefixes) = self.inner.prefixes.lock() {
+            match (separator, prefixes.as_ref()) {
+                (Some(separator), Some(table)) if table.separator == separator => {}
+                (Some(separator), _) => {
+                    *prefixes = Some(PrefixTable { separator: separator.to_string(), counts: HashMap::new() });
+                }
+                (None, _) => *prefixes = None,
+            }
+        }
+    }
+
+    pub fn prefix_separator(&self) -> Option<String> {
+        self.inner.prefixes.lock().ok()?.as_ref().map(|table| table.separator.clone())
+    }
+
+    /// Per-prefix counts, busiest first.
+    pub fn prefixes(&self) -> Vec<PrefixStats> {
+        let mut stats: Vec<PrefixStats> = self.inner.prefixes.lock()
+            .ok()
+            .and_then(|prefixes| prefixes.as_ref().map(|table| {
+                table.counts.iter()
+                    .map(|(prefix, (hits, misses))| PrefixStats { prefix: prefix.clone(), hits: *hits, misses: *misses })
+                    .collect()
+            }))
+            .unwrap_or_default();
+        stats.sort_by(|a, b| (b.hits + b.misses).cmp(&(a.hits + a.misses)).then_with(|| a.prefix.cmp(&b.prefix)));
+        stats
+    }
+
+    pub fn reset(&self) {
+        self.inner.hits.store(0, Ordering::Relaxed);
+        self.inner.misses.store(0, Ordering::Relaxed);
+        if let Ok(mut prefixes) = self.inner.prefixes.lock() {
+            if let Some(table) = prefixes.as_mut() {
+                table.counts.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hits_and_misses() {
+        let stats = KeyspaceStats::new();
+        stats.record("user:1", true);
+        stats.record("user:2", false);
+        assert_eq!((stats.hits(), stats.misses()), (1, 1));
+        assert!(stats.prefixes().is_empty());
+
+        stats.track_prefixes(Some(":"));
+        stats.record("user:1", true);
+        stats.record("user:1", true);
+        stats.record("session:9", false);
+        stats.record("plain", true);
+        let prefixes = stats.prefixes();
+        assert_eq!(prefixes[0], PrefixStats { prefix: "user:".to_string(), hits: 2, misses: 0 });
+        assert_eq!(prefixes.len(), 3);
+        assert_eq!(prefixes.iter().find(|p| p.prefix.is_empty()).unwrap().hits, 1);
+
+        stats.reset();
+        assert_eq!((stats.hits(), stats.misses()), (0, 0));
+        assert!(stats.prefixes().is_empty());
+        assert_eq!(stats.prefix_separator(), Some(":".to_string()));
+    }
+
+    #[test]
+    fn test_prefix_table_is_bounded() {
+        let stats = KeyspaceStats::new();
+        stats.track_prefixes(Some(":"));
+        for i in 0..MAX_PREFIXES + 10 {
+            stats.record(&format!("p{}:key", i), true);
+        }
+        let prefixes = stats.prefixes();
+        assert_eq!(prefixes.len(), MAX_PREFIXES + 1);
+        assert_eq!(prefixes[0], PrefixStats { prefix: OTHER_PREFIXES.to_string(), hits: 10, misses: 0 });
+        assert_eq!(hit_rate(3, 1), 0.75);
+    }
+}
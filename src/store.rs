@@ -1,11 +1,47 @@
-use std::collections::{HashMap, VecDeque};
-use std::sync::{Arc, Mutex};
+use crate::alarm::AlarmMonitor;
+use crate::alias::AliasRegistry;
+use crate::chaos::FaultInjector;
+use crate::crdt::{Crdt, Stamp};
+use crate::delayed::DelayedQueues;
+use crate::eviction::{Eviction, EvictionPolicy};
+use crate::extension::CommandRegistry;
+use crate::history::KeyHistory;
+use crate::lcs::{self, LcsResult};
+use crate::keyspace::ShardedMap;
+use crate::index::{pattern_matches, SecondaryIndex};
+use crate::schedule::PersistenceStatus;
+use crate::search::SearchIndex;
+use crate::latency::LatencyHistograms;
+use crate::pubsub::PubSub;
+use crate::replication::Replication;
+use crate::sentinel::Sentinel;
+use crate::slowlog::SlowLog;
+use crate::stats::{self, KeyspaceStats};
+use crate::telemetry;
+use crate::tenant::{self, TenantQuota, TenantRegistry};
+use crate::timeseries::{now_millis, Aggregation, TimeSeries};
+use crate::vector::{self, Metric};
+use crate::stream::{AutoClaim, ConsumerGroup, Entry, Fields, Stream, StreamId, Trim};
+use crate::zset::{ScoreBound, SortedSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
 use std::time::{Duration, Instant};
 
+/// Keys re-TTLed per lock acquisition by `expire_pattern`.
+const EXPIRE_BATCH: usize = 1000;
+
+/// Entries checked for expired keys each time a write needs room under
+/// `MEDUSA_MAX_KEYS`.
+const KEY_LIMIT_PURGE: usize = 64;
+
 #[derive(Clone, Debug)]
 pub struct ValueWithTtl {
     pub value: Value,
     pub expires_at: Option<Instant>,
+    /// When a command last touched the key, for `OBJECT IDLETIME`.
+    pub last_access: Instant,
 }
 
 impl ValueWithTtl {
@@ -13,6 +49,7 @@ impl ValueWithTtl {
         Self {
             value,
             expires_at: None,
+            last_access: Instant::now(),
         }
     }
 
@@ -20,11 +57,12 @@ impl ValueWithTtl {
         Self {
             value,
             expires_at: Some(Instant::now() + Duration::from_secs(ttl_seconds)),
+            last_access: Instant::now(),
         }
     }
 
     pub fn is_expired(&self) -> bool {
-        self.expires_at.map_or(false, |expires| Instant::now() > expires)
+        self.expires_at.is_some_and(|expires| Instant::now() > expires)
     }
 
     pub fn ttl_seconds(&self) -> Option<i64> {
@@ -41,11 +79,49 @@ impl ValueWithTtl {
     }
 }
 
+/// A wall-clock reading paired with the monotonic clock at the same moment.
+/// Persisted expiries are absolute unix-millisecond deadlines, since an
+/// `Instant` means nothing to another process; converting them through one
+/// anchor taken at load time keeps every restored key's lifetime exact, however
+/// long the load takes or if the wall clock is stepped meanwhile.
+#[derive(Clone, Copy, Debug)]
+pub struct ClockAnchor {
+    wall_ms: u64,
+    instant: Instant,
+}
+
+impl ClockAnchor {
+    pub fn now() -> Self {
+        ClockAnchor { wall_ms: now_millis(), instant: Instant::now() }
+    }
+
+    /// The unix-millisecond deadline of a monotonic expiry.
+    pub fn deadline_ms(&self, expires_at: Instant) -> u64 {
+        match expires_at.checked_duration_since(self.instant) {
+            Some(remaining) => self.wall_ms + remaining.as_millis() as u64,
+            None => self.wall_ms.saturating_sub(self.instant.duration_since(expires_at).as_millis() as u64),
+        }
+    }
+
+    /// The monotonic expiry of a unix-millisecond deadline, or `None` if it
+    /// had already passed when the anchor was taken.
+    pub fn instant_at(&self, deadline_ms: u64) -> Option<Instant> {
+        (deadline_ms > self.wall_ms).then(|| self.instant + Duration::from_millis(deadline_ms - self.wall_ms))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Value {
     String(String),
-    Hash(HashMap<String, String>),
+    /// Field values are bytes, so they can hold binary data; the text API
+    /// (`hget`, `hgetall`) refuses values that are not UTF-8.
+    Hash(HashMap<String, Vec<u8>>),
     List(VecDeque<String>),
+    Set(HashSet<String>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+    TimeSeries(TimeSeries),
+    Vector(Vec<f32>),
 }
 
 impl Value {
@@ -60,228 +136,2567 @@ impl Value {
     pub fn new_list() -> Self {
         Value::List(VecDeque::new())
     }
+
+    pub fn new_set() -> Self {
+        Value::Set(HashSet::new())
+    }
+
+    pub fn new_sorted_set() -> Self {
+        Value::SortedSet(SortedSet::new())
+    }
+
+    pub fn new_stream() -> Self {
+        Value::Stream(Stream::new())
+    }
+
+    pub fn new_timeseries(retention_ms: Option<u64>) -> Self {
+        Value::TimeSeries(TimeSeries::new(retention_ms))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Hash(_) => "hash",
+            Value::List(_) => "list",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+            Value::TimeSeries(_) => "timeseries",
+            Value::Vector(_) => "vector",
+        }
+    }
+
+    /// Approximate payload size in bytes, used for memory accounting.
+    pub fn estimated_size(&self) -> usize {
+        match self {
+            Value::String(s) => s.len(),
+            Value::Hash(hash) => hash.iter().map(|(field, value)| field.len() + value.len()).sum(),
+            Value::List(list) => list.iter().map(|item| item.len()).sum(),
+            Value::Set(set) => set.iter().map(|member| member.len()).sum(),
+            Value::SortedSet(set) => set.iter().map(|(member, _)| member.len() + 8).sum(),
+            Value::Stream(stream) => stream
+                .iter()
+                .map(|(_, fields)| 16 + fields.iter().map(|(field, value)| field.len() + value.len()).sum::<usize>())
+                .sum(),
+            Value::TimeSeries(series) => series.len() * 16,
+            Value::Vector(vector) => vector.len() * 4,
+        }
+    }
+}
+
+/// Keys written since the last backup in a chain of incremental backups.
+/// `chain_id` identifies the full backup the chain builds on.
+struct ChangeLog {
+    chain_id: u64,
+    sequence: u64,
+    keys: HashSet<String>,
+}
+
+/// A key's state for an incremental backup: `None` if it was deleted.
+pub type Change = (String, Option<(Value, Option<Duration>)>);
+
+fn key_states(map: &ShardedMap<ValueWithTtl>, keys: impl Iterator<Item = String>) -> Vec<Change> {
+    let now = Instant::now();
+    keys.map(|key| {
+        let state = map.get(&key)
+            .filter(|value_with_ttl| !value_with_ttl.is_expired())
+            .map(|value_with_ttl| {
+                let remaining = value_with_ttl.expires_at.map(|expires| expires.saturating_duration_since(now));
+                (value_with_ttl.value.clone(), remaining)
+            });
+        (key, state)
+    })
+    .collect()
+}
+
+fn unregister_waiter(waiting: &mut HashMap<String, (usize, u64)>, key: &str) {
+    if let Some((count, _)) = waiting.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            waiting.remove(key);
+        }
+    }
+}
+
+// Fixed per-entry bookkeeping cost added to every key in memory estimates
+const ENTRY_OVERHEAD: usize = 64;
+
+// Items `start..=stop` of a list, with negative indexes counting from the end
+fn list_range(list: &VecDeque<String>, start: i64, stop: i64) -> Vec<String> {
+    let len = list.len() as i64;
+    if len == 0 {
+        return Vec::new();
+    }
+
+    // Handle negative indices
+    let start_idx = if start < 0 {
+        std::cmp::max(0, len + start) as usize
+    } else {
+        std::cmp::min(start as usize, len as usize)
+    };
+
+    let stop_idx = if stop < 0 {
+        std::cmp::max(0, len + stop) as usize
+    } else {
+        std::cmp::min(stop as usize, len as usize - 1)
+    };
+
+    if start_idx > stop_idx {
+        return Vec::new();
+    }
+
+    list.iter().skip(start_idx).take(stop_idx - start_idx + 1).cloned().collect()
+}
+
+// Up to `n` items picked uniformly at random in one pass (reservoir
+// sampling), so sampling costs no more memory than the sample itself
+fn random_sample<T>(items: impl Iterator<Item = T>, n: usize) -> Vec<T> {
+    let mut state = telemetry::random_u64() | 1;
+    let mut next_random = move || {
+        // xorshift64: plenty for picking keys and much cheaper than a hasher per item
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut sample = Vec::with_capacity(n.min(1024));
+    for (seen, item) in items.enumerate() {
+        if sample.len() < n {
+            sample.push(item);
+        } else {
+            let slot = (next_random() % (seen as u64 + 1)) as usize;
+            if slot < n {
+                sample[slot] = item;
+            }
+        }
+    }
+    sample
+}
+
+fn entry_size(key: &str, value: &Value) -> usize {
+    key.len() + value.estimated_size() + ENTRY_OVERHEAD
+}
+
+/// Keys that `wait_for_key` callers are blocked on: per key, the number of
+/// waiters and a counter bumped on every write to it.
+#[derive(Default)]
+struct KeyWaiters {
+    keys: Mutex<HashMap<String, (usize, u64)>>,
+    changed: Condvar,
+}
+
+/// Keys a write may create, counted against `MEDUSA_MAX_KEYS` from
+/// `Store::reserve_keys` until this is dropped after the write.
+#[derive(Debug)]
+#[must_use]
+pub struct KeyReservation {
+    reservations: Arc<Mutex<Vec<String>>>,
+    keys: Vec<String>,
+}
+
+impl Drop for KeyReservation {
+    fn drop(&mut self) {
+        if self.keys.is_empty() {
+            return;
+        }
+        if let Ok(mut reservations) = self.reservations.lock() {
+            for key in &self.keys {
+                if let Some(position) = reservations.iter().position(|reserved| reserved == key) {
+                    reservations.swap_remove(position);
+                }
+            }
+        }
+    }
+}
+
+/// What `wait_for_key` waits for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaitCondition {
+    Exists,
+    Changed,
+}
+
+/// Key count and estimated memory of one key prefix, see `Store::memory_by_prefix`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefixUsage {
+    pub prefix: String,
+    pub keys: usize,
+    pub memory: usize,
+}
+
+/// Largest string `setrange` will build, matching Redis's 512MB limit.
+pub const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+/// What string range commands count in. Values are stored as UTF-8 text;
+/// `Bytes` indexes its encoding (the default, as in Redis), `Chars` its
+/// characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StringUnit {
+    Bytes,
+    Chars,
+}
+
+impl StringUnit {
+    pub fn len(self, s: &str) -> usize {
+        match self {
+            StringUnit::Bytes => s.len(),
+            StringUnit::Chars => s.chars().count(),
+        }
+    }
+
+    // Byte offset of the `index`th unit, or the end of `s` if past it
+    fn byte_offset(self, s: &str, index: usize) -> usize {
+        match self {
+            StringUnit::Bytes => index,
+            StringUnit::Chars => s.char_indices().nth(index).map_or(s.len(), |(offset, _)| offset),
+        }
+    }
+}
+
+// Resolve an inclusive `start..=end` range with negative indexes counting
+// from the end into a clamped half-open range, or `None` if it is empty
+fn resolve_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    if start > end || start >= len {
+        None
+    } else {
+        Some((start as usize, end as usize + 1))
+    }
+}
+
+/// Remaining-TTL buckets for `keyspace_distribution`: upper bounds in
+/// seconds, with keys past the last bound counted in a final bucket.
+pub const TTL_BUCKETS: &[(u64, &str)] = &[(60, "<1m"), (3600, "<1h"), (86400, "<1d"), (604800, "<7d")];
+pub const TTL_OVERFLOW: &str = ">=7d";
+
+/// Value-size buckets (estimated payload bytes, inclusive upper bounds).
+pub const SIZE_BUCKETS: &[(usize, &str)] = &[(64, "<=64B"), (1024, "<=1KB"), (16384, "<=16KB"), (1048576, "<=1MB")];
+pub const SIZE_OVERFLOW: &str = ">1MB";
+
+/// How a sample of the keyspace is distributed, for `DBSTATS`. Counts are
+/// of sampled keys; `total` is the number of live keys.
+#[derive(Debug, Default)]
+pub struct KeyspaceDistribution {
+    pub total: usize,
+    pub sampled: usize,
+    pub persistent: usize,
+    pub volatile: usize,
+    /// One count per `TTL_BUCKETS` entry plus the overflow bucket.
+    pub ttl_buckets: Vec<usize>,
+    /// One count per `SIZE_BUCKETS` entry plus the overflow bucket.
+    pub size_buckets: Vec<usize>,
+    /// Keys per type name, most common first.
+    pub types: Vec<(&'static str, usize)>,
+}
+
+fn bucket_index<T: PartialOrd>(value: T, bounds: impl Iterator<Item = T>, inclusive: bool) -> usize {
+    let mut index = 0;
+    for bound in bounds {
+        if value < bound || (inclusive && value == bound) {
+            return index;
+        }
+        index += 1;
+    }
+    index
 }
 
 #[derive(Clone)]
 pub struct Store {
-    map: Arc<Mutex<HashMap<String, ValueWithTtl>>>,
+    map: Arc<Mutex<ShardedMap<ValueWithTtl>>>,
+    indexes: Arc<Mutex<HashMap<String, SecondaryIndex>>>,
+    search_indexes: Arc<Mutex<HashMap<String, SearchIndex>>>,
+    tenants: TenantRegistry,
+    changes: Arc<Mutex<Option<ChangeLog>>>,
+    faults: FaultInjector,
+    slowlog: SlowLog,
+    latency: LatencyHistograms,
+    persistence: PersistenceStatus,
+    replication: Replication,
+    sentinel: Sentinel,
+    crdt: Crdt,
+    pubsub: PubSub,
+    alarms: AlarmMonitor,
+    waiters: Arc<KeyWaiters>,
+    aliases: AliasRegistry,
+    commands: CommandRegistry,
+    chunk_threshold: Arc<AtomicUsize>,
+    max_keys: Arc<AtomicUsize>,
+    // Keys that writes under way may create, see `reserve_keys`
+    key_reservations: Arc<Mutex<Vec<String>>>,
+    // Where the next search for expired keys to make room starts
+    purge_cursor: Arc<AtomicU64>,
+    delayed: DelayedQueues,
+    stats: KeyspaceStats,
+    flush_token: Arc<Mutex<Option<String>>>,
+    save_file: Arc<Mutex<Option<PathBuf>>>,
+    backup_dir: Arc<Mutex<Option<PathBuf>>>,
+    import_dir: Arc<Mutex<Option<PathBuf>>>,
+    // Writes since startup, for autosave
+    change_count: Arc<AtomicU64>,
+    history: KeyHistory,
+    eviction: Eviction,
+    exec_gate: Arc<RwLock<()>>,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Store {
     pub fn new() -> Self {
+        let tenants = TenantRegistry::new();
+        let mut map = ShardedMap::weighed(|key, value_with_ttl: &ValueWithTtl| entry_size(key, &value_with_ttl.value));
+        let usage = tenants.clone();
+        map.on_change(move |key, keys, memory| usage.account(key, keys, memory));
         Store {
-            map: Arc::new(Mutex::new(HashMap::new())),
+            map: Arc::new(Mutex::new(map)),
+            indexes: Arc::new(Mutex::new(HashMap::new())),
+            search_indexes: Arc::new(Mutex::new(HashMap::new())),
+            tenants,
+            changes: Arc::new(Mutex::new(None)),
+            faults: FaultInjector::new(),
+            slowlog: SlowLog::new(),
+            latency: LatencyHistograms::new(),
+            persistence: PersistenceStatus::new(),
+            replication: Replication::new(),
+            sentinel: Sentinel::new(),
+            crdt: Crdt::new(),
+            pubsub: PubSub::new(),
+            alarms: AlarmMonitor::new(),
+            waiters: Arc::new(KeyWaiters::default()),
+            aliases: AliasRegistry::new(),
+            commands: CommandRegistry::new(),
+            chunk_threshold: Arc::new(AtomicUsize::new(0)),
+            max_keys: Arc::new(AtomicUsize::new(0)),
+            key_reservations: Arc::new(Mutex::new(Vec::new())),
+            purge_cursor: Arc::new(AtomicU64::new(0)),
+            delayed: DelayedQueues::new(),
+            stats: KeyspaceStats::new(),
+            flush_token: Arc::new(Mutex::new(None)),
+            save_file: Arc::new(Mutex::new(None)),
+            backup_dir: Arc::new(Mutex::new(None)),
+            import_dir: Arc::new(Mutex::new(None)),
+            change_count: Arc::new(AtomicU64::new(0)),
+            history: KeyHistory::new(),
+            eviction: Eviction::new(),
+            exec_gate: Arc::new(RwLock::new(())),
         }
     }
 
-    pub fn set(&self, key: &str, value: &str) -> Result<(), String> {
-        match self.map.lock() {
-            Ok(mut map) => {
-                map.insert(key.to_string(), ValueWithTtl::new(Value::new(value.to_string())));
-                Ok(())
-            }
-            Err(_) => Err("Failed to acquire lock".to_string()),
-        }
+    /// A store whose server also answers the commands in `commands`.
+    pub fn with_commands(commands: CommandRegistry) -> Self {
+        Store { commands, ..Self::new() }
     }
 
-    pub fn set_with_ttl(&self, key: &str, value: &str, ttl_seconds: u64) -> Result<(), String> {
-        match self.map.lock() {
-            Ok(mut map) => {
-                map.insert(key.to_string(), ValueWithTtl::with_ttl(Value::new(value.to_string()), ttl_seconds));
-                Ok(())
-            }
-            Err(_) => Err("Failed to acquire lock".to_string()),
-        }
+    pub fn eviction(&self) -> &Eviction {
+        &self.eviction
     }
 
-    pub fn get(&self, key: &str) -> Result<Option<String>, String> {
-        match self.map.lock() {
-            Ok(map) => {
-                if let Some(value_with_ttl) = map.get(key) {
-                    if value_with_ttl.is_expired() {
-                        Ok(None)
-                    } else {
-                        match &value_with_ttl.value {
-                            Value::String(s) => Ok(Some(s.clone())),
-                            _ => Err("Key contains non-string value".to_string()),
+    /// Client commands run holding this shared and `EXEC` holds it
+    /// exclusively, so a transaction's commands run back to back without
+    /// other clients' commands in between.
+    pub fn exec_gate(&self) -> &RwLock<()> {
+        &self.exec_gate
+    }
+
+    /// Make room for a write of about `incoming_bytes` under the memory
+    /// limit, evicting keys by the configured policy. Returns the number of
+    /// keys evicted, or an error if the write must be refused.
+    pub fn evict_if_needed(&self, incoming_bytes: usize) -> Result<usize, String> {
+        let max_memory = self.eviction.max_memory();
+        if max_memory == 0 {
+            return Ok(0);
+        }
+        let policy = self.eviction.policy();
+        let samples = self.eviction.samples();
+        let evict_hook = self.eviction.evict_hook();
+        let pins = self.eviction.pins();
+        // Evicted entries are handed to the hook once the lock is released
+        let mut victims = Vec::new();
+
+        let result = match self.map.lock() {
+            Ok(mut map) => {
+                // The map keeps a running total of its entries' sizes, and
+                // candidates come from a few buckets picked at random, so
+                // neither costs a pass over the keyspace
+                let mut evicted = 0;
+                let mut out_of_memory = false;
+
+                while map.used() + incoming_bytes > max_memory {
+                    if policy == EvictionPolicy::NoEviction {
+                        out_of_memory = true;
+                        break;
+                    }
+                    let candidates = map.sample(samples, |key, value_with_ttl| {
+                        (policy == EvictionPolicy::AllKeysLru || value_with_ttl.expires_at.is_some()) && !pins.contains(key)
+                    });
+                    // Expired keys sampled along the way are the first to go
+                    let mut expired = Vec::new();
+                    for (key, value_with_ttl) in candidates {
+                        if value_with_ttl.is_expired() {
+                            expired.push(key.clone());
+                        } else {
+                            self.eviction.offer(key, value_with_ttl.last_access.elapsed());
+                        }
+                    }
+                    if !expired.is_empty() {
+                        for key in &expired {
+                            self.remove_expired(&mut map, key);
                         }
+                        continue;
                     }
+
+                    // Pooled candidates may have been deleted or pinned since they were sampled
+                    let victim = std::iter::from_fn(|| self.eviction.take_best())
+                        .filter(|key| !pins.contains(key))
+                        .find_map(|key| map.remove_entry(&key));
+                    let Some((key, value_with_ttl)) = victim else {
+                        out_of_memory = true;
+                        break;
+                    };
+                    self.unindex_value(&key, &value_with_ttl.value);
+                    self.reindex_search(&key, None);
+                    self.mark_changed(&mut map, &key);
+                    self.eviction.record_evicted();
+                    evicted += 1;
+                    if evict_hook.is_some() {
+                        victims.push((key, value_with_ttl.value));
+                    }
+                }
+                if out_of_memory {
+                    Err("OOM command not allowed when used memory > 'maxmemory'".to_string())
                 } else {
-                    Ok(None)
+                    Ok(evicted)
                 }
             }
+            Err(_) => return Err("Failed to acquire lock".to_string()),
+        };
+
+        if let Some(hook) = evict_hook {
+            for (key, value) in &victims {
+                hook(key, value, policy);
+            }
+        }
+        if result.is_err() {
+            if let Some(hook) = self.eviction.oom_hook() {
+                hook();
+            }
+        }
+        result
+    }
+
+    pub fn history(&self) -> &KeyHistory {
+        &self.history
+    }
+
+    pub fn stats(&self) -> &KeyspaceStats {
+        &self.stats
+    }
+
+    pub fn delayed(&self) -> &DelayedQueues {
+        &self.delayed
+    }
+
+    /// With a token set, `FLUSHALL` only runs as `FLUSHALL CONFIRM <token>`.
+    pub fn set_flush_token(&self, token: Option<String>) {
+        if let Ok(mut flush_token) = self.flush_token.lock() {
+            *flush_token = token;
+        }
+    }
+
+    /// Where `SAVE` and `BGSAVE` write their snapshot.
+    pub fn set_save_file(&self, path: Option<PathBuf>) {
+        if let Ok(mut save_file) = self.save_file.lock() {
+            *save_file = path;
+        }
+    }
+
+    pub fn save_file(&self) -> Option<PathBuf> {
+        self.save_file.lock().ok().and_then(|path| path.clone())
+    }
+
+    /// The directory `BACKUP` reads and writes files in.
+    pub fn set_backup_dir(&self, dir: Option<PathBuf>) {
+        if let Ok(mut backup_dir) = self.backup_dir.lock() {
+            *backup_dir = dir;
+        }
+    }
+
+    pub fn backup_dir(&self) -> Option<PathBuf> {
+        self.backup_dir.lock().ok().and_then(|dir| dir.clone())
+    }
+
+    /// The directory `IMPORT` reads files from.
+    pub fn set_import_dir(&self, dir: Option<PathBuf>) {
+        if let Ok(mut import_dir) = self.import_dir.lock() {
+            *import_dir = dir;
+        }
+    }
+
+    pub fn import_dir(&self) -> Option<PathBuf> {
+        self.import_dir.lock().ok().and_then(|dir| dir.clone())
+    }
+
+    /// Keys written or removed since startup, counting each change once.
+    pub fn change_count(&self) -> u64 {
+        self.change_count.load(Ordering::Relaxed)
+    }
+
+    /// Whether a flush confirmed with `confirm` may go ahead.
+    pub fn check_flush(&self, confirm: Option<&str>) -> Result<(), String> {
+        match self.flush_token.lock() {
+            Ok(token) => match (token.as_deref(), confirm) {
+                (None, _) => Ok(()),
+                (Some(token), Some(confirm)) if token == confirm => Ok(()),
+                (Some(_), Some(_)) => Err("Wrong confirmation token".to_string()),
+                (Some(_), None) => Err("Flushing is protected on this server, use FLUSHALL CONFIRM <token>".to_string()),
+            },
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn ttl(&self, key: &str) -> Result<Option<i64>, String> {
+    /// String values longer than this many bytes must be read with
+    /// `GETCHUNK`; 0 disables the limit.
+    pub fn set_chunk_threshold(&self, bytes: usize) {
+        self.chunk_threshold.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn chunk_threshold(&self) -> Option<usize> {
+        match self.chunk_threshold.load(Ordering::Relaxed) {
+            0 => None,
+            bytes => Some(bytes),
+        }
+    }
+
+    /// Writes that would create keys beyond this many live keys are
+    /// refused; 0 disables the limit. Updates and deletes still go ahead.
+    pub fn set_max_keys(&self, keys: usize) {
+        self.max_keys.store(keys, Ordering::Relaxed);
+    }
+
+    pub fn max_keys(&self) -> Option<usize> {
+        match self.max_keys.load(Ordering::Relaxed) {
+            0 => None,
+            keys => Some(keys),
+        }
+    }
+
+    /// Reserve room under the key limit for a write to `keys`. Only keys
+    /// that do not exist yet count, so a full store still takes updates.
+    /// The check and the reservation are made under the map lock, and the
+    /// reservation counts until it is dropped, so writes running at the
+    /// same time cannot together go over the limit.
+    pub fn reserve_keys(&self, keys: &[&str]) -> Result<KeyReservation, String> {
+        let mut reservation = KeyReservation { reservations: self.key_reservations.clone(), keys: Vec::new() };
+        let Some(max_keys) = self.max_keys() else { return Ok(reservation) };
         match self.map.lock() {
             Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(Some(-1))
-                    } else {
-                        Ok(value_with_ttl.ttl_seconds())
+                let mut new_keys: Vec<String> = Vec::new();
+                for key in keys {
+                    let live = map.get(key).is_some_and(|value_with_ttl| !value_with_ttl.is_expired());
+                    if !live && !new_keys.iter().any(|new_key| new_key == key) {
+                        new_keys.push(key.to_string());
                     }
-                } else {
-                    // Check if this is a key that was recently accessed and found to be expired
-                    // For now, we'll return None for truly non-existent keys
-                    // But the test expects Some(-1) for expired keys
-                    // We need a different approach - let's check if we just removed an expired key
-                    Ok(None)
                 }
+                if new_keys.is_empty() {
+                    return Ok(reservation);
+                }
+
+                let mut reservations = self.key_reservations.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+                loop {
+                    // Keys already in the map are counted by its length
+                    let pending: HashSet<&String> = reservations.iter().chain(&new_keys).filter(|key| !map.contains_key(key)).collect();
+                    if map.len() + pending.len() <= max_keys {
+                        break;
+                    }
+                    // Expired keys still take up room until purged, so look
+                    // through the next few entries for some
+                    if self.purge_expired(&mut map) == 0 {
+                        return Err(format!("MAXKEYS Key limit of {} reached, only existing keys can be written", max_keys));
+                    }
+                }
+                reservations.extend(new_keys.iter().cloned());
+                reservation.keys = new_keys;
+                Ok(reservation)
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn expire(&self, key: &str, ttl_seconds: u64) -> Result<bool, String> {
-        match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get_mut(key) {
-                    value_with_ttl.expires_at = Some(Instant::now() + Duration::from_secs(ttl_seconds));
-                    Ok(true)
-                } else {
-                    Ok(false)
-                }
+    // Remove the expired keys among the next `KEY_LIMIT_PURGE` entries,
+    // going round the keyspace over successive calls. Returns how many went.
+    fn purge_expired(&self, map: &mut ShardedMap<ValueWithTtl>) -> usize {
+        let mut expired = Vec::new();
+        let cursor = map.scan(self.purge_cursor.load(Ordering::Relaxed), KEY_LIMIT_PURGE, |key, value_with_ttl| {
+            if value_with_ttl.is_expired() {
+                expired.push(key.clone());
             }
-            Err(_) => Err("Failed to acquire lock".to_string()),
+        });
+        self.purge_cursor.store(cursor, Ordering::Relaxed);
+        for key in &expired {
+            self.remove_expired(map, key);
         }
+        expired.len()
     }
 
-    pub fn delete(&self, key: &str) -> Result<Option<String>, String> {
+    // Drop `key`, found expired, with its secondary and search index entries
+    fn remove_expired(&self, map: &mut ShardedMap<ValueWithTtl>, key: &str) {
+        if let Some(value_with_ttl) = map.remove(key) {
+            self.unindex_value(key, &value_with_ttl.value);
+            self.reindex_search(key, None);
+            self.mark_changed(map, key);
+        }
+    }
+
+    pub fn tenants(&self) -> &TenantRegistry {
+        &self.tenants
+    }
+
+    /// Define tenant `name` or update its quota. A new tenant's usage
+    /// starts from the keys already in its namespace.
+    pub fn set_tenant(&self, name: &str, quota: TenantQuota) -> Result<(), String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.remove(key) {
-                    match value_with_ttl.value {
-                        Value::String(s) => Ok(Some(s)),
-                        _ => Ok(Some("(non-string)".to_string())),
+            Ok(map) => {
+                let new = self.tenants.quota(name).is_none();
+                self.tenants.set(name, quota)?;
+                if new {
+                    let prefix = format!("{}:", name);
+                    for (key, value_with_ttl) in map.iter().filter(|(key, _)| key.starts_with(&prefix)) {
+                        self.tenants.account(key, 1, entry_size(key, &value_with_ttl.value) as isize);
                     }
-                } else {
-                    Ok(None)
                 }
+                Ok(())
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn list_keys(&self) -> Result<Vec<String>, String> {
+    pub fn faults(&self) -> &FaultInjector {
+        &self.faults
+    }
+
+    pub fn slowlog(&self) -> &SlowLog {
+        &self.slowlog
+    }
+
+    pub fn latency(&self) -> &LatencyHistograms {
+        &self.latency
+    }
+
+    pub fn persistence(&self) -> &PersistenceStatus {
+        &self.persistence
+    }
+
+    pub fn replication(&self) -> &Replication {
+        &self.replication
+    }
+
+    pub fn sentinel(&self) -> &Sentinel {
+        &self.sentinel
+    }
+
+    pub fn crdt(&self) -> &Crdt {
+        &self.crdt
+    }
+
+    pub fn pubsub(&self) -> &PubSub {
+        &self.pubsub
+    }
+
+    pub fn alarms(&self) -> &AlarmMonitor {
+        &self.alarms
+    }
+
+    pub fn aliases(&self) -> &AliasRegistry {
+        &self.aliases
+    }
+
+    pub fn commands(&self) -> &CommandRegistry {
+        &self.commands
+    }
+
+    // Fault injection: hold the map lock for `duration`, stalling every
+    // other command that touches the store
+    pub fn stall(&self, duration: Duration) -> Result<(), String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                map.retain(|_, value_with_ttl| !value_with_ttl.is_expired());
-                Ok(map.keys().cloned().collect())
+            Ok(_map) => {
+                std::thread::sleep(duration);
+                Ok(())
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn count(&self) -> Result<usize, String> {
+    /// Enforce the quota of the tenant owning `key` before running `command`.
+    /// Every command counts towards ops/sec; commands that can grow the
+    /// keyspace are also held to the key and memory limits, with
+    /// `incoming_bytes` as the expected growth.
+    pub fn check_tenant_quota(&self, command: &str, key: &str, incoming_bytes: usize) -> Result<(), String> {
+        let name = match self.tenants.tenant_for_key(key) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        self.tenants.admit_op(&name)?;
+
+        if !tenant::is_growing_command(command) {
+            return Ok(());
+        }
+        let quota = match self.tenants.quota(&name) {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let (keys, memory) = self.tenants.usage(&name).unwrap_or_default();
+
+        if let Some(max_keys) = quota.max_keys {
+            if keys >= max_keys && !self.exists(key)? {
+                self.tenants.record_rejection(&name);
+                return Err(format!("Tenant '{}' reached its limit of {} keys", name, max_keys));
+            }
+        }
+        if let Some(max_memory) = quota.max_memory {
+            if memory + incoming_bytes > max_memory {
+                self.tenants.record_rejection(&name);
+                return Err(format!("Tenant '{}' would exceed its memory limit of {} bytes", name, max_memory));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> Result<(), String> {
         match self.map.lock() {
             Ok(mut map) => {
-                map.retain(|_, value_with_ttl| !value_with_ttl.is_expired());
-                Ok(map.len())
+                if let Some(old) = map.insert(key.to_string(), ValueWithTtl::new(Value::new(value.to_string()))) {
+                    self.unindex_value(key, &old.value);
+                }
+                self.reindex_search(key, map.get(key).map(|entry| &entry.value));
+                self.mark_changed(&mut map, key);
+                Ok(())
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn exists(&self, key: &str) -> Result<bool, String> {
-        match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(false)
-                    } else {
-                        Ok(true)
+    pub fn set_with_ttl(&self, key: &str, value: &str, ttl_seconds: u64) -> Result<(), String> {
+        self.set_with_expiry(key, value, Duration::from_secs(ttl_seconds))
+    }
+
+    pub fn set_with_expiry(&self, key: &str, value: &str, ttl: Duration) -> Result<(), String> {
+        let expires_at = Instant::now().checked_add(ttl).ok_or_else(|| "Invalid expire time".to_string())?;
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = ValueWithTtl {
+                    expires_at: Some(expires_at),
+                    ..ValueWithTtl::new(Value::new(value.to_string()))
+                };
+                if let Some(old) = map.insert(key.to_string(), entry) {
+                    self.unindex_value(key, &old.value);
+                }
+                self.reindex_search(key, map.get(key).map(|entry| &entry.value));
+                self.mark_changed(&mut map, key);
+                Ok(())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>, String> {
+        match self.map.lock() {
+            Ok(map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        Ok(None)
+                    } else {
+                        match &value_with_ttl.value {
+                            Value::String(s) => Ok(Some(s.clone())),
+                            _ => Err("Key contains non-string value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(None)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Read a string value in place instead of copying it out, recording
+    /// the access for `OBJECT IDLETIME` under the same lock if `touch` is set.
+    pub fn read_string<R>(&self, key: &str, touch: bool, read: impl FnOnce(&str) -> R) -> Result<Option<R>, String> {
+        match self.map.lock() {
+            Ok(mut map) => match map.get_mut(key) {
+                Some(value_with_ttl) if !value_with_ttl.is_expired() => match &value_with_ttl.value {
+                    Value::String(s) => {
+                        if touch {
+                            value_with_ttl.last_access = Instant::now();
+                        }
+                        Ok(Some(read(s)))
+                    }
+                    _ => Err("Key contains non-string value".to_string()),
+                },
+                _ => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// A consistent read-only view of the whole store for reading several
+    /// keys that must agree with each other. Writers wait until it is
+    /// dropped, so keep it short-lived.
+    pub fn read_transaction(&self) -> Result<ReadTransaction<'_>, String> {
+        match self.map.lock() {
+            Ok(map) => Ok(ReadTransaction { map, now: Instant::now() }),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Length in bytes of a string value.
+    pub fn string_len(&self, key: &str) -> Result<Option<usize>, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key) {
+                Some(value_with_ttl) if !value_with_ttl.is_expired() => match &value_with_ttl.value {
+                    Value::String(s) => Ok(Some(s.len())),
+                    _ => Err("Key contains non-string value".to_string()),
+                },
+                _ => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Up to `length` bytes of a string value from byte `offset`, with the
+    /// value's total length. The range is narrowed to character boundaries
+    /// so every chunk is valid UTF-8 and the chunks join back up exactly.
+    pub fn get_chunk(&self, key: &str, offset: usize, length: usize) -> Result<Option<(String, usize)>, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key) {
+                Some(value_with_ttl) if !value_with_ttl.is_expired() => match &value_with_ttl.value {
+                    Value::String(s) => {
+                        if offset > s.len() {
+                            return Err(format!("Offset {} is past the end of the value ({} bytes)", offset, s.len()));
+                        }
+                        if !s.is_char_boundary(offset) {
+                            return Err(format!("Offset {} is inside a character", offset));
+                        }
+                        let mut end = offset.saturating_add(length).min(s.len());
+                        while !s.is_char_boundary(end) {
+                            end -= 1;
+                        }
+                        Ok(Some((s[offset..end].to_string(), s.len())))
+                    }
+                    _ => Err("Key contains non-string value".to_string()),
+                },
+                _ => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Write a chunk of a string value: offset 0 starts a new value, any
+    /// other offset must be the current length and appends. Returns the new
+    /// length. Requiring the offset makes a retried chunk fail instead of
+    /// being appended twice.
+    pub fn set_chunk(&self, key: &str, offset: usize, data: &str) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if offset == 0 {
+                    if let Some(old) = map.insert(key.to_string(), ValueWithTtl::new(Value::new(data.to_string()))) {
+                        self.unindex_value(key, &old.value);
+                    }
+                } else {
+                    match map.get_mut(key).filter(|entry| !entry.is_expired()) {
+                        Some(ValueWithTtl { value: Value::String(s), .. }) if s.len() == offset => s.push_str(data),
+                        Some(ValueWithTtl { value: Value::String(s), .. }) => {
+                            return Err(format!("Expected offset {}, the current length", s.len()));
+                        }
+                        Some(_) => return Err("Key contains non-string value".to_string()),
+                        None => return Err("Key not found; the first chunk must have offset 0".to_string()),
+                    }
+                }
+                let length = match map.get(key) {
+                    Some(entry) => {
+                        self.reindex_search(key, Some(&entry.value));
+                        entry.value.estimated_size()
+                    }
+                    None => 0,
+                };
+                self.mark_changed(&mut map, key);
+                Ok(length)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Length of a string value in `unit`s; a missing key has length 0.
+    pub fn strlen(&self, key: &str, unit: StringUnit) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key) {
+                Some(value_with_ttl) if !value_with_ttl.is_expired() => match &value_with_ttl.value {
+                    Value::String(s) => Ok(unit.len(s)),
+                    _ => Err("Key contains non-string value".to_string()),
+                },
+                _ => Ok(0),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// The part of a string value from `start` to `end` inclusive, counted
+    /// in `unit`s. Negative indexes count back from the end and the range is
+    /// clamped to the value, so an empty or missing value gives "". A byte
+    /// range that would split a character is an error rather than a lossy
+    /// slice.
+    pub fn getrange(&self, key: &str, start: i64, end: i64, unit: StringUnit) -> Result<String, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key) {
+                Some(value_with_ttl) if !value_with_ttl.is_expired() => match &value_with_ttl.value {
+                    Value::String(s) => match resolve_range(start, end, unit.len(s)) {
+                        Some((from, to)) => {
+                            let (from, to) = (unit.byte_offset(s, from), unit.byte_offset(s, to));
+                            if !s.is_char_boundary(from) || !s.is_char_boundary(to) {
+                                return Err(format!("Byte range {}-{} splits a character; use UTF8 to index by character", from, to - 1));
+                            }
+                            Ok(s[from..to].to_string())
+                        }
+                        None => Ok(String::new()),
+                    },
+                    _ => Err("Key contains non-string value".to_string()),
+                },
+                _ => Ok(String::new()),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Replace the string at `key` with `new` only if it currently equals
+    /// `expected`, keeping its TTL. A missing key never matches. Returns
+    /// whether the value was replaced.
+    pub fn compare_and_swap(&self, key: &str, expected: &str, new: &str) -> Result<bool, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                match map.get_mut(key).filter(|entry| !entry.is_expired()) {
+                    Some(ValueWithTtl { value: Value::String(current), .. }) if current == expected => *current = new.to_string(),
+                    Some(ValueWithTtl { value: Value::String(_), .. }) | None => return Ok(false),
+                    Some(_) => return Err("Key contains non-string value".to_string()),
+                }
+                self.reindex_search(key, map.get(key).map(|entry| &entry.value));
+                self.mark_changed(&mut map, key);
+                Ok(true)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Overwrite a string value with `data` from `offset` (in `unit`s),
+    /// padding with NUL characters when the offset is past the end and
+    /// creating the key if needed. Keeps the key's TTL. Returns the new
+    /// length in `unit`s. As with `getrange`, overwriting part of a
+    /// character is an error.
+    pub fn setrange(&self, key: &str, offset: usize, data: &str, unit: StringUnit) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let current = match map.get(key).filter(|entry| !entry.is_expired()) {
+                    Some(ValueWithTtl { value: Value::String(s), .. }) => s.as_str(),
+                    Some(_) => return Err("Key contains non-string value".to_string()),
+                    None => "",
+                };
+                let length = unit.len(current);
+                // Like Redis, an empty write to a missing key creates nothing
+                if data.is_empty() && current.is_empty() {
+                    return Ok(length);
+                }
+                let new_length = length.max(offset.saturating_add(unit.len(data)));
+                if new_length > MAX_STRING_LENGTH {
+                    return Err(format!("Value would exceed the maximum of {} bytes", MAX_STRING_LENGTH));
+                }
+
+                let mut value = current.to_string();
+                if offset > length {
+                    value.push_str(&"\0".repeat(offset - length));
+                }
+                let from = unit.byte_offset(&value, offset);
+                let to = unit.byte_offset(&value, offset + unit.len(data)).min(value.len());
+                if !value.is_char_boundary(from) || !value.is_char_boundary(to) {
+                    return Err(format!("Writing at byte {} would split a character; use UTF8 to index by character", offset));
+                }
+                value.replace_range(from..to, data);
+
+                match map.get_mut(key).filter(|entry| !entry.is_expired()) {
+                    Some(entry) => entry.value = Value::String(value),
+                    None => {
+                        if let Some(old) = map.insert(key.to_string(), ValueWithTtl::new(Value::String(value))) {
+                            self.unindex_value(key, &old.value);
+                        }
+                    }
+                }
+                if let Some(entry) = map.get(key) {
+                    self.reindex_search(key, Some(&entry.value));
+                }
+                self.mark_changed(&mut map, key);
+                Ok(new_length)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// The value and remaining TTL in seconds (`None` if it never expires),
+    /// read under one lock so they cannot disagree.
+    pub fn get_with_ttl(&self, key: &str) -> Result<Option<(String, Option<i64>)>, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key) {
+                Some(value_with_ttl) if !value_with_ttl.is_expired() => match &value_with_ttl.value {
+                    Value::String(s) => Ok(Some((s.clone(), value_with_ttl.ttl_seconds()))),
+                    _ => Err("Key contains non-string value".to_string()),
+                },
+                _ => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Longest common subsequence of two string values, read together.
+    /// Missing keys count as empty strings.
+    pub fn lcs(&self, key1: &str, key2: &str) -> Result<LcsResult, String> {
+        let (a, b) = match self.map.lock() {
+            Ok(map) => {
+                let read = |key: &str| match map.get(key) {
+                    Some(value_with_ttl) if !value_with_ttl.is_expired() => match &value_with_ttl.value {
+                        Value::String(s) => Ok(s.clone()),
+                        _ => Err(format!("Key '{}' contains non-string value", key)),
+                    },
+                    _ => Ok(String::new()),
+                };
+                (read(key1)?, read(key2)?)
+            }
+            Err(_) => return Err("Failed to acquire lock".to_string()),
+        };
+        lcs::longest_common_subsequence(&a, &b)
+    }
+
+    pub fn ttl(&self, key: &str) -> Result<Option<i64>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(Some(-1))
+                    } else {
+                        Ok(value_with_ttl.ttl_seconds())
+                    }
+                } else {
+                    // Check if this is a key that was recently accessed and found to be expired
+                    // For now, we'll return None for truly non-existent keys
+                    // But the test expects Some(-1) for expired keys
+                    // We need a different approach - let's check if we just removed an expired key
+                    Ok(None)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn expire(&self, key: &str, ttl_seconds: u64) -> Result<bool, String> {
+        let expires_at = Instant::now()
+            .checked_add(Duration::from_secs(ttl_seconds))
+            .ok_or_else(|| "Invalid TTL value".to_string())?;
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get_mut(key) {
+                    value_with_ttl.expires_at = Some(expires_at);
+                    self.mark_changed(&mut map, key);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Set the same TTL on each of `keys` that exists, under one lock.
+    /// Returns how many were updated.
+    pub fn expire_many(&self, keys: &[&str], ttl_seconds: u64) -> Result<usize, String> {
+        let expires_at = Instant::now()
+            .checked_add(Duration::from_secs(ttl_seconds))
+            .ok_or_else(|| "Invalid TTL value".to_string())?;
+        match self.map.lock() {
+            Ok(mut map) => {
+                let mut updated = 0;
+                for key in keys {
+                    if let Some(value_with_ttl) = map.get_mut(key).filter(|entry| !entry.is_expired()) {
+                        value_with_ttl.expires_at = Some(expires_at);
+                        self.mark_changed(&mut map, key);
+                        updated += 1;
+                    }
+                }
+                Ok(updated)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Set a TTL on every key matching the glob `pattern`. Matching keys
+    /// are collected first, then updated `EXPIRE_BATCH` at a time with the
+    /// lock released in between, so other clients are not stalled while
+    /// thousands of keys are re-TTLed.
+    pub fn expire_pattern(&self, pattern: &str, ttl_seconds: u64) -> Result<usize, String> {
+        let keys: Vec<String> = self.list_keys()?
+            .into_iter()
+            .filter(|key| pattern_matches(pattern, key))
+            .collect();
+
+        let mut updated = 0;
+        for batch in keys.chunks(EXPIRE_BATCH) {
+            let batch: Vec<&str> = batch.iter().map(String::as_str).collect();
+            updated += self.expire_many(&batch, ttl_seconds)?;
+        }
+        Ok(updated)
+    }
+
+    /// One step of an incremental keyspace scan. The cursor is a position
+    /// in the keyspace's hash tables, a shard and a bucket in it, so no
+    /// per-scan state is kept and a step only reads the buckets it returns.
+    /// A key present for the whole scan is returned at least once however
+    /// the keyspace changes in between; a key never present is never
+    /// returned. Keys added or removed during the scan may or may not be
+    /// seen. Each step inspects about `count` keys, whole buckets at a
+    /// time, and returns those that `pattern` and `type_name` let through.
+    /// Start with cursor 0; a returned cursor of 0 means the scan is
+    /// complete.
+    pub fn scan(&self, cursor: u64, count: usize, pattern: Option<&str>, type_name: Option<&str>) -> Result<(u64, Vec<String>), String> {
+        match self.map.lock() {
+            Ok(map) => {
+                let mut keys = Vec::new();
+                let next_cursor = map.scan(cursor, count, |key, value_with_ttl| {
+                    if !value_with_ttl.is_expired()
+                        && pattern.is_none_or(|pattern| pattern_matches(pattern, key))
+                        && type_name.is_none_or(|type_name| value_with_ttl.value.type_name() == type_name)
+                    {
+                        keys.push(key.clone());
+                    }
+                });
+                Ok((next_cursor, keys))
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn delete(&self, key: &str) -> Result<Option<String>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.remove(key) {
+                    self.unindex_value(key, &value_with_ttl.value);
+                    self.reindex_search(key, None);
+                    self.mark_changed(&mut map, key);
+                    match value_with_ttl.value {
+                        Value::String(s) => Ok(Some(s)),
+                        _ => Ok(Some("(non-string)".to_string())),
+                    }
+                } else {
+                    Ok(None)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Read-modify-write `key` under the map lock, so nothing can change it
+    /// between the read and the write. `f` gets the current value (`None`
+    /// if missing or expired) and returns the one to store, or `None` to
+    /// delete the key. A replaced key keeps its TTL. Returns the value now
+    /// stored. `f` must not call back into the store.
+    pub fn update(&self, key: &str, f: impl FnOnce(Option<Value>) -> Option<Value>) -> Result<Option<Value>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let (current, expires_at) = match map.remove(key) {
+                    Some(old) => {
+                        self.unindex_value(key, &old.value);
+                        if old.is_expired() {
+                            (None, None)
+                        } else {
+                            (Some(old.value), old.expires_at)
+                        }
+                    }
+                    None => (None, None),
+                };
+                let existed = current.is_some();
+                let updated = f(current);
+                match &updated {
+                    Some(value) => {
+                        if let Value::Hash(hash) = value {
+                            for (field, field_value) in hash {
+                                self.index_hash_field(key, field, None, Some(field_value));
+                            }
+                        }
+                        self.reindex_search(key, Some(value));
+                        let mut entry = ValueWithTtl::new(value.clone());
+                        entry.expires_at = expires_at;
+                        map.insert(key.to_string(), entry);
+                        self.mark_changed(&mut map, key);
+                    }
+                    None if existed => {
+                        self.reindex_search(key, None);
+                        self.mark_changed(&mut map, key);
+                    }
+                    None => {}
+                }
+                Ok(updated)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn list_keys(&self) -> Result<Vec<String>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                map.retain(|_, value_with_ttl| !value_with_ttl.is_expired());
+                Ok(map.keys().cloned().collect())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn count(&self) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                map.retain(|_, value_with_ttl| !value_with_ttl.is_expired());
+                Ok(map.len())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn exists(&self, key: &str) -> Result<bool, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(false)
+                    } else {
+                        Ok(true)
+                    }
+                } else {
+                    Ok(false)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn keys_pattern(&self, pattern: &str) -> Result<Vec<String>, String> {
+        Ok(self.list_keys()?.into_iter().filter(|key| pattern_matches(pattern, key)).collect())
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let keys: Vec<String> = map.keys().cloned().collect();
+                map.clear();
+                for key in &keys {
+                    self.mark_changed(&mut map, key);
+                }
+                if let Ok(mut indexes) = self.indexes.lock() {
+                    indexes.values_mut().for_each(SecondaryIndex::clear);
+                }
+                if let Ok(mut search_indexes) = self.search_indexes.lock() {
+                    search_indexes.values_mut().for_each(SearchIndex::clear);
+                }
+                Ok(())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn flush_all(&self) -> Result<(), String> {
+        self.clear()
+    }
+
+    // Alias for keys_pattern for compatibility
+    pub fn keys(&self, pattern: &str) -> Result<Vec<String>, String> {
+        self.keys_pattern(pattern)
+    }
+
+    // Server info method
+    pub fn info(&self) -> Result<String, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                map.retain(|_, value_with_ttl| !value_with_ttl.is_expired());
+                let count = map.len();
+                let used_memory = map.used();
+                let mut info = format!(
+                    "# Server\nmedusa_version:0.1.0\nuptime_in_seconds:unknown\nchunk_threshold:{}\n\n# Memory\nused_memory:{}\nmaxmemory:{}\nmaxmemory_policy:{}\nmaxmemory_samples:{}\nmaxkeys:{}\ntotal_keys:{}\n\n# Stats\ntotal_connections_received:unknown\ntotal_commands_processed:unknown\nevicted_keys:{}\nkeyspace_hits:{}\nkeyspace_misses:{}\nkeyspace_hit_rate:{:.4}",
+                    self.chunk_threshold().unwrap_or(0),
+                    used_memory,
+                    self.eviction.max_memory(),
+                    self.eviction.policy().name(),
+                    self.eviction.samples(),
+                    self.max_keys().unwrap_or(0),
+                    count,
+                    self.eviction.evicted(),
+                    self.stats.hits(),
+                    self.stats.misses(),
+                    stats::hit_rate(self.stats.hits(), self.stats.misses())
+                );
+
+                // Medusa has a single database, reported as db0 like Redis;
+                // avg_ttl is the mean remaining TTL in ms of keys that have one
+                let now = Instant::now();
+                let (expires, ttl_total) = map
+                    .values()
+                    .filter_map(|value_with_ttl| value_with_ttl.expires_at)
+                    .fold((0u128, 0u128), |(expires, total), at| (expires + 1, total + at.saturating_duration_since(now).as_millis()));
+                let avg_ttl = ttl_total.checked_div(expires).unwrap_or(0);
+                info.push_str(&format!("\n\n# Keyspace\ndb0:keys={},expires={},avg_ttl={}", count, expires, avg_ttl));
+
+                // Shown once snapshots are scheduled or a save has been asked for
+                let persistence = self.persistence.get();
+                let time = |t: Option<u64>| t.map(|t| t.to_string()).unwrap_or_else(|| "-1".to_string());
+                let status = |error: &Option<String>| error.as_ref().map(|e| format!("err ({})", e)).unwrap_or_else(|| "ok".to_string());
+                let saved = persistence.save_started.is_some() || persistence.last_save.is_some() || persistence.last_save_error.is_some();
+                if persistence.schedule.is_some() || persistence.autosave.is_some() || saved {
+                    info.push_str(&format!(
+                        "\n\n# Persistence\nchanges_since_last_save:{}\nautosave:{}\nsave_in_progress:{}\ncurrent_save_time_sec:{}\nlast_save_time:{}\nlast_save_keys:{}\nlast_save_status:{}",
+                        self.change_count().saturating_sub(persistence.changes_at_last_save),
+                        persistence.autosave.as_deref().unwrap_or("off"),
+                        u8::from(persistence.save_started.is_some()),
+                        persistence.save_started.map(|started| (now_millis() / 1000).saturating_sub(started) as i64).unwrap_or(-1),
+                        time(persistence.last_save),
+                        persistence.last_save_keys,
+                        status(&persistence.last_save_error)
+                    ));
+                }
+                if let Some(schedule) = &persistence.schedule {
+                    info.push_str(&format!(
+                        "\nsnapshot_schedule:{}\nsnapshot_dir:{}\nsnapshot_keep:{}\nnext_snapshot_time:{}\nlast_snapshot_time:{}\nlast_snapshot_status:{}\nsnapshots_taken:{}\nsnapshots_failed:{}\nsnapshots_retained:{}",
+                        schedule,
+                        persistence.directory.as_ref().map(|dir| dir.display().to_string()).unwrap_or_default(),
+                        persistence.keep,
+                        time(persistence.next_snapshot),
+                        time(persistence.last_snapshot),
+                        status(&persistence.last_error),
+                        persistence.snapshots_taken,
+                        persistence.snapshots_failed,
+                        persistence.snapshots_retained
+                    ));
+                }
+
+                info.push_str(&self.replication.info());
+                info.push_str(&self.sentinel.info());
+                info.push_str(&self.crdt.info());
+                info.push_str(&self.pubsub.info());
+
+                let tenants = self.tenants.names()?;
+                if !tenants.is_empty() {
+                    info.push_str("\n\n# Tenants");
+                }
+                for name in tenants {
+                    let (keys, memory) = self.tenants.usage(&name).unwrap_or_default();
+                    let stats = self.tenants.stats(&name).unwrap_or_default();
+                    info.push_str(&format!(
+                        "\ntenant_{}:keys={},used_memory={},total_ops={},rejected_ops={}",
+                        name, keys, memory, stats.total_ops, stats.rejected_ops
+                    ));
+                }
+                Ok(info)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Estimated memory of every key, kept as a running total as keys are
+    /// written, so it costs nothing to read. Expired keys count until purged.
+    pub fn used_memory(&self) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(map) => Ok(map.used()),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Key count and estimated memory of every live key starting with `prefix`
+    pub fn prefix_usage(&self, prefix: &str) -> Result<(usize, usize), String> {
+        match self.map.lock() {
+            Ok(map) => {
+                Ok(map.iter()
+                    .filter(|(key, value_with_ttl)| key.starts_with(prefix) && !value_with_ttl.is_expired())
+                    .fold((0, 0), |(keys, memory), (key, value_with_ttl)| {
+                        (keys + 1, memory + entry_size(key, &value_with_ttl.value))
+                    }))
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Group live keys by the part before the first `separator` (inclusive)
+    /// and total their key counts and estimated memory, largest first. Keys
+    /// without the separator are grouped under an empty prefix. With
+    /// `samples`, only that many keys are inspected and the totals are
+    /// scaled up to the whole keyspace. Also returns how many keys were inspected.
+    pub fn memory_by_prefix(&self, separator: &str, samples: Option<usize>) -> Result<(Vec<PrefixUsage>, usize), String> {
+        match self.map.lock() {
+            Ok(map) => {
+                let live = map.iter().filter(|(_, value_with_ttl)| !value_with_ttl.is_expired());
+                let total = live.clone().count();
+                let limit = samples.unwrap_or(total).min(total);
+
+                let mut groups: HashMap<&str, (usize, usize)> = HashMap::new();
+                for (key, value_with_ttl) in random_sample(live, limit) {
+                    let prefix = match key.find(separator) {
+                        Some(pos) if !separator.is_empty() => &key[..pos + separator.len()],
+                        _ => "",
+                    };
+                    let group = groups.entry(prefix).or_insert((0, 0));
+                    group.0 += 1;
+                    group.1 += entry_size(key, &value_with_ttl.value);
+                }
+
+                let scale = if limit == 0 { 1.0 } else { total as f64 / limit as f64 };
+                let mut usage: Vec<PrefixUsage> = groups.into_iter()
+                    .map(|(prefix, (keys, memory))| PrefixUsage {
+                        prefix: prefix.to_string(),
+                        keys: (keys as f64 * scale).round() as usize,
+                        memory: (memory as f64 * scale).round() as usize,
+                    })
+                    .collect();
+                usage.sort_by(|a, b| b.memory.cmp(&a.memory).then_with(|| a.prefix.cmp(&b.prefix)));
+                Ok((usage, limit))
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Up to `n` live keys picked at random, optionally only those of one
+    /// type (as `TYPE` names it) or matching a glob pattern. Each call
+    /// starts at a random bucket of a random shard and stops as soon as it
+    /// has `n` keys, so a small sample of a large keyspace is cheap; only
+    /// when fewer than `n` keys match is the whole keyspace read.
+    pub fn sample(&self, n: usize, type_name: Option<&str>, pattern: Option<&str>) -> Result<Vec<String>, String> {
+        match self.map.lock() {
+            Ok(map) => {
+                let matching = map.sample(n, |key, value_with_ttl| {
+                    !value_with_ttl.is_expired()
+                        && type_name.is_none_or(|type_name| value_with_ttl.value.type_name() == type_name)
+                        && pattern.is_none_or(|pattern| pattern_matches(pattern, key))
+                });
+                Ok(matching.into_iter().map(|(key, _)| key.clone()).collect())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Sample up to `samples` live keys (all of them with `None`) and count
+    /// them by TTL, value size and type.
+    pub fn keyspace_distribution(&self, samples: Option<usize>) -> Result<KeyspaceDistribution, String> {
+        match self.map.lock() {
+            Ok(map) => {
+                let now = Instant::now();
+                let live = map.values().filter(|value_with_ttl| !value_with_ttl.is_expired());
+                let mut distribution = KeyspaceDistribution {
+                    total: live.clone().count(),
+                    ttl_buckets: vec![0; TTL_BUCKETS.len() + 1],
+                    size_buckets: vec![0; SIZE_BUCKETS.len() + 1],
+                    ..Default::default()
+                };
+
+                for value_with_ttl in random_sample(live, samples.unwrap_or(usize::MAX)) {
+                    distribution.sampled += 1;
+                    match value_with_ttl.expires_at {
+                        Some(expires) => {
+                            distribution.volatile += 1;
+                            let remaining = expires.saturating_duration_since(now).as_secs();
+                            let bucket = bucket_index(remaining, TTL_BUCKETS.iter().map(|(bound, _)| *bound), false);
+                            distribution.ttl_buckets[bucket] += 1;
+                        }
+                        None => distribution.persistent += 1,
+                    }
+
+                    let size = value_with_ttl.value.estimated_size();
+                    distribution.size_buckets[bucket_index(size, SIZE_BUCKETS.iter().map(|(bound, _)| *bound), true)] += 1;
+
+                    let type_name = value_with_ttl.value.type_name();
+                    match distribution.types.iter_mut().find(|(name, _)| *name == type_name) {
+                        Some((_, count)) => *count += 1,
+                        None => distribution.types.push((type_name, 1)),
+                    }
+                }
+                distribution.types.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+                Ok(distribution)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Snapshot support: every live entry with its remaining time to live
+    pub fn export_entries(&self) -> Result<Vec<(String, Value, Option<Duration>)>, String> {
+        match self.map.lock() {
+            Ok(map) => {
+                let now = Instant::now();
+                Ok(map.iter()
+                    .filter(|(_, value_with_ttl)| !value_with_ttl.is_expired())
+                    .map(|(key, value_with_ttl)| {
+                        let remaining = value_with_ttl.expires_at.map(|expires| expires.saturating_duration_since(now));
+                        (key.clone(), value_with_ttl.value.clone(), remaining)
+                    })
+                    .collect())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // A copy of a live entry with its remaining time to live, for browsing
+    pub fn inspect(&self, key: &str) -> Result<Option<(Value, Option<Duration>)>, String> {
+        match self.map.lock() {
+            Ok(map) => Ok(map.get(key)
+                .filter(|value_with_ttl| !value_with_ttl.is_expired())
+                .map(|value_with_ttl| {
+                    let remaining = value_with_ttl.expires_at
+                        .map(|expires| expires.saturating_duration_since(Instant::now()));
+                    (value_with_ttl.value.clone(), remaining)
+                })),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Record an access to each of `keys` that is still live.
+    pub fn touch(&self, keys: &[&str]) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let now = Instant::now();
+                let mut touched = 0;
+                for key in keys {
+                    if let Some(value_with_ttl) = map.get_mut(key).filter(|entry| !entry.is_expired()) {
+                        value_with_ttl.last_access = now;
+                        touched += 1;
+                    }
+                }
+                Ok(touched)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Time since a command last touched `key`.
+    pub fn idle_time(&self, key: &str) -> Result<Option<Duration>, String> {
+        match self.map.lock() {
+            Ok(map) => Ok(map.get(key)
+                .filter(|value_with_ttl| !value_with_ttl.is_expired())
+                .map(|value_with_ttl| value_with_ttl.last_access.elapsed())),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn import_entry(&self, key: &str, value: Value, ttl: Option<Duration>) -> Result<(), String> {
+        self.import_entry_until(key, value, ttl.map(|ttl| Instant::now() + ttl))
+    }
+
+    /// Like `import_entry`, with the expiry as a point in time.
+    pub fn import_entry_until(&self, key: &str, value: Value, expires_at: Option<Instant>) -> Result<(), String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(old) = map.remove(key) {
+                    self.unindex_value(key, &old.value);
+                }
+                if let Value::Hash(hash) = &value {
+                    for (field, field_value) in hash {
+                        self.index_hash_field(key, field, None, Some(field_value));
+                    }
+                }
+                self.reindex_search(key, Some(&value));
+
+                let mut entry = ValueWithTtl::new(value);
+                entry.expires_at = expires_at;
+                map.insert(key.to_string(), entry);
+                self.mark_changed(&mut map, key);
+                Ok(())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Incremental backup support: start a new chain of change tracking,
+    // called right before a full backup is taken. Returns the chain id.
+    pub fn start_change_tracking(&self) -> Result<u64, String> {
+        match self.changes.lock() {
+            Ok(mut changes) => {
+                let chain_id = crate::timeseries::now_millis();
+                *changes = Some(ChangeLog {
+                    chain_id,
+                    sequence: 0,
+                    keys: HashSet::new(),
+                });
+                Ok(chain_id)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Current state of every key changed since the previous backup in the
+    /// chain, along with the chain id and this incremental's sequence number.
+    pub fn take_changes(&self) -> Result<(u64, u64, Vec<Change>), String> {
+        match self.map.lock() {
+            Ok(map) => {
+                let mut changes = self.changes.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+                let log = match changes.as_mut() {
+                    Some(log) => log,
+                    None => return Err("No full backup to build on".to_string()),
+                };
+                log.sequence += 1;
+                Ok((log.chain_id, log.sequence, key_states(&map, log.keys.drain())))
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Current state of each of `keys`, read in one go, as replication
+    /// streams them.
+    pub fn key_states(&self, keys: Vec<String>) -> Result<Vec<Change>, String> {
+        match self.map.lock() {
+            Ok(map) => Ok(key_states(&map, keys.into_iter())),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// `key_states` with each key's multi-primary stamp, read under the
+    /// same lock so a stamp always matches the state sent with it.
+    pub fn stamped_states(&self, keys: Vec<String>) -> Result<Vec<(Change, Stamp)>, String> {
+        match self.map.lock() {
+            Ok(map) => Ok(key_states(&map, keys.into_iter()).into_iter().map(|change| {
+                let stamp = self.crdt.stamp(&change.0);
+                (change, stamp)
+            }).collect()),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Apply a multi-primary peer's version of a key if its `stamp` beats
+    /// the one held here. Returns whether it did.
+    pub fn merge_change(&self, (key, state): Change, stamp: Stamp) -> Result<bool, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let exists = map.get(&key).is_some_and(|value_with_ttl| !value_with_ttl.is_expired());
+                let merged = self.crdt.merge(&key, stamp, exists, || {
+                    if let Some(old) = map.remove(&key) {
+                        self.unindex_value(&key, &old.value);
+                    }
+                    match state {
+                        Some((value, ttl)) => {
+                            if let Value::Hash(hash) = &value {
+                                for (field, field_value) in hash {
+                                    self.index_hash_field(&key, field, None, Some(field_value));
+                                }
+                            }
+                            self.reindex_search(&key, Some(&value));
+                            let mut entry = ValueWithTtl::new(value);
+                            entry.expires_at = ttl.map(|ttl| Instant::now() + ttl);
+                            map.insert(key.clone(), entry);
+                        }
+                        None => self.reindex_search(&key, None),
+                    }
+                    self.mark_changed(&mut map, &key);
+                });
+                Ok(merged.is_some())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Must be called with the locked map, after any write to `key`
+    fn mark_changed(&self, map: &mut ShardedMap<ValueWithTtl>, key: &str) {
+        map.reweigh(key);
+        self.change_count.fetch_add(1, Ordering::Relaxed);
+        self.history.record(key);
+        self.replication.record(key);
+        self.crdt.record(key);
+        if let Ok(mut changes) = self.changes.lock() {
+            if let Some(log) = changes.as_mut() {
+                log.keys.insert(key.to_string());
+            }
+        }
+        if let Ok(mut waiting) = self.waiters.keys.lock() {
+            if let Some((_, changes)) = waiting.get_mut(key) {
+                *changes += 1;
+                self.waiters.changed.notify_all();
+            }
+        }
+    }
+
+    /// Block until `key` exists (returning at once if it already does) or
+    /// is next written to, or `timeout` passes (`None` waits forever).
+    /// Returns false on timeout.
+    pub fn wait_for_key(&self, key: &str, condition: WaitCondition, timeout: Option<Duration>) -> Result<bool, String> {
+        self.wait_for_key_until(key, condition, timeout, &|| false)
+    }
+
+    /// `wait_for_key` that also gives up, returning false, once
+    /// `interrupted` says so. It is checked whenever the waiters are woken,
+    /// see `wake_waiters`.
+    pub fn wait_for_key_until(
+        &self,
+        key: &str,
+        condition: WaitCondition,
+        timeout: Option<Duration>,
+        interrupted: &dyn Fn() -> bool,
+    ) -> Result<bool, String> {
+        let mut woken = false;
+        self.wait_for_keys_until(&[key], timeout, interrupted, &mut || match condition {
+            WaitCondition::Exists => self.exists(key),
+            // Not ready on the first check, only once a write wakes it
+            WaitCondition::Changed => Ok(std::mem::replace(&mut woken, true)),
+        })
+    }
+
+    /// Block until `ready` returns true, calling it once up front and again
+    /// after every write to one of `keys`. Returns false if `timeout`
+    /// passes (`None` waits forever) or `interrupted` says so first.
+    pub fn wait_for_keys_until(
+        &self,
+        keys: &[&str],
+        timeout: Option<Duration>,
+        interrupted: &dyn Fn() -> bool,
+        ready: &mut dyn FnMut() -> Result<bool, String>,
+    ) -> Result<bool, String> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let lock_error = || "Failed to acquire lock".to_string();
+        let unregister = |waiting: &mut HashMap<String, (usize, u64)>| keys.iter().for_each(|key| unregister_waiter(waiting, key));
+
+        // Register before the first check so a write in between is not missed
+        let mut seen: Vec<u64> = {
+            let mut waiting = self.waiters.keys.lock().map_err(|_| lock_error())?;
+            keys.iter()
+                .map(|key| {
+                    let entry = waiting.entry(key.to_string()).or_insert((0, 0));
+                    entry.0 += 1;
+                    entry.1
+                })
+                .collect()
+        };
+
+        let result = loop {
+            match ready() {
+                Ok(true) => break Ok(true),
+                Ok(false) => {}
+                Err(e) => break Err(e),
+            }
+
+            let mut waiting = self.waiters.keys.lock().map_err(|_| lock_error())?;
+            loop {
+                let changes: Vec<u64> =
+                    keys.iter().zip(&seen).map(|(key, seen)| waiting.get(*key).map(|(_, changes)| *changes).unwrap_or(*seen)).collect();
+                if changes != seen {
+                    seen = changes;
+                    break;
+                }
+                if interrupted() {
+                    unregister(&mut waiting);
+                    return Ok(false);
+                }
+                let remaining = match deadline {
+                    Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                        Some(remaining) if !remaining.is_zero() => remaining,
+                        _ => {
+                            unregister(&mut waiting);
+                            return Ok(false);
+                        }
+                    },
+                    None => Duration::from_secs(60),
+                };
+                waiting = self.waiters.changed.wait_timeout(waiting, remaining).map_err(|_| lock_error())?.0;
+            }
+        };
+
+        if let Ok(mut waiting) = self.waiters.keys.lock() {
+            unregister(&mut waiting);
+        }
+        result
+    }
+
+    /// Wake every blocked `wait_for_key_until` to re-check its interrupt.
+    pub fn wake_waiters(&self) {
+        // Holding the lock orders this after a waiter's check, so the
+        // notification cannot slip in before it starts waiting
+        if let Ok(_waiting) = self.waiters.keys.lock() {
+            self.waiters.changed.notify_all();
+        }
+    }
+
+    // Hash operations
+    pub fn hset(&self, key: &str, field: &str, value: &str) -> Result<bool, String> {
+        self.hset_bytes(key, field, value.as_bytes())
+    }
+
+    /// Like `hset`, with a value that need not be UTF-8.
+    pub fn hset_bytes(&self, key: &str, field: &str, value: &[u8]) -> Result<bool, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_hash()));
+                
+                let is_new = match &mut entry.value {
+                    Value::Hash(ref mut hash) => {
+                        let old = hash.insert(field.to_string(), value.to_vec());
+                        self.index_hash_field(key, field, old.as_deref(), Some(value));
+                        old.is_none()
+                    }
+                    _ => {
+                        // Convert to hash if not already
+                        self.index_hash_field(key, field, None, Some(value));
+                        let mut hash = HashMap::new();
+                        hash.insert(field.to_string(), value.to_vec());
+                        entry.value = Value::Hash(hash);
+                        true
+                    }
+                };
+                self.reindex_search(key, Some(&entry.value));
+                self.mark_changed(&mut map, key);
+                Ok(is_new)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn hget(&self, key: &str, field: &str) -> Result<Option<String>, String> {
+        self.hget_bytes(key, field)?.map(|value| hash_text(field, value)).transpose()
+    }
+
+    /// Like `hget`, for values that need not be UTF-8.
+    pub fn hget_bytes(&self, key: &str, field: &str) -> Result<Option<Vec<u8>>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(None)
+                    } else {
+                        match &value_with_ttl.value {
+                            Value::Hash(hash) => Ok(hash.get(field).cloned()),
+                            _ => Err("Key contains non-hash value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(None)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn hgetall(&self, key: &str) -> Result<HashMap<String, String>, String> {
+        hash_texts(self.hgetall_bytes(key)?)
+    }
+
+    /// Like `hgetall`, for hashes whose values need not be UTF-8.
+    pub fn hgetall_bytes(&self, key: &str) -> Result<HashMap<String, Vec<u8>>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(HashMap::new())
+                    } else {
+                        match &value_with_ttl.value {
+                            Value::Hash(hash) => Ok(hash.clone()),
+                            _ => Err("Key contains non-hash value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(HashMap::new())
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn hdel(&self, key: &str, field: &str) -> Result<bool, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get_mut(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(false)
+                    } else {
+                        let removed = match &mut value_with_ttl.value {
+                            Value::Hash(ref mut hash) => {
+                                let old = hash.remove(field);
+                                self.index_hash_field(key, field, old.as_deref(), None);
+                                old.is_some()
+                            }
+                            _ => return Err("Key contains non-hash value".to_string()),
+                        };
+                        self.reindex_search(key, Some(&value_with_ttl.value));
+                        self.mark_changed(&mut map, key);
+                        Ok(removed)
+                    }
+                } else {
+                    Ok(false)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn hexists(&self, key: &str, field: &str) -> Result<bool, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(false)
+                    } else {
+                        match &value_with_ttl.value {
+                            Value::Hash(hash) => Ok(hash.contains_key(field)),
+                            _ => Err("Key contains non-hash value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(false)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn hlen(&self, key: &str) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(0)
+                    } else {
+                        match &value_with_ttl.value {
+                            Value::Hash(hash) => Ok(hash.len()),
+                            _ => Err("Key contains non-hash value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(0)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // List operations
+    pub fn lpush(&self, key: &str, value: &str) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_list()));
+                
+                let len = match &mut entry.value {
+                    Value::List(ref mut list) => {
+                        list.push_front(value.to_string());
+                        list.len()
+                    }
+                    _ => {
+                        // Convert to list if not already
+                        self.unindex_value(key, &entry.value);
+                        self.reindex_search(key, None);
+                        let mut list = VecDeque::new();
+                        list.push_front(value.to_string());
+                        entry.value = Value::List(list);
+                        1
+                    }
+                };
+                self.mark_changed(&mut map, key);
+                Ok(len)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn rpush(&self, key: &str, value: &str) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_list()));
+                
+                let len = match &mut entry.value {
+                    Value::List(ref mut list) => {
+                        list.push_back(value.to_string());
+                        list.len()
+                    }
+                    _ => {
+                        // Convert to list if not already
+                        self.unindex_value(key, &entry.value);
+                        self.reindex_search(key, None);
+                        let mut list = VecDeque::new();
+                        list.push_back(value.to_string());
+                        entry.value = Value::List(list);
+                        1
+                    }
+                };
+                self.mark_changed(&mut map, key);
+                Ok(len)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn lpop(&self, key: &str) -> Result<Option<String>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get_mut(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(None)
+                    } else {
+                        match &mut value_with_ttl.value {
+                            Value::List(ref mut list) => {
+                                let popped = list.pop_front();
+                                self.mark_changed(&mut map, key);
+                                Ok(popped)
+                            }
+                            _ => Err("Key contains non-list value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(None)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn rpop(&self, key: &str) -> Result<Option<String>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get_mut(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(None)
+                    } else {
+                        match &mut value_with_ttl.value {
+                            Value::List(ref mut list) => {
+                                let popped = list.pop_back();
+                                self.mark_changed(&mut map, key);
+                                Ok(popped)
+                            }
+                            _ => Err("Key contains non-list value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(None)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn llen(&self, key: &str) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(0)
+                    } else {
+                        match &value_with_ttl.value {
+                            Value::List(list) => Ok(list.len()),
+                            _ => Err("Key contains non-list value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(0)
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<String>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                if let Some(value_with_ttl) = map.get(key) {
+                    if value_with_ttl.is_expired() {
+                        map.remove(key);
+                        Ok(Vec::new())
+                    } else {
+                        match &value_with_ttl.value {
+                            Value::List(list) => Ok(list_range(list, start, stop)),
+                            _ => Err("Key contains non-list value".to_string()),
+                        }
+                    }
+                } else {
+                    Ok(Vec::new())
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Set operations
+    /// Add `members` to the set at `key`, creating it if missing. Returns
+    /// how many were not members already.
+    pub fn sadd(&self, key: &str, members: &[&str]) -> Result<usize, String> {
+        if members.is_empty() {
+            return Ok(0);
+        }
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_set()));
+                if entry.is_expired() {
+                    self.unindex_value(key, &entry.value);
+                    self.reindex_search(key, None);
+                    *entry = ValueWithTtl::new(Value::new_set());
+                }
+                let Value::Set(set) = &mut entry.value else {
+                    return Err("Key contains non-set value".to_string());
+                };
+                let added = members.iter().filter(|member| set.insert(member.to_string())).count();
+                if added > 0 {
+                    self.mark_changed(&mut map, key);
+                }
+                Ok(added)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Remove `members` from the set at `key`, deleting the key once the
+    /// set is empty. Returns how many were members.
+    pub fn srem(&self, key: &str, members: &[&str]) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let Some(entry) = map.get_mut(key).filter(|entry| !entry.is_expired()) else {
+                    return Ok(0);
+                };
+                let Value::Set(set) = &mut entry.value else {
+                    return Err("Key contains non-set value".to_string());
+                };
+                let removed = members.iter().filter(|member| set.remove(**member)).count();
+                if set.is_empty() {
+                    map.remove(key);
+                }
+                if removed > 0 {
+                    self.mark_changed(&mut map, key);
+                }
+                Ok(removed)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Members of the set at `key`, sorted.
+    pub fn smembers(&self, key: &str) -> Result<Vec<String>, String> {
+        let members = self.read_set(key, |set| {
+            let mut members: Vec<String> = set.iter().cloned().collect();
+            members.sort();
+            members
+        })?;
+        Ok(members.unwrap_or_default())
+    }
+
+    pub fn scard(&self, key: &str) -> Result<usize, String> {
+        Ok(self.read_set(key, HashSet::len)?.unwrap_or(0))
+    }
+
+    pub fn sismember(&self, key: &str, member: &str) -> Result<bool, String> {
+        Ok(self.read_set(key, |set| set.contains(member))?.unwrap_or(false))
+    }
+
+    // Run `read` on the set at `key`; `None` if it is missing or expired
+    fn read_set<R>(&self, key: &str, read: impl FnOnce(&HashSet<String>) -> R) -> Result<Option<R>, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key).filter(|entry| !entry.is_expired()).map(|entry| &entry.value) {
+                Some(Value::Set(set)) => Ok(Some(read(set))),
+                Some(_) => Err("Key contains non-set value".to_string()),
+                None => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Sorted set operations
+    /// Set the score of each `(score, member)` in the sorted set at `key`,
+    /// creating it if missing. Returns how many members were new.
+    pub fn zadd(&self, key: &str, entries: &[(f64, &str)]) -> Result<usize, String> {
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_sorted_set()));
+                if entry.is_expired() {
+                    self.unindex_value(key, &entry.value);
+                    self.reindex_search(key, None);
+                    *entry = ValueWithTtl::new(Value::new_sorted_set());
+                }
+                let Value::SortedSet(set) = &mut entry.value else {
+                    return Err("Key contains non-sorted-set value".to_string());
+                };
+                let added = entries.iter().filter(|(score, member)| set.insert(member, *score)).count();
+                self.mark_changed(&mut map, key);
+                Ok(added)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn zscore(&self, key: &str, member: &str) -> Result<Option<f64>, String> {
+        Ok(self.read_sorted_set(key, |set| set.score(member))?.flatten())
+    }
+
+    pub fn zrank(&self, key: &str, member: &str) -> Result<Option<usize>, String> {
+        Ok(self.read_sorted_set(key, |set| set.rank(member))?.flatten())
+    }
+
+    /// Members from rank `start` to `stop` with their scores; negative
+    /// ranks count from the end.
+    pub fn zrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<(String, f64)>, String> {
+        Ok(self.read_sorted_set(key, |set| set.range(start, stop))?.unwrap_or_default())
+    }
+
+    /// Members scored between `min` and `max` with their scores, after
+    /// skipping `offset` of them and keeping at most `count`.
+    pub fn zrangebyscore(&self, key: &str, min: ScoreBound, max: ScoreBound, offset: usize, count: Option<usize>) -> Result<Vec<(String, f64)>, String> {
+        let members = self.read_sorted_set(key, |set| {
+            set.range_by_score(min, max)
+                .skip(offset)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(member, score)| (member.to_string(), score))
+                .collect()
+        })?;
+        Ok(members.unwrap_or_default())
+    }
+
+    /// Remove the members scored between `min` and `max`, returning how
+    /// many there were.
+    pub fn zremrangebyscore(&self, key: &str, min: ScoreBound, max: ScoreBound) -> Result<usize, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let Some(entry) = map.get_mut(key).filter(|entry| !entry.is_expired()) else {
+                    return Ok(0);
+                };
+                let Value::SortedSet(set) = &mut entry.value else {
+                    return Err("Key contains non-sorted-set value".to_string());
+                };
+                let removed = set.remove_range_by_score(min, max);
+                if set.is_empty() {
+                    map.remove(key);
+                }
+                if removed > 0 {
+                    self.mark_changed(&mut map, key);
+                }
+                Ok(removed)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Add `increment` to `member`'s score, starting from 0 if it is not in
+    /// the sorted set at `key`. Returns the new score.
+    pub fn zincrby(&self, key: &str, increment: f64, member: &str) -> Result<f64, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_sorted_set()));
+                if entry.is_expired() {
+                    self.unindex_value(key, &entry.value);
+                    self.reindex_search(key, None);
+                    *entry = ValueWithTtl::new(Value::new_sorted_set());
+                }
+                let Value::SortedSet(set) = &mut entry.value else {
+                    return Err("Key contains non-sorted-set value".to_string());
+                };
+                // Only an existing member can get here, as inf plus -inf
+                let score = set.score(member).unwrap_or(0.0) + increment;
+                if score.is_nan() {
+                    return Err("Resulting score is not a number".to_string());
+                }
+                set.insert(member, score);
+                self.mark_changed(&mut map, key);
+                Ok(score)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// Remove and return up to `count` members with the lowest scores,
+    /// lowest first.
+    pub fn zpopmin(&self, key: &str, count: usize) -> Result<Vec<(String, f64)>, String> {
+        self.zpop(key, count, SortedSet::pop_min)
+    }
+
+    /// Remove and return up to `count` members with the highest scores,
+    /// highest first.
+    pub fn zpopmax(&self, key: &str, count: usize) -> Result<Vec<(String, f64)>, String> {
+        self.zpop(key, count, SortedSet::pop_max)
+    }
+
+    fn zpop(&self, key: &str, count: usize, pop: fn(&mut SortedSet) -> Option<(String, f64)>) -> Result<Vec<(String, f64)>, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let Some(entry) = map.get_mut(key).filter(|entry| !entry.is_expired()) else {
+                    return Ok(Vec::new());
+                };
+                let Value::SortedSet(set) = &mut entry.value else {
+                    return Err("Key contains non-sorted-set value".to_string());
+                };
+                let popped: Vec<(String, f64)> = std::iter::from_fn(|| pop(set)).take(count).collect();
+                if set.is_empty() {
+                    map.remove(key);
+                }
+                if !popped.is_empty() {
+                    self.mark_changed(&mut map, key);
+                }
+                Ok(popped)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn zcard(&self, key: &str) -> Result<usize, String> {
+        Ok(self.read_sorted_set(key, SortedSet::len)?.unwrap_or(0))
+    }
+
+    // Run `read` on the sorted set at `key`; `None` if it is missing or expired
+    fn read_sorted_set<R>(&self, key: &str, read: impl FnOnce(&SortedSet) -> R) -> Result<Option<R>, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key).filter(|entry| !entry.is_expired()).map(|entry| &entry.value) {
+                Some(Value::SortedSet(set)) => Ok(Some(read(set))),
+                Some(_) => Err("Key contains non-sorted-set value".to_string()),
+                None => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Stream operations
+    /// Append an entry to the stream at `key`, creating it if missing, then
+    /// apply `trim`. Without an `id` one is generated from the clock.
+    /// Returns the ID.
+    pub fn xadd(&self, key: &str, id: Option<StreamId>, fields: &[(&str, &str)], trim: Option<Trim>) -> Result<StreamId, String> {
+        match self.map.lock() {
+            Ok(mut map) => {
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_stream()));
+                if entry.is_expired() {
+                    self.unindex_value(key, &entry.value);
+                    self.reindex_search(key, None);
+                    *entry = ValueWithTtl::new(Value::new_stream());
+                }
+                let Value::Stream(stream) = &mut entry.value else {
+                    return Err("Key contains non-stream value".to_string());
+                };
+                let fields: Fields = fields.iter().map(|(field, value)| (field.to_string(), value.to_string())).collect();
+                match stream.add(id, fields, now_millis()) {
+                    Ok(id) => {
+                        if let Some(trim) = trim {
+                            stream.trim(trim);
+                        }
+                        self.mark_changed(&mut map, key);
+                        Ok(id)
+                    }
+                    Err(e) => {
+                        // Do not leave behind a stream created for a refused ID
+                        if stream.is_empty() && stream.last_id() == StreamId::MIN && stream.groups().next().is_none() {
+                            map.remove(key);
+                        }
+                        Err(e)
+                    }
+                }
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn xlen(&self, key: &str) -> Result<usize, String> {
+        Ok(self.read_stream(key, Stream::len)?.unwrap_or(0))
+    }
+
+    /// Entries from `start` to `end` inclusive, oldest first, at most `count`.
+    pub fn xrange(&self, key: &str, start: StreamId, end: StreamId, count: Option<usize>) -> Result<Vec<Entry>, String> {
+        let entries = self.read_stream(key, |stream| {
+            stream.range(start, end).take(count.unwrap_or(usize::MAX)).map(|(id, fields)| (id, fields.clone())).collect()
+        })?;
+        Ok(entries.unwrap_or_default())
+    }
+
+    /// For each `(key, id)`, up to `count` entries newer than `id`. Streams
+    /// with none are left out.
+    pub fn xread(&self, streams: &[(&str, StreamId)], count: Option<usize>) -> Result<Vec<(String, Vec<Entry>)>, String> {
+        let mut read = Vec::new();
+        for (key, id) in streams {
+            let entries: Option<Vec<Entry>> = self.read_stream(key, |stream| {
+                stream.after(*id).take(count.unwrap_or(usize::MAX)).map(|(id, fields)| (id, fields.clone())).collect()
+            })?;
+            if let Some(entries) = entries.filter(|entries| !entries.is_empty()) {
+                read.push((key.to_string(), entries));
+            }
+        }
+        Ok(read)
+    }
+
+    /// Remove old entries from the stream at `key`, returning how many.
+    pub fn xtrim(&self, key: &str, trim: Trim) -> Result<usize, String> {
+        let removed = self.update_stream(key, |stream| {
+            let removed = stream.trim(trim);
+            Ok((removed, removed > 0))
+        })?;
+        Ok(removed.unwrap_or(0))
+    }
+
+    /// Add consumer group `group` to the stream at `key`, delivering
+    /// entries after `start` (`None` for entries added from now on).
+    /// `mkstream` creates the stream if it is missing. Returns false if the
+    /// group already exists.
+    pub fn xgroup_create(&self, key: &str, group: &str, start: Option<StreamId>, mkstream: bool) -> Result<bool, String> {
+        if mkstream {
+            match self.map.lock() {
+                Ok(mut map) => {
+                    let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_stream()));
+                    if entry.is_expired() {
+                        self.unindex_value(key, &entry.value);
+                        self.reindex_search(key, None);
+                        *entry = ValueWithTtl::new(Value::new_stream());
+                        map.reweigh(key);
                     }
-                } else {
-                    Ok(false)
                 }
+                Err(_) => return Err("Failed to acquire lock".to_string()),
             }
-            Err(_) => Err("Failed to acquire lock".to_string()),
         }
+        let created = self.update_stream(key, |stream| {
+            let created = stream.create_group(group, start.unwrap_or(stream.last_id()));
+            Ok((created, created))
+        })?;
+        created.ok_or_else(|| format!("Stream '{}' does not exist (MKSTREAM creates it)", key))
     }
 
-    pub fn keys_pattern(&self, pattern: &str) -> Result<Vec<String>, String> {
-        let keys = self.list_keys()?;
-        if pattern == "*" {
-            return Ok(keys);
-        }
-        
-        Ok(keys.into_iter().filter(|key| {
-            if pattern.contains('*') {
-                let prefix = pattern.split('*').next().unwrap_or("");
-                key.starts_with(prefix)
-            } else {
-                key == pattern
-            }
-        }).collect())
+    /// Remove consumer group `group`, returning whether it existed.
+    pub fn xgroup_destroy(&self, key: &str, group: &str) -> Result<bool, String> {
+        let destroyed = self.update_stream(key, |stream| {
+            let destroyed = stream.destroy_group(group);
+            Ok((destroyed, destroyed))
+        })?;
+        Ok(destroyed.unwrap_or(false))
     }
 
-    pub fn clear(&self) -> Result<(), String> {
-        match self.map.lock() {
-            Ok(mut map) => {
-                map.clear();
-                Ok(())
+    /// For each `(key, after)`, up to `count` entries read by `consumer` in
+    /// `group`, see `Stream::read_group`. Streams with none are left out.
+    pub fn xreadgroup(&self, group: &str, consumer: &str, streams: &[(&str, Option<StreamId>)], count: Option<usize>) -> Result<Vec<(String, Vec<Entry>)>, String> {
+        let mut read = Vec::new();
+        for (key, after) in streams {
+            let entries = self.update_stream(key, |stream| {
+                let entries = stream.read_group(group, consumer, *after, count.unwrap_or(usize::MAX), now_millis())?;
+                // Only new deliveries change the group
+                let delivered = after.is_none() && !entries.is_empty();
+                Ok((entries, delivered))
+            })?;
+            match entries {
+                Some(entries) if !entries.is_empty() => read.push((key.to_string(), entries)),
+                Some(_) => {}
+                None => return Err(format!("No consumer group '{}' for stream '{}'", group, key)),
             }
-            Err(_) => Err("Failed to acquire lock".to_string()),
         }
+        Ok(read)
     }
 
-    pub fn flush_all(&self) -> Result<(), String> {
-        self.clear()
+    /// Acknowledge entries in `group`, returning how many were pending.
+    pub fn xack(&self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize, String> {
+        let acked = self.update_stream(key, |stream| {
+            let acked = stream.ack(group, ids)?;
+            Ok((acked, acked > 0))
+        })?;
+        Ok(acked.unwrap_or(0))
     }
 
-    // Alias for keys_pattern for compatibility
-    pub fn keys(&self, pattern: &str) -> Result<Vec<String>, String> {
-        self.keys_pattern(pattern)
+    /// A copy of consumer group `group` of the stream at `key`, for its
+    /// pending entries.
+    pub fn xgroup(&self, key: &str, group: &str) -> Result<ConsumerGroup, String> {
+        let state = self.read_stream(key, |stream| stream.group(group).cloned())?.flatten();
+        state.ok_or_else(|| format!("No consumer group '{}' for stream '{}'", group, key))
     }
 
-    // Server info method
-    pub fn info(&self) -> Result<String, String> {
+    /// Claim stalled pending entries for `consumer`, see `Stream::autoclaim`.
+    pub fn xautoclaim(&self, key: &str, group: &str, consumer: &str, min_idle_ms: u64, start: StreamId, count: usize) -> Result<AutoClaim, String> {
+        let claim = self.update_stream(key, |stream| {
+            let claim = stream.autoclaim(group, consumer, min_idle_ms, start, count, now_millis())?;
+            let changed = !claim.claimed.is_empty() || !claim.deleted.is_empty();
+            Ok((claim, changed))
+        })?;
+        claim.ok_or_else(|| format!("No consumer group '{}' for stream '{}'", group, key))
+    }
+
+    /// The highest ID added to the stream at `key`, 0-0 if it is missing.
+    pub fn xlast_id(&self, key: &str) -> Result<StreamId, String> {
+        Ok(self.read_stream(key, Stream::last_id)?.unwrap_or(StreamId::MIN))
+    }
+
+    // Run `update` on the stream at `key`, which also says whether it
+    // changed anything; `None` if the stream is missing or expired
+    fn update_stream<R>(&self, key: &str, update: impl FnOnce(&mut Stream) -> Result<(R, bool), String>) -> Result<Option<R>, String> {
+        match self.map.lock() {
+            Ok(mut map) => match map.get_mut(key).filter(|entry| !entry.is_expired()).map(|entry| &mut entry.value) {
+                Some(Value::Stream(stream)) => {
+                    let (result, changed) = update(stream)?;
+                    if changed {
+                        self.mark_changed(&mut map, key);
+                    }
+                    Ok(Some(result))
+                }
+                Some(_) => Err("Key contains non-stream value".to_string()),
+                None => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Run `read` on the stream at `key`; `None` if it is missing or expired
+    fn read_stream<R>(&self, key: &str, read: impl FnOnce(&Stream) -> R) -> Result<Option<R>, String> {
+        match self.map.lock() {
+            Ok(map) => match map.get(key).filter(|entry| !entry.is_expired()).map(|entry| &entry.value) {
+                Some(Value::Stream(stream)) => Ok(Some(read(stream))),
+                Some(_) => Err("Key contains non-stream value".to_string()),
+                None => Ok(None),
+            },
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    // Time series operations
+    pub fn ts_create(&self, key: &str, retention_ms: Option<u64>) -> Result<bool, String> {
         match self.map.lock() {
             Ok(mut map) => {
-                map.retain(|_, value_with_ttl| !value_with_ttl.is_expired());
-                let count = map.len();
-                let info = format!(
-                    "# Server\nmedusa_version:0.1.0\nuptime_in_seconds:unknown\n\n# Memory\nused_memory:{}\ntotal_keys:{}\n\n# Stats\ntotal_connections_received:unknown\ntotal_commands_processed:unknown",
-                    count * 64, // rough estimate
-                    count
-                );
-                Ok(info)
+                if map.get(key).is_some_and(|value_with_ttl| !value_with_ttl.is_expired()) {
+                    return Ok(false);
+                }
+                map.insert(key.to_string(), ValueWithTtl::new(Value::new_timeseries(retention_ms)));
+                self.mark_changed(&mut map, key);
+                Ok(true)
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    // Hash operations
-    pub fn hset(&self, key: &str, field: &str, value: &str) -> Result<bool, String> {
+    pub fn ts_add(&self, key: &str, timestamp: u64, value: f64) -> Result<u64, String> {
         match self.map.lock() {
             Ok(mut map) => {
-                let entry = map.entry(key.to_string()).or_insert_with(|| ValueWithTtl::new(Value::new_hash()));
-                
+                if map.get(key).is_some_and(|value_with_ttl| value_with_ttl.is_expired()) {
+                    map.remove(key);
+                }
+                let entry = map.get_or_insert_with(key, || ValueWithTtl::new(Value::new_timeseries(None)));
+
                 match &mut entry.value {
-                    Value::Hash(ref mut hash) => {
-                        let is_new = !hash.contains_key(field);
-                        hash.insert(field.to_string(), value.to_string());
-                        Ok(is_new)
+                    Value::TimeSeries(ref mut series) => {
+                        series.add(timestamp, value);
                     }
                     _ => {
-                        // Convert to hash if not already
-                        let mut hash = HashMap::new();
-                        hash.insert(field.to_string(), value.to_string());
-                        entry.value = Value::Hash(hash);
-                        Ok(true)
+                        // Convert to time series if not already
+                        self.unindex_value(key, &entry.value);
+                        self.reindex_search(key, None);
+                        let mut series = TimeSeries::new(None);
+                        series.add(timestamp, value);
+                        entry.value = Value::TimeSeries(series);
                     }
                 }
+                self.mark_changed(&mut map, key);
+                Ok(timestamp)
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn hget(&self, key: &str, field: &str) -> Result<Option<String>, String> {
+    pub fn ts_get(&self, key: &str) -> Result<Option<(u64, f64)>, String> {
         match self.map.lock() {
             Ok(mut map) => {
                 if let Some(value_with_ttl) = map.get(key) {
@@ -290,8 +2705,8 @@ impl Store {
                         Ok(None)
                     } else {
                         match &value_with_ttl.value {
-                            Value::Hash(hash) => Ok(hash.get(field).cloned()),
-                            _ => Err("Key contains non-hash value".to_string()),
+                            Value::TimeSeries(series) => Ok(series.latest()),
+                            _ => Err("Key contains non-timeseries value".to_string()),
                         }
                     }
                 } else {
@@ -302,250 +2717,341 @@ impl Store {
         }
     }
 
-    pub fn hgetall(&self, key: &str) -> Result<HashMap<String, String>, String> {
+    pub fn ts_range(
+        &self,
+        key: &str,
+        from: u64,
+        to: u64,
+        aggregation: Option<(Aggregation, u64)>,
+    ) -> Result<Vec<(u64, f64)>, String> {
         match self.map.lock() {
             Ok(mut map) => {
                 if let Some(value_with_ttl) = map.get(key) {
                     if value_with_ttl.is_expired() {
                         map.remove(key);
-                        Ok(HashMap::new())
+                        Ok(Vec::new())
                     } else {
                         match &value_with_ttl.value {
-                            Value::Hash(hash) => Ok(hash.clone()),
-                            _ => Err("Key contains non-hash value".to_string()),
+                            Value::TimeSeries(series) => Ok(series.range(from, to, aggregation)),
+                            _ => Err("Key contains non-timeseries value".to_string()),
                         }
                     }
                 } else {
-                    Ok(HashMap::new())
+                    Ok(Vec::new())
                 }
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn hdel(&self, key: &str, field: &str) -> Result<bool, String> {
+    // Vector operations
+    pub fn vadd(&self, key: &str, vector: Vec<f32>) -> Result<(), String> {
+        if vector.is_empty() {
+            return Err("Vector must have at least one dimension".to_string());
+        }
         match self.map.lock() {
             Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get_mut(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(false)
-                    } else {
-                        match &mut value_with_ttl.value {
-                            Value::Hash(ref mut hash) => {
-                                Ok(hash.remove(field).is_some())
-                            }
-                            _ => Err("Key contains non-hash value".to_string()),
-                        }
-                    }
-                } else {
-                    Ok(false)
+                if let Some(old) = map.insert(key.to_string(), ValueWithTtl::new(Value::Vector(vector))) {
+                    self.unindex_value(key, &old.value);
+                    self.reindex_search(key, None);
                 }
+                self.mark_changed(&mut map, key);
+                Ok(())
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn hexists(&self, key: &str, field: &str) -> Result<bool, String> {
+    pub fn vget(&self, key: &str) -> Result<Option<Vec<f32>>, String> {
         match self.map.lock() {
             Ok(mut map) => {
                 if let Some(value_with_ttl) = map.get(key) {
                     if value_with_ttl.is_expired() {
                         map.remove(key);
-                        Ok(false)
+                        Ok(None)
                     } else {
                         match &value_with_ttl.value {
-                            Value::Hash(hash) => Ok(hash.contains_key(field)),
-                            _ => Err("Key contains non-hash value".to_string()),
+                            Value::Vector(vector) => Ok(Some(vector.clone())),
+                            _ => Err("Key contains non-vector value".to_string()),
                         }
                     }
                 } else {
-                    Ok(false)
+                    Ok(None)
                 }
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn hlen(&self, key: &str) -> Result<usize, String> {
+    pub fn vsearch(&self, pattern: &str, query: &[f32], k: usize, metric: Metric) -> Result<Vec<(String, f32)>, String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(0)
-                    } else {
-                        match &value_with_ttl.value {
-                            Value::Hash(hash) => Ok(hash.len()),
-                            _ => Err("Key contains non-hash value".to_string()),
-                        }
+            Ok(map) => {
+                let candidates = map.iter().filter_map(|(key, value_with_ttl)| {
+                    if value_with_ttl.is_expired() || !pattern_matches(pattern, key) {
+                        return None;
                     }
-                } else {
-                    Ok(0)
-                }
+                    match &value_with_ttl.value {
+                        Value::Vector(vector) => Some((key, vector)),
+                        _ => None,
+                    }
+                });
+                Ok(vector::nearest(candidates, query, k, metric))
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    // List operations
-    pub fn lpush(&self, key: &str, value: &str) -> Result<usize, String> {
+    // Secondary index operations
+    pub fn create_index(&self, name: &str, pattern: &str, field: &str) -> Result<bool, String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                let entry = map.entry(key.to_string()).or_insert_with(|| ValueWithTtl::new(Value::new_list()));
-                
-                match &mut entry.value {
-                    Value::List(ref mut list) => {
-                        list.push_front(value.to_string());
-                        Ok(list.len())
+            Ok(map) => {
+                let mut indexes = self.indexes.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+                if indexes.contains_key(name) {
+                    return Ok(false);
+                }
+
+                let mut index = SecondaryIndex::new(pattern, field);
+                for (key, value_with_ttl) in map.iter() {
+                    if value_with_ttl.is_expired() || !index.covers(key) {
+                        continue;
                     }
-                    _ => {
-                        // Convert to list if not already
-                        let mut list = VecDeque::new();
-                        list.push_front(value.to_string());
-                        entry.value = Value::List(list);
-                        Ok(1)
+                    if let Value::Hash(hash) = &value_with_ttl.value {
+                        if let Some(value) = hash.get(field).and_then(|value| std::str::from_utf8(value).ok()) {
+                            index.insert(key, value);
+                        }
                     }
                 }
+                indexes.insert(name.to_string(), index);
+                Ok(true)
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn rpush(&self, key: &str, value: &str) -> Result<usize, String> {
-        match self.map.lock() {
-            Ok(mut map) => {
-                let entry = map.entry(key.to_string()).or_insert_with(|| ValueWithTtl::new(Value::new_list()));
-                
-                match &mut entry.value {
-                    Value::List(ref mut list) => {
-                        list.push_back(value.to_string());
-                        Ok(list.len())
-                    }
-                    _ => {
-                        // Convert to list if not already
-                        let mut list = VecDeque::new();
-                        list.push_back(value.to_string());
-                        entry.value = Value::List(list);
-                        Ok(1)
-                    }
-                }
+    pub fn drop_index(&self, name: &str) -> Result<bool, String> {
+        match self.indexes.lock() {
+            Ok(mut indexes) => Ok(indexes.remove(name).is_some()),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn list_indexes(&self) -> Result<Vec<(String, String, String)>, String> {
+        match self.indexes.lock() {
+            Ok(indexes) => {
+                let mut result: Vec<(String, String, String)> = indexes.iter()
+                    .map(|(name, index)| (name.clone(), index.pattern.clone(), index.field.clone()))
+                    .collect();
+                result.sort();
+                Ok(result)
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn lpop(&self, key: &str) -> Result<Option<String>, String> {
+    pub fn find(&self, name: &str, value: &str) -> Result<Vec<String>, String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get_mut(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(None)
+            Ok(map) => {
+                let mut indexes = self.indexes.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+                let index = match indexes.get_mut(name) {
+                    Some(index) => index,
+                    None => return Err(format!("No such index '{}'", name)),
+                };
+
+                let mut keys = Vec::new();
+                for key in index.lookup(value) {
+                    let still_matches = map.get(&key).is_some_and(|value_with_ttl| {
+                        !value_with_ttl.is_expired()
+                            && matches!(&value_with_ttl.value, Value::Hash(hash) if hash.get(&index.field).map(Vec::as_slice) == Some(value.as_bytes()))
+                    });
+                    if still_matches {
+                        keys.push(key);
                     } else {
-                        match &mut value_with_ttl.value {
-                            Value::List(ref mut list) => Ok(list.pop_front()),
-                            _ => Err("Key contains non-list value".to_string()),
-                        }
+                        index.remove(&key, value);
                     }
-                } else {
-                    Ok(None)
                 }
+                keys.sort();
+                Ok(keys)
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn rpop(&self, key: &str) -> Result<Option<String>, String> {
+    // Must be called while holding the map lock so index updates stay in
+    // step with the data they describe. Binary values are not indexed.
+    fn index_hash_field(&self, key: &str, field: &str, old: Option<&[u8]>, new: Option<&[u8]>) {
+        let old = old.and_then(|old| std::str::from_utf8(old).ok());
+        let new = new.and_then(|new| std::str::from_utf8(new).ok());
+        if let Ok(mut indexes) = self.indexes.lock() {
+            for index in indexes.values_mut() {
+                if index.field != field || !index.covers(key) {
+                    continue;
+                }
+                if let Some(old) = old {
+                    index.remove(key, old);
+                }
+                if let Some(new) = new {
+                    index.insert(key, new);
+                }
+            }
+        }
+    }
+
+    fn unindex_value(&self, key: &str, value: &Value) {
+        if let Value::Hash(hash) = value {
+            for (field, old) in hash {
+                self.index_hash_field(key, field, Some(old), None);
+            }
+        }
+    }
+
+    // Full-text search operations
+    pub fn ft_create(&self, name: &str, pattern: &str, fields: Vec<String>) -> Result<bool, String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get_mut(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(None)
-                    } else {
-                        match &mut value_with_ttl.value {
-                            Value::List(ref mut list) => Ok(list.pop_back()),
-                            _ => Err("Key contains non-list value".to_string()),
-                        }
+            Ok(map) => {
+                let mut search_indexes = self.search_indexes.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+                if search_indexes.contains_key(name) {
+                    return Ok(false);
+                }
+
+                let mut index = SearchIndex::new(pattern, fields);
+                for (key, value_with_ttl) in map.iter() {
+                    if !value_with_ttl.is_expired() && index.covers(key) {
+                        index.index_document(key, Some(&value_with_ttl.value));
                     }
-                } else {
-                    Ok(None)
                 }
+                search_indexes.insert(name.to_string(), index);
+                Ok(true)
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn llen(&self, key: &str) -> Result<usize, String> {
+    pub fn ft_add(&self, name: &str, key: &str) -> Result<bool, String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(0)
-                    } else {
-                        match &value_with_ttl.value {
-                            Value::List(list) => Ok(list.len()),
-                            _ => Err("Key contains non-list value".to_string()),
-                        }
+            Ok(map) => {
+                let mut search_indexes = self.search_indexes.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+                let index = match search_indexes.get_mut(name) {
+                    Some(index) => index,
+                    None => return Err(format!("No such search index '{}'", name)),
+                };
+
+                match map.get(key) {
+                    Some(value_with_ttl) if !value_with_ttl.is_expired() => {
+                        index.add_document(key, &value_with_ttl.value);
+                        Ok(true)
                     }
-                } else {
-                    Ok(0)
+                    _ => Ok(false),
                 }
             }
             Err(_) => Err("Failed to acquire lock".to_string()),
         }
     }
 
-    pub fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<String>, String> {
+    pub fn ft_drop(&self, name: &str) -> Result<bool, String> {
+        match self.search_indexes.lock() {
+            Ok(mut search_indexes) => Ok(search_indexes.remove(name).is_some()),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn ft_search(&self, name: &str, query: &str) -> Result<Vec<String>, String> {
         match self.map.lock() {
-            Ok(mut map) => {
-                if let Some(value_with_ttl) = map.get(key) {
-                    if value_with_ttl.is_expired() {
-                        map.remove(key);
-                        Ok(Vec::new())
-                    } else {
-                        match &value_with_ttl.value {
-                            Value::List(list) => {
-                                let len = list.len() as i64;
-                                if len == 0 {
-                                    return Ok(Vec::new());
-                                }
+            Ok(map) => {
+                let search_indexes = self.search_indexes.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+                let index = match search_indexes.get(name) {
+                    Some(index) => index,
+                    None => return Err(format!("No such search index '{}'", name)),
+                };
 
-                                // Handle negative indices
-                                let start_idx = if start < 0 { 
-                                    std::cmp::max(0, len + start) as usize 
-                                } else { 
-                                    std::cmp::min(start as usize, len as usize) 
-                                };
-                                
-                                let stop_idx = if stop < 0 { 
-                                    std::cmp::max(0, len + stop) as usize 
-                                } else { 
-                                    std::cmp::min(stop as usize, len as usize - 1) 
-                                };
-
-                                if start_idx > stop_idx {
-                                    return Ok(Vec::new());
-                                }
+                let mut keys: Vec<String> = index.search(query)
+                    .into_iter()
+                    .filter(|key| map.get(key).is_some_and(|value_with_ttl| !value_with_ttl.is_expired()))
+                    .collect();
+                keys.sort();
+                Ok(keys)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
 
-                                let result: Vec<String> = list.iter()
-                                    .skip(start_idx)
-                                    .take(stop_idx - start_idx + 1)
-                                    .cloned()
-                                    .collect();
-                                
-                                Ok(result)
-                            }
-                            _ => Err("Key contains non-list value".to_string()),
-                        }
-                    }
-                } else {
-                    Ok(Vec::new())
+    // Must be called while holding the map lock, like index_hash_field
+    fn reindex_search(&self, key: &str, value: Option<&Value>) {
+        if let Ok(mut search_indexes) = self.search_indexes.lock() {
+            for index in search_indexes.values_mut() {
+                if index.covers(key) {
+                    index.index_document(key, value);
                 }
             }
-            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+}
+
+// Hash values are bytes; the text API only hands out the ones that are UTF-8
+fn hash_text(field: &str, value: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(value).map_err(|_| format!("Field '{}' holds binary data", field))
+}
+
+fn hash_texts(hash: HashMap<String, Vec<u8>>) -> Result<HashMap<String, String>, String> {
+    hash.into_iter().map(|(field, value)| Ok((field.clone(), hash_text(&field, value)?))).collect()
+}
+
+/// Reads against a single moment of the store, see `Store::read_transaction`.
+/// Expiry is judged at the moment the transaction started, so a key cannot
+/// vanish between two reads of the same transaction.
+pub struct ReadTransaction<'a> {
+    map: MutexGuard<'a, ShardedMap<ValueWithTtl>>,
+    now: Instant,
+}
+
+impl ReadTransaction<'_> {
+    fn value(&self, key: &str) -> Option<&Value> {
+        self.map
+            .get(key)
+            .filter(|entry| entry.expires_at.is_none_or(|expires| self.now <= expires))
+            .map(|entry| &entry.value)
+    }
+
+    pub fn exists(&self, key: &str) -> bool {
+        self.value(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>, String> {
+        match self.value(key) {
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err("Key contains non-string value".to_string()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn hget(&self, key: &str, field: &str) -> Result<Option<String>, String> {
+        match self.value(key) {
+            Some(Value::Hash(hash)) => hash.get(field).map(|value| hash_text(field, value.clone())).transpose(),
+            Some(_) => Err("Key contains non-hash value".to_string()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn hgetall(&self, key: &str) -> Result<HashMap<String, String>, String> {
+        match self.value(key) {
+            Some(Value::Hash(hash)) => hash_texts(hash.clone()),
+            Some(_) => Err("Key contains non-hash value".to_string()),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    pub fn llen(&self, key: &str) -> Result<usize, String> {
+        match self.value(key) {
+            Some(Value::List(list)) => Ok(list.len()),
+            Some(_) => Err("Key contains non-list value".to_string()),
+            None => Ok(0),
+        }
+    }
+
+    pub fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<String>, String> {
+        match self.value(key) {
+            Some(Value::List(list)) => Ok(list_range(list, start, stop)),
+            Some(_) => Err("Key contains non-list value".to_string()),
+            None => Ok(Vec::new()),
         }
     }
 }
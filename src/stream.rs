@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Bound;
+
+/// An entry's ID, written `ms-seq`: the millisecond it was added and a
+/// sequence number for entries added within the same millisecond.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// Parse `ms-seq`, or a bare `ms` with `default_seq` as its sequence
+    /// number (0 for the start of a range, `u64::MAX` for its end).
+    pub fn parse(text: &str, default_seq: u64) -> Option<Self> {
+        match text.split_once('-') {
+            Some((ms, seq)) => Some(Self::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(Self::new(text.parse().ok()?, default_seq)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// An entry's field/value pairs, in the order they were added.
+pub type Fields = Vec<(String, String)>;
+
+/// An entry as read out of a stream.
+pub type Entry = (StreamId, Fields);
+
+/// How to trim a stream: keep the newest `MaxLen` entries, or remove the
+/// entries with IDs below `MinId`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trim {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// An entry delivered to a consumer and not yet acknowledged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pending {
+    pub consumer: String,
+    /// Unix milliseconds of the last delivery.
+    pub delivered_ms: u64,
+    pub deliveries: u64,
+}
+
+/// Consumers reading a stream together: each new entry goes to one of
+/// them and stays pending until acknowledged, so an entry whose consumer
+/// stalls can be claimed by another.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConsumerGroup {
+    last_delivered: StreamId,
+    pending: BTreeMap<StreamId, Pending>,
+}
+
+impl ConsumerGroup {
+    /// A group that has delivered everything up to `last_delivered`, with
+    /// `pending` entries still unacknowledged, as saved in a snapshot.
+    pub fn restore(last_delivered: StreamId, pending: impl IntoIterator<Item = (StreamId, Pending)>) -> Self {
+        Self { last_delivered, pending: pending.into_iter().collect() }
+    }
+
+    pub fn last_delivered(&self) -> StreamId {
+        self.last_delivered
+    }
+
+    /// Unacknowledged entries, oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = (StreamId, &Pending)> {
+        self.pending.iter().map(|(id, pending)| (*id, pending))
+    }
+}
+
+/// What `Stream::autoclaim` claimed, and where to carry on from (0-0 once
+/// the pending entries have all been looked at).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AutoClaim {
+    pub next: StreamId,
+    pub claimed: Vec<Entry>,
+    /// Pending entries trimmed from the stream, dropped instead of claimed.
+    pub deleted: Vec<StreamId>,
+}
+
+/// An append-only log of entries ordered by ID. The last ID is kept apart
+/// from the entries, so IDs keep increasing after entries are removed.
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    groups: BTreeMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A stream with `entries` whose IDs so far reached `last_id`, as
+    /// saved in a snapshot.
+    pub fn restore(last_id: StreamId, entries: impl IntoIterator<Item = (StreamId, Fields)>) -> Self {
+        let entries: BTreeMap<StreamId, Fields> = entries.into_iter().collect();
+        let last_id = entries.keys().next_back().map_or(last_id, |newest| last_id.max(*newest));
+        Self { entries, last_id, groups: BTreeMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The highest ID ever added, 0-0 for a new stream.
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Append an entry. Without an `id`, one is made from `now_ms`, or
+    /// from the last ID if the clock is behind it; a given ID must be
+    /// greater than the last.
+    pub fn add(&mut self, id: Option<StreamId>, fields: Fields, now_ms: u64) -> Result<StreamId, String> {
+        let id = match id {
+            Some(id) if id > self.last_id => id,
+            Some(_) => return Err(format!("ID must be greater than the stream's last ID {}", self.last_id)),
+            None if now_ms > self.last_id.ms => StreamId::new(now_ms, 0),
+            None => match self.last_id.seq.checked_add(1) {
+                Some(seq) => StreamId::new(self.last_id.ms, seq),
+                None => StreamId::new(self.last_id.ms.checked_add(1).ok_or("Stream IDs are exhausted")?, 0),
+            },
+        };
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    /// Entries with IDs from `start` to `end` inclusive, oldest first.
+    pub fn range(&self, start: StreamId, end: StreamId) -> impl Iterator<Item = (StreamId, &Fields)> {
+        // BTreeMap::range panics on a start past the end, so that becomes
+        // a range that is empty instead
+        let bounds = if start <= end { (Bound::Included(start), Bound::Included(end)) } else { (Bound::Included(start), Bound::Excluded(start)) };
+        self.entries.range(bounds).map(|(id, fields)| (*id, fields))
+    }
+
+    /// Entries with IDs greater than `id`, oldest first.
+    pub fn after(&self, id: StreamId) -> impl Iterator<Item = (StreamId, &Fields)> {
+        self.entries.range((Bound::Excluded(id), Bound::Unbounded)).map(|(id, fields)| (*id, fields))
+    }
+
+    /// Every entry, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = (StreamId, &Fields)> {
+        self.entries.iter().map(|(id, fields)| (*id, fields))
+    }
+
+    /// Remove old entries, returning how many. Consumer groups keep any
+    /// pending ones in their lists until acknowledged or claimed.
+    pub fn trim(&mut self, trim: Trim) -> usize {
+        let before = self.entries.len();
+        match trim {
+            Trim::MaxLen(max) => {
+                while self.entries.len() > max {
+                    self.entries.pop_first();
+                }
+            }
+            Trim::MinId(min) => self.entries = self.entries.split_off(&min),
+        }
+        before - self.entries.len()
+    }
+
+    /// Add a consumer group that delivers entries after `start`. Returns
+    /// false if it already exists.
+    pub fn create_group(&mut self, name: &str, start: StreamId) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        self.groups.insert(name.to_string(), ConsumerGroup::restore(start, []));
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    /// Every consumer group by name.
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &ConsumerGroup)> {
+        self.groups.iter().map(|(name, group)| (name.as_str(), group))
+    }
+
+    pub fn restore_group(&mut self, name: &str, group: ConsumerGroup) {
+        self.groups.insert(name.to_string(), group);
+    }
+
+    /// Read up to `count` entries for `consumer` in `group`. With no
+    /// `after`, these are entries never delivered to the group, which
+    /// become pending for `consumer`; with one, the consumer's own pending
+    /// entries after it are read again.
+    pub fn read_group(&mut self, group: &str, consumer: &str, after: Option<StreamId>, count: usize, now_ms: u64) -> Result<Vec<Entry>, String> {
+        let Some(state) = self.groups.get_mut(group) else {
+            return Err(format!("No consumer group '{}'", group));
+        };
+        let entries = &self.entries;
+        let read: Vec<Entry> = match after {
+            None => {
+                let read: Vec<Entry> = entries
+                    .range((Bound::Excluded(state.last_delivered), Bound::Unbounded))
+                    .take(count)
+                    .map(|(id, fields)| (*id, fields.clone()))
+                    .collect();
+                for (id, _) in &read {
+                    state.pending.insert(*id, Pending { consumer: consumer.to_string(), delivered_ms: now_ms, deliveries: 1 });
+                    state.last_delivered = *id;
+                }
+                read
+            }
+            // Entries trimmed away since are left out
+            Some(after) => state
+                .pending
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .filter(|(_, pending)| pending.consumer == consumer)
+                .filter_map(|(id, _)| entries.get(id).map(|fields| (*id, fields.clone())))
+                .take(count)
+                .collect(),
+        };
+        Ok(read)
+    }
+
+    /// Acknowledge entries in `group`, returning how many were pending.
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> Result<usize, String> {
+        let Some(state) = self.groups.get_mut(group) else {
+            return Err(format!("No consumer group '{}'", group));
+        };
+        Ok(ids.iter().filter(|id| state.pending.remove(id).is_some()).count())
+    }
+
+    /// Hand `consumer` up to `count` of `group`'s pending entries from
+    /// `start` on that have gone `min_idle_ms` since their last delivery.
+    pub fn autoclaim(&mut self, group: &str, consumer: &str, min_idle_ms: u64, start: StreamId, count: usize, now_ms: u64) -> Result<AutoClaim, String> {
+        let Some(state) = self.groups.get_mut(group) else {
+            return Err(format!("No consumer group '{}'", group));
+        };
+        let mut result = AutoClaim::default();
+        let idle: Vec<StreamId> = state
+            .pending
+            .range(start..)
+            .filter(|(_, pending)| now_ms.saturating_sub(pending.delivered_ms) >= min_idle_ms)
+            .map(|(id, _)| *id)
+            .take(count + 1)
+            .collect();
+        for id in idle.iter().take(count) {
+            match self.entries.get(id) {
+                Some(fields) => {
+                    if let Some(pending) = state.pending.get_mut(id) {
+                        pending.consumer = consumer.to_string();
+                        pending.delivered_ms = now_ms;
+                        pending.deliveries += 1;
+                    }
+                    result.claimed.push((*id, fields.clone()));
+                }
+                None => {
+                    state.pending.remove(id);
+                    result.deleted.push(*id);
+                }
+            }
+        }
+        result.next = idle.get(count).copied().unwrap_or(StreamId::MIN);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> Fields {
+        pairs.iter().map(|(field, value)| (field.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut stream = Stream::new();
+        assert_eq!(stream.add(None, fields(&[("temp", "21")]), 1000), Ok(StreamId::new(1000, 0)));
+        assert_eq!(stream.add(None, fields(&[("temp", "22")]), 1000), Ok(StreamId::new(1000, 1)));
+        // A clock that went back still gets increasing IDs
+        assert_eq!(stream.add(None, fields(&[("temp", "23")]), 900), Ok(StreamId::new(1000, 2)));
+        assert_eq!(stream.add(Some(StreamId::new(2000, 5)), fields(&[("temp", "24")]), 0), Ok(StreamId::new(2000, 5)));
+        assert!(stream.add(Some(StreamId::new(2000, 5)), fields(&[("temp", "25")]), 0).is_err());
+        assert!(Stream::new().add(Some(StreamId::MIN), Fields::new(), 0).is_err());
+        assert_eq!(stream.len(), 4);
+
+        let ids = |entries: Vec<(StreamId, &Fields)>| entries.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(ids(stream.range(StreamId::new(1000, 1), StreamId::new(1000, u64::MAX)).collect()), ["1000-1", "1000-2"]);
+        assert_eq!(ids(stream.after(StreamId::new(1000, 2)).collect()), ["2000-5"]);
+        assert!(stream.range(StreamId::MAX, StreamId::MIN).next().is_none());
+        assert_eq!(stream.iter().next().unwrap().1, &fields(&[("temp", "21")]));
+
+        let restored = Stream::restore(StreamId::new(3000, 0), stream.iter().map(|(id, fields)| (id, fields.clone())));
+        assert_eq!(restored.last_id(), StreamId::new(3000, 0));
+        assert_eq!(restored.len(), 4);
+
+        assert_eq!(stream.trim(Trim::MaxLen(3)), 1);
+        assert_eq!(stream.trim(Trim::MinId(StreamId::new(1000, 2))), 1);
+        assert_eq!(stream.trim(Trim::MaxLen(5)), 0);
+        assert_eq!(ids(stream.iter().collect()), ["1000-2", "2000-5"]);
+        assert_eq!(stream.last_id(), StreamId::new(2000, 5));
+
+        assert_eq!(StreamId::parse("1526919030474-55", 0), Some(StreamId::new(1526919030474, 55)));
+        assert_eq!(StreamId::parse("1526919030474", u64::MAX), Some(StreamId::new(1526919030474, u64::MAX)));
+        assert_eq!(StreamId::parse("12-x", 0), None);
+        assert_eq!(StreamId::parse("-1", 0), None);
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let mut stream = Stream::new();
+        for ms in 1..=4 {
+            stream.add(Some(StreamId::new(ms, 0)), fields(&[("job", &ms.to_string())]), 0).unwrap();
+        }
+        assert!(stream.create_group("workers", StreamId::MIN));
+        assert!(!stream.create_group("workers", StreamId::MIN));
+        assert!(stream.read_group("nobody", "ann", None, 10, 0).is_err());
+
+        let ids = |entries: Vec<Entry>| entries.into_iter().map(|(id, _)| id.ms).collect::<Vec<_>>();
+        assert_eq!(ids(stream.read_group("workers", "ann", None, 2, 1000).unwrap()), [1, 2]);
+        assert_eq!(ids(stream.read_group("workers", "bob", None, 10, 1000).unwrap()), [3, 4]);
+        assert!(stream.read_group("workers", "bob", None, 10, 1000).unwrap().is_empty());
+        // History: a consumer's own pending entries again
+        assert_eq!(ids(stream.read_group("workers", "ann", Some(StreamId::MIN), 10, 1000).unwrap()), [1, 2]);
+
+        assert_eq!(stream.ack("workers", &[StreamId::new(1, 0), StreamId::new(1, 0), StreamId::new(9, 0)]), Ok(1));
+        let pending = |stream: &Stream| stream.group("workers").unwrap().pending().map(|(id, pending)| (id.ms, pending.consumer.clone())).collect::<Vec<_>>();
+        assert_eq!(pending(&stream), [(2, "ann".to_string()), (3, "bob".to_string()), (4, "bob".to_string())]);
+
+        // Entry 2 was trimmed away, so it is dropped rather than claimed
+        stream.trim(Trim::MinId(StreamId::new(3, 0)));
+        assert_eq!(stream.autoclaim("workers", "cy", 5000, StreamId::MIN, 10, 2000).unwrap(), AutoClaim::default());
+        let claim = stream.autoclaim("workers", "cy", 5000, StreamId::MIN, 2, 7000).unwrap();
+        assert_eq!(claim.next, StreamId::new(4, 0));
+        assert_eq!(claim.deleted, [StreamId::new(2, 0)]);
+        assert_eq!(ids(claim.claimed), [3]);
+        let claim = stream.autoclaim("workers", "cy", 5000, claim.next, 2, 7000).unwrap();
+        assert_eq!((claim.next, ids(claim.claimed)), (StreamId::MIN, vec![4]));
+        assert_eq!(pending(&stream), [(3, "cy".to_string()), (4, "cy".to_string())]);
+        assert_eq!(stream.group("workers").unwrap().pending().next().unwrap().1.deliveries, 2);
+
+        assert!(stream.destroy_group("workers"));
+        assert!(stream.group("workers").is_none());
+    }
+}
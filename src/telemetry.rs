@@ -0,0 +1,344 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const BATCH_SIZE: usize = 256;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Debug)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+}
+
+/// A finished span, ready to be exported.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub parent_span_id: Option<[u8; 8]>,
+    pub name: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(String, AttributeValue)>,
+    pub is_error: bool,
+}
+
+/// A span that is still running. Call `finish` to hand it to the exporter.
+pub struct ActiveSpan {
+    span: Span,
+    started: Instant,
+}
+
+impl ActiveSpan {
+    pub fn trace_id(&self) -> [u8; 16] {
+        self.span.trace_id
+    }
+
+    pub fn span_id(&self) -> [u8; 8] {
+        self.span.span_id
+    }
+
+    pub fn set_string(&mut self, key: &str, value: &str) {
+        self.span.attributes.push((key.to_string(), AttributeValue::String(value.to_string())));
+    }
+
+    pub fn set_int(&mut self, key: &str, value: i64) {
+        self.span.attributes.push((key.to_string(), AttributeValue::Int(value)));
+    }
+
+    pub fn set_error(&mut self, is_error: bool) {
+        self.span.is_error = is_error;
+    }
+
+    pub fn finish(mut self, tracer: &Tracer) {
+        let elapsed = self.started.elapsed();
+        self.span.end = self.span.start + elapsed;
+        self.set_int("medusa.latency_us", elapsed.as_micros() as i64);
+        tracer.export(self.span);
+    }
+}
+
+/// Cheap, cloneable handle used by connection handlers to record spans.
+/// A disabled tracer drops everything without allocating an exporter.
+#[derive(Clone, Default)]
+pub struct Tracer {
+    sender: Option<Sender<Span>>,
+}
+
+impl Tracer {
+    pub fn disabled() -> Self {
+        Tracer { sender: None }
+    }
+
+    /// Start a background exporter that POSTs batches of spans as OTLP/HTTP
+    /// JSON to `endpoint` (e.g. `http://127.0.0.1:4318`).
+    pub fn otlp(endpoint: &str, service_name: &str) -> Result<Self, String> {
+        let target = OtlpTarget::parse(endpoint)?;
+        let service_name = service_name.to_string();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || run_exporter(receiver, target, service_name));
+
+        Ok(Tracer { sender: Some(sender) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub fn start_span(&self, name: &str, parent: Option<&ActiveSpan>) -> ActiveSpan {
+        let (trace_id, parent_span_id) = match parent {
+            Some(parent) => (parent.trace_id(), Some(parent.span_id())),
+            None => {
+                let mut trace_id = [0u8; 16];
+                trace_id[..8].copy_from_slice(&random_u64().to_be_bytes());
+                trace_id[8..].copy_from_slice(&random_u64().to_be_bytes());
+                (trace_id, None)
+            }
+        };
+
+        let now = SystemTime::now();
+        ActiveSpan {
+            span: Span {
+                trace_id,
+                span_id: random_u64().to_be_bytes(),
+                parent_span_id,
+                name: name.to_string(),
+                start: now,
+                end: now,
+                attributes: Vec::new(),
+                is_error: false,
+            },
+            started: Instant::now(),
+        }
+    }
+
+    fn export(&self, span: Span) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(span);
+        }
+    }
+}
+
+struct OtlpTarget {
+    address: String,
+    host: String,
+    path: String,
+}
+
+impl OtlpTarget {
+    fn parse(endpoint: &str) -> Result<Self, String> {
+        let rest = endpoint
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("Unsupported OTLP endpoint '{}' (only http:// is supported)", endpoint))?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, ""),
+        };
+        if authority.is_empty() {
+            return Err(format!("Invalid OTLP endpoint '{}'", endpoint));
+        }
+
+        let address = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+        let path = if path.is_empty() || path == "/" {
+            "/v1/traces".to_string()
+        } else {
+            path.to_string()
+        };
+
+        Ok(OtlpTarget {
+            address,
+            host: authority.to_string(),
+            path,
+        })
+    }
+}
+
+fn run_exporter(receiver: Receiver<Span>, target: OtlpTarget, service_name: String) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut last_flush = Instant::now();
+
+    loop {
+        match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(span) => batch.push(span),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                flush(&target, &service_name, &mut batch);
+                return;
+            }
+        }
+
+        if batch.len() >= BATCH_SIZE || (!batch.is_empty() && last_flush.elapsed() >= FLUSH_INTERVAL) {
+            flush(&target, &service_name, &mut batch);
+            last_flush = Instant::now();
+        }
+    }
+}
+
+fn flush(target: &OtlpTarget, service_name: &str, batch: &mut Vec<Span>) {
+    if batch.is_empty() {
+        return;
+    }
+    let body = encode_otlp_json(service_name, batch);
+    batch.clear();
+
+    if let Err(e) = post(target, &body) {
+        eprintln!("Failed to export spans to {}: {}", target.address, e);
+    }
+}
+
+fn post(target: &OtlpTarget, body: &str) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect(&target.address)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        target.path,
+        target.host,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes())?;
+
+    // Only the status line matters; the collector's body is ignored
+    let mut response = [0u8; 64];
+    let read = stream.read(&mut response)?;
+    let status_line = String::from_utf8_lossy(&response[..read]);
+    if status_line.split_whitespace().nth(1).is_some_and(|code| code.starts_with('2')) {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "collector responded with '{}'",
+            status_line.lines().next().unwrap_or("")
+        )))
+    }
+}
+
+/// Encode spans using the OTLP/HTTP JSON mapping of `ExportTraceServiceRequest`.
+pub fn encode_otlp_json(service_name: &str, spans: &[Span]) -> String {
+    let encoded: Vec<String> = spans.iter().map(encode_span).collect();
+    format!(
+        "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{}]}},\"scopeSpans\":[{{\"scope\":{{\"name\":\"medusa\",\"version\":\"{}\"}},\"spans\":[{}]}}]}}]}}",
+        encode_attribute("service.name", &AttributeValue::String(service_name.to_string())),
+        env!("CARGO_PKG_VERSION"),
+        encoded.join(",")
+    )
+}
+
+fn encode_span(span: &Span) -> String {
+    let attributes: Vec<String> = span.attributes.iter()
+        .map(|(key, value)| encode_attribute(key, value))
+        .collect();
+    let parent = span.parent_span_id
+        .map(|id| format!("\"parentSpanId\":\"{}\",", hex(&id)))
+        .unwrap_or_default();
+
+    format!(
+        "{{\"traceId\":\"{}\",\"spanId\":\"{}\",{}\"name\":\"{}\",\"kind\":2,\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[{}],\"status\":{{\"code\":{}}}}}",
+        hex(&span.trace_id),
+        hex(&span.span_id),
+        parent,
+        escape_json(&span.name),
+        unix_nanos(span.start),
+        unix_nanos(span.end),
+        attributes.join(","),
+        if span.is_error { 2 } else { 1 }
+    )
+}
+
+fn encode_attribute(key: &str, value: &AttributeValue) -> String {
+    let value = match value {
+        AttributeValue::String(s) => format!("{{\"stringValue\":\"{}\"}}", escape_json(s)),
+        AttributeValue::Int(i) => format!("{{\"intValue\":\"{}\"}}", i),
+    };
+    format!("{{\"key\":\"{}\",\"value\":{}}}", escape_json(key), value)
+}
+
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+}
+
+pub(crate) fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(ID_COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(unix_nanos(SystemTime::now()));
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_otlp_target() {
+        let target = OtlpTarget::parse("http://collector:4318").unwrap();
+        assert_eq!(target.address, "collector:4318");
+        assert_eq!(target.path, "/v1/traces");
+
+        let target = OtlpTarget::parse("http://collector/custom/path").unwrap();
+        assert_eq!(target.address, "collector:80");
+        assert_eq!(target.path, "/custom/path");
+
+        assert!(OtlpTarget::parse("https://collector:4318").is_err());
+    }
+
+    #[test]
+    fn test_child_spans_share_trace() {
+        let tracer = Tracer::disabled();
+        let parent = tracer.start_span("medusa.connection", None);
+        let child = tracer.start_span("medusa.command", Some(&parent));
+
+        assert_eq!(child.trace_id(), parent.trace_id());
+        assert_ne!(child.span_id(), parent.span_id());
+        assert_eq!(child.span.parent_span_id, Some(parent.span_id()));
+    }
+
+    #[test]
+    fn test_encode_otlp_json() {
+        let tracer = Tracer::disabled();
+        let mut span = tracer.start_span("medusa.command", None);
+        span.set_string("db.operation", "GET");
+        span.set_string("db.medusa.key", "quote\"key");
+        span.set_int("medusa.response_bytes", 12);
+
+        let json = encode_otlp_json("medusa", &[span.span]);
+        assert!(json.contains("\"name\":\"medusa.command\""));
+        assert!(json.contains("{\"key\":\"db.operation\",\"value\":{\"stringValue\":\"GET\"}}"));
+        assert!(json.contains("quote\\\"key"));
+        assert!(json.contains("{\"intValue\":\"12\"}"));
+        assert!(!json.contains("parentSpanId"));
+    }
+}
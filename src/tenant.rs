@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Commands that can grow a tenant's key count or memory footprint.
+const GROWING_COMMANDS: &[&str] = &[
+    "SET", "SETEX", "PSETEX", "SETCHUNK", "SETRANGE", "HSET", "HSETBIN", "LPUSH", "RPUSH", "SADD", "ZADD", "ZADDDELAY", "TS.CREATE", "TS.ADD",
+    "ZINCRBY", "XADD", "VADD", "CAS",
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TenantQuota {
+    pub max_keys: Option<usize>,
+    pub max_memory: Option<usize>,
+    pub max_ops_per_sec: Option<u64>,
+}
+
+impl TenantQuota {
+    /// Parse `keys=1000;memory=1048576;ops=500` (any subset, any order).
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut quota = TenantQuota::default();
+        for setting in spec.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid quota setting '{}'", setting))?;
+            let value = value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid value for quota '{}'", name))?;
+            match name.trim().to_lowercase().as_str() {
+                "keys" => quota.max_keys = Some(value as usize),
+                "memory" => quota.max_memory = Some(value as usize),
+                "ops" => quota.max_ops_per_sec = Some(value),
+                other => return Err(format!("Unknown quota '{}'", other)),
+            }
+        }
+        Ok(quota)
+    }
+
+    pub fn describe(&self) -> String {
+        let limit = |value: Option<String>| value.unwrap_or_else(|| "unlimited".to_string());
+        format!(
+            "max_keys={} max_memory={} max_ops_per_sec={}",
+            limit(self.max_keys.map(|v| v.to_string())),
+            limit(self.max_memory.map(|v| v.to_string())),
+            limit(self.max_ops_per_sec.map(|v| v.to_string())),
+        )
+    }
+}
+
+/// Parse `MEDUSA_TENANTS`-style definitions: `team_a:keys=100;ops=50,team_b:memory=1048576`.
+pub fn parse_tenants(spec: &str) -> Result<Vec<(String, TenantQuota)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|tenant| {
+            let (name, quota) = tenant.split_once(':').unwrap_or((tenant, ""));
+            if name.is_empty() {
+                return Err(format!("Invalid tenant definition '{}'", tenant));
+            }
+            Ok((name.to_string(), TenantQuota::parse(quota)?))
+        })
+        .collect()
+}
+
+pub fn is_growing_command(command: &str) -> bool {
+    GROWING_COMMANDS.contains(&command)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TenantStats {
+    pub total_ops: u64,
+    pub rejected_ops: u64,
+}
+
+#[derive(Debug)]
+struct TenantState {
+    quota: TenantQuota,
+    stats: TenantStats,
+    window_second: u64,
+    window_ops: u64,
+    keys: usize,
+    memory: usize,
+}
+
+/// Tenants are key namespaces: tenant `team_a` owns every key starting
+/// with `team_a:`. The registry holds each tenant's quota, op counters and
+/// running key count and memory, which the store updates on every write.
+#[derive(Clone, Default)]
+pub struct TenantRegistry {
+    tenants: Arc<Mutex<HashMap<String, TenantState>>>,
+}
+
+impl TenantRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, name: &str, quota: TenantQuota) -> Result<(), String> {
+        match self.tenants.lock() {
+            Ok(mut tenants) => {
+                match tenants.get_mut(name) {
+                    Some(state) => state.quota = quota,
+                    None => {
+                        tenants.insert(name.to_string(), TenantState {
+                            quota,
+                            stats: TenantStats::default(),
+                            window_second: 0,
+                            window_ops: 0,
+                            keys: 0,
+                            memory: 0,
+                        });
+                    }
+                }
+                Ok(())
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn remove(&self, name: &str) -> Result<bool, String> {
+        match self.tenants.lock() {
+            Ok(mut tenants) => Ok(tenants.remove(name).is_some()),
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    pub fn names(&self) -> Result<Vec<String>, String> {
+        match self.tenants.lock() {
+            Ok(tenants) => {
+                let mut names: Vec<String> = tenants.keys().cloned().collect();
+                names.sort();
+                Ok(names)
+            }
+            Err(_) => Err("Failed to acquire lock".to_string()),
+        }
+    }
+
+    /// The tenant owning `key`, if any.
+    pub fn tenant_for_key(&self, key: &str) -> Option<String> {
+        let (namespace, _) = key.split_once(':')?;
+        let tenants = self.tenants.lock().ok()?;
+        tenants.contains_key(namespace).then(|| namespace.to_string())
+    }
+
+    pub fn quota(&self, name: &str) -> Option<TenantQuota> {
+        self.tenants.lock().ok()?.get(name).map(|state| state.quota.clone())
+    }
+
+    pub fn stats(&self, name: &str) -> Option<TenantStats> {
+        self.tenants.lock().ok()?.get(name).map(|state| state.stats.clone())
+    }
+
+    /// Key count and estimated memory of the tenant's keys. Expired keys
+    /// count until purged.
+    pub fn usage(&self, name: &str) -> Option<(usize, usize)> {
+        self.tenants.lock().ok()?.get(name).map(|state| (state.keys, state.memory))
+    }
+
+    /// Add `keys` and `memory` (either may be negative) to the usage of
+    /// the tenant owning `key`, if any.
+    pub fn account(&self, key: &str, keys: isize, memory: isize) {
+        let Some((namespace, _)) = key.split_once(':') else {
+            return;
+        };
+        if let Ok(mut tenants) = self.tenants.lock() {
+            if let Some(state) = tenants.get_mut(namespace) {
+                state.keys = state.keys.saturating_add_signed(keys);
+                state.memory = state.memory.saturating_add_signed(memory);
+            }
+        }
+    }
+
+    /// Count one operation against the tenant's per-second budget.
+    pub fn admit_op(&self, name: &str) -> Result<(), String> {
+        let mut tenants = self.tenants.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        let state = match tenants.get_mut(name) {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        let second = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if state.window_second != second {
+            state.window_second = second;
+            state.window_ops = 0;
+        }
+
+        if let Some(limit) = state.quota.max_ops_per_sec {
+            if state.window_ops >= limit {
+                state.stats.rejected_ops += 1;
+                return Err(format!("Tenant '{}' exceeded {} ops/sec", name, limit));
+            }
+        }
+        state.window_ops += 1;
+        state.stats.total_ops += 1;
+        Ok(())
+    }
+
+    pub fn record_rejection(&self, name: &str) {
+        if let Ok(mut tenants) = self.tenants.lock() {
+            if let Some(state) = tenants.get_mut(name) {
+                state.stats.rejected_ops += 1;
+            }
+        }
+    }
+}
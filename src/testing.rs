@@ -0,0 +1,134 @@
+//! An in-process server for integration tests, here and in applications
+//! that embed Medusa.
+
+use crate::lifecycle::Lifecycle;
+use crate::server::{self, ServerConfig, ServerHandle};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A server running on its own thread, bound to an ephemeral port.
+///
+/// The listener is bound before `start` returns, so clients can connect
+/// straight away without waiting for the server to come up. Dropping the
+/// handle (or calling `shutdown`) disconnects every client and stops the
+/// server.
+pub struct TestServer {
+    server: Option<ServerHandle>,
+    addr: SocketAddr,
+    lifecycle: Lifecycle,
+}
+
+impl TestServer {
+    /// A server with the default configuration on 127.0.0.1.
+    pub fn start() -> Result<Self, String> {
+        Self::with_config(ServerConfig::default())
+    }
+
+    /// A server with `config`. Its port is ignored in favour of an
+    /// ephemeral one.
+    pub fn with_config(config: ServerConfig) -> Result<Self, String> {
+        let server = server::start_server_with_config(ServerConfig { port: 0, ..config })?;
+        let (addr, lifecycle) = (server.local_addr(), server.lifecycle().clone());
+        Ok(TestServer { server: Some(server), addr, lifecycle })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// The server's lifecycle, to inspect its clients or start a drain.
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    /// Open a connection that keeps its session between commands.
+    pub fn connect(&self) -> Result<TestClient, String> {
+        TestClient::connect(self.addr)
+    }
+
+    /// Run one command on a fresh connection and return its whole reply.
+    pub fn command(&self, command: &str) -> Result<String, String> {
+        self.connect()?.command(command)
+    }
+
+    /// Disconnect every client and wait for the server thread to finish.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.stop();
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A connection to a [`TestServer`]. Replies are framed with their length
+/// (`CLIENT FRAMING ON`), so multi-line replies come back whole.
+pub struct TestClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    pub fn connect(addr: SocketAddr) -> Result<Self, String> {
+        let stream = TcpStream::connect(addr).map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+        stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| format!("Failed to set read timeout: {}", e))?;
+        let reader = BufReader::new(stream.try_clone().map_err(|e| format!("Failed to clone stream: {}", e))?);
+        let mut client = TestClient { stream, reader };
+
+        // Speaking first saves waiting out the server's protocol detection
+        client.send("CLIENT FRAMING ON")?;
+        let mut welcome = String::new();
+        client.reader.read_line(&mut welcome).map_err(|e| format!("Failed to read welcome: {}", e))?;
+        client.read_reply()?;
+        Ok(client)
+    }
+
+    /// Send one command and return its whole reply.
+    pub fn command(&mut self, command: &str) -> Result<String, String> {
+        self.send(command)?;
+        self.read_reply()
+    }
+
+    /// Wait for the next message pushed to this connection without a
+    /// command, such as a `MESSAGE:` for a subscribed channel.
+    pub fn receive(&mut self) -> Result<String, String> {
+        self.read_reply()
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        self.stream
+            .write_all(format!("{}\n", command).as_bytes())
+            .map_err(|e| format!("Failed to send '{}': {}", command, e))
+    }
+
+    fn read_reply(&mut self) -> Result<String, String> {
+        let mut header = String::new();
+        match self.reader.read_line(&mut header) {
+            Ok(0) => return Err("Connection closed".to_string()),
+            Ok(_) => {}
+            Err(e) => return Err(format!("Failed to read reply: {}", e)),
+        }
+        let length: usize = header
+            .strip_prefix("FRAME ")
+            .and_then(|length| length.trim_end().parse().ok())
+            .ok_or_else(|| format!("Expected a FRAME header, got '{}'", header.trim_end()))?;
+        let mut reply = vec![0; length];
+        self.reader.read_exact(&mut reply).map_err(|e| format!("Failed to read reply: {}", e))?;
+        String::from_utf8(reply).map_err(|_| "Reply is not valid UTF-8".to_string())
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "avg" => Some(Aggregation::Avg),
+            "min" => Some(Aggregation::Min),
+            "max" => Some(Aggregation::Max),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[f64]) -> f64 {
+        match self {
+            Aggregation::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::Min => values.iter().cloned().fold(f64::INFINITY, f64::min),
+            Aggregation::Max => values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// Append-optimized series of (timestamp in ms, value) samples kept in
+/// timestamp order. Samples older than `retention_ms` relative to the newest
+/// sample are dropped on every append.
+#[derive(Clone, Debug, Default)]
+pub struct TimeSeries {
+    samples: VecDeque<(u64, f64)>,
+    retention_ms: Option<u64>,
+}
+
+impl TimeSeries {
+    pub fn new(retention_ms: Option<u64>) -> Self {
+        TimeSeries {
+            samples: VecDeque::new(),
+            retention_ms,
+        }
+    }
+
+    pub fn retention_ms(&self) -> Option<u64> {
+        self.retention_ms
+    }
+
+    pub fn set_retention(&mut self, retention_ms: Option<u64>) {
+        self.retention_ms = retention_ms;
+        self.trim();
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &(u64, f64)> {
+        self.samples.iter()
+    }
+
+    pub fn latest(&self) -> Option<(u64, f64)> {
+        self.samples.back().copied()
+    }
+
+    pub fn add(&mut self, timestamp: u64, value: f64) {
+        match self.samples.back() {
+            Some(&(last, _)) if timestamp > last => self.samples.push_back((timestamp, value)),
+            None => self.samples.push_back((timestamp, value)),
+            _ => {
+                // Out-of-order or duplicate sample: keep the series sorted,
+                // a duplicate timestamp overwrites the previous value
+                match self.samples.binary_search_by(|(ts, _)| ts.cmp(&timestamp)) {
+                    Ok(idx) => self.samples[idx].1 = value,
+                    Err(idx) => self.samples.insert(idx, (timestamp, value)),
+                }
+            }
+        }
+        self.trim();
+    }
+
+    pub fn range(&self, from: u64, to: u64, aggregation: Option<(Aggregation, u64)>) -> Vec<(u64, f64)> {
+        let start = self.samples.partition_point(|(ts, _)| *ts < from);
+        let in_range = self.samples.iter().skip(start).take_while(|(ts, _)| *ts <= to);
+
+        let (aggregation, bucket_ms) = match aggregation {
+            Some((aggregation, bucket_ms)) if bucket_ms > 0 => (aggregation, bucket_ms),
+            _ => return in_range.copied().collect(),
+        };
+
+        let mut result = Vec::new();
+        let mut bucket: Option<u64> = None;
+        let mut values = Vec::new();
+
+        for &(ts, value) in in_range {
+            let bucket_start = ts - ts % bucket_ms;
+            if bucket != Some(bucket_start) {
+                if let Some(previous) = bucket {
+                    result.push((previous, aggregation.apply(&values)));
+                }
+                bucket = Some(bucket_start);
+                values.clear();
+            }
+            values.push(value);
+        }
+        if let Some(previous) = bucket {
+            result.push((previous, aggregation.apply(&values)));
+        }
+
+        result
+    }
+
+    fn trim(&mut self) {
+        if let (Some(retention), Some(&(newest, _))) = (self.retention_ms, self.samples.back()) {
+            let cutoff = newest.saturating_sub(retention);
+            while self.samples.front().is_some_and(|(ts, _)| *ts < cutoff) {
+                self.samples.pop_front();
+            }
+        }
+    }
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
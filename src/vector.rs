@@ -0,0 +1,50 @@
+/// Distance metric used by VSEARCH. Smaller scores are always closer, so
+/// cosine similarity is reported as cosine distance (1 - similarity).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    Cosine,
+    L2,
+}
+
+impl Metric {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "COSINE" => Some(Metric::Cosine),
+            "L2" => Some(Metric::L2),
+            _ => None,
+        }
+    }
+
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => {
+                let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+                let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+                let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    1.0
+                } else {
+                    1.0 - dot / (norm_a * norm_b)
+                }
+            }
+            Metric::L2 => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt(),
+        }
+    }
+}
+
+/// Brute-force k-nearest-neighbor search over `candidates`. Vectors whose
+/// dimension differs from the query are skipped.
+pub fn nearest<'a, I>(candidates: I, query: &[f32], k: usize, metric: Metric) -> Vec<(String, f32)>
+where
+    I: IntoIterator<Item = (&'a String, &'a Vec<f32>)>,
+{
+    let mut scored: Vec<(String, f32)> = candidates
+        .into_iter()
+        .filter(|(_, vector)| vector.len() == query.len())
+        .map(|(key, vector)| (key.clone(), metric.distance(vector, query)))
+        .collect();
+
+    scored.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    scored.truncate(k);
+    scored
+}
@@ -0,0 +1,234 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Bound;
+
+/// A score ordered with `total_cmp`, so it can key a `BTreeSet`. NaN is
+/// refused before a score gets here.
+#[derive(Clone, Copy, Debug)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Parse a score as Redis writes them: a number, `inf`, `+inf` or `-inf`.
+pub fn parse_score(text: &str) -> Option<f64> {
+    text.parse::<f64>().ok().filter(|score| !score.is_nan())
+}
+
+/// One end of a score range: inclusive unless written with a leading `(`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoreBound {
+    pub score: f64,
+    pub exclusive: bool,
+}
+
+impl ScoreBound {
+    /// Parse a bound as Redis writes them: a score, optionally after `(`.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.strip_prefix('(') {
+            Some(score) => parse_score(score).map(|score| Self { score, exclusive: true }),
+            None => parse_score(text).map(|score| Self { score, exclusive: false }),
+        }
+    }
+
+    fn below(&self, score: f64) -> bool {
+        if self.exclusive { self.score < score } else { self.score <= score }
+    }
+
+    fn above(&self, score: f64) -> bool {
+        if self.exclusive { score < self.score } else { score <= self.score }
+    }
+}
+
+impl fmt::Display for ScoreBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.exclusive { "(" } else { "" }, self.score)
+    }
+}
+
+/// Members ordered by score, ties broken by member, each with a score
+/// looked up in O(1). Ranks count from 0 at the lowest score; finding one
+/// walks the members below it.
+#[derive(Clone, Debug, Default)]
+pub struct SortedSet {
+    ordered: BTreeSet<(Score, String)>,
+    scores: HashMap<String, f64>,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Set `member`'s score, adding it if missing. Returns whether it was new.
+    pub fn insert(&mut self, member: &str, score: f64) -> bool {
+        // -0 and 0 are the same score
+        let score = score + 0.0;
+        match self.scores.insert(member.to_string(), score) {
+            Some(old) => {
+                self.ordered.remove(&(Score(old), member.to_string()));
+                self.ordered.insert((Score(score), member.to_string()));
+                false
+            }
+            None => {
+                self.ordered.insert((Score(score), member.to_string()));
+                true
+            }
+        }
+    }
+
+    /// Remove `member`, returning its score.
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.ordered.remove(&(Score(score), member.to_string()));
+        Some(score)
+    }
+
+    /// Remove and return the member with the lowest score.
+    pub fn pop_min(&mut self) -> Option<(String, f64)> {
+        let (score, member) = self.ordered.pop_first()?;
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+
+    /// Remove and return the member with the highest score.
+    pub fn pop_max(&mut self) -> Option<(String, f64)> {
+        let (score, member) = self.ordered.pop_last()?;
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn rank(&self, member: &str) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.ordered.range(..(Score(score), member.to_string())).count())
+    }
+
+    /// Members from rank `start` to `stop` inclusive, with their scores.
+    /// Negative ranks count from the highest score, as in `LRANGE`.
+    pub fn range(&self, start: i64, stop: i64) -> Vec<(String, f64)> {
+        let len = self.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop {
+            return Vec::new();
+        }
+        self.iter().skip(start as usize).take((stop - start + 1) as usize).map(|(member, score)| (member.to_string(), score)).collect()
+    }
+
+    /// Members scored between `min` and `max`, lowest score first.
+    pub fn range_by_score(&self, min: ScoreBound, max: ScoreBound) -> impl Iterator<Item = (&str, f64)> {
+        // Members tied on a score sort after the empty string, so this
+        // starts at the first member scored `min`
+        let start = (Score(min.score + 0.0), String::new());
+        self.ordered
+            .range((Bound::Included(start), Bound::Unbounded))
+            .map(|(score, member)| (member.as_str(), score.0))
+            .skip_while(move |(_, score)| !min.below(*score))
+            .take_while(move |(_, score)| max.above(*score))
+    }
+
+    /// Remove the members scored between `min` and `max`, returning how
+    /// many there were.
+    pub fn remove_range_by_score(&mut self, min: ScoreBound, max: ScoreBound) -> usize {
+        let members: Vec<String> = self.range_by_score(min, max).map(|(member, _)| member.to_string()).collect();
+        for member in &members {
+            self.remove(member);
+        }
+        members.len()
+    }
+
+    /// Every member with its score, lowest score first.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.ordered.iter().map(|(score, member)| (member.as_str(), score.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sorted_set() {
+        let mut set = SortedSet::new();
+        assert!(set.insert("bob", 20.0));
+        assert!(set.insert("ann", 12.5));
+        assert!(set.insert("cy", 20.0));
+        assert!(set.insert("dan", f64::NEG_INFINITY));
+        assert!(!set.insert("bob", 5.0));
+
+        let order: Vec<&str> = set.iter().map(|(member, _)| member).collect();
+        assert_eq!(order, ["dan", "bob", "ann", "cy"]);
+        assert_eq!(set.score("bob"), Some(5.0));
+        assert_eq!(set.rank("ann"), Some(2));
+        assert_eq!(set.rank("eve"), None);
+
+        assert_eq!(set.range(1, 2), [("bob".to_string(), 5.0), ("ann".to_string(), 12.5)]);
+        assert_eq!(set.range(-1, -1), [("cy".to_string(), 20.0)]);
+        assert_eq!(set.range(0, 100).len(), 4);
+        assert!(set.range(3, 1).is_empty());
+        assert!(set.range(10, 20).is_empty());
+
+        // Negative zero ties with zero
+        set.insert("x", -0.0);
+        set.insert("y", 0.0);
+        assert_eq!(set.rank("y"), Some(set.rank("x").unwrap() + 1));
+
+        assert_eq!(set.remove("bob"), Some(5.0));
+        assert_eq!(set.remove("bob"), None);
+        assert_eq!(set.len(), 5);
+
+        let bound = |text| ScoreBound::parse(text).unwrap();
+        let members = |min, max| set.range_by_score(bound(min), bound(max)).map(|(member, _)| member).collect::<Vec<_>>();
+        assert_eq!(members("-inf", "+inf"), ["dan", "x", "y", "ann", "cy"]);
+        assert_eq!(members("0", "20"), ["x", "y", "ann", "cy"]);
+        assert_eq!(members("(0", "(20"), ["ann"]);
+        assert_eq!(members("(-inf", "0"), ["x", "y"]);
+        assert!(members("20", "0").is_empty());
+        assert_eq!(ScoreBound::parse("(1.5"), Some(ScoreBound { score: 1.5, exclusive: true }));
+        assert_eq!(ScoreBound::parse("(x"), None);
+
+        assert_eq!(set.remove_range_by_score(bound("-inf"), bound("(12.5")), 3);
+        assert_eq!(set.iter().map(|(member, _)| member).collect::<Vec<_>>(), ["ann", "cy"]);
+
+        assert_eq!(set.pop_max(), Some(("cy".to_string(), 20.0)));
+        assert_eq!(set.pop_min(), Some(("ann".to_string(), 12.5)));
+        assert_eq!(set.pop_min(), None);
+        assert!(set.is_empty() && set.score("ann").is_none());
+
+        assert_eq!(parse_score("-inf"), Some(f64::NEG_INFINITY));
+        assert_eq!(parse_score("+inf"), Some(f64::INFINITY));
+        assert_eq!(parse_score("1e3"), Some(1000.0));
+        assert_eq!(parse_score("NaN"), None);
+        assert_eq!(parse_score("high"), None);
+    }
+}
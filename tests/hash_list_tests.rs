@@ -88,6 +88,36 @@ fn test_list_operations() {
     assert_eq!(store.rpop("nonexistent").unwrap(), None);
 }
 
+#[test]
+fn test_set_operations() {
+    let store = Store::new();
+
+    // SADD counts only new members
+    assert_eq!(store.sadd("tags", &["rust", "db", "rust"]).unwrap(), 2);
+    assert_eq!(store.sadd("tags", &["db", "cache"]).unwrap(), 1);
+    assert_eq!(store.scard("tags").unwrap(), 3);
+    assert_eq!(store.smembers("tags").unwrap(), vec!["cache", "db", "rust"]);
+    assert!(store.sismember("tags", "db").unwrap());
+    assert!(!store.sismember("tags", "go").unwrap());
+
+    // Missing keys read as empty sets
+    assert_eq!(store.scard("nonexistent").unwrap(), 0);
+    assert!(store.smembers("nonexistent").unwrap().is_empty());
+    assert!(!store.sismember("nonexistent", "x").unwrap());
+    assert_eq!(store.srem("nonexistent", &["x"]).unwrap(), 0);
+
+    // Removing the last member deletes the key
+    assert_eq!(store.srem("tags", &["db", "go"]).unwrap(), 1);
+    assert_eq!(store.srem("tags", &["rust", "cache"]).unwrap(), 2);
+    assert!(!store.exists("tags").unwrap());
+
+    // Other types are not sets
+    store.set("name", "Ada").unwrap();
+    assert!(store.sadd("name", &["x"]).is_err());
+    assert!(store.scard("name").is_err());
+    assert_eq!(store.get("name").unwrap(), Some("Ada".to_string()));
+}
+
 #[test]
 fn test_hash_with_ttl() {
     let store = Store::new();
@@ -181,6 +211,13 @@ fn test_info_command() {
     assert!(info.contains("# Server"));
     assert!(info.contains("# Memory"));
     assert!(info.contains("# Stats"));
+    assert!(info.contains("\n# Keyspace\ndb0:keys=3,expires=0,avg_ttl=0"));
+
+    assert!(store.set_with_ttl("session", "x", 100).is_ok());
+    let info = store.info().unwrap();
+    let db0 = info.lines().find(|line| line.starts_with("db0:")).unwrap();
+    let avg_ttl: u64 = db0.strip_prefix("db0:keys=4,expires=1,avg_ttl=").unwrap().parse().unwrap();
+    assert!((99_000..=100_000).contains(&avg_ttl));
 }
 
 #[test]
@@ -0,0 +1,112 @@
+use medusa::http::start_http_server;
+use medusa::latency::CommandFamily;
+use medusa::lifecycle::Lifecycle;
+use medusa::store::Store;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+fn start_dashboard(store: &Store, lifecycle: &Lifecycle) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    start_http_server(listener, store.clone(), lifecycle.clone());
+    address
+}
+
+fn get(address: &str, target: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, address).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+#[test]
+fn test_dashboard_page() {
+    let address = start_dashboard(&Store::new(), &Lifecycle::new());
+    let (status, body) = get(&address, "/");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(body.contains("<title>Medusa Dashboard</title>"));
+
+    let (status, _) = get(&address, "/missing");
+    assert!(status.contains("404"));
+}
+
+#[test]
+fn test_info_and_clients_api() {
+    let store = Store::new();
+    let lifecycle = Lifecycle::new();
+    store.set("a", "1").unwrap();
+    let client = lifecycle.register_client("127.0.0.1:4000");
+    lifecycle.client_command(client, "SET");
+    let address = start_dashboard(&store, &lifecycle);
+
+    let (_, info) = get(&address, "/api/info");
+    assert!(info.contains("\"total_keys\":1"));
+    assert!(info.contains("\"medusa_version\":\"0.1.0\""));
+    assert!(info.contains("\"connected_clients\":1"));
+    assert!(info.contains("\"total_commands_processed\":1"));
+
+    let (_, clients) = get(&address, "/api/clients");
+    assert!(clients.contains("\"addr\":\"127.0.0.1:4000\""));
+    assert!(clients.contains("\"last_command\":\"SET\""));
+}
+
+#[test]
+fn test_key_browser_api() {
+    let store = Store::new();
+    store.set_with_ttl("user:1", "Ann \"the\" admin", 100).unwrap();
+    store.hset("user:2", "name", "Bob").unwrap();
+    store.rpush("queue", "job").unwrap();
+    store.ts_add("temp", 1000, 21.5).unwrap();
+    let address = start_dashboard(&store, &Lifecycle::new());
+
+    let (_, keys) = get(&address, "/api/keys?pattern=user%3A*");
+    assert!(keys.starts_with("{\"total\":2,"));
+    assert!(keys.contains("{\"key\":\"user:2\",\"type\":\"hash\",\"ttl\":-1}"));
+    assert!(keys.contains("\"type\":\"string\",\"ttl\":99") || keys.contains("\"type\":\"string\",\"ttl\":100"));
+
+    let (_, value) = get(&address, "/api/key?name=user%3A1");
+    assert!(value.contains("\"value\":\"Ann \\\"the\\\" admin\""));
+    let (_, value) = get(&address, "/api/key?name=user:2");
+    assert!(value.contains("\"value\":{\"name\":\"Bob\"}"));
+    let (_, value) = get(&address, "/api/key?name=queue");
+    assert!(value.contains("\"value\":[\"job\"]"));
+    let (_, value) = get(&address, "/api/key?name=temp");
+    assert!(value.contains("\"value\":[[1000,21.5]]"));
+
+    let (status, _) = get(&address, "/api/key?name=nope");
+    assert!(status.contains("404"));
+}
+
+#[test]
+fn test_slowlog_api() {
+    let store = Store::new();
+    store.slowlog().set_threshold(Duration::ZERO);
+    store.slowlog().record("KEYS *", Duration::from_millis(15), "127.0.0.1:4000");
+    let address = start_dashboard(&store, &Lifecycle::new());
+
+    let (_, slowlog) = get(&address, "/api/slowlog");
+    assert!(slowlog.contains("\"duration_us\":15000"));
+    assert!(slowlog.contains("\"command\":\"KEYS *\",\"tag\":null"));
+}
+
+#[test]
+fn test_metrics_endpoint() {
+    let store = Store::new();
+    store.latency().set_buckets(vec![1000, 10_000]);
+    store.latency().record(CommandFamily::of(&["HGET", "h", "f"]), Duration::from_micros(400));
+    store.latency().record(CommandFamily::of(&["SET", "k", "v"]), Duration::from_millis(20));
+    let address = start_dashboard(&store, &Lifecycle::new());
+
+    let (status, metrics) = get(&address, "/metrics");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(metrics.starts_with("# HELP medusa_command_duration_seconds "));
+    assert!(metrics.contains("\nmedusa_command_duration_seconds_bucket{family=\"collection_read\",le=\"0.001\"} 1\n"));
+    assert!(metrics.contains("\nmedusa_command_duration_seconds_bucket{family=\"set\",le=\"0.01\"} 0\n"));
+    assert!(metrics.contains("\nmedusa_command_duration_seconds_bucket{family=\"set\",le=\"+Inf\"} 1\n"));
+    assert!(metrics.contains("\nmedusa_command_duration_seconds_sum{family=\"set\"} 0.02\n"));
+    assert!(metrics.contains("\nmedusa_command_duration_seconds_count{family=\"blocking\"} 0\n"));
+}
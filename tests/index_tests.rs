@@ -0,0 +1,69 @@
+use medusa::store::Store;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_index_maintained_on_write() {
+    let store = Store::new();
+
+    assert!(store.hset("user:1", "email", "john@example.com").unwrap());
+    assert!(store.create_index("by_email", "user:*", "email").unwrap());
+    assert!(!store.create_index("by_email", "user:*", "email").unwrap()); // Already exists
+
+    // Existing data is indexed on creation
+    assert_eq!(store.find("by_email", "john@example.com").unwrap(), vec!["user:1"]);
+
+    // New writes are indexed
+    assert!(store.hset("user:2", "email", "jane@example.com").unwrap());
+    assert!(store.hset("user:3", "email", "jane@example.com").unwrap());
+    assert_eq!(store.find("by_email", "jane@example.com").unwrap(), vec!["user:2", "user:3"]);
+
+    // Keys outside the pattern are ignored
+    assert!(store.hset("admin:1", "email", "jane@example.com").unwrap());
+    assert_eq!(store.find("by_email", "jane@example.com").unwrap().len(), 2);
+
+    // Updates move the key to the new value
+    assert!(!store.hset("user:3", "email", "jim@example.com").unwrap());
+    assert_eq!(store.find("by_email", "jane@example.com").unwrap(), vec!["user:2"]);
+    assert_eq!(store.find("by_email", "jim@example.com").unwrap(), vec!["user:3"]);
+
+    // Field deletes, key deletes and overwrites drop the entry
+    assert!(store.hdel("user:3", "email").unwrap());
+    assert!(store.find("by_email", "jim@example.com").unwrap().is_empty());
+    assert!(store.delete("user:2").unwrap().is_some());
+    assert!(store.find("by_email", "jane@example.com").unwrap().is_empty());
+    assert!(store.set("user:1", "plain").is_ok());
+    assert!(store.find("by_email", "john@example.com").unwrap().is_empty());
+
+    assert!(store.find("missing", "value").is_err());
+}
+
+#[test]
+fn test_index_ignores_expired_keys() {
+    let store = Store::new();
+
+    assert!(store.create_index("by_status", "job:*", "status").unwrap());
+    assert!(store.hset("job:1", "status", "queued").unwrap());
+    assert!(store.hset("job:2", "status", "queued").unwrap());
+    assert!(store.expire("job:1", 1).unwrap());
+
+    thread::sleep(Duration::from_millis(1100));
+
+    assert_eq!(store.find("by_status", "queued").unwrap(), vec!["job:2"]);
+}
+
+#[test]
+fn test_drop_and_list_indexes() {
+    let store = Store::new();
+
+    assert!(store.create_index("a", "user:*", "email").unwrap());
+    assert!(store.create_index("b", "order:*", "status").unwrap());
+
+    let indexes = store.list_indexes().unwrap();
+    assert_eq!(indexes.len(), 2);
+    assert_eq!(indexes[0], ("a".to_string(), "user:*".to_string(), "email".to_string()));
+
+    assert!(store.drop_index("a").unwrap());
+    assert!(!store.drop_index("a").unwrap());
+    assert_eq!(store.list_indexes().unwrap().len(), 1);
+}
@@ -1,25 +1,19 @@
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
-use std::sync::atomic::{AtomicU16, Ordering};
+use medusa::resp::{self, Frame};
+use medusa::crdt::CrdtConfig;
+use medusa::extension::CommandRegistry;
+use medusa::reply::Reply;
+use medusa::sentinel::SentinelConfig;
+use medusa::server::{self, ServerConfig};
+use medusa::store::Value;
+use medusa::testing::{TestClient, TestServer};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-static PORT_COUNTER: AtomicU16 = AtomicU16::new(12312);
-
-fn start_test_server() -> u16 {
-    let port = PORT_COUNTER.fetch_add(1, Ordering::SeqCst);
-    thread::spawn(move || {
-        let config = medusa::server::ServerConfig {
-            host: "127.0.0.1".to_string(),
-            port,
-            max_connections: 10,
-            connection_timeout: Duration::from_secs(5),
-            enable_timeouts: false,
-        };
-        medusa::server::start_server_with_config(config);
-    });
-    thread::sleep(Duration::from_millis(200)); // Give more time for server to start
-    port
+// A port nothing is listening on, for servers that bind their own address
+fn unused_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
 }
 
 fn send_command(port: u16, command: &str) -> Result<String, Box<dyn std::error::Error>> {
@@ -28,14 +22,15 @@ fn send_command(port: u16, command: &str) -> Result<String, Box<dyn std::error::
     
     let mut reader = BufReader::new(stream.try_clone()?);
     
+    // Send command before the welcome message arrives, so the server
+    // does not wait to see which protocol we speak
+    stream.write_all(format!("{}\n", command).as_bytes())?;
+    stream.flush()?;
+    
     // Read welcome message
     let mut welcome = String::new();
     reader.read_line(&mut welcome)?;
     
-    // Send command
-    stream.write_all(format!("{}\n", command).as_bytes())?;
-    stream.flush()?;
-    
     // Read response
     let mut response = String::new();
     reader.read_line(&mut response)?;
@@ -45,7 +40,8 @@ fn send_command(port: u16, command: &str) -> Result<String, Box<dyn std::error::
 
 #[test]
 fn test_basic_operations() {
-    let port = start_test_server();
+    let server = TestServer::start().unwrap();
+    let port = server.port();
     
     let response = send_command(port, "SET test_key test_value").unwrap();
     assert!(response.contains("OK"));
@@ -62,9 +58,10 @@ fn test_basic_operations() {
 
 #[test]
 fn test_ttl_operations() {
-    let port = start_test_server();
+    let server = TestServer::start().unwrap();
+    let port = server.port();
     
-    let response = send_command(port, "SET ttl_key ttl_value 1").unwrap();
+    let response = send_command(port, "SET ttl_key ttl_value EX 1").unwrap();
     assert!(response.contains("OK"));
     
     let response = send_command(port, "TTL ttl_key").unwrap();
@@ -78,7 +75,8 @@ fn test_ttl_operations() {
 
 #[test]
 fn test_pattern_matching() {
-    let port = start_test_server();
+    let server = TestServer::start().unwrap();
+    let port = server.port();
     
     send_command(port, "SET user:1 john").unwrap();
     send_command(port, "SET user:2 jane").unwrap();
@@ -92,7 +90,8 @@ fn test_pattern_matching() {
 
 #[test]
 fn test_connection_resilience() {
-    let port = start_test_server();
+    let server = TestServer::start().unwrap();
+    let port = server.port();
     
     // First connection - set a value and disconnect abruptly
     {
@@ -121,7 +120,8 @@ fn test_connection_resilience() {
 
 #[test]
 fn test_concurrent_connections() {
-    let port = start_test_server();
+    let server = TestServer::start().unwrap();
+    let port = server.port();
     let mut handles = vec![];
     
     for i in 0..5 {
@@ -139,4 +139,1910 @@ fn test_concurrent_connections() {
     for handle in handles {
         handle.join().unwrap();
     }
-}
\ No newline at end of file
+}
+#[test]
+fn test_connection_draining() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    // Long-lived client that negotiates server notices
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+
+    stream.write_all(b"CLIENT NOTICES ON\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.contains("OK"));
+
+    // A grace period too long to be a deadline is refused without draining
+    assert!(send_command(port, "DRAIN 18446744073709551615").unwrap().starts_with("ERROR"));
+
+    let response = send_command(port, "DRAIN 5").unwrap();
+    assert!(response.contains("Draining"));
+
+    // New connections are turned away
+    let mut rejected = BufReader::new(TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap());
+    line.clear();
+    rejected.read_line(&mut line).unwrap();
+    assert!(line.contains("draining"));
+
+    // The existing client keeps working and is told the server is closing
+    stream.write_all(b"SET drain_key value\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("NOTICE"));
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("OK"));
+}
+
+#[test]
+fn test_fault_injection() {
+    // Disabled unless the server opts in
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+    let response = send_command(port, "DEBUG INJECT DROP 100").unwrap();
+    assert!(response.starts_with("ERROR"));
+
+    let server = TestServer::with_config(ServerConfig { enable_fault_injection: true, ..Default::default() }).unwrap();
+    let port = server.port();
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+
+    stream.write_all(b"DEBUG INJECT DROP 100\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("OK"));
+
+    // The next regular command closes the connection without a reply
+    stream.write_all(b"PING\n").unwrap();
+    line.clear();
+    assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+
+    let response = send_command(port, "DEBUG INJECT CLEAR").unwrap();
+    assert!(response.starts_with("OK"));
+    assert_eq!(send_command(port, "PING").unwrap(), "PONG\n");
+}
+
+#[test]
+fn test_slowlog_and_client_list() {
+    let server = TestServer::with_config(ServerConfig { slowlog_threshold: Duration::ZERO, ..Default::default() }).unwrap();
+    let port = server.port();
+
+    send_command(port, "SET slow_key value").unwrap();
+    let response = send_command(port, "SLOWLOG LEN").unwrap();
+    assert_eq!(response, "OK: 1\n");
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+
+    stream.write_all(b"SLOWLOG GET 1\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "OK: 1 slow commands:\n");
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.contains("SLOWLOG LEN"));
+
+    stream.write_all(b"CLIENT LIST\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "OK: 1 clients:\n");
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.contains("commands=1 last=SLOWLOG"));
+
+    stream.write_all(b"SLOWLOG RESET\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("OK"));
+}
+
+#[test]
+fn test_help() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+
+    stream.write_all(b"HELP client\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "OK: Help for CLIENT:\n");
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "  CLIENT NOTICES ON|OFF\n");
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.contains("(since 0.1.0)"));
+
+    let response = send_command(port, "HELP NOPE").unwrap();
+    assert!(response.starts_with("ERROR"));
+}
+
+#[test]
+fn test_command_getkeys() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    assert_eq!(send_command(port, "COMMAND GETKEYS SET foo bar").unwrap(), "OK: Keys: foo\n");
+    assert_eq!(send_command(port, "COMMAND GETKEYS FT.ADD docs doc:1").unwrap(), "OK: Keys: doc:1\n");
+    assert!(send_command(port, "COMMAND GETKEYS KEYS user:*").unwrap().contains("no key arguments"));
+    assert!(send_command(port, "COMMAND GETKEYS GET").unwrap().contains("Invalid number of arguments"));
+    assert!(send_command(port, "COMMAND GETKEYS NOPE foo").unwrap().contains("Unknown command"));
+    assert_eq!(send_command(port, "COMMAND INFO get").unwrap(), "OK: GET kind=read first_key=1 last_key=1 key_step=1\n");
+    assert_eq!(send_command(port, "COMMAND INFO CLIENT KILL").unwrap(), "OK: CLIENT KILL kind=admin first_key=0 last_key=0 key_step=0\n");
+    assert!(send_command(port, "COMMAND INFO WAITKEY").unwrap().contains(" kind=blocking "));
+    assert!(send_command(port, "COMMAND INFO NOPE").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_max_keys() {
+    let server = TestServer::with_config(ServerConfig { max_keys: 2, ..Default::default() }).unwrap();
+    let port = server.port();
+
+    assert!(send_command(port, "SET a 1").unwrap().starts_with("OK"));
+    assert!(send_command(port, "HSET b f v").unwrap().starts_with("OK"));
+    assert_eq!(
+        send_command(port, "SET c 1").unwrap(),
+        "ERROR: MAXKEYS Key limit of 2 reached, only existing keys can be written\n"
+    );
+    // Updates and deletes still go ahead, and a delete makes room
+    assert!(send_command(port, "SET a 2").unwrap().starts_with("OK"));
+    assert!(send_command(port, "HSET b g w").unwrap().starts_with("OK"));
+    assert!(send_command(port, "DELETE a").unwrap().starts_with("OK"));
+    assert!(send_command(port, "SET c 1").unwrap().starts_with("OK"));
+    assert!(server.command("INFO").unwrap().contains("\nmaxkeys:2\n"));
+}
+
+#[test]
+fn test_pinned_keys() {
+    let server = TestServer::with_config(ServerConfig { pinned_keys: vec!["config:*".to_string()], ..Default::default() }).unwrap();
+    let port = server.port();
+
+    assert_eq!(send_command(port, "PIN settings").unwrap(), "TRUE: Pinned 'settings'\n");
+    assert_eq!(send_command(port, "PIN settings").unwrap(), "FALSE: 'settings' is already pinned\n");
+    assert_eq!(server.command("PINNED").unwrap(), "OK: 2 pinned:\n  config:*\n  settings\n");
+    assert_eq!(send_command(port, "UNPIN config:*").unwrap(), "TRUE: Unpinned 'config:*'\n");
+    assert_eq!(send_command(port, "UNPIN config:*").unwrap(), "FALSE: 'config:*' is not pinned\n");
+    assert!(send_command(port, "PIN").unwrap().starts_with("ERROR: PIN requires a key or pattern"));
+}
+
+#[test]
+fn test_config_help() {
+    let server = TestServer::start().unwrap();
+
+    let reply = server.command("CONFIG HELP").unwrap();
+    assert!(reply.starts_with("OK: 57 settings:\n"));
+    assert!(reply.contains("\n  MEDUSA_PORT integer default=2312 startup - Port to listen on\n"));
+    assert!(reply.contains("\n  MEDUSA_ALARMS list default=(none) runtime (ALARMS SET) - "));
+
+    assert_eq!(
+        server.command("CONFIG HELP maxmemory_policy").unwrap(),
+        "OK: MEDUSA_MAXMEMORY_POLICY enum(noeviction|allkeys-lru|volatile-lru) default=noeviction startup - What a write over MEDUSA_MAXMEMORY evicts\n"
+    );
+    assert!(server.command("CONFIG HELP NOPE").unwrap().starts_with("ERROR: Unknown setting"));
+    assert!(server.command("CONFIG").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_alarms() {
+    let server = TestServer::with_config(ServerConfig { alarm_thresholds: vec![("keys", 1)], ..Default::default() }).unwrap();
+    let port = server.port();
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    stream.write_all(b"CLIENT NOTICES ON\n").unwrap();
+    reader.read_line(&mut line).unwrap();
+
+    assert_eq!(send_command(port, "ALARMS").unwrap(), "OK: No active alarms\n");
+    send_command(port, "SET alarm:1 a").unwrap();
+    send_command(port, "SET alarm:2 b").unwrap();
+    thread::sleep(Duration::from_millis(1500));
+
+    assert_eq!(send_command(port, "ALARMS").unwrap(), "OK: 1 active alarms:\n");
+
+    // The notice-enabled client hears about it before its next reply
+    stream.write_all(b"PING\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "NOTICE: Alarm raised: keys is 2 (threshold 1)\n");
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "PONG\n");
+
+    assert!(send_command(port, "ALARMS SET keys OFF").unwrap().starts_with("OK"));
+    assert_eq!(send_command(port, "ALARMS").unwrap(), "OK: No active alarms\n");
+    assert!(send_command(port, "ALARMS SET disk 5").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_waitkey() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    assert_eq!(send_command(port, "WAITKEY config:ready 0.2").unwrap(), "FALSE: Timed out waiting for 'config:ready'\n");
+
+    let waiter = thread::spawn(move || send_command(port, "WAITKEY config:ready 5").unwrap());
+    thread::sleep(Duration::from_millis(200));
+    send_command(port, "SET config:ready yes").unwrap();
+    assert_eq!(waiter.join().unwrap(), "TRUE: Key 'config:ready' exists\n");
+
+    // Already there: returns at once, unless waiting for the next change
+    assert!(send_command(port, "WAITKEY config:ready 5").unwrap().starts_with("TRUE"));
+    let waiter = thread::spawn(move || send_command(port, "WAITKEY config:ready 5 CHANGE").unwrap());
+    thread::sleep(Duration::from_millis(200));
+    send_command(port, "DELETE config:ready").unwrap();
+    assert_eq!(waiter.join().unwrap(), "TRUE: Key 'config:ready' changed\n");
+}
+
+#[test]
+fn test_command_aliases() {
+    let server = TestServer::with_config(ServerConfig {
+        aliases: vec![("SESSIONS".to_string(), "KEYS session:*".to_string())],
+        ..Default::default()
+    })
+    .unwrap();
+    let port = server.port();
+
+    send_command(port, "SET session:1 a").unwrap();
+    send_command(port, "SET user:1 b").unwrap();
+    let response = send_command(port, "sessions").unwrap();
+    assert!(response.contains("session:1"));
+    assert!(!response.contains("user:1"));
+
+    // Runtime aliases get extra arguments appended
+    assert!(send_command(port, "ALIAS SET NAME HGET").unwrap().starts_with("OK"));
+    send_command(port, "HSET user:2 name Ann").unwrap();
+    assert!(send_command(port, "NAME user:2 name").unwrap().contains("Ann"));
+
+    assert!(send_command(port, "ALIAS SET GET KEYS *").unwrap().starts_with("ERROR"));
+    assert!(send_command(port, "ALIAS LIST").unwrap().contains("SESSIONS -> KEYS session:*"));
+    assert!(send_command(port, "ALIAS DEL name").unwrap().starts_with("OK"));
+    assert!(send_command(port, "NAME user:2 name").unwrap().contains("Unknown command"));
+}
+
+#[test]
+fn test_custom_commands() {
+    let commands = CommandRegistry::new();
+    commands
+        .register("INCRBY2", |args, store| {
+            let [key] = args else { return Err("INCRBY2 requires a key (INCRBY2 key)".to_string()) };
+            let value = store.update(key, |current| {
+                let n = match current {
+                    Some(Value::String(s)) => s.parse::<i64>().unwrap_or(0),
+                    _ => 0,
+                };
+                Some(Value::new((n + 2).to_string()))
+            })?;
+            match value {
+                Some(Value::String(n)) => Ok(Reply::text(n)),
+                _ => Err("Counter vanished".to_string()),
+            }
+        })
+        .unwrap();
+    let server = TestServer::with_config(ServerConfig { commands: commands.clone(), ..Default::default() }).unwrap();
+    let mut client = server.connect().unwrap();
+
+    assert_eq!(client.command("incrby2 hits").unwrap(), "OK: 2\n");
+    assert_eq!(client.command("INCRBY2 hits").unwrap(), "OK: 4\n");
+    assert_eq!(client.command("GET hits").unwrap(), "OK: 'hits' = 4\n");
+    assert_eq!(client.command("INCRBY2").unwrap(), "ERROR: INCRBY2 requires a key (INCRBY2 key)\n");
+
+    // Custom commands can be queued in a transaction
+    assert!(client.command("MULTI").unwrap().starts_with("OK"));
+    assert!(client.command("INCRBY2 hits").unwrap().starts_with("QUEUED"));
+    assert!(client.command("EXEC").unwrap().contains("OK: 6"));
+
+    // Commands registered while the server runs are picked up
+    assert!(client.command("HELLOWORLD").unwrap().starts_with("ERROR: Unknown command"));
+    commands.register("HELLOWORLD", |_, _| Ok(Reply::ok("Hello"))).unwrap();
+    assert_eq!(client.command("HELLOWORLD").unwrap(), "OK: Hello\n");
+}
+
+#[test]
+fn test_pubsub() {
+    let server = TestServer::start().unwrap();
+    let mut subscriber = server.connect().unwrap();
+    let mut other = server.connect().unwrap();
+    let mut publisher = server.connect().unwrap();
+
+    assert_eq!(subscriber.command("SUBSCRIBE news alerts").unwrap(), "OK: Subscribed to 2 channels:\n  news 1\n  alerts 2\n");
+    assert!(other.command("SUBSCRIBE news").unwrap().starts_with("OK"));
+    assert_eq!(publisher.command("PUBLISH news hello  world").unwrap(), "OK: 2\n");
+    assert_eq!(publisher.command("PUBLISH alerts disk full").unwrap(), "OK: 1\n");
+    assert_eq!(publisher.command("PUBLISH sports goal").unwrap(), "OK: 0\n");
+    assert_eq!(subscriber.receive().unwrap(), "MESSAGE: news hello world\n");
+    assert_eq!(subscriber.receive().unwrap(), "MESSAGE: alerts disk full\n");
+    assert_eq!(other.receive().unwrap(), "MESSAGE: news hello world\n");
+
+    // Subscribed connections are limited to the subscription commands
+    assert!(subscriber.command("GET k").unwrap().starts_with("ERROR: Only SUBSCRIBE, UNSUBSCRIBE, PING and QUIT"));
+    assert_eq!(subscriber.command("PING").unwrap(), "PONG\n");
+    assert_eq!(subscriber.command("UNSUBSCRIBE news").unwrap(), "OK: Unsubscribed from 1 channels:\n  news 1\n");
+    assert_eq!(publisher.command("PUBLISH news again").unwrap(), "OK: 1\n");
+    assert_eq!(subscriber.command("UNSUBSCRIBE").unwrap(), "OK: Unsubscribed from 1 channels:\n  alerts 0\n");
+    assert!(subscriber.command("GET k").unwrap().starts_with("NULL"));
+
+    // A closed connection stops counting as a subscriber
+    drop(other);
+    wait_for_reply(&mut publisher, "PUBLISH news anyone", "OK: 0\n");
+    assert!(publisher.command("INFO").unwrap().contains("pubsub_channels:0"));
+}
+
+#[test]
+fn test_pubsub_over_resp() {
+    let server = TestServer::start().unwrap();
+    // The main port detects a Redis client from its first request
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    stream.write_all(b"*3\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n$1\r\nb\r\n").unwrap();
+    let bulk = |value: &str| Frame::Bulk(Some(value.to_string()));
+    let confirm = |channel, count| Frame::Array(vec![bulk("subscribe"), bulk(channel), Frame::Integer(count)]);
+    assert_eq!(resp::read_frame(&mut reader).unwrap(), confirm("a", 1));
+    assert_eq!(resp::read_frame(&mut reader).unwrap(), confirm("b", 2));
+
+    assert_eq!(server.command("PUBLISH b hi there").unwrap(), "OK: 1\n");
+    assert_eq!(resp::read_frame(&mut reader).unwrap(), Frame::Array(vec![bulk("message"), bulk("b"), bulk("hi there")]));
+}
+
+#[test]
+fn test_lcs() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    send_command(port, "SET lcs:a ohmytext").unwrap();
+    send_command(port, "SET lcs:b mynewtext").unwrap();
+    assert_eq!(send_command(port, "LCS lcs:a lcs:b").unwrap(), "OK: LCS of 'lcs:a' and 'lcs:b' = 'mytext'\n");
+    assert_eq!(send_command(port, "LCS lcs:a lcs:b LEN").unwrap(), "OK: 6\n");
+    assert_eq!(send_command(port, "LCS lcs:a lcs:b IDX").unwrap(), "OK: LCS length 6, 2 matches:\n");
+    assert_eq!(send_command(port, "LCS lcs:a lcs:b IDX MINMATCHLEN 4").unwrap(), "OK: LCS length 6, 1 matches:\n");
+
+    // A missing key is an empty string
+    assert_eq!(send_command(port, "LCS lcs:a lcs:missing LEN").unwrap(), "OK: 0\n");
+    send_command(port, "HSET lcs:hash f v").unwrap();
+    assert!(send_command(port, "LCS lcs:a lcs:hash").unwrap().starts_with("ERROR"));
+    assert!(send_command(port, "LCS lcs:a lcs:b LEN IDX").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_dbstats() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    assert_eq!(send_command(port, "DBSTATS").unwrap(), "OK: No keys to sample\n");
+    send_command(port, "SET dbstats:a 1").unwrap();
+    send_command(port, "SETEX dbstats:b 60 2").unwrap();
+    assert_eq!(send_command(port, "DBSTATS SAMPLES 0").unwrap(), "OK: Sampled 2 of 2 keys:\n");
+    assert!(send_command(port, "DBSTATS SAMPLES x").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_string_range_commands() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    send_command(port, "SET range:k héllo").unwrap();
+    assert_eq!(send_command(port, "STRLEN range:k").unwrap(), "OK: 'range:k' is 6 bytes\n");
+    assert_eq!(send_command(port, "STRLEN range:k UTF8").unwrap(), "OK: 'range:k' is 5 characters\n");
+    assert_eq!(send_command(port, "GETRANGE range:k -3 -1").unwrap(), "OK: 'range:k' bytes -3 to -1 = llo\n");
+    assert_eq!(send_command(port, "SUBSTR range:k 1 1 UTF8").unwrap(), "OK: 'range:k' characters 1 to 1 = é\n");
+    assert!(send_command(port, "GETRANGE range:k 0 1").unwrap().starts_with("ERROR"));
+    assert_eq!(send_command(port, "SETRANGE range:k 1 e UTF8").unwrap(), "OK: 'range:k' is now 5 characters\n");
+    // A lone UTF8 is the value, not the flag
+    assert_eq!(send_command(port, "SETRANGE range:k 5 UTF8").unwrap(), "OK: 'range:k' is now 9 bytes\n");
+    assert_eq!(send_command(port, "GET range:k").unwrap(), "OK: 'range:k' = helloUTF8\n");
+}
+
+#[test]
+fn test_set_commands() {
+    let server = TestServer::start().unwrap();
+    let mut client = server.connect().unwrap();
+
+    assert_eq!(client.command("SADD visitors ann bob ann").unwrap(), "OK: Added 2 new members to set 'visitors'\n");
+    assert_eq!(client.command("SADD visitors cy").unwrap(), "OK: Added 1 new members to set 'visitors'\n");
+    assert_eq!(client.command("SCARD visitors").unwrap(), "OK: Set 'visitors' has 3 members\n");
+    assert_eq!(client.command("SMEMBERS visitors").unwrap(), "OK: Set 'visitors' has 3 members:\n  ann\n  bob\n  cy\n");
+    assert_eq!(client.command("SISMEMBER visitors bob").unwrap(), "TRUE: 'bob' is a member of set 'visitors'\n");
+    assert_eq!(client.command("SISMEMBER visitors dan").unwrap(), "FALSE: 'dan' is not a member of set 'visitors'\n");
+    assert!(client.command("SCAN 0 TYPE set").unwrap().contains("  visitors\n"));
+
+    assert_eq!(client.command("SREM visitors ann dan").unwrap(), "OK: Removed 1 members from set 'visitors'\n");
+    assert_eq!(client.command("SREM visitors bob cy").unwrap(), "OK: Removed 2 members from set 'visitors'\n");
+    assert_eq!(client.command("SMEMBERS visitors").unwrap(), "OK: Set 'visitors' is empty\n");
+    assert!(client.command("EXISTS visitors").unwrap().starts_with("FALSE"));
+
+    client.command("SET plain value").unwrap();
+    assert_eq!(client.command("SADD plain x").unwrap(), "ERROR: Failed to add to set: Key contains non-set value\n");
+    assert!(client.command("SADD visitors").unwrap().starts_with("ERROR: SADD requires key and at least one member"));
+}
+
+#[test]
+fn test_sorted_set_commands() {
+    let server = TestServer::start().unwrap();
+    let mut client = server.connect().unwrap();
+
+    assert_eq!(client.command("ZADD board 20 bob 12.5 ann 20 cy").unwrap(), "OK: Added 3 new members to sorted set 'board'\n");
+    assert_eq!(client.command("ZADD board 5 bob").unwrap(), "OK: Added 0 new members to sorted set 'board'\n");
+    assert_eq!(client.command("ZCARD board").unwrap(), "OK: Sorted set 'board' has 3 members\n");
+    assert_eq!(client.command("ZSCORE board ann").unwrap(), "OK: 'board:ann' = 12.5\n");
+    assert_eq!(client.command("ZSCORE board eve").unwrap(), "NULL: 'eve' is not in sorted set 'board'\n");
+    assert_eq!(client.command("ZRANK board cy").unwrap(), "OK: 2\n");
+    assert!(client.command("ZRANK board eve").unwrap().starts_with("NULL"));
+
+    assert_eq!(client.command("ZRANGE board 0 -1").unwrap(), "OK: Sorted set 'board' range [0, -1]:\n  bob\n  ann\n  cy\n");
+    assert_eq!(client.command("ZRANGE board -2 -1 WITHSCORES").unwrap(), "OK: Sorted set 'board' range [-2, -1]:\n  ann\n  12.5\n  cy\n  20\n");
+    assert_eq!(client.command("ZRANGE board 5 9").unwrap(), "OK: No members in range [5, 9] for sorted set 'board'\n");
+
+    assert_eq!(client.command("ZADD board x ann").unwrap(), "ERROR: Invalid score 'x'\n");
+    client.command("SET plain value").unwrap();
+    assert_eq!(client.command("ZCARD plain").unwrap(), "ERROR: Failed to get sorted set size: Key contains non-sorted-set value\n");
+}
+
+#[test]
+fn test_sorted_set_score_ranges() {
+    let server = TestServer::start().unwrap();
+    let mut client = server.connect().unwrap();
+
+    client.command("ZADD board 10 ann 20 bob 30 cy 40 dan").unwrap();
+    assert_eq!(client.command("ZRANGEBYSCORE board 20 30").unwrap(), "OK: Sorted set 'board' scores [20, 30]:\n  bob\n  cy\n");
+    assert_eq!(client.command("ZRANGEBYSCORE board (20 +inf WITHSCORES").unwrap(), "OK: Sorted set 'board' scores [(20, inf]:\n  cy\n  30\n  dan\n  40\n");
+    assert_eq!(client.command("ZRANGEBYSCORE board -inf +inf LIMIT 1 2").unwrap(), "OK: Sorted set 'board' scores [-inf, inf]:\n  bob\n  cy\n");
+    assert_eq!(client.command("ZRANGEBYSCORE board (10 (20").unwrap(), "OK: No members scored in [(10, (20] for sorted set 'board'\n");
+
+    // Leaderboard: bump a score, adding the member if it is new
+    assert_eq!(client.command("ZINCRBY board 25 ann").unwrap(), "OK: 'board:ann' = 35\n");
+    assert_eq!(client.command("ZINCRBY board 1.5 eve").unwrap(), "OK: 'board:eve' = 1.5\n");
+    assert_eq!(client.command("ZRANGE board -1 -1").unwrap(), "OK: Sorted set 'board' range [-1, -1]:\n  dan\n");
+    client.command("ZADD board inf top").unwrap();
+    assert_eq!(client.command("ZINCRBY board -inf top").unwrap(), "ERROR: Failed to increment score: Resulting score is not a number\n");
+
+    // Sliding window: drop everything older than a cutoff
+    assert_eq!(client.command("ZREMRANGEBYSCORE board -inf (30").unwrap(), "OK: Removed 2 members from sorted set 'board'\n");
+    assert_eq!(client.command("ZCARD board").unwrap(), "OK: Sorted set 'board' has 4 members\n");
+    assert_eq!(client.command("ZREMRANGEBYSCORE board -inf +inf").unwrap(), "OK: Removed 4 members from sorted set 'board'\n");
+    assert!(client.command("EXISTS board").unwrap().starts_with("FALSE"));
+
+    assert_eq!(client.command("ZRANGEBYSCORE board x 1").unwrap(), "ERROR: Invalid score bound 'x'\n");
+    client.command("SET plain value").unwrap();
+    assert!(client.command("ZINCRBY plain 1 x").unwrap().ends_with("Key contains non-sorted-set value\n"));
+}
+
+#[test]
+fn test_sorted_set_priority_queue() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+    let mut client = server.connect().unwrap();
+
+    client.command("ZADD jobs 3 low 1 urgent 2 normal 1 also-urgent").unwrap();
+    assert_eq!(client.command("ZPOPMIN jobs").unwrap(), "OK: Popped 1 members from sorted set 'jobs':\n  also-urgent\n  1\n");
+    assert_eq!(client.command("ZPOPMAX jobs 2").unwrap(), "OK: Popped 2 members from sorted set 'jobs':\n  low\n  3\n  normal\n  2\n");
+    assert_eq!(client.command("ZPOPMIN jobs 10").unwrap(), "OK: Popped 1 members from sorted set 'jobs':\n  urgent\n  1\n");
+    assert_eq!(client.command("ZPOPMIN jobs").unwrap(), "NULL: Sorted set 'jobs' is empty\n");
+    assert!(client.command("EXISTS jobs").unwrap().starts_with("FALSE"));
+
+    // Two workers wait; each job goes to exactly one of them
+    assert_eq!(client.command("BZPOPMIN jobs 0.2").unwrap(), "NULL: Timed out waiting for sorted set 'jobs'\n");
+    let workers: Vec<_> = (0..2).map(|_| thread::spawn(move || send_command(port, "BZPOPMIN jobs 5").unwrap())).collect();
+    thread::sleep(Duration::from_millis(200));
+    client.command("ZADD jobs 5 first").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    client.command("ZADD jobs 7 second").unwrap();
+    let mut replies: Vec<String> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+    replies.sort();
+    assert_eq!(replies, ["OK: Popped 'first' from sorted set 'jobs':\n", "OK: Popped 'second' from sorted set 'jobs':\n"]);
+
+    client.command("ZADD jobs 1 a 9 z").unwrap();
+    assert_eq!(client.command("BZPOPMAX jobs 1").unwrap(), "OK: Popped 'z' from sorted set 'jobs':\n  jobs\n  z\n  9\n");
+    // A huge timeout still waits, but one too large to be a deadline is refused
+    assert_eq!(client.command("BZPOPMIN jobs 1e15").unwrap(), "OK: Popped 'a' from sorted set 'jobs':\n  jobs\n  a\n  1\n");
+    for timeout in ["99999999999999999999", "1e19", "1e300"] {
+        assert_eq!(client.command(&format!("BZPOPMAX jobs {}", timeout)).unwrap(), "ERROR: Invalid timeout (seconds, 0 waits forever)\n");
+    }
+    client.command("SET plain value").unwrap();
+    assert!(client.command("BZPOPMIN plain 1").unwrap().ends_with("Key contains non-sorted-set value\n"));
+    assert_eq!(client.command("BZPOPMIN jobs soon").unwrap(), "ERROR: Invalid timeout (seconds, 0 waits forever)\n");
+}
+
+#[test]
+fn test_stream_commands() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+    let mut client = server.connect().unwrap();
+
+    assert_eq!(client.command("XADD events 1000-0 type click").unwrap(), "OK: Added entry to stream 'events': 1000-0\n");
+    assert_eq!(client.command("XADD events 1000-1 type view page home").unwrap(), "OK: Added entry to stream 'events': 1000-1\n");
+    assert_eq!(client.command("XADD events 999 type late").unwrap(), "ERROR: Failed to add to stream: ID must be greater than the stream's last ID 1000-1\n");
+    // Generated IDs come from the clock, so they are past the explicit ones
+    let reply = client.command("XADD events * type scroll").unwrap();
+    let id = reply.trim_end().rsplit(' ').next().unwrap().to_string();
+    assert!(id.split_once('-').unwrap().0.parse::<u64>().unwrap() > 1000);
+    assert_eq!(client.command("XLEN events").unwrap(), "OK: Stream 'events' has 3 entries\n");
+
+    assert_eq!(
+        client.command("XRANGE events - 1000").unwrap(),
+        "OK: Stream 'events' range [0-0, 1000-18446744073709551615]:\n  1000-0\n    type\n    click\n  1000-1\n    type\n    view\n    page\n    home\n"
+    );
+    assert_eq!(client.command("XRANGE events 1000-1 + COUNT 1").unwrap().lines().nth(1), Some("  1000-1"));
+    assert!(client.command("XRANGE events 5 6").unwrap().starts_with("OK: No entries in range"));
+
+    assert_eq!(
+        client.command("XREAD COUNT 1 STREAMS events missing 1000-0 0").unwrap(),
+        "OK: Read 1 entries from 1 streams:\n  events\n    1000-1\n      type\n      view\n      page\n      home\n"
+    );
+    assert_eq!(client.command(&format!("XREAD STREAMS events {}", id)).unwrap(), "NULL: No new entries\n");
+
+    // Tail the stream: a blocked reader wakes for the next entry
+    assert_eq!(client.command("XREAD BLOCK 200 STREAMS events $").unwrap(), "NULL: Timed out waiting for new entries\n");
+    let reader = thread::spawn(move || send_command(port, "XREAD BLOCK 5000 STREAMS other events $ $").unwrap());
+    thread::sleep(Duration::from_millis(200));
+    client.command("XADD events 999999999999999 type later").unwrap();
+    assert_eq!(reader.join().unwrap(), "OK: Read 1 entries from 1 streams:\n");
+
+    assert!(client.command("XREAD STREAMS events").unwrap().starts_with("ERROR: XREAD requires streams and IDs"));
+    assert_eq!(client.command("XREAD STREAMS events x").unwrap(), "ERROR: Invalid stream ID 'x'\n");
+    client.command("SET plain value").unwrap();
+    assert_eq!(client.command("XLEN plain").unwrap(), "ERROR: Failed to get stream length: Key contains non-stream value\n");
+}
+
+#[test]
+fn test_stream_trimming_and_consumer_groups() {
+    let server = TestServer::start().unwrap();
+    let mut client = server.connect().unwrap();
+
+    for ms in 1..=5 {
+        client.command(&format!("XADD jobs MAXLEN ~ 4 {} task t{}", ms, ms)).unwrap();
+    }
+    assert_eq!(client.command("XLEN jobs").unwrap(), "OK: Stream 'jobs' has 4 entries\n");
+    assert_eq!(client.command("XTRIM jobs MINID 3").unwrap(), "OK: Trimmed 1 entries from stream 'jobs'\n");
+    assert_eq!(client.command("XRANGE jobs - +").unwrap().lines().nth(1), Some("  3-0"));
+
+    assert_eq!(client.command("XGROUP CREATE jobs workers 0").unwrap(), "OK: Created consumer group 'workers' for stream 'jobs'\n");
+    assert_eq!(client.command("XGROUP CREATE jobs workers 0").unwrap(), "ERROR: Consumer group 'workers' already exists for stream 'jobs'\n");
+    assert!(client.command("XGROUP CREATE nothing workers $").unwrap().contains("MKSTREAM creates it"));
+    assert!(client.command("XGROUP CREATE audit readers $ MKSTREAM").unwrap().starts_with("OK"));
+
+    assert_eq!(
+        client.command("XREADGROUP GROUP workers ann COUNT 2 STREAMS jobs >").unwrap(),
+        "OK: Read 2 entries from 1 streams:\n  jobs\n    3-0\n      task\n      t3\n    4-0\n      task\n      t4\n"
+    );
+    assert!(client.command("XREADGROUP GROUP workers bob STREAMS jobs >").unwrap().contains("\n    5-0\n"));
+    assert_eq!(client.command("XREADGROUP GROUP workers bob STREAMS jobs >").unwrap(), "NULL: No new entries\n");
+    assert!(client.command("XREADGROUP GROUP nobody bob STREAMS jobs >").unwrap().starts_with("ERROR"));
+
+    assert_eq!(client.command("XACK jobs workers 3-0 9-0").unwrap(), "OK: Acknowledged 1 entries in group 'workers' of stream 'jobs'\n");
+    assert_eq!(client.command("XPENDING jobs workers").unwrap(), "OK: Group 'workers' of stream 'jobs' has 2 pending entries, 4-0 to 5-0:\n  ann\n  1\n  bob\n  1\n");
+    let pending = client.command("XPENDING jobs workers - + 10 ann").unwrap();
+    assert!(pending.starts_with("OK: 1 pending entries in group 'workers' of stream 'jobs':\n  4-0\n    consumer\n    ann\n    idle_ms\n"), "{}", pending);
+    assert!(pending.ends_with("    deliveries\n    1\n"));
+    assert!(client.command("XPENDING jobs workers IDLE 60000 - + 10").unwrap().starts_with("OK: No pending entries"));
+
+    // ann stalls: cy claims what has been idle long enough, one at a time
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(
+        client.command("XAUTOCLAIM jobs workers cy 50 0 COUNT 1").unwrap(),
+        "OK: Claimed 1 entries for 'cy' in group 'workers' of stream 'jobs' (0 deleted), next 5-0:\n  4-0\n    task\n    t4\n"
+    );
+    client.command("XTRIM jobs MAXLEN 0").unwrap();
+    assert_eq!(client.command("XAUTOCLAIM jobs workers cy 50 5-0").unwrap(), "OK: Claimed 0 entries for 'cy' in group 'workers' of stream 'jobs' (1 deleted), next 0-0:\n");
+    assert!(client.command("XPENDING jobs workers").unwrap().ends_with("4-0 to 4-0:\n  cy\n  1\n"));
+    assert!(client.command("XPENDING jobs workers - + 10").unwrap().ends_with("    deliveries\n    2\n"));
+
+    assert!(client.command("XGROUP DESTROY jobs workers").unwrap().starts_with("TRUE"));
+    assert!(client.command("XPENDING jobs workers").unwrap().starts_with("ERROR: Failed to get pending entries: No consumer group 'workers'"));
+}
+
+#[test]
+fn test_compare_and_swap() {
+    let server = TestServer::start().unwrap();
+    let mut client = server.connect().unwrap();
+
+    client.command("SETEX state 100 idle").unwrap();
+    assert_eq!(client.command("CAS state idle running").unwrap(), "TRUE: 'state' swapped\n");
+    assert_eq!(client.command("CAS state idle stopped").unwrap(), "FALSE: 'state' does not hold 'idle'\n");
+    assert_eq!(client.command("GET state").unwrap(), "OK: 'state' = running\n");
+    // The TTL is kept
+    assert!(client.command("TTL state").unwrap().starts_with("TTL: Key 'state' expires in"));
+
+    assert!(client.command("CAS missing x y").unwrap().starts_with("FALSE"));
+    assert!(client.command("EXISTS missing").unwrap().starts_with("FALSE"));
+    client.command("RPUSH list a").unwrap();
+    assert!(client.command("CAS list a b").unwrap().starts_with("ERROR: Failed to compare and swap: Key contains non-string value"));
+    assert!(client.command("CAS state running").unwrap().starts_with("ERROR: CAS requires"));
+    assert_eq!(client.command("CAS state running done for now").unwrap(), "TRUE: 'state' swapped\n");
+    assert_eq!(client.command("GET state").unwrap(), "OK: 'state' = done for now\n");
+}
+
+#[test]
+fn test_flush_protection() {
+    let server = TestServer::with_config(ServerConfig { flush_token: Some("s3cret".to_string()), ..Default::default() }).unwrap();
+    let port = server.port();
+
+    send_command(port, "SET keep me").unwrap();
+    assert!(send_command(port, "FLUSHALL").unwrap().starts_with("ERROR: Flushing is protected"));
+    assert!(send_command(port, "CLEAR").unwrap().starts_with("ERROR"));
+    assert_eq!(send_command(port, "FLUSHALL CONFIRM wrong").unwrap(), "ERROR: Wrong confirmation token\n");
+    assert_eq!(send_command(port, "GET keep").unwrap(), "OK: 'keep' = me\n");
+    assert_eq!(send_command(port, "FLUSHALL CONFIRM s3cret").unwrap(), "OK: All entries cleared\n");
+    assert!(send_command(port, "GET keep").unwrap().starts_with("NULL"));
+}
+
+#[test]
+fn test_scan_command() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    send_command(port, "SET scan:a 1").unwrap();
+    send_command(port, "SET scan:b 2").unwrap();
+    send_command(port, "HSET other f v").unwrap();
+    assert_eq!(send_command(port, "SCAN 0 MATCH scan:* COUNT 100").unwrap(), "OK: Cursor 0, 2 keys:\n");
+    assert_eq!(send_command(port, "SCAN 0 TYPE hash").unwrap(), "OK: Cursor 0, 1 keys:\n");
+    assert!(send_command(port, "SCAN 0 COUNT 0").unwrap().starts_with("ERROR"));
+    assert!(send_command(port, "SCAN").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_request_tags() {
+    let server = TestServer::with_config(ServerConfig { slowlog_threshold: Duration::ZERO, ..Default::default() }).unwrap();
+    let port = server.port();
+
+    // The tag is stripped before the command runs
+    assert_eq!(send_command(port, "*TAG req-7 SET tagged v").unwrap(), "OK: Set 'tagged' = 'v'\n");
+    assert!(send_command(port, "*TAG req-8").unwrap().starts_with("ERROR"));
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    stream.write_all(b"SLOWLOG GET 10\n").unwrap();
+    let mut log = String::new();
+    for _ in 0..2 {
+        reader.read_line(&mut log).unwrap();
+    }
+    assert!(log.ends_with(" tag=req-7 SET tagged v\n"), "{}", log);
+}
+
+#[test]
+fn test_setex_and_numeric_values() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    // A trailing number is part of the value, not a TTL
+    assert_eq!(send_command(port, "SET version 1 2").unwrap(), "OK: Set 'version' = '1 2'\n");
+    assert!(send_command(port, "TTL version").unwrap().starts_with("NULL"));
+    assert_eq!(send_command(port, "SET count 42").unwrap(), "OK: Set 'count' = '42'\n");
+
+    assert_eq!(send_command(port, "SETEX session 60 user 7").unwrap(), "OK: Set 'session' = 'user 7' with TTL 60s\n");
+    assert!(send_command(port, "TTL session").unwrap().contains("expires in"));
+    assert_eq!(send_command(port, "SET lock owner PX 1500").unwrap(), "OK: Set 'lock' = 'owner' with TTL 1500ms\n");
+
+    assert_eq!(send_command(port, "PSETEX flash 100 now").unwrap(), "OK: Set 'flash' = 'now' with TTL 100ms\n");
+    thread::sleep(Duration::from_millis(200));
+    assert!(send_command(port, "GET flash").unwrap().starts_with("NULL"));
+
+    assert!(send_command(port, "SETEX bad 0 value").unwrap().starts_with("ERROR"));
+    assert!(send_command(port, "SET bad value EX soon").unwrap().starts_with("ERROR"));
+    // A TTL too far off to be a deadline is refused, and the store stays usable
+    assert!(send_command(port, "SETEX bad 18446744073709551615 value").unwrap().starts_with("ERROR"));
+    assert!(send_command(port, "SET bad value EX 18446744073709551615").unwrap().starts_with("ERROR"));
+    assert!(send_command(port, "EXPIRE count 18446744073709551615").unwrap().starts_with("ERROR"));
+    assert_eq!(send_command(port, "GET count").unwrap(), "OK: 'count' = 42\n");
+}
+
+#[test]
+fn test_client_no_touch() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+    send_command(port, "SET report:1 data").unwrap();
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    for command in ["CLIENT NO-TOUCH ON", "CLIENT NO-EVICT ON"] {
+        stream.write_all(format!("{}\n", command).as_bytes()).unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("OK"));
+    }
+
+    thread::sleep(Duration::from_millis(1100));
+    // Reads from the no-touch connection leave the key looking idle
+    stream.write_all(b"GET report:1\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(send_command(port, "OBJECT IDLETIME report:1").unwrap(), "OK: 1\n");
+
+    send_command(port, "GET report:1").unwrap();
+    assert_eq!(send_command(port, "OBJECT IDLETIME report:1").unwrap(), "OK: 0\n");
+
+    stream.write_all(b"CLIENT LIST\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    let count: usize = line.trim_start_matches("OK: ").split(' ').next().unwrap().parse().unwrap();
+    let mut list = String::new();
+    for _ in 0..count {
+        reader.read_line(&mut list).unwrap();
+    }
+    assert!(list.contains("flags=eT"));
+    assert!(send_command(port, "CLIENT NO-EVICT maybe").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_chunked_transfer() {
+    let server = TestServer::with_config(ServerConfig { chunk_threshold: 8, ..Default::default() }).unwrap();
+    let port = server.port();
+
+    assert_eq!(send_command(port, "SETCHUNK big 0 abcdef").unwrap(), "OK: 'big' is now 6 bytes\n");
+    assert!(send_command(port, "GET big").unwrap().starts_with("OK"));
+    assert_eq!(send_command(port, "SETCHUNK big 6 ghijkl").unwrap(), "OK: 'big' is now 12 bytes\n");
+    assert!(send_command(port, "SETCHUNK big 0 ").unwrap().starts_with("ERROR"));
+
+    assert!(send_command(port, "GET big").unwrap().contains("read it with GETCHUNK"));
+    assert_eq!(send_command(port, "GETCHUNK big 0 8").unwrap(), "OK: 'big' bytes 0-8 of 12 = abcdefgh\n");
+    assert_eq!(send_command(port, "GETCHUNK big 8 8").unwrap(), "OK: 'big' bytes 8-12 of 12 = ijkl\n");
+}
+
+#[test]
+fn test_delayed_delivery() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+
+    send_command(port, &format!("ZADDDELAY jobs {} later", now + 400)).unwrap();
+    let response = send_command(port, &format!("ZADDDELAY jobs {} soon", now + 100)).unwrap();
+    assert_eq!(response, format!("OK: Scheduled for 'jobs' at {} (2 pending)\n", now + 100));
+    assert_eq!(send_command(port, "LLEN jobs").unwrap(), "OK: List 'jobs' has 0 items\n");
+
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(send_command(port, "DELAYED jobs").unwrap(), format!("OK: 1 items pending for 'jobs', next due at {}\n", now + 400));
+    assert!(send_command(port, "LPOP jobs").unwrap().contains("soon"));
+
+    thread::sleep(Duration::from_millis(300));
+    assert!(send_command(port, "LPOP jobs").unwrap().contains("later"));
+    assert!(send_command(port, "DELAYED jobs").unwrap().starts_with("OK: No items"));
+}
+
+#[test]
+fn test_client_unblock_and_kill() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    stream.write_all(b"CLIENT ID\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    let id = line.trim_start_matches("OK: ").trim().to_string();
+
+    assert_eq!(send_command(port, &format!("CLIENT UNBLOCK {}", id)).unwrap(), format!("FALSE: Client {} is not blocked\n", id));
+
+    stream.write_all(b"WAITKEY never:set 0\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(send_command(port, &format!("CLIENT UNBLOCK {} ERROR", id)).unwrap(), format!("TRUE: Client {} unblocked\n", id));
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("ERROR: Unblocked"));
+
+    // Killing a blocked client ends the wait and the connection
+    stream.write_all(b"WAITKEY never:set 0\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(send_command(port, &format!("CLIENT KILL ID {}", id)).unwrap(), "OK: Killed 1 clients\n");
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("ERROR: Unblocked"));
+    line.clear();
+    assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+
+    assert!(send_command(port, "CLIENT KILL NAME x").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_client_ephemeral_keys() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+    send_command(port, "SET shared before").unwrap();
+
+    let mut client = server.connect().unwrap();
+    assert!(client.command("SET before 1").unwrap().starts_with("OK"));
+    assert!(client.command("CLIENT EPHEMERAL ON").unwrap().starts_with("OK"));
+    assert!(client.command("SET presence:ann online").unwrap().starts_with("OK"));
+    assert!(client.command("HSET scratch:ann step 1").unwrap().starts_with("OK"));
+    assert!(client.command("RPUSH scratch:log a").unwrap().starts_with("OK"));
+    assert!(client.command("SET shared overwritten").unwrap().starts_with("OK"));
+    assert!(client.command("DELETE scratch:log").unwrap().starts_with("OK"));
+    assert!(server.command("CLIENT LIST").unwrap().contains(" flags=x\n"));
+    assert_eq!(
+        client.command("CLIENT EPHEMERAL OFF").unwrap(),
+        "OK: EPHEMERAL disabled, 3 keys created so far will still be deleted when the connection closes\n"
+    );
+    assert!(client.command("SET durable 1").unwrap().starts_with("OK"));
+    assert!(send_command(port, "EXISTS presence:ann").unwrap().starts_with("TRUE"));
+
+    // Only the keys it created while ephemeral go with the connection
+    drop(client);
+    thread::sleep(Duration::from_millis(200));
+    for key in ["presence:ann", "scratch:ann", "scratch:log"] {
+        assert!(send_command(port, &format!("EXISTS {}", key)).unwrap().starts_with("FALSE"), "{}", key);
+    }
+    for key in ["before", "shared", "durable"] {
+        assert!(send_command(port, &format!("EXISTS {}", key)).unwrap().starts_with("TRUE"), "{}", key);
+    }
+    assert!(send_command(port, "CLIENT EPHEMERAL MAYBE").unwrap().starts_with("ERROR"));
+}
+
+#[test]
+fn test_client_kill_filters() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    let mut idle = server.connect().unwrap();
+    assert_eq!(idle.command("PING").unwrap(), "PONG\n");
+    let mut subscriber = server.connect().unwrap();
+    assert!(subscriber.command("SUBSCRIBE news").unwrap().starts_with("OK"));
+    assert!(server.command("CLIENT LIST").unwrap().contains(" flags=P\n"));
+    thread::sleep(Duration::from_millis(1100));
+    let mut busy = server.connect().unwrap();
+    assert_eq!(busy.command("PING").unwrap(), "PONG\n");
+
+    // Nothing here is a replica or has another user
+    assert_eq!(send_command(port, "CLIENT KILL TYPE replica IDLE 0").unwrap(), "OK: Killed 0 clients\n");
+    assert_eq!(send_command(port, "CLIENT KILL USER admin IDLE 0").unwrap(), "OK: Killed 0 clients\n");
+    assert_eq!(send_command(port, "CLIENT KILL MAXAGE 3600").unwrap(), "OK: Killed 0 clients\n");
+
+    // Only the connection that has been quiet for a second goes, not the
+    // subscriber that has been quiet as long
+    assert_eq!(send_command(port, "CLIENT KILL TYPE normal USER default IDLE 1").unwrap(), "OK: Killed 1 clients\n");
+    // The killed handler exits in the background, so wait for it to leave
+    // the list before checking its connection is gone
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !server.command("CLIENT LIST").unwrap().starts_with("OK: 3 clients:") {
+        assert!(Instant::now() < deadline, "killed client is still listed");
+    }
+    assert!(idle.command("PING").is_err());
+    assert_eq!(busy.command("PING").unwrap(), "PONG\n");
+
+    assert_eq!(send_command(port, "CLIENT KILL TYPE pubsub").unwrap(), "OK: Killed 1 clients\n");
+    while !server.command("CLIENT LIST").unwrap().starts_with("OK: 2 clients:") {
+        assert!(Instant::now() < deadline, "killed subscriber is still listed");
+    }
+    assert!(subscriber.command("PING").is_err());
+    assert_eq!(busy.command("PING").unwrap(), "PONG\n");
+
+    assert!(server.command("CLIENT LIST").unwrap().contains(" idle=0s "));
+    assert_eq!(send_command(port, "CLIENT KILL TYPE bogus").unwrap(), "ERROR: Unknown client type 'bogus'\n");
+    assert_eq!(send_command(port, "CLIENT KILL IDLE soon").unwrap(), "ERROR: Invalid IDLE 'soon', expected seconds\n");
+    assert!(send_command(port, "CLIENT KILL IDLE").unwrap().starts_with("ERROR: CLIENT KILL requires filters"));
+}
+
+#[test]
+fn test_keyspace_stats() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+
+    send_command(port, "SET cache:1 a").unwrap();
+    send_command(port, "HSET user:1 name Ann").unwrap();
+    send_command(port, "GET cache:1").unwrap();
+    send_command(port, "GET cache:2").unwrap();
+    send_command(port, "HGET user:1 name").unwrap();
+    send_command(port, "EXISTS user:2").unwrap();
+    assert_eq!(send_command(port, "STATS").unwrap(), "OK: keyspace_hits=2 keyspace_misses=2 hit_rate=0.5000\n");
+
+    assert!(send_command(port, "STATS PREFIXES").unwrap().contains("off"));
+    send_command(port, "STATS PREFIXES ON").unwrap();
+    send_command(port, "GET cache:1").unwrap();
+    assert_eq!(send_command(port, "STATS PREFIXES").unwrap(), "OK: 1 prefixes:\n");
+
+    send_command(port, "STATS RESET").unwrap();
+    assert_eq!(send_command(port, "STATS").unwrap(), "OK: keyspace_hits=0 keyspace_misses=0 hit_rate=0.0000\n");
+}
+
+#[test]
+fn test_proxy_mode() {
+    let primary_server = TestServer::start().unwrap();
+    let replica_server = TestServer::start().unwrap();
+    let (primary, replica) = (primary_server.port(), replica_server.port());
+    let port = unused_port();
+    thread::spawn(move || {
+        medusa::proxy::start_proxy(medusa::proxy::ProxyConfig {
+            listen: format!("127.0.0.1:{}", port),
+            primary: format!("127.0.0.1:{}", primary),
+            replicas: vec![format!("127.0.0.1:{}", replica)],
+            pool_size: 2,
+        });
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    // Writes reach the primary; reads are served by the replica
+    assert!(send_command(port, "SET user:1 Ann").unwrap().starts_with("OK"));
+    assert!(send_command(primary, "GET user:1").unwrap().contains("Ann"));
+    assert!(send_command(port, "GET user:1").unwrap().starts_with("NULL"));
+    assert!(send_command(port, "CLIENT ID").unwrap().starts_with("ERROR"));
+
+    // Pipelined commands come back in order, multi-line replies intact
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "Medusa proxy ready\n");
+    stream.write_all(b"SET n 1\nHELP GET\nPING\nQUIT\n").unwrap();
+    let mut replies = String::new();
+    reader.read_to_string(&mut replies).unwrap();
+    assert_eq!(
+        replies,
+        "OK: Set 'n' = '1'\nOK: Help for GET:\n  GET key\n    Retrieve a string value (since 0.1.0)\nPONG\nOK: Goodbye!\n"
+    );
+}
+
+#[test]
+fn test_client_read_replicas() {
+    use medusa::client::{Client, Endpoint, Role};
+
+    let primary_server = TestServer::start().unwrap();
+    let replica_server = TestServer::start().unwrap();
+    let (primary, replica) = (primary_server.port(), replica_server.port());
+    // Nothing listens here, so reads routed to it must fail over
+    let dead = unused_port();
+    let endpoint = |port: u16, role| Endpoint { address: format!("127.0.0.1:{}", port), role };
+
+    // Writes reach the primary; reads are served by the replica
+    let client = Client::new(&[endpoint(primary, Role::Primary), endpoint(replica, Role::Replica)]).unwrap();
+    assert!(client.execute("SET user:1 Ann").unwrap().starts_with("OK"));
+    assert!(client.execute("GET user:1").unwrap().starts_with("NULL"));
+    assert_eq!(
+        client.pipeline(&["SET n 1", "HELP GET"]).unwrap(),
+        vec!["OK: Set 'n' = '1'\n", "OK: Help for GET:\n  GET key\n    Retrieve a string value (since 0.1.0)\n"]
+    );
+    assert!(client.execute("CLIENT ID").is_err());
+
+    // A replica that cannot be reached is skipped and the primary answers
+    let client = Client::new(&[endpoint(primary, Role::Primary), endpoint(dead, Role::Replica)]).unwrap();
+    assert_eq!(client.execute("GET user:1").unwrap(), "OK: 'user:1' = Ann\n");
+    assert_eq!(client.execute("GET user:1").unwrap(), "OK: 'user:1' = Ann\n");
+    assert_eq!(client.check_health(), vec![(format!("127.0.0.1:{}", dead), false)]);
+}
+
+#[test]
+fn test_fair_scheduling_of_pipelines() {
+    let server = TestServer::with_config(ServerConfig { fair_quantum: 2, ..Default::default() }).unwrap();
+    let port = server.port();
+
+    // Two clients pipeline at once and take turns; neither loses a reply
+    let pipeliners: Vec<_> = ["fair:a", "fair:b"]
+        .into_iter()
+        .map(|key| {
+            thread::spawn(move || {
+                let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+                stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let batch: String = (0..300).map(|i| format!("RPUSH {} {}\n", key, i)).collect();
+                stream.write_all(batch.as_bytes()).unwrap();
+                for _ in 0..300 {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    assert!(line.starts_with("OK"), "{}", line);
+                }
+            })
+        })
+        .collect();
+    assert!(send_command(port, "PING").unwrap().starts_with("PONG"));
+    for pipeliner in pipeliners {
+        pipeliner.join().unwrap();
+    }
+    assert_eq!(send_command(port, "LLEN fair:a").unwrap(), "OK: List 'fair:a' has 300 items\n");
+    assert_eq!(send_command(port, "LLEN fair:b").unwrap(), "OK: List 'fair:b' has 300 items\n");
+}
+
+#[test]
+fn test_pipelined_replies_are_batched() {
+    let server = TestServer::start().unwrap();
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+
+    let batch: String = (0..50).map(|i| format!("SET p:{} {}\nGET p:{}\n", i, i, i)).collect();
+    stream.write_all(batch.as_bytes()).unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "OK: Set 'p:0' = '0'\n");
+    // The other 99 replies came in the same write
+    assert_eq!(reader.buffer().iter().filter(|byte| **byte == b'\n').count(), 99);
+    for i in 0..50 {
+        line.clear();
+        if i > 0 {
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, format!("OK: Set 'p:{}' = '{}'\n", i, i));
+            line.clear();
+        }
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, format!("OK: 'p:{}' = {}\n", i, i));
+    }
+}
+
+#[test]
+fn test_get_fast_path() {
+    let server = TestServer::start().unwrap();
+    let port = server.port();
+    send_command(port, "SET greeting hello world").unwrap();
+    send_command(port, "LPUSH jobs one").unwrap();
+
+    // Plain GETs skip the general command path but must answer the same
+    assert_eq!(send_command(port, "GET greeting").unwrap(), "OK: 'greeting' = hello world\n");
+    assert_eq!(send_command(port, "get greeting").unwrap(), "OK: 'greeting' = hello world\n");
+    assert_eq!(send_command(port, "GET missing").unwrap(), "NULL: Key 'missing' not found or expired\n");
+    assert_eq!(send_command(port, "GET jobs").unwrap(), "ERROR: Failed to get value: Key contains non-string value\n");
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    stream.write_all(b"CLIENT FRAMING ON\nGET greeting\nGET missing\n").unwrap();
+    let mut replies = Vec::new();
+    for _ in 0..5 {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        replies.push(line.clone());
+    }
+    assert_eq!(replies[2], "FRAME 29\n");
+    assert_eq!(replies[3], "OK: 'greeting' = hello world\n");
+    assert_eq!(replies[4], "FRAME 41\n");
+}
+
+#[test]
+fn test_key_history() {
+    let server = TestServer::with_config(ServerConfig { key_history: 2, ..Default::default() }).unwrap();
+    let port = server.port();
+
+    send_command(port, "SET config:mode a").unwrap();
+    send_command(port, "GET config:mode").unwrap();
+    send_command(port, "*TAG deploy-9 SET config:mode b").unwrap();
+    send_command(port, "DELETE config:mode").unwrap();
+    assert_eq!(send_command(port, "HISTORY config:mode").unwrap(), "OK: 2 changes to 'config:mode':\n");
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    stream.write_all(b"HISTORY config:mode\n").unwrap();
+    let mut lines = Vec::new();
+    for _ in 0..3 {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        lines.push(line.clone());
+    }
+    assert!(lines[1].contains(" op=DELETE client=127.0.0.1:"), "{}", lines[1]);
+    assert!(lines[2].contains(" op=SET client=127.0.0.1:") && lines[2].ends_with(" tag=deploy-9\n"), "{}", lines[2]);
+    assert_eq!(send_command(port, "HISTORY never:set").unwrap(), "OK: No recorded changes to 'never:set'\n");
+}
+
+#[test]
+fn test_test_server_harness() {
+    let server = TestServer::start().unwrap();
+
+    // Replies come back whole, multi-line ones included
+    assert_eq!(server.command("SET n 1").unwrap(), "OK: Set 'n' = '1'\n");
+    assert_eq!(server.command("HELP GET").unwrap(), "OK: Help for GET:\n  GET key\n    Retrieve a string value (since 0.1.0)\n");
+
+    // A client keeps its session between commands
+    let mut client = server.connect().unwrap();
+    let id = client.command("CLIENT ID").unwrap();
+    assert_eq!(client.command("CLIENT ID").unwrap(), id);
+
+    // Shutdown disconnects clients and releases the port
+    let addr = server.addr();
+    server.shutdown();
+    assert!(client.command("PING").is_err());
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn test_binary_hash_fields() {
+    use medusa::client::{Client, Endpoint, Role};
+
+    let server = TestServer::start().unwrap();
+    let blob = [0x82, 0xA4, b'n', b'a', b'm', b'e', 0xA3, b'A', b'n', b'n', 0xFF, b'\n'];
+
+    let client = Client::new(&[Endpoint { address: server.addr().to_string(), role: Role::Primary }]).unwrap();
+    assert!(client.hset_bytes("user:1", "profile", &blob).unwrap());
+    assert!(!client.hset_bytes("user:1", "profile", &blob).unwrap());
+    assert_eq!(client.hget_bytes("user:1", "profile").unwrap(), Some(blob.to_vec()));
+    assert_eq!(client.hget_bytes("user:1", "missing").unwrap(), None);
+
+    assert_eq!(server.command("HSETBIN user:1 name QW5u").unwrap(), "OK: Created new field 'name' in hash 'user:1'\n");
+    assert_eq!(server.command("HGET user:1 name").unwrap(), "OK: 'user:1:name' = Ann\n");
+    assert_eq!(server.command("HGETBIN user:1 name").unwrap(), "OK: 'user:1:name' = QW5u\n");
+    assert!(server.command("HGET user:1 profile").unwrap().contains("binary data"));
+    server.command("HDEL user:1 name").unwrap();
+    assert_eq!(server.command("HGETALL user:1").unwrap(), "OK: Hash 'user:1' fields: profile:<12 bytes binary>\n");
+    assert!(server.command("HSETBIN user:1 name not-base64").unwrap().starts_with("ERROR: Invalid base64"));
+}
+
+#[test]
+fn test_multi_exec() {
+    let server = TestServer::start().unwrap();
+    server.command("LPUSH jobs one").unwrap();
+    let mut client = server.connect().unwrap();
+
+    // Runtime errors are reported per command and do not stop the rest
+    assert!(client.command("MULTI").unwrap().starts_with("OK"));
+    assert_eq!(client.command("SET counter 1").unwrap(), "QUEUED\n");
+    assert_eq!(client.command("GET jobs").unwrap(), "QUEUED\n");
+    assert_eq!(client.command("HELP PING").unwrap(), "QUEUED\n");
+    assert_eq!(client.command("GET counter").unwrap(), "QUEUED\n");
+    assert_eq!(server.command("GET counter").unwrap(), "NULL: Key 'counter' not found or expired\n");
+    assert_eq!(
+        client.command("EXEC").unwrap(),
+        "OK: 4 replies:\n  OK: Set 'counter' = '1'\n  ERROR: Failed to get value: Key contains non-string value\n  OK: Help for PING:\n    PING\n      Server health check (since 0.1.0)\n  OK: 'counter' = 1\n"
+    );
+
+    // A command refused while queueing aborts the whole transaction
+    client.command("MULTI").unwrap();
+    client.command("SET counter 2").unwrap();
+    assert_eq!(client.command("FROBNICATE x").unwrap(), "ERROR: Unknown command 'FROBNICATE'\n");
+    assert!(client.command("WAITKEY counter 1").unwrap().contains("cannot be used inside MULTI"));
+    assert!(client.command("EXEC").unwrap().starts_with("ERROR: EXECABORT"));
+    assert_eq!(client.command("GET counter").unwrap(), "OK: 'counter' = 1\n");
+
+    client.command("MULTI").unwrap();
+    client.command("SET counter 3").unwrap();
+    assert!(client.command("MULTI").unwrap().contains("cannot be nested"));
+    assert_eq!(client.command("DISCARD").unwrap(), "OK: Discarded 1 queued commands\n");
+    assert_eq!(client.command("EXEC").unwrap(), "ERROR: EXEC without MULTI\n");
+    assert_eq!(client.command("GET counter").unwrap(), "OK: 'counter' = 1\n");
+}
+
+#[test]
+fn test_resp_protocol() {
+    let server = TestServer::start().unwrap();
+    server.command("HSET user:1 name Ada").unwrap();
+
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // Pipelined, with no greeting ahead of the first reply
+    stream
+        .write_all(
+            b"*3\r\n$3\r\nSET\r\n$8\r\ngreeting\r\n$11\r\nhello world\r\n\
+              *2\r\n$3\r\nGET\r\n$8\r\ngreeting\r\n\
+              *2\r\n$3\r\nGET\r\n$7\r\nmissing\r\n\
+              *3\r\n$4\r\nHGET\r\n$6\r\nuser:1\r\n$4\r\nname\r\n\
+              *2\r\n$6\r\nEXISTS\r\n$8\r\ngreeting\r\n\
+              *1\r\n$4\r\nPING\r\n\
+              PING\r\n\
+              *3\r\n$3\r\nSET\r\n$5\r\nempty\r\n$0\r\n\r\n\
+              *2\r\n$3\r\nGET\r\n$5\r\nempty\r\n\
+              *3\r\n$3\r\nSET\r\n$9\r\ntwo words\r\n$7\r\n a  b  \r\n\
+              *2\r\n$3\r\nGET\r\n$9\r\ntwo words\r\n\
+              TTL greeting\r\n\
+              TTL missing\r\n\
+              RPUSH queue a\r\n\
+              LLEN queue\r\n\
+              LRANGE queue 0 -1\r\n\
+              LRANGE missing 0 -1\r\n\
+              HGETALL user:1\r\n\
+              HSET user:1 age 36\r\n\
+              HLEN user:1\r\n\
+              HGETALL missing\r\n\
+              SADD tags a b\r\n\
+              SREM tags a\r\n\
+              SCARD tags\r\n\
+              SMEMBERS missing\r\n\
+              ZADD board 1 ann 2 bob\r\n\
+              ZCARD board\r\n\
+              XADD events 1-0 type click\r\n\
+              XLEN events\r\n\
+              *1\r\n$10\r\nFROBNICATE\r\n\
+              *1\r\n$3\r\nGET\r\n",
+        )
+        .unwrap();
+    let bulk = |value: &str| Frame::Bulk(Some(value.to_string()));
+    let expected = [
+        Frame::Simple("OK".to_string()),
+        bulk("hello world"),
+        Frame::Bulk(None),
+        bulk("Ada"),
+        Frame::Integer(1),
+        Frame::Simple("PONG".to_string()),
+        Frame::Simple("PONG".to_string()),
+        // Arguments arrive as sent, even empty or with spaces in them
+        Frame::Simple("OK".to_string()),
+        bulk(""),
+        Frame::Simple("OK".to_string()),
+        bulk(" a  b  "),
+        // Counts and lengths are integers, lists are arrays
+        Frame::Integer(-1),
+        Frame::Integer(-2),
+        Frame::Integer(1),
+        Frame::Integer(1),
+        Frame::Array(vec![bulk("a")]),
+        Frame::Array(vec![]),
+        Frame::Array(vec![bulk("name"), bulk("Ada")]),
+        Frame::Integer(1),
+        Frame::Integer(2),
+        Frame::Array(vec![]),
+        Frame::Integer(2),
+        Frame::Integer(1),
+        Frame::Integer(1),
+        Frame::Array(vec![]),
+        Frame::Integer(2),
+        Frame::Integer(2),
+        bulk("1-0"),
+        Frame::Integer(1),
+    ];
+    for frame in expected {
+        assert_eq!(resp::read_frame(&mut reader).unwrap(), frame);
+    }
+    for _ in 0..2 {
+        assert!(matches!(resp::read_frame(&mut reader).unwrap(), Frame::Error(e) if e.starts_with("ERR ")));
+    }
+    assert_eq!(server.command("GET greeting").unwrap(), "OK: 'greeting' = hello world\n");
+
+    // A framing error is answered and ends the connection
+    stream.write_all(b"*1\r\n+PING\r\n").unwrap();
+    assert!(matches!(resp::read_frame(&mut reader).unwrap(), Frame::Error(e) if e.contains("Protocol error")));
+    let mut rest = Vec::new();
+    assert_eq!(reader.read_to_end(&mut rest).unwrap(), 0);
+}
+
+#[test]
+fn test_resp_values_with_reply_punctuation() {
+    let server = TestServer::start().unwrap();
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // Line breaks, `, ` and `:` are how the text protocol lays out its
+    // replies, and come back over RESP as sent
+    let requests: [&[&str]; 12] = [
+        &["SET", "k", "line1\nline2"],
+        &["GET", "k"],
+        &["HSET", "h", "a:b", "x, y"],
+        &["HGETALL", "h"],
+        &["RPUSH", "l", "a, b"],
+        &["LRANGE", "l", "0", "-1"],
+        &["XADD", "s", "1-0", "f", "v:1\n  2"],
+        &["XRANGE", "s", "-", "+"],
+        &["MULTI"],
+        &["GET", "k"],
+        &["EXEC"],
+        &["GET", "no\nsuch"],
+    ];
+    for args in requests {
+        let request = Frame::Array(args.iter().map(|arg| Frame::Bulk(Some(arg.to_string()))).collect());
+        let mut out = Vec::new();
+        request.encode(&mut out);
+        stream.write_all(&out).unwrap();
+    }
+
+    let bulk = |value: &str| Frame::Bulk(Some(value.to_string()));
+    let expected = [
+        Frame::Simple("OK".to_string()),
+        bulk("line1\nline2"),
+        Frame::Integer(1),
+        Frame::Array(vec![bulk("a:b"), bulk("x, y")]),
+        Frame::Integer(1),
+        Frame::Array(vec![bulk("a, b")]),
+        bulk("1-0"),
+        Frame::Array(vec![Frame::Array(vec![bulk("1-0"), Frame::Array(vec![bulk("f"), bulk("v:1\n  2")])])]),
+        Frame::Simple("OK".to_string()),
+        Frame::Simple("QUEUED".to_string()),
+        Frame::Array(vec![bulk("line1\nline2")]),
+        Frame::Bulk(None),
+    ];
+    for frame in expected {
+        assert_eq!(resp::read_frame(&mut reader).unwrap(), frame);
+    }
+}
+
+#[test]
+fn test_resp_scan() {
+    let server = TestServer::start().unwrap();
+    for i in 0..5 {
+        server.command(&format!("SET scan:{} v", i)).unwrap();
+    }
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // Each reply is [cursor, [keys]], and the cursor carries on until 0
+    let mut cursor = "0".to_string();
+    let mut seen = Vec::new();
+    for _ in 0..10 {
+        let mut out = Vec::new();
+        Frame::Array(["SCAN", &cursor, "COUNT", "2"].iter().map(|arg| Frame::Bulk(Some(arg.to_string()))).collect()).encode(&mut out);
+        stream.write_all(&out).unwrap();
+        let Frame::Array(reply) = resp::read_frame(&mut reader).unwrap() else { panic!("SCAN did not reply with an array") };
+        let [Frame::Bulk(Some(next)), Frame::Array(keys)] = reply.as_slice() else { panic!("unexpected SCAN reply {:?}", reply) };
+        seen.extend(keys.iter().map(|key| match key {
+            Frame::Bulk(Some(key)) => key.clone(),
+            other => panic!("unexpected key {:?}", other),
+        }));
+        cursor = next.clone();
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(cursor, "0");
+    seen.sort();
+    assert_eq!(seen, (0..5).map(|i| format!("scan:{}", i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_protocol_detection() {
+    let server = TestServer::start().unwrap();
+
+    // A Redis client speaks first and gets RESP, with no greeting
+    let mut redis = TcpStream::connect(server.addr()).unwrap();
+    redis.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    redis.write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n").unwrap();
+    let mut reader = BufReader::new(redis.try_clone().unwrap());
+    assert_eq!(resp::read_frame(&mut reader).unwrap(), Frame::Simple("OK".to_string()));
+    assert_eq!(resp::read_frame(&mut reader).unwrap(), Frame::Bulk(Some("v".to_string())));
+
+    // A text client that waits is greeted once the detection window passes
+    let telnet = TcpStream::connect(server.addr()).unwrap();
+    telnet.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(telnet.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "Medusa server ready\n");
+    (&telnet).write_all(b"GET k\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "OK: 'k' = v\n");
+
+    // *TAG is text, not RESP
+    assert_eq!(send_command(server.port(), "*TAG r1 GET k").unwrap(), "OK: 'k' = v\n");
+}
+
+#[test]
+fn test_idle_connections() {
+    let server = TestServer::with_config(ServerConfig {
+        enable_timeouts: true,
+        connection_timeout: Duration::from_millis(300),
+        max_connections: 200,
+        ..Default::default()
+    })
+    .unwrap();
+
+    // Idle connections are tasks, not threads, so holding many is cheap
+    let idle: Vec<TcpStream> = (0..100).map(|_| TcpStream::connect(server.addr()).unwrap()).collect();
+    let mut client = server.connect().unwrap();
+    assert_eq!(client.command("PING").unwrap(), "PONG\n");
+    assert!(server.lifecycle().active_connections() >= 100);
+
+    // Past the timeout a silent client is disconnected
+    thread::sleep(Duration::from_millis(800));
+    assert!(client.command("PING").is_err());
+    let mut stream = &idle[0];
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"Medusa server ready\n");
+}
+
+#[test]
+fn test_single_worker_thread() {
+    let server = TestServer::with_config(ServerConfig { worker_threads: 1, ..Default::default() }).unwrap();
+
+    // Clients blocked in a command do not hold the one worker
+    let waiters: Vec<_> = (0..4)
+        .map(|i| {
+            let mut client = server.connect().unwrap();
+            thread::spawn(move || client.command(&format!("WAITKEY ready:{} 5", i)).unwrap())
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(100));
+
+    let mut clients: Vec<_> = (0..8).map(|_| server.connect().unwrap()).collect();
+    for (i, client) in clients.iter_mut().enumerate() {
+        assert!(client.command(&format!("SET key:{} {}", i, i)).unwrap().starts_with("OK"));
+    }
+    for i in 0..4 {
+        server.command(&format!("SET ready:{} yes", i)).unwrap();
+    }
+    for waiter in waiters {
+        assert!(waiter.join().unwrap().starts_with("TRUE"));
+    }
+    assert_eq!(clients[7].command("GET key:3").unwrap(), "OK: 'key:3' = 3\n");
+}
+
+#[test]
+fn test_max_connections() {
+    let server = TestServer::with_config(ServerConfig { max_connections: 2, ..Default::default() }).unwrap();
+    let mut first = server.connect().unwrap();
+    let mut second = server.connect().unwrap();
+    assert_eq!(first.command("PING").unwrap(), "PONG\n");
+
+    // A third client is told why before it is closed
+    let mut rejected = TcpStream::connect(server.addr()).unwrap();
+    rejected.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut reply = String::new();
+    rejected.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "ERROR: Max clients reached\n");
+
+    // A client leaving frees its slot for the next one
+    drop(first);
+    for _ in 0..100 {
+        if server.lifecycle().active_connections() < 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let mut third = server.connect().unwrap();
+    assert_eq!(third.command("PING").unwrap(), "PONG\n");
+    assert_eq!(second.command("PING").unwrap(), "PONG\n");
+}
+
+#[test]
+fn test_server_handle() {
+    let server = server::start_server_with_config(ServerConfig { port: 0, ..Default::default() }).unwrap();
+    let addr = server.local_addr();
+    assert_ne!(addr.port(), 0);
+    assert_eq!(send_command(addr.port(), "PING").unwrap(), "PONG\n");
+
+    let mut client = TcpStream::connect(addr).unwrap();
+    server.stop();
+    // Clients are disconnected and nothing listens any more
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn test_multiple_bind_addresses() {
+    let server = TestServer::with_config(ServerConfig { host: "127.0.0.1, 127.0.0.2".to_string(), ..Default::default() }).unwrap();
+
+    // Both addresses share the port and the store
+    let mut loopback = server.connect().unwrap();
+    let mut other = TestClient::connect(SocketAddr::from(([127, 0, 0, 2], server.port()))).unwrap();
+    assert_eq!(other.command("SET greeting hello").unwrap(), "OK: Set 'greeting' = 'hello'\n");
+    assert_eq!(loopback.command("GET greeting").unwrap(), "OK: 'greeting' = hello\n");
+}
+
+#[test]
+fn test_save_file_survives_restart() {
+    let path = std::env::temp_dir().join(format!("medusa-save-{}.mdb", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let config = || ServerConfig { save_file: Some(path.clone()), ..Default::default() };
+
+    let server = TestServer::with_config(config()).unwrap();
+    let mut client = server.connect().unwrap();
+    client.command("SET greeting hello world").unwrap();
+    client.command("SET session abc EX 100").unwrap();
+    client.command("HSET user:1 name Ann").unwrap();
+    client.command("RPUSH queue a").unwrap();
+    client.command("RPUSH queue b").unwrap();
+    assert_eq!(client.command("SAVE").unwrap(), format!("OK: Saved 4 keys to '{}'\n", path.display()));
+    server.shutdown();
+
+    // The next server starts with the saved keys, TTLs included
+    let server = TestServer::with_config(config()).unwrap();
+    let mut client = server.connect().unwrap();
+    assert_eq!(client.command("GET greeting").unwrap(), "OK: 'greeting' = hello world\n");
+    assert!(client.command("TTL session").unwrap().starts_with("TTL: Key 'session' expires in "));
+    assert_eq!(client.command("HGET user:1 name").unwrap(), "OK: 'user:1:name' = Ann\n");
+    assert_eq!(client.command("LRANGE queue 0 -1").unwrap(), "OK: List 'queue' range [0, -1]: a, b\n");
+    server.shutdown();
+    std::fs::remove_file(&path).unwrap();
+
+    // Without MEDUSA_SAVE_FILE there is nowhere to save, and a client
+    // cannot name a file instead
+    let server = TestServer::start().unwrap();
+    assert_eq!(server.command("SAVE").unwrap(), "ERROR: SAVE requires MEDUSA_SAVE_FILE to be set\n");
+    assert_eq!(server.command(&format!("BGSAVE {}", path.display())).unwrap(), "ERROR: BGSAVE takes no arguments, it writes to MEDUSA_SAVE_FILE\n");
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_background_save() {
+    let path = std::env::temp_dir().join(format!("medusa-bgsave-{}.mdb", std::process::id()));
+    let server = TestServer::with_config(ServerConfig { save_file: Some(path.clone()), ..Default::default() }).unwrap();
+    let mut client = server.connect().unwrap();
+    for i in 0..1000 {
+        client.command(&format!("SET key:{} {}", i, i)).unwrap();
+    }
+    assert!(!client.command("INFO").unwrap().contains("# Persistence"));
+
+    assert_eq!(client.command("BGSAVE").unwrap(), format!("OK: Background save to '{}' started\n", path.display()));
+    let mut info = String::new();
+    for _ in 0..200 {
+        info = client.command("INFO").unwrap();
+        if info.contains("save_in_progress:0") {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(info.contains("last_save_keys:1000\nlast_save_status:ok"), "{}", info);
+    assert_eq!(medusa::snapshot::check_file(&path).unwrap().keys(), 1000);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_backups_stay_in_backup_dir() {
+    let directory = std::env::temp_dir().join(format!("medusa-backups-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let server = TestServer::with_config(ServerConfig { backup_dir: Some(directory.clone()), ..Default::default() }).unwrap();
+    let mut client = server.connect().unwrap();
+
+    client.command("SET a 1").unwrap();
+    assert_eq!(client.command("BACKUP FULL full.mdb").unwrap(), "OK: Wrote full backup of 1 keys to 'full.mdb'\n");
+    client.command("SET b 2").unwrap();
+    assert_eq!(client.command("BACKUP INCREMENTAL incr-1.mdb").unwrap(), "OK: Wrote incremental backup of 1 keys to 'incr-1.mdb'\n");
+    assert!(directory.join("full.mdb").exists() && directory.join("incr-1.mdb").exists());
+    client.command("FLUSHALL").unwrap();
+    assert_eq!(client.command("BACKUP RESTORE full.mdb incr-1.mdb").unwrap(), "OK: Restored 2 keys from 2 backup file(s)\n");
+
+    // Nothing outside the directory can be written or read
+    let outside = std::env::temp_dir().join(format!("medusa-outside-{}.mdb", std::process::id()));
+    for command in [format!("BACKUP FULL {}", outside.display()), "BACKUP INCREMENTAL ../outside.mdb".to_string()] {
+        assert!(client.command(&command).unwrap().ends_with("must be a relative path inside MEDUSA_BACKUP_DIR\n"), "{}", command);
+    }
+    assert!(client.command("BACKUP RESTORE full.mdb ../../etc/passwd").unwrap().starts_with("ERROR: '../../etc/passwd' must be"));
+    assert!(!outside.exists());
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    let server = TestServer::start().unwrap();
+    assert_eq!(server.command("BACKUP FULL full.mdb").unwrap(), "ERROR: MEDUSA_BACKUP_DIR is not set\n");
+}
+
+#[test]
+fn test_imports_stay_in_import_dir() {
+    let directory = std::env::temp_dir().join(format!("medusa-imports-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let store = medusa::store::Store::new();
+    store.set("imported", "yes").unwrap();
+    medusa::snapshot::save_file(&store, directory.join("batch.snap")).unwrap();
+    let server = TestServer::with_config(ServerConfig { import_dir: Some(directory.clone()), ..Default::default() }).unwrap();
+    let mut client = server.connect().unwrap();
+
+    assert_eq!(client.command("IMPORT SNAPSHOT batch.snap").unwrap(), "OK: Imported 'batch.snap': 1 keys loaded\n");
+    assert_eq!(client.command("GET imported").unwrap(), "OK: 'imported' = yes\n");
+
+    // Nothing outside the directory can be read
+    let outside = directory.join("batch.snap").canonicalize().unwrap();
+    for command in [format!("IMPORT SNAPSHOT {}", outside.display()), "IMPORT RDB ../dump.rdb".to_string(), "IMPORT RDB a/../../b".to_string()] {
+        assert!(client.command(&command).unwrap().ends_with("must be a relative path inside MEDUSA_IMPORT_DIR\n"), "{}", command);
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    let server = TestServer::start().unwrap();
+    assert_eq!(server.command("IMPORT RDB dump.rdb").unwrap(), "ERROR: MEDUSA_IMPORT_DIR is not set\n");
+}
+
+#[test]
+fn test_restore_from_scheduled_snapshot() {
+    let directory = std::env::temp_dir().join(format!("medusa-restore-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    let cron = medusa::schedule::CronSchedule::parse("0 0 1 1 *").unwrap();
+    let snapshot_schedule = medusa::schedule::SnapshotSchedule { cron, directory: directory.clone(), keep: 1 };
+    let store = medusa::store::Store::new();
+    store.set("restored", "yes").unwrap();
+    snapshot_schedule.take_snapshot(&store).unwrap();
+
+    // No save file yet, so the newest scheduled snapshot is loaded
+    let config = ServerConfig {
+        save_file: Some(directory.join("medusa.mdb")),
+        snapshot_schedule: Some(snapshot_schedule),
+        ..Default::default()
+    };
+    let server = TestServer::with_config(config).unwrap();
+    let mut client = server.connect().unwrap();
+    assert_eq!(client.command("GET restored").unwrap(), "OK: 'restored' = yes\n");
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_damaged_save_file_at_startup() {
+    let path = std::env::temp_dir().join(format!("medusa-corrupt-{}.mdb", std::process::id()));
+    let store = medusa::store::Store::new();
+    store.set("greeting", "hello").unwrap();
+    medusa::snapshot::save_file(&store, &path).unwrap();
+    let mut bytes = std::fs::read(&path).unwrap();
+    let at = bytes.windows(5).position(|window| window == b"hello").unwrap();
+    bytes[at] = b'j';
+    std::fs::write(&path, bytes).unwrap();
+
+    let error = TestServer::with_config(ServerConfig { save_file: Some(path.clone()), ..Default::default() }).err().unwrap();
+    assert!(error.contains("Refusing to start"), "{}", error);
+
+    // Only the checksum is wrong, so every record loads
+    let config = ServerConfig {
+        save_file: Some(path.clone()),
+        corrupt_snapshot: medusa::snapshot::CorruptionPolicy::Truncate,
+        ..Default::default()
+    };
+    let server = TestServer::with_config(config).unwrap();
+    let mut client = server.connect().unwrap();
+    assert_eq!(client.command("GET greeting").unwrap(), "OK: 'greeting' = jello\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+// Poll `command` on `client` until the reply is `expected`, for up to 5 seconds
+fn wait_for_reply(client: &mut TestClient, command: &str, expected: &str) {
+    let mut reply = String::new();
+    for _ in 0..500 {
+        reply = client.command(command).unwrap();
+        if reply == expected {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("'{}' replied {:?}, expected {:?}", command, reply, expected);
+}
+
+#[test]
+fn test_replication() {
+    let primary = TestServer::start().unwrap();
+    let mut writer = primary.connect().unwrap();
+    writer.command("SET greeting hello").unwrap();
+    writer.command("HSET user:1 name Ada").unwrap();
+    writer.command("SET session abc EX 100").unwrap();
+
+    let replica = TestServer::with_config(ServerConfig { replica_of: Some(primary.addr().to_string()), ..Default::default() }).unwrap();
+    let mut reader = replica.connect().unwrap();
+    wait_for_reply(&mut reader, "GET greeting", "OK: 'greeting' = hello\n");
+    assert_eq!(reader.command("HGET user:1 name").unwrap(), "OK: 'user:1:name' = Ada\n");
+    assert!(reader.command("TTL session").unwrap().starts_with("TTL: Key 'session' expires in"));
+
+    // Writes after the full sync stream over, deletes included
+    writer.command("RPUSH queue a").unwrap();
+    writer.command("DELETE greeting").unwrap();
+    wait_for_reply(&mut reader, "LLEN queue", "OK: List 'queue' has 1 items\n");
+    wait_for_reply(&mut reader, "GET greeting", "NULL: Key 'greeting' not found or expired\n");
+
+    assert!(reader.command("SET greeting again").unwrap().starts_with("ERROR: This server is a read-only replica"));
+    let info = writer.command("INFO").unwrap();
+    assert!(info.contains("role:primary\n") && info.contains("connected_replicas:1\n"), "{}", info);
+    let info = reader.command("INFO").unwrap();
+    assert!(info.contains("role:replica\n") && info.contains("primary_link_status:up\n"), "{}", info);
+}
+
+#[test]
+fn test_client_kill_replication_links() {
+    let primary = TestServer::start().unwrap();
+    let replica = TestServer::with_config(ServerConfig { replica_of: Some(primary.addr().to_string()), ..Default::default() }).unwrap();
+    let mut reader = replica.connect().unwrap();
+    let full_syncs = |reader: &mut TestClient, n: u32| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !reader.command("INFO").unwrap().contains(&format!("full_syncs:{}\n", n)) {
+            assert!(Instant::now() < deadline, "replica did not sync again");
+            thread::sleep(Duration::from_millis(20));
+        }
+    };
+    full_syncs(&mut reader, 1);
+
+    // Each side lists the link, and killing it makes the replica sync again
+    assert!(primary.command("CLIENT LIST").unwrap().contains(" flags=S\n"));
+    assert_eq!(primary.command("CLIENT KILL TYPE slave").unwrap(), "OK: Killed 1 clients\n");
+    full_syncs(&mut reader, 2);
+
+    assert!(replica.command("CLIENT LIST").unwrap().contains(" flags=M\n"));
+    assert_eq!(replica.command("CLIENT KILL TYPE replica").unwrap(), "OK: Killed 0 clients\n");
+    assert_eq!(replica.command("CLIENT KILL TYPE master").unwrap(), "OK: Killed 1 clients\n");
+    full_syncs(&mut reader, 3);
+
+    primary.command("SET after kill").unwrap();
+    wait_for_reply(&mut reader, "GET after", "OK: 'after' = kill\n");
+}
+
+#[test]
+fn test_wait_for_replicas() {
+    let primary = TestServer::start().unwrap();
+    let mut writer = primary.connect().unwrap();
+    assert_eq!(writer.command("WAIT 0 0").unwrap(), "OK: 0\n");
+    let start = Instant::now();
+    assert_eq!(writer.command("WAIT 1 100").unwrap(), "OK: 0\n");
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    let replica = TestServer::with_config(ServerConfig { replica_of: Some(primary.addr().to_string()), ..Default::default() }).unwrap();
+    let mut reader = replica.connect().unwrap();
+    wait_for_reply(&mut writer, "WAIT 1 10", "OK: 1\n");
+    for i in 0..20 {
+        writer.command(&format!("SET k{} v", i)).unwrap();
+    }
+    assert_eq!(writer.command("WAIT 1 5000").unwrap(), "OK: 1\n");
+    // Acknowledged means applied
+    assert_eq!(reader.command("GET k19").unwrap(), "OK: 'k19' = v\n");
+
+    let info = writer.command("INFO").unwrap();
+    assert!(info.contains("replication_offset:20\n") && info.contains(",offset=20,lag=0,"), "{}", info);
+    let info = reader.command("INFO").unwrap();
+    assert!(info.contains("replication_offset:20\n"), "{}", info);
+    assert!(reader.command("WAIT 1 10").unwrap().starts_with("ERROR: WAIT cannot be used on a replica"));
+    assert!(writer.command("WAIT one 10").unwrap().starts_with("ERROR: Invalid replica count"));
+}
+
+#[test]
+fn test_sentinel_failover() {
+    let primary = TestServer::start().unwrap();
+    primary.command("SET k before").unwrap();
+
+    // Two replicas watching the primary; both must agree it is down
+    let ports = [unused_port(), unused_port()];
+    let address = |port: u16| format!("127.0.0.1:{}", port);
+    let nodes: Vec<server::ServerHandle> = ports
+        .iter()
+        .map(|&port| {
+            let peers = ports.iter().filter(|&&other| other != port).map(|&other| address(other)).collect();
+            let sentinel = SentinelConfig { announce: address(port), peers, quorum: 0, down_after: Duration::from_millis(300) };
+            let config = ServerConfig { port, replica_of: Some(primary.addr().to_string()), sentinel: Some(sentinel), ..Default::default() };
+            server::start_server_with_config(config).unwrap()
+        })
+        .collect();
+    let mut clients: Vec<TestClient> = nodes.iter().map(|node| TestClient::connect(node.local_addr()).unwrap()).collect();
+    for client in &mut clients {
+        wait_for_reply(client, "GET k", "OK: 'k' = before\n");
+    }
+    assert_eq!(clients[0].command("SENTINEL PRIMARY").unwrap(), format!("OK: {}\n", primary.addr()));
+
+    // The lowest addressed replica takes over and the other follows it
+    primary.shutdown();
+    let chosen = ports.iter().map(|&port| address(port)).min().unwrap();
+    for client in &mut clients {
+        wait_for_reply(client, "SENTINEL PRIMARY", &format!("OK: {}\n", chosen));
+    }
+    let (winner, follower) = if address(ports[0]) == chosen { (0, 1) } else { (1, 0) };
+    let reply = clients[winner].command("SET k after").unwrap();
+    assert!(reply.starts_with("OK"), "{}", reply);
+    wait_for_reply(&mut clients[follower], "GET k", "OK: 'k' = after\n");
+    let info = clients[winner].command("INFO").unwrap();
+    assert!(info.contains("role:primary\n") && info.contains("failovers:1"), "{}", info);
+
+    drop(clients);
+    for node in nodes {
+        node.stop();
+    }
+}
+
+#[test]
+fn test_sentinel_gossip() {
+    // The replicas are only told about the primary and find each other
+    let ports = [unused_port(), unused_port(), unused_port()];
+    let address = |port: u16| format!("127.0.0.1:{}", port);
+    let primary = address(ports[0]);
+    let nodes: Vec<server::ServerHandle> = ports
+        .iter()
+        .map(|&port| {
+            let peers = if address(port) == primary { Vec::new() } else { vec![primary.clone()] };
+            let sentinel = SentinelConfig { announce: address(port), peers, quorum: 0, down_after: Duration::from_millis(300) };
+            let replica_of = Some(primary.clone()).filter(|primary| *primary != address(port));
+            server::start_server_with_config(ServerConfig { port, replica_of, sentinel: Some(sentinel), ..Default::default() }).unwrap()
+        })
+        .collect();
+    let mut clients: Vec<TestClient> = nodes.iter().map(|node| TestClient::connect(node.local_addr()).unwrap()).collect();
+    let members = |client: &mut TestClient| {
+        let info = client.command("INFO").unwrap();
+        info.lines().find_map(|line| line.strip_prefix("sentinel_members:")).unwrap_or_default().to_string()
+    };
+    let start = Instant::now();
+    while !(1..3).all(|i| members(&mut clients[i]).matches("=up").count() == 2) {
+        assert!(start.elapsed() < Duration::from_secs(5), "{} / {}", members(&mut clients[1]), members(&mut clients[2]));
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(members(&mut clients[1]).contains(&format!("{}=up", address(ports[2]))));
+
+    // With the primary gone it is marked failed, and the two replicas
+    // still make a majority of the three
+    let mut nodes = nodes.into_iter();
+    nodes.next().unwrap().stop();
+    let chosen = address(ports[1]).min(address(ports[2]));
+    for client in &mut clients[1..] {
+        wait_for_reply(client, "SENTINEL PRIMARY", &format!("OK: {}\n", chosen));
+    }
+    while !members(&mut clients[1]).contains(&format!("{}=failed", primary)) {
+        assert!(start.elapsed() < Duration::from_secs(10), "{}", members(&mut clients[1]));
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(clients[1].command("SENTINEL GOSSIP nonsense").unwrap().starts_with("ERROR"));
+
+    drop(clients);
+    for node in nodes {
+        node.stop();
+    }
+}
+
+#[test]
+fn test_multi_primary() {
+    let ports = [unused_port(), unused_port()];
+    let start = |port: u16, peer: u16| {
+        let crdt = CrdtConfig { peers: vec![format!("127.0.0.1:{}", peer)], interval: Duration::from_millis(50) };
+        server::start_server_with_config(ServerConfig { port, crdt: Some(crdt), ..Default::default() }).unwrap()
+    };
+    let first = start(ports[0], ports[1]);
+    let mut a = TestClient::connect(first.local_addr()).unwrap();
+    a.command("SET early before").unwrap();
+
+    // A node that joins late catches up on what it missed
+    let second = start(ports[1], ports[0]);
+    let mut b = TestClient::connect(second.local_addr()).unwrap();
+    wait_for_reply(&mut b, "GET early", "OK: 'early' = before\n");
+
+    // Both accept writes
+    a.command("HSET user:1 name Ada").unwrap();
+    b.command("RPUSH queue job").unwrap();
+    wait_for_reply(&mut b, "HGET user:1 name", "OK: 'user:1:name' = Ada\n");
+    wait_for_reply(&mut a, "LLEN queue", "OK: List 'queue' has 1 items\n");
+
+    // The later of two writes to one key wins on both, deletes included
+    a.command("SET color red").unwrap();
+    thread::sleep(Duration::from_millis(5));
+    b.command("SET color blue").unwrap();
+    wait_for_reply(&mut a, "GET color", "OK: 'color' = blue\n");
+    wait_for_reply(&mut b, "GET color", "OK: 'color' = blue\n");
+    b.command("DELETE early").unwrap();
+    wait_for_reply(&mut a, "EXISTS early", "FALSE: Key 'early' does not exist\n");
+
+    let info = a.command("INFO").unwrap();
+    assert!(info.contains("# Multi-primary") && info.contains("link=up"), "{}", info);
+    assert!(a.command("REPLICAOF 127.0.0.1 1").unwrap().starts_with("ERROR"));
+
+    drop((a, b));
+    first.stop();
+    second.stop();
+}
+
+#[test]
+fn test_multi_primary_excludes_replication() {
+    let crdt = CrdtConfig { peers: vec!["127.0.0.1:1".to_string()], interval: Duration::from_millis(50) };
+    let error = TestServer::with_config(ServerConfig { crdt: Some(crdt), replica_of: Some("127.0.0.1:2".to_string()), ..Default::default() }).err().unwrap();
+    assert!(error.contains("Refusing to start"), "{}", error);
+}
+
+#[test]
+fn test_autosave_rules() {
+    let path = std::env::temp_dir().join(format!("medusa-autosave-{}.mdb", std::process::id()));
+    let save_rules = medusa::schedule::parse_save_rules("0 2").unwrap();
+    let server = TestServer::with_config(ServerConfig { save_file: Some(path.clone()), save_rules, ..Default::default() }).unwrap();
+    let mut client = server.connect().unwrap();
+    let info = client.command("INFO").unwrap();
+    assert!(info.contains("changes_since_last_save:0\nautosave:0 2\n"), "{}", info);
+
+    // One change is below the threshold, the second triggers a save
+    client.command("SET a 1").unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    assert!(!path.exists());
+    client.command("SET b 2").unwrap();
+    let mut info = String::new();
+    for _ in 0..300 {
+        info = client.command("INFO").unwrap();
+        if info.contains("last_save_keys:2") {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(info.contains("changes_since_last_save:0\n"), "{}", info);
+    assert_eq!(medusa::snapshot::check_file(&path).unwrap().keys(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,262 @@
+use medusa::eviction::EvictionPolicy;
+use medusa::store::{Store, StringUnit, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_memory_by_prefix() {
+    let store = Store::new();
+    for i in 0..3 {
+        assert!(store.set(&format!("user:{}", i), "a fairly long profile value").is_ok());
+    }
+    assert!(store.set("session:1", "x").is_ok());
+    assert!(store.hset("session:2", "token", "y").unwrap());
+    assert!(store.set("standalone", "z").is_ok());
+
+    let (usage, inspected) = store.memory_by_prefix(":", None).unwrap();
+    assert_eq!(inspected, 6);
+    assert_eq!(usage.len(), 3);
+    assert_eq!(usage[0].prefix, "user:");
+    assert_eq!(usage[0].keys, 3);
+    assert!(usage[0].memory > usage[1].memory);
+
+    let session = usage.iter().find(|group| group.prefix == "session:").unwrap();
+    assert_eq!(session.keys, 2);
+    let unprefixed = usage.iter().find(|group| group.prefix.is_empty()).unwrap();
+    assert_eq!(unprefixed.keys, 1);
+
+    // A different separator regroups the same keys
+    let (usage, _) = store.memory_by_prefix("_", None).unwrap();
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].keys, 6);
+}
+
+#[test]
+fn test_memory_by_prefix_sampled() {
+    let store = Store::new();
+    for i in 0..100 {
+        assert!(store.set(&format!("cache:{}", i), "value").is_ok());
+    }
+
+    let (usage, inspected) = store.memory_by_prefix(":", Some(10)).unwrap();
+    assert_eq!(inspected, 10);
+    assert_eq!(usage[0].keys, 100); // Scaled up to the whole keyspace
+
+    let (_, inspected) = store.memory_by_prefix(":", Some(1000)).unwrap();
+    assert_eq!(inspected, 100);
+
+    let (usage, inspected) = Store::new().memory_by_prefix(":", None).unwrap();
+    assert!(usage.is_empty());
+    assert_eq!(inspected, 0);
+}
+
+#[test]
+fn test_keyspace_distribution() {
+    let store = Store::new();
+    store.set("small", "x").unwrap();
+    store.set("large", &"x".repeat(2000)).unwrap();
+    store.set_with_ttl("session", "x", 30).unwrap();
+    store.set_with_ttl("cache", "x", 7200).unwrap();
+    assert!(store.hset("user:1", "name", "ada").unwrap());
+
+    let distribution = store.keyspace_distribution(None).unwrap();
+    assert_eq!((distribution.total, distribution.sampled), (5, 5));
+    assert_eq!((distribution.persistent, distribution.volatile), (3, 2));
+    assert_eq!(distribution.ttl_buckets, vec![1, 0, 1, 0, 0]);
+    assert_eq!(distribution.size_buckets, vec![4, 0, 1, 0, 0]);
+    assert_eq!(distribution.types, vec![("string", 4), ("hash", 1)]);
+
+    let sampled = store.keyspace_distribution(Some(2)).unwrap();
+    assert_eq!((sampled.total, sampled.sampled), (5, 2));
+    assert_eq!(sampled.size_buckets.iter().sum::<usize>(), 2);
+}
+
+#[test]
+fn test_lru_eviction() {
+    let store = Store::new();
+    for i in 0..20 {
+        store.set(&format!("cold:{:02}", i), "x").unwrap();
+    }
+    thread::sleep(Duration::from_millis(20));
+    for i in 0..5 {
+        store.set(&format!("hot:{:03}", i), "x").unwrap();
+    }
+    let hot: Vec<String> = (0..5).map(|i| format!("hot:{:03}", i)).collect();
+    store.touch(&hot.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+
+    // No limit, nothing to do
+    assert_eq!(store.evict_if_needed(1 << 20).unwrap(), 0);
+
+    let eviction = store.eviction();
+    let (_, memory) = store.prefix_usage("hot:").unwrap();
+    let per_key = memory / 5;
+    eviction.set_max_memory(per_key * 15);
+    assert!(store.evict_if_needed(0).unwrap_err().starts_with("OOM"));
+
+    // Sampling every key makes the approximation exact
+    eviction.set_policy(EvictionPolicy::AllKeysLru);
+    eviction.set_samples(100);
+    assert_eq!(store.evict_if_needed(0).unwrap(), 10);
+    assert_eq!(eviction.evicted(), 10);
+    assert_eq!(store.count().unwrap(), 15);
+    assert!(hot.iter().all(|key| store.exists(key).unwrap()));
+
+    // volatile-lru only considers keys with a TTL
+    eviction.set_policy(EvictionPolicy::VolatileLru);
+    store.set_with_ttl("session", "x", 100).unwrap();
+    assert_eq!(store.prefix_usage("session").unwrap().1, per_key);
+    assert_eq!(store.evict_if_needed(0).unwrap(), 1);
+    assert!(!store.exists("session").unwrap());
+    assert!(store.evict_if_needed(per_key).unwrap_err().starts_with("OOM"));
+}
+
+#[test]
+fn test_used_memory_follows_writes() {
+    let store = Store::new();
+    let total = |store: &Store| store.prefix_usage("").unwrap().1;
+    assert_eq!(store.used_memory().unwrap(), 0);
+
+    store.set("greeting", "hello").unwrap();
+    store.hset("user:1", "name", "Ada").unwrap();
+    store.hset("user:1", "bio", &"x".repeat(500)).unwrap();
+    store.rpush("queue", "job").unwrap();
+    store.sadd("tags", &["a", "b"]).unwrap();
+    store.setrange("greeting", 5, " world", StringUnit::Bytes).unwrap();
+    assert_eq!(store.used_memory().unwrap(), total(&store));
+
+    store.hdel("user:1", "bio").unwrap();
+    store.lpop("queue").unwrap();
+    store.delete("tags").unwrap();
+    assert_eq!(store.used_memory().unwrap(), total(&store));
+
+    store.clear().unwrap();
+    assert_eq!(store.used_memory().unwrap(), 0);
+}
+
+#[test]
+fn test_pinned_keys_are_not_evicted() {
+    let store = Store::new();
+    for i in 0..10 {
+        store.set(&format!("config:{}", i), "x").unwrap();
+    }
+    thread::sleep(Duration::from_millis(20));
+    for i in 0..10 {
+        store.set(&format!("cached:{}", i), "x").unwrap();
+    }
+    let (_, memory) = store.prefix_usage("cached:").unwrap();
+    let per_key = memory / 10;
+
+    // The least recently used keys are the pinned ones
+    let eviction = store.eviction();
+    eviction.pin("config:*");
+    eviction.set_policy(EvictionPolicy::AllKeysLru);
+    eviction.set_samples(100);
+    eviction.set_max_memory(per_key * 15);
+    assert_eq!(store.evict_if_needed(0).unwrap(), 5);
+    assert_eq!(store.prefix_usage("config:").unwrap().0, 10);
+    assert_eq!(store.prefix_usage("cached:").unwrap().0, 5);
+
+    // With only pinned keys left, a write that needs room is refused
+    assert!(store.evict_if_needed(per_key * 6).unwrap_err().starts_with("OOM"));
+    assert_eq!(store.prefix_usage("config:").unwrap().0, 10);
+
+    eviction.unpin("config:*");
+    assert!(store.evict_if_needed(per_key * 6).is_ok());
+    assert!(store.prefix_usage("config:").unwrap().0 < 10);
+}
+
+#[test]
+fn test_eviction_hooks() {
+    let store = Store::new();
+    for i in 0..10 {
+        store.set(&format!("key:{}", i), "value").unwrap();
+    }
+    let (_, memory) = store.prefix_usage("key:").unwrap();
+    let per_key = memory / 10;
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let ooms = Arc::new(AtomicUsize::new(0));
+    let eviction = store.eviction();
+    let (seen, hook_store) = (evicted.clone(), store.clone());
+    eviction.on_evict(move |key, value, reason| {
+        // The store is usable from the hook
+        assert!(!hook_store.exists(key).unwrap());
+        if let Value::String(value) = value {
+            seen.lock().unwrap().push((key.to_string(), value.clone(), reason));
+        }
+    });
+    let counter = ooms.clone();
+    eviction.on_oom(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    eviction.set_max_memory(per_key * 8);
+    assert!(store.evict_if_needed(0).is_err());
+    assert_eq!(ooms.load(Ordering::SeqCst), 1);
+    assert!(evicted.lock().unwrap().is_empty());
+
+    eviction.set_policy(EvictionPolicy::AllKeysLru);
+    assert_eq!(store.evict_if_needed(0).unwrap(), 2);
+    let evicted = evicted.lock().unwrap().clone();
+    assert_eq!(evicted.len(), 2);
+    assert!(evicted.iter().all(|(key, value, reason)| key.starts_with("key:") && value == "value" && *reason == EvictionPolicy::AllKeysLru));
+    assert_eq!(ooms.load(Ordering::SeqCst), 1);
+
+    eviction.clear_hooks();
+    eviction.set_policy(EvictionPolicy::NoEviction);
+    assert!(store.evict_if_needed(per_key).is_err());
+    assert_eq!(ooms.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_key_limit() {
+    let store = Store::new();
+    assert!(store.reserve_keys(&["anything"]).is_ok());
+
+    store.set_max_keys(3);
+    store.set("a", "1").unwrap();
+    store.set("b", "1").unwrap();
+    assert!(store.reserve_keys(&["c"]).is_ok());
+    // Two new keys where there is room for one
+    assert!(store.reserve_keys(&["c", "d"]).unwrap_err().starts_with("MAXKEYS"));
+    assert!(store.reserve_keys(&["c", "c", "a"]).is_ok());
+
+    // Room held for one write is not given to another until it is dropped,
+    // and counts once the key exists
+    let reservation = store.reserve_keys(&["c"]).unwrap();
+    assert!(store.reserve_keys(&["d"]).is_err());
+    assert!(store.reserve_keys(&["c"]).is_ok());
+    store.set("c", "1").unwrap();
+    assert!(store.reserve_keys(&["d"]).is_err());
+    drop(reservation);
+    assert!(store.reserve_keys(&["d"]).is_err());
+    assert!(store.reserve_keys(&["a", "b"]).is_ok());
+
+    // An expired key no longer counts
+    store.set_with_expiry("c", "1", Duration::from_millis(10)).unwrap();
+    thread::sleep(Duration::from_millis(20));
+    assert!(store.reserve_keys(&["d"]).is_ok());
+
+    // Writers racing for the last free keys never go over the limit
+    store.set_max_keys(10);
+    let writers: Vec<_> = (0..8)
+        .map(|writer| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for i in 0..50 {
+                    let key = format!("race:{}:{}", writer, i);
+                    if let Ok(_reservation) = store.reserve_keys(&[&key]) {
+                        store.set(&key, "1").unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    writers.into_iter().for_each(|writer| writer.join().unwrap());
+    assert_eq!(store.count().unwrap(), 10);
+
+    store.set_max_keys(0);
+    assert_eq!(store.max_keys(), None);
+}
@@ -0,0 +1,153 @@
+use medusa::migrate::{migrate, MigrateOptions, RedisClient, Reply};
+use medusa::server::ServerConfig;
+use medusa::testing::TestServer;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+// DUMP payload: value, RDB version 11, zeroed CRC64
+fn dump(value: &[u8]) -> Vec<u8> {
+    let mut payload = value.to_vec();
+    payload.extend([0x0B, 0x00]);
+    payload.extend([0u8; 8]);
+    payload
+}
+
+fn bulk(data: &[u8]) -> Vec<u8> {
+    let mut out = format!("${}\r\n", data.len()).into_bytes();
+    out.extend_from_slice(data);
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+fn read_command<R: BufRead>(reader: &mut R) -> Option<Vec<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).ok()? == 0 {
+        return None;
+    }
+    let count: usize = line.trim()[1..].parse().ok()?;
+    let mut args = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let len: usize = line.trim()[1..].parse().ok()?;
+        let mut data = vec![0u8; len + 2];
+        reader.read_exact(&mut data).ok()?;
+        args.push(String::from_utf8_lossy(&data[..len]).to_string());
+    }
+    Some(args)
+}
+
+/// A fake Redis serving two SCAN pages over a fixed dataset.
+fn start_fake_redis() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut dumps: HashMap<&str, Vec<u8>> = HashMap::new();
+    dumps.insert("greeting", dump(b"\x00\x05hello"));
+    dumps.insert("session", dump(b"\x00\x03abc"));
+    dumps.insert("profile", dump(b"\x04\x01\x04name\x03Ann"));
+    dumps.insert("tags", dump(b"\x02\x01\x03red"));
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let dumps = dumps.clone();
+            thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                while let Some(args) = read_command(&mut reader) {
+                    let reply = match args[0].as_str() {
+                        "SCAN" => {
+                            let (next, keys): (&str, &[&str]) = match args[1].as_str() {
+                                "0" => ("7", &["greeting", "session"]),
+                                _ => ("0", &["profile", "tags", "vanished"]),
+                            };
+                            let mut reply = b"*2\r\n".to_vec();
+                            reply.extend(bulk(next.as_bytes()));
+                            reply.extend(format!("*{}\r\n", keys.len()).into_bytes());
+                            for key in keys {
+                                reply.extend(bulk(key.as_bytes()));
+                            }
+                            reply
+                        }
+                        "DUMP" => match dumps.get(args[1].as_str()) {
+                            Some(payload) => bulk(payload),
+                            None => b"$-1\r\n".to_vec(),
+                        },
+                        "PTTL" => match args[1].as_str() {
+                            "session" => b":100000\r\n".to_vec(),
+                            "vanished" => b":-2\r\n".to_vec(),
+                            _ => b":-1\r\n".to_vec(),
+                        },
+                        _ => b"-ERR unknown command\r\n".to_vec(),
+                    };
+                    if writer.write_all(&reply).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    address
+}
+
+#[test]
+fn test_redis_client_pipeline() {
+    let mut redis = RedisClient::connect(&start_fake_redis()).unwrap();
+    let replies = redis
+        .pipeline(&[vec![&b"PTTL"[..], b"session"], vec![&b"DUMP"[..], b"missing"], vec![&b"NOPE"[..]]])
+        .unwrap();
+    assert_eq!(replies[0], Reply::Integer(100000));
+    assert_eq!(replies[1], Reply::Bulk(None));
+    assert!(matches!(replies[2], Reply::Error(_)));
+}
+
+// Batches are staged in the temp directory by default, so the server has
+// to import from there
+fn staging_config() -> ServerConfig {
+    ServerConfig { import_dir: Some(std::env::temp_dir()), ..Default::default() }
+}
+
+#[test]
+fn test_migrate_and_verify() {
+    let server = TestServer::with_config(staging_config()).unwrap();
+    let options = MigrateOptions {
+        source: start_fake_redis(),
+        target: server.addr().to_string(),
+        verify: true,
+        ..Default::default()
+    };
+
+    let report = migrate(&options).unwrap();
+    assert_eq!(report.migrated, 3);
+    assert_eq!(report.skipped.len(), 1); // Sets have no Medusa equivalent
+    assert_eq!(report.skipped[0].0, "tags");
+    assert_eq!(report.verified, 3);
+    assert!(report.mismatches.is_empty());
+
+    assert!(server.command("GET greeting").unwrap().contains("hello"));
+    assert!(server.command("HGET profile name").unwrap().contains("Ann"));
+    let ttl = server.command("TTL session").unwrap();
+    assert!(ttl.contains("expires in 99") || ttl.contains("expires in 100"), "{}", ttl);
+}
+
+#[test]
+fn test_migrate_resumes_from_progress_file() {
+    let server = TestServer::with_config(staging_config()).unwrap();
+    let progress = std::env::temp_dir().join(format!("medusa-migrate-progress-{}", std::process::id()));
+    std::fs::write(&progress, "7").unwrap();
+
+    let options = MigrateOptions {
+        source: start_fake_redis(),
+        target: server.addr().to_string(),
+        progress_file: Some(progress.clone()),
+        ..Default::default()
+    };
+
+    let report = migrate(&options).unwrap();
+    assert_eq!(report.migrated, 1); // Only the second SCAN page
+    assert!(!progress.exists()); // Removed once the migration completes
+    assert!(server.command("GET greeting").unwrap().starts_with("NULL"));
+    assert!(server.command("HGET profile name").unwrap().contains("Ann"));
+}
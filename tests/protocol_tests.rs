@@ -0,0 +1,173 @@
+use medusa::command_table::{self, CommandKind};
+use medusa::protocol::{detect, parse_line, Protocol, Request, MAX_REQUEST_TAG_LEN};
+use medusa::testing::TestServer;
+
+// Deterministic xorshift, so a failure reproduces from the seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+#[test]
+fn test_valid_requests() {
+    let request = |tag, command| Some(Request { tag, command });
+    let long_tag = "t".repeat(MAX_REQUEST_TAG_LEN);
+    let tagged_max = format!("*TAG {} PING", long_tag);
+    let corpus: Vec<(&[u8], Option<Request>)> = vec![
+        (b"PING", request(None, "PING")),
+        (b"PING\n", request(None, "PING")),
+        (b"PING\r\n", request(None, "PING")),
+        (b"  SET  k   v \t\r\n", request(None, "SET  k   v")),
+        (b"", None),
+        (b"\n", None),
+        (b" \t\r\n", None),
+        (b"*TAG req-7 SET k v\n", request(Some("req-7"), "SET k v")),
+        (b"*tag req-7 \t GET k", request(Some("req-7"), "GET k")),
+        (b"*TAGS x", request(None, "*TAGS x")),
+        (b"*TAG", request(None, "*TAG")),
+        (b"*TA", request(None, "*TA")),
+        ("SET cl\u{e9} caf\u{e9}".as_bytes(), request(None, "SET cl\u{e9} caf\u{e9}")),
+        // Multi-byte characters where the tag prefix would end
+        ("*TA\u{e9}x PING".as_bytes(), request(None, "*TA\u{e9}x PING")),
+        (tagged_max.as_bytes(), request(Some(&long_tag), "PING")),
+    ];
+    for (line, expected) in corpus {
+        assert_eq!(parse_line(line).unwrap(), expected, "{:?}", String::from_utf8_lossy(line));
+    }
+
+    let request = parse_line(b"*TAG r1 hset user:1 name Ada").unwrap().unwrap();
+    assert_eq!(request.name(), "hset");
+    assert_eq!(request.args(), ["hset", "user:1", "name", "Ada"]);
+}
+
+#[test]
+fn test_malformed_requests() {
+    let too_long = format!("*TAG {} PING", "t".repeat(MAX_REQUEST_TAG_LEN + 1));
+    let corpus: Vec<(&[u8], &str)> = vec![
+        (b"*TAG req-8", "*TAG requires a tag and a command"),
+        (b"*TAG req-8   \r\n", "*TAG requires a tag and a command"),
+        (too_long.as_bytes(), "Request tag is longer than 64 bytes"),
+        (b"SET k \xff\xfe", "Request is not valid UTF-8"),
+        (b"\xc3", "Request is not valid UTF-8"),
+        (b"*TAG \xe9 PING", "Request is not valid UTF-8"),
+    ];
+    for (line, expected) in corpus {
+        let error = parse_line(line).unwrap_err();
+        assert!(error.starts_with(expected), "{:?}: {}", String::from_utf8_lossy(line), error);
+    }
+}
+
+#[test]
+fn test_protocol_detection() {
+    let corpus: Vec<(&[u8], Option<Protocol>)> = vec![
+        (b"", None),
+        (b"*", None),
+        (b"*1\r\n$4\r\nPING\r\n", Some(Protocol::Resp)),
+        (b"*3", Some(Protocol::Resp)),
+        (b"*-1", Some(Protocol::Resp)),
+        (b"*TAG r1 PING", Some(Protocol::Text)),
+        (b"*tag", Some(Protocol::Text)),
+        (b"PING\r\n", Some(Protocol::Text)),
+        (b"\r\n", Some(Protocol::Text)),
+        (b"\xff", Some(Protocol::Text)),
+    ];
+    for (prefix, expected) in corpus {
+        assert_eq!(detect(prefix), expected, "{:?}", String::from_utf8_lossy(prefix));
+    }
+}
+
+#[test]
+fn test_parser_fuzz() {
+    let fragments: &[&[u8]] = &[
+        b"*TAG ", b"*tag", b"*", b" ", b"\t", b"\r", b"\n", b"\r\n", b"\0", b"SET", b"GET", b"k", b"v", b"\xc3\xa9", b"\xe2\x82\xac",
+        b"\xf0\x9f\x90\x8d", b"\xff", b"\xc3", b"\x80",
+    ];
+    let mut rng = Rng(0x5eed_1234_abcd_0042);
+    for _ in 0..20_000 {
+        let mut line = Vec::new();
+        for _ in 0..rng.below(12) {
+            if rng.below(4) == 0 {
+                line.push(rng.next() as u8);
+            } else {
+                line.extend_from_slice(fragments[rng.below(fragments.len())]);
+            }
+        }
+        if rng.below(50) == 0 {
+            line.extend(std::iter::repeat_n(b't', MAX_REQUEST_TAG_LEN + rng.below(3)));
+        }
+
+        if let Ok(Some(request)) = parse_line(&line) {
+            assert!(!request.command.is_empty());
+            assert_eq!(request.command, request.command.trim());
+            assert!(request.tag.is_none_or(|tag| tag.len() <= MAX_REQUEST_TAG_LEN && !tag.is_empty()));
+            assert_eq!(request.args().first().copied(), Some(request.name()));
+        }
+    }
+}
+
+// Random argument lists for every command must get a reply, never a
+// dropped connection, which is what a panicking handler looks like
+#[test]
+fn test_commands_survive_hostile_arguments() {
+    let server = TestServer::start().unwrap();
+    let mut client = server.connect().unwrap();
+
+    // Commands that end the connection or touch the filesystem or the
+    // process are left out, and blocking ones are tried separately below
+    let skipped = ["QUIT", "EXIT", "DRAIN", "HANDOFF", "CLIENT", "DEBUG", "MULTI", "SYNC", "CRDTSYNC", "REPLICAOF", "SUBSCRIBE"];
+    let names: Vec<&str> = command_table::COMMANDS
+        .iter()
+        .filter(|spec| spec.kind != CommandKind::Blocking)
+        .map(|spec| spec.name)
+        .filter(|name| !skipped.iter().any(|skipped| name.starts_with(skipped)))
+        .collect();
+    let arguments = [
+        "k", "user:1", "*", "0", "1", "-1", "2", "-2", "3", "10", "99999999999999999999", "-9223372036854775808", "1.5", "NaN", "inf",
+        "ON", "OFF", "COSINE", "L2", "SAMPLES", "FIELDS", "KEYS", "MEMORY", "OPS", "COUNT", "MATCH", "CONFIRM", "\u{e9}", "a:b:c", "''",
+        "\"", "AAAA", "%%%", "===",
+    ];
+
+    let mut rng = Rng(0x00c0_ffee_d00d_0001);
+    for _ in 0..3_000 {
+        let mut command = rng.pick(&names).to_string();
+        for _ in 0..rng.below(6) {
+            command.push(' ');
+            command.push_str(rng.pick(&arguments));
+        }
+        if let Err(e) = client.command(&command) {
+            panic!("'{}' got no reply: {}", command, e);
+        }
+    }
+
+    // Blocking commands get the same arguments, except that every number
+    // is a hostile timeout or a short one, so none of them waits for long
+    let blocking: Vec<&str> =
+        command_table::COMMANDS.iter().filter(|spec| spec.kind == CommandKind::Blocking).map(|spec| spec.name).collect();
+    let timeouts = ["0.01", "1e19", "1e300", "99999999999999999999", "18446744073709551615", "-1", "NaN", "inf"];
+    for _ in 0..300 {
+        let mut command = rng.pick(&blocking).to_string();
+        for _ in 0..rng.below(6) {
+            let argument = rng.pick(&arguments);
+            command.push(' ');
+            command.push_str(if argument.parse::<f64>().is_ok() { rng.pick(&timeouts) } else { argument });
+        }
+        if let Err(e) = client.command(&command) {
+            panic!("'{}' got no reply: {}", command, e);
+        }
+    }
+    assert_eq!(client.command("PING").unwrap(), "PONG\n");
+}
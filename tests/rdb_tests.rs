@@ -0,0 +1,164 @@
+use medusa::rdb::load_rdb;
+use medusa::store::Store;
+
+// Length-prefixed RDB string (6-bit length encoding)
+fn string(bytes: &[u8]) -> Vec<u8> {
+    assert!(bytes.len() < 64);
+    let mut out = vec![bytes.len() as u8];
+    out.extend_from_slice(bytes);
+    out
+}
+
+fn rdb(body: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = b"REDIS0011".to_vec();
+    out.push(0xFA); // AUX redis-ver
+    out.extend(string(b"redis-ver"));
+    out.extend(string(b"7.2.4"));
+    out.extend([0xFE, 0x00, 0xFB, 0x02, 0x00]); // SELECTDB 0, RESIZEDB
+    for part in body {
+        out.extend(part);
+    }
+    out.push(0xFF);
+    out.extend([0u8; 8]); // Checksum disabled
+    out
+}
+
+fn entry(value_type: u8, key: &[u8], payload: Vec<u8>) -> Vec<u8> {
+    let mut out = vec![value_type];
+    out.extend(string(key));
+    out.extend(payload);
+    out
+}
+
+fn ziplist(entries: &[&[u8]]) -> Vec<u8> {
+    let mut out = vec![0u8; 10];
+    for entry in entries {
+        out.extend(entry.iter());
+    }
+    out.push(0xFF);
+    out
+}
+
+fn listpack(entries: &[&[u8]]) -> Vec<u8> {
+    let mut out = vec![0u8; 6];
+    for entry in entries {
+        out.extend(entry.iter());
+    }
+    out.push(0xFF);
+    out
+}
+
+#[test]
+fn test_rdb_strings_and_expiry() {
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    let mut expiring = vec![0xFC];
+    expiring.extend((now_ms + 100_000).to_le_bytes());
+    expiring.extend(entry(0, b"session", string(b"abc")));
+
+    let mut expired = vec![0xFC];
+    expired.extend((now_ms - 1000).to_le_bytes());
+    expired.extend(entry(0, b"old", string(b"gone")));
+
+    let file = rdb(&[
+        entry(0, b"greeting", string(b"hello")),
+        entry(0, b"small", vec![0xC0, 0x85]),        // int8 -123
+        entry(0, b"medium", vec![0xC1, 0x39, 0x30]), // int16 12345
+        entry(0, b"packed", vec![0xC3, 0x05, 0x0A, 0x00, b'a', 0xE0, 0x00, 0x00]), // LZF "a" x 10
+        expiring,
+        expired,
+        vec![0xFE, 0x01], // SELECTDB 1
+        entry(0, b"other_db", string(b"skipped")),
+    ]);
+
+    let store = Store::new();
+    let import = load_rdb(&store, &mut file.as_slice()).unwrap();
+    assert_eq!(import.loaded, 5);
+    assert_eq!(import.expired, 1);
+    assert_eq!(import.skipped_total(), 1);
+
+    assert_eq!(store.get("greeting").unwrap(), Some("hello".to_string()));
+    assert_eq!(store.get("small").unwrap(), Some("-123".to_string()));
+    assert_eq!(store.get("medium").unwrap(), Some("12345".to_string()));
+    assert_eq!(store.get("packed").unwrap(), Some("a".repeat(10)));
+    let ttl = store.ttl("session").unwrap().unwrap();
+    assert!(ttl > 90 && ttl <= 100);
+    assert!(!store.exists("old").unwrap());
+    assert!(!store.exists("other_db").unwrap());
+}
+
+#[test]
+fn test_rdb_collections() {
+    let mut plain_list = vec![0x02];
+    plain_list.extend(string(b"x"));
+    plain_list.extend(string(b"y"));
+
+    let mut plain_hash = vec![0x02];
+    plain_hash.extend(string(b"field"));
+    plain_hash.extend(string(b"value"));
+    plain_hash.extend(string(b"blob"));
+    plain_hash.extend(string(&[0x82, 0xA1, 0x61, 0xFF])); // msgpack, not UTF-8
+
+    let mut plain_set = vec![0x01];
+    plain_set.extend(string(b"member"));
+
+    let zipped_hash = ziplist(&[
+        &[0x00, 0x04, b'n', b'a', b'm', b'e'],
+        &[0x06, 0x03, b'A', b'n', b'n'],
+        &[0x05, 0x03, b'a', b'g', b'e'],
+        &[0x05, 0xF8], // Immediate 7
+    ]);
+
+    let packed_hash = listpack(&[
+        &[0x83, b'B', b'o', b'b', 0x04],
+        &[0x2A, 0x01], // 7-bit uint 42
+        &[0x84, b'l', b'u', b'c', b'k', 0x05],
+        &[0xDF, 0xFB, 0x02], // 13-bit int -5
+    ]);
+
+    let mut quicklist = vec![0x02];
+    quicklist.push(0x02); // Packed node
+    quicklist.extend(string(&listpack(&[&[0x81, b'a', 0x02], &[0x81, b'b', 0x02]])));
+    quicklist.push(0x01); // Plain node
+    quicklist.extend(string(b"c"));
+
+    let intset = string(&[0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x07, 0x00]);
+
+    let file = rdb(&[
+        entry(1, b"list", plain_list),
+        entry(4, b"hash", plain_hash),
+        entry(2, b"set", plain_set),
+        entry(13, b"zipped", string(&zipped_hash)),
+        entry(16, b"packed", string(&packed_hash)),
+        entry(18, b"quick", quicklist),
+        entry(11, b"intset", intset),
+    ]);
+
+    let store = Store::new();
+    let import = load_rdb(&store, &mut file.as_slice()).unwrap();
+    assert_eq!(import.loaded, 5);
+    assert_eq!(import.skipped.get("sets"), Some(&2));
+
+    assert_eq!(store.lrange("list", 0, -1).unwrap(), vec!["x", "y"]);
+    assert_eq!(store.hget("hash", "field").unwrap(), Some("value".to_string()));
+    assert_eq!(store.hget_bytes("hash", "blob").unwrap(), Some(vec![0x82, 0xA1, 0x61, 0xFF]));
+    assert_eq!(store.hget("zipped", "name").unwrap(), Some("Ann".to_string()));
+    assert_eq!(store.hget("zipped", "age").unwrap(), Some("7".to_string()));
+    assert_eq!(store.hget("packed", "Bob").unwrap(), Some("42".to_string()));
+    assert_eq!(store.hget("packed", "luck").unwrap(), Some("-5".to_string()));
+    assert_eq!(store.lrange("quick", 0, -1).unwrap(), vec!["a", "b", "c"]);
+    assert!(!store.exists("set").unwrap());
+}
+
+#[test]
+fn test_rdb_rejects_garbage() {
+    let store = Store::new();
+    assert!(load_rdb(&store, &mut &b"MDSNAP\x01"[..]).is_err());
+
+    let mut truncated = rdb(&[entry(0, b"key", string(b"value"))]);
+    truncated.truncate(truncated.len() - 12);
+    assert!(load_rdb(&store, &mut truncated.as_slice()).is_err());
+}
@@ -0,0 +1,142 @@
+use medusa::schedule::{latest_persisted, parse_save_rules, CronSchedule, SaveRule, SnapshotSchedule};
+use medusa::store::Store;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+// 2024-01-01 00:00:00 UTC, a Monday
+const NEW_YEAR_2024: u64 = 1_704_067_200;
+const HOUR: u64 = 3600;
+const DAY: u64 = 24 * HOUR;
+
+#[test]
+fn test_cron_parse() {
+    assert!(CronSchedule::parse("0 2 * * *").is_ok());
+    assert!(CronSchedule::parse("*/15 9-17 * * 1-5").is_ok());
+    assert!(CronSchedule::parse("0 0 1,15 * 7").is_ok());
+    assert!(CronSchedule::parse("0 2 * *").is_err());
+    assert!(CronSchedule::parse("60 2 * * *").is_err());
+    assert!(CronSchedule::parse("0 2 0 * *").is_err());
+    assert!(CronSchedule::parse("*/0 * * * *").is_err());
+    assert!(CronSchedule::parse("0 5-2 * * *").is_err());
+}
+
+#[test]
+fn test_cron_next_after() {
+    let nightly = CronSchedule::parse("0 2 * * *").unwrap();
+    assert_eq!(nightly.next_after(NEW_YEAR_2024), Some(NEW_YEAR_2024 + 2 * HOUR));
+    // Strictly after: a run at 02:00 schedules the next one for tomorrow
+    assert_eq!(nightly.next_after(NEW_YEAR_2024 + 2 * HOUR), Some(NEW_YEAR_2024 + DAY + 2 * HOUR));
+
+    let quarter_hourly = CronSchedule::parse("*/15 * * * *").unwrap();
+    assert_eq!(quarter_hourly.next_after(NEW_YEAR_2024 + 61), Some(NEW_YEAR_2024 + 15 * 60));
+
+    let mondays = CronSchedule::parse("30 0 * * 1").unwrap();
+    assert_eq!(mondays.next_after(NEW_YEAR_2024), Some(NEW_YEAR_2024 + 30 * 60));
+    assert_eq!(mondays.next_after(NEW_YEAR_2024 + HOUR), Some(NEW_YEAR_2024 + 7 * DAY + 30 * 60));
+
+    let sundays = CronSchedule::parse("0 0 * * 7").unwrap();
+    assert_eq!(sundays.next_after(NEW_YEAR_2024), Some(NEW_YEAR_2024 + 6 * DAY));
+
+    // 2024 is a leap year; 2024-02-29 is day 59 of the year
+    let leap_day = CronSchedule::parse("0 0 29 2 *").unwrap();
+    assert_eq!(leap_day.next_after(NEW_YEAR_2024), Some(NEW_YEAR_2024 + 59 * DAY));
+
+    let never = CronSchedule::parse("0 0 31 2 *").unwrap();
+    assert_eq!(never.next_after(NEW_YEAR_2024), None);
+}
+
+#[test]
+fn test_save_rules() {
+    let rules = parse_save_rules("900 1, 300 100,60  10000").unwrap();
+    assert_eq!(
+        rules,
+        [SaveRule { seconds: 900, changes: 1 }, SaveRule { seconds: 300, changes: 100 }, SaveRule { seconds: 60, changes: 10000 }]
+    );
+    assert!(rules[1].is_due(300, 100));
+    assert!(!rules[1].is_due(299, 5000));
+    assert!(!rules[1].is_due(3600, 99));
+    assert_eq!(parse_save_rules("").unwrap(), []);
+
+    assert!(parse_save_rules("900").is_err());
+    assert!(parse_save_rules("900 1 2").is_err());
+    assert!(parse_save_rules("soon 1").is_err());
+    assert!(parse_save_rules("60 0").is_err());
+}
+
+#[test]
+fn test_latest_persisted() {
+    let directory = std::env::temp_dir().join(format!("medusa-latest-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    let schedule = SnapshotSchedule { cron: CronSchedule::parse("0 2 * * *").unwrap(), directory: directory.clone(), keep: 2 };
+    let save_file = directory.join("medusa.mdb");
+    assert_eq!(latest_persisted(Some(&save_file), Some(&schedule)), None);
+
+    let store = Store::new();
+    store.set("key", "value").unwrap();
+    let snapshot = schedule.take_snapshot(&store).unwrap();
+    assert_eq!(latest_persisted(Some(&save_file), Some(&schedule)), Some(snapshot.clone()));
+    assert_eq!(latest_persisted(Some(&save_file), None), None);
+
+    // Whichever was written last wins
+    thread::sleep(Duration::from_millis(20));
+    medusa::snapshot::save_file(&store, &save_file).unwrap();
+    assert_eq!(latest_persisted(Some(&save_file), Some(&schedule)), Some(save_file.clone()));
+    thread::sleep(Duration::from_millis(20));
+    let snapshot = schedule.take_snapshot(&store).unwrap();
+    assert_eq!(latest_persisted(Some(&save_file), Some(&schedule)), Some(snapshot));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_snapshot_retention() {
+    let directory = std::env::temp_dir().join(format!("medusa-schedule-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    let schedule = SnapshotSchedule {
+        cron: CronSchedule::parse("0 2 * * *").unwrap(),
+        directory: directory.clone(),
+        keep: 2,
+    };
+
+    let store = Store::new();
+    store.set("key", "value").unwrap();
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("unrelated.txt"), "keep me").unwrap();
+
+    let mut written = Vec::new();
+    for _ in 0..3 {
+        written.push(schedule.take_snapshot(&store).unwrap());
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    assert_eq!(schedule.snapshots().unwrap(), written[1..].to_vec());
+    assert!(directory.join("unrelated.txt").exists());
+
+    let restored = Store::new();
+    let mut file = fs::File::open(&written[2]).unwrap();
+    medusa::snapshot::read_snapshot(&restored, &mut file).unwrap();
+    assert_eq!(restored.get("key").unwrap(), Some("value".to_string()));
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_scheduler_reports_in_info() {
+    let directory = std::env::temp_dir().join(format!("medusa-schedule-info-{}", std::process::id()));
+    let store = Store::new();
+    assert!(!store.info().unwrap().contains("# Persistence"));
+
+    medusa::schedule::start_snapshot_scheduler(store.clone(), SnapshotSchedule {
+        cron: CronSchedule::parse("0 2 * * *").unwrap(),
+        directory,
+        keep: 3,
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    let info = store.info().unwrap();
+    assert!(info.contains("# Persistence"));
+    assert!(info.contains("snapshot_schedule:0 2 * * *"));
+    assert!(info.contains("snapshot_keep:3"));
+    assert!(info.contains("last_snapshot_time:-1"));
+    assert!(!info.contains("next_snapshot_time:-1"));
+}
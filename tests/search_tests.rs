@@ -0,0 +1,63 @@
+use medusa::search::tokenize;
+use medusa::store::Store;
+
+#[test]
+fn test_tokenize() {
+    assert_eq!(tokenize("Hello, World! rust-lang 2024"), vec!["hello", "world", "rust", "lang", "2024"]);
+    assert!(tokenize("  ...  ").is_empty());
+}
+
+#[test]
+fn test_search_strings() {
+    let store = Store::new();
+
+    assert!(store.set("doc:1", "The quick brown fox").is_ok());
+    assert!(store.set("doc:2", "A quick red car").is_ok());
+    assert!(store.ft_create("docs", "doc:*", Vec::new()).unwrap());
+    assert!(!store.ft_create("docs", "doc:*", Vec::new()).unwrap());
+
+    assert!(store.set("doc:3", "Lazy brown dog").is_ok());
+    assert!(store.set("other:1", "quick brown").is_ok()); // Outside the pattern
+
+    // AND
+    assert_eq!(store.ft_search("docs", "quick brown").unwrap(), vec!["doc:1"]);
+    // OR
+    assert_eq!(store.ft_search("docs", "fox OR dog").unwrap(), vec!["doc:1", "doc:3"]);
+    // Prefix
+    assert_eq!(store.ft_search("docs", "qui*").unwrap(), vec!["doc:1", "doc:2"]);
+    // Case-insensitive
+    assert_eq!(store.ft_search("docs", "LAZY").unwrap(), vec!["doc:3"]);
+
+    // Updates and deletes are reflected
+    assert!(store.set("doc:1", "slow green turtle").is_ok());
+    assert!(store.ft_search("docs", "fox").unwrap().is_empty());
+    assert!(store.delete("doc:3").unwrap().is_some());
+    assert_eq!(store.ft_search("docs", "brown").unwrap(), Vec::<String>::new());
+
+    assert!(store.ft_search("missing", "fox").is_err());
+}
+
+#[test]
+fn test_search_hash_fields_and_explicit_keys() {
+    let store = Store::new();
+
+    assert!(store.ft_create("articles", "article:*", vec!["title".to_string()]).unwrap());
+    assert!(store.hset("article:1", "title", "Rust ownership explained").unwrap());
+    assert!(store.hset("article:1", "author", "ferris").unwrap());
+
+    assert_eq!(store.ft_search("articles", "ownership").unwrap(), vec!["article:1"]);
+    assert!(store.ft_search("articles", "ferris").unwrap().is_empty()); // Field not indexed
+
+    // Keys outside the pattern can be added explicitly
+    assert!(store.hset("draft:7", "title", "Ownership in practice").unwrap());
+    assert!(store.ft_add("articles", "draft:7").unwrap());
+    assert!(!store.ft_add("articles", "missing").unwrap());
+    assert_eq!(store.ft_search("articles", "ownership").unwrap(), vec!["article:1", "draft:7"]);
+
+    // Explicit keys keep being maintained on write
+    assert!(!store.hset("draft:7", "title", "Borrowing basics").unwrap());
+    assert_eq!(store.ft_search("articles", "borrow*").unwrap(), vec!["draft:7"]);
+
+    assert!(store.ft_drop("articles").unwrap());
+    assert!(store.ft_search("articles", "rust").is_err());
+}
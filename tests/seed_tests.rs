@@ -0,0 +1,92 @@
+use medusa::seed::{parse_json_fixture, parse_medusa_fixture, seed_directory};
+use medusa::store::{Store, Value};
+use std::fs;
+
+#[test]
+fn test_medusa_fixture() {
+    let fixtures = parse_medusa_fixture(
+        "# demo users\n\
+         SET greeting hello world\n\
+         HSET user:1 name Ada Lovelace\n\
+         HSET user:1 born 1815\n\
+         RPUSH queue first\n\
+         RPUSH queue second\n\
+         EXPIRE greeting 60\n",
+    )
+    .unwrap();
+
+    let keys: Vec<&str> = fixtures.iter().map(|(key, _, _)| key.as_str()).collect();
+    assert_eq!(keys, vec!["greeting", "user:1", "queue"]);
+    assert!(matches!(&fixtures[0].1, Value::String(s) if s == "hello world"));
+    assert_eq!(fixtures[0].2.map(|ttl| ttl.as_secs()), Some(60));
+    assert!(matches!(&fixtures[1].1, Value::Hash(hash) if hash["name"] == b"Ada Lovelace" && hash.len() == 2));
+    assert!(matches!(&fixtures[2].1, Value::List(list) if list.len() == 2 && list[0] == "first"));
+
+    let error = parse_medusa_fixture("SET a 1\nDEL a\n").unwrap_err();
+    assert_eq!(error, "line 2: unsupported command 'DEL'");
+    assert!(parse_medusa_fixture("SET a 1\nRPUSH a x\n").is_err());
+    assert!(parse_medusa_fixture("EXPIRE a 5\n").is_err());
+}
+
+#[test]
+fn test_json_fixture() {
+    let fixtures = parse_json_fixture(
+        r#"{
+            "greeting": "café \"quoted\"",
+            "count": 42,
+            "flag": true,
+            "tags": ["a", 1, false],
+            "user:1": {"name": "Ada", "born": 1815}
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(fixtures.len(), 5);
+    assert!(matches!(&fixtures[0].1, Value::String(s) if s == "café \"quoted\""));
+    assert!(matches!(&fixtures[1].1, Value::String(s) if s == "42"));
+    assert!(matches!(&fixtures[2].1, Value::String(s) if s == "true"));
+    assert!(matches!(&fixtures[3].1, Value::List(list) if list.iter().eq(["a", "1", "false"].iter())));
+    assert!(matches!(&fixtures[4].1, Value::Hash(hash) if hash["born"] == b"1815"));
+
+    assert!(parse_json_fixture("[1, 2]").is_err());
+    assert!(parse_json_fixture(r#"{"a": null}"#).is_err());
+    assert!(parse_json_fixture(r#"{"a": [[1]]}"#).is_err());
+    assert_eq!(parse_json_fixture("{\n\"a\": 1,\n}").unwrap_err(), "line 3: expected a string key");
+}
+
+#[test]
+fn test_seed_directory() {
+    let directory = std::env::temp_dir().join(format!("medusa-seed-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("01-users.medusa"), "HSET user:1 name Ada\nRPUSH jobs one\n").unwrap();
+    fs::write(directory.join("02-config.json"), r#"{"mode": "demo"}"#).unwrap();
+    fs::write(directory.join("03-broken.json"), r#"{"half": "#).unwrap();
+    fs::write(directory.join("README.txt"), "not a fixture").unwrap();
+
+    let store = Store::new();
+    store.set("mode", "production").unwrap();
+    let results = seed_directory(&store, &directory).unwrap();
+    let outcomes: Vec<(String, bool)> = results
+        .iter()
+        .map(|result| (result.path.file_name().unwrap().to_string_lossy().to_string(), result.outcome.is_ok()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("01-users.medusa".to_string(), true),
+            ("02-config.json".to_string(), true),
+            ("03-broken.json".to_string(), false),
+        ]
+    );
+    assert_eq!(store.get("mode").unwrap(), Some("demo".to_string()));
+    assert_eq!(store.hget("user:1", "name").unwrap(), Some("Ada".to_string()));
+
+    // Seeding again replaces rather than appends
+    seed_directory(&store, &directory).unwrap();
+    assert_eq!(store.llen("jobs").unwrap(), 1);
+    assert_eq!(store.count().unwrap(), 3);
+
+    assert!(seed_directory(&store, directory.join("missing")).is_err());
+    fs::remove_dir_all(&directory).unwrap();
+}
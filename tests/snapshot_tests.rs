@@ -0,0 +1,256 @@
+use medusa::snapshot::{
+    check_snapshot, load_file, load_file_truncated, read_incremental, read_snapshot, restore_backup_chain, save_file, write_full_backup,
+    write_incremental, write_snapshot, CorruptionPolicy,
+};
+use medusa::store::{ClockAnchor, Store, Value};
+use medusa::stream::StreamId;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_snapshot_roundtrip() {
+    let store = Store::new();
+    assert!(store.set("greeting", "hello world").is_ok());
+    assert!(store.set_with_ttl("session", "abc", 100).is_ok());
+    assert!(store.hset("user:1", "name", "Ann").unwrap());
+    assert!(store.hset_bytes("user:1", "prefs", &[0x81, 0xA2, 0xC0, 0xFF]).unwrap());
+    assert_eq!(store.rpush("queue", "a").unwrap(), 1);
+    assert_eq!(store.rpush("queue", "b").unwrap(), 2);
+    assert!(store.ts_create("cpu", Some(60_000)).unwrap());
+    assert_eq!(store.ts_add("cpu", 1000, 0.5).unwrap(), 1000);
+    assert!(store.vadd("emb", vec![0.25, -1.0]).is_ok());
+    assert_eq!(store.sadd("tags", &["rust", "db"]).unwrap(), 2);
+    assert_eq!(store.zadd("board", &[(12.5, "ann"), (f64::INFINITY, "bob")]).unwrap(), 2);
+    let event = StreamId::new(1000, 3);
+    assert_eq!(store.xadd("events", Some(event), &[("type", "click"), ("page", "home")], None).unwrap(), event);
+    assert!(store.xgroup_create("events", "workers", Some(StreamId::MIN), false).unwrap());
+    assert_eq!(store.xreadgroup("workers", "ann", &[("events", None)], None).unwrap().len(), 1);
+
+    let mut buffer = Vec::new();
+    assert_eq!(write_snapshot(&store, &mut buffer).unwrap(), 9);
+
+    let restored = Store::new();
+    assert_eq!(read_snapshot(&restored, &mut buffer.as_slice()).unwrap(), 9);
+
+    assert_eq!(restored.get("greeting").unwrap(), Some("hello world".to_string()));
+    let ttl = restored.ttl("session").unwrap().unwrap();
+    assert!(ttl > 90 && ttl <= 100);
+    assert_eq!(restored.ttl("greeting").unwrap(), None);
+    assert_eq!(restored.hget("user:1", "name").unwrap(), Some("Ann".to_string()));
+    assert_eq!(restored.hget_bytes("user:1", "prefs").unwrap(), Some(vec![0x81, 0xA2, 0xC0, 0xFF]));
+    assert_eq!(restored.lrange("queue", 0, -1).unwrap(), vec!["a", "b"]);
+    assert_eq!(restored.ts_get("cpu").unwrap(), Some((1000, 0.5)));
+    assert_eq!(restored.vget("emb").unwrap(), Some(vec![0.25, -1.0]));
+    assert_eq!(restored.smembers("tags").unwrap(), vec!["db", "rust"]);
+    assert_eq!(restored.zrange("board", 0, -1).unwrap(), vec![("ann".to_string(), 12.5), ("bob".to_string(), f64::INFINITY)]);
+    let fields = vec![("type".to_string(), "click".to_string()), ("page".to_string(), "home".to_string())];
+    assert_eq!(restored.xrange("events", StreamId::MIN, StreamId::MAX, None).unwrap(), vec![(event, fields)]);
+    assert_eq!(restored.xlast_id("events").unwrap(), event);
+    let group = restored.xgroup("events", "workers").unwrap();
+    assert_eq!(group.last_delivered(), event);
+    assert_eq!(group.pending().map(|(id, pending)| (id, pending.consumer.as_str())).collect::<Vec<_>>(), [(event, "ann")]);
+}
+
+#[test]
+fn test_snapshot_rejects_garbage() {
+    let store = Store::new();
+    assert!(read_snapshot(&store, &mut &b"NOTASNAPSHOT"[..]).is_err());
+
+    let mut buffer = Vec::new();
+    assert!(store.set("key", "value").is_ok());
+    write_snapshot(&store, &mut buffer).unwrap();
+    buffer.truncate(buffer.len() - 3);
+    assert!(read_snapshot(&Store::new(), &mut buffer.as_slice()).is_err());
+}
+
+#[test]
+fn test_check_snapshot() {
+    let store = Store::new();
+    store.set("greeting", "hello").unwrap();
+    store.set_with_ttl("session", "abc", 100).unwrap();
+    store.hset("user:1", "name", "Ann").unwrap();
+    store.set("big", &"x".repeat(1000)).unwrap();
+    let mut buffer = Vec::new();
+    write_full_backup(&store, &mut buffer).unwrap();
+
+    let check = check_snapshot(&mut buffer.as_slice()).unwrap();
+    assert!(!check.incremental && check.checksummed);
+    assert_eq!(check.keys(), 4);
+    assert_eq!((check.types["string"], check.types["hash"]), (3, 1));
+    assert_eq!((check.with_ttl, check.expired), (1, 0));
+    assert_eq!(check.largest.as_ref().unwrap().0, "big");
+    assert_eq!(check.bytes, buffer.len() as u64);
+    assert!(check.describe().iter().any(|line| line == "4 keys (1 hash, 3 string), 1 with a TTL, 0 already expired"));
+
+    // A flipped byte in a value still decodes, so only the checksum catches it
+    let mut corrupt = buffer.clone();
+    let at = corrupt.windows(5).position(|window| window == b"hello").unwrap();
+    corrupt[at] = b'j';
+    assert!(check_snapshot(&mut corrupt.as_slice()).unwrap_err().starts_with("Snapshot checksum mismatch"));
+    assert!(read_snapshot(&Store::new(), &mut corrupt.as_slice()).is_err());
+
+    let mut trailing = buffer.clone();
+    trailing.extend_from_slice(b"junk");
+    assert!(check_snapshot(&mut trailing.as_slice()).unwrap_err().starts_with("4 unexpected bytes"));
+    buffer.truncate(buffer.len() / 2);
+    assert!(check_snapshot(&mut buffer.as_slice()).unwrap_err().contains("(at byte"));
+
+    store.delete("greeting").unwrap();
+    let mut incremental = Vec::new();
+    write_incremental(&store, &mut incremental).unwrap();
+    let check = check_snapshot(&mut incremental.as_slice()).unwrap();
+    assert!(check.incremental);
+    assert_eq!(check.chain.unwrap().1, 1);
+    assert_eq!((check.keys(), check.deletes), (0, 1));
+}
+
+#[test]
+fn test_read_version_1_snapshot() {
+    // Before checksums: magic, version, records and the EOF tag
+    let mut file = b"MDSNAP\x01".to_vec();
+    file.push(1);
+    file.extend_from_slice(&3u32.to_le_bytes());
+    file.extend_from_slice(b"key");
+    file.extend_from_slice(&0u64.to_le_bytes());
+    file.extend_from_slice(&5u32.to_le_bytes());
+    file.extend_from_slice(b"value");
+    file.push(0xFF);
+
+    let store = Store::new();
+    assert_eq!(read_snapshot(&store, &mut file.as_slice()).unwrap(), 1);
+    assert_eq!(store.get("key").unwrap(), Some("value".to_string()));
+    let check = check_snapshot(&mut file.as_slice()).unwrap();
+    assert!(!check.checksummed);
+    assert_eq!(check.version, 1);
+}
+
+#[test]
+fn test_incremental_backup_chain() {
+    let store = Store::new();
+    assert!(write_incremental(&store, &mut Vec::new()).is_err()); // Needs a full backup first
+
+    assert!(store.set("kept", "v1").is_ok());
+    assert!(store.set("removed", "gone soon").is_ok());
+    let mut full = Vec::new();
+    assert_eq!(write_full_backup(&store, &mut full).unwrap(), 2);
+
+    assert!(store.set("kept", "v2").is_ok());
+    assert!(store.delete("removed").unwrap().is_some());
+    let mut first = Vec::new();
+    assert_eq!(write_incremental(&store, &mut first).unwrap(), 2);
+
+    assert!(store.hset("added", "field", "value").unwrap());
+    let mut second = Vec::new();
+    assert_eq!(write_incremental(&store, &mut second).unwrap(), 1);
+
+    let mut empty = Vec::new();
+    assert_eq!(write_incremental(&store, &mut empty).unwrap(), 0);
+
+    let restored = Store::new();
+    assert_eq!(read_snapshot(&restored, &mut full.as_slice()).unwrap(), 2);
+    let (chain, sequence, applied) = read_incremental(&restored, &mut first.as_slice()).unwrap();
+    assert_eq!((sequence, applied), (1, 2));
+    let (second_chain, sequence, _) = read_incremental(&restored, &mut second.as_slice()).unwrap();
+    assert_eq!((second_chain, sequence), (chain, 2));
+
+    assert_eq!(restored.get("kept").unwrap(), Some("v2".to_string()));
+    assert!(!restored.exists("removed").unwrap());
+    assert_eq!(restored.hget("added", "field").unwrap(), Some("value".to_string()));
+    assert!(read_incremental(&restored, &mut full.as_slice()).is_err());
+}
+
+#[test]
+fn test_restore_backup_chain_from_files() {
+    let dir = std::env::temp_dir().join(format!("medusa-backup-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let full = dir.join("full.mdb");
+    let first = dir.join("incr-1.mdb");
+    let second = dir.join("incr-2.mdb");
+
+    let store = Store::new();
+    assert!(store.set("a", "1").is_ok());
+    write_full_backup(&store, &mut std::fs::File::create(&full).unwrap()).unwrap();
+    assert!(store.set("b", "2").is_ok());
+    write_incremental(&store, &mut std::fs::File::create(&first).unwrap()).unwrap();
+    assert!(store.set("c", "3").is_ok());
+    write_incremental(&store, &mut std::fs::File::create(&second).unwrap()).unwrap();
+
+    let restored = Store::new();
+    assert!(restored.set("stale", "cleared by restore").is_ok());
+    assert_eq!(restore_backup_chain(&restored, &full, &[&first, &second]).unwrap(), 3);
+    assert!(!restored.exists("stale").unwrap());
+    assert_eq!(restored.get("c").unwrap(), Some("3".to_string()));
+
+    // Incrementals must be applied in order
+    assert!(restore_backup_chain(&restored, &full, &[&second, &first]).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_load_damaged_file() {
+    let path = std::env::temp_dir().join(format!("medusa-damaged-{}.mdb", std::process::id()));
+    let store = Store::new();
+    for i in 0..10 {
+        assert!(store.set(&format!("key:{}", i), &"x".repeat(100)).is_ok());
+    }
+    save_file(&store, &path).unwrap();
+
+    // Cut off the second half, as a crash mid-write without the rename would
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    let restored = Store::new();
+    assert!(load_file(&restored, &path).unwrap_err().contains("is damaged"));
+    assert_eq!(restored.keys("*").unwrap().len(), 0);
+
+    let (loaded, damage) = load_file_truncated(&restored, &path).unwrap();
+    assert!(loaded > 0 && loaded < 10, "{}", loaded);
+    assert!(damage.is_some());
+    assert_eq!(restored.keys("*").unwrap().len(), loaded);
+
+    assert_eq!(CorruptionPolicy::parse("Truncate").unwrap(), CorruptionPolicy::Truncate);
+    assert!(CorruptionPolicy::parse("ignore").is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_injected_persistence_failure() {
+    let store = Store::new();
+    assert!(store.set("key", "value").is_ok());
+    store.faults().set_enabled(true);
+    store.faults().set_persistence_failure(true);
+
+    assert!(write_snapshot(&store, &mut Vec::new()).is_err());
+    assert!(write_full_backup(&store, &mut Vec::new()).is_err());
+
+    store.faults().set_persistence_failure(false);
+    assert_eq!(write_snapshot(&store, &mut Vec::new()).unwrap(), 1);
+}
+
+#[test]
+fn test_snapshot_expiry_across_restart() {
+    let store = Store::new();
+    store.import_entry("short", Value::new("x".to_string()), Some(Duration::from_millis(50))).unwrap();
+    store.import_entry("long", Value::new("y".to_string()), Some(Duration::from_secs(100))).unwrap();
+
+    let mut buffer = Vec::new();
+    assert_eq!(write_snapshot(&store, &mut buffer).unwrap(), 2);
+    std::thread::sleep(Duration::from_millis(100));
+
+    // The short-lived key came due while "down" and is not restored; the
+    // other keeps the rest of its lifetime
+    let restored = Store::new();
+    assert_eq!(read_snapshot(&restored, &mut buffer.as_slice()).unwrap(), 1);
+    assert!(!restored.exists("short").unwrap());
+    let ttl = restored.ttl("long").unwrap().unwrap();
+    assert!(ttl > 98 && ttl <= 100, "{}", ttl);
+}
+
+#[test]
+fn test_clock_anchor() {
+    let clock = ClockAnchor::now();
+    let later = Instant::now() + Duration::from_secs(30);
+    let deadline = clock.deadline_ms(later);
+    let back = clock.instant_at(deadline).unwrap();
+    assert!(back.max(later) - back.min(later) < Duration::from_millis(2));
+    assert!(clock.instant_at(deadline - 60_000).is_none());
+}
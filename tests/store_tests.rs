@@ -1,4 +1,4 @@
-use medusa::store::{Store, Value, ValueWithTtl};
+use medusa::store::{Store, StringUnit, Value, ValueWithTtl, WaitCondition};
 use std::thread;
 use std::time::Duration;
 
@@ -56,6 +56,91 @@ fn test_expire_functionality() {
     assert_eq!(result, None);
 }
 
+#[test]
+fn test_get_with_ttl() {
+    let store = Store::new();
+
+    store.set("plain", "value").unwrap();
+    store.set_with_ttl("session", "data", 60).unwrap();
+    assert_eq!(store.get_with_ttl("plain").unwrap(), Some(("value".to_string(), None)));
+    let (value, ttl) = store.get_with_ttl("session").unwrap().unwrap();
+    assert_eq!(value, "data");
+    assert!(matches!(ttl, Some(59..=60)));
+    assert_eq!(store.get_with_ttl("missing").unwrap(), None);
+
+    store.set_with_ttl("short", "gone", 0).unwrap();
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(store.get_with_ttl("short").unwrap(), None);
+}
+
+#[test]
+fn test_idle_time() {
+    let store = Store::new();
+    store.set("cold", "value").unwrap();
+    store.set("hot", "value").unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    assert_eq!(store.touch(&["hot", "missing"]).unwrap(), 1);
+    let cold = store.idle_time("cold").unwrap().unwrap();
+    let hot = store.idle_time("hot").unwrap().unwrap();
+    assert!(cold >= Duration::from_millis(50));
+    assert!(hot < cold);
+    assert_eq!(store.idle_time("missing").unwrap(), None);
+}
+
+#[test]
+fn test_chunked_values() {
+    let store = Store::new();
+
+    assert_eq!(store.set_chunk("blob", 0, "hello ").unwrap(), 6);
+    assert_eq!(store.set_chunk("blob", 6, "wörld").unwrap(), 12);
+    // A retried chunk is rejected rather than appended twice
+    assert!(store.set_chunk("blob", 6, "wörld").is_err());
+    assert!(store.set_chunk("missing", 3, "x").is_err());
+    assert_eq!(store.get("blob").unwrap(), Some("hello wörld".to_string()));
+
+    assert_eq!(store.get_chunk("blob", 0, 6).unwrap(), Some(("hello ".to_string(), 12)));
+    // "ö" is two bytes; the chunk stops before splitting it
+    assert_eq!(store.get_chunk("blob", 6, 2).unwrap(), Some(("w".to_string(), 12)));
+    assert_eq!(store.get_chunk("blob", 7, 100).unwrap(), Some(("örld".to_string(), 12)));
+    assert!(store.get_chunk("blob", 8, 1).is_err());
+    assert!(store.get_chunk("blob", 13, 1).is_err());
+    assert_eq!(store.get_chunk("missing", 0, 1).unwrap(), None);
+
+    // Offset 0 starts over
+    assert_eq!(store.set_chunk("blob", 0, "new").unwrap(), 3);
+    assert_eq!(store.string_len("blob").unwrap(), Some(3));
+
+    assert_eq!(store.chunk_threshold(), None);
+    store.set_chunk_threshold(1024);
+    assert!(store.info().unwrap().contains("chunk_threshold:1024"));
+}
+
+#[test]
+fn test_expire_many_and_pattern() {
+    let store = Store::new();
+    for i in 0..2500 {
+        store.set(&format!("cache:{}", i), "value").unwrap();
+    }
+    store.set("session:1", "value").unwrap();
+    store.set("session:2", "value").unwrap();
+
+    assert_eq!(store.expire_many(&["session:1", "session:2", "missing"], 60).unwrap(), 2);
+    assert!(store.ttl("session:2").unwrap().is_some());
+
+    // More keys than one batch
+    assert_eq!(store.expire_pattern("cache:*", 30).unwrap(), 2500);
+    assert!(store.ttl("cache:0").unwrap().is_some());
+    assert!(store.ttl("cache:2499").unwrap().is_some());
+    assert_eq!(store.expire_pattern("nothing:*", 30).unwrap(), 0);
+
+    // A TTL too far off to be a deadline is refused, and the store stays usable
+    assert!(store.expire_many(&["session:1"], u64::MAX).is_err());
+    assert!(store.expire_pattern("cache:*", u64::MAX).is_err());
+    assert!(store.expire("session:1", u64::MAX).is_err());
+    assert_eq!(store.get("session:1").unwrap(), Some("value".to_string()));
+}
+
 #[test]
 fn test_delete_functionality() {
     let store = Store::new();
@@ -181,4 +266,307 @@ fn test_value_with_ttl_creation() {
     assert!(!value_with_ttl.is_expired());
     let ttl = value_with_ttl.ttl_seconds().unwrap();
     assert!(ttl > 0 && ttl <= 5);
-}
\ No newline at end of file
+}
+#[test]
+fn test_wait_for_key() {
+    let store = Store::new();
+    assert!(!store.wait_for_key("job", WaitCondition::Exists, Some(Duration::from_millis(50))).unwrap());
+
+    let writer = store.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        writer.hset("job", "state", "done").unwrap();
+    });
+    assert!(store.wait_for_key("job", WaitCondition::Exists, Some(Duration::from_secs(5))).unwrap());
+    handle.join().unwrap();
+
+    // Writes to other keys don't count as a change
+    let writer = store.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        writer.set("other", "x").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        writer.hset("job", "state", "again").unwrap();
+    });
+    assert!(store.wait_for_key("job", WaitCondition::Changed, Some(Duration::from_secs(5))).unwrap());
+    assert_eq!(store.hget("job", "state").unwrap(), Some("again".to_string()));
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_string_ranges() {
+    let store = Store::new();
+    store.set("greeting", "Hello, World").unwrap();
+
+    assert_eq!(store.strlen("greeting", StringUnit::Bytes).unwrap(), 12);
+    assert_eq!(store.strlen("missing", StringUnit::Bytes).unwrap(), 0);
+    assert_eq!(store.getrange("greeting", 0, 4, StringUnit::Bytes).unwrap(), "Hello");
+    assert_eq!(store.getrange("greeting", -5, -1, StringUnit::Bytes).unwrap(), "World");
+    assert_eq!(store.getrange("greeting", 7, 100, StringUnit::Bytes).unwrap(), "World");
+    assert_eq!(store.getrange("greeting", 5, 2, StringUnit::Bytes).unwrap(), "");
+    assert_eq!(store.getrange("missing", 0, -1, StringUnit::Bytes).unwrap(), "");
+
+    assert_eq!(store.setrange("greeting", 7, "Redis", StringUnit::Bytes).unwrap(), 12);
+    assert_eq!(store.get("greeting").unwrap().unwrap(), "Hello, Redis");
+    assert_eq!(store.setrange("padded", 3, "x", StringUnit::Bytes).unwrap(), 4);
+    assert_eq!(store.get("padded").unwrap().unwrap(), "\0\0\0x");
+    assert_eq!(store.setrange("empty", 5, "", StringUnit::Bytes).unwrap(), 0);
+    assert!(!store.exists("empty").unwrap());
+}
+
+#[test]
+fn test_string_ranges_utf8() {
+    let store = Store::new();
+    store.set("word", "héllo").unwrap();
+
+    // Byte offsets count the two-byte 'é' twice and may not split it
+    assert_eq!(store.strlen("word", StringUnit::Bytes).unwrap(), 6);
+    assert_eq!(store.strlen("word", StringUnit::Chars).unwrap(), 5);
+    assert_eq!(store.getrange("word", 0, 2, StringUnit::Bytes).unwrap(), "hé");
+    assert!(store.getrange("word", 0, 1, StringUnit::Bytes).is_err());
+    assert_eq!(store.getrange("word", 1, 1, StringUnit::Chars).unwrap(), "é");
+    assert_eq!(store.getrange("word", -3, -1, StringUnit::Chars).unwrap(), "llo");
+
+    assert!(store.setrange("word", 2, "x", StringUnit::Bytes).is_err());
+    assert_eq!(store.get("word").unwrap().unwrap(), "héllo");
+    assert_eq!(store.setrange("word", 1, "e", StringUnit::Chars).unwrap(), 5);
+    assert_eq!(store.get("word").unwrap().unwrap(), "hello");
+    assert_eq!(store.setrange("word", 4, "ö!", StringUnit::Chars).unwrap(), 6);
+    assert_eq!(store.get("word").unwrap().unwrap(), "hellö!");
+
+    store.hset("hash", "f", "v").unwrap();
+    assert!(store.strlen("hash", StringUnit::Bytes).is_err());
+}
+
+#[test]
+fn test_flush_token() {
+    let store = Store::new();
+    assert!(store.check_flush(None).is_ok());
+
+    store.set_flush_token(Some("token".to_string()));
+    assert!(store.check_flush(None).is_err());
+    assert!(store.check_flush(Some("other")).is_err());
+    assert!(store.check_flush(Some("token")).is_ok());
+
+    store.set_flush_token(None);
+    assert!(store.check_flush(None).is_ok());
+}
+
+fn scan_all(store: &Store, count: usize, pattern: Option<&str>) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next, batch) = store.scan(cursor, count, pattern, None).unwrap();
+        assert!(batch.len() <= count);
+        keys.extend(batch);
+        if next == 0 {
+            return keys;
+        }
+        cursor = next;
+    }
+}
+
+#[test]
+fn test_scan() {
+    let store = Store::new();
+    assert_eq!(store.scan(0, 10, None, None).unwrap(), (0, Vec::new()));
+
+    for i in 0..100 {
+        store.set(&format!("user:{}", i), "x").unwrap();
+    }
+    store.hset("profile:1", "name", "Ada").unwrap();
+
+    let mut keys = scan_all(&store, 7, None);
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), 101);
+
+    assert_eq!(scan_all(&store, 10, Some("profile:*")), vec!["profile:1".to_string()]);
+    let (_, hashes) = store.scan(0, 1000, None, Some("hash")).unwrap();
+    assert_eq!(hashes, vec!["profile:1".to_string()]);
+}
+
+#[test]
+fn test_scan_under_concurrent_writes() {
+    let store = Store::new();
+    for i in 0..500 {
+        store.set(&format!("stable:{}", i), "x").unwrap();
+    }
+
+    // Keys come and go throughout the scan
+    let writer = {
+        let store = store.clone();
+        thread::spawn(move || {
+            for round in 0..2000 {
+                store.set(&format!("churn:{}", round % 300), "y").unwrap();
+                store.delete(&format!("churn:{}", (round + 150) % 300)).unwrap();
+            }
+        })
+    };
+
+    let mut cursor = 0;
+    let mut seen = std::collections::HashSet::new();
+    loop {
+        let (next, batch) = store.scan(cursor, 5, None, None).unwrap();
+        for key in batch {
+            assert!(key.starts_with("stable:") || key.starts_with("churn:"), "never-present key {}", key);
+            seen.insert(key);
+        }
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    writer.join().unwrap();
+
+    for i in 0..500 {
+        assert!(seen.contains(&format!("stable:{}", i)), "stable:{} was never returned", i);
+    }
+}
+
+#[test]
+fn test_read_transaction() {
+    let store = Store::new();
+    store.set("account:a", "100").unwrap();
+    store.set("account:b", "0").unwrap();
+    store.hset("meta", "owner", "ann").unwrap();
+    store.rpush("log", "open").unwrap();
+
+    let txn = store.read_transaction().unwrap();
+    assert_eq!(txn.get("account:a").unwrap(), Some("100".to_string()));
+    assert_eq!(txn.hget("meta", "owner").unwrap(), Some("ann".to_string()));
+    assert_eq!(txn.hgetall("meta").unwrap().len(), 1);
+    assert_eq!(txn.lrange("log", 0, -1).unwrap(), vec!["open".to_string()]);
+    assert_eq!(txn.llen("log").unwrap(), 1);
+    assert!(txn.get("meta").is_err());
+    assert!(!txn.exists("missing"));
+    drop(txn);
+
+    // Writes made while a transaction is open wait for it to finish
+    let txn = store.read_transaction().unwrap();
+    let writer = {
+        let store = store.clone();
+        thread::spawn(move || store.set("account:a", "40").unwrap())
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(txn.get("account:a").unwrap(), Some("100".to_string()));
+    assert!(!writer.is_finished());
+    drop(txn);
+    writer.join().unwrap();
+    assert_eq!(store.get("account:a").unwrap(), Some("40".to_string()));
+}
+
+#[test]
+fn test_sample() {
+    let store = Store::new();
+    for i in 0..100 {
+        store.set(&format!("user:{}", i), "x").unwrap();
+    }
+    store.hset("user:profile", "name", "Ann").unwrap();
+    store.set_with_expiry("user:gone", "x", Duration::from_millis(1)).unwrap();
+    thread::sleep(Duration::from_millis(5));
+
+    let sample = store.sample(10, None, None).unwrap();
+    assert_eq!(sample.len(), 10);
+    assert_eq!(sample.iter().collect::<std::collections::HashSet<_>>().len(), 10);
+    assert!(!sample.contains(&"user:gone".to_string()));
+
+    assert_eq!(store.sample(10, Some("hash"), None).unwrap(), vec!["user:profile".to_string()]);
+    assert_eq!(store.sample(200, None, Some("user:1*")).unwrap().len(), 11);
+    assert!(store.sample(0, None, None).unwrap().is_empty());
+
+    // Every key turns up eventually, not just the first few in map order
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..200 {
+        seen.extend(store.sample(5, Some("string"), None).unwrap());
+    }
+    assert!(seen.len() > 90, "{}", seen.len());
+}
+
+#[test]
+fn test_read_string() {
+    let store = Store::new();
+    store.set("greeting", "hello").unwrap();
+    store.rpush("queue", "job").unwrap();
+
+    assert_eq!(store.read_string("greeting", false, |value| value.len()).unwrap(), Some(5));
+    assert_eq!(store.read_string("missing", false, |value| value.len()).unwrap(), None);
+    assert!(store.read_string("queue", false, |value| value.len()).is_err());
+
+    // Only a touching read resets the idle time
+    thread::sleep(Duration::from_millis(20));
+    store.read_string("greeting", false, |_| ()).unwrap();
+    assert!(store.idle_time("greeting").unwrap().unwrap() >= Duration::from_millis(20));
+    store.read_string("greeting", true, |_| ()).unwrap();
+    assert!(store.idle_time("greeting").unwrap().unwrap() < Duration::from_millis(20));
+}
+
+#[test]
+fn test_binary_hash_values() {
+    let store = Store::new();
+    let blob = vec![0x82, 0xA4, b'n', b'a', b'm', b'e', 0xC0, 0xFF, b'\n'];
+    assert!(store.hset_bytes("user:1", "profile", &blob).unwrap());
+    assert!(store.hset("user:1", "name", "Ann").unwrap());
+
+    assert_eq!(store.hget_bytes("user:1", "profile").unwrap(), Some(blob.clone()));
+    assert_eq!(store.hget_bytes("user:1", "name").unwrap(), Some(b"Ann".to_vec()));
+    assert_eq!(store.hget("user:1", "name").unwrap(), Some("Ann".to_string()));
+    assert_eq!(store.hgetall_bytes("user:1").unwrap().len(), 2);
+
+    // The text API will not hand out bytes that are not UTF-8
+    assert!(store.hget("user:1", "profile").unwrap_err().contains("binary"));
+    assert!(store.hgetall("user:1").is_err());
+    store.hdel("user:1", "profile").unwrap();
+    assert_eq!(store.hgetall("user:1").unwrap().len(), 1);
+}
+
+#[test]
+fn test_update() {
+    let store = Store::new();
+
+    // A counter bumped from many threads loses no increments
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    store
+                        .update("counter", |current| {
+                            let n = match current {
+                                Some(Value::String(s)) => s.parse::<u64>().unwrap(),
+                                _ => 0,
+                            };
+                            Some(Value::new((n + 1).to_string()))
+                        })
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(store.get("counter").unwrap(), Some("800".to_string()));
+
+    // Dedupe a list in place; the TTL is kept
+    store.rpush("queue", "a").unwrap();
+    store.rpush("queue", "b").unwrap();
+    store.rpush("queue", "a").unwrap();
+    store.expire("queue", 100).unwrap();
+    let updated = store
+        .update("queue", |current| {
+            let Some(Value::List(items)) = current else { return current };
+            let mut seen = std::collections::HashSet::new();
+            Some(Value::List(items.into_iter().filter(|item| seen.insert(item.clone())).collect()))
+        })
+        .unwrap();
+    assert!(matches!(updated, Some(Value::List(items)) if items == ["a", "b"]));
+    assert!(store.ttl("queue").unwrap().unwrap() > 0);
+
+    // Returning None deletes the key; a missing key stays missing
+    assert!(store.update("counter", |_| None).unwrap().is_none());
+    assert!(!store.exists("counter").unwrap());
+    let before = store.change_count();
+    assert!(store.update("missing", |current| current).unwrap().is_none());
+    assert_eq!(store.change_count(), before);
+}
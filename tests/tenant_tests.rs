@@ -0,0 +1,112 @@
+use medusa::store::Store;
+use medusa::tenant::{parse_tenants, TenantQuota};
+
+#[test]
+fn test_parse_tenant_quotas() {
+    let tenants = parse_tenants("team_a:keys=100;ops=50, team_b:memory=1048576").unwrap();
+    assert_eq!(tenants.len(), 2);
+    assert_eq!(tenants[0].0, "team_a");
+    assert_eq!(tenants[0].1.max_keys, Some(100));
+    assert_eq!(tenants[0].1.max_ops_per_sec, Some(50));
+    assert_eq!(tenants[0].1.max_memory, None);
+    assert_eq!(tenants[1].1.max_memory, Some(1048576));
+
+    assert!(parse_tenants("team_a:keys=lots").is_err());
+    assert!(parse_tenants("team_a:bogus=1").is_err());
+}
+
+#[test]
+fn test_tenant_key_quota() {
+    let store = Store::new();
+    let quota = TenantQuota {
+        max_keys: Some(2),
+        ..Default::default()
+    };
+    store.set_tenant("team_a", quota).unwrap();
+
+    assert!(store.check_tenant_quota("SET", "team_a:1", 10).is_ok());
+    store.set("team_a:1", "one").unwrap();
+    assert!(store.check_tenant_quota("SET", "team_a:2", 10).is_ok());
+    store.set("team_a:2", "two").unwrap();
+
+    // Full: new keys are rejected, existing keys can still be overwritten
+    assert!(store.check_tenant_quota("SET", "team_a:3", 10).is_err());
+    assert!(store.check_tenant_quota("SET", "team_a:1", 10).is_ok());
+    assert!(store.check_tenant_quota("GET", "team_a:3", 10).is_ok());
+    assert!(store.check_tenant_quota("DELETE", "team_a:1", 10).is_ok());
+
+    // Keys outside any tenant namespace are unrestricted
+    assert!(store.check_tenant_quota("SET", "team_b:1", 10).is_ok());
+    assert!(store.check_tenant_quota("SET", "global", 10).is_ok());
+
+    let stats = store.tenants().stats("team_a").unwrap();
+    assert_eq!(stats.rejected_ops, 1);
+}
+
+#[test]
+fn test_tenant_memory_quota() {
+    let store = Store::new();
+    let quota = TenantQuota {
+        max_memory: Some(200),
+        ..Default::default()
+    };
+    store.set_tenant("team_a", quota).unwrap();
+
+    assert!(store.check_tenant_quota("SET", "team_a:big", 100).is_ok());
+    store.set("team_a:big", &"x".repeat(100)).unwrap();
+    assert!(store.check_tenant_quota("SET", "team_a:more", 100).is_err());
+    // CAS can grow a value too, so it is held to the same limit
+    assert!(store.check_tenant_quota("CAS", "team_a:big", 100).is_err());
+
+    let (keys, memory) = store.prefix_usage("team_a:").unwrap();
+    assert_eq!(keys, 1);
+    assert!(memory >= 100);
+}
+
+#[test]
+fn test_tenant_ops_quota_and_info() {
+    let store = Store::new();
+    let quota = TenantQuota {
+        max_ops_per_sec: Some(3),
+        ..Default::default()
+    };
+    store.set_tenant("team_a", quota).unwrap();
+
+    let admitted = (0..10)
+        .filter(|_| store.check_tenant_quota("GET", "team_a:key", 0).is_ok())
+        .count();
+    // The window resets every second; a tight loop spans at most two windows
+    assert!((3..=6).contains(&admitted));
+
+    store.set("team_a:key", "value").unwrap();
+    let info = store.info().unwrap();
+    assert!(info.contains("# Tenants"));
+    assert!(info.contains("tenant_team_a:keys=1"));
+
+    assert!(store.tenants().remove("team_a").unwrap());
+    assert!(!store.tenants().remove("team_a").unwrap());
+    assert!(!store.info().unwrap().contains("# Tenants"));
+}
+
+#[test]
+fn test_tenant_usage_follows_writes() {
+    let store = Store::new();
+    store.set("team_a:1", "one").unwrap();
+    store.set("team_a:2", "two").unwrap();
+    store.set("team_b:1", "one").unwrap();
+
+    // A tenant defined over existing keys starts from their usage
+    store.set_tenant("team_a", TenantQuota { max_keys: Some(3), ..Default::default() }).unwrap();
+    assert_eq!(store.tenants().usage("team_a").unwrap(), store.prefix_usage("team_a:").unwrap());
+
+    store.set("team_a:3", "three").unwrap();
+    assert!(store.check_tenant_quota("SET", "team_a:4", 10).is_err());
+    store.delete("team_a:1").unwrap();
+    assert!(store.check_tenant_quota("SET", "team_a:4", 10).is_ok());
+    store.set("team_a:2", &"x".repeat(100)).unwrap();
+    assert_eq!(store.tenants().usage("team_a").unwrap(), store.prefix_usage("team_a:").unwrap());
+
+    // Updating the quota keeps the usage
+    store.set_tenant("team_a", TenantQuota::default()).unwrap();
+    assert_eq!(store.tenants().usage("team_a").unwrap().0, 2);
+}
@@ -0,0 +1,64 @@
+use medusa::store::Store;
+use medusa::timeseries::{Aggregation, TimeSeries};
+
+#[test]
+fn test_timeseries_add_and_range() {
+    let store = Store::new();
+
+    assert!(store.ts_create("cpu", None).unwrap());
+    assert!(!store.ts_create("cpu", None).unwrap()); // Already exists
+
+    assert_eq!(store.ts_add("cpu", 1000, 10.0).unwrap(), 1000);
+    assert_eq!(store.ts_add("cpu", 2000, 20.0).unwrap(), 2000);
+    assert_eq!(store.ts_add("cpu", 1500, 15.0).unwrap(), 1500); // Out of order
+
+    assert_eq!(store.ts_get("cpu").unwrap(), Some((2000, 20.0)));
+    assert_eq!(store.ts_get("nonexistent").unwrap(), None);
+
+    let all = store.ts_range("cpu", 0, u64::MAX, None).unwrap();
+    assert_eq!(all, vec![(1000, 10.0), (1500, 15.0), (2000, 20.0)]);
+
+    let partial = store.ts_range("cpu", 1200, 2000, None).unwrap();
+    assert_eq!(partial, vec![(1500, 15.0), (2000, 20.0)]);
+
+    assert!(store.ts_range("nonexistent", 0, u64::MAX, None).unwrap().is_empty());
+
+    // Wrong type
+    assert!(store.set("plain", "value").is_ok());
+    assert!(store.ts_get("plain").is_err());
+}
+
+#[test]
+fn test_timeseries_aggregation() {
+    let store = Store::new();
+
+    for (ts, value) in [(0, 1.0), (500, 3.0), (1000, 10.0), (1999, 20.0), (2500, 7.0)] {
+        store.ts_add("metrics", ts, value).unwrap();
+    }
+
+    let avg = store.ts_range("metrics", 0, u64::MAX, Some((Aggregation::Avg, 1000))).unwrap();
+    assert_eq!(avg, vec![(0, 2.0), (1000, 15.0), (2000, 7.0)]);
+
+    let min = store.ts_range("metrics", 0, u64::MAX, Some((Aggregation::Min, 1000))).unwrap();
+    assert_eq!(min, vec![(0, 1.0), (1000, 10.0), (2000, 7.0)]);
+
+    let max = store.ts_range("metrics", 0, u64::MAX, Some((Aggregation::Max, 1000))).unwrap();
+    assert_eq!(max, vec![(0, 3.0), (1000, 20.0), (2000, 7.0)]);
+}
+
+#[test]
+fn test_timeseries_retention() {
+    let mut series = TimeSeries::new(Some(1000));
+
+    series.add(0, 1.0);
+    series.add(500, 2.0);
+    series.add(1200, 3.0);
+    assert_eq!(series.len(), 2); // Sample at 0 fell out of the retention window
+
+    series.add(3000, 4.0);
+    assert_eq!(series.range(0, u64::MAX, None), vec![(3000, 4.0)]);
+
+    // Duplicate timestamps overwrite
+    series.add(3000, 5.0);
+    assert_eq!(series.latest(), Some((3000, 5.0)));
+}
@@ -0,0 +1,45 @@
+use medusa::store::Store;
+use medusa::vector::Metric;
+
+#[test]
+fn test_vector_add_and_get() {
+    let store = Store::new();
+
+    assert!(store.vadd("emb:1", vec![1.0, 0.0, 0.0]).is_ok());
+    assert_eq!(store.vget("emb:1").unwrap(), Some(vec![1.0, 0.0, 0.0]));
+    assert_eq!(store.vget("nonexistent").unwrap(), None);
+    assert!(store.vadd("emb:empty", Vec::new()).is_err());
+
+    assert!(store.set("plain", "value").is_ok());
+    assert!(store.vget("plain").is_err());
+}
+
+#[test]
+fn test_vector_search() {
+    let store = Store::new();
+
+    assert!(store.vadd("emb:x", vec![1.0, 0.0]).is_ok());
+    assert!(store.vadd("emb:y", vec![0.0, 1.0]).is_ok());
+    assert!(store.vadd("emb:xy", vec![1.0, 1.0]).is_ok());
+    assert!(store.vadd("emb:3d", vec![1.0, 0.0, 0.0]).is_ok()); // Different dimension
+    assert!(store.vadd("other:x", vec![1.0, 0.0]).is_ok()); // Outside the pattern
+
+    let cosine = store.vsearch("emb:*", &[2.0, 0.1], 2, Metric::Cosine).unwrap();
+    assert_eq!(cosine.len(), 2);
+    assert_eq!(cosine[0].0, "emb:x");
+    assert_eq!(cosine[1].0, "emb:xy");
+
+    let l2 = store.vsearch("emb:*", &[0.0, 0.9], 10, Metric::L2).unwrap();
+    assert_eq!(l2.len(), 3);
+    assert_eq!(l2[0].0, "emb:y");
+    assert!((l2[0].1 - 0.1).abs() < 1e-5);
+}
+
+#[test]
+fn test_metric_distance() {
+    assert!(Metric::Cosine.distance(&[1.0, 0.0], &[1.0, 0.0]).abs() < 1e-6);
+    assert!((Metric::Cosine.distance(&[1.0, 0.0], &[0.0, 1.0]) - 1.0).abs() < 1e-6);
+    assert!((Metric::L2.distance(&[0.0, 0.0], &[3.0, 4.0]) - 5.0).abs() < 1e-6);
+    assert_eq!(Metric::parse("cosine"), Some(Metric::Cosine));
+    assert_eq!(Metric::parse("dot"), None);
+}